@@ -1,3 +1,4 @@
+use crate::metrics::LatencyHistogram;
 use crate::HashMap;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -8,6 +9,30 @@ pub struct ClientData {
     // - mapping from operation end time to all latencies registered at that
     //   end time
     data: HashMap<u64, Vec<Duration>>,
+    // - mapping from operation end time to the commit latency and execution
+    //   delay of each operation that ended at that end time (only for
+    //   operations whose executors reported execution timestamps)
+    breakdown: HashMap<u64, Vec<(Duration, Duration)>>,
+    // - mapping from operation end time to the latencies of the operations
+    //   that failed (i.e. some of their ops returned an error) at that end
+    //   time; these are not in `data`
+    errors: HashMap<u64, Vec<Duration>>,
+    // - mapping from time to how long each operation whose deadline expired at
+    //   that time had been waiting for (operations submitted again after a
+    //   timeout may still end, and thus also be in `data` or `errors`)
+    timeouts: HashMap<u64, Vec<Duration>>,
+    // HDR histograms: these can be merged across clients without keeping
+    // raw values
+    // - latencies in `data`
+    latency: LatencyHistogram,
+    // - mapping from type of operation (see `Command::op_type`) to the
+    //   latencies in `data` of that type (only if recorded per type)
+    op_type_latency: HashMap<String, LatencyHistogram>,
+    // - mapping from type of operation to the latencies in `data` of that
+    //   type that took the fast (resp. slow) path (only if recorded per type,
+    //   and if the executors reported the path taken)
+    fast_path_latency: HashMap<String, LatencyHistogram>,
+    slow_path_latency: HashMap<String, LatencyHistogram>,
 }
 
 impl ClientData {
@@ -18,19 +43,143 @@ impl ClientData {
 
     /// Merges two histograms.
     pub fn merge(&mut self, other: &Self) {
-        data_merge(&mut self.data, &other.data)
+        data_merge(&mut self.data, &other.data);
+        data_merge(&mut self.breakdown, &other.breakdown);
+        data_merge(&mut self.errors, &other.errors);
+        data_merge(&mut self.timeouts, &other.timeouts);
+        self.latency.merge(&other.latency);
+        histograms_merge(&mut self.op_type_latency, &other.op_type_latency);
+        histograms_merge(&mut self.fast_path_latency, &other.fast_path_latency);
+        histograms_merge(&mut self.slow_path_latency, &other.slow_path_latency);
     }
 
     /// Records a more mata.
     pub fn record(&mut self, latency: Duration, end_time: u64) {
         let latencies = self.data.entry(end_time).or_insert_with(Vec::new);
         latencies.push(latency);
+        self.latency.record(latency);
+    }
+
+    /// Records the latency of an operation of type `op_type`, in addition to
+    /// `ClientData::record`.
+    pub fn record_op_type(&mut self, op_type: &str, latency: Duration) {
+        self.op_type_latency
+            .entry(op_type.to_string())
+            .or_default()
+            .record(latency);
+    }
+
+    /// Records the latency of an operation of type `op_type` that took the fast
+    /// path (if `fast_path`) or the slow path, in addition to
+    /// `ClientData::record_op_type`.
+    pub fn record_path(
+        &mut self,
+        op_type: &str,
+        fast_path: bool,
+        latency: Duration,
+    ) {
+        let path_latency = if fast_path {
+            &mut self.fast_path_latency
+        } else {
+            &mut self.slow_path_latency
+        };
+        path_latency
+            .entry(op_type.to_string())
+            .or_default()
+            .record(latency);
+    }
+
+    /// Records the latency breakdown of an operation, given its commit latency
+    /// (i.e. the time between submit and commit) and its execution delay (i.e.
+    /// the time between commit and execution).
+    pub fn record_breakdown(
+        &mut self,
+        commit_latency: Duration,
+        execution_delay: Duration,
+        end_time: u64,
+    ) {
+        let breakdown = self.breakdown.entry(end_time).or_default();
+        breakdown.push((commit_latency, execution_delay));
+    }
+
+    /// Records the latency of an operation that failed.
+    pub fn record_error(&mut self, latency: Duration, end_time: u64) {
+        let latencies = self.errors.entry(end_time).or_default();
+        latencies.push(latency);
+    }
+
+    /// Records that an operation timed out after waiting `waited`.
+    pub fn record_timeout(&mut self, waited: Duration, time: u64) {
+        let timeouts = self.timeouts.entry(time).or_default();
+        timeouts.push(waited);
+    }
+
+    pub fn commit_latency_data(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.breakdown
+            .values()
+            .flat_map(|v| v.iter())
+            .map(|(commit_latency, _)| *commit_latency)
+    }
+
+    pub fn execution_delay_data(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.breakdown
+            .values()
+            .flat_map(|v| v.iter())
+            .map(|(_, execution_delay)| *execution_delay)
     }
 
     pub fn latency_data(&self) -> impl Iterator<Item = Duration> + '_ {
         self.data.values().flat_map(|v| v.iter()).cloned()
     }
 
+    /// Returns the HDR histogram of the latencies.
+    pub fn latency_histogram(&self) -> &LatencyHistogram {
+        &self.latency
+    }
+
+    /// Returns the HDR histogram of the latencies of each type of operation.
+    pub fn op_type_latency_histograms(
+        &self,
+    ) -> impl Iterator<Item = (&String, &LatencyHistogram)> {
+        self.op_type_latency.iter()
+    }
+
+    /// Returns the HDR histogram of the latencies of each type of operation
+    /// that took the fast (if `fast_path`) or the slow path.
+    pub fn path_latency_histograms(
+        &self,
+        fast_path: bool,
+    ) -> impl Iterator<Item = (&String, &LatencyHistogram)> {
+        if fast_path {
+            self.fast_path_latency.iter()
+        } else {
+            self.slow_path_latency.iter()
+        }
+    }
+
+    pub fn error_latency_data(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.errors.values().flat_map(|v| v.iter()).cloned()
+    }
+
+    /// Returns the number of operations that failed.
+    pub fn error_count(&self) -> usize {
+        self.errors.values().map(|v| v.len()).sum()
+    }
+
+    /// Returns the number of timeouts.
+    pub fn timeout_count(&self) -> usize {
+        self.timeouts.values().map(|v| v.len()).sum()
+    }
+
+    /// Returns the latency of each operation along with its end time.
+    pub fn latency_data_by_end_time(
+        &self,
+    ) -> impl Iterator<Item = (u64, Duration)> + '_ {
+        self.data.iter().flat_map(|(end_time, latencies)| {
+            latencies.iter().map(move |latency| (*end_time, *latency))
+        })
+    }
+
     pub fn throughput_data(&self) -> impl Iterator<Item = (u64, usize)> + '_ {
         self.data
             .iter()
@@ -68,15 +217,36 @@ impl ClientData {
         })
     }
 
-    /// Prune events that are before `start` or after `end`.
+    /// Prune events that are before `start` or after `end`. The latency
+    /// histogram is rebuilt from the remaining events, but the histograms per
+    /// type of operation (and path) are kept as they are, as the type of each
+    /// event is not stored.
     pub fn prune(&mut self, start: u64, end: u64) {
-        self.data.retain(|&time, _| {
-            // retain if within the given bounds
-            time >= start && time <= end
-        })
+        // retain if within the given bounds
+        let within_bounds = |time: u64| time >= start && time <= end;
+        self.data.retain(|&time, _| within_bounds(time));
+        self.breakdown.retain(|&time, _| within_bounds(time));
+        self.errors.retain(|&time, _| within_bounds(time));
+        self.timeouts.retain(|&time, _| within_bounds(time));
+        let mut latency = LatencyHistogram::new();
+        self.latency_data().for_each(|value| latency.record(value));
+        self.latency = latency;
     }
 }
 
+// Merges histograms per type of operation.
+fn histograms_merge(
+    histograms: &mut HashMap<String, LatencyHistogram>,
+    other: &HashMap<String, LatencyHistogram>,
+) {
+    other.iter().for_each(|(op_type, histogram)| {
+        histograms
+            .entry(op_type.clone())
+            .or_default()
+            .merge(histogram)
+    });
+}
+
 pub fn data_merge<V>(
     map: &mut HashMap<u64, Vec<V>>,
     other: &HashMap<u64, Vec<V>>,
@@ -174,4 +344,150 @@ mod tests {
         throughput.sort();
         assert_eq!(throughput, vec![]);
     }
+
+    #[test]
+    fn client_data_histograms_test() {
+        let mut data = ClientData::new();
+        assert_eq!(data.latency_histogram().count(), 0);
+
+        // at time 10, a get with latency 1 ended, and at time 11, a put with
+        // latency 2 ended
+        data.record(Duration::from_millis(1), 10);
+        data.record_op_type("get", Duration::from_millis(1));
+        data.record(Duration::from_millis(2), 11);
+        data.record_op_type("put", Duration::from_millis(2));
+        data.record_path("put", true, Duration::from_millis(2));
+
+        // at time 12, another get with latency 3 ended, after taking the slow
+        // path
+        let mut other = ClientData::new();
+        other.record(Duration::from_millis(3), 12);
+        other.record_op_type("get", Duration::from_millis(3));
+        other.record_path("get", false, Duration::from_millis(3));
+
+        // merge serialized data, as done in post-processing
+        let other: ClientData =
+            bincode::deserialize(&bincode::serialize(&other).unwrap()).unwrap();
+        data.merge(&other);
+        assert_eq!(data.latency_histogram().count(), 3);
+        assert_eq!(data.latency_histogram().percentile(0.5), 2_000);
+        let mut op_types: Vec<_> = data
+            .op_type_latency_histograms()
+            .map(|(op_type, histogram)| (op_type.clone(), histogram.count()))
+            .collect();
+        op_types.sort();
+        assert_eq!(
+            op_types,
+            vec![("get".to_string(), 2), ("put".to_string(), 1)]
+        );
+        let paths = |fast_path| {
+            data.path_latency_histograms(fast_path)
+                .map(|(op_type, histogram)| {
+                    (op_type.clone(), histogram.count())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(true), vec![("put".to_string(), 1)]);
+        assert_eq!(paths(false), vec![("get".to_string(), 1)]);
+
+        // prune event 10 out: the latency histogram is rebuilt
+        data.prune(11, 20);
+        assert_eq!(data.latency_histogram().count(), 2);
+        assert_eq!(data.latency_histogram().percentile(0.0), 2_000);
+    }
+
+    #[test]
+    fn client_data_errors_test() {
+        let mut data = ClientData::new();
+        assert_eq!(data.error_count(), 0);
+
+        // at time 10, an operation with latency 1 ended, and another one with
+        // latency 2 failed
+        data.record(Duration::from_millis(1), 10);
+        data.record_error(Duration::from_millis(2), 10);
+
+        // at time 12, another operation with latency 3 failed
+        let mut other = ClientData::new();
+        other.record_error(Duration::from_millis(3), 12);
+        data.merge(&other);
+
+        // failed operations are not accounted as successful ones
+        let latency: Vec<_> = data.latency_data().collect();
+        assert_eq!(latency, vec![Duration::from_millis(1)]);
+        assert_eq!(data.error_count(), 2);
+        let mut error_latency: Vec<_> = data.error_latency_data().collect();
+        error_latency.sort();
+        assert_eq!(
+            error_latency,
+            vec![Duration::from_millis(2), Duration::from_millis(3)]
+        );
+
+        // prune event 10 out
+        data.prune(11, 20);
+        assert_eq!(data.error_count(), 1);
+    }
+
+    #[test]
+    fn client_data_timeouts_test() {
+        let mut data = ClientData::new();
+        assert_eq!(data.timeout_count(), 0);
+
+        // at time 10, an operation timed out after waiting 5, and at time 15
+        // it timed out again after waiting 10
+        data.record_timeout(Duration::from_millis(5), 10);
+        let mut other = ClientData::new();
+        other.record_timeout(Duration::from_millis(10), 15);
+        data.merge(&other);
+
+        // timeouts are not accounted as operations
+        assert_eq!(data.latency_data().count(), 0);
+        assert_eq!(data.error_count(), 0);
+        assert_eq!(data.timeout_count(), 2);
+
+        // prune event 10 out
+        data.prune(11, 20);
+        assert_eq!(data.timeout_count(), 1);
+    }
+
+    #[test]
+    fn client_data_breakdown_test() {
+        let mut data = ClientData::new();
+        assert_eq!(data.commit_latency_data().count(), 0);
+
+        // at time 10, an operation committed after 6 and executed 4 later
+        // ended
+        data.record_breakdown(
+            Duration::from_millis(6),
+            Duration::from_millis(4),
+            10,
+        );
+
+        // at time 12, another operation committed after 2 and executed 1
+        // later ended
+        let mut other = ClientData::new();
+        other.record_breakdown(
+            Duration::from_millis(2),
+            Duration::from_millis(1),
+            12,
+        );
+        data.merge(&other);
+
+        let mut commit_latency: Vec<_> = data.commit_latency_data().collect();
+        commit_latency.sort();
+        assert_eq!(
+            commit_latency,
+            vec![Duration::from_millis(2), Duration::from_millis(6)]
+        );
+        let mut execution_delay: Vec<_> = data.execution_delay_data().collect();
+        execution_delay.sort();
+        assert_eq!(
+            execution_delay,
+            vec![Duration::from_millis(1), Duration::from_millis(4)]
+        );
+
+        // prune event 10 out
+        data.prune(11, 20);
+        let execution_delay: Vec<_> = data.execution_delay_data().collect();
+        assert_eq!(execution_delay, vec![Duration::from_millis(1)]);
+    }
 }
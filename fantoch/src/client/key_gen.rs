@@ -1,7 +1,8 @@
 use crate::id::ClientId;
 use crate::kvs::Key;
 use rand::distributions::Distribution;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use zipf::ZipfDistribution;
 
@@ -55,6 +56,8 @@ pub struct KeyGenState {
     key_gen: KeyGen,
     client_id: ClientId,
     zipf: Option<ZipfDistribution>,
+    // random number generator used to generate the commands of the client
+    rng: StdRng,
 }
 
 impl KeyGenState {
@@ -79,9 +82,26 @@ impl KeyGenState {
             key_gen,
             client_id,
             zipf,
+            rng: StdRng::from_entropy(),
         }
     }
 
+    /// Seeds the random number generator used to generate the commands of the
+    /// client. Clients with the same identifier and seed generate the same
+    /// commands, while clients with different identifiers don't.
+    pub fn set_seed(&mut self, seed: u64) {
+        let mut client_seed = [0; 32];
+        client_seed[..8].copy_from_slice(&seed.to_le_bytes());
+        client_seed[8..16].copy_from_slice(&self.client_id.to_le_bytes());
+        self.rng = StdRng::from_seed(client_seed);
+    }
+
+    /// Returns the random number generator used to generate the commands of
+    /// the client.
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
     pub fn gen_cmd_key(&mut self) -> Key {
         match self.key_gen {
             KeyGen::ConflictPool {
@@ -93,15 +113,20 @@ impl KeyGenState {
     }
 
     /// Generate a command key based on the conflict rate provided.
-    fn gen_conflict_rate(&self, conflict_rate: usize, pool_size: usize) -> Key {
+    fn gen_conflict_rate(
+        &mut self,
+        conflict_rate: usize,
+        pool_size: usize,
+    ) -> Key {
         debug_assert!(conflict_rate <= 100);
         debug_assert!(pool_size >= 1);
 
         // check if we should generate a conflict
-        let should_conflict = true_if_random_is_less_than(conflict_rate);
+        let should_conflict =
+            true_if_random_is_less_than(conflict_rate, &mut self.rng);
         if should_conflict {
             // if it should conflict, select a random key from the pool
-            let random_key = rand::thread_rng().gen_range(0..pool_size);
+            let random_key = self.rng.gen_range(0..pool_size);
             format!("{}{}", CONFLICT_COLOR, random_key)
         } else {
             // avoid conflict with unique client key
@@ -114,15 +139,17 @@ impl KeyGenState {
         let zipf = self
             .zipf
             .expect("ZipfDistribution should already be initialized");
-        let mut rng = rand::thread_rng();
-        zipf.sample(&mut rng).to_string()
+        zipf.sample(&mut self.rng).to_string()
     }
 }
 
-pub fn true_if_random_is_less_than(percentage: usize) -> bool {
+pub fn true_if_random_is_less_than<R: Rng>(
+    percentage: usize,
+    rng: &mut R,
+) -> bool {
     match percentage {
         0 => false,
         100 => true,
-        _ => rand::thread_rng().gen_range(0..100) < percentage,
+        _ => rng.gen_range(0..100) < percentage,
     }
 }
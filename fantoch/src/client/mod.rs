@@ -11,18 +11,32 @@ pub mod pending;
 // This module contains the definition of `ClientData`
 pub mod data;
 
+// This module contains the definition of `Trace`
+pub mod trace;
+
+// This module contains the definition of `LoadRamp`
+pub mod ramp;
+
+// This module contains the definition of `MetricsReport`
+pub mod report;
+
 // Re-exports.
 pub use data::ClientData;
 pub use key_gen::KeyGen;
 pub use pending::Pending;
-pub use workload::Workload;
+pub use ramp::LoadRamp;
+pub use report::MetricsReport;
+pub use trace::{Trace, TraceCommand};
+pub use workload::{OpMix, PayloadSize, Workload};
 
-use crate::command::Command;
+use crate::command::{Command, ExecutionTimestamps};
 use crate::id::{ClientId, ProcessId, Rifl, RiflGen, ShardId};
+use crate::kvs::Key;
 use crate::time::SysTime;
 use crate::HashMap;
 use crate::{info, trace};
 use key_gen::KeyGenState;
+use std::time::Duration;
 
 pub struct Client {
     /// id of this client
@@ -40,6 +54,19 @@ pub struct Client {
     pending: Pending,
     /// mapping from
     data: ClientData,
+    /// mapping from pending command RIFL to its type (only if latencies are
+    /// recorded per type of command)
+    op_types: HashMap<Rifl, &'static str>,
+    /// mapping from key to the last write on it acknowledged to this client
+    /// (only if eventual reads observe the client's writes)
+    session: HashMap<Key, Rifl>,
+    /// mapping from pending command RIFL to the keys it writes (only if
+    /// eventual reads observe the client's writes)
+    pending_writes: HashMap<Rifl, Vec<Key>>,
+    /// commands issued (only if recorded)
+    trace: Vec<TraceCommand>,
+    /// time (in milliseconds) at which the first command was issued
+    start_time: Option<u64>,
     /// frequency of status messages; if set with Some(1), a status message
     /// will be shown after each command completes
     status_frequency: Option<usize>,
@@ -49,13 +76,19 @@ impl Client {
     /// Creates a new client.
     pub fn new(
         client_id: ClientId,
-        workload: Workload,
+        mut workload: Workload,
         status_frequency: Option<usize>,
     ) -> Self {
-        // create key gen state
-        let key_gen_state = workload
+        // if the workload replays a trace, replay the commands of this client
+        workload.set_trace_client(client_id);
+        // create key gen state (seeded if the workload should be
+        // reproducible)
+        let mut key_gen_state = workload
             .key_gen()
             .initial_state(workload.shard_count(), client_id);
+        if let Some(seed) = workload.seed() {
+            key_gen_state.set_seed(seed);
+        }
         // create client
         Self {
             client_id,
@@ -65,6 +98,11 @@ impl Client {
             key_gen_state,
             pending: Pending::new(),
             data: ClientData::new(),
+            op_types: HashMap::new(),
+            session: HashMap::new(),
+            pending_writes: HashMap::new(),
+            trace: Vec::new(),
+            start_time: None,
             status_frequency,
         }
     }
@@ -95,7 +133,7 @@ impl Client {
         // generate next command in the workload if some process_id
         self.workload
             .next_cmd(&mut self.rifl_gen, &mut self.key_gen_state)
-            .map(|(target_shard, cmd)| {
+            .map(|(target_shard, mut cmd)| {
                 // if a new command was generated, start it in pending
                 let rifl = cmd.rifl();
                 trace!(
@@ -104,17 +142,75 @@ impl Client {
                     rifl,
                     time.micros()
                 );
+                let start_time =
+                    *self.start_time.get_or_insert_with(|| time.millis());
                 self.pending.start(rifl, time);
+                if self.workload.record_trace() {
+                    self.trace.push(TraceCommand::new(
+                        self.client_id,
+                        time.millis() - start_time,
+                        target_shard,
+                        &cmd,
+                    ));
+                }
+                if self.workload.latency_per_op_type() {
+                    self.op_types.insert(rifl, cmd.op_type());
+                }
+                if self.workload.read_your_writes() {
+                    self.session_send(&mut cmd);
+                }
                 (target_shard, cmd)
             })
     }
 
+    /// Attaches to eventual reads the session token with the last writes
+    /// acknowledged to this client on the keys read (see
+    /// `Command::set_session`), and records the keys written by any other
+    /// command, so that they're added to the session once it's acknowledged.
+    fn session_send(&mut self, cmd: &mut Command) {
+        if cmd.local_read() {
+            let session = cmd
+                .shards()
+                .flat_map(|shard_id| cmd.iter(*shard_id))
+                .filter_map(|(key, _)| {
+                    self.session.get(key).map(|rifl| (key.clone(), *rifl))
+                })
+                .collect();
+            cmd.set_session(session);
+        } else if !cmd.read_only() {
+            let written = cmd
+                .shards()
+                .flat_map(|shard_id| cmd.write_set(*shard_id))
+                .cloned()
+                .collect();
+            self.pending_writes.insert(cmd.rifl(), written);
+        }
+    }
+
     /// Handle executed command and return a boolean indicating whether we have
     /// generated all commands and receive all the corresponding command
-    /// results.
-    pub fn cmd_recv(&mut self, rifl: Rifl, time: &dyn SysTime) {
+    /// results. If the executors reported when the command was committed and
+    /// executed, its latency breakdown is also recorded. Commands that `failed`
+    /// (i.e. some of their ops returned an error) are recorded separately.
+    /// Commands excluded from the metrics by the workload (e.g. because they
+    /// were issued during the warmup) are not recorded.
+    pub fn cmd_recv(
+        &mut self,
+        rifl: Rifl,
+        timestamps: Option<ExecutionTimestamps>,
+        failed: bool,
+        time: &dyn SysTime,
+    ) {
         // end command in pending and save command latency
         let (latency, end_time) = self.pending.end(rifl, time);
+        let op_type = self.op_types.remove(&rifl);
+        // the writes of the command (even if some failed) were applied, and
+        // so the next eventual reads should observe them
+        if let Some(written) = self.pending_writes.remove(&rifl) {
+            for key in written {
+                self.session.insert(key, rifl);
+            }
+        }
         trace!(
             "c{}: rifl {:?} ended after {} micros at {}",
             self.client_id,
@@ -122,7 +218,39 @@ impl Client {
             latency.as_micros(),
             end_time
         );
-        self.data.record(latency, end_time);
+        if self.measured(rifl, latency, end_time) {
+            if failed {
+                self.data.record_error(latency, end_time);
+            } else {
+                self.data.record(latency, end_time);
+                if let Some(op_type) = op_type {
+                    self.data.record_op_type(op_type, latency);
+                    // the executors at the coordinator of the command report
+                    // the path it took
+                    let fast_path =
+                        timestamps.and_then(|timestamps| timestamps.fast_path);
+                    if let Some(fast_path) = fast_path {
+                        self.data.record_path(op_type, fast_path, latency);
+                    }
+                }
+                // the breakdown is only known if the coordinator of the
+                // command reported when it was submitted
+                let commit_latency = timestamps
+                    .and_then(|timestamps| timestamps.commit_latency());
+                if let (Some(timestamps), Some(commit_latency)) =
+                    (timestamps, commit_latency)
+                {
+                    let commit_latency = Duration::from_millis(commit_latency);
+                    let execution_delay =
+                        Duration::from_millis(timestamps.execution_delay());
+                    self.data.record_breakdown(
+                        commit_latency,
+                        execution_delay,
+                        end_time,
+                    );
+                }
+            }
+        }
 
         if let Some(frequency) = self.status_frequency {
             if self.workload.issued_commands() % frequency == 0 {
@@ -136,6 +264,61 @@ impl Client {
         }
     }
 
+    /// Handle a command whose deadline expired, recording the timeout. Unless
+    /// the command was `retried` (i.e. submitted again), the client gives up
+    /// on it.
+    pub fn cmd_timeout(
+        &mut self,
+        rifl: Rifl,
+        retried: bool,
+        time: &dyn SysTime,
+    ) {
+        let (waited, time) = if retried {
+            self.pending.elapsed(rifl, time)
+        } else {
+            // the command may have not been applied, and so it's not added to
+            // the session (otherwise, eventual reads could wait forever)
+            self.op_types.remove(&rifl);
+            self.pending_writes.remove(&rifl);
+            self.pending.end(rifl, time)
+        };
+        trace!(
+            "c{}: rifl {:?} timed out after {} micros at {} | retried = {}",
+            self.client_id,
+            rifl,
+            waited.as_micros(),
+            time,
+            retried
+        );
+        if self.measured(rifl, waited, time) {
+            self.data.record_timeout(waited, time);
+        }
+    }
+
+    /// Checks if the command with rifl `rifl` that ended (or timed out) at
+    /// `end_time` after `latency` should be recorded.
+    fn measured(&self, rifl: Rifl, latency: Duration, end_time: u64) -> bool {
+        let start_time =
+            self.start_time.expect("client should have issued commands");
+        // compute times since the first command was issued
+        let end = end_time.saturating_sub(start_time);
+        let start = end.saturating_sub(latency.as_millis() as u64);
+        self.workload.measured(rifl.sequence(), start, end)
+    }
+
+    /// Returns how long the client should wait before issuing the next
+    /// command, if the workload replays a trace in which the next command was
+    /// issued later (since the first command was issued) than now.
+    pub fn next_cmd_delay(&self, time: &dyn SysTime) -> Option<Duration> {
+        let next_cmd_time = self.workload.next_cmd_time()?;
+        let elapsed = self
+            .start_time
+            .map(|start_time| time.millis().saturating_sub(start_time))
+            .unwrap_or_default();
+        (next_cmd_time > elapsed)
+            .then(|| Duration::from_millis(next_cmd_time - elapsed))
+    }
+
     pub fn workload_finished(&self) -> bool {
         self.workload.finished()
     }
@@ -151,6 +334,12 @@ impl Client {
         &self.data
     }
 
+    /// Returns the commands issued, if the workload records them (see
+    /// `Workload::set_record_trace`).
+    pub fn trace(&self) -> &[TraceCommand] {
+        &self.trace
+    }
+
     /// Returns the number of commands already issued.
     pub fn issued_commands(&self) -> usize {
         self.workload.issued_commands()
@@ -250,6 +439,7 @@ mod tests {
         let region = Region::new("europe-west2");
         let commands_per_client = 2;
         let mut client = gen_client(commands_per_client);
+        client.workload.set_latency_per_op_type(true);
 
         // discover
         let closest =
@@ -269,7 +459,7 @@ mod tests {
 
         // handle result at time 10
         time.add_millis(10);
-        client.cmd_recv(cmd.rifl(), &time);
+        client.cmd_recv(cmd.rifl(), None, false, &time);
         let next = client.cmd_send(&time);
 
         // check there's next command
@@ -281,7 +471,10 @@ mod tests {
 
         // handle result at time 15
         time.add_millis(5);
-        client.cmd_recv(cmd.rifl(), &time);
+        let timestamps = ExecutionTimestamps::new(12, 14)
+            .with_submit(Some(11))
+            .with_fast_path(Some(false));
+        client.cmd_recv(cmd.rifl(), Some(timestamps), false, &time);
         let next = client.cmd_send(&time);
 
         // check there's no next command
@@ -299,5 +492,165 @@ mod tests {
         let mut throughput: Vec<_> = client.data().throughput_data().collect();
         throughput.sort();
         assert_eq!(throughput, vec![(10, 1), (15, 1)],);
+
+        // check latency breakdown: only the second command reported its
+        // execution timestamps
+        let commit_latency: Vec<_> =
+            client.data().commit_latency_data().collect();
+        assert_eq!(commit_latency, vec![Duration::from_millis(1)]);
+        let execution_delay: Vec<_> =
+            client.data().execution_delay_data().collect();
+        assert_eq!(execution_delay, vec![Duration::from_millis(2)]);
+
+        // check latency per path: only the second command reported the path
+        // it took
+        assert_eq!(client.data().path_latency_histograms(true).count(), 0);
+        let slow_path: Vec<_> = client
+            .data()
+            .path_latency_histograms(false)
+            .map(|(op_type, histogram)| (op_type.as_str(), histogram.count()))
+            .collect();
+        assert_eq!(slow_path, vec![("put", 1)]);
+    }
+
+    #[test]
+    fn client_timeout() {
+        // client
+        let commands_per_client = 2;
+        let mut client = gen_client(commands_per_client);
+        let mut time = SimTime::new();
+
+        // the first command times out at time 10, and it's submitted again
+        let (_, cmd) = client
+            .cmd_send(&time)
+            .expect("there should be a first operation");
+        time.add_millis(10);
+        client.cmd_timeout(cmd.rifl(), true, &time);
+        assert!(!client.finished());
+
+        // its result arrives at time 12
+        time.add_millis(2);
+        client.cmd_recv(cmd.rifl(), None, false, &time);
+
+        // the second command times out at time 22, and the client gives up on
+        // it
+        let (_, cmd) = client
+            .cmd_send(&time)
+            .expect("there should be a second operation");
+        time.add_millis(10);
+        client.cmd_timeout(cmd.rifl(), false, &time);
+        assert!(client.cmd_send(&time).is_none());
+        assert!(client.finished());
+
+        // only the first command completed, but both timed out
+        let latency: Vec<_> = client.data().latency_data().collect();
+        assert_eq!(latency, vec![Duration::from_millis(12)]);
+        assert_eq!(client.data().timeout_count(), 2);
+    }
+
+    #[test]
+    fn client_warmup() {
+        // client with a warmup of 1 command and 10ms, and a measurement window
+        // of 15ms
+        let commands_per_client = 5;
+        let mut client = gen_client(commands_per_client);
+        client.workload.set_warmup(1, Duration::from_millis(10));
+        client
+            .workload
+            .set_measurement_window(Duration::from_millis(15));
+        let mut time = SimTime::new();
+
+        // each command takes 6ms: the first is excluded by the command warmup,
+        // and the second by the warmup duration (as it started at 6ms); the
+        // third and fourth started after 10ms and ended before 25ms, but the
+        // fifth ended after that
+        while let Some((_, cmd)) = client.cmd_send(&time) {
+            time.add_millis(6);
+            client.cmd_recv(cmd.rifl(), None, false, &time);
+        }
+        assert!(client.finished());
+        let mut latency: Vec<_> = client.data().latency_data().collect();
+        latency.sort();
+        assert_eq!(latency, vec![Duration::from_millis(6); 2]);
+        let mut throughput: Vec<_> = client.data().throughput_data().collect();
+        throughput.sort();
+        assert_eq!(throughput, vec![(18, 1), (24, 1)]);
+    }
+
+    #[test]
+    fn client_trace() {
+        // client recording the commands it issues, at 0ms, 5ms and 12ms
+        let commands_per_client = 3;
+        let mut client = gen_client(commands_per_client);
+        client.workload.set_record_trace(true);
+        let mut time = SimTime::new();
+        for delay in [5, 7, 0] {
+            let (_, cmd) = client.cmd_send(&time).unwrap();
+            time.add_millis(delay);
+            client.cmd_recv(cmd.rifl(), None, false, &time);
+        }
+        assert!(client.finished());
+        let trace = Trace::new(client.trace().to_vec());
+        assert_eq!(trace.commands(client.id()).len(), 3);
+
+        // replay the trace, recording the commands issued
+        let path = std::env::temp_dir().join("fantoch_client_trace");
+        trace.write(&path).unwrap();
+        let shard_count = 1;
+        let mut workload = Workload::from_trace(shard_count, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        workload.set_record_trace(true);
+        let status_frequency = None;
+        let mut replay = Client::new(client.id(), workload, status_frequency);
+        let mut time = SimTime::new();
+        while !replay.workload_finished() {
+            // wait until the next command should be issued
+            if let Some(delay) = replay.next_cmd_delay(&time) {
+                time.add_millis(delay.as_millis() as u64);
+                assert!(replay.next_cmd_delay(&time).is_none());
+            }
+            let (_, cmd) = replay.cmd_send(&time).unwrap();
+            replay.cmd_recv(cmd.rifl(), None, false, &time);
+        }
+        assert!(replay.finished());
+
+        // the commands issued are the ones in the trace, at the same time
+        assert_eq!(replay.trace(), trace.commands(client.id()));
+    }
+
+    #[test]
+    fn client_read_your_writes() {
+        // all commands access the same key, and half of them are eventual
+        // reads
+        let commands_per_client = 100;
+        let mut client = gen_client(commands_per_client);
+        client.workload.set_read_only_percentage(50);
+        client.workload.set_eventual_read_percentage(100);
+        client.workload.set_read_your_writes(true);
+        let time = SimTime::new();
+
+        // eventual reads carry the last write acknowledged to the client, and
+        // writes that timed out are never acknowledged
+        let mut last_write = None;
+        let mut reads = 0;
+        while let Some((_, cmd)) = client.cmd_send(&time) {
+            let rifl = cmd.rifl();
+            if cmd.local_read() {
+                reads += 1;
+                let session: Vec<_> = cmd.session().values().collect();
+                assert_eq!(session, last_write.iter().collect::<Vec<_>>());
+                client.cmd_recv(rifl, None, false, &time);
+            } else if rifl.sequence() % 10 == 0 {
+                let retried = false;
+                client.cmd_timeout(rifl, retried, &time);
+            } else {
+                assert!(cmd.session().is_empty());
+                client.cmd_recv(rifl, None, false, &time);
+                last_write = Some(rifl);
+            }
+        }
+        assert!(client.finished());
+        assert!(reads > 0);
+        assert!(last_write.is_some());
     }
 }
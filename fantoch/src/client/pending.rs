@@ -33,6 +33,20 @@ impl Pending {
             .pending
             .remove(&rifl)
             .expect("can't end a command if a command has not started");
+        Self::latency(start_time, time)
+    }
+
+    /// Returns for how long a command has been pending and the current time.
+    pub fn elapsed(&self, rifl: Rifl, time: &dyn SysTime) -> (Duration, u64) {
+        // get start time
+        let start_time = *self
+            .pending
+            .get(&rifl)
+            .expect("can't check a command if a command has not started");
+        Self::latency(start_time, time)
+    }
+
+    fn latency(start_time: u64, time: &dyn SysTime) -> (Duration, u64) {
         // compute end time
         let end_time = time.micros();
         // make sure time is monotonic
@@ -87,6 +101,11 @@ mod tests {
         // pending is not empty
         assert!(!pending.is_empty());
 
+        // first rifl has been pending for 10 at time 10
+        let (elapsed, now) = pending.elapsed(rifl1, &time);
+        assert_eq!(elapsed.as_millis(), 10);
+        assert_eq!(now, 10);
+
         // end first rifl at time 11
         time.add_millis(1);
         let (latency, return_time) = pending.end(rifl1, &time);
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Schedule of the load offered by open-loop clients: the load starts at
+/// `start` commands per second, and increases by `step` commands per second
+/// every `step_duration`, up to `max` commands per second (if set). Since each
+/// step offers a constant load, a single run covers several points of the
+/// throughput-latency curve: the metrics of each point are the ones of the
+/// commands that ended within its step (see `LoadRamp::step_window` and
+/// `ClientData::prune`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadRamp {
+    start: u64,
+    step: u64,
+    step_duration: Duration,
+    max: Option<u64>,
+}
+
+impl LoadRamp {
+    /// Creates a new load ramp.
+    pub fn new(start: u64, step: u64, step_duration: Duration) -> Self {
+        assert!(start > 0, "the initial load should be > 0");
+        assert!(
+            step_duration >= Duration::from_millis(1),
+            "the duration of each step should be at least 1ms"
+        );
+        Self {
+            start,
+            step,
+            step_duration,
+            max: None,
+        }
+    }
+
+    /// Returns the maximum load (if any).
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    /// Sets the maximum load, after which the load no longer increases.
+    pub fn set_max(&mut self, max: u64) {
+        assert!(
+            max >= self.start,
+            "the maximum load should be at least the initial load"
+        );
+        self.max = Some(max);
+    }
+
+    /// Returns the step of the ramp `elapsed` after it started.
+    pub fn step_at(&self, elapsed: Duration) -> u64 {
+        (elapsed.as_millis() / self.step_duration.as_millis()) as u64
+    }
+
+    /// Returns the load (in commands per second) offered at step `step`.
+    pub fn load(&self, step: u64) -> u64 {
+        let load = self.start.saturating_add(step.saturating_mul(self.step));
+        match self.max {
+            Some(max) => load.min(max),
+            None => load,
+        }
+    }
+
+    /// Returns the interval between the commands of each of `clients` clients
+    /// so that, together, they offer the load of step `step`.
+    pub fn interval(&self, step: u64, clients: usize) -> Duration {
+        Duration::from_secs_f64(clients as f64 / self.load(step) as f64)
+    }
+
+    /// Returns the window (relative to the start of the ramp) during which
+    /// step `step` lasts.
+    pub fn step_window(&self, step: u64) -> (Duration, Duration) {
+        let start = self.step_duration * step as u32;
+        (start, start + self.step_duration)
+    }
+
+    /// Checks if the load increases after step `step`.
+    pub fn increases_after(&self, step: u64) -> bool {
+        self.step > 0 && self.load(step) < self.load(step + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_ramp() {
+        // start at 1000 ops/s and increase by 500 ops/s every 10s
+        let mut ramp = LoadRamp::new(1000, 500, Duration::from_secs(10));
+        assert_eq!(ramp.step_at(Duration::from_secs(0)), 0);
+        assert_eq!(ramp.step_at(Duration::from_millis(9999)), 0);
+        assert_eq!(ramp.step_at(Duration::from_secs(10)), 1);
+        assert_eq!(ramp.step_at(Duration::from_secs(25)), 2);
+        assert_eq!(ramp.load(0), 1000);
+        assert_eq!(ramp.load(1), 1500);
+        assert_eq!(ramp.load(2), 2000);
+        assert!(ramp.increases_after(2));
+        assert_eq!(
+            ramp.step_window(2),
+            (Duration::from_secs(20), Duration::from_secs(30))
+        );
+
+        // with 10 clients, each client issues a command every 10ms at first,
+        // and every 5ms at step 2
+        assert_eq!(ramp.interval(0, 10), Duration::from_millis(10));
+        assert_eq!(ramp.interval(2, 10), Duration::from_millis(5));
+
+        // with a maximum of 1800 ops/s, the load stops increasing at step 2
+        ramp.set_max(1800);
+        assert_eq!(ramp.load(1), 1500);
+        assert_eq!(ramp.load(2), 1800);
+        assert_eq!(ramp.load(3), 1800);
+        assert!(ramp.increases_after(1));
+        assert!(!ramp.increases_after(2));
+    }
+}
@@ -0,0 +1,211 @@
+use super::ClientData;
+use crate::metrics::LatencyHistogram;
+use crate::HashMap;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Summary of a `LatencyHistogram`, with latencies in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub p99_9: u64,
+    pub max: u64,
+}
+
+impl LatencySummary {
+    /// Summarizes histogram `histogram`.
+    pub fn new(histogram: &LatencyHistogram) -> Self {
+        Self {
+            count: histogram.count(),
+            mean: histogram.mean(),
+            p50: histogram.percentile(0.5),
+            p90: histogram.percentile(0.9),
+            p95: histogram.percentile(0.95),
+            p99: histogram.percentile(0.99),
+            p99_9: histogram.percentile(0.999),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// Number of commands that ended in a given second.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputSample {
+    /// time (in seconds since the UNIX epoch)
+    pub time: u64,
+    pub commands: usize,
+}
+
+/// Structured summary of the metrics of a set of clients. Unlike
+/// `ClientData`, it's written as JSON (see `MetricsReport::write`), and so it
+/// can be consumed by other tools without parsing the output of the clients.
+/// All latencies are in microseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsReport {
+    /// tags that identify the clients (e.g. the region where they ran)
+    pub tags: BTreeMap<String, String>,
+    /// number of clients
+    pub clients: usize,
+    /// time (in milliseconds since the UNIX epoch) at which the first and last
+    /// commands ended, if any
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    /// number of commands that ended, failed and timed out (see `ClientData`)
+    pub commands: u64,
+    pub errors: usize,
+    pub timeouts: usize,
+    /// average number of commands that ended per second
+    pub throughput: f64,
+    /// number of commands that ended in each second, ordered by time
+    pub throughput_over_time: Vec<ThroughputSample>,
+    pub latency: LatencySummary,
+    /// latency per type of operation, and per path taken (if recorded)
+    pub op_type_latency: BTreeMap<String, LatencySummary>,
+    pub fast_path_latency: BTreeMap<String, LatencySummary>,
+    pub slow_path_latency: BTreeMap<String, LatencySummary>,
+}
+
+impl MetricsReport {
+    /// Creates a report with the metrics in `data`, which are the ones of
+    /// `clients` clients identified by `tags`.
+    pub fn new(
+        data: &ClientData,
+        clients: usize,
+        tags: BTreeMap<String, String>,
+    ) -> Self {
+        let (start, end) = match data.start_and_end() {
+            Some((start, end)) => (Some(start), Some(end)),
+            None => (None, None),
+        };
+
+        // aggregate throughput per second
+        let mut seconds_to_commands: HashMap<u64, usize> = HashMap::new();
+        for (time_millis, commands) in data.throughput_data() {
+            *seconds_to_commands.entry(time_millis / 1000).or_default() +=
+                commands;
+        }
+        let mut throughput_over_time: Vec<_> = seconds_to_commands
+            .into_iter()
+            .map(|(time, commands)| ThroughputSample { time, commands })
+            .collect();
+        throughput_over_time.sort_by_key(|sample| sample.time);
+
+        Self {
+            tags,
+            clients,
+            start,
+            end,
+            commands: data.latency_histogram().count(),
+            errors: data.error_count(),
+            timeouts: data.timeout_count(),
+            throughput: data.throughput(),
+            throughput_over_time,
+            latency: LatencySummary::new(data.latency_histogram()),
+            op_type_latency: summaries(data.op_type_latency_histograms()),
+            fast_path_latency: summaries(data.path_latency_histograms(true)),
+            slow_path_latency: summaries(data.path_latency_histograms(false)),
+        }
+    }
+
+    /// Loads the report stored in file `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .wrap_err_with(|| format!("open metrics report {:?}", path))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .wrap_err("parse metrics report")
+    }
+
+    /// Writes the report to file `path` (as JSON).
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create metrics report {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .wrap_err("serialize metrics report")?;
+        writer.flush().wrap_err("write metrics report")
+    }
+}
+
+// Summarizes the histogram of each type of operation.
+fn summaries<'a>(
+    histograms: impl Iterator<Item = (&'a String, &'a LatencyHistogram)>,
+) -> BTreeMap<String, LatencySummary> {
+    histograms
+        .map(|(op_type, histogram)| {
+            (op_type.clone(), LatencySummary::new(histogram))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn metrics_report() {
+        let mut data = ClientData::new();
+        // two commands end in the first second, and one in the third
+        data.record(Duration::from_millis(10), 1_000);
+        data.record(Duration::from_millis(20), 1_500);
+        data.record(Duration::from_millis(30), 3_200);
+        data.record_op_type("put", Duration::from_millis(30));
+        data.record_path("put", true, Duration::from_millis(30));
+        data.record_error(Duration::from_millis(5), 1_100);
+        data.record_timeout(Duration::from_millis(100), 1_200);
+
+        let tags = vec![(String::from("region"), String::from("eu-west-1"))]
+            .into_iter()
+            .collect();
+        let report = MetricsReport::new(&data, 2, tags);
+        assert_eq!(report.clients, 2);
+        assert_eq!(report.start, Some(1_000));
+        assert_eq!(report.end, Some(3_200));
+        assert_eq!(report.commands, 3);
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.timeouts, 1);
+        assert_eq!(
+            report.throughput_over_time,
+            vec![
+                ThroughputSample {
+                    time: 1,
+                    commands: 2
+                },
+                ThroughputSample {
+                    time: 3,
+                    commands: 1
+                },
+            ]
+        );
+        assert_eq!(report.latency.count, 3);
+        assert!(report.latency.max >= 30_000);
+        assert_eq!(report.op_type_latency["put"].count, 1);
+        assert_eq!(report.fast_path_latency["put"].count, 1);
+        assert!(report.slow_path_latency.is_empty());
+
+        // check that the report survives being written and loaded
+        let path = std::env::temp_dir().join("fantoch_metrics_report");
+        report.write(&path).unwrap();
+        assert_eq!(MetricsReport::load(&path).unwrap(), report);
+        std::fs::remove_file(&path).unwrap();
+
+        // reports of clients without commands can also be written
+        let report = MetricsReport::new(&ClientData::new(), 1, BTreeMap::new());
+        assert_eq!(report.start, None);
+        assert_eq!(report.latency.count, 0);
+        report.write(&path).unwrap();
+        assert_eq!(MetricsReport::load(&path).unwrap(), report);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
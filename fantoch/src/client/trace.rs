@@ -0,0 +1,275 @@
+use crate::command::{Command, Consistency};
+use crate::id::{ClientId, ShardId};
+use crate::kvs::{KVOp, Key};
+use crate::HashMap;
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+// types of operation that can be replayed (see `KVOp::name`)
+const REPLAYABLE_OPS: &[&str] = &[
+    "get",
+    "get_versioned",
+    "put",
+    "delete",
+    "cas",
+    "put_if_absent",
+    "add",
+    "append",
+    "scan",
+];
+
+/// An operation of a command recorded in a trace. Payloads are not recorded,
+/// only their size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceOp {
+    pub key: Key,
+    /// type of operation (see `KVOp::name`)
+    pub op: String,
+    pub payload_size: usize,
+}
+
+/// A command recorded in a trace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceCommand {
+    /// client that issued the command
+    pub client_id: ClientId,
+    /// time (in milliseconds since the client issued its first command) at
+    /// which the command was issued
+    pub time: u64,
+    pub consistency: Consistency,
+    /// operations of the command; the command is submitted to the shard of
+    /// the first one
+    pub ops: Vec<TraceOp>,
+}
+
+impl TraceCommand {
+    /// Records command `cmd`, issued by client `client_id` at `time` to shard
+    /// `target_shard`.
+    pub fn new(
+        client_id: ClientId,
+        time: u64,
+        target_shard: ShardId,
+        cmd: &Command,
+    ) -> Self {
+        // record the operations on the target shard first, so that the
+        // command is submitted to the same shard when replayed
+        let shards = std::iter::once(target_shard).chain(
+            cmd.shards()
+                .cloned()
+                .filter(move |shard_id| *shard_id != target_shard),
+        );
+        let ops = shards
+            .flat_map(|shard_id| cmd.iter(shard_id))
+            .flat_map(|(key, ops)| {
+                ops.iter().map(move |op| TraceOp {
+                    key: key.clone(),
+                    op: op.name().to_string(),
+                    payload_size: payload_size(op),
+                })
+            })
+            .collect();
+        Self {
+            client_id,
+            time,
+            consistency: cmd.consistency(),
+            ops,
+        }
+    }
+}
+
+/// A trace of the commands issued by a set of clients, stored as a file with
+/// a `TraceCommand` per line in JSON (see `Trace::write`). Traces can be
+/// replayed by a `Workload` (see `Workload::from_trace`).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    /// mapping from client identifier to the commands it issued, in the
+    /// order they were issued
+    commands: HashMap<ClientId, Vec<TraceCommand>>,
+}
+
+impl Trace {
+    /// Creates a trace from the commands provided.
+    pub fn new(commands: impl IntoIterator<Item = TraceCommand>) -> Self {
+        let mut trace = Self {
+            commands: HashMap::new(),
+        };
+        for command in commands {
+            trace
+                .commands
+                .entry(command.client_id)
+                .or_default()
+                .push(command);
+        }
+        // commands are replayed in the order they were issued
+        for commands in trace.commands.values_mut() {
+            commands.sort_by_key(|command| command.time);
+        }
+        trace
+    }
+
+    /// Loads the trace stored in file `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .wrap_err_with(|| format!("open trace file {:?}", path))?;
+        let mut commands = Vec::new();
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.wrap_err("read trace file")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let command: TraceCommand = serde_json::from_str(&line)
+                .wrap_err_with(|| {
+                    format!("parse line {} of trace", index + 1)
+                })?;
+            if let Some(op) = command
+                .ops
+                .iter()
+                .find(|op| !REPLAYABLE_OPS.contains(&op.op.as_str()))
+            {
+                eyre::bail!(
+                    "line {} of trace has an operation that can't be replayed: {}",
+                    index + 1,
+                    op.op
+                );
+            }
+            if command.ops.is_empty() {
+                eyre::bail!("line {} of trace has no operations", index + 1);
+            }
+            commands.push(command);
+        }
+        Ok(Self::new(commands))
+    }
+
+    /// Writes the trace to file `path`, with the commands of each client in
+    /// the order they were issued.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create trace file {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        let mut client_ids: Vec<_> = self.commands.keys().collect();
+        client_ids.sort();
+        for client_id in client_ids {
+            for command in &self.commands[client_id] {
+                serde_json::to_writer(&mut writer, command)
+                    .wrap_err("serialize trace command")?;
+                writeln!(writer).wrap_err("write trace file")?;
+            }
+        }
+        writer.flush().wrap_err("write trace file")
+    }
+
+    /// Returns the commands issued by client `client_id`.
+    pub fn commands(&self, client_id: ClientId) -> &[TraceCommand] {
+        self.commands
+            .get(&client_id)
+            .map(|commands| commands.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the total number of commands in the trace.
+    pub fn command_count(&self) -> usize {
+        self.commands.values().map(|commands| commands.len()).sum()
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trace {{ clients: {}, commands: {} }}",
+            self.commands.len(),
+            self.command_count()
+        )
+    }
+}
+
+// Computes the size of the payload of an operation.
+fn payload_size(op: &KVOp) -> usize {
+    match op {
+        KVOp::Put(value)
+        | KVOp::PutWithTtl { value, .. }
+        | KVOp::PutIfAbsent(value)
+        | KVOp::Append(value)
+        | KVOp::Opaque(value)
+        | KVOp::Cas { new: value, .. } => value.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Rifl;
+    use crate::kvs::Value;
+
+    #[test]
+    fn trace_write_and_load() {
+        // command with ops on two shards, submitted to shard 1
+        let rifl = Rifl::new(1, 1);
+        let mut ops: HashMap<_, HashMap<_, _>> = HashMap::new();
+        ops.entry(0)
+            .or_default()
+            .insert(String::from("a"), vec![KVOp::Get]);
+        ops.entry(1).or_default().insert(
+            String::from("b"),
+            vec![KVOp::Put(Value::from(vec![0u8; 10]))],
+        );
+        let cmd = Command::new(rifl, ops);
+        let first = TraceCommand::new(1, 5, 1, &cmd);
+        assert_eq!(
+            first.ops,
+            vec![
+                TraceOp {
+                    key: String::from("b"),
+                    op: String::from("put"),
+                    payload_size: 10,
+                },
+                TraceOp {
+                    key: String::from("a"),
+                    op: String::from("get"),
+                    payload_size: 0,
+                },
+            ]
+        );
+        let second = TraceCommand {
+            client_id: 1,
+            time: 0,
+            consistency: Consistency::Eventual,
+            ops: vec![TraceOp {
+                key: String::from("a"),
+                op: String::from("scan"),
+                payload_size: 0,
+            }],
+        };
+        let third = TraceCommand {
+            client_id: 2,
+            time: 3,
+            ..second.clone()
+        };
+
+        // commands of each client are sorted by the time they were issued
+        let trace = Trace::new(vec![first.clone(), second.clone(), third]);
+        assert_eq!(trace.command_count(), 3);
+        assert_eq!(trace.commands(1), &[second, first]);
+        assert_eq!(trace.commands(3), &[]);
+
+        // check that the trace survives being written and loaded
+        let path = std::env::temp_dir().join("fantoch_trace_write_and_load");
+        trace.write(&path).unwrap();
+        let loaded = Trace::load(&path).unwrap();
+        assert_eq!(loaded, trace);
+
+        // traces with operations that can't be replayed are rejected
+        let mut unreplayable = trace.commands(1)[0].clone();
+        unreplayable.ops[0].op = String::from("opaque");
+        Trace::new(vec![unreplayable]).write(&path).unwrap();
+        assert!(Trace::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,15 +1,127 @@
 use crate::client::key_gen::{KeyGen, KeyGenState};
-use crate::command::Command;
-use crate::id::{RiflGen, ShardId};
-use crate::kvs::{KVOp, Key, Value};
+use crate::client::trace::{Trace, TraceCommand, TraceOp};
+use crate::command::{Command, Consistency, ShardMap};
+use crate::id::{ClientId, RiflGen, ShardId};
+use crate::kvs::{self, KVOp, Key, Value};
 use crate::trace;
 use crate::HashMap;
+use color_eyre::Report;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::iter;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+// tables used when the workload has a metadata table (see
+// `Workload::set_metadata_table`)
+pub const DATA_TABLE: &str = "data";
+pub const METADATA_TABLE: &str = "metadata";
+
+// maximum number of entries returned by the scans generated (see `OpMix`)
+pub const SCAN_LIMIT: usize = 10;
+
+/// Percentage of the commands generated by a `Workload` with each type of
+/// operation (see `Workload::set_op_mix`):
+/// - `get`: `KVOp::Get`s
+/// - `put`: `KVOp::Put`s
+/// - `cas`: `KVOp::Cas`s, which expect a freshly generated value (as clients
+///   don't know the current value of keys), and thus usually fail
+/// - `scan`: `KVOp::Scan`s of up to `SCAN_LIMIT` entries starting at the key
+///   generated (and within its table, if keys are namespaced by a table)
+/// - `delete`: `KVOp::Delete`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpMix {
+    pub get: usize,
+    pub put: usize,
+    pub cas: usize,
+    pub scan: usize,
+    pub delete: usize,
+}
+
+/// Size (in bytes) of the payload of the commands generated by a `Workload`
+/// (see `Workload::set_payload_size`), drawn from one of these distributions:
+/// - `Constant`: always the same size
+/// - `Uniform`: uniformly between `min` and `max` (inclusive)
+/// - `LogNormal`: log-normally, i.e. the natural logarithm of the size is
+///   normally distributed with mean `mu` and standard deviation `sigma` (which
+///   models the skew of object sizes usually found in storage systems)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PayloadSize {
+    Constant(usize),
+    Uniform { min: usize, max: usize },
+    LogNormal { mu: f64, sigma: f64 },
+}
+
+impl PayloadSize {
+    /// Generates a payload size.
+    pub fn gen<R: Rng>(&self, rng: &mut R) -> usize {
+        match *self {
+            Self::Constant(size) => size,
+            Self::Uniform { min, max } => rng.gen_range(min..=max),
+            Self::LogNormal { mu, sigma } => {
+                // draw from a standard normal distribution using the
+                // Box-Muller transform (`1 - u` is in (0, 1], and thus its
+                // logarithm is finite)
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                let normal = (-2.0 * u1.ln()).sqrt()
+                    * (2.0 * std::f64::consts::PI * u2).cos();
+                (mu + sigma * normal).exp().round() as usize
+            }
+        }
+    }
+}
+
+// Payload sizes are represented as they're given to clients: either a
+// constant (e.g. "100"), "uniform,MIN,MAX" or "lognormal,MU,SIGMA".
+impl std::fmt::Display for PayloadSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Constant(size) => write!(f, "{}", size),
+            Self::Uniform { min, max } => write!(f, "uniform,{},{}", min, max),
+            Self::LogNormal { mu, sigma } => {
+                write!(f, "lognormal,{},{}", mu, sigma)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpType {
+    Get,
+    Put,
+    Cas,
+    Scan,
+    Delete,
+}
+
+impl OpMix {
+    fn total(&self) -> usize {
+        self.get + self.put + self.cas + self.scan + self.delete
+    }
+
+    /// Randomly selects an operation type according to the mix.
+    fn gen_op_type<R: Rng>(&self, rng: &mut R) -> OpType {
+        let mut random = rng.gen_range(0..self.total());
+        let types = [
+            (OpType::Get, self.get),
+            (OpType::Put, self.put),
+            (OpType::Cas, self.cas),
+            (OpType::Scan, self.scan),
+            (OpType::Delete, self.delete),
+        ];
+        for (op_type, percentage) in types {
+            if random < percentage {
+                return op_type;
+            }
+            random -= percentage;
+        }
+        unreachable!("the percentages in the mix should add up to 100")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workload {
     /// number of shards
     shard_count: u64,
@@ -21,8 +133,40 @@ pub struct Workload {
     commands_per_client: usize,
     /// percentage of read-only commands
     read_only_percentage: usize,
+    /// percentage of (non read-only) commands that are read-modify-writes
+    rmw_percentage: usize,
+    /// percentage of read-only commands with eventual consistency
+    eventual_read_percentage: usize,
+    /// if set, reads with eventual consistency observe the previous writes of
+    /// the client on the keys read
+    read_your_writes: bool,
+    /// number of keys in the metadata table
+    metadata_table_keys: usize,
+    /// percentage of keys accessed in the metadata table
+    metadata_table_percentage: usize,
+    /// if set, the type of operation in each command
+    op_mix: Option<OpMix>,
     /// size of payload in command (in bytes)
-    payload_size: usize,
+    payload_size: PayloadSize,
+    /// number of commands (the first ones issued) excluded from the metrics
+    warmup_commands: usize,
+    /// time (since the first command was issued) during which started
+    /// commands are excluded from the metrics
+    warmup: Duration,
+    /// if set, only commands ending within this time after the warmup are
+    /// included in the metrics
+    measurement_window: Option<Duration>,
+    /// if set, latencies are also recorded per type of command
+    latency_per_op_type: bool,
+    /// if set, commands are replayed from this trace instead of generated
+    trace: Option<Arc<Trace>>,
+    /// client whose commands in the trace are replayed
+    trace_client: Option<ClientId>,
+    /// if set, the commands issued are recorded (see `TraceCommand`)
+    record_trace: bool,
+    /// if set, the commands generated are determined by this seed (and the
+    /// identifier of the client)
+    seed: Option<u64>,
     /// number of commands already issued in this workload
     command_count: usize,
 }
@@ -36,36 +180,123 @@ impl Workload {
         payload_size: usize,
     ) -> Self {
         // check for valid workloads
-        if let KeyGen::ConflictPool {
-            pool_size,
-            conflict_rate,
-        } = key_gen
-        {
-            assert!(
-                conflict_rate <= 100,
-                "the conflict rate must be less or equal to 100"
-            );
-            assert!(pool_size >= 1, "the pool size should be at least 1");
-            if conflict_rate == 100 && keys_per_command > 1 {
-                panic!("invalid workload; can't generate more than one key when the conflict_rate is 100");
-            }
-            if keys_per_command > 2 {
-                panic!("invalid workload; can't generate more than two keys with the conflict_rate key generator");
-            }
-        }
+        Self::check_key_gen(key_gen, keys_per_command);
         // by default, the read-only percentage is 0
         let read_only_percentage = 0;
+        // by default, the read-modify-write percentage is 0
+        let rmw_percentage = 0;
+        // by default, all commands are linearizable
+        let eventual_read_percentage = 0;
+        // by default, there's no metadata table
+        let metadata_table_keys = 0;
+        let metadata_table_percentage = 0;
+        // by default, the type of operation is given by the read-only and
+        // read-modify-write percentages
+        let op_mix = None;
         Self {
             shard_count: shard_count as u64,
             keys_per_command,
             key_gen,
             commands_per_client,
             read_only_percentage,
-            payload_size,
+            rmw_percentage,
+            eventual_read_percentage,
+            // by default, eventual reads may not observe the client's writes
+            read_your_writes: false,
+            metadata_table_keys,
+            metadata_table_percentage,
+            op_mix,
+            payload_size: PayloadSize::Constant(payload_size),
+            // by default, all commands are measured
+            warmup_commands: 0,
+            warmup: Duration::ZERO,
+            measurement_window: None,
+            latency_per_op_type: false,
+            // by default, commands are generated and not recorded
+            trace: None,
+            trace_client: None,
+            record_trace: false,
+            // by default, the commands generated are not reproducible
+            seed: None,
             command_count: 0,
         }
     }
 
+    /// Creates a workload that replays the trace stored in file `path` (see
+    /// `Trace`): each client issues the commands issued by the client with
+    /// the same identifier in the trace (see `Workload::set_trace_client`),
+    /// with their keys, types of operation and payload sizes, instead of
+    /// generating them. Commands are not issued earlier (since the first
+    /// command was issued) than they were in the trace, except in the
+    /// simulator, which ignores the trace timestamps.
+    pub fn from_trace(
+        shard_count: usize,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Report> {
+        let trace = Trace::load(path)?;
+        // the key generator is not used when replaying a trace, and the
+        // number of commands is set once the client is known
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 0,
+            pool_size: 1,
+        };
+        let mut workload = Self::new(shard_count, key_gen, 1, 0, 0);
+        workload.trace = Some(Arc::new(trace));
+        Ok(workload)
+    }
+
+    /// Returns the trace replayed by this workload (if any).
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_deref()
+    }
+
+    /// Selects client `client_id` as the one whose commands in the trace are
+    /// replayed. This has no effect if the workload doesn't replay a trace.
+    pub fn set_trace_client(&mut self, client_id: ClientId) {
+        if let Some(trace) = &self.trace {
+            let commands = trace.commands(client_id).len();
+            assert!(
+                commands > 0,
+                "the trace has no commands of client {}",
+                client_id
+            );
+            self.commands_per_client = commands;
+            self.trace_client = Some(client_id);
+        }
+    }
+
+    /// Returns the time (in milliseconds since the first command was issued)
+    /// at which the next command was issued in the trace (if the workload
+    /// replays a trace and not all commands have been issued).
+    pub fn next_cmd_time(&self) -> Option<u64> {
+        self.trace_commands()
+            .get(self.command_count)
+            .map(|command| command.time)
+    }
+
+    /// Checks if the commands issued should be recorded.
+    pub fn record_trace(&self) -> bool {
+        self.record_trace
+    }
+
+    /// Sets whether the commands issued should be recorded (see
+    /// `TraceCommand`).
+    pub fn set_record_trace(&mut self, record_trace: bool) {
+        self.record_trace = record_trace;
+    }
+
+    /// Returns the seed of the commands generated (if any).
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Sets the seed of the random number generator of each client (see
+    /// `KeyGenState::set_seed`), so that runs with the same seed generate the
+    /// same commands.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
     /// Returns the number of shards in the system.
     pub fn shard_count(&self) -> usize {
         self.shard_count as usize
@@ -76,6 +307,12 @@ impl Workload {
         self.key_gen
     }
 
+    /// Changes the key generator.
+    pub fn set_key_gen(&mut self, key_gen: KeyGen) {
+        Self::check_key_gen(key_gen, self.keys_per_command);
+        self.key_gen = key_gen;
+    }
+
     /// Returns the total number of commands to be generated by this workload.
     pub fn commands_per_client(&self) -> usize {
         self.commands_per_client
@@ -103,12 +340,195 @@ impl Workload {
         self.read_only_percentage = read_only_percentage;
     }
 
+    /// Returns the percentage of (non read-only) commands to be generated by
+    /// this workload that are read-modify-writes.
+    pub fn rmw_percentage(&self) -> usize {
+        self.rmw_percentage
+    }
+
+    /// Sets the percentage of (non read-only) commands to be generated by
+    /// this workload that are read-modify-writes.
+    pub fn set_rmw_percentage(&mut self, rmw_percentage: usize) {
+        assert!(
+            rmw_percentage <= 100,
+            "the percentage of read-modify-write commands must be less or equal to 100"
+        );
+        self.rmw_percentage = rmw_percentage;
+    }
+
+    /// Returns the percentage of read-only commands to be generated by this
+    /// workload that have eventual consistency.
+    pub fn eventual_read_percentage(&self) -> usize {
+        self.eventual_read_percentage
+    }
+
+    /// Sets the percentage of read-only commands to be generated by this
+    /// workload that have eventual consistency (see `Consistency::Eventual`).
+    pub fn set_eventual_read_percentage(
+        &mut self,
+        eventual_read_percentage: usize,
+    ) {
+        assert!(
+            eventual_read_percentage <= 100,
+            "the percentage of eventual reads must be less or equal to 100"
+        );
+        self.eventual_read_percentage = eventual_read_percentage;
+    }
+
+    /// Checks if reads with eventual consistency observe the previous writes
+    /// of the client.
+    pub fn read_your_writes(&self) -> bool {
+        self.read_your_writes
+    }
+
+    /// Sets whether reads with eventual consistency should observe the
+    /// previous writes of the client on the keys read: each read carries a
+    /// session token (see `Command::set_session`), and it's delayed until the
+    /// local store reflects the writes in the token.
+    pub fn set_read_your_writes(&mut self, read_your_writes: bool) {
+        self.read_your_writes = read_your_writes;
+    }
+
+    /// Returns the number of keys in the metadata table.
+    pub fn metadata_table_keys(&self) -> usize {
+        self.metadata_table_keys
+    }
+
+    /// Returns the percentage of keys accessed in the metadata table.
+    pub fn metadata_table_percentage(&self) -> usize {
+        self.metadata_table_percentage
+    }
+
+    /// Sets a metadata table with `keys` keys, in which `percentage` of the
+    /// keys are accessed (uniformly). Since the metadata table is usually
+    /// small, its keys are hot. All other keys are generated by the key
+    /// generator in the data table. If `percentage` is 0, keys are not
+    /// namespaced by a table.
+    pub fn set_metadata_table(&mut self, keys: usize, percentage: usize) {
+        assert!(
+            percentage <= 100,
+            "the percentage of keys in the metadata table must be less or equal to 100"
+        );
+        assert!(
+            percentage == 0 || keys >= self.keys_per_command,
+            "the metadata table should have at least as many keys as the ones accessed by each command"
+        );
+        self.metadata_table_keys = keys;
+        self.metadata_table_percentage = percentage;
+    }
+
+    /// Returns the operation mix of the commands to be generated by this
+    /// workload (if any).
+    pub fn op_mix(&self) -> Option<OpMix> {
+        self.op_mix
+    }
+
+    /// Sets the operation mix of the commands to be generated by this
+    /// workload: the type of operation in each command (used for all the keys
+    /// it accesses) is selected according to the percentages in `op_mix`.
+    /// The mix replaces the read-only and read-modify-write percentages, and
+    /// thus these should not be set.
+    pub fn set_op_mix(&mut self, op_mix: OpMix) {
+        assert_eq!(
+            op_mix.total(),
+            100,
+            "the percentages in the operation mix must add up to 100"
+        );
+        assert!(
+            self.read_only_percentage == 0 && self.rmw_percentage == 0,
+            "the operation mix can't be combined with the read-only and read-modify-write percentages"
+        );
+        self.op_mix = Some(op_mix);
+    }
+
     /// Returns the payload size of the commands to be generated by this
     /// workload.
-    pub fn payload_size(&self) -> usize {
+    pub fn payload_size(&self) -> PayloadSize {
         self.payload_size
     }
 
+    /// Sets the payload size of the commands to be generated by this
+    /// workload, which may be drawn from some distribution (by default, it's
+    /// the constant given to `Workload::new`).
+    pub fn set_payload_size(&mut self, payload_size: PayloadSize) {
+        match payload_size {
+            PayloadSize::Constant(_) => {}
+            PayloadSize::Uniform { min, max } => assert!(
+                min <= max,
+                "the minimum payload size must be less or equal to the maximum"
+            ),
+            PayloadSize::LogNormal { mu, sigma } => assert!(
+                mu.is_finite() && sigma.is_finite() && sigma >= 0.0,
+                "the log-normal payload size must have a finite mu and a finite, non-negative sigma"
+            ),
+        }
+        self.payload_size = payload_size;
+    }
+
+    /// Returns the number of commands excluded from the metrics for being
+    /// issued during the warmup.
+    pub fn warmup_commands(&self) -> usize {
+        self.warmup_commands
+    }
+
+    /// Returns the duration of the warmup.
+    pub fn warmup(&self) -> Duration {
+        self.warmup
+    }
+
+    /// Returns the measurement window (if any).
+    pub fn measurement_window(&self) -> Option<Duration> {
+        self.measurement_window
+    }
+
+    /// Sets a warmup, excluding from the metrics the first `commands` commands
+    /// issued, and the commands started until `duration` after the first
+    /// command was issued.
+    pub fn set_warmup(&mut self, commands: usize, duration: Duration) {
+        // the number of commands of workloads replaying a trace is only known
+        // once the client is (see `Workload::set_trace_client`)
+        assert!(
+            self.trace.is_some() || commands <= self.commands_per_client,
+            "the number of warmup commands must be less or equal to the number of commands"
+        );
+        self.warmup_commands = commands;
+        self.warmup = duration;
+    }
+
+    /// Sets a measurement window, excluding from the metrics the commands
+    /// ending later than `window` after the warmup ends (see
+    /// `Workload::set_warmup`).
+    pub fn set_measurement_window(&mut self, window: Duration) {
+        self.measurement_window = Some(window);
+    }
+
+    /// Checks if latencies are also recorded per type of command.
+    pub fn latency_per_op_type(&self) -> bool {
+        self.latency_per_op_type
+    }
+
+    /// Sets whether latencies should also be recorded per type of command
+    /// (see `Command::op_type`), and per path taken by the command (see
+    /// `Command::fast_path`).
+    pub fn set_latency_per_op_type(&mut self, latency_per_op_type: bool) {
+        self.latency_per_op_type = latency_per_op_type;
+    }
+
+    /// Checks if a command should be included in the metrics, given its
+    /// sequence (i.e. how many commands were issued until it, inclusive), and
+    /// the time it started and ended (in milliseconds since the first command
+    /// was issued).
+    pub fn measured(&self, sequence: u64, start: u64, end: u64) -> bool {
+        let warmup_end = self.warmup.as_millis() as u64;
+        if sequence <= self.warmup_commands as u64 || start < warmup_end {
+            return false;
+        }
+        match self.measurement_window {
+            Some(window) => end <= warmup_end + window.as_millis() as u64,
+            None => true,
+        }
+    }
+
     /// Generate the next command.
     pub fn next_cmd(
         &mut self,
@@ -119,8 +539,12 @@ impl Workload {
         if self.command_count < self.commands_per_client {
             // increment command count
             self.command_count += 1;
-            // generate new command
-            Some(self.gen_cmd(rifl_gen, key_gen_state))
+            // generate new command (or replay it from the trace)
+            if self.trace.is_some() {
+                Some(self.gen_trace_cmd(rifl_gen, key_gen_state.rng()))
+            } else {
+                Some(self.gen_cmd(rifl_gen, key_gen_state))
+            }
         } else {
             trace!("c{:?}: done!", rifl_gen.source());
             None
@@ -154,21 +578,45 @@ impl Workload {
         // - since we store them in Vec, this ensures that the target shard will
         // be the shard of the first key generated
         let keys = self.gen_unique_keys(key_gen_state);
+        // select the type of operation if there's an operation mix
+        let rng = key_gen_state.rng();
+        let op_type = self.op_mix.map(|op_mix| op_mix.gen_op_type(rng));
         // check if the command should be read-only
-        let read_only = super::key_gen::true_if_random_is_less_than(
-            self.read_only_percentage,
-        );
+        let read_only = match op_type {
+            Some(op_type) => matches!(op_type, OpType::Get | OpType::Scan),
+            None => super::key_gen::true_if_random_is_less_than(
+                self.read_only_percentage,
+                rng,
+            ),
+        };
+        // check if the command should be a read-modify-write
+        let rmw = !read_only
+            && super::key_gen::true_if_random_is_less_than(
+                self.rmw_percentage,
+                rng,
+            );
         let mut target_shard = None;
 
         for key in keys {
             // compute op
-            let op = if read_only {
+            let op = if let Some(op_type) = op_type {
+                self.gen_mix_op(op_type, &key, rng)
+            } else if read_only {
                 // if read-only, the op is a `Get`
                 KVOp::Get
+            } else if rmw {
+                // if read-modify-write, the op is either an `Add` (modeling
+                // a counter) or an `Append` (modeling a log)
+                if super::key_gen::true_if_random_is_less_than(50, rng) {
+                    KVOp::Add(1)
+                } else {
+                    let value = self.gen_cmd_value(rng);
+                    KVOp::Append(value)
+                }
             } else {
                 // if not read-only, the op is a `Put`:
                 // - generate payload for `Put` op
-                let value = self.gen_cmd_value();
+                let value = self.gen_cmd_value(rng);
                 KVOp::Put(value)
             };
             // compute key's shard and save op
@@ -182,13 +630,108 @@ impl Workload {
             target_shard.expect("there should be a target shard");
 
         // create command
-        (target_shard, Command::new(rifl, ops))
+        let mut cmd = Command::new(rifl, ops);
+        // check if the command should have eventual consistency
+        if read_only
+            && super::key_gen::true_if_random_is_less_than(
+                self.eventual_read_percentage,
+                rng,
+            )
+        {
+            cmd.set_consistency(Consistency::Eventual);
+        }
+        (target_shard, cmd)
+    }
+
+    /// Replays the next command in the trace.
+    fn gen_trace_cmd<R: Rng>(
+        &self,
+        rifl_gen: &mut RiflGen,
+        rng: &mut R,
+    ) -> (ShardId, Command) {
+        // generate rifl
+        let rifl = rifl_gen.next_id();
+
+        // the command count was already incremented
+        let command = &self.trace_commands()[self.command_count - 1];
+        let mut ops: HashMap<_, HashMap<_, Vec<_>>> = HashMap::new();
+        let mut target_shard = None;
+        for op in command.ops.iter() {
+            let shard_id = self.shard_id(&op.key);
+            ops.entry(shard_id)
+                .or_default()
+                .entry(op.key.clone())
+                .or_default()
+                .push(self.gen_trace_op(op, rng));
+
+            // target shard is the shard of the first op
+            target_shard = target_shard.or(Some(shard_id));
+        }
+        let target_shard =
+            target_shard.expect("there should be a target shard");
+
+        let mut cmd = Command::new(rifl, ops);
+        cmd.set_consistency(command.consistency);
+        (target_shard, cmd)
+    }
+
+    /// Generates the operation recorded in a trace.
+    fn gen_trace_op<R: Rng>(&self, op: &TraceOp, rng: &mut R) -> KVOp {
+        let mut value = || gen_value(op.payload_size, rng);
+        match op.op.as_str() {
+            "get" => KVOp::Get,
+            "get_versioned" => KVOp::GetVersioned,
+            "put" => KVOp::Put(value()),
+            "delete" => KVOp::Delete,
+            "cas" => KVOp::Cas {
+                expected: value(),
+                new: value(),
+            },
+            "put_if_absent" => KVOp::PutIfAbsent(value()),
+            "add" => KVOp::Add(1),
+            "append" => KVOp::Append(value()),
+            "scan" => KVOp::Scan {
+                end: scan_end(&op.key),
+                limit: SCAN_LIMIT,
+            },
+            other => panic!("operation {} can't be replayed", other),
+        }
+    }
+
+    /// Returns the commands in the trace replayed by this workload (if any).
+    fn trace_commands(&self) -> &[TraceCommand] {
+        match (&self.trace, self.trace_client) {
+            (Some(trace), Some(client_id)) => trace.commands(client_id),
+            _ => &[],
+        }
+    }
+
+    /// Generates an operation of type `op_type` on key `key`.
+    fn gen_mix_op<R: Rng>(
+        &self,
+        op_type: OpType,
+        key: &Key,
+        rng: &mut R,
+    ) -> KVOp {
+        match op_type {
+            OpType::Get => KVOp::Get,
+            OpType::Put => KVOp::Put(self.gen_cmd_value(rng)),
+            OpType::Cas => KVOp::Cas {
+                expected: self.gen_cmd_value(rng),
+                new: self.gen_cmd_value(rng),
+            },
+            OpType::Scan => KVOp::Scan {
+                end: scan_end(key),
+                limit: SCAN_LIMIT,
+            },
+            OpType::Delete => KVOp::Delete,
+        }
     }
 
     fn gen_unique_keys(&self, key_gen_state: &mut KeyGenState) -> Vec<Key> {
         let mut keys = Vec::with_capacity(self.keys_per_command);
         while keys.len() != self.keys_per_command {
-            let key = key_gen_state.gen_cmd_key();
+            let key = self.gen_cmd_key(key_gen_state);
             if !keys.contains(&key) {
                 keys.push(key);
             }
@@ -196,25 +739,81 @@ impl Workload {
         keys
     }
 
+    /// Generates a key, possibly in the metadata table.
+    fn gen_cmd_key(&self, key_gen_state: &mut KeyGenState) -> Key {
+        if self.metadata_table_percentage == 0 {
+            return key_gen_state.gen_cmd_key();
+        }
+        let rng = key_gen_state.rng();
+        if super::key_gen::true_if_random_is_less_than(
+            self.metadata_table_percentage,
+            rng,
+        ) {
+            let key = rng.gen_range(0..self.metadata_table_keys);
+            kvs::table_key(METADATA_TABLE, &key.to_string())
+        } else {
+            kvs::table_key(DATA_TABLE, &key_gen_state.gen_cmd_key())
+        }
+    }
+
     /// Generate a command payload with the payload size provided.
-    fn gen_cmd_value(&self) -> Value {
-        let mut rng = rand::thread_rng();
-        iter::repeat(())
-            .map(|_| rng.sample(Alphanumeric) as char)
-            .take(self.payload_size)
-            .collect()
+    fn gen_cmd_value<R: Rng>(&self, rng: &mut R) -> Value {
+        let size = self.payload_size.gen(rng);
+        gen_value(size, rng)
+    }
+
+    /// Returns the mapping from keys to shards used by this workload.
+    pub fn shard_map(&self) -> ShardMap {
+        ShardMap::new(self.shard_count())
     }
 
     /// Computes which shard the key belongs to.
     fn shard_id(&self, key: &Key) -> ShardId {
-        crate::util::key_hash(key) % self.shard_count
+        self.shard_map().shard_id(key)
+    }
+
+    /// Checks that the key generator can generate `keys_per_command` keys.
+    fn check_key_gen(key_gen: KeyGen, keys_per_command: usize) {
+        if let KeyGen::ConflictPool {
+            pool_size,
+            conflict_rate,
+        } = key_gen
+        {
+            assert!(
+                conflict_rate <= 100,
+                "the conflict rate must be less or equal to 100"
+            );
+            assert!(pool_size >= 1, "the pool size should be at least 1");
+            if conflict_rate == 100 && keys_per_command > 1 {
+                panic!("invalid workload; can't generate more than one key when the conflict_rate is 100");
+            }
+            if keys_per_command > 2 {
+                panic!("invalid workload; can't generate more than two keys with the conflict_rate key generator");
+            }
+        }
     }
 }
 
+// Computes the end of the scan starting at `key`: the scan covers the rest of
+// the key's table (or of all keys, if keys are not namespaced by a table).
+fn scan_end(key: &Key) -> Key {
+    match kvs::split_table_key(key) {
+        Some((table, _)) => kvs::table_range(table).1,
+        None => char::MAX.to_string(),
+    }
+}
+
+// Generates a payload with `size` bytes.
+fn gen_value<R: Rng>(size: usize, rng: &mut R) -> Value {
+    let payload: Vec<u8> = rng.sample_iter(Alphanumeric).take(size).collect();
+    Value::from(payload)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::kvs::KVOp;
+    use crate::HashSet;
 
     const POOL_SIZE: usize = 1;
     // since the pool size is 1, the conflict color must be the following
@@ -347,6 +946,474 @@ mod tests {
         assert!(workload.finished());
     }
 
+    #[test]
+    fn rmw_percentage() {
+        // create rilf gen
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 1;
+        let commands_per_client = 100000;
+        let payload_size = 10;
+        let read_only_percentage = 20;
+        let rmw_percentage = 50;
+
+        // create workload
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 100,
+            pool_size: POOL_SIZE,
+        };
+        let mut workload = Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            commands_per_client,
+            payload_size,
+        );
+        workload.set_read_only_percentage(read_only_percentage);
+        workload.set_rmw_percentage(rmw_percentage);
+        assert_eq!(workload.rmw_percentage(), rmw_percentage);
+        let mut key_gen_state =
+            key_gen.initial_state(workload.shard_count(), client_id);
+
+        // count read-only and read-modify-write commands
+        let mut read_only_count = 0;
+        let mut rmw_count = 0;
+        while let Some((target_shard, cmd)) =
+            workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+        {
+            let (_, ops) = cmd.into_iter(target_shard).next().unwrap();
+            match &ops[0] {
+                KVOp::Get => read_only_count += 1,
+                KVOp::Add(delta) => {
+                    assert_eq!(*delta, 1);
+                    rmw_count += 1;
+                }
+                KVOp::Append(payload) => {
+                    assert_eq!(payload.len(), payload_size);
+                    rmw_count += 1;
+                }
+                KVOp::Put(_) => {}
+                op => panic!("unexpected op generated: {:?}", op),
+            }
+        }
+
+        // compute percentages: rmw commands are 50% of the 80% of commands
+        // that are not read-only
+        let percentage = |count: usize| {
+            ((count * 100) as f64 / commands_per_client as f64).round() as usize
+        };
+        assert_eq!(percentage(read_only_count), read_only_percentage);
+        assert_eq!(percentage(rmw_count), 40);
+    }
+
+    #[test]
+    fn op_mix() {
+        // create rilf gen
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 2;
+        let commands_per_client = 100000;
+        let payload_size = 10;
+        let op_mix = OpMix {
+            get: 50,
+            put: 20,
+            cas: 10,
+            scan: 15,
+            delete: 5,
+        };
+
+        // create workload, with half of the keys in a metadata table
+        let key_gen = KeyGen::Zipf {
+            coefficient: 1.0,
+            total_keys_per_shard: 1000,
+        };
+        let mut workload = Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            commands_per_client,
+            payload_size,
+        );
+        workload.set_metadata_table(10, 50);
+        workload.set_op_mix(op_mix);
+        assert_eq!(workload.op_mix(), Some(op_mix));
+        let mut key_gen_state =
+            key_gen.initial_state(workload.shard_count(), client_id);
+
+        // count commands with each type of operation
+        let mut counts = HashMap::new();
+        while let Some((target_shard, cmd)) =
+            workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+        {
+            let ops: Vec<_> = cmd
+                .into_iter(target_shard)
+                .map(|(key, ops)| (key, ops[0].clone()))
+                .collect();
+            assert_eq!(ops.len(), keys_per_command);
+            // all keys in the command have the same type of operation
+            let op_type = |op: &KVOp| match op {
+                KVOp::Get => "get",
+                KVOp::Put(payload) => {
+                    assert_eq!(payload.len(), payload_size);
+                    "put"
+                }
+                KVOp::Cas { expected, new } => {
+                    assert_eq!(expected.len(), payload_size);
+                    assert_eq!(new.len(), payload_size);
+                    "cas"
+                }
+                KVOp::Scan { end, limit } => {
+                    assert_eq!(*limit, SCAN_LIMIT);
+                    let (_, table_end) = kvs::table_range(METADATA_TABLE);
+                    assert!(*end == table_end || end.starts_with(DATA_TABLE));
+                    "scan"
+                }
+                KVOp::Delete => "delete",
+                op => panic!("unexpected op generated: {:?}", op),
+            };
+            let op_types: HashSet<_> =
+                ops.iter().map(|(_, op)| op_type(op)).collect();
+            assert_eq!(op_types.len(), 1);
+            *counts
+                .entry(op_types.into_iter().next().unwrap())
+                .or_insert(0) += 1;
+        }
+
+        // check percentages
+        let percentage = |op_type: &str| {
+            let count = counts.get(op_type).cloned().unwrap_or_default();
+            ((count * 100) as f64 / commands_per_client as f64).round() as usize
+        };
+        assert_eq!(percentage("get"), op_mix.get);
+        assert_eq!(percentage("put"), op_mix.put);
+        assert_eq!(percentage("cas"), op_mix.cas);
+        assert_eq!(percentage("scan"), op_mix.scan);
+        assert_eq!(percentage("delete"), op_mix.delete);
+    }
+
+    #[test]
+    #[should_panic]
+    fn op_mix_total() {
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 100,
+            pool_size: POOL_SIZE,
+        };
+        let mut workload = Workload::new(1, key_gen, 1, 1, 1);
+        // percentages add up to 90
+        workload.set_op_mix(OpMix {
+            get: 50,
+            put: 40,
+            cas: 0,
+            scan: 0,
+            delete: 0,
+        });
+    }
+
+    #[test]
+    fn payload_size() {
+        // create rilf gen
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 1;
+        let commands_per_client = 100000;
+
+        // payload sizes generated by a workload
+        let mut gen_sizes = |payload_size: PayloadSize| {
+            let key_gen = KeyGen::ConflictPool {
+                conflict_rate: 100,
+                pool_size: POOL_SIZE,
+            };
+            let mut workload = Workload::new(
+                shard_count,
+                key_gen,
+                keys_per_command,
+                commands_per_client,
+                0,
+            );
+            workload.set_payload_size(payload_size);
+            assert_eq!(workload.payload_size(), payload_size);
+            let mut key_gen_state =
+                key_gen.initial_state(workload.shard_count(), client_id);
+            let mut sizes = Vec::with_capacity(commands_per_client);
+            while let Some((target_shard, cmd)) =
+                workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+            {
+                let (_, ops) = cmd.into_iter(target_shard).next().unwrap();
+                match &ops[0] {
+                    KVOp::Put(payload) => sizes.push(payload.len()),
+                    op => panic!("unexpected op generated: {:?}", op),
+                }
+            }
+            sizes.sort_unstable();
+            sizes
+        };
+
+        // constant
+        let sizes = gen_sizes(PayloadSize::Constant(10));
+        assert!(sizes.iter().all(|size| *size == 10));
+
+        // uniform: all sizes in the range are generated
+        let sizes = gen_sizes(PayloadSize::Uniform { min: 10, max: 20 });
+        assert_eq!(sizes.first(), Some(&10));
+        assert_eq!(sizes.last(), Some(&20));
+        let distinct: HashSet<_> = sizes.iter().collect();
+        assert_eq!(distinct.len(), 11);
+
+        // log-normal: the median is (roughly) e^mu, and the mean is
+        // e^(mu + sigma^2 / 2)
+        let mu = 5.0f64;
+        let sigma = 1.0f64;
+        let sizes = gen_sizes(PayloadSize::LogNormal { mu, sigma });
+        let median = sizes[sizes.len() / 2] as f64;
+        let expected_median = mu.exp();
+        assert!((median - expected_median).abs() / expected_median < 0.05);
+        let mean = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+        let expected_mean = (mu + sigma * sigma / 2.0).exp();
+        assert!((mean - expected_mean).abs() / expected_mean < 0.05);
+    }
+
+    #[test]
+    fn measured() {
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 100,
+            pool_size: POOL_SIZE,
+        };
+        let mut workload = Workload::new(1, key_gen, 1, 100, 1);
+
+        // by default, all commands are measured
+        assert!(workload.measured(1, 0, 0));
+
+        // with a warmup of 10 commands and 100ms, commands are only measured
+        // once both end
+        workload.set_warmup(10, Duration::from_millis(100));
+        assert!(!workload.measured(10, 200, 210));
+        assert!(!workload.measured(11, 99, 210));
+        assert!(workload.measured(11, 100, 210));
+
+        // with a measurement window of 50ms, commands ending after 150ms are
+        // not measured
+        workload.set_measurement_window(Duration::from_millis(50));
+        assert!(workload.measured(11, 100, 150));
+        assert!(!workload.measured(11, 100, 151));
+    }
+
+    #[test]
+    fn trace_replay() {
+        // trace with two commands of client 1 (one of which on two shards),
+        // and a command of client 2
+        let get = |key: &str| TraceOp {
+            key: key.to_string(),
+            op: String::from("get"),
+            payload_size: 0,
+        };
+        let put = |key: &str, payload_size| TraceOp {
+            key: key.to_string(),
+            op: String::from("put"),
+            payload_size,
+        };
+        let first = TraceCommand {
+            client_id: 1,
+            time: 0,
+            consistency: Consistency::Eventual,
+            ops: vec![get("a")],
+        };
+        let second = TraceCommand {
+            client_id: 1,
+            time: 10,
+            consistency: Consistency::Linearizable,
+            ops: vec![put("b", 10), put("c", 20)],
+        };
+        let third = TraceCommand {
+            client_id: 2,
+            ..first.clone()
+        };
+        let trace = Trace::new(vec![second, first, third]);
+
+        // create rifl gen and key gen state (not used when replaying)
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+        let mut key_gen_state = KeyGen::ConflictPool {
+            conflict_rate: 100,
+            pool_size: POOL_SIZE,
+        }
+        .initial_state(1, client_id);
+
+        let path = std::env::temp_dir().join("fantoch_trace_replay");
+        trace.write(&path).unwrap();
+        let shard_count = 2;
+        let mut workload = Workload::from_trace(shard_count, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        workload.set_trace_client(client_id);
+        assert_eq!(workload.commands_per_client(), 2);
+
+        // the first command is an eventual get
+        assert_eq!(workload.next_cmd_time(), Some(0));
+        let (target_shard, cmd) = workload
+            .next_cmd(&mut rifl_gen, &mut key_gen_state)
+            .unwrap();
+        assert_eq!(target_shard, workload.shard_id(&String::from("a")));
+        assert_eq!(cmd.consistency(), Consistency::Eventual);
+        assert_eq!(cmd.op_type(), "get");
+        assert_eq!(cmd.total_key_count(), 1);
+
+        // the second command puts payloads with the recorded sizes
+        assert_eq!(workload.next_cmd_time(), Some(10));
+        let (target_shard, cmd) = workload
+            .next_cmd(&mut rifl_gen, &mut key_gen_state)
+            .unwrap();
+        assert_eq!(target_shard, workload.shard_id(&String::from("b")));
+        assert_eq!(cmd.consistency(), Consistency::Linearizable);
+        let mut payload_sizes: Vec<_> = cmd
+            .shards()
+            .flat_map(|shard_id| cmd.iter(*shard_id))
+            .flat_map(|(key, ops)| {
+                ops.iter().map(move |op| match op {
+                    KVOp::Put(value) => (key.clone(), value.len()),
+                    op => panic!("unexpected op: {:?}", op),
+                })
+            })
+            .collect();
+        payload_sizes.sort();
+        assert_eq!(
+            payload_sizes,
+            vec![(String::from("b"), 10), (String::from("c"), 20)]
+        );
+
+        // then the workload is finished
+        assert_eq!(workload.next_cmd_time(), None);
+        assert!(workload.finished());
+        assert!(workload
+            .next_cmd(&mut rifl_gen, &mut key_gen_state)
+            .is_none());
+    }
+
+    #[test]
+    fn eventual_read_percentage() {
+        // create rilf gen
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 1;
+        let commands_per_client = 100000;
+        let payload_size = 10;
+        let read_only_percentage = 50;
+        let eventual_read_percentage = 40;
+
+        // create workload
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 100,
+            pool_size: POOL_SIZE,
+        };
+        let mut workload = Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            commands_per_client,
+            payload_size,
+        );
+        workload.set_read_only_percentage(read_only_percentage);
+        workload.set_eventual_read_percentage(eventual_read_percentage);
+        assert_eq!(
+            workload.eventual_read_percentage(),
+            eventual_read_percentage
+        );
+        let mut key_gen_state =
+            key_gen.initial_state(workload.shard_count(), client_id);
+
+        // count commands with eventual consistency, which should all be
+        // read-only
+        let mut eventual_count = 0;
+        while let Some((_, cmd)) =
+            workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+        {
+            if cmd.consistency() == Consistency::Eventual {
+                assert!(cmd.local_read());
+                eventual_count += 1;
+            }
+        }
+
+        // compute percentage: eventual reads are 40% of the 50% of commands
+        // that are read-only
+        let percentage = ((eventual_count * 100) as f64
+            / commands_per_client as f64)
+            .round() as usize;
+        assert_eq!(percentage, 20);
+    }
+
+    #[test]
+    fn metadata_table() {
+        // create rilf gen
+        let client_id = 1;
+        let mut rifl_gen = RiflGen::new(client_id);
+
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 1;
+        let commands_per_client = 100000;
+        let payload_size = 10;
+        let metadata_table_keys = 4;
+        let metadata_table_percentage = 30;
+
+        // create workload where data keys never conflict
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 0,
+            pool_size: POOL_SIZE,
+        };
+        let mut workload = Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            commands_per_client,
+            payload_size,
+        );
+        workload
+            .set_metadata_table(metadata_table_keys, metadata_table_percentage);
+        assert_eq!(workload.metadata_table_keys(), metadata_table_keys);
+        assert_eq!(
+            workload.metadata_table_percentage(),
+            metadata_table_percentage
+        );
+        let mut key_gen_state =
+            key_gen.initial_state(workload.shard_count(), client_id);
+
+        // count keys in each table
+        let mut metadata_keys = HashMap::new();
+        let mut data_count = 0;
+        while let Some((target_shard, cmd)) =
+            workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+        {
+            for key in cmd.keys(target_shard) {
+                match kvs::split_table_key(key) {
+                    Some((METADATA_TABLE, key)) => {
+                        *metadata_keys.entry(key.to_string()).or_insert(0) += 1
+                    }
+                    Some((DATA_TABLE, _)) => data_count += 1,
+                    _ => panic!("unexpected key generated: {:?}", key),
+                }
+            }
+        }
+
+        // all metadata keys are accessed, and the percentage of keys in the
+        // metadata table is (roughly) `metadata_table_percentage`
+        assert_eq!(metadata_keys.len(), metadata_table_keys);
+        let metadata_count: usize = metadata_keys.values().sum();
+        let total = (commands_per_client * keys_per_command) as f64;
+        let percentage = (metadata_count * 100) as f64 / total;
+        assert!((percentage - metadata_table_percentage as f64).abs() < 1.0);
+        assert_eq!(metadata_count + data_count, total as usize);
+    }
+
     #[test]
     fn conflict_rate() {
         for conflict_rate in vec![1, 2, 10, 50] {
@@ -449,4 +1516,61 @@ mod tests {
         // - 1 key in shard 1
         cmd.key_count(0) == 1 && cmd.key_count(1) == 1
     }
+
+    #[test]
+    fn seed() {
+        // general config
+        let shard_count = 1;
+        let keys_per_command = 1;
+        let commands_per_client = 100;
+
+        // commands generated by a client with a seeded workload
+        let gen_cmds = |client_id: ClientId, seed: u64| {
+            let key_gen = KeyGen::Zipf {
+                coefficient: 1.0,
+                total_keys_per_shard: 1000,
+            };
+            let mut workload = Workload::new(
+                shard_count,
+                key_gen,
+                keys_per_command,
+                commands_per_client,
+                0,
+            );
+            workload.set_op_mix(OpMix {
+                get: 20,
+                put: 20,
+                cas: 20,
+                scan: 20,
+                delete: 20,
+            });
+            workload.set_payload_size(PayloadSize::Uniform { min: 1, max: 10 });
+            workload.set_seed(seed);
+            assert_eq!(workload.seed(), Some(seed));
+
+            let mut rifl_gen = RiflGen::new(client_id);
+            let mut key_gen_state =
+                key_gen.initial_state(workload.shard_count(), client_id);
+            key_gen_state.set_seed(seed);
+            let mut cmds = Vec::with_capacity(commands_per_client);
+            while let Some((_, cmd)) =
+                workload.next_cmd(&mut rifl_gen, &mut key_gen_state)
+            {
+                cmds.push(cmd);
+            }
+            cmds
+        };
+
+        // the same client with the same seed generates the same commands
+        let cmds = gen_cmds(1, 42);
+        assert!(gen_cmds(1, 42) == cmds);
+
+        // but not with another seed, or another client with the same seed
+        assert!(gen_cmds(1, 43) != cmds);
+        let other_cmds = gen_cmds(2, 42);
+        assert!(other_cmds
+            .iter()
+            .zip(cmds.iter())
+            .any(|(other, cmd)| other.keys(0).ne(cmd.keys(0))));
+    }
 }
@@ -1,7 +1,10 @@
 use crate::executor::ExecutorResult;
 use crate::id::{Rifl, ShardId};
-use crate::kvs::{KVOp, KVOpResult, KVStore, Key};
-use crate::HashMap;
+use crate::kvs::{
+    self, KVOp, KVOpError, KVOpResult, KVStore, Key, Value, Version,
+};
+use crate::util;
+use crate::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Debug};
 use std::iter::FromIterator;
@@ -9,6 +12,60 @@ use std::sync::Arc;
 
 pub const DEFAULT_SHARD_ID: ShardId = 0;
 
+/// Mapping from keys to the shard that replicates them, shared by clients,
+/// protocols and the run layer so that all of them route keys consistently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardMap {
+    shard_count: u64,
+}
+
+impl ShardMap {
+    /// Creates a new `ShardMap` with `shard_count` shards.
+    pub fn new(shard_count: usize) -> Self {
+        assert!(shard_count > 0, "there should be at least one shard");
+        Self {
+            shard_count: shard_count as u64,
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shard_count as usize
+    }
+
+    /// Computes which shard the key belongs to.
+    pub fn shard_id(&self, key: &Key) -> ShardId {
+        util::key_hash(key) % self.shard_count
+    }
+}
+
+/// Consistency level requested by a command. Levels are ordered from the
+/// weakest to the strongest.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum Consistency {
+    /// Read-only commands are answered immediately from the local store of
+    /// the process they're submitted to, and thus may observe stale values.
+    /// Commands that write are ordered as if they were linearizable.
+    Eventual,
+    /// Currently served as `Linearizable`.
+    Sequential,
+    /// Commands take the full protocol path.
+    #[default]
+    Linearizable,
+}
+
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Command {
     rifl: Rifl,
@@ -16,6 +73,25 @@ pub struct Command {
     // mapping from shard to the keys on that shard; this will be used by
     // `Tempo` to exchange `MStable` messages between shards
     shard_to_keys: Arc<HashMap<ShardId, Vec<Key>>>,
+    // whether all ops are read-only; this is computed once, when the command
+    // is created, so that protocols and executors can check it on the hot
+    // path without inspecting every op
+    read_only: bool,
+    // consistency level requested by the client
+    consistency: Consistency,
+    // mapping from key to the last write on it acknowledged to the client,
+    // that local reads should observe (see `Command::set_session`); it's
+    // empty for most commands, and so it's `Arc`ed to keep commands small
+    session: Arc<HashMap<Key, Rifl>>,
+    // whether the command was committed in the fast path; this is only known
+    // by its coordinator (see `Command::set_fast_path`)
+    fast_path: Option<bool>,
+    // time (in milliseconds) at which the command was submitted to its
+    // coordinator (see `Command::set_submit_time`)
+    submit_time: Option<u64>,
+    // logical time at which the command is executed, assigned by its
+    // coordinator (see `Command::set_logical_time`)
+    logical_time: Option<u64>,
     // field used to output and empty iterator of keys when rustc can't figure
     // out what we mean
     _empty_keys: HashMap<Key, Arc<Vec<KVOp>>>,
@@ -28,6 +104,13 @@ impl Command {
         shard_to_ops: HashMap<ShardId, HashMap<Key, Vec<KVOp>>>,
     ) -> Self {
         let mut shard_to_keys: HashMap<ShardId, Vec<Key>> = Default::default();
+        // a command is read-only if all ops are read-only (i.e. `Get`s and
+        // `Scan`s)
+        let read_only = shard_to_ops.values().all(|shard_ops| {
+            shard_ops
+                .values()
+                .all(|ops| ops.iter().all(KVOp::is_read_only))
+        });
         let shard_to_ops = shard_to_ops
             .into_iter()
             .map(|(shard_id, shard_ops)| {
@@ -53,6 +136,12 @@ impl Command {
             rifl,
             shard_to_ops,
             shard_to_keys: Arc::new(shard_to_keys),
+            read_only,
+            consistency: Consistency::default(),
+            session: Default::default(),
+            fast_path: None,
+            submit_time: None,
+            logical_time: None,
             _empty_keys: HashMap::new(),
         }
     }
@@ -71,19 +160,190 @@ impl Command {
         Self::new(rifl, shard_to_ops)
     }
 
+    /// Create a new `Command` that returns up to `limit` entries whose keys are
+    /// between `start_key` (inclusive) and `end_key` (exclusive).
+    pub fn scan(
+        rifl: Rifl,
+        start_key: Key,
+        end_key: Key,
+        limit: usize,
+    ) -> Self {
+        let op = KVOp::Scan {
+            end: end_key,
+            limit,
+        };
+        Self::from(rifl, std::iter::once((start_key, op)))
+    }
+
+    /// Create a new transactional `Command` that reads the keys in `read_set`
+    /// and writes the key-value pairs in `write_set`, with keys placed in
+    /// shards according to `shard_map`. The command executes atomically and
+    /// all its reads observe the state before any of its writes, even if a
+    /// key is both read and written.
+    pub fn transaction<R, W>(
+        shard_map: &ShardMap,
+        rifl: Rifl,
+        read_set: R,
+        write_set: W,
+    ) -> Self
+    where
+        R: IntoIterator<Item = Key>,
+        W: IntoIterator<Item = (Key, Value)>,
+    {
+        // reads are added first so that, on keys that are also written, they
+        // are executed before the writes
+        let ops = read_set.into_iter().map(|key| (key, KVOp::Get)).chain(
+            write_set
+                .into_iter()
+                .map(|(key, value)| (key, KVOp::Put(value))),
+        );
+        let mut shard_to_ops: HashMap<ShardId, HashMap<Key, Vec<KVOp>>> =
+            HashMap::new();
+        for (key, op) in ops {
+            shard_to_ops
+                .entry(shard_map.shard_id(&key))
+                .or_default()
+                .entry(key)
+                .or_default()
+                .push(op);
+        }
+        Self::new(rifl, shard_to_ops)
+    }
+
+    /// Create a new `Command` that applies an opaque `payload`, interpreted by
+    /// the `StateMachine` of the store, to each key in `keys`. These keys are
+    /// also the keys used to detect conflicts with other commands.
+    pub fn opaque<K>(rifl: Rifl, keys: K, payload: Value) -> Self
+    where
+        K: IntoIterator<Item = Key>,
+    {
+        let ops = keys
+            .into_iter()
+            .map(|key| (key, KVOp::Opaque(payload.clone())));
+        Self::from(rifl, ops)
+    }
+
     /// Checks if the NFR optimization can be applied.
     pub fn nfr_allowed(&self) -> bool {
-        self.read_only() && self.total_key_count() == 1
+        // scans read more than one key, and thus NFR can't be applied
+        self.read_only() && self.total_key_count() == 1 && !self.has_scans()
     }
 
     /// Checks if the command is read-only.
     pub fn read_only(&self) -> bool {
-        // a command is read-only if all ops are `Get`s
-        self.shard_to_ops.values().all(|shard_ops| {
-            shard_ops
-                .values()
-                .all(|ops| ops.iter().all(|op| op == &KVOp::Get))
-        })
+        self.read_only
+    }
+
+    /// Returns the consistency level requested by the command.
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+
+    /// Sets the consistency level requested by the command.
+    pub fn set_consistency(&mut self, consistency: Consistency) {
+        self.consistency = consistency;
+    }
+
+    /// Returns the session token of the command (see `Command::set_session`).
+    pub fn session(&self) -> &HashMap<Key, Rifl> {
+        &self.session
+    }
+
+    /// Sets the session token of the command: a mapping from keys to the last
+    /// write on each of them acknowledged to the client. If the command is a
+    /// local read, the read on each of these keys waits until the local store
+    /// reflects that write, and thus clients read their own writes.
+    pub fn set_session(&mut self, session: HashMap<Key, Rifl>) {
+        self.session = Arc::new(session);
+    }
+
+    /// Returns whether the command was committed in the fast path, if known.
+    pub fn fast_path(&self) -> Option<bool> {
+        self.fast_path
+    }
+
+    /// Records whether the command was committed in the fast path. This is set
+    /// by the coordinator of the command once it picks a path, so that its
+    /// executors report it to the client (see `ExecutionTimestamps`).
+    pub fn set_fast_path(&mut self, fast_path: bool) {
+        self.fast_path = Some(fast_path);
+    }
+
+    /// Returns the time (in milliseconds) at which the command was submitted
+    /// to its coordinator, if known.
+    pub fn submit_time(&self) -> Option<u64> {
+        self.submit_time
+    }
+
+    /// Records the time at which the command was submitted. This is set by
+    /// the coordinator of the command when it's submitted, so that its
+    /// executors report it to the client (see `ExecutionTimestamps`).
+    pub fn set_submit_time(&mut self, submit_time: u64) {
+        self.submit_time = Some(submit_time);
+    }
+
+    /// Returns the logical time at which the command is executed, if it was
+    /// already assigned.
+    pub fn logical_time(&self) -> Option<u64> {
+        self.logical_time
+    }
+
+    /// Records the logical time at which the command is executed. This is set
+    /// by the coordinator of the command when it's submitted (see
+    /// `BaseProcess::next_logical_time`) and, as it's part of the command, all
+    /// replicas execute the command at the same time. Executors whose commands
+    /// have no timestamp use it to expire values (see `KVStore::set_time_on`).
+    pub fn set_logical_time(&mut self, logical_time: u64) {
+        self.logical_time = Some(logical_time);
+    }
+
+    /// Checks if the command can be answered from the local store, without
+    /// going through the protocol. This is the case for read-only commands
+    /// with `Consistency::Eventual`.
+    pub fn local_read(&self) -> bool {
+        self.read_only && self.consistency == Consistency::Eventual
+    }
+
+    /// Checks if the command has some `Scan` (on any shard).
+    pub fn has_scans(&self) -> bool {
+        self.shard_to_ops
+            .keys()
+            .any(|shard_id| self.has_scans_on(*shard_id))
+    }
+
+    /// Checks if the command has some `Scan` on the shard provided.
+    pub fn has_scans_on(&self, shard_id: ShardId) -> bool {
+        self.iter(shard_id)
+            .any(|(_, ops)| ops.iter().any(KVOp::is_scan))
+    }
+
+    /// Checks if the command has some op that may fail on the shard provided
+    /// (see `KVOp::is_conditional`). The ops of such commands should be
+    /// executed with `Command::execute`, so that either all or none of them
+    /// is executed.
+    pub fn has_conditions_on(&self, shard_id: ShardId) -> bool {
+        self.iter(shard_id)
+            .any(|(_, ops)| ops.iter().any(KVOp::is_conditional))
+    }
+
+    /// Checks if the command has some `Validate` on the shard provided.
+    pub fn has_validations_on(&self, shard_id: ShardId) -> bool {
+        self.iter(shard_id)
+            .any(|(_, ops)| ops.iter().any(KVOp::is_validate))
+    }
+
+    /// Returns the keys read by this command on the shard provided.
+    pub fn read_set(&self, shard_id: ShardId) -> impl Iterator<Item = &Key> {
+        self.iter(shard_id)
+            .filter(|(_, ops)| ops.iter().any(KVOp::is_read_only))
+            .map(|(key, _)| key)
+    }
+
+    /// Returns the keys written by this command on the shard provided.
+    pub fn write_set(&self, shard_id: ShardId) -> impl Iterator<Item = &Key> {
+        self.iter(shard_id)
+            .filter(|(_, ops)| !ops.iter().all(KVOp::is_read_only))
+            .map(|(key, _)| key)
     }
 
     /// Checks if the command is replicated by `shard_id`.
@@ -137,26 +397,116 @@ impl Command {
         self.shard_to_ops.len()
     }
 
+    /// Returns the name of the type of the operations in this command, or
+    /// "mixed" if the command has operations of different types.
+    pub fn op_type(&self) -> &'static str {
+        let mut names = self
+            .shard_to_ops
+            .values()
+            .flat_map(|shard_ops| shard_ops.values())
+            .flat_map(|ops| ops.iter())
+            .map(|op| op.name());
+        match names.next() {
+            Some(name) if names.all(|other| other == name) => name,
+            Some(_) => "mixed",
+            None => "empty",
+        }
+    }
+
     /// Returns the shards accessed by this command.
     pub fn shards(&self) -> impl Iterator<Item = &ShardId> {
         self.shard_to_ops.keys()
     }
 
+    /// Returns the shards that replicate the keys accessed by this command,
+    /// according to `shard_map`. Scans are mapped to the shard of their start
+    /// key.
+    pub fn key_shards(&self, shard_map: &ShardMap) -> HashSet<ShardId> {
+        self.shard_to_ops
+            .values()
+            .flat_map(|shard_ops| shard_ops.keys())
+            .map(|key| shard_map.shard_id(key))
+            .collect()
+    }
+
+    /// Splits self into one command per shard, according to `shard_map`. Each
+    /// command has the same identifier, consistency level and session token
+    /// (restricted to its keys) as self, and only accesses keys in its shard.
+    pub fn split(self, shard_map: &ShardMap) -> HashMap<ShardId, Command> {
+        let rifl = self.rifl;
+        let consistency = self.consistency;
+        let session = self.session;
+        let mut shard_to_ops: HashMap<ShardId, HashMap<Key, Vec<KVOp>>> =
+            HashMap::new();
+        for (key, ops) in self.shard_to_ops.into_values().flatten() {
+            // take the ops inside the arc if we're the last with a
+            // reference to it (otherwise, clone them)
+            let ops =
+                Arc::try_unwrap(ops).unwrap_or_else(|ops| ops.as_ref().clone());
+            shard_to_ops
+                .entry(shard_map.shard_id(&key))
+                .or_default()
+                .entry(key)
+                .or_default()
+                .extend(ops);
+        }
+        shard_to_ops
+            .into_iter()
+            .map(|(shard_id, ops)| {
+                let shard_to_ops =
+                    HashMap::from_iter(std::iter::once((shard_id, ops)));
+                let mut cmd = Command::new(rifl, shard_to_ops);
+                cmd.set_consistency(consistency);
+                cmd.set_session(
+                    session
+                        .iter()
+                        .filter(|(key, _)| shard_map.shard_id(key) == shard_id)
+                        .map(|(key, rifl)| (key.clone(), *rifl))
+                        .collect(),
+                );
+                (shard_id, cmd)
+            })
+            .collect()
+    }
+
     /// Executes self in a `KVStore`, returning the resulting an iterator of
-    /// `ExecutorResult`.
+    /// `ExecutorResult`. The ops on keys that belong to `shard_id` are
+    /// executed atomically: if some op would fail (e.g. a `Cas` or a
+    /// `Validate`), none of them is executed. Since ops are checked against
+    /// the state before the command, validations should precede writes on
+    /// the same key.
     pub fn execute<'a>(
         self,
         shard_id: ShardId,
         store: &'a mut KVStore,
     ) -> impl Iterator<Item = ExecutorResult> + 'a {
         let rifl = self.rifl;
+        let read_only = self.read_only;
+        let mut failures: HashMap<_, _> = self
+            .iter(shard_id)
+            .filter_map(|(key, ops)| {
+                let failure = store.check(key, ops, rifl)?;
+                Some((key.clone(), failure))
+            })
+            .collect();
+        let aborted = !failures.is_empty();
         self.into_iter(shard_id).map(move |(key, ops)| {
+            if aborted {
+                let failure = failures.remove(&key);
+                let partial_results = kvs::aborted(ops.len(), failure);
+                return ExecutorResult::new(rifl, key, partial_results);
+            }
             // take the ops inside the arc if we're the last with a
             // reference to it (otherwise, clone them)
             let ops =
                 Arc::try_unwrap(ops).unwrap_or_else(|ops| ops.as_ref().clone());
-            // execute this op
-            let partial_results = store.execute(&key, ops, rifl);
+            // execute this op (if the command is read-only, so are its ops
+            // on each key, and thus there's no need to inspect them)
+            let partial_results = if read_only {
+                store.execute_as(&key, ops, rifl, true)
+            } else {
+                store.execute(&key, ops, rifl)
+            };
             ExecutorResult::new(rifl, key, partial_results)
         })
     }
@@ -183,25 +533,48 @@ impl Command {
             .unwrap_or_else(|| self._empty_keys.into_iter())
     }
 
-    /// Checks if a command conflicts with another given command.
+    /// Checks if a command conflicts with another given command, i.e. if
+    /// both access some key and one of them writes it (see
+    /// `KVOp::is_read_only`). Conditional ops (such as `KVOp::Cas` and
+    /// `KVOp::PutIfAbsent`) are writes, and thus conflict with any op on the
+    /// same key.
     pub fn conflicts(&self, other: &Command) -> bool {
         self.shard_to_ops.iter().any(|(shard_id, shard_ops)| {
-            shard_ops
-                .iter()
-                .any(|(key, _)| other.contains_key(*shard_id, key))
+            // scans conflict with all writes on the same shard
+            if self.has_scans_on(*shard_id) && other.writes_on(*shard_id)
+                || other.has_scans_on(*shard_id) && self.writes_on(*shard_id)
+            {
+                return true;
+            }
+            shard_ops.iter().any(|(key, ops)| {
+                let writes = !ops.iter().all(KVOp::is_read_only);
+                match other.key_ops(*shard_id, key) {
+                    Some(other_ops) => {
+                        writes || !other_ops.iter().all(KVOp::is_read_only)
+                    }
+                    None => false,
+                }
+            })
         })
     }
 
-    /// Checks if `key` is accessed by this command.
-    fn contains_key(&self, shard_id: ShardId, key: &Key) -> bool {
+    /// Checks if the command writes some key on the shard provided.
+    fn writes_on(&self, shard_id: ShardId) -> bool {
+        self.write_set(shard_id).next().is_some()
+    }
+
+    /// Returns the ops of this command on `key` (if it's accessed).
+    fn key_ops(&self, shard_id: ShardId, key: &Key) -> Option<&Arc<Vec<KVOp>>> {
         self.shard_to_ops
             .get(&shard_id)
-            .map(|shard_ops| shard_ops.contains_key(key))
-            .unwrap_or(false)
+            .and_then(|shard_ops| shard_ops.get(key))
     }
 
     /// Adds the operations in the `other` command to this command.
     pub fn merge(&mut self, other: Command) {
+        self.read_only = self.read_only && other.read_only;
+        // the merged command gets the strongest of both consistency levels
+        self.consistency = self.consistency.max(other.consistency);
         for (shard_id, shard_ops) in other.shard_to_ops {
             let current_shard_ops =
                 self.shard_to_ops.entry(shard_id).or_default();
@@ -227,12 +600,85 @@ impl fmt::Debug for Command {
     }
 }
 
+/// Times (in milliseconds) at which a command was submitted, committed and
+/// executed. The submit time is the one recorded by the coordinator of the
+/// command (see `Command::submit_time`), while the others are seen by the
+/// executor that executed it. The executors at the coordinator of the command
+/// also report whether it was committed in the fast path.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct ExecutionTimestamps {
+    pub submit: Option<u64>,
+    pub commit: u64,
+    pub execute: u64,
+    pub fast_path: Option<bool>,
+}
+
+impl ExecutionTimestamps {
+    /// Creates a new `ExecutionTimestamps`.
+    pub fn new(commit: u64, execute: u64) -> Self {
+        Self {
+            submit: None,
+            commit,
+            execute,
+            fast_path: None,
+        }
+    }
+
+    /// Sets the time at which the command was submitted, if known (see
+    /// `Command::submit_time`).
+    pub fn with_submit(mut self, submit: Option<u64>) -> Self {
+        self.submit = submit;
+        self
+    }
+
+    /// Sets whether the command was committed in the fast path, if known (see
+    /// `Command::fast_path`).
+    pub fn with_fast_path(mut self, fast_path: Option<bool>) -> Self {
+        self.fast_path = fast_path;
+        self
+    }
+
+    /// Merges the timestamps of another partial result. A command is only
+    /// committed (resp. executed) once it's committed (resp. executed) on all
+    /// keys/shards it accesses, and thus the latest timestamps are kept. On
+    /// the other hand, the command was submitted as soon as it was submitted
+    /// on some shard, and thus the earliest submit timestamp is kept.
+    /// Similarly, a command only took the fast path if it did so on all
+    /// shards reporting a path.
+    pub fn merge(&mut self, other: Self) {
+        self.submit = match (self.submit, other.submit) {
+            (Some(submit), Some(other)) => Some(std::cmp::min(submit, other)),
+            (submit, other) => submit.or(other),
+        };
+        self.commit = std::cmp::max(self.commit, other.commit);
+        self.execute = std::cmp::max(self.execute, other.execute);
+        self.fast_path = match (self.fast_path, other.fast_path) {
+            (Some(fast_path), Some(other)) => Some(fast_path && other),
+            (fast_path, other) => fast_path.or(other),
+        };
+    }
+
+    /// Returns the time between submit and commit (in milliseconds), if the
+    /// submit time is known.
+    pub fn commit_latency(&self) -> Option<u64> {
+        self.submit.map(|submit| self.commit.saturating_sub(submit))
+    }
+
+    /// Returns the time between commit and execution (in milliseconds).
+    pub fn execution_delay(&self) -> u64 {
+        self.execute.saturating_sub(self.commit)
+    }
+}
+
 /// Structure that aggregates partial results of multi-key commands.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CommandResultBuilder {
     rifl: Rifl,
     key_count: usize,
     results: HashMap<Key, Vec<KVOpResult>>,
+    timestamps: Option<ExecutionTimestamps>,
 }
 
 impl CommandResultBuilder {
@@ -243,17 +689,35 @@ impl CommandResultBuilder {
             rifl,
             key_count,
             results: HashMap::new(),
+            timestamps: None,
         }
     }
 
-    /// Adds a partial command result to the overall result.
-    /// Returns a boolean indicating whether the full result is ready.
-    pub fn add_partial(&mut self, key: Key, partial_results: Vec<KVOpResult>) {
-        // add op result for `key`
-        let res = self.results.insert(key, partial_results);
+    /// Adds a partial command result to the overall result. Returns a boolean
+    /// indicating whether it was added: partial results on a key that already
+    /// has one are ignored, which happens if the command was executed more
+    /// than once (e.g. when submitted to more than one process, in which case
+    /// executors should deduplicate it; see
+    /// `Config::set_executor_dedup_window`).
+    pub fn add_partial(
+        &mut self,
+        key: Key,
+        partial_results: Vec<KVOpResult>,
+    ) -> bool {
+        if self.results.contains_key(&key) {
+            false
+        } else {
+            self.results.insert(key, partial_results);
+            true
+        }
+    }
 
-        // assert there was nothing about this `key` previously
-        assert!(res.is_none());
+    /// Adds the execution timestamps of a partial command result.
+    pub fn add_timestamps(&mut self, timestamps: ExecutionTimestamps) {
+        match self.timestamps.as_mut() {
+            Some(current) => current.merge(timestamps),
+            None => self.timestamps = Some(timestamps),
+        }
     }
 
     pub fn ready(&self) -> bool {
@@ -267,12 +731,17 @@ impl CommandResultBuilder {
 pub struct CommandResult {
     rifl: Rifl,
     results: HashMap<Key, Vec<KVOpResult>>,
+    timestamps: Option<ExecutionTimestamps>,
 }
 
 impl CommandResult {
     /// Creates a new `CommandResult`.
     pub fn new(rifl: Rifl, results: HashMap<Key, Vec<KVOpResult>>) -> Self {
-        CommandResult { rifl, results }
+        CommandResult {
+            rifl,
+            results,
+            timestamps: None,
+        }
     }
 
     /// Returns the command identifier.
@@ -284,6 +753,37 @@ impl CommandResult {
     pub fn results(&self) -> &HashMap<Key, Vec<KVOpResult>> {
         &self.results
     }
+
+    /// Returns the commit and execution timestamps of the command, if the
+    /// executors reported them.
+    pub fn timestamps(&self) -> Option<ExecutionTimestamps> {
+        self.timestamps
+    }
+
+    /// Returns the errors of the operations that failed, along with the key
+    /// they accessed.
+    pub fn errors(&self) -> impl Iterator<Item = (&Key, &KVOpError)> {
+        self.results.iter().flat_map(|(key, results)| {
+            results
+                .iter()
+                .filter_map(move |result| result.error().map(|e| (key, e)))
+        })
+    }
+
+    /// Checks if some operation failed.
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Returns the versions returned by `KVOp::GetVersioned` operations, along
+    /// with the key they accessed.
+    pub fn versions(&self) -> impl Iterator<Item = (&Key, &Version)> {
+        self.results.iter().flat_map(|(key, results)| {
+            results
+                .iter()
+                .filter_map(move |result| result.version().map(|v| (key, v)))
+        })
+    }
 }
 
 impl From<CommandResultBuilder> for CommandResult {
@@ -292,18 +792,139 @@ impl From<CommandResultBuilder> for CommandResult {
         Self {
             rifl: cmd_result_builder.rifl,
             results: cmd_result_builder.results,
+            timestamps: cmd_result_builder.timestamps,
         }
     }
 }
 
+/// A batch of commands, each with its own identifier, that is submitted as a
+/// single command. The results of that command can then be split back into
+/// the results of each command in the batch (see `CommandBatch::unbatch`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandBatch {
+    // the commands in the batch merged into a single command (which has the
+    // identifier of the first command in the batch)
+    cmd: Command,
+    // identifier of each command in the batch (in the order they were added),
+    // along with the number of ops it has on each key
+    layout: Vec<(Rifl, HashMap<Key, usize>)>,
+}
+
+impl CommandBatch {
+    /// Creates a new batch with a single command.
+    pub fn new(cmd: Command) -> Self {
+        let layout = vec![Self::layout(&cmd)];
+        Self { cmd, layout }
+    }
+
+    /// Adds a command to the batch.
+    pub fn push(&mut self, cmd: Command) {
+        assert!(
+            self.rifls().all(|rifl| rifl != cmd.rifl()),
+            "commands in a batch should have different identifiers"
+        );
+        self.layout.push(Self::layout(&cmd));
+        self.cmd.merge(cmd);
+    }
+
+    /// Returns the number of commands in the batch.
+    pub fn len(&self) -> usize {
+        self.layout.len()
+    }
+
+    /// Checks if the batch is empty (which is never the case).
+    pub fn is_empty(&self) -> bool {
+        self.layout.is_empty()
+    }
+
+    /// Returns the identifiers of the commands in the batch.
+    pub fn rifls(&self) -> impl Iterator<Item = Rifl> + '_ {
+        self.layout.iter().map(|(rifl, _)| *rifl)
+    }
+
+    /// Returns the command to be submitted.
+    pub fn command(&self) -> &Command {
+        &self.cmd
+    }
+
+    /// Returns the command to be submitted, along with the identifiers of
+    /// the commands in the batch.
+    pub fn unpack(self) -> (Command, Vec<Rifl>) {
+        let rifls = self.rifls().collect();
+        (self.cmd, rifls)
+    }
+
+    /// Splits the result of the batch command (on some shard) into the
+    /// results of the commands in the batch that accessed that shard. Each
+    /// of these results keeps the timestamps of the batch result.
+    pub fn unbatch(&self, result: CommandResult) -> Vec<CommandResult> {
+        assert_eq!(
+            result.rifl,
+            self.cmd.rifl(),
+            "result should be of the batch command"
+        );
+        let timestamps = result.timestamps;
+        // since ops on the same key are executed in the order the commands
+        // were added to the batch, the results of each command are the next
+        // ones on that key
+        let mut key_to_results: HashMap<_, _> = result
+            .results
+            .into_iter()
+            .map(|(key, results)| (key, results.into_iter()))
+            .collect();
+        self.layout
+            .iter()
+            .filter_map(|(rifl, key_to_op_count)| {
+                let results: HashMap<_, _> = key_to_op_count
+                    .iter()
+                    .filter_map(|(key, op_count)| {
+                        let results = key_to_results.get_mut(key)?;
+                        let results: Vec<_> =
+                            results.by_ref().take(*op_count).collect();
+                        assert_eq!(
+                            results.len(),
+                            *op_count,
+                            "there should be a result per op"
+                        );
+                        Some((key.clone(), results))
+                    })
+                    .collect();
+                // skip commands that didn't access this shard
+                if results.is_empty() {
+                    None
+                } else {
+                    Some(CommandResult {
+                        rifl: *rifl,
+                        results,
+                        timestamps,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    fn layout(cmd: &Command) -> (Rifl, HashMap<Key, usize>) {
+        let key_to_op_count = cmd
+            .shard_to_ops
+            .values()
+            .flat_map(|shard_ops| {
+                shard_ops.iter().map(|(key, ops)| (key.clone(), ops.len()))
+            })
+            .collect();
+        (cmd.rifl(), key_to_op_count)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kvs::Value;
 
     fn multi_put(rifl: Rifl, keys: Vec<String>) -> Command {
         Command::from(
             rifl,
-            keys.into_iter().map(|key| (key.clone(), KVOp::Put(key))),
+            keys.into_iter()
+                .map(|key| (key.clone(), KVOp::Put(Value::from(key)))),
         )
     }
 
@@ -339,5 +960,448 @@ mod tests {
         assert!(cmd_ab.conflicts(&cmd_b));
         assert!(!cmd_ab.conflicts(&cmd_c));
         assert!(cmd_ab.conflicts(&cmd_ab));
+
+        // reads only conflict with writes, and conditional ops are writes
+        let op = |op: KVOp| Command::from(rifl, vec![(String::from("A"), op)]);
+        let get_a = op(KVOp::Get);
+        let validate_a = op(KVOp::Validate(None));
+        let cas_a = op(KVOp::Cas {
+            expected: Value::from("x"),
+            new: Value::from("y"),
+        });
+        let put_if_absent_a = op(KVOp::PutIfAbsent(Value::from("x")));
+        assert!(!get_a.conflicts(&get_a));
+        assert!(!get_a.conflicts(&validate_a));
+        assert!(get_a.conflicts(&cmd_a));
+        for cmd in [&cas_a, &put_if_absent_a] {
+            assert!(cmd.conflicts(&get_a));
+            assert!(get_a.conflicts(cmd));
+            assert!(cmd.conflicts(&validate_a));
+            assert!(cmd.conflicts(&cmd_a));
+            assert!(cmd.conflicts(&cas_a));
+            assert!(cmd.conflicts(&put_if_absent_a));
+            assert!(!cmd.conflicts(&cmd_b));
+        }
+    }
+
+    #[test]
+    fn read_only() {
+        let rifl = Rifl::new(1, 1);
+        let get_a = Command::from(rifl, vec![(String::from("A"), KVOp::Get)]);
+        let mut get_b =
+            Command::from(rifl, vec![(String::from("B"), KVOp::Get)]);
+        let put_c = multi_put(rifl, vec![String::from("C")]);
+        assert!(get_a.read_only());
+        assert!(!put_c.read_only());
+
+        // the flag is serialized with the command
+        let bytes = bincode::serialize(&get_a).unwrap();
+        let deserialized: Command = bincode::deserialize(&bytes).unwrap();
+        assert!(deserialized.read_only());
+
+        // merging a read-only command keeps it read-only, but merging a
+        // command that writes doesn't
+        get_b.merge(get_a);
+        assert!(get_b.read_only());
+        get_b.merge(put_c);
+        assert!(!get_b.read_only());
+    }
+
+    #[test]
+    fn consistency() {
+        let rifl = Rifl::new(1, 1);
+        let mut get_a =
+            Command::from(rifl, vec![(String::from("A"), KVOp::Get)]);
+        let mut get_b =
+            Command::from(rifl, vec![(String::from("B"), KVOp::Get)]);
+        let mut put_c = multi_put(rifl, vec![String::from("C")]);
+
+        // commands are linearizable by default
+        assert_eq!(get_a.consistency(), Consistency::Linearizable);
+        assert!(!get_a.local_read());
+
+        // only read-only commands can be answered locally
+        get_a.set_consistency(Consistency::Eventual);
+        put_c.set_consistency(Consistency::Eventual);
+        assert!(get_a.local_read());
+        assert!(!put_c.local_read());
+
+        // the consistency level is serialized with the command
+        let bytes = bincode::serialize(&get_a).unwrap();
+        let deserialized: Command = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(deserialized.consistency(), Consistency::Eventual);
+
+        // merging keeps the strongest consistency level
+        get_b.set_consistency(Consistency::Sequential);
+        get_b.merge(get_a);
+        assert_eq!(get_b.consistency(), Consistency::Sequential);
+        assert!(!get_b.local_read());
+    }
+
+    #[test]
+    fn batch() {
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(1, 2);
+        let rifl_3 = Rifl::new(2, 1);
+        let put = |rifl, key: &Key, value: &'static str| {
+            Command::from(rifl, vec![(key.clone(), KVOp::Put(value.into()))])
+        };
+        let get = |rifl, key: &Key| {
+            Command::from(rifl, vec![(key.clone(), KVOp::Get)])
+        };
+
+        // batch: put a x, get a, get b
+        let mut batch = CommandBatch::new(put(rifl_1, &key_a, "x"));
+        batch.push(get(rifl_2, &key_a));
+        batch.push(get(rifl_3, &key_b));
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.rifls().collect::<Vec<_>>(), [rifl_1, rifl_2, rifl_3]);
+
+        // the batch is submitted as a single command
+        let cmd = batch.command().clone();
+        assert_eq!(cmd.rifl(), rifl_1);
+        assert_eq!(cmd.key_count(DEFAULT_SHARD_ID), 2);
+
+        // execute the batch command
+        let mut store = KVStore::new(false);
+        let mut builder = CommandResultBuilder::new(rifl_1, 2);
+        for executor_result in cmd.execute(DEFAULT_SHARD_ID, &mut store) {
+            builder.add_partial(
+                executor_result.key,
+                executor_result.partial_results,
+            );
+        }
+        builder.add_timestamps(ExecutionTimestamps::new(1, 2));
+
+        // and split its result into the result of each command
+        let results = batch.unbatch(CommandResult::from(builder));
+        let expected = vec![
+            (rifl_1, key_a.clone(), None),
+            (rifl_2, key_a.clone(), Some(Value::from("x"))),
+            (rifl_3, key_b.clone(), None),
+        ];
+        assert_eq!(results.len(), expected.len());
+        for (result, (rifl, key, value)) in results.into_iter().zip(expected) {
+            assert_eq!(result.rifl(), rifl);
+            assert_eq!(result.results().len(), 1);
+            assert_eq!(result.results()[&key], vec![KVOpResult::Value(value)]);
+            assert_eq!(
+                result.timestamps(),
+                Some(ExecutionTimestamps::new(1, 2))
+            );
+        }
+    }
+
+    #[test]
+    fn scan_conflicts() {
+        let rifl = Rifl::new(1, 1);
+        let scan =
+            Command::scan(rifl, String::from("A"), String::from("C"), 10);
+        let put_b = multi_put(rifl, vec![String::from("B")]);
+        let put_z = multi_put(rifl, vec![String::from("Z")]);
+        let get_z = Command::from(rifl, vec![(String::from("Z"), KVOp::Get)]);
+
+        // scans are read-only, but can't use NFR
+        assert!(scan.read_only());
+        assert!(scan.has_scans());
+        assert!(!scan.nfr_allowed());
+        assert!(!put_b.has_scans());
+
+        // scans conflict with writes on the same shard (even if outside of the
+        // range), but not with reads
+        assert!(scan.conflicts(&put_b));
+        assert!(put_b.conflicts(&scan));
+        assert!(scan.conflicts(&put_z));
+        assert!(put_z.conflicts(&scan));
+        assert!(!scan.conflicts(&get_z));
+        assert!(!get_z.conflicts(&scan));
+    }
+
+    #[test]
+    fn transaction() {
+        let rifl = Rifl::new(1, 1);
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let key_c = String::from("C");
+        let txn = Command::transaction(
+            &ShardMap::new(1),
+            rifl,
+            vec![key_a.clone(), key_b.clone()],
+            vec![
+                (key_b.clone(), Value::from("b2")),
+                (key_c.clone(), Value::from("c2")),
+            ],
+        );
+        let sorted = |keys: Vec<&Key>| {
+            let mut keys: Vec<_> = keys.into_iter().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(
+            sorted(txn.read_set(DEFAULT_SHARD_ID).collect()),
+            vec![key_a.clone(), key_b.clone()]
+        );
+        assert_eq!(
+            sorted(txn.write_set(DEFAULT_SHARD_ID).collect()),
+            vec![key_b.clone(), key_c.clone()]
+        );
+        assert!(!txn.read_only());
+        assert_eq!(txn.key_count(DEFAULT_SHARD_ID), 3);
+
+        // reads observe the state before the writes of the transaction
+        let mut store = KVStore::new(false);
+        store.execute(&key_b, vec![KVOp::Put(Value::from("b1"))], rifl);
+        let results: HashMap<_, _> = txn
+            .execute(DEFAULT_SHARD_ID, &mut store)
+            .map(|executor_result| {
+                (executor_result.key, executor_result.partial_results)
+            })
+            .collect();
+        assert_eq!(results[&key_a], vec![KVOpResult::Value(None)]);
+        assert_eq!(
+            results[&key_b],
+            vec![
+                KVOpResult::Value(Some(Value::from("b1"))),
+                KVOpResult::Value(None)
+            ]
+        );
+        assert_eq!(results[&key_c], vec![KVOpResult::Value(None)]);
+
+        // keys are placed in their shards
+        let shard_map = ShardMap::new(4);
+        let keys: Vec<_> = (0..16).map(|i| i.to_string()).collect();
+        let writes = keys.iter().map(|key| (key.clone(), Value::from("x")));
+        let txn = Command::transaction(&shard_map, rifl, keys.clone(), writes);
+        for key in keys.iter() {
+            let shard_id = shard_map.shard_id(key);
+            assert!(txn.keys(shard_id).any(|shard_key| shard_key == key));
+        }
+        assert_eq!(txn.total_key_count(), keys.len());
+    }
+
+    #[test]
+    fn atomic_execution() {
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let mut store = KVStore::new(false);
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], Rifl::new(1, 1));
+
+        // the cas on key_a fails, and thus neither the put on key_b nor the
+        // put on key_a before the cas are executed
+        let mut shard_to_ops = HashMap::new();
+        let ops = vec![
+            KVOp::Put(y.clone()),
+            KVOp::Cas {
+                expected: x.clone(),
+                new: y.clone(),
+            },
+        ];
+        shard_to_ops.insert(key_a.clone(), ops);
+        shard_to_ops.insert(key_b.clone(), vec![KVOp::Put(y.clone())]);
+        let cmd = Command::new(
+            Rifl::new(2, 1),
+            HashMap::from_iter(std::iter::once((
+                DEFAULT_SHARD_ID,
+                shard_to_ops,
+            ))),
+        );
+        assert!(cmd.has_conditions_on(DEFAULT_SHARD_ID));
+        let results: HashMap<_, _> = cmd
+            .execute(DEFAULT_SHARD_ID, &mut store)
+            .map(|executor_result| {
+                (executor_result.key, executor_result.partial_results)
+            })
+            .collect();
+        assert_eq!(
+            results[&key_a],
+            vec![
+                KVOpResult::Error(KVOpError::Aborted),
+                KVOpResult::Error(KVOpError::CasMismatch {
+                    current: Some(y.clone())
+                })
+            ]
+        );
+        assert_eq!(
+            results[&key_b],
+            vec![KVOpResult::Error(KVOpError::Aborted)]
+        );
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x));
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+    }
+
+    #[test]
+    fn timestamps() {
+        let rifl = Rifl::new(1, 1);
+        let mut builder = CommandResultBuilder::new(rifl, 2);
+
+        // partial results on both keys
+        builder.add_partial(String::from("A"), vec![KVOpResult::Value(None)]);
+        builder.add_timestamps(
+            ExecutionTimestamps::new(10, 15)
+                .with_submit(Some(4))
+                .with_fast_path(Some(true)),
+        );
+        builder.add_partial(String::from("B"), vec![KVOpResult::Value(None)]);
+        builder.add_timestamps(
+            ExecutionTimestamps::new(12, 13)
+                .with_submit(Some(5))
+                .with_fast_path(Some(false)),
+        );
+        assert!(builder.ready());
+
+        // the earliest submit and the latest commit and execute timestamps
+        // are kept, and the command didn't take the fast path as it took the
+        // slow path on some key
+        let result = CommandResult::from(builder);
+        let timestamps = result.timestamps().expect("timestamps should exist");
+        assert_eq!(
+            timestamps,
+            ExecutionTimestamps::new(12, 15)
+                .with_submit(Some(4))
+                .with_fast_path(Some(false))
+        );
+        assert_eq!(timestamps.commit_latency(), Some(8));
+        assert_eq!(timestamps.execution_delay(), 3);
+
+        // partial results that don't know the path taken are ignored
+        let mut timestamps =
+            ExecutionTimestamps::new(10, 15).with_fast_path(Some(true));
+        timestamps.merge(ExecutionTimestamps::new(12, 13));
+        assert_eq!(timestamps.fast_path, Some(true));
+    }
+
+    #[test]
+    fn errors() {
+        let rifl = Rifl::new(1, 1);
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let cmd = Command::from(
+            rifl,
+            vec![
+                (key_a.clone(), KVOp::PutIfAbsent(x.clone())),
+                (key_b.clone(), KVOp::PutIfAbsent(x.clone())),
+            ],
+        );
+
+        // put-if-absent fails on key b, since it already has a value, and thus
+        // the one on key a is not executed
+        let mut store = KVStore::new(false);
+        store.execute(&key_b, vec![KVOp::Put(x.clone())], rifl);
+        let mut builder = CommandResultBuilder::new(rifl, 2);
+        for executor_result in cmd.execute(DEFAULT_SHARD_ID, &mut store) {
+            builder.add_partial(
+                executor_result.key,
+                executor_result.partial_results,
+            );
+        }
+        let result = CommandResult::from(builder);
+        assert!(result.has_errors());
+        let mut errors: Vec<_> = result.errors().collect();
+        errors.sort_by_key(|(key, _)| *key);
+        assert_eq!(
+            errors,
+            vec![
+                (&key_a, &KVOpError::Aborted),
+                (&key_b, &KVOpError::KeyExists { current: x })
+            ]
+        );
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+    }
+
+    #[test]
+    fn versions() {
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let cmd = Command::from(
+            rifl_2,
+            vec![
+                (key_a.clone(), KVOp::GetVersioned),
+                (key_b.clone(), KVOp::Get),
+            ],
+        );
+        assert!(cmd.read_only());
+
+        // only versioned gets return versions
+        let mut store = KVStore::new(false);
+        store.execute(&key_a, vec![KVOp::Put(Value::from("x"))], rifl_1);
+        store.execute(&key_b, vec![KVOp::Put(Value::from("y"))], rifl_1);
+        let mut builder = CommandResultBuilder::new(rifl_2, 2);
+        for executor_result in cmd.execute(DEFAULT_SHARD_ID, &mut store) {
+            builder.add_partial(
+                executor_result.key,
+                executor_result.partial_results,
+            );
+        }
+        let result = CommandResult::from(builder);
+        let versions: Vec<_> = result.versions().collect();
+        assert_eq!(versions, vec![(&key_a, &Version::new(rifl_1, 0))]);
+    }
+
+    #[test]
+    fn split() {
+        let rifl = Rifl::new(1, 1);
+        let shard_count = 4;
+        let shard_map = ShardMap::new(shard_count);
+        assert_eq!(shard_map.shard_count(), shard_count);
+
+        // a command with many keys in the default shard
+        let keys: Vec<Key> = (0..20).map(|key| key.to_string()).collect();
+        let mut cmd = Command::from(
+            rifl,
+            keys.iter().map(|key| (key.clone(), KVOp::Get)),
+        );
+        cmd.set_consistency(Consistency::Eventual);
+        let shards = cmd.key_shards(&shard_map);
+        assert!(shards.len() > 1);
+        assert!(shards.iter().all(|shard_id| *shard_id < shard_count as u64));
+
+        // splitting creates one command per shard, each accessing only the
+        // keys in its shard
+        let split = cmd.split(&shard_map);
+        assert_eq!(split.keys().cloned().collect::<HashSet<_>>(), shards);
+        let mut split_keys = Vec::new();
+        for (shard_id, cmd) in split {
+            assert_eq!(cmd.rifl(), rifl);
+            assert_eq!(cmd.consistency(), Consistency::Eventual);
+            assert_eq!(cmd.shard_count(), 1);
+            assert_eq!(
+                cmd.key_shards(&shard_map),
+                HashSet::from_iter(vec![shard_id])
+            );
+            split_keys.extend(cmd.keys(shard_id).cloned());
+        }
+        split_keys.sort();
+        let mut expected = keys;
+        expected.sort();
+        assert_eq!(split_keys, expected);
+    }
+
+    #[test]
+    fn opaque() {
+        let rifl = Rifl::new(1, 1);
+        let payload = Value::from("payload");
+        let keys = vec![String::from("A"), String::from("B")];
+        let cmd = Command::opaque(rifl, keys.clone(), payload.clone());
+
+        // the payload is applied to each key, and opaque ops are not
+        // read-only
+        assert!(!cmd.read_only());
+        for (key, ops) in cmd.iter(DEFAULT_SHARD_ID) {
+            assert!(keys.contains(key));
+            assert_eq!(ops.as_ref(), &vec![KVOp::Opaque(payload.clone())]);
+        }
+
+        // conflicts are detected on the keys declared
+        let get_b = Command::from(rifl, vec![(String::from("B"), KVOp::Get)]);
+        let get_c = Command::from(rifl, vec![(String::from("C"), KVOp::Get)]);
+        assert!(cmd.conflicts(&get_b));
+        assert!(!cmd.conflicts(&get_c));
     }
 }
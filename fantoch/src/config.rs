@@ -23,6 +23,27 @@ pub struct Config {
     /// defines whether the executor should monitor the execution order of
     /// commands
     executor_monitor_execution_order: bool,
+    /// defines whether the graph executor should search for SCCs in parallel
+    /// and, if so, the minimum number of pending commands for that to happen
+    executor_parallel_scc_threshold: Option<usize>,
+    /// defines whether executors should periodically compact their internal
+    /// state and, if so, the interval between compactions
+    executor_compaction_interval: Option<Duration>,
+    /// defines whether executors should speculatively execute commands as
+    /// soon as they are committed (rolling them back if the final order
+    /// differs from the predicted one)
+    executor_speculative: bool,
+    /// defines whether the memory used by executor stores is bounded and, if
+    /// so, the maximum number of bytes used by keys and values (above which
+    /// the least recently used keys are evicted)
+    executor_store_capacity: Option<usize>,
+    /// defines whether executors make commands execute at most once and, if
+    /// so, the number of commands per key whose results are cached (so that
+    /// retried commands get them instead of being executed again)
+    executor_dedup_window: Option<usize>,
+    /// defines whether executors track per-key access and conflict counts
+    /// and, if so, the number of hottest keys reported in their metrics
+    executor_hot_keys: Option<usize>,
     /// defines the interval between garbage collections
     gc_interval: Option<Duration>,
     /// starting leader process
@@ -65,6 +86,18 @@ impl Config {
         let executor_monitor_pending_interval = None;
         // by default, executors do not monitor execution order
         let executor_monitor_execution_order = false;
+        // by default, SCCs are not searched in parallel
+        let executor_parallel_scc_threshold = None;
+        // by default, executors do not compact their state
+        let executor_compaction_interval = None;
+        // by default, executors do not execute speculatively
+        let executor_speculative = false;
+        // by default, executor stores are not bounded
+        let executor_store_capacity = None;
+        // by default, executors do not deduplicate commands
+        let executor_dedup_window = None;
+        // by default, executors do not track hot keys
+        let executor_hot_keys = None;
         // by default, commands are deleted at commit time
         let gc_interval = None;
         // by default, there's no leader
@@ -90,6 +123,12 @@ impl Config {
             executor_executed_notification_interval,
             executor_monitor_pending_interval,
             executor_monitor_execution_order,
+            executor_parallel_scc_threshold,
+            executor_compaction_interval,
+            executor_speculative,
+            executor_store_capacity,
+            executor_dedup_window,
+            executor_hot_keys,
             gc_interval,
             leader,
             nfr,
@@ -111,6 +150,16 @@ impl Config {
         self.f
     }
 
+    /// Changes the number of processes and the number of faults tolerated,
+    /// keeping all the other settings.
+    pub fn set_n_f(&mut self, n: usize, f: usize) {
+        if f > n / 2 {
+            panic!("f={} is larger than a minority with n={}", f, n);
+        }
+        self.n = n;
+        self.f = f;
+    }
+
     /// Retrieve the number of shards.
     pub fn shard_count(&self) -> usize {
         self.shard_count
@@ -169,6 +218,32 @@ impl Config {
             executor_monitor_execution_order;
     }
 
+    /// Checks the executor parallel SCC threshold.
+    pub fn executor_parallel_scc_threshold(&self) -> Option<usize> {
+        self.executor_parallel_scc_threshold
+    }
+
+    /// Sets the executor parallel SCC threshold.
+    pub fn set_executor_parallel_scc_threshold<T>(&mut self, threshold: T)
+    where
+        T: Into<Option<usize>>,
+    {
+        self.executor_parallel_scc_threshold = threshold.into();
+    }
+
+    /// Checks the executor compaction interval.
+    pub fn executor_compaction_interval(&self) -> Option<Duration> {
+        self.executor_compaction_interval
+    }
+
+    /// Sets the executor compaction interval.
+    pub fn set_executor_compaction_interval<I>(&mut self, interval: I)
+    where
+        I: Into<Option<Duration>>,
+    {
+        self.executor_compaction_interval = interval.into();
+    }
+
     /// Checks the executed notification interval.
     pub fn executor_executed_notification_interval(&self) -> Duration {
         self.executor_executed_notification_interval
@@ -182,6 +257,55 @@ impl Config {
         self.executor_executed_notification_interval = interval;
     }
 
+    /// Checks whether executors execute speculatively.
+    pub fn executor_speculative(&self) -> bool {
+        self.executor_speculative
+    }
+
+    /// Changes the value of `executor_speculative`.
+    pub fn set_executor_speculative(&mut self, executor_speculative: bool) {
+        self.executor_speculative = executor_speculative;
+    }
+
+    /// Checks the executor store capacity.
+    pub fn executor_store_capacity(&self) -> Option<usize> {
+        self.executor_store_capacity
+    }
+
+    /// Sets the executor store capacity.
+    pub fn set_executor_store_capacity<C>(&mut self, capacity: C)
+    where
+        C: Into<Option<usize>>,
+    {
+        self.executor_store_capacity = capacity.into();
+    }
+
+    /// Checks the executor dedup window.
+    pub fn executor_dedup_window(&self) -> Option<usize> {
+        self.executor_dedup_window
+    }
+
+    /// Sets the executor dedup window.
+    pub fn set_executor_dedup_window<W>(&mut self, dedup_window: W)
+    where
+        W: Into<Option<usize>>,
+    {
+        self.executor_dedup_window = dedup_window.into();
+    }
+
+    /// Checks the number of hottest keys reported by executors.
+    pub fn executor_hot_keys(&self) -> Option<usize> {
+        self.executor_hot_keys
+    }
+
+    /// Sets the number of hottest keys reported by executors.
+    pub fn set_executor_hot_keys<T>(&mut self, hot_keys: T)
+    where
+        T: Into<Option<usize>>,
+    {
+        self.executor_hot_keys = hot_keys.into();
+    }
+
     /// Checks the garbage collection interval.
     pub fn gc_interval(&self) -> Option<Duration> {
         self.gc_interval
@@ -415,6 +539,48 @@ mod tests {
         config.set_executor_monitor_execution_order(true);
         assert_eq!(config.executor_monitor_execution_order(), true);
 
+        // by default, there's no executor parallel SCC threshold
+        assert_eq!(config.executor_parallel_scc_threshold(), None);
+
+        // change its value and check it has changed
+        config.set_executor_parallel_scc_threshold(1000);
+        assert_eq!(config.executor_parallel_scc_threshold(), Some(1000));
+
+        // by default, there's no executor compaction interval
+        assert_eq!(config.executor_compaction_interval(), None);
+
+        // change its value and check it has changed
+        let interval = Duration::from_secs(1);
+        config.set_executor_compaction_interval(interval);
+        assert_eq!(config.executor_compaction_interval(), Some(interval));
+
+        // by default, executor speculative is false
+        assert!(!config.executor_speculative());
+        // but that can change
+        config.set_executor_speculative(true);
+        assert!(config.executor_speculative());
+
+        // by default, there's no executor store capacity
+        assert_eq!(config.executor_store_capacity(), None);
+
+        // change its value and check it has changed
+        config.set_executor_store_capacity(1024);
+        assert_eq!(config.executor_store_capacity(), Some(1024));
+
+        // by default, there's no executor dedup window
+        assert_eq!(config.executor_dedup_window(), None);
+
+        // change its value and check it has changed
+        config.set_executor_dedup_window(100);
+        assert_eq!(config.executor_dedup_window(), Some(100));
+
+        // by default, executors don't track hot keys
+        assert_eq!(config.executor_hot_keys(), None);
+
+        // change its value and check it has changed
+        config.set_executor_hot_keys(10);
+        assert_eq!(config.executor_hot_keys(), Some(10));
+
         // by default, there's no garbage collection interval
         assert_eq!(config.gc_interval(), None);
 
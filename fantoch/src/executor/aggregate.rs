@@ -1,6 +1,6 @@
 use crate::command::{Command, CommandResult, CommandResultBuilder};
 use crate::executor::ExecutorResult;
-use crate::id::{ProcessId, Rifl, ShardId};
+use crate::id::{ClientId, ProcessId, Rifl, ShardId};
 use crate::trace;
 use crate::HashMap;
 
@@ -26,6 +26,16 @@ impl AggregatePending {
         }
     }
 
+    /// Checks if there are no pending commands.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Checks if there are pending commands submitted by client `client_id`.
+    pub fn has_pending(&self, client_id: ClientId) -> bool {
+        self.pending.keys().any(|rifl| rifl.source() == client_id)
+    }
+
     /// Starts tracking a command submitted by some client.
     pub fn wait_for(&mut self, cmd: &Command) -> bool {
         // get command rifl and key count
@@ -53,6 +63,7 @@ impl AggregatePending {
             rifl,
             key,
             partial_results,
+            timestamps,
         } = executor_result;
         // get current value:
         // - if it's not part of pending, then ignore it
@@ -61,8 +72,13 @@ impl AggregatePending {
         // called)
         let cmd_result_builder = self.pending.get_mut(&rifl)?;
 
-        // add partial result and check if it's ready
-        cmd_result_builder.add_partial(key, partial_results);
+        // add partial result (along with its timestamps, unless it's a
+        // duplicate) and check if it's ready
+        if cmd_result_builder.add_partial(key, partial_results) {
+            if let Some(timestamps) = timestamps {
+                cmd_result_builder.add_timestamps(timestamps);
+            }
+        }
         if cmd_result_builder.ready() {
             trace!(
                 "p{}: AggregatePending::add_partial {:?} is ready",
@@ -91,7 +107,7 @@ impl AggregatePending {
 mod tests {
     use super::*;
     use crate::command::Command;
-    use crate::kvs::{KVOp, KVStore};
+    use crate::kvs::{KVOp, KVOpResult, KVStore, Value};
 
     #[test]
     fn pending_flow() {
@@ -105,8 +121,8 @@ mod tests {
         // keys and commands
         let key_a = String::from("A");
         let key_b = String::from("B");
-        let foo = String::from("foo");
-        let bar = String::from("bar");
+        let foo = Value::from("foo");
+        let bar = Value::from("bar");
 
         // command put a
         let put_a_rifl = Rifl::new(1, 1);
@@ -136,8 +152,13 @@ mod tests {
         // starting a command already started `false`
         assert!(!pending.wait_for(&put_b));
 
+        // only clients 2 and 3 have pending commands
+        assert!(!pending.has_pending(1));
+        assert!(pending.has_pending(2));
+        assert!(pending.has_pending(3));
+
         // add the result of get b and assert that the command is not ready yet
-        let get_b_res = store.test_execute(&key_b, KVOp::Get);
+        let get_b_res = KVOpResult::from(store.test_execute(&key_b, KVOp::Get));
         let res = pending.add_executor_result(ExecutorResult::new(
             get_ab_rifl,
             key_b.clone(),
@@ -146,7 +167,9 @@ mod tests {
         assert!(res.is_none());
 
         // add the result of put a before being waited for
-        let put_a_res = store.test_execute(&key_a, KVOp::Put(foo.clone()));
+        let put_a_res = KVOpResult::from(
+            store.test_execute(&key_a, KVOp::Put(foo.clone())),
+        );
         let res = pending.add_executor_result(ExecutorResult::new(
             put_a_rifl,
             key_a.clone(),
@@ -171,10 +194,15 @@ mod tests {
         assert_eq!(res.results().len(), 1);
 
         // check that there was nothing in the kvs before
-        assert_eq!(res.results().get(&key_a).unwrap(), &vec![None]);
+        assert_eq!(
+            res.results().get(&key_a).unwrap(),
+            &[KVOpResult::Value(None)]
+        );
 
         // add the result of put b and assert that the command is ready
-        let put_b_res = store.test_execute(&key_b, KVOp::Put(bar.clone()));
+        let put_b_res = KVOpResult::from(
+            store.test_execute(&key_b, KVOp::Put(bar.clone())),
+        );
         let res = pending.add_executor_result(ExecutorResult::new(
             put_b_rifl,
             key_b.clone(),
@@ -187,10 +215,23 @@ mod tests {
         assert_eq!(res.results().len(), 1);
 
         // check that there was nothing in the kvs before
-        assert_eq!(res.results().get(&key_b).unwrap(), &vec![None]);
+        assert_eq!(
+            res.results().get(&key_b).unwrap(),
+            &[KVOpResult::Value(None)]
+        );
+
+        // a duplicate result of get b (e.g. since the command was executed
+        // twice) is ignored
+        let get_b_res = KVOpResult::from(store.test_execute(&key_b, KVOp::Get));
+        let res = pending.add_executor_result(ExecutorResult::new(
+            get_ab_rifl,
+            key_b.clone(),
+            vec![get_b_res],
+        ));
+        assert!(res.is_none());
 
         // add the result of get a and assert that the command is ready
-        let get_a_res = store.test_execute(&key_a, KVOp::Get);
+        let get_a_res = KVOpResult::from(store.test_execute(&key_a, KVOp::Get));
         let res = pending.add_executor_result(ExecutorResult::new(
             get_ab_rifl,
             key_a.clone(),
@@ -204,7 +245,13 @@ mod tests {
         assert_eq!(res.results().len(), 2);
 
         // check that `get_ab` saw `put_a` but not `put_b`
-        assert_eq!(res.results().get(&key_a).unwrap(), &vec![Some(foo)]);
-        assert_eq!(res.results().get(&key_b).unwrap(), &vec![None]);
+        assert_eq!(
+            res.results().get(&key_a).unwrap(),
+            &[KVOpResult::Value(Some(foo))]
+        );
+        assert_eq!(
+            res.results().get(&key_b).unwrap(),
+            &[KVOpResult::Value(None)]
+        );
     }
 }
@@ -1,10 +1,11 @@
+use crate::command::ExecutionTimestamps;
 use crate::config::Config;
 use crate::executor::{
-    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult,
-    MessageKey,
+    DelayedReads, ExecutionOrderMonitor, Executor, ExecutorMetrics,
+    ExecutorMetricsKind, ExecutorResult, LocalRead, MessageKey,
 };
 use crate::id::{ProcessId, Rifl, ShardId};
-use crate::kvs::{KVOp, KVStore, Key};
+use crate::kvs::{KVOp, KVStore, Key, Store, WatchNotification};
 use crate::time::SysTime;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -14,44 +15,98 @@ pub struct BasicExecutor {
     store: KVStore,
     metrics: ExecutorMetrics,
     to_clients: Vec<ExecutorResult>,
+    // local reads waiting for the writes they should observe
+    delayed_reads: DelayedReads,
 }
 
 impl Executor for BasicExecutor {
     type ExecutionInfo = BasicExecutionInfo;
 
-    fn new(
-        _process_id: ProcessId,
-        _shard_id: ShardId,
-        _config: Config,
-    ) -> Self {
+    fn new(_process_id: ProcessId, _shard_id: ShardId, config: Config) -> Self {
         let monitor = false;
-        let store = KVStore::new(monitor);
+        let mut store = KVStore::new(monitor);
+        // with more than one shard, each store only has part of the keys
+        store.set_scans(config.shard_count() == 1);
         let metrics = ExecutorMetrics::new();
         let to_clients = Vec::new();
+        let delayed_reads = DelayedReads::new();
 
         Self {
             store,
             metrics,
             to_clients,
+            delayed_reads,
         }
     }
 
-    fn handle(&mut self, info: Self::ExecutionInfo, _time: &dyn SysTime) {
-        let BasicExecutionInfo { rifl, key, ops } = info;
+    fn set_executor_count(&mut self, count: usize) {
+        // each executor only has the keys assigned to it (see `store_index`)
+        if count > 1 {
+            self.store.set_scans(false);
+        }
+    }
+
+    fn handle(&mut self, info: Self::ExecutionInfo, time: &dyn SysTime) {
+        let BasicExecutionInfo {
+            rifl,
+            key,
+            ops,
+            submit_time,
+        } = info;
         // take the ops inside the arc if we're the last with a
         // reference to it (otherwise, clone them)
         let ops =
             Arc::try_unwrap(ops).unwrap_or_else(|ops| ops.as_ref().clone());
-        // execute op in the `KVStore`
+        // execute op in the `KVStore`, using the time at which the command
+        // was submitted as the logical time at which it's executed
+        self.store.set_time_on(
+            submit_time.unwrap_or_default(),
+            std::iter::once(&key),
+        );
         let partial_results = self.store.execute(&key, ops, rifl);
-        self.to_clients
-            .push(ExecutorResult::new(rifl, key, partial_results));
+        // commands are executed as soon as they are committed
+        let now = time.millis();
+        let timestamps =
+            ExecutionTimestamps::new(now, now).with_submit(submit_time);
+        self.to_clients.push(
+            ExecutorResult::new(rifl, key, partial_results)
+                .with_timestamps(timestamps),
+        );
+    }
+
+    fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store.set_store(store);
+    }
+
+    fn local_read(&mut self, read: LocalRead) {
+        match self.delayed_reads.delay(read, &self.store) {
+            Some(read) => self.to_clients.push(read.execute(&mut self.store)),
+            None => {
+                self.metrics.aggregate(ExecutorMetricsKind::DelayedReads, 1)
+            }
+        }
+    }
+
+    fn watch(&mut self, key: Key) {
+        self.store.watch(key);
+    }
+
+    fn unwatch(&mut self, key: &Key) {
+        self.store.unwatch(key);
     }
 
     fn to_clients(&mut self) -> Option<ExecutorResult> {
+        // execute the delayed reads whose writes have meanwhile been applied
+        while let Some(read) = self.delayed_reads.next_ready(&self.store) {
+            self.to_clients.push(read.execute(&mut self.store));
+        }
         self.to_clients.pop()
     }
 
+    fn to_watchers(&mut self) -> Vec<WatchNotification> {
+        self.store.take_notifications()
+    }
+
     fn parallel() -> bool {
         true
     }
@@ -70,11 +125,24 @@ pub struct BasicExecutionInfo {
     rifl: Rifl,
     key: Key,
     ops: Arc<Vec<KVOp>>,
+    submit_time: Option<u64>,
 }
 
 impl BasicExecutionInfo {
     pub fn new(rifl: Rifl, key: Key, ops: Arc<Vec<KVOp>>) -> Self {
-        Self { rifl, key, ops }
+        Self {
+            rifl,
+            key,
+            ops,
+            submit_time: None,
+        }
+    }
+
+    /// Sets the time at which the command was submitted (see
+    /// `Command::submit_time`).
+    pub fn with_submit_time(mut self, submit_time: Option<u64>) -> Self {
+        self.submit_time = submit_time;
+        self
     }
 }
 
@@ -0,0 +1,229 @@
+use crate::command::Command;
+use crate::config::Config;
+use crate::executor::{AggregatePending, Executor};
+use crate::id::{ProcessId, Rifl, ShardId};
+use crate::kvs::{KVOpResult, KVStore, Key};
+use crate::time::SimTime;
+use crate::HashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+// number of random interleavings checked by `executor_conformance!` when none
+// is specified
+pub const DEFAULT_INTERLEAVINGS: usize = 100;
+
+/// Defines a test that checks that an `Executor` implementation conforms to
+/// the expected behavior of executors (see `ConformanceCase::check`).
+///
+/// Usage: `executor_conformance!(test_name, ExecutorType, cases)`, where
+/// `cases` is an expression that evaluates to something that can be iterated
+/// into `ConformanceCase<ExecutorType>`. Optionally, the number of random
+/// interleavings to be checked per case can be passed as fourth argument.
+#[macro_export]
+macro_rules! executor_conformance {
+    ($name:ident, $executor:ty, $cases:expr) => {
+        $crate::executor_conformance!(
+            $name,
+            $executor,
+            $cases,
+            $crate::executor::conformance::DEFAULT_INTERLEAVINGS
+        );
+    };
+    ($name:ident, $executor:ty, $cases:expr, $interleavings:expr) => {
+        #[test]
+        fn $name() {
+            for (seed, case) in
+                ::std::iter::IntoIterator::into_iter($cases).enumerate()
+            {
+                let case: $crate::executor::conformance::ConformanceCase<
+                    $executor,
+                > = case;
+                case.check($interleavings, seed as u64);
+            }
+        }
+    };
+}
+
+// mapping from each command to its results on each key
+type Results = HashMap<Rifl, HashMap<Key, Vec<KVOpResult>>>;
+
+/// A conformance test case: a set of commands, the order in which they are
+/// expected to be executed, and the execution infos an executor would receive
+/// for them.
+pub struct ConformanceCase<E: Executor> {
+    process_id: ProcessId,
+    shard_id: ShardId,
+    config: Config,
+    commands: Vec<Command>,
+    infos: Vec<E::ExecutionInfo>,
+}
+
+impl<E> ConformanceCase<E>
+where
+    E: Executor,
+{
+    /// Creates a new test case. Commands should be given in the order they
+    /// are expected to be executed.
+    pub fn new(
+        process_id: ProcessId,
+        shard_id: ShardId,
+        config: Config,
+        commands: Vec<Command>,
+        infos: Vec<E::ExecutionInfo>,
+    ) -> Self {
+        Self {
+            process_id,
+            shard_id,
+            config,
+            commands,
+            infos,
+        }
+    }
+
+    /// Checks that, when infos are handled in the order they were given, and
+    /// in `interleavings` random orders (seeded with `seed`), all commands are
+    /// executed and their results are the same as the ones of a sequential
+    /// execution of the commands in their expected order.
+    pub fn check(&self, interleavings: usize, seed: u64) {
+        let expected = self.reference_results();
+
+        // check the order in which infos were given
+        let results = self.run(self.infos.clone());
+        assert_eq!(
+            results, expected,
+            "p{}: results differ from the sequential execution",
+            self.process_id
+        );
+
+        // check random interleavings
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..interleavings {
+            let mut infos = self.infos.clone();
+            infos.shuffle(&mut rng);
+            let results = self.run(infos.clone());
+            assert_eq!(
+                results, expected,
+                "p{}: results differ from the sequential execution when infos are handled in order {:?}",
+                self.process_id, infos
+            );
+        }
+    }
+
+    /// Computes the results of executing commands sequentially, in their
+    /// expected order.
+    pub fn reference_results(&self) -> Results {
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        let mut pending = self.register();
+        let mut results = HashMap::new();
+        for cmd in self.commands.iter().cloned() {
+            for executor_result in cmd.execute(self.shard_id, &mut store) {
+                if let Some(result) =
+                    pending.add_executor_result(executor_result)
+                {
+                    results.insert(result.rifl(), result.results().clone());
+                }
+            }
+        }
+        results
+    }
+
+    /// Handles all `infos` (in the order given) in a new executor, returning
+    /// the results of the commands it executed.
+    fn run(&self, infos: Vec<E::ExecutionInfo>) -> Results {
+        let time = SimTime::new();
+        let mut executor = E::new(self.process_id, self.shard_id, self.config);
+        let mut pending = self.register();
+        let mut results = HashMap::new();
+
+        let mut to_handle = infos;
+        // infos are popped from the back, so reverse them first
+        to_handle.reverse();
+        while let Some(info) = to_handle.pop() {
+            executor.handle(info, &time);
+
+            for executor_result in executor.to_clients_iter() {
+                if let Some(result) =
+                    pending.add_executor_result(executor_result)
+                {
+                    results.insert(result.rifl(), result.results().clone());
+                }
+            }
+
+            // infos sent to this shard are handled right away
+            for (shard_id, info) in executor.to_executors_iter() {
+                assert_eq!(
+                    shard_id, self.shard_id,
+                    "p{}: conformance tests only support a single shard",
+                    self.process_id
+                );
+                to_handle.push(info);
+            }
+        }
+        results
+    }
+
+    /// Starts tracking all commands that access this shard.
+    fn register(&self) -> AggregatePending {
+        let mut pending = AggregatePending::new(self.process_id, self.shard_id);
+        for cmd in self.commands.iter() {
+            if cmd.key_count(self.shard_id) > 0 {
+                assert!(
+                    pending.wait_for(cmd),
+                    "p{}: command {:?} registered more than once",
+                    self.process_id,
+                    cmd.rifl()
+                );
+            }
+        }
+        pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{BasicExecutionInfo, BasicExecutor};
+    use crate::kvs::{KVOp, Value};
+
+    fn basic_case(keys: Vec<&str>) -> ConformanceCase<BasicExecutor> {
+        let process_id = 1;
+        let shard_id = 0;
+        let config = Config::new(0, 0);
+        let mut commands = Vec::new();
+        let mut infos = Vec::new();
+        for (seq, key) in (1..).zip(keys) {
+            let rifl = Rifl::new(1, seq);
+            let key = String::from(key);
+            let op = if seq % 2 == 0 {
+                KVOp::Get
+            } else {
+                KVOp::Put(Value::from(seq.to_string()))
+            };
+            let cmd = Command::from(rifl, vec![(key.clone(), op.clone())]);
+            infos.push(BasicExecutionInfo::new(
+                rifl,
+                key,
+                std::sync::Arc::new(vec![op]),
+            ));
+            commands.push(cmd);
+        }
+        ConformanceCase::new(process_id, shard_id, config, commands, infos)
+    }
+
+    // the basic executor executes commands as soon as it receives them, which
+    // is only correct if commands don't conflict
+    executor_conformance!(
+        basic_executor_conformance,
+        BasicExecutor,
+        vec![basic_case(vec!["a", "b", "c"])]
+    );
+
+    #[test]
+    #[should_panic]
+    fn basic_executor_non_conformance() {
+        let case = basic_case(vec!["a", "a", "a", "a"]);
+        case.check(DEFAULT_INTERLEAVINGS, 0);
+    }
+}
@@ -8,14 +8,19 @@ mod basic;
 // This module contains the definition of `ExecutionOrderMonitor`.
 mod monitor;
 
+// This module contains the definition of `ConformanceCase` and the
+// `executor_conformance!` macro, used to test `Executor` implementations.
+pub mod conformance;
+
 // Re-exports.
 pub use aggregate::AggregatePending;
 pub use basic::{BasicExecutionInfo, BasicExecutor};
 pub use monitor::ExecutionOrderMonitor;
 
+use crate::command::ExecutionTimestamps;
 use crate::config::Config;
-use crate::id::{ProcessId, Rifl, ShardId};
-use crate::kvs::{KVOpResult, Key};
+use crate::id::{Dot, ProcessId, Rifl, ShardId};
+use crate::kvs::{KVOp, KVOpResult, KVStore, Key, Store, WatchNotification};
 use crate::metrics::Metrics;
 use crate::protocol::{CommittedAndExecuted, MessageIndex};
 use crate::time::SysTime;
@@ -23,6 +28,7 @@ use crate::util;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Debug};
+use std::sync::Arc;
 
 pub trait Executor: Clone {
     // TODO why is Send needed?
@@ -40,6 +46,16 @@ pub trait Executor: Clone {
         // executors interested in the index should overwrite this
     }
 
+    fn set_executor_count(&mut self, _count: usize) {
+        // executors interested in the number of executors (e.g. because each
+        // has part of the store) should overwrite this
+    }
+
+    /// Sets the `Store` where the executor keeps values (see
+    /// `KVStore::set_store`). Since executors are cloned, this is how each of
+    /// them gets its own (e.g. persistent) store.
+    fn set_store(&mut self, store: Box<dyn Store>);
+
     fn cleanup(&mut self, _time: &dyn SysTime) {
         // executors interested in a periodic cleanup should overwrite this
     }
@@ -51,9 +67,42 @@ pub trait Executor: Clone {
 
     fn handle(&mut self, infos: Self::ExecutionInfo, time: &dyn SysTime);
 
+    /// Returns the dot of the command an execution info is about, if any.
+    /// This is used to index execution logs.
+    fn execution_info_dot(_info: &Self::ExecutionInfo) -> Option<Dot> {
+        // executors whose execution infos are about dots should overwrite
+        // this
+        None
+    }
+
+    /// Answers a read with `Consistency::Eventual` from the local store,
+    /// outside of the execution order. The result should be made available
+    /// in `Executor::to_clients`.
+    fn local_read(&mut self, read: LocalRead);
+
+    /// Returns the index of the executor whose store holds `key`, and thus
+    /// should answer local reads and watches on it (see `MessageIndex`).
+    #[allow(clippy::ptr_arg)]
+    fn store_index(key: &Key) -> Option<(usize, usize)> {
+        // executors that don't partition their store by key should overwrite
+        // this
+        Some(key_index(key))
+    }
+
+    /// Starts watching `key`: writes applied to it should be made available
+    /// in `Executor::to_watchers`.
+    fn watch(&mut self, key: Key);
+
+    /// Stops watching `key`.
+    #[allow(clippy::ptr_arg)]
+    fn unwatch(&mut self, key: &Key);
+
     #[must_use]
     fn to_clients(&mut self) -> Option<ExecutorResult>;
 
+    #[must_use]
+    fn to_watchers(&mut self) -> Vec<WatchNotification>;
+
     #[must_use]
     fn to_clients_iter(&mut self) -> ToClientsIter<'_, Self> {
         ToClientsIter { executor: self }
@@ -127,6 +176,14 @@ pub enum ExecutorMetricsKind {
     OutRequests,
     InRequests,
     InRequestReplies,
+    CompactedTables,
+    SpeculationGain,
+    Rollbacks,
+    StoreMemory,
+    StoreEvictions,
+    HotKeyAccesses,
+    HotKeyConflicts,
+    DelayedReads,
 }
 
 impl Debug for ExecutorMetricsKind {
@@ -141,6 +198,28 @@ impl Debug for ExecutorMetricsKind {
             ExecutorMetricsKind::InRequestReplies => {
                 write!(f, "in_request_replies")
             }
+            // table executor specific
+            ExecutorMetricsKind::CompactedTables => {
+                write!(f, "compacted_tables")
+            }
+            // speculative execution specific
+            ExecutorMetricsKind::SpeculationGain => {
+                write!(f, "speculation_gain")
+            }
+            ExecutorMetricsKind::Rollbacks => write!(f, "rollbacks"),
+            // store specific
+            ExecutorMetricsKind::StoreMemory => write!(f, "store_memory"),
+            ExecutorMetricsKind::StoreEvictions => {
+                write!(f, "store_evictions")
+            }
+            ExecutorMetricsKind::HotKeyAccesses => {
+                write!(f, "hot_key_accesses")
+            }
+            ExecutorMetricsKind::HotKeyConflicts => {
+                write!(f, "hot_key_conflicts")
+            }
+            // local read specific
+            ExecutorMetricsKind::DelayedReads => write!(f, "delayed_reads"),
         }
     }
 }
@@ -166,11 +245,104 @@ fn key_index(key: &Key) -> (usize, usize) {
     (0, index)
 }
 
+/// Read-only ops of a command with `Consistency::Eventual` on a given key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalRead {
+    pub rifl: Rifl,
+    pub key: Key,
+    pub ops: Arc<Vec<KVOp>>,
+    // write on the key that the read should observe, if any (see
+    // `Command::set_session`)
+    pub after: Option<Rifl>,
+}
+
+impl LocalRead {
+    pub fn new(
+        rifl: Rifl,
+        key: Key,
+        ops: Arc<Vec<KVOp>>,
+        after: Option<Rifl>,
+    ) -> Self {
+        Self {
+            rifl,
+            key,
+            ops,
+            after,
+        }
+    }
+
+    /// Checks if the read can be executed in a `KVStore`, i.e. if the store
+    /// reflects the write that the read should observe (if any).
+    pub fn ready(&self, store: &KVStore) -> bool {
+        self.after
+            .map(|after| store.applied(&self.key, after))
+            .unwrap_or(true)
+    }
+
+    /// Executes the read in a `KVStore`.
+    pub fn execute(self, store: &mut KVStore) -> ExecutorResult {
+        let LocalRead { rifl, key, ops, .. } = self;
+        // take the ops inside the arc if we're the last with a reference to
+        // it (otherwise, clone them)
+        let ops =
+            Arc::try_unwrap(ops).unwrap_or_else(|ops| ops.as_ref().clone());
+        let partial_results = store.read(&key, ops);
+        ExecutorResult::new(rifl, key, partial_results)
+    }
+}
+
+/// Local reads waiting for the `KVStore` to reflect the writes they should
+/// observe (see `LocalRead::ready`). Executors delay such reads until the
+/// writes are executed (and, if speculatively executed, confirmed).
+#[derive(Debug, Clone, Default)]
+pub struct DelayedReads {
+    reads: Vec<LocalRead>,
+}
+
+impl DelayedReads {
+    /// Creates an empty set of delayed reads.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `read` if it can be executed right away. Otherwise, the read is
+    /// delayed and `None` is returned.
+    pub fn delay(
+        &mut self,
+        read: LocalRead,
+        store: &KVStore,
+    ) -> Option<LocalRead> {
+        if read.ready(store) {
+            Some(read)
+        } else {
+            self.reads.push(read);
+            None
+        }
+    }
+
+    /// Returns the oldest delayed read that can now be executed, if any.
+    pub fn next_ready(&mut self, store: &KVStore) -> Option<LocalRead> {
+        let position = self.reads.iter().position(|read| read.ready(store))?;
+        Some(self.reads.remove(position))
+    }
+
+    /// Returns the number of reads delayed.
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Checks if there are no reads delayed.
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExecutorResult {
     pub rifl: Rifl,
     pub key: Key,
     pub partial_results: Vec<KVOpResult>,
+    pub timestamps: Option<ExecutionTimestamps>,
 }
 
 impl ExecutorResult {
@@ -179,6 +351,13 @@ impl ExecutorResult {
             rifl,
             key,
             partial_results,
+            timestamps: None,
         }
     }
+
+    /// Sets the times at which the command was committed and executed.
+    pub fn with_timestamps(mut self, timestamps: ExecutionTimestamps) -> Self {
+        self.timestamps = Some(timestamps);
+        self
+    }
 }
@@ -0,0 +1,128 @@
+use super::Key;
+use crate::HashMap;
+use std::collections::BTreeSet;
+
+/// Per-key access and conflict counts, used to find the hottest keys (e.g. to
+/// check that a skewed workload matches the intended distribution).
+///
+/// Each command executed on a key counts as an access, and as a conflict if
+/// either the command or the previous one executed on the key is not
+/// read-only.
+#[derive(Debug, Clone, Default)]
+pub struct HotKeys {
+    // whether the last command executed on each key was read-only
+    last_read_only: HashMap<Key, bool>,
+    accesses: Counts,
+    conflicts: Counts,
+}
+
+impl HotKeys {
+    /// Creates a new `HotKeys` instance.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records a command executed on `key`.
+    #[allow(clippy::ptr_arg)]
+    pub fn record(&mut self, key: &Key, read_only: bool) {
+        self.accesses.increment(key);
+        if let Some(last_read_only) =
+            self.last_read_only.insert(key.clone(), read_only)
+        {
+            if !(last_read_only && read_only) {
+                self.conflicts.increment(key);
+            }
+        }
+    }
+
+    /// Returns the number of accesses to `key`.
+    #[allow(clippy::ptr_arg)]
+    pub fn accesses(&self, key: &Key) -> u64 {
+        self.accesses.get(key)
+    }
+
+    /// Returns the number of conflicts on `key`.
+    #[allow(clippy::ptr_arg)]
+    pub fn conflicts(&self, key: &Key) -> u64 {
+        self.conflicts.get(key)
+    }
+
+    /// Returns the `k` most accessed keys (and their number of accesses),
+    /// most accessed first.
+    pub fn top_accesses(&self, k: usize) -> Vec<(Key, u64)> {
+        self.accesses.top(k)
+    }
+
+    /// Returns the `k` most conflicting keys (and their number of conflicts),
+    /// most conflicting first.
+    pub fn top_conflicts(&self, k: usize) -> Vec<(Key, u64)> {
+        self.conflicts.top(k)
+    }
+}
+
+// Counts per key, also kept sorted so that the highest can be found without
+// going through all keys.
+#[derive(Debug, Clone, Default)]
+struct Counts {
+    counts: HashMap<Key, u64>,
+    sorted: BTreeSet<(u64, Key)>,
+}
+
+impl Counts {
+    fn increment(&mut self, key: &Key) {
+        let count = self.counts.entry(key.clone()).or_default();
+        if *count > 0 {
+            self.sorted.remove(&(*count, key.clone()));
+        }
+        *count += 1;
+        self.sorted.insert((*count, key.clone()));
+    }
+
+    fn get(&self, key: &Key) -> u64 {
+        self.counts.get(key).cloned().unwrap_or_default()
+    }
+
+    fn top(&self, k: usize) -> Vec<(Key, u64)> {
+        self.sorted
+            .iter()
+            .rev()
+            .take(k)
+            .map(|(count, key)| (key.clone(), *count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hot_keys_flow() {
+        let key = |key: &str| String::from(key);
+        let mut hot_keys = HotKeys::new();
+
+        // A is read 3 times, B is written twice, and C is read once
+        for _ in 0..3 {
+            hot_keys.record(&key("A"), true);
+        }
+        hot_keys.record(&key("B"), false);
+        hot_keys.record(&key("B"), false);
+        hot_keys.record(&key("C"), true);
+        assert_eq!(hot_keys.accesses(&key("A")), 3);
+        assert_eq!(hot_keys.accesses(&key("D")), 0);
+        assert_eq!(
+            hot_keys.top_accesses(2),
+            vec![(key("A"), 3), (key("B"), 2)]
+        );
+
+        // reads don't conflict with reads, but conflict with writes
+        assert_eq!(hot_keys.conflicts(&key("A")), 0);
+        assert_eq!(hot_keys.conflicts(&key("B")), 1);
+        hot_keys.record(&key("A"), false);
+        hot_keys.record(&key("A"), true);
+        assert_eq!(
+            hot_keys.top_conflicts(10),
+            vec![(key("A"), 2), (key("B"), 1)]
+        );
+    }
+}
@@ -0,0 +1,2474 @@
+// This module contains the definition of `Store` and its implementations.
+mod store;
+
+// This module contains the definition of `StateMachine`.
+mod state_machine;
+
+// This module contains the definition of `HotKeys`.
+mod hot_keys;
+
+// Re-exports.
+pub use hot_keys::HotKeys;
+pub use state_machine::StateMachine;
+#[cfg(feature = "sled")]
+pub use store::SledStore;
+pub use store::{MemoryStore, Store};
+
+use crate::executor::{
+    ExecutionOrderMonitor, ExecutorMetrics, ExecutorMetricsKind,
+};
+use crate::id::{ClientId, Rifl};
+use crate::{HashMap, HashSet};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+// Definition of `Key` and `Value` types:
+// - values are opaque bytes (which are cheap to clone, since `Bytes` is
+//   reference-counted), so that payloads are never UTF-8 validated or copied
+pub type Key = String;
+pub type Value = Bytes;
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum KVOp {
+    Get,
+    // returns the current value along with the version of the last write on
+    // the key (see `Version`)
+    GetVersioned,
+    Put(Value),
+    // puts a value that expires `ttl` units of logical time after the
+    // operation is executed (see `KVStore::set_time`)
+    PutWithTtl { value: Value, ttl: u64 },
+    Delete,
+    // sets the value to `new` if the current value is `expected` (and fails
+    // otherwise)
+    Cas { expected: Value, new: Value },
+    // sets the value if the key has no value (and fails otherwise)
+    PutIfAbsent(Value),
+    // adds to the counter stored in the key (see `kvs::counter_to_value`);
+    // if the key has no value, the counter is assumed to be 0, and if its
+    // value is not a counter, the op fails
+    Add(i64),
+    // appends to the current value
+    Append(Value),
+    // returns up to `limit` entries whose keys are between the key of the
+    // operation (inclusive) and `end` (exclusive)
+    Scan { end: Key, limit: usize },
+    // applies an opaque payload to the key, interpreted by the `StateMachine`
+    // of the store (see `KVStore::set_state_machine`)
+    Opaque(Value),
+    // fails if the version of the last write on the key is not the one
+    // expected (see `Command::execute` for how commands with validations are
+    // executed)
+    Validate(Option<Version>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KVOpResult {
+    // the result of single-key operations:
+    // - `Get` and `Delete` return the current value
+    // - `Put`, `PutWithTtl`, `PutIfAbsent`, `Append` and `Validate` return
+    //   `None`
+    // - `Add` returns the new value of the counter
+    // - `Cas` returns the value before the operation (i.e. the expected value)
+    // - `Opaque` returns the output of the `StateMachine`
+    Value(Option<Value>),
+    // the result of `GetVersioned`: the current value, and the version of the
+    // last write on the key (if it was ever written)
+    Versioned {
+        value: Option<Value>,
+        version: Option<Version>,
+    },
+    // the result of `Scan`: the entries found, sorted by key
+    Entries(Vec<(Key, Value)>),
+    // the result of operations that failed, in which case the store is not
+    // modified
+    Error(KVOpError),
+}
+
+/// Version of the last write applied to a key: the identifier of the command
+/// that wrote it, and the logical time at which it was executed (see
+/// `KVStore::set_time`).
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct Version {
+    pub rifl: Rifl,
+    pub time: u64,
+}
+
+impl Version {
+    /// Creates a new `Version`.
+    pub fn new(rifl: Rifl, time: u64) -> Self {
+        Self { rifl, time }
+    }
+}
+
+/// Notification of a write applied to a watched key (see `KVStore::watch`):
+/// the new value of the key (`None` if it was removed), and the version of the
+/// write.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchNotification {
+    pub key: Key,
+    pub value: Option<Value>,
+    pub version: Version,
+}
+
+impl WatchNotification {
+    /// Creates a new `WatchNotification`.
+    pub fn new(key: Key, value: Option<Value>, version: Version) -> Self {
+        Self {
+            key,
+            value,
+            version,
+        }
+    }
+}
+
+/// Reasons why an operation can fail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KVOpError {
+    /// A `Cas` found a value different from the one expected.
+    CasMismatch { current: Option<Value> },
+    /// A `PutIfAbsent` found that the key already has a value.
+    KeyExists { current: Value },
+    /// A `Validate` found a version different from the one expected.
+    VersionMismatch { current: Option<Version> },
+    /// An `Add` found a value that is not a counter.
+    TypeMismatch { current: Value },
+    /// A `Scan` was submitted to a store that only has part of the keys (e.g.
+    /// with more than one shard), and thus can't return all entries in the
+    /// range.
+    ScanUnsupported,
+    /// The operation was not executed, since some validation of its command
+    /// failed.
+    Aborted,
+}
+
+impl fmt::Display for KVOpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KVOpError::CasMismatch { current } => {
+                write!(f, "cas mismatch: current value is {:?}", current)
+            }
+            KVOpError::KeyExists { current } => {
+                write!(f, "key exists with value {:?}", current)
+            }
+            KVOpError::VersionMismatch { current } => {
+                write!(f, "version mismatch: current version is {:?}", current)
+            }
+            KVOpError::TypeMismatch { current } => {
+                write!(f, "type mismatch: {:?} is not a counter", current)
+            }
+            KVOpError::ScanUnsupported => {
+                write!(f, "scans are not supported in this configuration")
+            }
+            KVOpError::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+impl std::error::Error for KVOpError {}
+
+impl KVOpResult {
+    /// Returns the value in the result of a single-key operation.
+    pub fn value(&self) -> Option<&Value> {
+        match self {
+            KVOpResult::Value(value) => value.as_ref(),
+            KVOpResult::Versioned { value, .. } => value.as_ref(),
+            KVOpResult::Entries(_) | KVOpResult::Error(_) => None,
+        }
+    }
+
+    /// Returns the version in the result of a `GetVersioned`.
+    pub fn version(&self) -> Option<&Version> {
+        match self {
+            KVOpResult::Versioned { version, .. } => version.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the entries in the result of a `Scan`.
+    pub fn entries(&self) -> Option<&[(Key, Value)]> {
+        match self {
+            KVOpResult::Entries(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Returns the error in the result of an operation that failed.
+    pub fn error(&self) -> Option<&KVOpError> {
+        match self {
+            KVOpResult::Error(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Checks if the operation failed.
+    pub fn is_error(&self) -> bool {
+        self.error().is_some()
+    }
+}
+
+impl From<Option<Value>> for KVOpResult {
+    fn from(value: Option<Value>) -> Self {
+        KVOpResult::Value(value)
+    }
+}
+
+impl KVOp {
+    /// Checks if the operation does not modify the store.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            KVOp::Get
+                | KVOp::GetVersioned
+                | KVOp::Scan { .. }
+                | KVOp::Validate(_)
+        )
+    }
+
+    /// Returns the name of the type of the operation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KVOp::Get => "get",
+            KVOp::GetVersioned => "get_versioned",
+            KVOp::Put(_) => "put",
+            KVOp::PutWithTtl { .. } => "put_with_ttl",
+            KVOp::Delete => "delete",
+            KVOp::Cas { .. } => "cas",
+            KVOp::PutIfAbsent(_) => "put_if_absent",
+            KVOp::Add(_) => "add",
+            KVOp::Append(_) => "append",
+            KVOp::Scan { .. } => "scan",
+            KVOp::Opaque(_) => "opaque",
+            KVOp::Validate(_) => "validate",
+        }
+    }
+
+    /// Checks if the operation is a `Scan`.
+    pub fn is_scan(&self) -> bool {
+        matches!(self, KVOp::Scan { .. })
+    }
+
+    /// Checks if the operation may fail depending on the state of its key
+    /// (see `KVOpError`), in which case none of the ops of its command is
+    /// executed.
+    pub fn is_conditional(&self) -> bool {
+        matches!(
+            self,
+            KVOp::Cas { .. }
+                | KVOp::PutIfAbsent(_)
+                | KVOp::Add(_)
+                | KVOp::Validate(_)
+        )
+    }
+
+    /// Checks if the operation is a `Validate`.
+    pub fn is_validate(&self) -> bool {
+        matches!(self, KVOp::Validate(_))
+    }
+}
+
+/// Encodes a counter as a `Value`, the representation used by `KVOp::Add`.
+pub fn counter_to_value(counter: i64) -> Value {
+    Value::copy_from_slice(&counter.to_be_bytes())
+}
+
+/// Decodes a counter from a `Value`. Returns `None` if the value is not a
+/// counter.
+pub fn value_to_counter(value: &Value) -> Option<i64> {
+    let bytes: [u8; 8] = value.as_ref().try_into().ok()?;
+    Some(i64::from_be_bytes(bytes))
+}
+
+/// Returns the results of `count` ops that were not executed because one of
+/// them (or of some other ops of their command) would fail: the op that would
+/// fail, if any, gets its error, and the others get `KVOpError::Aborted`.
+pub fn aborted(
+    count: usize,
+    failure: Option<(usize, KVOpError)>,
+) -> Vec<KVOpResult> {
+    let mut results = vec![KVOpResult::Error(KVOpError::Aborted); count];
+    if let Some((failed, error)) = failure {
+        results[failed] = KVOpResult::Error(error);
+    }
+    results
+}
+
+// Keys can be namespaced by a table, in which case they're represented as
+// "table\0key". Since keys are sorted, all keys of a table are contiguous and
+// can be scanned with `table_range`. The separator is the NUL character, which
+// plain keys are assumed not to contain, so that they're never mistaken for
+// namespaced keys (as it would happen to "a/b" with a '/' separator).
+pub const TABLE_SEPARATOR: char = '\0';
+
+/// Creates the key `key` in table `table`.
+pub fn table_key(table: &str, key: &str) -> Key {
+    assert!(
+        !table.contains(TABLE_SEPARATOR) && !key.contains(TABLE_SEPARATOR),
+        "table names and keys can't contain the table separator"
+    );
+    format!("{}{}{}", table, TABLE_SEPARATOR, key)
+}
+
+/// Splits a key into its table and the key within that table. Returns `None`
+/// if the key is not namespaced by a table.
+pub fn split_table_key(key: &str) -> Option<(&str, &str)> {
+    key.split_once(TABLE_SEPARATOR)
+}
+
+/// Returns the start (inclusive) and end (exclusive) of the range containing
+/// all keys in table `table`.
+pub fn table_range(table: &str) -> (Key, Key) {
+    let start = table_key(table, "");
+    // the end is the table followed by the character after the separator
+    let after_separator = char::from_u32(TABLE_SEPARATOR as u32 + 1)
+        .expect("the character after the table separator should exist");
+    let end = format!("{}{}", table, after_separator);
+    (start, end)
+}
+
+// Maximum number of sequences kept above the watermark in `AppliedWrites`.
+const APPLIED_ABOVE_WATERMARK: usize = 64;
+
+// Sequences of the writes of a client applied to a key: all sequences up to
+// `watermark`, and the ones in `above`. Since clients with more than one
+// pending command (e.g. open-loop clients) may have their writes applied out
+// of order, the highest sequence alone doesn't tell whether a lower one was
+// applied. The watermark advances once there are no gaps below the sequences
+// in `above`; however, since gaps may also correspond to writes on other keys
+// (that are never applied to this one), at most `APPLIED_ABOVE_WATERMARK`
+// sequences are kept, and the watermark jumps to the lowest one when that
+// limit is exceeded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct AppliedWrites {
+    watermark: u64,
+    above: BTreeSet<u64>,
+}
+
+impl AppliedWrites {
+    fn add(&mut self, sequence: u64) {
+        if sequence <= self.watermark {
+            return;
+        }
+        self.above.insert(sequence);
+        while self.above.len() > APPLIED_ABOVE_WATERMARK {
+            self.watermark =
+                self.above.pop_first().expect("sequence should exist");
+        }
+        while self.above.remove(&(self.watermark + 1)) {
+            self.watermark += 1;
+        }
+    }
+
+    fn contains(&self, sequence: u64) -> bool {
+        sequence <= self.watermark || self.above.contains(&sequence)
+    }
+}
+
+// Metadata of a key (i.e. its state other than its value), persisted in
+// persistent `Store`s (see `KVStore::persist_meta`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyMeta {
+    version: Option<Version>,
+    expiration: Option<u64>,
+    applied: HashMap<ClientId, AppliedWrites>,
+    executed: VecDeque<(Rifl, Vec<KVOpResult>)>,
+}
+
+// Entry of the undo log, created when some command is speculatively executed.
+#[derive(Clone)]
+struct UndoEntry {
+    rifl: Rifl,
+    read_only: bool,
+    // whether the command is a duplicate (and thus it was not executed)
+    duplicate: bool,
+    // results of the command, only kept if they should be cached once the
+    // command is confirmed (see `KVStore::set_dedup_window`)
+    results: Option<Vec<KVOpResult>>,
+    // value of the key before the command was executed
+    previous: Option<Value>,
+    // expiration time of the value before the command was executed
+    previous_expiration: Option<u64>,
+    // version of the key before the command was executed
+    previous_version: Option<Version>,
+    // notification of the write, only released once the command is confirmed
+    notification: Option<WatchNotification>,
+}
+
+#[derive(Clone)]
+pub struct KVStore {
+    store: Box<dyn Store>,
+    // mapping from key to the logical time at which its value expires; values
+    // are only removed from `store` when the key is next written
+    expirations: HashMap<Key, u64>,
+    // mapping from key to the version of the last write on it
+    versions: HashMap<Key, Version>,
+    // mapping from key to the writes of each client applied to it (see
+    // `KVStore::applied`)
+    applied: HashMap<Key, HashMap<ClientId, AppliedWrites>>,
+    // logical time at which commands are being executed
+    time: u64,
+    // mapping from key to the memory (in bytes) used by the key and its value
+    key_memory: HashMap<Key, usize>,
+    // total memory (in bytes) used by keys and values
+    memory: usize,
+    // if set, the least recently used keys are evicted while `memory` is above
+    // this capacity
+    capacity: Option<usize>,
+    // mapping from key to its last access (only tracked if there's a
+    // capacity), and from last access to key (so that the least recently used
+    // key is the first)
+    last_access: HashMap<Key, u64>,
+    lru: BTreeMap<u64, Key>,
+    access_count: u64,
+    evictions: u64,
+    monitor: Option<ExecutionOrderMonitor>,
+    // mapping from key to the commands speculatively executed on it (oldest
+    // first) that have not been confirmed yet
+    undo_log: HashMap<Key, VecDeque<UndoEntry>>,
+    // state machine interpreting `KVOp::Opaque` payloads
+    state_machine: Option<Arc<dyn StateMachine>>,
+    // keys whose writes are notified, and the notifications not yet taken
+    watched: HashSet<Key>,
+    notifications: Vec<WatchNotification>,
+    // if set, the results of the last `dedup_window` commands executed on
+    // each key (that are not read-only) are cached, so that duplicates are
+    // not executed again
+    dedup_window: Option<usize>,
+    executed: HashMap<Key, VecDeque<(Rifl, Vec<KVOpResult>)>>,
+    // if set, per-key access and conflict counts are tracked, and the number
+    // of hottest keys reported in metrics
+    hot_keys: Option<(usize, HotKeys)>,
+    // whether this store has all keys and thus can execute scans
+    scans: bool,
+}
+
+impl KVStore {
+    /// Creates a new `KVStore` instance, kept in memory.
+    pub fn new(monitor_execution_order: bool) -> Self {
+        Self::with_store(Box::new(MemoryStore::new()), monitor_execution_order)
+    }
+
+    /// Creates a new `KVStore` instance, kept in the `Store` provided.
+    pub fn with_store(
+        store: Box<dyn Store>,
+        monitor_execution_order: bool,
+    ) -> Self {
+        let monitor = if monitor_execution_order {
+            Some(ExecutionOrderMonitor::new())
+        } else {
+            None
+        };
+        Self {
+            store,
+            expirations: Default::default(),
+            versions: Default::default(),
+            applied: Default::default(),
+            time: 0,
+            key_memory: Default::default(),
+            memory: 0,
+            capacity: None,
+            last_access: Default::default(),
+            lru: Default::default(),
+            access_count: 0,
+            evictions: 0,
+            monitor,
+            undo_log: Default::default(),
+            state_machine: None,
+            watched: Default::default(),
+            notifications: Vec::new(),
+            dedup_window: None,
+            executed: Default::default(),
+            hot_keys: None,
+            scans: true,
+        }
+    }
+
+    /// Replaces the `Store` where values are kept. This should be done before
+    /// executing any command. If the store is persistent (see
+    /// `Store::persistent`), the metadata of each key kept in it (e.g. its
+    /// version) is restored, so that a store reopened after a restart gives
+    /// the same results (e.g. detecting the same duplicates).
+    pub fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store = store;
+        let metas: Vec<_> = self.store.metas().collect();
+        for (key, meta) in metas {
+            let meta: KeyMeta = bincode::deserialize(&meta)
+                .expect("key metadata should be deserialized");
+            if let Some(version) = meta.version {
+                self.versions.insert(key.clone(), version);
+            }
+            if let Some(expiration) = meta.expiration {
+                self.expirations.insert(key.clone(), expiration);
+            }
+            if !meta.applied.is_empty() {
+                self.applied.insert(key.clone(), meta.applied);
+            }
+            if !meta.executed.is_empty() {
+                self.executed.insert(key.clone(), meta.executed);
+            }
+            if let Some(value) = self.store.get(&key) {
+                let memory = key.len() + value.len();
+                self.key_memory.insert(key.clone(), memory);
+                self.memory += memory;
+            }
+            self.touch(&key);
+        }
+        self.evict();
+    }
+
+    pub fn monitor(&self) -> Option<&ExecutionOrderMonitor> {
+        self.monitor.as_ref()
+    }
+
+    /// Sets the logical time at which the next commands are executed, used to
+    /// expire values put with `KVOp::PutWithTtl`. So that all replicas expire
+    /// values identically, this time must be the same at all replicas for a
+    /// given command (e.g. its timestamp in `Tempo`, or its slot in `FPaxos`),
+    /// and not decrease between commands on the same key (see also
+    /// `KVStore::set_time_on`). If it's never set, it's always 0, and thus
+    /// only values with a zero TTL expire.
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    /// Sets the logical time at which a command on `keys` is executed (see
+    /// `KVStore::set_time`) to `time`, or to the time of the last write on one
+    /// of these keys, if later. This is meant for executors whose commands
+    /// have no timestamp, which can use the logical time assigned to each
+    /// command by its coordinator (see `Command::logical_time`; e.g. in
+    /// `Atlas`): since this time is part of the command,
+    /// and commands on the same key are executed in the same order at all
+    /// replicas, all of them compute the same time for each command, and this
+    /// time never decreases between writes on the same key.
+    pub fn set_time_on<'a, I>(&mut self, time: u64, keys: I)
+    where
+        I: IntoIterator<Item = &'a Key>,
+    {
+        self.time = keys
+            .into_iter()
+            .filter_map(|key| self.versions.get(key))
+            .map(|version| version.time)
+            .fold(time, std::cmp::max);
+    }
+
+    /// Removes the values that expire at or before `horizon(key)`, so that
+    /// expired keys that are not written again don't keep using memory. Since
+    /// removed values are no longer seen by any command, no command on `key`
+    /// should be executed from then on at a time before `horizon(key)` (e.g.
+    /// the last slot executed in `FPaxos`). Returns the number of values
+    /// removed.
+    pub fn purge_expired<F>(&mut self, horizon: F) -> usize
+    where
+        F: Fn(&Key) -> u64,
+    {
+        let expired: Vec<_> = self
+            .expirations
+            .iter()
+            .filter(|(key, expiration)| **expiration <= horizon(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired.iter() {
+            self.store_remove(key);
+            self.expirations.remove(key);
+            self.persist_meta(key);
+        }
+        expired.len()
+    }
+
+    /// Sets whether `KVOp::Scan`s can be executed. This should be disabled
+    /// when the store only has part of the keys (e.g. with more than one
+    /// shard, or with several executors each with its own store), as scans
+    /// would then silently miss entries; once disabled, scans fail with
+    /// `KVOpError::ScanUnsupported`.
+    pub fn set_scans(&mut self, scans: bool) {
+        self.scans = scans;
+    }
+
+    /// Sets the `StateMachine` that interprets `KVOp::Opaque` payloads. All
+    /// replicas must use the same state machine.
+    pub fn set_state_machine(&mut self, state_machine: Arc<dyn StateMachine>) {
+        self.state_machine = Some(state_machine);
+    }
+
+    /// Makes commands executed at most once: the results of the last
+    /// `dedup_window` commands executed on each key (that are not read-only)
+    /// are cached, and a command with the same `Rifl` as one of them (e.g. a
+    /// retry by its client) is not executed again, getting the cached results
+    /// instead. So that all replicas detect the same duplicates, the window is
+    /// a number of commands (and not a period of time).
+    pub fn set_dedup_window<W>(&mut self, dedup_window: W)
+    where
+        W: Into<Option<usize>>,
+    {
+        self.dedup_window = dedup_window.into();
+        if self.dedup_window.is_none() {
+            self.executed.clear();
+        }
+    }
+
+    /// Starts tracking per-key access and conflict counts (see `HotKeys`),
+    /// reporting the `top` hottest keys in metrics (see
+    /// `KVStore::update_metrics`). If `top` is `None`, counts are no longer
+    /// tracked.
+    pub fn set_hot_keys<T>(&mut self, top: T)
+    where
+        T: Into<Option<usize>>,
+    {
+        self.hot_keys = top.into().map(|top| {
+            let hot_keys = self
+                .hot_keys
+                .take()
+                .map(|(_, hot_keys)| hot_keys)
+                .unwrap_or_default();
+            (top, hot_keys)
+        });
+    }
+
+    /// Returns the per-key access and conflict counts, if they're tracked.
+    pub fn hot_keys(&self) -> Option<&HotKeys> {
+        self.hot_keys.as_ref().map(|(_, hot_keys)| hot_keys)
+    }
+
+    /// Starts watching `key`: from now on, each command that successfully
+    /// writes the key creates a `WatchNotification` (see
+    /// `KVStore::take_notifications`). Speculative writes are only notified
+    /// once they're confirmed.
+    pub fn watch(&mut self, key: Key) {
+        self.watched.insert(key);
+    }
+
+    /// Stops watching `key`.
+    #[allow(clippy::ptr_arg)]
+    pub fn unwatch(&mut self, key: &Key) {
+        self.watched.remove(key);
+    }
+
+    /// Returns the notifications created since the last call (oldest first).
+    #[must_use]
+    pub fn take_notifications(&mut self) -> Vec<WatchNotification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Bounds the memory (in bytes) used by keys and values: while above
+    /// `capacity`, the least recently used keys are evicted. Since keys may be
+    /// accessed in different orders at different replicas, they may also be
+    /// evicted differently, and thus this should only be used for cache-style
+    /// workloads, where reading an evicted key is acceptable.
+    pub fn set_capacity<C>(&mut self, capacity: C)
+    where
+        C: Into<Option<usize>>,
+    {
+        self.capacity = capacity.into();
+        // keys are only tracked while there's a capacity, so start tracking
+        // the ones that aren't (in key order, so that it's deterministic)
+        let mut untracked: Vec<_> = self
+            .key_memory
+            .keys()
+            .filter(|key| !self.last_access.contains_key(*key))
+            .cloned()
+            .collect();
+        untracked.sort();
+        for key in untracked {
+            self.touch(&key);
+        }
+        self.evict();
+    }
+
+    /// Returns the memory (in bytes) used by keys and values. Entries that
+    /// were in the `Store` before this `KVStore` was created are not
+    /// accounted for.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Returns the memory (in bytes) used by `key` and its value, if it has
+    /// one.
+    #[allow(clippy::ptr_arg)]
+    pub fn key_memory(&self, key: &Key) -> Option<usize> {
+        self.key_memory.get(key).cloned()
+    }
+
+    /// Returns the version of the last write on `key`, if it was ever written
+    /// (and not evicted since).
+    #[allow(clippy::ptr_arg)]
+    pub fn version(&self, key: &Key) -> Option<Version> {
+        self.versions.get(key).cloned()
+    }
+
+    /// Checks if the write with identifier `rifl` on `key` was applied (i.e.
+    /// executed or, if speculatively executed, confirmed). The writes of a
+    /// client can be applied in any order, as long as it doesn't have more
+    /// than 64 writes on the key applied before an older one.
+    #[allow(clippy::ptr_arg)]
+    pub fn applied(&self, key: &Key, rifl: Rifl) -> bool {
+        self.applied
+            .get(key)
+            .and_then(|applied| applied.get(&rifl.source()))
+            .map(|applied| applied.contains(rifl.sequence()))
+            .unwrap_or(false)
+    }
+
+    /// Returns the number of keys evicted until now.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Records the memory used by the store, the number of keys evicted and
+    /// the hottest keys (if tracked) in `metrics`.
+    pub fn update_metrics(&self, metrics: &mut ExecutorMetrics) {
+        if let Some((top, hot_keys)) = self.hot_keys.as_ref() {
+            metrics.set_ranked(
+                ExecutorMetricsKind::HotKeyAccesses,
+                hot_keys.top_accesses(*top),
+            );
+            metrics.set_ranked(
+                ExecutorMetricsKind::HotKeyConflicts,
+                hot_keys.top_conflicts(*top),
+            );
+        }
+        metrics.set_aggregated(
+            ExecutorMetricsKind::StoreMemory,
+            self.memory as u64,
+        );
+        metrics.set_aggregated(
+            ExecutorMetricsKind::StoreEvictions,
+            self.evictions,
+        );
+    }
+
+    /// Executes a single-key `KVOp` in the `KVStore`.
+    #[cfg(test)]
+    pub fn test_execute(&mut self, key: &Key, op: KVOp) -> Option<Value> {
+        let mut results = self.do_execute(key, vec![op], None);
+        assert_eq!(results.len(), 1);
+        match results.pop().unwrap() {
+            KVOpResult::Value(value) => value,
+            KVOpResult::Versioned { value, .. } => value,
+            KVOpResult::Entries(_) => panic!("op should be single-key"),
+            KVOpResult::Error(error) => panic!("op failed: {}", error),
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        key: &Key,
+        ops: Vec<KVOp>,
+        rifl: Rifl,
+    ) -> Vec<KVOpResult> {
+        let read_only = ops.iter().all(KVOp::is_read_only);
+        self.execute_as(key, ops, rifl, read_only)
+    }
+
+    /// Same as `KVStore::execute`, but with whether `ops` are read-only
+    /// already known (e.g. from `Command::read_only`), and thus without
+    /// inspecting each op again. The results of read-only ops are not kept
+    /// for deduplication (see `KVStore::set_dedup_window`).
+    pub fn execute_as(
+        &mut self,
+        key: &Key,
+        ops: Vec<KVOp>,
+        rifl: Rifl,
+        read_only: bool,
+    ) -> Vec<KVOpResult> {
+        debug_assert_eq!(read_only, ops.iter().all(KVOp::is_read_only));
+        // duplicates are not executed (nor added to the execution order)
+        if let Some(results) = self.cached_results(key, rifl) {
+            return results;
+        }
+        // update monitor, if we're monitoring
+        if let Some(monitor) = self.monitor.as_mut() {
+            monitor.add(&key, read_only, rifl);
+        }
+        self.record_access(key, read_only);
+        let results = self.do_execute(key, ops, Some(rifl));
+        if !read_only {
+            self.cache_results(key, rifl, &results);
+            self.record_applied(key, rifl);
+            self.persist_meta(key);
+        }
+        results
+    }
+
+    /// Executes read-only `KVOp`s in the `KVStore`, outside of the execution
+    /// order (and thus without updating the monitor). This is used to answer
+    /// reads with `Consistency::Eventual`.
+    pub fn read(&mut self, key: &Key, ops: Vec<KVOp>) -> Vec<KVOpResult> {
+        assert!(
+            ops.iter().all(KVOp::is_read_only),
+            "only read-only ops can be executed outside of the execution order"
+        );
+        self.record_access(key, true);
+        self.do_execute(key, ops, None)
+    }
+
+    /// Speculatively executes `KVOp`s in the `KVStore`. The execution must
+    /// later be either confirmed (with `KVStore::confirm`) or undone (with
+    /// `KVStore::rollback`).
+    pub fn speculate(
+        &mut self,
+        key: &Key,
+        ops: Vec<KVOp>,
+        rifl: Rifl,
+    ) -> Vec<KVOpResult> {
+        // the monitor is only updated once the execution is confirmed
+        let read_only = ops.iter().all(KVOp::is_read_only);
+        let cached_results = self.cached_results(key, rifl);
+        let previous = self.store.get(key);
+        let previous_expiration = self.expirations.get(key).cloned();
+        let previous_version = self.versions.get(key).cloned();
+        self.undo_log
+            .entry(key.clone())
+            .or_default()
+            .push_back(UndoEntry {
+                rifl,
+                read_only,
+                duplicate: cached_results.is_some(),
+                results: None,
+                previous,
+                previous_expiration,
+                previous_version,
+                notification: None,
+            });
+        // duplicates are not executed
+        if let Some(results) = cached_results {
+            return results;
+        }
+        let notifications = self.notifications.len();
+        let results = self.do_execute(key, ops, Some(rifl));
+        let entry = self
+            .undo_log
+            .get_mut(key)
+            .and_then(VecDeque::back_mut)
+            .expect("undo entry should exist");
+        // hold the notification of the write (if any) until it's confirmed
+        if self.notifications.len() > notifications {
+            entry.notification = self.notifications.pop();
+        }
+        // and the same for the results to be cached
+        if !read_only && self.dedup_window.is_some() {
+            entry.results = Some(results.clone());
+        }
+        if !read_only {
+            self.persist_meta(key);
+        }
+        results
+    }
+
+    /// Confirms the oldest speculative execution on `key`, if it belongs to
+    /// the command with identifier `rifl`. Returns whether it was confirmed.
+    #[allow(clippy::ptr_arg)]
+    pub fn confirm(&mut self, key: &Key, rifl: Rifl) -> bool {
+        let log = match self.undo_log.get_mut(key) {
+            Some(log) => log,
+            None => return false,
+        };
+        match log.front() {
+            Some(entry) if entry.rifl == rifl => {
+                let entry = log.pop_front().expect("entry should exist");
+                if log.is_empty() {
+                    self.undo_log.remove(key);
+                }
+                // update monitor, if we're monitoring (and the command was
+                // executed)
+                if let Some(monitor) = self.monitor.as_mut() {
+                    if !entry.duplicate {
+                        monitor.add(key, entry.read_only, rifl);
+                    }
+                }
+                if !entry.duplicate {
+                    self.record_access(key, entry.read_only);
+                    if !entry.read_only {
+                        self.record_applied(key, rifl);
+                    }
+                }
+                if let Some(results) = entry.results {
+                    self.cache_results(key, rifl, &results);
+                }
+                if !entry.read_only {
+                    self.persist_meta(key);
+                }
+                // release the notification, if the key is still watched
+                if let Some(notification) = entry.notification {
+                    if self.watched.contains(key) {
+                        self.notifications.push(notification);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Undoes all speculative executions on `key` that have not been
+    /// confirmed, returning the identifiers of the commands undone (oldest
+    /// first).
+    #[allow(clippy::ptr_arg)]
+    pub fn rollback(&mut self, key: &Key) -> Vec<Rifl> {
+        let log = match self.undo_log.remove(key) {
+            Some(log) => log,
+            None => return Vec::new(),
+        };
+        // the value before the oldest speculative execution is the one to be
+        // restored
+        let oldest = log.front().expect("undo log should be non-empty");
+        match oldest.previous.clone() {
+            Some(value) => self.store_insert(key, value),
+            None => self.store_remove(key),
+        };
+        match oldest.previous_expiration {
+            Some(expiration) => {
+                self.expirations.insert(key.clone(), expiration)
+            }
+            None => self.expirations.remove(key),
+        };
+        match oldest.previous_version {
+            Some(version) => self.versions.insert(key.clone(), version),
+            None => self.versions.remove(key),
+        };
+        self.persist_meta(key);
+        self.evict();
+        log.into_iter().map(|entry| entry.rifl).collect()
+    }
+
+    /// Returns the keys between `start` (inclusive) and `end` (exclusive) with
+    /// speculative executions that have not been confirmed.
+    #[allow(clippy::ptr_arg)]
+    pub fn speculated_keys(&self, start: &Key, end: &Key) -> Vec<Key> {
+        self.undo_log
+            .keys()
+            .filter(|key| *key >= start && *key < end)
+            .cloned()
+            .collect()
+    }
+
+    // Returns the cached results of `rifl` on `key`, if it's a duplicate. Since
+    // speculative executions are only cached once confirmed, they're also
+    // searched (and count towards the window).
+    fn cached_results(&self, key: &Key, rifl: Rifl) -> Option<Vec<KVOpResult>> {
+        let dedup_window = self.dedup_window?;
+        let mut speculated = 0;
+        if let Some(log) = self.undo_log.get(key) {
+            for entry in log.iter().rev() {
+                if let Some(results) = entry.results.as_ref() {
+                    if entry.rifl == rifl {
+                        return Some(results.clone());
+                    }
+                    speculated += 1;
+                }
+            }
+        }
+        self.executed
+            .get(key)?
+            .iter()
+            .rev()
+            .take(dedup_window.saturating_sub(speculated))
+            .find(|(executed_rifl, _)| *executed_rifl == rifl)
+            .map(|(_, results)| results.clone())
+    }
+
+    // Records an access to `key` in the hot keys, if they're tracked.
+    fn record_access(&mut self, key: &Key, read_only: bool) {
+        if let Some((_, hot_keys)) = self.hot_keys.as_mut() {
+            hot_keys.record(key, read_only);
+        }
+    }
+
+    // Records that the write with identifier `rifl` on `key` was applied.
+    fn record_applied(&mut self, key: &Key, rifl: Rifl) {
+        self.applied
+            .entry(key.clone())
+            .or_default()
+            .entry(rifl.source())
+            .or_default()
+            .add(rifl.sequence());
+    }
+
+    // Caches the results of `rifl` on `key`, if there's a dedup window.
+    fn cache_results(&mut self, key: &Key, rifl: Rifl, results: &[KVOpResult]) {
+        let dedup_window = match self.dedup_window {
+            Some(dedup_window) => dedup_window,
+            None => return,
+        };
+        let executed = self.executed.entry(key.clone()).or_default();
+        executed.push_back((rifl, results.to_vec()));
+        while executed.len() > dedup_window {
+            executed.pop_front();
+        }
+    }
+
+    /// Checks if the `KVOp`s of command `rifl` on `key` would all succeed,
+    /// without executing them. Otherwise, returns the index of the first op
+    /// that would fail, along with its error. Duplicates are never executed
+    /// again (see `KVStore::set_dedup_window`), and thus never fail.
+    pub fn check(
+        &self,
+        key: &Key,
+        ops: &[KVOp],
+        rifl: Rifl,
+    ) -> Option<(usize, KVOpError)> {
+        if self.cached_results(key, rifl).is_some() {
+            return None;
+        }
+        self.first_failure(key, ops, Some(rifl))
+    }
+
+    // Returns the index of the first op that would fail if `ops` were executed
+    // on `key`, along with its error, by simulating their execution on the
+    // current value and version of the key.
+    fn first_failure(
+        &self,
+        key: &Key,
+        ops: &[KVOp],
+        rifl: Option<Rifl>,
+    ) -> Option<(usize, KVOpError)> {
+        let mut value = self.get(key);
+        let mut version = self.version(key);
+        for (index, op) in ops.iter().enumerate() {
+            let error = match op {
+                KVOp::Cas { expected, .. }
+                    if value.as_ref() != Some(expected) =>
+                {
+                    Some(KVOpError::CasMismatch {
+                        current: value.clone(),
+                    })
+                }
+                KVOp::PutIfAbsent(_) => {
+                    value.as_ref().map(|current| KVOpError::KeyExists {
+                        current: current.clone(),
+                    })
+                }
+                KVOp::Add(_) => value
+                    .as_ref()
+                    .filter(|current| value_to_counter(current).is_none())
+                    .map(|current| KVOpError::TypeMismatch {
+                        current: current.clone(),
+                    }),
+                KVOp::Validate(expected) if version != *expected => {
+                    Some(KVOpError::VersionMismatch { current: version })
+                }
+                _ => None,
+            };
+            if let Some(error) = error {
+                return Some((index, error));
+            }
+            if op.is_read_only() {
+                continue;
+            }
+            // writes change the value and version seen by the next ops
+            value = match op {
+                KVOp::Put(new)
+                | KVOp::Cas { new, .. }
+                | KVOp::PutIfAbsent(new) => Some(new.clone()),
+                // values with a zero TTL expire right away
+                KVOp::PutWithTtl { value, ttl } => {
+                    (*ttl > 0).then(|| value.clone())
+                }
+                KVOp::Delete => None,
+                KVOp::Add(delta) => {
+                    let counter = value
+                        .as_ref()
+                        .and_then(value_to_counter)
+                        .unwrap_or_default();
+                    Some(counter_to_value(counter.wrapping_add(*delta)))
+                }
+                KVOp::Append(suffix) => Some(match value {
+                    Some(current) => {
+                        Value::from([current.as_ref(), suffix].concat())
+                    }
+                    None => suffix.clone(),
+                }),
+                KVOp::Opaque(payload) => {
+                    if let Some(state_machine) = self.state_machine.as_ref() {
+                        state_machine.apply(key, &mut value, payload);
+                    }
+                    value
+                }
+                op => unreachable!("op {:?} should be read-only", op),
+            };
+            if let Some(rifl) = rifl {
+                version = Some(Version::new(rifl, self.time));
+            }
+        }
+        None
+    }
+
+    #[allow(clippy::ptr_arg)]
+    fn do_execute(
+        &mut self,
+        key: &Key,
+        ops: Vec<KVOp>,
+        rifl: Option<Rifl>,
+    ) -> Vec<KVOpResult> {
+        // the ops of a command on a key are executed atomically: if some op
+        // would fail, none of them is executed
+        if let Some((failed, error)) = self.first_failure(key, &ops, rifl) {
+            self.touch(key);
+            return aborted(ops.len(), Some((failed, error)));
+        }
+        let mut written = None;
+        let results = ops
+            .into_iter()
+            .map(|op| {
+                let write = !op.is_read_only();
+                let result = self.do_execute_op(key, op);
+                // writes that didn't fail update the version of the key
+                match rifl {
+                    Some(rifl) if write && !result.is_error() => {
+                        let version = Version::new(rifl, self.time);
+                        self.versions.insert(key.clone(), version);
+                        written = Some(version);
+                    }
+                    _ => {}
+                }
+                result
+            })
+            .collect();
+        // notify the final value of a watched key, once per command
+        if let Some(version) = written {
+            if self.watched.contains(key) {
+                let notification =
+                    WatchNotification::new(key.clone(), self.get(key), version);
+                self.notifications.push(notification);
+            }
+        }
+        self.touch(key);
+        self.evict();
+        results
+    }
+
+    fn do_execute_op(&mut self, key: &Key, op: KVOp) -> KVOpResult {
+        match op {
+            KVOp::Scan { .. } if !self.scans => {
+                KVOpResult::Error(KVOpError::ScanUnsupported)
+            }
+            KVOp::Scan { end, limit } => {
+                KVOpResult::Entries(self.scan(key, &end, limit))
+            }
+            op => self.do_execute_single_key_op(key, op),
+        }
+    }
+
+    fn scan(&self, start: &Key, end: &Key, limit: usize) -> Vec<(Key, Value)> {
+        // `Store::range` doesn't support decreasing ranges
+        if start >= end {
+            return Vec::new();
+        }
+        self.store
+            .range(start, end)
+            .filter(|(key, _)| !self.expired(key))
+            .take(limit)
+            .collect()
+    }
+
+    fn do_execute_single_key_op(&mut self, key: &Key, op: KVOp) -> KVOpResult {
+        let result = match op {
+            KVOp::Get => self.get(key),
+            KVOp::GetVersioned => {
+                return KVOpResult::Versioned {
+                    value: self.get(key),
+                    version: self.versions.get(key).cloned(),
+                };
+            }
+            KVOp::Put(value) => {
+                // don't return the previous value
+                self.put(key, value, None);
+                None
+            }
+            KVOp::PutWithTtl { value, ttl } => {
+                let expiration = self.time.saturating_add(ttl);
+                self.put(key, value, Some(expiration));
+                None
+            }
+            KVOp::Delete => {
+                let current = self.get(key);
+                self.store_remove(key);
+                self.expirations.remove(key);
+                current
+            }
+            KVOp::Cas { expected, new } => {
+                let current = self.get(key);
+                if current.as_ref() != Some(&expected) {
+                    return KVOpResult::Error(KVOpError::CasMismatch {
+                        current,
+                    });
+                }
+                self.put(key, new, None);
+                current
+            }
+            KVOp::PutIfAbsent(value) => {
+                if let Some(current) = self.get(key) {
+                    return KVOpResult::Error(KVOpError::KeyExists { current });
+                }
+                self.put(key, value, None);
+                None
+            }
+            KVOp::Add(delta) => {
+                let counter = match self.get(key) {
+                    Some(current) => match value_to_counter(&current) {
+                        Some(counter) => counter,
+                        None => {
+                            return KVOpResult::Error(
+                                KVOpError::TypeMismatch { current },
+                            );
+                        }
+                    },
+                    None => 0,
+                };
+                let value = counter_to_value(counter.wrapping_add(delta));
+                // updates keep the expiration of the current value
+                let expiration = self.current_expiration(key);
+                self.put(key, value.clone(), expiration);
+                Some(value)
+            }
+            KVOp::Append(suffix) => {
+                let value = match self.get(key) {
+                    Some(current) => {
+                        Value::from([current.as_ref(), &suffix].concat())
+                    }
+                    None => suffix,
+                };
+                // don't return the new value
+                let expiration = self.current_expiration(key);
+                self.put(key, value, expiration);
+                None
+            }
+            KVOp::Opaque(payload) => {
+                let state_machine = self.state_machine.clone().expect(
+                    "opaque ops can only be executed with a state machine",
+                );
+                let mut state = self.get(key);
+                let output = state_machine.apply(key, &mut state, &payload);
+                match state {
+                    Some(value) => {
+                        // updates keep the expiration of the current value
+                        let expiration = self.current_expiration(key);
+                        self.put(key, value, expiration);
+                    }
+                    None => {
+                        self.store_remove(key);
+                        self.expirations.remove(key);
+                    }
+                }
+                output
+            }
+            KVOp::Validate(expected) => {
+                let current = self.version(key);
+                if current != expected {
+                    return KVOpResult::Error(KVOpError::VersionMismatch {
+                        current,
+                    });
+                }
+                None
+            }
+            KVOp::Scan { .. } => unreachable!("scans are not single-key ops"),
+        };
+        KVOpResult::Value(result)
+    }
+
+    // Returns the value of `key`, if it exists and hasn't expired.
+    fn get(&self, key: &Key) -> Option<Value> {
+        if self.expired(key) {
+            None
+        } else {
+            self.store.get(key)
+        }
+    }
+
+    // Returns the expiration time of the value of `key`, if it hasn't expired.
+    fn current_expiration(&self, key: &Key) -> Option<u64> {
+        if self.expired(key) {
+            None
+        } else {
+            self.expirations.get(key).cloned()
+        }
+    }
+
+    fn put(&mut self, key: &Key, value: Value, expiration: Option<u64>) {
+        self.store_insert(key, value);
+        match expiration {
+            Some(expiration) => {
+                self.expirations.insert(key.clone(), expiration)
+            }
+            None => self.expirations.remove(key),
+        };
+    }
+
+    // Inserts in the `Store`, updating the memory used.
+    fn store_insert(&mut self, key: &Key, value: Value) {
+        let memory = key.len() + value.len();
+        let previous = self.key_memory.insert(key.clone(), memory);
+        self.memory = self.memory + memory - previous.unwrap_or(0);
+        self.store.insert(key.clone(), value);
+    }
+
+    // Removes from the `Store`, updating the memory used.
+    fn store_remove(&mut self, key: &Key) {
+        if let Some(memory) = self.key_memory.remove(key) {
+            self.memory -= memory;
+        }
+        if let Some(access) = self.last_access.remove(key) {
+            self.lru.remove(&access);
+        }
+        self.store.remove(key);
+    }
+
+    // Marks `key` as the most recently used key (if it has a value and there's
+    // a capacity).
+    fn touch(&mut self, key: &Key) {
+        if self.capacity.is_none() || !self.key_memory.contains_key(key) {
+            return;
+        }
+        self.access_count += 1;
+        let access = self.access_count;
+        if let Some(previous) = self.last_access.insert(key.clone(), access) {
+            self.lru.remove(&previous);
+        }
+        self.lru.insert(access, key.clone());
+    }
+
+    // Evicts the least recently used keys while above capacity.
+    fn evict(&mut self) {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        while self.memory > capacity {
+            let key = match self.lru.iter().next() {
+                Some((_, key)) => key.clone(),
+                None => break,
+            };
+            self.store_remove(&key);
+            self.expirations.remove(&key);
+            self.versions.remove(&key);
+            self.persist_meta(&key);
+            self.evictions += 1;
+        }
+    }
+
+    // Persists the metadata of `key` in the `Store`, if it's persistent, so
+    // that it's restored along with its value after a restart (see
+    // `KVStore::set_store`). Keys with a value always have their metadata
+    // persisted, so that their memory is accounted for when restored.
+    fn persist_meta(&mut self, key: &Key) {
+        if !self.store.persistent() {
+            return;
+        }
+        let meta = KeyMeta {
+            version: self.versions.get(key).cloned(),
+            expiration: self.expirations.get(key).cloned(),
+            applied: self.applied.get(key).cloned().unwrap_or_default(),
+            executed: self.executed.get(key).cloned().unwrap_or_default(),
+        };
+        let empty = meta.version.is_none()
+            && meta.expiration.is_none()
+            && meta.applied.is_empty()
+            && meta.executed.is_empty();
+        if empty && !self.key_memory.contains_key(key) {
+            self.store.remove_meta(key);
+        } else {
+            let meta = bincode::serialize(&meta)
+                .expect("key metadata should be serialized");
+            self.store.insert_meta(key.clone(), Value::from(meta));
+        }
+    }
+
+    fn expired(&self, key: &Key) -> bool {
+        self.expirations
+            .get(key)
+            .map(|expiration| *expiration <= self.time)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let z = Value::from("z");
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+
+        // get key_a    -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+        // get key_b    -> none
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+
+        // put key_a x -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Put(x.clone())), None);
+        // get key_a    -> some(x)
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+
+        // put key_b y -> none
+        assert_eq!(store.test_execute(&key_b, KVOp::Put(y.clone())), None);
+        // get key_b    -> some(y)
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), Some(y.clone()));
+
+        // put key_a z -> some(x)
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::Put(z.clone())),
+            None,
+            /*
+            the following is correct if Put returns the previous value
+            Some(x.clone())
+             */
+        );
+        // get key_a    -> some(z)
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(z.clone()));
+        // get key_b    -> some(y)
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), Some(y.clone()));
+
+        // delete key_a -> some(z)
+        assert_eq!(store.test_execute(&key_a, KVOp::Delete), Some(z.clone()));
+        // get key_a    -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+        // get key_b    -> some(y)
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), Some(y.clone()));
+
+        // delete key_b -> some(y)
+        assert_eq!(store.test_execute(&key_b, KVOp::Delete), Some(y.clone()));
+        // get key_b    -> none
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+        // get key_a    -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+
+        // put key_a x -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Put(x.clone())), None);
+        // get key_a    -> some(x)
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        // get key_b    -> none
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+
+        // delete key_a -> some(x)
+        assert_eq!(store.test_execute(&key_a, KVOp::Delete), Some(x.clone()));
+        // get key_a    -> none
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+    }
+
+    #[test]
+    fn conditional_ops_flow() {
+        // key and values
+        let key = String::from("A");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let z = Value::from("z");
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        let mut execute =
+            |op: KVOp| store.do_execute(&key, vec![op], None).pop();
+
+        // cas key x -> y fails, since key has no value
+        let op = KVOp::Cas {
+            expected: x.clone(),
+            new: y.clone(),
+        };
+        let error = KVOpError::CasMismatch { current: None };
+        assert_eq!(execute(op), Some(KVOpResult::Error(error)));
+        assert_eq!(execute(KVOp::Get), Some(KVOpResult::Value(None)));
+
+        // put if absent key x succeeds
+        let op = KVOp::PutIfAbsent(x.clone());
+        assert_eq!(execute(op), Some(KVOpResult::Value(None)));
+        assert_eq!(execute(KVOp::Get), Some(KVOpResult::from(Some(x.clone()))));
+
+        // put if absent key z fails, since key has value x
+        let op = KVOp::PutIfAbsent(z.clone());
+        let error = KVOpError::KeyExists { current: x.clone() };
+        assert_eq!(execute(op), Some(KVOpResult::Error(error)));
+        assert_eq!(execute(KVOp::Get), Some(KVOpResult::from(Some(x.clone()))));
+
+        // cas key x -> y succeeds
+        let op = KVOp::Cas {
+            expected: x.clone(),
+            new: y.clone(),
+        };
+        assert_eq!(execute(op), Some(KVOpResult::from(Some(x.clone()))));
+        assert_eq!(execute(KVOp::Get), Some(KVOpResult::from(Some(y.clone()))));
+
+        // cas key x -> z fails, since key has value y
+        let op = KVOp::Cas {
+            expected: x.clone(),
+            new: z.clone(),
+        };
+        let result = execute(op).unwrap();
+        assert!(result.is_error());
+        assert_eq!(result.value(), None);
+        assert_eq!(
+            result.error(),
+            Some(&KVOpError::CasMismatch {
+                current: Some(y.clone())
+            })
+        );
+        assert_eq!(execute(KVOp::Get), Some(KVOpResult::from(Some(y))));
+
+        // only gets and scans are read-only
+        assert!(KVOp::Get.is_read_only());
+        assert!(!KVOp::PutIfAbsent(z.clone()).is_read_only());
+        assert!(!KVOp::Cas {
+            expected: x,
+            new: z
+        }
+        .is_read_only());
+    }
+
+    #[test]
+    fn atomic_flow() {
+        let key = String::from("A");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let rifl = Rifl::new(1, 1);
+
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        let ops = vec![
+            KVOp::Put(x.clone()),
+            KVOp::PutIfAbsent(y.clone()),
+            KVOp::Append(y),
+        ];
+
+        // the put-if-absent fails after the put, and thus no op is executed
+        let failure = Some((1, KVOpError::KeyExists { current: x.clone() }));
+        assert_eq!(store.check(&key, &ops, rifl), failure);
+        assert_eq!(store.execute(&key, ops, rifl), aborted(3, failure));
+        assert_eq!(store.test_execute(&key, KVOp::Get), None);
+        assert_eq!(store.version(&key), None);
+
+        // ops that succeed are all executed
+        let ops = vec![
+            KVOp::PutIfAbsent(x.clone()),
+            KVOp::Cas {
+                expected: x.clone(),
+                new: counter_to_value(1),
+            },
+            KVOp::Add(1),
+        ];
+        assert_eq!(store.check(&key, &ops, Rifl::new(1, 2)), None);
+        let results = store.execute(&key, ops, Rifl::new(1, 2));
+        assert!(results.iter().all(|result| !result.is_error()));
+        assert_eq!(
+            store.test_execute(&key, KVOp::Get),
+            Some(counter_to_value(2))
+        );
+    }
+
+    #[test]
+    fn rmw_ops_flow() {
+        // keys and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+
+        // add 5 key_a -> some(5)
+        let five = counter_to_value(5);
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::Add(5)),
+            Some(five.clone())
+        );
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(five));
+        // add -7 key_a -> some(-2)
+        let result = store.test_execute(&key_a, KVOp::Add(-7));
+        assert_eq!(result.as_ref().and_then(value_to_counter), Some(-2));
+
+        // adding to values that are not counters fails, keeping the value
+        assert_eq!(store.test_execute(&key_b, KVOp::Put(x.clone())), None);
+        assert_eq!(value_to_counter(&x), None);
+        let result = store.do_execute(&key_b, vec![KVOp::Add(1)], None).pop();
+        let error = KVOpError::TypeMismatch { current: x.clone() };
+        assert_eq!(result, Some(KVOpResult::Error(error)));
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), Some(x.clone()));
+
+        // delete key_b, then append x and y -> none
+        assert_eq!(store.test_execute(&key_b, KVOp::Delete), Some(x.clone()));
+        assert_eq!(store.test_execute(&key_b, KVOp::Append(x.clone())), None);
+        assert_eq!(store.test_execute(&key_b, KVOp::Append(y)), None);
+        // get key_b    -> some(xy)
+        assert_eq!(
+            store.test_execute(&key_b, KVOp::Get),
+            Some(Value::from("xy"))
+        );
+    }
+
+    #[test]
+    fn scan_flow() {
+        // keys and values
+        let keys = ["A", "B", "C", "D"];
+        let x = Value::from("x");
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        for key in keys.iter() {
+            store.test_execute(&key.to_string(), KVOp::Put(x.clone()));
+        }
+
+        let mut scan = |start: &str, end: &str, limit: usize| {
+            let op = KVOp::Scan {
+                end: end.to_string(),
+                limit,
+            };
+            let mut results =
+                store.do_execute(&start.to_string(), vec![op], None);
+            let result = results.pop().unwrap();
+            result
+                .entries()
+                .expect("scans should return entries")
+                .iter()
+                .map(|(key, value)| {
+                    assert_eq!(value, &x);
+                    key.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the start key is included, but the end key is not
+        assert_eq!(scan("B", "D", 10), vec!["B", "C"]);
+        // keys not in the store can be used as bounds
+        assert_eq!(scan("0", "BB", 10), vec!["A", "B"]);
+        assert_eq!(scan("A", "Z", 10), vec!["A", "B", "C", "D"]);
+        // at most `limit` entries are returned
+        assert_eq!(scan("A", "Z", 3), vec!["A", "B", "C"]);
+        // empty and decreasing ranges return no entries
+        assert!(scan("B", "B", 10).is_empty());
+        assert!(scan("D", "A", 10).is_empty());
+
+        // once disabled, scans fail instead of returning partial results
+        store.set_scans(false);
+        let op = KVOp::Scan {
+            end: String::from("Z"),
+            limit: 10,
+        };
+        let results = store.do_execute(&String::from("A"), vec![op], None);
+        assert_eq!(
+            results,
+            vec![KVOpResult::Error(KVOpError::ScanUnsupported)]
+        );
+    }
+
+    #[test]
+    fn table_flow() {
+        let x = Value::from("x");
+
+        // keys are namespaced by their table
+        let key = table_key("data", "1");
+        assert_eq!(key, "data\u{0}1");
+        assert_eq!(split_table_key(&key), Some(("data", "1")));
+        assert_eq!(split_table_key("1"), None);
+        // plain keys can contain any other character
+        assert_eq!(split_table_key("data/1"), None);
+        let key = table_key("data", "a/b");
+        assert_eq!(split_table_key(&key), Some(("data", "a/b")));
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        for (table, key) in
+            [("data", "1"), ("data", "2"), ("meta", "1"), ("dat", "1")]
+        {
+            store.test_execute(&table_key(table, key), KVOp::Put(x.clone()));
+        }
+
+        // scanning a table only returns its keys
+        let (start, end) = table_range("data");
+        let op = KVOp::Scan { end, limit: 10 };
+        let result = store.do_execute(&start, vec![op], None).pop().unwrap();
+        let keys: Vec<_> = result
+            .entries()
+            .expect("scans should return entries")
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect();
+        assert_eq!(keys, vec![table_key("data", "1"), table_key("data", "2")]);
+    }
+
+    #[test]
+    fn ttl_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let put_with_ttl = |value: &Value, ttl| KVOp::PutWithTtl {
+            value: value.clone(),
+            ttl,
+        };
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        store.set_time(10);
+
+        // put key_a x with ttl 5 -> none (expires at 15)
+        assert_eq!(store.test_execute(&key_a, put_with_ttl(&x, 5)), None);
+        // put key_b y with ttl 10 -> none (expires at 20)
+        assert_eq!(store.test_execute(&key_b, put_with_ttl(&y, 10)), None);
+
+        // get key_a at 14 -> some(x)
+        store.set_time(14);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        // append to key_b at 14 -> keeps the expiration
+        assert_eq!(store.test_execute(&key_b, KVOp::Append(y.clone())), None);
+
+        // get key_a at 15 -> none
+        store.set_time(15);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+        // expired values are not scanned
+        let scan = KVOp::Scan {
+            end: String::from("C"),
+            limit: 10,
+        };
+        let expected = vec![(key_b.clone(), Value::from("yy"))];
+        assert_eq!(
+            store.do_execute(&key_a, vec![scan.clone()], None),
+            vec![KVOpResult::Entries(expected)]
+        );
+        // put-if-absent on key_a succeeds, since its value expired
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::PutIfAbsent(y.clone())),
+            None
+        );
+
+        // at 20, key_b expires, but key_a (put without ttl) doesn't
+        store.set_time(20);
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(y.clone()));
+        let expected = vec![(key_a.clone(), y.clone())];
+        assert_eq!(
+            store.do_execute(&key_a, vec![scan], None),
+            vec![KVOpResult::Entries(expected)]
+        );
+
+        // a put removes the expiration of a key
+        assert_eq!(store.test_execute(&key_b, put_with_ttl(&x, 1)), None);
+        assert_eq!(store.test_execute(&key_b, KVOp::Put(x.clone())), None);
+        store.set_time(100);
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), Some(x));
+    }
+
+    #[test]
+    fn ttl_time_on_flow() {
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let put_with_ttl = KVOp::PutWithTtl {
+            value: x.clone(),
+            ttl: 5,
+        };
+
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+
+        // put key_a x with ttl 5 at 10 (expires at 15)
+        store.set_time_on(10, vec![&key_a]);
+        store.execute(&key_a, vec![put_with_ttl], Rifl::new(1, 1));
+
+        // commands on key_a never execute before its last write
+        store.set_time_on(8, vec![&key_a]);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        store.set_time_on(8, vec![&key_a, &key_b]);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        store.set_time_on(15, vec![&key_a]);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+
+        // expired values are only purged up to the horizon
+        assert_eq!(store.purge_expired(|_| 14), 0);
+        assert!(store.key_memory(&key_a).is_some());
+        assert_eq!(store.purge_expired(|_| 15), 1);
+        assert_eq!(store.key_memory(&key_a), None);
+        assert_eq!(store.memory(), 0);
+    }
+
+    #[test]
+    fn memory_flow() {
+        // keys and values (each key and value uses 2 bytes)
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let key_c = String::from("C");
+        let x = Value::from("x");
+        let xx = Value::from("xx");
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+
+        // memory is updated by puts and deletes
+        store.test_execute(&key_a, KVOp::Put(x.clone()));
+        store.test_execute(&key_b, KVOp::Put(xx.clone()));
+        assert_eq!(store.key_memory(&key_a), Some(2));
+        assert_eq!(store.key_memory(&key_b), Some(3));
+        assert_eq!(store.memory(), 5);
+        store.test_execute(&key_b, KVOp::Put(x.clone()));
+        assert_eq!(store.memory(), 4);
+        store.test_execute(&key_b, KVOp::Delete);
+        assert_eq!(store.key_memory(&key_b), None);
+        assert_eq!(store.memory(), 2);
+
+        // with a capacity of 4 bytes, at most 2 keys fit
+        store.set_capacity(4);
+        store.test_execute(&key_b, KVOp::Put(x.clone()));
+        assert_eq!(store.memory(), 4);
+        assert_eq!(store.evictions(), 0);
+
+        // key_a is read, and thus key_b is the least recently used
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        store.test_execute(&key_c, KVOp::Put(x.clone()));
+        assert_eq!(store.evictions(), 1);
+        assert_eq!(store.memory(), 4);
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+        assert_eq!(store.test_execute(&key_c, KVOp::Get), Some(x.clone()));
+
+        // growing key_c evicts key_a
+        store.test_execute(&key_c, KVOp::Append(x.clone()));
+        assert_eq!(store.evictions(), 2);
+        assert_eq!(store.memory(), 3);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+
+        // metrics report memory and evictions
+        let mut metrics = ExecutorMetrics::new();
+        store.update_metrics(&mut metrics);
+        assert_eq!(
+            metrics.get_aggregated(ExecutorMetricsKind::StoreMemory),
+            Some(&3)
+        );
+        assert_eq!(
+            metrics.get_aggregated(ExecutorMetricsKind::StoreEvictions),
+            Some(&2)
+        );
+    }
+
+    #[test]
+    fn speculation_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let z = Value::from("z");
+
+        // rifls
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let rifl_3 = Rifl::new(3, 1);
+        let rifl_4 = Rifl::new(4, 1);
+
+        // store
+        let monitor = true;
+        let mut store = KVStore::new(monitor);
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_1);
+
+        // speculate put key_a y and get key_a -> some(y)
+        assert_eq!(
+            store.speculate(&key_a, vec![KVOp::Put(y.clone())], rifl_2),
+            vec![KVOpResult::Value(None)]
+        );
+        assert_eq!(
+            store.speculate(&key_a, vec![KVOp::Get], rifl_3),
+            vec![KVOpResult::Value(Some(y.clone()))]
+        );
+
+        // only the oldest speculative execution can be confirmed
+        assert!(!store.confirm(&key_a, rifl_3));
+        assert!(store.confirm(&key_a, rifl_2));
+        assert_eq!(
+            store.monitor().unwrap().get_order(&key_a),
+            Some(&vec![rifl_1, rifl_2])
+        );
+
+        // speculate put key_a z, and then rollback both pending executions
+        store.speculate(&key_a, vec![KVOp::Put(z.clone())], rifl_4);
+        assert_eq!(store.rollback(&key_a), vec![rifl_3, rifl_4]);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(y.clone()));
+        assert!(!store.confirm(&key_a, rifl_3));
+
+        // rolling back a speculative put on a new key removes it
+        let key_b = String::from("B");
+        store.speculate(&key_b, vec![KVOp::Put(z.clone())], rifl_4);
+        assert_eq!(store.rollback(&key_b), vec![rifl_4]);
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+        assert!(store.rollback(&key_b).is_empty());
+    }
+
+    #[test]
+    fn versioned_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let z = Value::from("z");
+
+        // rifls
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let rifl_3 = Rifl::new(3, 1);
+        let rifl_4 = Rifl::new(4, 1);
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        let get_versioned = |store: &mut KVStore, rifl| {
+            let mut results =
+                store.execute(&key_a, vec![KVOp::GetVersioned], rifl);
+            assert_eq!(results.len(), 1);
+            results.pop().unwrap()
+        };
+
+        // keys never written have no version
+        assert_eq!(
+            get_versioned(&mut store, rifl_1),
+            KVOpResult::Versioned {
+                value: None,
+                version: None,
+            }
+        );
+
+        // put key_a x at time 10
+        store.set_time(10);
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_2);
+        let result = get_versioned(&mut store, rifl_1);
+        assert_eq!(result.value(), Some(&x));
+        assert_eq!(result.version(), Some(&Version::new(rifl_2, 10)));
+
+        // reads and failed writes don't change the version
+        store.set_time(20);
+        let cas = KVOp::Cas {
+            expected: y.clone(),
+            new: z.clone(),
+        };
+        assert!(store.execute(&key_a, vec![cas], rifl_3)[0].is_error());
+        assert_eq!(
+            get_versioned(&mut store, rifl_1).version(),
+            Some(&Version::new(rifl_2, 10))
+        );
+
+        // speculative writes change the version, until they're rolled back
+        store.speculate(&key_a, vec![KVOp::Put(y.clone())], rifl_4);
+        assert_eq!(
+            get_versioned(&mut store, rifl_1).version(),
+            Some(&Version::new(rifl_4, 20))
+        );
+        store.rollback(&key_a);
+        assert_eq!(
+            get_versioned(&mut store, rifl_1).version(),
+            Some(&Version::new(rifl_2, 10))
+        );
+    }
+
+    #[test]
+    fn watch_flow() {
+        // keys and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let z = Value::from("z");
+
+        // rifls
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let rifl_3 = Rifl::new(3, 1);
+        let rifl_4 = Rifl::new(4, 1);
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        store.watch(key_a.clone());
+        store.set_time(10);
+
+        // writes on watched keys are notified once per command, with the
+        // final value of the key
+        let ops = vec![KVOp::Put(x.clone()), KVOp::Append(y.clone())];
+        store.execute(&key_a, ops, rifl_1);
+        store.execute(&key_b, vec![KVOp::Put(x.clone())], rifl_1);
+        assert_eq!(
+            store.take_notifications(),
+            vec![WatchNotification::new(
+                key_a.clone(),
+                Some(Value::from("xy")),
+                Version::new(rifl_1, 10)
+            )]
+        );
+        assert!(store.take_notifications().is_empty());
+
+        // reads and failed writes are not notified, but deletes are
+        store.execute(&key_a, vec![KVOp::Get], rifl_2);
+        store.execute(&key_a, vec![KVOp::PutIfAbsent(z.clone())], rifl_2);
+        assert!(store.take_notifications().is_empty());
+        store.execute(&key_a, vec![KVOp::Delete], rifl_2);
+        assert_eq!(
+            store.take_notifications(),
+            vec![WatchNotification::new(
+                key_a.clone(),
+                None,
+                Version::new(rifl_2, 10)
+            )]
+        );
+
+        // speculative writes are only notified once confirmed
+        store.speculate(&key_a, vec![KVOp::Put(y.clone())], rifl_3);
+        assert!(store.take_notifications().is_empty());
+        assert!(store.confirm(&key_a, rifl_3));
+        assert_eq!(
+            store.take_notifications(),
+            vec![WatchNotification::new(
+                key_a.clone(),
+                Some(y),
+                Version::new(rifl_3, 10)
+            )]
+        );
+
+        // and never if rolled back
+        store.speculate(&key_a, vec![KVOp::Put(z.clone())], rifl_4);
+        store.rollback(&key_a);
+        assert!(store.take_notifications().is_empty());
+
+        // writes on unwatched keys are not notified
+        store.unwatch(&key_a);
+        store.execute(&key_a, vec![KVOp::Put(z)], rifl_4);
+        assert!(store.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn applied_flow() {
+        use crate::executor::{DelayedReads, LocalRead};
+
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+        let y = Value::from("y");
+
+        // rifls of client 1, and of a read by client 2
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(1, 2);
+        let rifl_3 = Rifl::new(1, 3);
+        let read_rifl = Rifl::new(2, 1);
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+
+        // only writes are applied
+        store.execute(&key_a, vec![KVOp::Get], rifl_1);
+        assert!(!store.applied(&key_a, rifl_1));
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_2);
+        assert!(!store.applied(&key_a, rifl_1));
+        assert!(store.applied(&key_a, rifl_2));
+        assert!(!store.applied(&key_a, rifl_3));
+        assert!(!store.applied(&String::from("B"), rifl_1));
+
+        // a read that should observe `rifl_3` is delayed until it's applied
+        let mut delayed = DelayedReads::new();
+        let read = LocalRead::new(
+            read_rifl,
+            key_a.clone(),
+            Arc::new(vec![KVOp::Get]),
+            Some(rifl_3),
+        );
+        assert!(delayed.delay(read, &store).is_none());
+        assert_eq!(delayed.len(), 1);
+        assert!(delayed.next_ready(&store).is_none());
+
+        // speculative writes are only applied once confirmed
+        store.speculate(&key_a, vec![KVOp::Put(y.clone())], rifl_3);
+        assert!(!store.applied(&key_a, rifl_3));
+        assert!(delayed.next_ready(&store).is_none());
+        assert!(store.confirm(&key_a, rifl_3));
+        assert!(store.applied(&key_a, rifl_3));
+
+        // the delayed read now observes the write
+        let read = delayed.next_ready(&store).expect("read should be ready");
+        assert!(delayed.is_empty());
+        let result = read.execute(&mut store);
+        assert_eq!(result.partial_results, vec![KVOpResult::Value(Some(y))]);
+
+        // reads without a session token are never delayed
+        let read =
+            LocalRead::new(read_rifl, key_a, Arc::new(vec![KVOp::Get]), None);
+        assert!(delayed.delay(read, &store).is_some());
+    }
+
+    #[test]
+    fn applied_out_of_order() {
+        let key = String::from("A");
+        let x = Value::from("x");
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        let put = |store: &mut KVStore, sequence| {
+            let rifl = Rifl::new(1, sequence);
+            store.execute(&key, vec![KVOp::Put(x.clone())], rifl);
+        };
+        let applied = |store: &KVStore, sequence| {
+            store.applied(&key, Rifl::new(1, sequence))
+        };
+
+        // the writes of an open-loop client are applied out of order: a
+        // higher sequence doesn't imply that the lower ones were applied
+        put(&mut store, 3);
+        put(&mut store, 1);
+        assert!(applied(&store, 1));
+        assert!(!applied(&store, 2));
+        assert!(applied(&store, 3));
+        put(&mut store, 2);
+        assert!((1..=3).all(|sequence| applied(&store, sequence)));
+        assert!(!applied(&store, 4));
+
+        // gaps that are never filled (e.g. writes on other keys) are only
+        // kept for the last `APPLIED_ABOVE_WATERMARK` writes
+        let sequences = (0..=APPLIED_ABOVE_WATERMARK as u64).map(|i| 5 + 2 * i);
+        for sequence in sequences.clone() {
+            put(&mut store, sequence);
+        }
+        assert!(!applied(&store, 4 + 2 * APPLIED_ABOVE_WATERMARK as u64));
+        assert!(sequences.clone().all(|sequence| applied(&store, sequence)));
+        // the lowest gap was assumed to be applied
+        assert!(applied(&store, 4));
+    }
+
+    #[test]
+    fn dedup_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+
+        // rifls
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let rifl_3 = Rifl::new(3, 1);
+        let rifl_4 = Rifl::new(4, 1);
+
+        // store
+        let monitor = true;
+        let mut store = KVStore::new(monitor);
+        store.set_dedup_window(2);
+        let append = || vec![KVOp::Append(x.clone()), KVOp::Get];
+        let xs = |count: usize| {
+            vec![
+                KVOpResult::Value(None),
+                KVOpResult::Value(Some(Value::from("x".repeat(count)))),
+            ]
+        };
+
+        // duplicates are not executed, getting the cached results instead
+        assert_eq!(store.execute(&key_a, append(), rifl_1), xs(1));
+        assert_eq!(store.execute(&key_a, append(), rifl_1), xs(1));
+        assert_eq!(store.execute(&key_a, append(), rifl_2), xs(2));
+        assert_eq!(
+            store.monitor().unwrap().get_order(&key_a),
+            Some(&vec![rifl_1, rifl_2])
+        );
+
+        // speculative duplicates are also detected, both of speculative and
+        // of confirmed executions
+        assert_eq!(store.speculate(&key_a, append(), rifl_3), xs(3));
+        assert_eq!(store.speculate(&key_a, append(), rifl_3), xs(3));
+        assert_eq!(store.speculate(&key_a, append(), rifl_2), xs(2));
+        assert!(store.confirm(&key_a, rifl_3));
+        assert!(store.confirm(&key_a, rifl_3));
+        assert!(store.confirm(&key_a, rifl_2));
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::Get),
+            Some(Value::from("xxx"))
+        );
+
+        // rolled back executions are not cached
+        store.speculate(&key_a, append(), rifl_4);
+        store.rollback(&key_a);
+        assert_eq!(store.execute(&key_a, append(), rifl_4), xs(4));
+
+        // only the last 2 commands are cached: rifl_2 is executed again
+        assert_eq!(store.execute(&key_a, append(), rifl_2), xs(5));
+        assert_eq!(
+            store.monitor().unwrap().get_order(&key_a),
+            Some(&vec![rifl_1, rifl_2, rifl_3, rifl_4, rifl_2])
+        );
+
+        // reads are never cached
+        let get = vec![KVOp::Get];
+        store.execute(&key_a, get.clone(), rifl_1);
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_3);
+        assert_eq!(
+            store.execute(&key_a, get, rifl_1),
+            vec![KVOpResult::Value(Some(x))]
+        );
+    }
+
+    #[test]
+    fn dedup_out_of_order() {
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+
+        // store
+        let monitor = true;
+        let mut store = KVStore::new(monitor);
+        store.set_dedup_window(4);
+        let append = || vec![KVOp::Append(x.clone()), KVOp::Get];
+        let xs = |count: usize| {
+            vec![
+                KVOpResult::Value(None),
+                KVOpResult::Value(Some(Value::from("x".repeat(count)))),
+            ]
+        };
+
+        // the commands of an open-loop client are executed out of order:
+        // commands with a lower sequence than one already executed are not
+        // duplicates, and thus they're executed
+        let rifls = [3, 1, 4, 2].map(|sequence| Rifl::new(1, sequence));
+        for (count, rifl) in rifls.iter().enumerate() {
+            assert_eq!(store.execute(&key_a, append(), *rifl), xs(count + 1));
+        }
+        assert_eq!(
+            store.monitor().unwrap().get_order(&key_a),
+            Some(&rifls.to_vec())
+        );
+
+        // while retries of any of them are
+        for (count, rifl) in rifls.iter().enumerate() {
+            assert_eq!(store.execute(&key_a, append(), *rifl), xs(count + 1));
+        }
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::Get),
+            Some(Value::from("xxxx"))
+        );
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_store_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("fantoch_sled_store_restart_{}", std::process::id()));
+        let open = || {
+            let monitor = false;
+            let mut store = KVStore::new(monitor);
+            store.set_dedup_window(2);
+            let sled = SledStore::open(&path).expect("sled store should open");
+            store.set_store(Box::new(sled));
+            store
+        };
+
+        // key and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+        let y = Value::from("y");
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(1, 2);
+        let rifl_3 = Rifl::new(1, 3);
+
+        // put x on key A, and x with a TTL on key B
+        let mut store = open();
+        store.set_time(10);
+        let put_ttl = KVOp::PutWithTtl {
+            value: x.clone(),
+            ttl: 5,
+        };
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_1);
+        store.execute(&key_b, vec![put_ttl], rifl_2);
+        let version = store.version(&key_a);
+        let memory = store.memory();
+        drop(store);
+
+        // after a restart, the state of each key is restored
+        let mut store = open();
+        assert_eq!(store.version(&key_a), version);
+        assert_eq!(store.memory(), memory);
+        assert!(store.applied(&key_a, rifl_1));
+        assert!(store.applied(&key_b, rifl_2));
+        assert!(!store.applied(&key_a, rifl_3));
+
+        // duplicates are detected
+        let cas = KVOp::Cas {
+            expected: x.clone(),
+            new: y.clone(),
+        };
+        let results = vec![KVOpResult::Value(None)];
+        assert_eq!(store.execute(&key_a, vec![KVOp::Put(y)], rifl_1), results);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), Some(x.clone()));
+
+        // and so are version mismatches and expired values
+        let validate = KVOp::Validate(version);
+        assert!(store.check(&key_a, &[validate], rifl_3).is_none());
+        let validate = KVOp::Validate(None);
+        assert!(store.check(&key_a, &[validate], rifl_3).is_some());
+        assert!(store.check(&key_a, &[cas], rifl_3).is_none());
+        store.set_time(15);
+        assert_eq!(store.test_execute(&key_b, KVOp::Get), None);
+        drop(store);
+        std::fs::remove_dir_all(path).expect("sled store should be removed");
+    }
+
+    #[test]
+    fn hot_keys_flow() {
+        // keys and values
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let x = Value::from("x");
+
+        // rifls
+        let rifl_1 = Rifl::new(1, 1);
+        let rifl_2 = Rifl::new(2, 1);
+        let rifl_3 = Rifl::new(3, 1);
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        assert!(store.hot_keys().is_none());
+        store.set_hot_keys(1);
+
+        // executions, local reads and confirmed speculative executions are
+        // accesses, but rolled back ones are not
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_1);
+        store.read(&key_a, vec![KVOp::Get]);
+        store.speculate(&key_b, vec![KVOp::Get], rifl_2);
+        assert!(store.confirm(&key_b, rifl_2));
+        store.speculate(&key_b, vec![KVOp::Put(x)], rifl_3);
+        store.rollback(&key_b);
+        let hot_keys = store.hot_keys().unwrap();
+        assert_eq!(hot_keys.accesses(&key_a), 2);
+        assert_eq!(hot_keys.accesses(&key_b), 1);
+        assert_eq!(hot_keys.conflicts(&key_a), 1);
+        assert_eq!(hot_keys.conflicts(&key_b), 0);
+
+        // metrics report the hottest key
+        let mut metrics = ExecutorMetrics::new();
+        store.update_metrics(&mut metrics);
+        assert_eq!(
+            metrics.get_ranked(ExecutorMetricsKind::HotKeyAccesses),
+            Some(&[(key_a.clone(), 2)][..])
+        );
+        assert_eq!(
+            metrics.get_ranked(ExecutorMetricsKind::HotKeyConflicts),
+            Some(&[(key_a, 1)][..])
+        );
+    }
+
+    // State machine that keeps a log of payloads per key, and outputs the
+    // length of the log; empty payloads truncate the log.
+    struct LogStateMachine;
+
+    impl StateMachine for LogStateMachine {
+        fn apply(
+            &self,
+            _key: &Key,
+            state: &mut Option<Value>,
+            payload: &Value,
+        ) -> Option<Value> {
+            if payload.is_empty() {
+                *state = None;
+                return None;
+            }
+            let log = match state.take() {
+                Some(log) => Value::from([log.as_ref(), payload].concat()),
+                None => payload.clone(),
+            };
+            let output = counter_to_value(log.len() as i64);
+            *state = Some(log);
+            Some(output)
+        }
+    }
+
+    #[test]
+    fn opaque_flow() {
+        // key and payloads
+        let key_a = String::from("A");
+        let xy = Value::from("xy");
+        let z = Value::from("z");
+        let opaque = |payload: &Value| KVOp::Opaque(payload.clone());
+        assert!(!opaque(&z).is_read_only());
+
+        // store
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        store.set_state_machine(Arc::new(LogStateMachine));
+
+        // the state machine output is returned, and its state is the value of
+        // the key
+        assert_eq!(
+            store.test_execute(&key_a, opaque(&xy)),
+            Some(counter_to_value(2))
+        );
+        assert_eq!(
+            store.test_execute(&key_a, opaque(&z)),
+            Some(counter_to_value(3))
+        );
+        assert_eq!(
+            store.test_execute(&key_a, KVOp::Get),
+            Some(Value::from("xyz"))
+        );
+        assert_eq!(store.memory(), 4);
+
+        // the state machine can remove the key
+        assert_eq!(store.test_execute(&key_a, opaque(&Value::new())), None);
+        assert_eq!(store.test_execute(&key_a, KVOp::Get), None);
+        assert_eq!(store.memory(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn opaque_without_state_machine() {
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        store.test_execute(&String::from("A"), KVOp::Opaque(Value::from("x")));
+    }
+
+    #[test]
+    fn read_flow() {
+        // key and values
+        let key_a = String::from("A");
+        let x = Value::from("x");
+        let rifl_1 = Rifl::new(1, 1);
+
+        // store
+        let monitor = true;
+        let mut store = KVStore::new(monitor);
+        store.execute(&key_a, vec![KVOp::Put(x.clone())], rifl_1);
+
+        // reads observe the latest value, but are not part of the execution
+        // order
+        assert_eq!(
+            store.read(&key_a, vec![KVOp::Get]),
+            vec![KVOpResult::Value(Some(x))]
+        );
+        assert_eq!(
+            store.monitor().unwrap().get_order(&key_a),
+            Some(&vec![rifl_1])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_with_writes() {
+        let monitor = false;
+        let mut store = KVStore::new(monitor);
+        store.read(&String::from("A"), vec![KVOp::Put(Value::from("x"))]);
+    }
+}
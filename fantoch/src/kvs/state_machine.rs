@@ -0,0 +1,21 @@
+use super::{Key, Value};
+
+/// User-provided state machine that interprets the opaque payloads of
+/// `KVOp::Opaque`. This allows commands to be replicated and ordered by
+/// fantoch, while their semantics are defined by the application.
+///
+/// The state of each key is kept in the `KVStore` (as the value of the key),
+/// and thus it's speculated, rolled back and evicted like any other value.
+/// Since all replicas apply the same payloads in the same order, `apply` must
+/// be deterministic.
+pub trait StateMachine: Send + Sync {
+    /// Applies `payload` to `state`, the current state of `key` (`None` if
+    /// the key has no state), and returns the output of the operation. Setting
+    /// `state` to `None` removes the key.
+    fn apply(
+        &self,
+        key: &Key,
+        state: &mut Option<Value>,
+        payload: &Value,
+    ) -> Option<Value>;
+}
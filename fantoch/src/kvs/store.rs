@@ -0,0 +1,270 @@
+use super::{Key, Value};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Storage backend of a `KVStore`. Implementations must keep keys sorted, so
+/// that they can be scanned in order.
+pub trait Store: Send + Sync {
+    /// Returns the value of `key`, if any.
+    fn get(&self, key: &Key) -> Option<Value>;
+
+    /// Sets the value of `key`.
+    fn insert(&mut self, key: Key, value: Value);
+
+    /// Removes `key`, returning its value, if any.
+    fn remove(&mut self, key: &Key) -> Option<Value>;
+
+    /// Returns the entries whose keys are between `start` (inclusive) and
+    /// `end` (exclusive), sorted by key. `start` must not be greater than
+    /// `end`.
+    fn range<'a>(
+        &'a self,
+        start: &Key,
+        end: &Key,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + 'a>;
+
+    /// Returns a boxed clone of the store.
+    fn clone_box(&self) -> Box<dyn Store>;
+
+    /// Checks if the store survives restarts. If so, the `KVStore` also keeps
+    /// in it the metadata of each key (e.g. its version), so that it can be
+    /// restored along with the values (see `KVStore::set_store`).
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    /// Sets the (serialized) metadata of `key`. Metadata is kept apart from
+    /// values, and thus it's never returned by `Store::get` or `Store::range`.
+    fn insert_meta(&mut self, _key: Key, _meta: Value) {}
+
+    /// Removes the metadata of `key`, if any.
+    fn remove_meta(&mut self, _key: &Key) {}
+
+    /// Returns the metadata of all keys.
+    fn metas<'a>(&'a self) -> Box<dyn Iterator<Item = (Key, Value)> + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl Clone for Box<dyn Store> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// In-memory `Store`, used by default.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    entries: BTreeMap<Key, Value>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &Key) -> Option<Value> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: Key, value: Value) {
+        self.entries.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Value> {
+        self.entries.remove(key)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: &Key,
+        end: &Key,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + 'a> {
+        let range = self
+            .entries
+            .range::<Key, _>((Bound::Included(start), Bound::Excluded(end)))
+            .map(|(key, value)| (key.clone(), value.clone()));
+        Box::new(range)
+    }
+
+    fn clone_box(&self) -> Box<dyn Store> {
+        Box::new(self.clone())
+    }
+}
+
+/// `Store` persisted on disk with `sled`, which allows the store to be larger
+/// than memory and to survive restarts. Clones share the same underlying
+/// database. The metadata of each key (its version, expiration, the writes
+/// applied to it and the results cached for deduplication) is kept in a
+/// separate tree, and restored when the store is reopened.
+///
+/// What's not persisted is lost on a restart: the logical time (which
+/// executors set again before each command), the order in which keys were
+/// last used (and so which keys are evicted first, if there's a capacity), and
+/// the undo log of speculative executions. Thus, values speculatively written
+/// (see `KVStore::speculate`) and neither confirmed nor rolled back before a
+/// restart stay in the store.
+///
+/// Since executors can't recover from storage failures, all `sled` errors
+/// (other than when opening the database) are fatal.
+#[cfg(feature = "sled")]
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    db: sled::Db,
+    meta: sled::Tree,
+}
+
+// Name of the sled tree with the metadata of each key.
+#[cfg(feature = "sled")]
+const SLED_META_TREE: &str = "meta";
+
+#[cfg(feature = "sled")]
+impl SledStore {
+    /// Opens the database at `path`, creating it if it doesn't exist.
+    pub fn open<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Self, color_eyre::Report> {
+        let db = sled::open(path)?;
+        let meta = db.open_tree(SLED_META_TREE)?;
+        Ok(Self { db, meta })
+    }
+
+    fn to_entry(entry: sled::Result<(sled::IVec, sled::IVec)>) -> (Key, Value) {
+        let (key, value) = entry.expect("sled range should succeed");
+        let key = String::from_utf8(key.to_vec())
+            .expect("keys in the sled store should be valid UTF-8");
+        (key, Value::copy_from_slice(&value))
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Store for SledStore {
+    fn get(&self, key: &Key) -> Option<Value> {
+        self.db
+            .get(key.as_bytes())
+            .expect("sled get should succeed")
+            .map(|value| Value::copy_from_slice(&value))
+    }
+
+    fn insert(&mut self, key: Key, value: Value) {
+        self.db
+            .insert(key.as_bytes(), value.as_ref())
+            .expect("sled insert should succeed");
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Value> {
+        self.db
+            .remove(key.as_bytes())
+            .expect("sled remove should succeed")
+            .map(|value| Value::copy_from_slice(&value))
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: &Key,
+        end: &Key,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + 'a> {
+        let range = self
+            .db
+            .range(start.as_bytes()..end.as_bytes())
+            .map(Self::to_entry);
+        Box::new(range)
+    }
+
+    fn clone_box(&self) -> Box<dyn Store> {
+        Box::new(self.clone())
+    }
+
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    fn insert_meta(&mut self, key: Key, meta: Value) {
+        self.meta
+            .insert(key.as_bytes(), meta.as_ref())
+            .expect("sled insert should succeed");
+    }
+
+    fn remove_meta(&mut self, key: &Key) {
+        self.meta
+            .remove(key.as_bytes())
+            .expect("sled remove should succeed");
+    }
+
+    fn metas<'a>(&'a self) -> Box<dyn Iterator<Item = (Key, Value)> + 'a> {
+        Box::new(self.meta.iter().map(Self::to_entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_flow(mut store: Box<dyn Store>) {
+        let key = |key: &str| String::from(key);
+        let value = |value: &str| Value::from(value.to_string());
+
+        // get, insert and remove
+        assert_eq!(store.get(&key("B")), None);
+        store.insert(key("B"), value("x"));
+        assert_eq!(store.get(&key("B")), Some(value("x")));
+        store.insert(key("B"), value("y"));
+        assert_eq!(store.remove(&key("B")), Some(value("y")));
+        assert_eq!(store.remove(&key("B")), None);
+
+        // entries in a range are sorted by key
+        for k in ["D", "A", "C", "B"] {
+            store.insert(key(k), value(k));
+        }
+        let range: Vec<_> = store.range(&key("B"), &key("D")).collect();
+        assert_eq!(range, vec![(key("B"), value("B")), (key("C"), value("C"))]);
+        assert_eq!(store.range(&key("E"), &key("F")).count(), 0);
+    }
+
+    #[test]
+    fn memory_store_flow() {
+        store_flow(Box::new(MemoryStore::new()));
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_store_flow() {
+        let path = std::env::temp_dir()
+            .join(format!("fantoch_sled_store_flow_{}", std::process::id()));
+        let store = SledStore::open(&path).expect("sled store should open");
+        store_flow(Box::new(store));
+        std::fs::remove_dir_all(path).expect("sled store should be removed");
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn sled_store_reopen() {
+        let path = std::env::temp_dir()
+            .join(format!("fantoch_sled_store_reopen_{}", std::process::id()));
+        let key = String::from("A");
+        let value = Value::from("x".to_string());
+
+        // write a value and its metadata, and close the database
+        let meta = Value::from("meta".to_string());
+        let mut store = SledStore::open(&path).expect("sled store should open");
+        store.insert(key.clone(), value.clone());
+        store.insert_meta(key.clone(), meta.clone());
+        drop(store);
+
+        // both survive reopening it, and metadata is kept apart from values
+        let mut store =
+            SledStore::open(&path).expect("sled store should reopen");
+        assert_eq!(store.get(&key), Some(value.clone()));
+        let range: Vec<_> = store.range(&key, &String::from("B")).collect();
+        assert_eq!(range, vec![(key.clone(), value)]);
+        let metas: Vec<_> = store.metas().collect();
+        assert_eq!(metas, vec![(key.clone(), meta)]);
+        store.remove_meta(&key);
+        assert_eq!(store.metas().count(), 0);
+        drop(store);
+        std::fs::remove_dir_all(path).expect("sled store should be removed");
+    }
+}
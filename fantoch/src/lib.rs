@@ -78,6 +78,10 @@ pub mod load_balance {
 // This module contains some utilitary functions.
 pub mod util;
 
+// This module contains the definition of `OtlpLayer`, which exports the
+// traces of sampled commands to an OpenTelemetry collector.
+pub mod otlp;
+
 // Re-export `HashMap` and `HashSet`.
 pub use hash_map::HashMap;
 pub use hash_set::HashSet;
@@ -53,9 +53,15 @@ impl Histogram {
 
     /// Increments the occurrence of some value in the histogram.
     pub fn increment(&mut self, value: u64) {
-        // register another occurrence of `value`
-        let count = self.values.entry(value).or_insert(0);
-        *count += 1;
+        self.increment_by(value, 1);
+    }
+
+    /// Increments the occurrence of some value in the histogram by `count`.
+    pub fn increment_by(&mut self, value: u64, count: usize) {
+        if count > 0 {
+            // register `count` more occurrences of `value`
+            *self.values.entry(value).or_insert(0) += count;
+        }
     }
 
     pub fn mean(&self) -> F64 {
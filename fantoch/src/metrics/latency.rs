@@ -0,0 +1,186 @@
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+// number of significant digits kept by the histogram: values are recorded
+// with a relative error of at most 0.1%
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+/// HDR histogram with latencies in microseconds. Unlike `Histogram`, raw
+/// values are not stored, and so its size does not depend on the number of
+/// values recorded. It is serialized in the HdrHistogram V2 format, which
+/// allows histograms from different clients to be combined in
+/// post-processing.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    histogram: hdrhistogram::Histogram<u64>,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        let histogram = hdrhistogram::Histogram::new(SIGNIFICANT_DIGITS)
+            .expect("creating an auto-resizable histogram should work");
+        Self { histogram }
+    }
+
+    /// Returns the number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    /// Records a latency.
+    pub fn record(&mut self, latency: Duration) {
+        self.histogram
+            .record(latency.as_micros() as u64)
+            .expect("recording in an auto-resizable histogram should work");
+    }
+
+    /// Merges two histograms.
+    pub fn merge(&mut self, other: &Self) {
+        self.histogram
+            .add(&other.histogram)
+            .expect("adding to an auto-resizable histogram should work");
+    }
+
+    /// Returns the mean latency in microseconds.
+    pub fn mean(&self) -> f64 {
+        self.histogram.mean()
+    }
+
+    /// Returns the latency (in microseconds) below which `percentile` of the
+    /// latencies are.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        assert!((0.0..=1.0).contains(&percentile));
+        self.histogram.value_at_quantile(percentile)
+    }
+
+    /// Returns the highest latency recorded in microseconds.
+    pub fn max(&self) -> u64 {
+        self.histogram.max()
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for LatencyHistogram {
+    fn eq(&self, other: &Self) -> bool {
+        // histograms may have been resized differently (e.g. when
+        // deserialized), and so we compare their recorded values
+        let recorded = |histogram: &hdrhistogram::Histogram<u64>| {
+            histogram
+                .iter_recorded()
+                .map(|value| {
+                    (value.value_iterated_to(), value.count_at_value())
+                })
+                .collect::<Vec<_>>()
+        };
+        recorded(&self.histogram) == recorded(&other.histogram)
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.histogram.is_empty() {
+            write!(f, "(empty)")
+        } else {
+            write!(
+                f,
+                "count={} avg={}us p50={}us p90={}us p99={}us p99.9={}us max={}us",
+                self.count(),
+                self.mean().round(),
+                self.percentile(0.5),
+                self.percentile(0.9),
+                self.percentile(0.99),
+                self.percentile(0.999),
+                self.max(),
+            )
+        }
+    }
+}
+
+impl Serialize for LatencyHistogram {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut bytes = Vec::new();
+        V2Serializer::new()
+            .serialize(&self.histogram, &mut bytes)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        let mut histogram: hdrhistogram::Histogram<u64> = Deserializer::new()
+            .deserialize(&mut bytes.as_slice())
+            .map_err(serde::de::Error::custom)?;
+        // deserialized histograms are not auto-resizable, which would
+        // prevent merging them with others
+        histogram.auto(true);
+        Ok(Self { histogram })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_histogram() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.count(), 0);
+
+        // record latencies from 1ms to 100ms
+        (1..=100)
+            .for_each(|millis| histogram.record(Duration::from_millis(millis)));
+        assert_eq!(histogram.count(), 100);
+        // values are recorded with 3 significant digits
+        let within_precision = |value: u64, expected: u64| {
+            (value as f64 - expected as f64).abs() <= expected as f64 * 0.001
+        };
+        assert!(within_precision(histogram.percentile(0.5), 50_000));
+        assert!(within_precision(histogram.percentile(0.9), 90_000));
+        assert!(within_precision(histogram.percentile(0.99), 99_000));
+        assert!(within_precision(histogram.max(), 100_000));
+
+        // merge with a histogram with a much higher latency
+        let mut other = LatencyHistogram::new();
+        other.record(Duration::from_secs(100));
+        histogram.merge(&other);
+        assert_eq!(histogram.count(), 101);
+        assert!(within_precision(histogram.max(), 100_000_000));
+    }
+
+    #[test]
+    fn latency_histogram_serialization() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record(Duration::from_micros(10));
+        histogram.record(Duration::from_millis(10));
+
+        // check that the histogram survives serialization, and that the
+        // deserialized histogram can still be merged with others
+        let bytes = bincode::serialize(&histogram).unwrap();
+        let mut deserialized: LatencyHistogram =
+            bincode::deserialize(&bytes).unwrap();
+        assert_eq!(deserialized, histogram);
+
+        let mut other = LatencyHistogram::new();
+        other.record(Duration::from_secs(10));
+        deserialized.merge(&other);
+        histogram.merge(&other);
+        assert_eq!(deserialized, histogram);
+        assert_eq!(deserialized.count(), 3);
+    }
+}
@@ -4,9 +4,13 @@ pub mod float;
 // This module contains the definition of `Histogram`.
 mod histogram;
 
+// This module contains the definition of `LatencyHistogram`.
+mod latency;
+
 // Re-exports.
 pub use float::F64;
 pub use histogram::{Histogram, Stats};
+pub use latency::LatencyHistogram;
 
 use crate::HashMap;
 use serde::{Deserialize, Serialize};
@@ -17,6 +21,8 @@ use std::hash::Hash;
 pub struct Metrics<K: Eq + Hash> {
     collected: HashMap<K, Histogram>,
     aggregated: HashMap<K, u64>,
+    // named values sorted from highest to lowest (e.g. the hottest keys)
+    ranked: HashMap<K, Vec<(String, u64)>>,
 }
 
 impl<K> Metrics<K>
@@ -28,6 +34,7 @@ where
         Self {
             collected: HashMap::new(),
             aggregated: HashMap::new(),
+            ranked: HashMap::new(),
         }
     }
 
@@ -47,6 +54,18 @@ where
         *current += by;
     }
 
+    /// Sets the aggregated value of `kind`, for values that don't only grow
+    /// (e.g. memory usage).
+    pub fn set_aggregated(&mut self, kind: K, value: u64) {
+        self.aggregated.insert(kind, value);
+    }
+
+    /// Sets the ranked values of `kind`, which should be sorted from highest
+    /// to lowest.
+    pub fn set_ranked(&mut self, kind: K, ranked: Vec<(String, u64)>) {
+        self.ranked.insert(kind, ranked);
+    }
+
     pub fn get_collected(&self, kind: K) -> Option<&Histogram> {
         self.collected.get(&kind)
     }
@@ -55,6 +74,18 @@ where
         self.aggregated.get(&kind)
     }
 
+    pub fn get_ranked(&self, kind: K) -> Option<&[(String, u64)]> {
+        self.ranked.get(&kind).map(|ranked| ranked.as_slice())
+    }
+
+    pub fn collected(&self) -> impl Iterator<Item = (&K, &Histogram)> {
+        self.collected.iter()
+    }
+
+    pub fn aggregated(&self) -> impl Iterator<Item = (&K, &u64)> {
+        self.aggregated.iter()
+    }
+
     pub fn merge(&mut self, other: &Self) {
         for (k, hist) in other.collected.iter() {
             let current = self.collected.entry(*k).or_default();
@@ -64,6 +95,22 @@ where
             let current = self.aggregated.entry(*k).or_default();
             *current += v;
         }
+        for (k, ranked) in other.ranked.iter() {
+            let current = self.ranked.entry(*k).or_default();
+            // values with the same name are added, and only as many values
+            // as the longest ranking are kept
+            let len = current.len().max(ranked.len());
+            let mut merged: HashMap<_, u64> = HashMap::new();
+            for (name, value) in current.drain(..).chain(ranked.iter().cloned())
+            {
+                *merged.entry(name).or_default() += value;
+            }
+            current.extend(merged);
+            current.sort_by(|(name_a, value_a), (name_b, value_b)| {
+                value_b.cmp(value_a).then_with(|| name_a.cmp(name_b))
+            });
+            current.truncate(len);
+        }
     }
 }
 
@@ -78,6 +125,38 @@ where
         for (kind, value) in self.aggregated.iter() {
             writeln!(f, "{:?}: {:?}", kind, value)?;
         }
+        for (kind, ranked) in self.ranked.iter() {
+            writeln!(f, "{:?}: {:?}", kind, ranked)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranked_merge() {
+        let ranked = |values: &[(&str, u64)]| {
+            values
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect::<Vec<_>>()
+        };
+
+        let mut metrics_a = Metrics::new();
+        metrics_a.set_ranked(0, ranked(&[("A", 5), ("B", 3)]));
+        let mut metrics_b = Metrics::new();
+        metrics_b.set_ranked(0, ranked(&[("C", 4), ("B", 3), ("D", 1)]));
+
+        // values with the same name are added, and only the longest ranking
+        // is kept
+        metrics_a.merge(&metrics_b);
+        assert_eq!(
+            metrics_a.get_ranked(0),
+            Some(&ranked(&[("B", 6), ("A", 5), ("C", 4)])[..])
+        );
+        assert_eq!(metrics_a.get_ranked(1), None);
+    }
+}
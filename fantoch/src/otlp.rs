@@ -0,0 +1,548 @@
+use crate::id::{Dot, ProcessId};
+use crate::warn;
+use crate::HashMap;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+// spans are exported in batches, at least once per interval
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+const EXPORT_BATCH_SIZE: usize = 512;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+const EXPORT_PATH: &str = "/v1/traces";
+const SCOPE_NAME: &str = "fantoch";
+
+// a command is sampled if the hash of its dot is at most this threshold (by
+// default, all commands are sampled)
+static SAMPLE_THRESHOLD: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Configuration of the OTLP exporter: spans are sent (as OTLP/HTTP JSON) to
+/// the collector at `endpoint` (e.g. Jaeger's `localhost:4318`), and only a
+/// fraction `sample_rate` of the commands is traced.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub sample_rate: f64,
+}
+
+/// Returns whether the command with dot `dot` should be traced. Since the
+/// decision only depends on the dot, all processes trace the same commands.
+pub fn sampled(dot: &Dot) -> bool {
+    hash(dot) <= SAMPLE_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Creates a layer that exports the stages of the sampled commands (i.e. the
+/// children of their `util::dot_span`) to the collector in `config`. The
+/// trace id of each command is derived from its dot, and thus the stages of
+/// a command at different processes end up in the same trace.
+pub fn layer(config: OtlpConfig) -> OtlpLayer {
+    let threshold = if config.sample_rate >= 1.0 {
+        u64::MAX
+    } else {
+        (config.sample_rate.max(0.0) * u64::MAX as f64) as u64
+    };
+    SAMPLE_THRESHOLD.store(threshold, Ordering::Relaxed);
+
+    let (to_exporter, from_layer) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("otlp_exporter".to_string())
+        .spawn(move || exporter(config.endpoint, from_layer))
+        .expect("it should be possible to spawn the OTLP exporter");
+    OtlpLayer { to_exporter }
+}
+
+#[derive(Debug)]
+pub struct OtlpLayer {
+    to_exporter: Sender<FinishedSpan>,
+}
+
+// extension of a dot span
+struct DotSpan(Dot);
+
+// extension of a worker or executor span, with its fields
+struct TaskSpan(&'static str, Vec<(&'static str, String)>);
+
+// extension of a stage span (i.e. a child of a dot span)
+struct StageSpan {
+    span_id: u64,
+    start: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FinishedSpan {
+    dot: Dot,
+    process_id: Option<ProcessId>,
+    name: String,
+    span_id: u64,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &Attributes<'_>,
+        id: &Id,
+        ctx: Context<'_, S>,
+    ) {
+        let span = ctx.span(id).expect("new span should exist");
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let name = attrs.metadata().name();
+        match name {
+            "dot" => {
+                if let (Some(source), Some(sequence)) =
+                    (fields.source, fields.sequence)
+                {
+                    let dot = Dot::new(source as ProcessId, sequence);
+                    if sampled(&dot) {
+                        span.extensions_mut().insert(DotSpan(dot));
+                    }
+                }
+            }
+            "worker" | "executor" => {
+                span.extensions_mut().insert(TaskSpan(name, fields.all));
+            }
+            _ => {
+                let in_dot = span.parent().is_some_and(|parent| {
+                    parent.extensions().get::<DotSpan>().is_some()
+                });
+                if in_dot {
+                    span.extensions_mut().insert(StageSpan {
+                        span_id: span_id(),
+                        start: SystemTime::now(),
+                        attributes: fields.all,
+                    });
+                }
+            }
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("recorded span should exist");
+        let mut extensions = span.extensions_mut();
+        if let Some(stage) = extensions.get_mut::<StageSpan>() {
+            let mut fields = Fields::default();
+            values.record(&mut fields);
+            stage.attributes.extend(fields.all);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // events directly in a dot span (e.g. the commit) are exported as
+        // zero-length spans named after their message
+        let span = match ctx.event_span(event) {
+            Some(span) => span,
+            None => return,
+        };
+        let dot = match span.extensions().get::<DotSpan>() {
+            Some(DotSpan(dot)) => *dot,
+            None => return,
+        };
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let now = SystemTime::now();
+        let (process_id, mut attributes) = task(span.scope());
+        attributes.extend(fields.all);
+        self.export(FinishedSpan {
+            dot,
+            process_id,
+            name: fields.message.unwrap_or_default(),
+            span_id: span_id(),
+            start: now,
+            end: now,
+            attributes,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).expect("closed span should exist");
+        let stage = match span.extensions_mut().remove::<StageSpan>() {
+            Some(stage) => stage,
+            None => return,
+        };
+        let dot = match span.parent() {
+            Some(parent) => match parent.extensions().get::<DotSpan>() {
+                Some(DotSpan(dot)) => *dot,
+                None => return,
+            },
+            None => return,
+        };
+        let (process_id, mut attributes) = task(span.scope());
+        attributes.extend(stage.attributes);
+        self.export(FinishedSpan {
+            dot,
+            process_id,
+            name: span.name().to_string(),
+            span_id: stage.span_id,
+            start: stage.start,
+            end: SystemTime::now(),
+            attributes,
+        });
+    }
+}
+
+impl OtlpLayer {
+    fn export(&self, span: FinishedSpan) {
+        // if the exporter is gone, the span is simply dropped
+        let _ = self.to_exporter.send(span);
+    }
+}
+
+// Finds the worker or executor in whose span a span was created, returning
+// its process and its fields (prefixed by the task name).
+fn task<'a, R>(
+    scope: tracing_subscriber::registry::Scope<'a, R>,
+) -> (Option<ProcessId>, Vec<(&'static str, String)>)
+where
+    R: LookupSpan<'a>,
+{
+    for span in scope {
+        if let Some(TaskSpan(name, fields)) =
+            span.extensions().get::<TaskSpan>()
+        {
+            let process_id = fields
+                .iter()
+                .find(|(field, _)| *field == "process")
+                .and_then(|(_, value)| value.parse().ok());
+            let mut attributes = vec![("task", name.to_string())];
+            attributes.extend(fields.iter().cloned());
+            return (process_id, attributes);
+        }
+    }
+    (None, Vec::new())
+}
+
+#[derive(Default)]
+struct Fields {
+    source: Option<u64>,
+    sequence: Option<u64>,
+    message: Option<String>,
+    all: Vec<(&'static str, String)>,
+}
+
+impl Visit for Fields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "source" => self.source = Some(value),
+            "sequence" => self.sequence = Some(value),
+            _ => {}
+        }
+        self.all.push((field.name(), value.to_string()));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_value(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_value(field, format!("{:?}", value));
+    }
+}
+
+impl Fields {
+    fn record_value(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.all.push((field.name(), value));
+        }
+    }
+}
+
+fn exporter(endpoint: String, from_layer: Receiver<FinishedSpan>) {
+    let mut batch = Vec::with_capacity(EXPORT_BATCH_SIZE);
+    let mut last_export = Instant::now();
+    loop {
+        let timeout = EXPORT_INTERVAL.saturating_sub(last_export.elapsed());
+        let disconnected = match from_layer.recv_timeout(timeout) {
+            Ok(span) => {
+                batch.push(span);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if disconnected
+            || batch.len() >= EXPORT_BATCH_SIZE
+            || last_export.elapsed() >= EXPORT_INTERVAL
+        {
+            if !batch.is_empty() {
+                let body = serde_json::to_vec(&request(batch.drain(..)))
+                    .expect("OTLP request should be serializable");
+                if let Err(e) = post(&endpoint, &body) {
+                    warn!(
+                        "[otlp] error exporting spans to {}: {:?}",
+                        endpoint, e
+                    );
+                }
+            }
+            last_export = Instant::now();
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+fn post(endpoint: &str, body: &[u8]) -> io::Result<()> {
+    let mut stream = TcpStream::connect(endpoint)?;
+    stream.set_read_timeout(Some(EXPORT_TIMEOUT))?;
+    stream.set_write_timeout(Some(EXPORT_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        EXPORT_PATH,
+        endpoint,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response.lines().next().unwrap_or_default();
+    if status
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|code| code.starts_with('2'))
+    {
+        Ok(())
+    } else {
+        Err(io::Error::other(status.to_string()))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRequest {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<Span>,
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    name: String,
+    // internal span
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: String,
+}
+
+impl KeyValue {
+    fn new(key: impl Into<String>, value: String) -> Self {
+        Self {
+            key: key.into(),
+            value: AnyValue {
+                string_value: value,
+            },
+        }
+    }
+}
+
+// Creates an export request with `spans`, grouped by process (each process
+// being a different service).
+fn request(spans: impl Iterator<Item = FinishedSpan>) -> ExportRequest {
+    let mut processes: HashMap<Option<ProcessId>, Vec<Span>> = HashMap::new();
+    for span in spans {
+        let mut attributes = vec![
+            KeyValue::new("dot.source", span.dot.source().to_string()),
+            KeyValue::new("dot.sequence", span.dot.sequence().to_string()),
+        ];
+        attributes.extend(
+            span.attributes
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key, value)),
+        );
+        processes.entry(span.process_id).or_default().push(Span {
+            trace_id: trace_id(&span.dot),
+            span_id: format!("{:016x}", span.span_id),
+            name: span.name,
+            kind: 1,
+            start_time_unix_nano: unix_nanos(span.start),
+            end_time_unix_nano: unix_nanos(span.end),
+            attributes,
+        });
+    }
+    let resource_spans = processes
+        .into_iter()
+        .map(|(process_id, spans)| {
+            let service = match process_id {
+                Some(process_id) => format!("fantoch process {}", process_id),
+                None => String::from("fantoch"),
+            };
+            ResourceSpans {
+                resource: Resource {
+                    attributes: vec![KeyValue::new("service.name", service)],
+                },
+                scope_spans: vec![ScopeSpans {
+                    scope: Scope { name: SCOPE_NAME },
+                    spans,
+                }],
+            }
+        })
+        .collect();
+    ExportRequest { resource_spans }
+}
+
+// The trace id of a command: its source followed by its sequence.
+fn trace_id(dot: &Dot) -> String {
+    format!("{:016x}{:016x}", dot.source(), dot.sequence())
+}
+
+fn span_id() -> u64 {
+    // span ids can't be zero
+    rand::random::<u64>().max(1)
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+// splitmix64 of the dot, so that the sampled dots are spread evenly
+fn hash(dot: &Dot) -> u64 {
+    let mut x = (dot.source() as u64).rotate_left(48) ^ dot.sequence();
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn otlp_layer() {
+        let (to_exporter, from_layer) = mpsc::channel();
+        let layer = OtlpLayer { to_exporter };
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        let dot = Dot::new(1, 42);
+        tracing::subscriber::with_default(subscriber, || {
+            let worker = tracing::info_span!("worker", process = 2, index = 0);
+            worker.in_scope(|| {
+                let span = crate::util::dot_span(&dot);
+                span.in_scope(|| {
+                    let _handle = tracing::debug_span!(
+                        "handle",
+                        from = 1,
+                        msg = "MCollect"
+                    )
+                    .entered();
+                    // spans not in a dot span are not exported
+                    let _nested = tracing::debug_span!("nested").entered();
+                });
+                tracing::debug!(parent: &span, "commit");
+            });
+            // neither are spans and events outside a dot span
+            let _other = tracing::debug_span!("other").entered();
+            tracing::debug!("other");
+        });
+
+        let spans: Vec<_> = from_layer.try_iter().collect();
+        assert_eq!(spans.len(), 2);
+        let handle = &spans[0];
+        assert_eq!(handle.dot, dot);
+        assert_eq!(handle.process_id, Some(2));
+        assert_eq!(handle.name, "handle");
+        assert!(handle.start <= handle.end);
+        assert_eq!(
+            handle.attributes,
+            vec![
+                ("task", "worker".to_string()),
+                ("process", "2".to_string()),
+                ("index", "0".to_string()),
+                ("from", "1".to_string()),
+                ("msg", "MCollect".to_string()),
+            ]
+        );
+        let commit = &spans[1];
+        assert_eq!(commit.name, "commit");
+        assert_eq!(commit.process_id, Some(2));
+        assert_eq!(commit.start, commit.end);
+
+        // spans are grouped by process, and their trace id is the same for
+        // all spans of a command
+        let json = serde_json::to_value(request(spans.into_iter()))
+            .expect("request should be serializable");
+        let resource_spans = json["resourceSpans"].as_array().unwrap();
+        assert_eq!(resource_spans.len(), 1);
+        assert_eq!(
+            resource_spans[0]["resource"]["attributes"][0]["value"]
+                ["stringValue"],
+            "fantoch process 2"
+        );
+        let spans = resource_spans[0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 2);
+        for span in spans {
+            assert_eq!(span["traceId"], "0000000000000001000000000000002a");
+            assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        }
+    }
+
+    #[test]
+    fn sampling() {
+        let threshold = u64::MAX / 10;
+        let sampled = (1..=10_000)
+            .filter(|sequence| hash(&Dot::new(1, *sequence)) <= threshold)
+            .count();
+        // roughly 10% of the commands are sampled
+        assert!(sampled > 800 && sampled < 1200, "sampled: {}", sampled);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::planet::dat::Dat;
+use crate::planet::matrix;
+use crate::planet::Region;
+use crate::HashMap;
+use color_eyre::eyre;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// directory that contains all dat files for GCP
+pub(crate) const GCP_LAT_DIR: &str = "../latency_gcp/";
+
+// directory that contains all dat files for AWS
+pub(crate) const AWS_LAT_DIR: &str = "../latency_aws/2020_06_05/";
+
+// name of the file with the latency matrix in Azure datasets
+const AZURE_MATRIX_FILE: &str = "latencies.csv";
+
+/// Dataset with the latencies between the regions of some cloud provider,
+/// stored in directory `dir`, from which a `Planet` can be created (see
+/// `Planet::from_dataset`). All latencies are round-trip times in
+/// milliseconds. The directory format depends on the provider:
+/// - GCP and AWS: one `{region}.dat` file per region, where each line has the
+///   summary of the pings from that region to some other region, as output by
+///   `ping` (i.e. `min/avg/max/mdev:{other region}`); the average is the
+///   latency used. The bundled datasets are `latency_gcp/` and
+///   `latency_aws/{date}/`.
+/// - Azure: a `latencies.csv` file with the latency matrix, as published by
+///   Azure; the first line is `source` followed by the destination regions,
+///   and then each line has a source region followed by the latency to each
+///   destination region (left empty if unknown).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "provider", content = "dir", rename_all = "snake_case")]
+pub enum LatencyDataset {
+    Gcp(String),
+    Aws(String),
+    Azure(String),
+}
+
+impl LatencyDataset {
+    /// Returns the bundled GCP dataset.
+    pub fn gcp() -> Self {
+        Self::Gcp(GCP_LAT_DIR.to_string())
+    }
+
+    /// Returns the bundled AWS dataset (measured in June 2020).
+    pub fn aws() -> Self {
+        Self::Aws(AWS_LAT_DIR.to_string())
+    }
+
+    /// Returns the name of the cloud provider.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            Self::Gcp(_) => "gcp",
+            Self::Aws(_) => "aws",
+            Self::Azure(_) => "azure",
+        }
+    }
+
+    /// Returns the directory of the dataset.
+    pub fn dir(&self) -> &str {
+        match self {
+            Self::Gcp(dir) | Self::Aws(dir) | Self::Azure(dir) => dir,
+        }
+    }
+
+    /// Loads the latencies from each region to every other region in the
+    /// dataset.
+    pub(crate) fn latencies(
+        &self,
+    ) -> Result<HashMap<Region, HashMap<Region, u64>>, Report> {
+        let dir = Path::new(self.dir());
+        if !dir.is_dir() {
+            eyre::bail!("latency dataset {:?} is not a directory", dir);
+        }
+        match self {
+            Self::Gcp(dir) | Self::Aws(dir) => Ok(Dat::all_dats(dir)
+                .iter()
+                .map(|dat| (dat.region(), dat.latencies()))
+                .collect()),
+            Self::Azure(_) => matrix::from_csv(&dir.join(AZURE_MATRIX_FILE)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_matrix() {
+        let dir = std::env::temp_dir().join("fantoch_azure_dataset_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(AZURE_MATRIX_FILE),
+            "source,eastus,westeurope,japaneast\n\
+             eastus,,81.5,\n\
+             westeurope,82,1,226\n",
+        )
+        .unwrap();
+
+        let dataset = LatencyDataset::Azure(dir.to_str().unwrap().into());
+        let latencies = dataset.latencies().expect("matrix should be parsed");
+        let east_us = Region::new("eastus");
+        let west_eu = Region::new("westeurope");
+        let japan_east = Region::new("japaneast");
+        assert_eq!(latencies.len(), 2);
+        assert_eq!(latencies[&east_us][&east_us], 0);
+        assert_eq!(latencies[&east_us][&west_eu], 81);
+        assert!(!latencies[&east_us].contains_key(&japan_east));
+        assert_eq!(latencies[&west_eu][&west_eu], 0);
+        assert_eq!(latencies[&west_eu][&japan_east], 226);
+        let _ = std::fs::remove_dir_all(dir);
+
+        // directories that don't exist are an error
+        let dataset = LatencyDataset::Azure("latency_none".into());
+        assert!(dataset.latencies().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::planet::{Region, INTRA_REGION_LATENCY};
+use crate::HashMap;
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use std::path::Path;
+
+type Latencies = HashMap<Region, HashMap<Region, u64>>;
+
+// Parses the latency matrix in CSV file `path`: the first line has some label
+// (e.g. `source`) followed by the destination regions, and then each line has a
+// source region followed by the latency to each destination region (left empty
+// if unknown).
+pub(crate) fn from_csv(path: &Path) -> Result<Latencies, Report> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("read latency matrix {:?}", path))?;
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = match lines.next() {
+        Some(header) => header,
+        None => eyre::bail!("latency matrix {:?} is empty", path),
+    };
+    let destinations: Vec<_> = header
+        .split(',')
+        .skip(1)
+        .map(|region| Region::new(region.trim()))
+        .collect();
+
+    let mut latencies = HashMap::new();
+    for line in lines {
+        let mut cells = line.split(',');
+        // there's always a first cell
+        let source = Region::new(cells.next().unwrap().trim());
+        let mut source_latencies = HashMap::new();
+        for (destination, cell) in destinations.iter().zip(cells) {
+            let cell = cell.trim();
+            if cell.is_empty() {
+                continue;
+            }
+            let latency = cell
+                .parse()
+                .map_err(Report::from)
+                .and_then(to_millis)
+                .wrap_err_with(|| {
+                    format!(
+                        "parse latency from {:?} to {:?} in {:?}",
+                        source, destination, path
+                    )
+                })?;
+            source_latencies.insert(destination.clone(), latency);
+        }
+        add_source(&mut latencies, source, source_latencies);
+    }
+    Ok(latencies)
+}
+
+// Parses the latency matrix in JSON file `path`: an object that maps each
+// source region to an object with the latency to each destination region.
+pub(crate) fn from_json(path: &Path) -> Result<Latencies, Report> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("read latency matrix {:?}", path))?;
+    let matrix: HashMap<Region, HashMap<Region, f64>> =
+        serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("parse latency matrix {:?}", path))?;
+
+    let mut latencies = HashMap::new();
+    for (source, destinations) in matrix {
+        let mut source_latencies = HashMap::new();
+        for (destination, latency) in destinations {
+            let latency = to_millis(latency).wrap_err_with(|| {
+                format!(
+                    "latency from {:?} to {:?} in {:?}",
+                    source, destination, path
+                )
+            })?;
+            source_latencies.insert(destination, latency);
+        }
+        add_source(&mut latencies, source, source_latencies);
+    }
+    Ok(latencies)
+}
+
+fn to_millis(latency: f64) -> Result<u64, Report> {
+    if !latency.is_finite() || latency < 0.0 {
+        eyre::bail!("invalid latency {}", latency);
+    }
+    // it always rounds down, as with dat files
+    Ok(latency as u64)
+}
+
+fn add_source(
+    latencies: &mut Latencies,
+    source: Region,
+    mut source_latencies: HashMap<Region, u64>,
+) {
+    // intra-region latency is assumed to be 0
+    source_latencies.insert(source.clone(), INTRA_REGION_LATENCY);
+    latencies.insert(source, source_latencies);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_and_json() {
+        let dir = std::env::temp_dir().join("fantoch_matrix_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("latencies.csv");
+        std::fs::write(
+            &csv,
+            "source,dc-lisbon,dc-paris,dc-tokyo\n\
+             dc-lisbon,,21.7,\n\
+             dc-paris,22,1,226\n",
+        )
+        .unwrap();
+        let json = dir.join("latencies.json");
+        std::fs::write(
+            &json,
+            r#"{
+                "dc-lisbon": {"dc-paris": 21.7},
+                "dc-paris": {"dc-lisbon": 22, "dc-paris": 1, "dc-tokyo": 226}
+            }"#,
+        )
+        .unwrap();
+
+        let lisbon = Region::new("dc-lisbon");
+        let paris = Region::new("dc-paris");
+        let tokyo = Region::new("dc-tokyo");
+        let csv_latencies =
+            from_csv(&csv).expect("CSV matrix should be parsed");
+        let json_latencies =
+            from_json(&json).expect("JSON matrix should be parsed");
+        for latencies in [csv_latencies, json_latencies] {
+            assert_eq!(latencies.len(), 2);
+            assert_eq!(latencies[&lisbon][&lisbon], 0);
+            assert_eq!(latencies[&lisbon][&paris], 21);
+            assert!(!latencies[&lisbon].contains_key(&tokyo));
+            assert_eq!(latencies[&paris][&paris], 0);
+            assert_eq!(latencies[&paris][&lisbon], 22);
+            assert_eq!(latencies[&paris][&tokyo], 226);
+        }
+
+        // negative latencies are an error
+        std::fs::write(&csv, "source,dc-lisbon\ndc-paris,-1\n").unwrap();
+        assert!(from_csv(&csv).is_err());
+        std::fs::write(&json, r#"{"dc-paris": {"dc-lisbon": -1}}"#).unwrap();
+        assert!(from_json(&json).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // and so are files that don't exist
+        assert!(from_csv(&dir.join("none.csv")).is_err());
+        assert!(from_json(&dir.join("none.json")).is_err());
+    }
+}
@@ -1,19 +1,27 @@
 // This module contains the definition of `Dat`.
 mod dat;
 
+// This module contains the definition of `LatencyDataset`.
+pub mod dataset;
+
+// This module contains the parsers of latency matrices.
+mod matrix;
+
 // This module contains the definition of `Region`.
 pub mod region;
 
 // Re-exports.
+pub use dataset::LatencyDataset;
 pub use region::Region;
 
 use crate::planet::dat::Dat;
+use crate::planet::dataset::GCP_LAT_DIR;
 use crate::HashMap;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Write};
-
-// directory that contains all dat files for GCP
-const GCP_LAT_DIR: &str = "../latency_gcp/";
+use std::path::Path;
 
 // assume that intra region latency is 0
 const INTRA_REGION_LATENCY: u64 = 0;
@@ -21,8 +29,12 @@ const INTRA_REGION_LATENCY: u64 = 0;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Planet {
     /// mapping from region A to a mapping from region B to the latency between
-    /// A and B
+    /// A and B (i.e. the round-trip time measured at A, which may differ from
+    /// the one measured at B)
     latencies: HashMap<Region, HashMap<Region, u64>>,
+    /// mapping from region A to a mapping from region B to the one-way delay
+    /// from A to B, if known
+    one_way: Option<HashMap<Region, HashMap<Region, u64>>>,
     /// mapping from each region to the regions sorted by distance
     sorted: HashMap<Region, Vec<(u64, Region)>>,
 }
@@ -44,13 +56,70 @@ impl Planet {
         Self::from_latencies(latencies)
     }
 
+    /// Creates a new `Planet` instance from the latencies in `dataset`.
+    pub fn from_dataset(dataset: &LatencyDataset) -> Result<Self, Report> {
+        let latencies = dataset
+            .latencies()
+            .wrap_err_with(|| format!("load latency dataset {:?}", dataset))?;
+        Ok(Self::from_latencies(latencies))
+    }
+
+    /// Creates a new `Planet` instance from the latency matrix in CSV file
+    /// `path`, with arbitrary region names. The first line has some label
+    /// (e.g. `source`) followed by the destination regions, and then each line
+    /// has a source region followed by the round-trip time (in milliseconds)
+    /// to each destination region (left empty if unknown).
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let latencies = matrix::from_csv(path.as_ref())?;
+        Ok(Self::from_latencies(latencies))
+    }
+
+    /// Creates a new `Planet` instance from the latency matrix in JSON file
+    /// `path`, with arbitrary region names: an object that maps each source
+    /// region to an object with the round-trip time (in milliseconds) to each
+    /// destination region.
+    pub fn from_json(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let latencies = matrix::from_json(path.as_ref())?;
+        Ok(Self::from_latencies(latencies))
+    }
+
     /// Creates a new `Planet` instance from the latencies provided.
     pub fn from_latencies(
         latencies: HashMap<Region, HashMap<Region, u64>>,
     ) -> Self {
         // create sorted and and planet
         let sorted = Self::sort_by_distance(latencies.clone());
-        Planet { latencies, sorted }
+        Planet {
+            latencies,
+            one_way: None,
+            sorted,
+        }
+    }
+
+    /// Creates a new `Planet` instance from the one-way delays provided, where
+    /// the delay from A to B may differ from the delay from B to A. The latency
+    /// between A and B is the round-trip time, i.e. the sum of the delays in
+    /// both directions (if both are known).
+    pub fn from_one_way_latencies(
+        one_way: HashMap<Region, HashMap<Region, u64>>,
+    ) -> Self {
+        let latencies = one_way
+            .iter()
+            .map(|(from, entries)| {
+                let entries = entries
+                    .iter()
+                    .filter_map(|(to, from_to)| {
+                        let to_from = one_way.get(to)?.get(from)?;
+                        Some((to.clone(), from_to + to_from))
+                    })
+                    .collect();
+                (from.clone(), entries)
+            })
+            .collect();
+        Planet {
+            one_way: Some(one_way),
+            ..Self::from_latencies(latencies)
+        }
     }
 
     /// Creates a equidistant `Planet`.
@@ -94,7 +163,11 @@ impl Planet {
         let sorted = Self::sort_by_distance(latencies.clone());
 
         // create single-region planet
-        let planet = Planet { latencies, sorted };
+        let planet = Planet {
+            latencies,
+            one_way: None,
+            sorted,
+        };
         (regions, planet)
     }
 
@@ -112,6 +185,22 @@ impl Planet {
         entries.get(to).cloned()
     }
 
+    /// Checks if the planet was created from one-way delays (see
+    /// `Planet::from_one_way_latencies`).
+    pub fn has_one_way_latencies(&self) -> bool {
+        self.one_way.is_some()
+    }
+
+    /// Retrieves the one-way delay from `from` to `to`. If the planet was not
+    /// created from one-way delays, it's half the latency between the two
+    /// regions (as measured at `from`).
+    pub fn one_way_latency(&self, from: &Region, to: &Region) -> Option<u64> {
+        match &self.one_way {
+            Some(one_way) => one_way.get(from)?.get(to).cloned(),
+            None => self.ping_latency(from, to).map(|latency| latency / 2),
+        }
+    }
+
     /// Returns a list of `Region`s sorted by the distance to the `Region`
     /// passed as argument. The distance to each region is also returned.
     pub fn sorted(&self, from: &Region) -> Option<&Vec<(u64, Region)>> {
@@ -209,6 +298,54 @@ mod tests {
         assert!(!symmetric(&us_w1, &eu_w3, &planet));
     }
 
+    #[test]
+    fn one_way_latency() {
+        let lisbon = Region::new("dc-lisbon");
+        let paris = Region::new("dc-paris");
+        let tokyo = Region::new("dc-tokyo");
+        let one_way = vec![
+            (
+                lisbon.clone(),
+                vec![(lisbon.clone(), 0), (paris.clone(), 10)],
+            ),
+            (
+                paris.clone(),
+                vec![
+                    (paris.clone(), 0),
+                    (lisbon.clone(), 30),
+                    (tokyo.clone(), 100),
+                ],
+            ),
+        ]
+        .into_iter()
+        .map(|(from, entries)| (from, entries.into_iter().collect()))
+        .collect();
+        let planet = Planet::from_one_way_latencies(one_way);
+
+        // one-way delays are directed
+        assert_eq!(planet.one_way_latency(&lisbon, &paris), Some(10));
+        assert_eq!(planet.one_way_latency(&paris, &lisbon), Some(30));
+        assert_eq!(planet.one_way_latency(&paris, &tokyo), Some(100));
+        assert_eq!(planet.one_way_latency(&tokyo, &paris), None);
+
+        // the latency is the round-trip time, known only if the delays in both
+        // directions are known
+        assert_eq!(planet.ping_latency(&lisbon, &paris), Some(40));
+        assert_eq!(planet.ping_latency(&paris, &lisbon), Some(40));
+        assert_eq!(planet.ping_latency(&paris, &paris), Some(0));
+        assert_eq!(planet.ping_latency(&paris, &tokyo), None);
+        let sorted = planet.sorted(&paris).unwrap();
+        assert_eq!(sorted, &vec![(0, paris.clone()), (40, lisbon.clone())]);
+
+        // without one-way delays, they're half the latency measured at the
+        // source
+        let planet = Planet::new();
+        let us_e1 = Region::new("us-east1");
+        let eu_w3 = Region::new("europe-west3");
+        let ping = planet.ping_latency(&us_e1, &eu_w3).unwrap();
+        assert_eq!(planet.one_way_latency(&us_e1, &eu_w3), Some(ping / 2));
+    }
+
     #[test]
     fn sorted() {
         // planet
@@ -253,6 +390,27 @@ mod tests {
         assert_eq!(res, expected);
     }
 
+    #[test]
+    fn datasets() {
+        // the bundled GCP dataset is the one used by default
+        let gcp = Planet::from_dataset(&LatencyDataset::gcp())
+            .expect("GCP dataset should be loaded");
+        let mut regions = gcp.regions();
+        regions.sort();
+        let mut expected = Planet::new().regions();
+        expected.sort();
+        assert_eq!(regions, expected);
+
+        // the bundled AWS dataset
+        let aws = Planet::from_dataset(&LatencyDataset::aws())
+            .expect("AWS dataset should be loaded");
+        assert_eq!(aws.regions().len(), 19);
+        let eu_west1 = Region::new("eu-west-1");
+        let us_west1 = Region::new("us-west-1");
+        assert_eq!(aws.ping_latency(&eu_west1, &eu_west1), Some(0));
+        assert!(aws.ping_latency(&eu_west1, &us_west1).unwrap() > 0);
+    }
+
     #[test]
     fn equidistant() {
         let planet_distance = 10;
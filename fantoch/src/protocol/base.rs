@@ -23,6 +23,9 @@ pub struct BaseProcess {
     fast_quorum_size: usize,
     write_quorum_size: usize,
     dot_gen: DotGen,
+    // highest logical time assigned to or seen in a command (see
+    // `BaseProcess::next_logical_time`)
+    logical_clock: u64,
     metrics: ProtocolMetrics,
 }
 
@@ -53,6 +56,7 @@ impl BaseProcess {
             fast_quorum_size,
             write_quorum_size,
             dot_gen: DotGen::new(process_id),
+            logical_clock: 0,
             metrics: ProtocolMetrics::new(),
         }
     }
@@ -158,6 +162,23 @@ impl BaseProcess {
         self.dot_gen.next_id()
     }
 
+    /// Returns the logical time to be assigned to the next command submitted
+    /// (see `Command::set_logical_time`). As with Lamport clocks, this time is
+    /// higher than the time of any command seen by this process (see
+    /// `BaseProcess::observe_logical_time`).
+    pub fn next_logical_time(&mut self) -> u64 {
+        self.logical_clock += 1;
+        self.logical_clock
+    }
+
+    /// Records the logical time of a command coordinated by some process.
+    pub fn observe_logical_time(&mut self, cmd: &Command) {
+        if let Some(logical_time) = cmd.logical_time() {
+            self.logical_clock =
+                std::cmp::max(self.logical_clock, logical_time);
+        }
+    }
+
     // Returns all processes.
     pub fn all(&self) -> HashSet<ProcessId> {
         self.all
@@ -495,4 +516,27 @@ mod tests {
         assert!(cmd_both_shards.replicated_by(&shard_id_0));
         assert!(cmd_both_shards.replicated_by(&shard_id_1));
     }
+
+    #[test]
+    fn logical_clock() {
+        let process_id = 1;
+        let shard_id = 0;
+        let config = Config::new(3, 1);
+        let mut bp = BaseProcess::new(process_id, shard_id, config, 2, 2);
+
+        // commands submitted are assigned increasing times
+        assert_eq!(bp.next_logical_time(), 1);
+        assert_eq!(bp.next_logical_time(), 2);
+
+        // and later than the time of the commands seen
+        let mut cmd = Command::from(Rifl::new(2, 1), vec![]);
+        bp.observe_logical_time(&cmd);
+        assert_eq!(bp.next_logical_time(), 3);
+        cmd.set_logical_time(10);
+        bp.observe_logical_time(&cmd);
+        assert_eq!(bp.next_logical_time(), 11);
+        cmd.set_logical_time(5);
+        bp.observe_logical_time(&cmd);
+        assert_eq!(bp.next_logical_time(), 12);
+    }
 }
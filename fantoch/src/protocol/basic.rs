@@ -104,7 +104,13 @@ impl Protocol for Basic {
     }
 
     /// Submits a command issued by some client.
-    fn submit(&mut self, dot: Option<Dot>, cmd: Command, _time: &dyn SysTime) {
+    fn submit(
+        &mut self,
+        dot: Option<Dot>,
+        mut cmd: Command,
+        time: &dyn SysTime,
+    ) {
+        cmd.set_submit_time(time.millis());
         self.handle_submit(dot, cmd);
     }
 
@@ -260,9 +266,11 @@ impl Basic {
             // - one entry per key being accessed will be created, which allows
             //   the basic executor to run in parallel
             let rifl = cmd.rifl();
+            let submit_time = cmd.submit_time();
             let execution_info =
                 cmd.iter(self.bp.shard_id).map(|(key, ops)| {
                     BasicExecutionInfo::new(rifl, key.clone(), ops.clone())
+                        .with_submit_time(submit_time)
                 });
             self.to_executors.extend(execution_info);
 
@@ -402,6 +410,24 @@ impl MessageIndex for Message {
             Self::MStable { .. } => None,
         }
     }
+
+    fn background(&self) -> bool {
+        matches!(
+            self,
+            Self::MCommitDot { .. }
+                | Self::MGarbageCollection { .. }
+                | Self::MStable { .. }
+        )
+    }
+
+    fn dot(&self) -> Option<&Dot> {
+        match self {
+            Self::MStore { dot, .. }
+            | Self::MStoreAck { dot, .. }
+            | Self::MCommit { dot, .. } => Some(dot),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -427,6 +453,14 @@ mod tests {
     use crate::time::SimTime;
     use crate::util;
 
+    #[test]
+    fn message_dot() {
+        let dot = Dot::new(1, 1);
+        assert_eq!(Message::MCommit { dot }.dot(), Some(&dot));
+        // gc messages are not traced in the span of any command
+        assert_eq!(Message::MCommitDot { dot }.dot(), None);
+    }
+
     #[test]
     fn basic_flow() {
         // create simulation
@@ -89,6 +89,17 @@ pub trait Protocol: Debug + Clone {
         // this
     }
 
+    fn handle_failure(
+        &mut self,
+        _process_id: ProcessId,
+        _detection: FailureDetection,
+        _time: &dyn SysTime,
+    ) {
+        // protocols interested in the suspicions of the failure detector (e.g.
+        // to start recovery, or to elect a new leader) should overwrite this;
+        // these notifications are delivered to all workers
+    }
+
     #[must_use]
     fn to_processes(&mut self) -> Option<Action<Self>>;
 
@@ -114,6 +125,15 @@ pub trait Protocol: Debug + Clone {
     fn metrics(&self) -> &ProtocolMetrics;
 }
 
+/// Notification from the failure detector about some process: either the
+/// process is now suspected of having failed, or it's trusted again (after
+/// being suspected).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureDetection {
+    Suspected,
+    Trusted,
+}
+
 pub struct ToProcessesIter<'a, P> {
     process: &'a mut P,
 }
@@ -234,6 +254,21 @@ pub trait MessageIndex {
     ///   making sure that index is higher than `reserved`
     /// - None: no indexing; message will be sent to all workers
     fn index(&self) -> Option<(usize, usize)>;
+
+    /// Background messages (e.g. garbage collection messages) are forwarded
+    /// to workers on separate channels, which workers only drain when there
+    /// are no latency-critical messages to be handled. By default, messages
+    /// are latency-critical.
+    fn background(&self) -> bool {
+        false
+    }
+
+    /// Returns the dot of the command this message is about, if any, so that
+    /// handling the message is traced in the span of that command (see
+    /// `util::dot_span`). By default, messages are not about any command.
+    fn dot(&self) -> Option<&Dot> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
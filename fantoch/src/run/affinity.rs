@@ -0,0 +1,174 @@
+use crate::run::task;
+use crate::warn;
+use core_affinity::CoreId;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Tasks that can be pinned to cores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinnedTask {
+    Worker,
+    Executor,
+    Reader,
+    Writer,
+}
+
+impl PinnedTask {
+    const ALL: [Self; 4] =
+        [Self::Worker, Self::Executor, Self::Reader, Self::Writer];
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Worker => 0,
+            Self::Executor => 1,
+            Self::Reader => 2,
+            Self::Writer => 3,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Worker => "worker",
+            Self::Executor => "executor",
+            Self::Reader => "reader",
+            Self::Writer => "writer",
+        }
+    }
+}
+
+/// Cores to which each kind of task is pinned. Tasks of each kind are pinned
+/// to the cores configured for that kind in a round-robin fashion: e.g. with
+/// cores `[0, 1]` for workers, the first worker is pinned to core 0, the
+/// second to core 1, the third to core 0, and so on. Tasks of a kind with no
+/// cores configured are not pinned (which is the default).
+///
+/// Each pinned task runs on its own thread (with its own single-threaded tokio
+/// runtime), as tokio may move the other tasks between its worker threads.
+#[derive(Debug, Default)]
+pub struct CpuAffinity {
+    cores: [Vec<usize>; 4],
+    // index of the next core to be used by each kind of task
+    next: [AtomicUsize; 4],
+}
+
+impl CpuAffinity {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cores(&self, task: PinnedTask) -> &[usize] {
+        &self.cores[task.index()]
+    }
+
+    pub fn set_cores(&mut self, task: PinnedTask, cores: Vec<usize>) {
+        self.cores[task.index()] = cores;
+    }
+
+    /// Checks whether no task is pinned.
+    pub fn is_empty(&self) -> bool {
+        PinnedTask::ALL
+            .iter()
+            .all(|task| self.cores(*task).is_empty())
+    }
+
+    /// Spawns a task of some kind, pinning it to the next core configured for
+    /// that kind (if any).
+    pub fn spawn<F>(&self, task: PinnedTask, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match self.next_core(task) {
+            Some(core) => spawn_pinned(task, core, future),
+            None => task::spawn(future),
+        }
+    }
+
+    fn next_core(&self, task: PinnedTask) -> Option<usize> {
+        let cores = self.cores(task);
+        if cores.is_empty() {
+            None
+        } else {
+            let next = self.next[task.index()].fetch_add(1, Ordering::Relaxed);
+            Some(cores[next % cores.len()])
+        }
+    }
+}
+
+fn spawn_pinned<F>(
+    task: PinnedTask,
+    core: usize,
+    future: F,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    std::thread::Builder::new()
+        .name(format!("{}_core_{}", task.name(), core))
+        .spawn(move || {
+            if !core_affinity::set_for_current(CoreId { id: core }) {
+                warn!(
+                    "[affinity] couldn't pin {} to core {}",
+                    task.name(),
+                    core
+                );
+            }
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("tokio runtime build should work");
+            // the receiver may be gone if the handle was dropped
+            let _ = tx.send(runtime.block_on(future));
+        })
+        .expect("[affinity] thread spawn should work");
+
+    // if the pinned task panics, the sender is dropped and this task panics
+    // as well
+    task::spawn(
+        async move { rx.await.expect("[affinity] pinned task should end") },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawn_test() {
+        let mut affinity = CpuAffinity::new();
+        assert!(affinity.is_empty());
+        affinity.set_cores(PinnedTask::Worker, vec![0]);
+        assert!(!affinity.is_empty());
+
+        // pinned tasks run on their own thread
+        let name = affinity
+            .spawn(PinnedTask::Worker, async {
+                std::thread::current().name().map(String::from)
+            })
+            .await
+            .expect("pinned task should end");
+        assert_eq!(name.as_deref(), Some("worker_core_0"));
+
+        // other tasks are spawned as usual
+        let result = affinity
+            .spawn(PinnedTask::Executor, async { 10 })
+            .await
+            .expect("task should end");
+        assert_eq!(result, 10);
+    }
+
+    #[test]
+    fn next_core_test() {
+        let mut affinity = CpuAffinity::new();
+        affinity.set_cores(PinnedTask::Reader, vec![2, 3]);
+        let cores: Vec<_> = (0..5)
+            .map(|_| affinity.next_core(PinnedTask::Reader))
+            .collect();
+        assert_eq!(cores, vec![Some(2), Some(3), Some(2), Some(3), Some(2)]);
+        assert_eq!(affinity.next_core(PinnedTask::Writer), None);
+    }
+}
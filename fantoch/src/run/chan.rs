@@ -1,6 +1,8 @@
 use crate::warn;
 use color_eyre::Report;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 
@@ -8,20 +10,45 @@ use tokio::sync::mpsc::{self, Receiver, Sender};
 pub struct ChannelSender<M> {
     name: Option<String>,
     sender: Sender<M>,
+    len: ChannelLen,
 }
 
 #[derive(Debug)]
 pub struct ChannelReceiver<M> {
     receiver: Receiver<M>,
+    len: ChannelLen,
+}
+
+/// Number of messages queued in a channel (i.e. sent but not yet received).
+#[derive(Debug, Clone, Default)]
+pub struct ChannelLen(Arc<AtomicUsize>);
+
+impl ChannelLen {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn decrement(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 pub fn channel<M>(
     channel_buffer_size: usize,
 ) -> (ChannelSender<M>, ChannelReceiver<M>) {
     let (sender, receiver) = mpsc::channel(channel_buffer_size);
+    let len = ChannelLen::default();
     (
-        ChannelSender { name: None, sender },
-        ChannelReceiver { receiver },
+        ChannelSender {
+            name: None,
+            sender,
+            len: len.clone(),
+        },
+        ChannelReceiver { receiver, len },
     )
 }
 
@@ -34,6 +61,17 @@ where
     }
 
     pub async fn send(&mut self, value: M) -> Result<(), Report> {
+        // increment the length before sending so that it doesn't underflow if
+        // the message is received before the increment
+        self.len.increment();
+        let result = self.do_send(value).await;
+        if result.is_err() {
+            self.len.decrement();
+        }
+        result
+    }
+
+    async fn do_send(&mut self, value: M) -> Result<(), Report> {
         match self.sender.try_send(value) {
             Ok(()) => {
                 // if it was sent, we're done
@@ -59,7 +97,13 @@ where
 }
 
 impl<M> ChannelSender<M> {
+    /// Returns the number of messages queued in this channel.
+    pub fn queued(&self) -> ChannelLen {
+        self.len.clone()
+    }
+
     pub async fn blind_send(&mut self, value: M) {
+        self.len.increment();
         let res = self.sender.send(value).await;
         assert!(res.is_ok(), "blind_send should succeeed");
     }
@@ -67,7 +111,26 @@ impl<M> ChannelSender<M> {
 
 impl<M> ChannelReceiver<M> {
     pub async fn recv(&mut self) -> Option<M> {
-        self.receiver.recv().await
+        let value = self.receiver.recv().await;
+        if value.is_some() {
+            self.len.decrement();
+        }
+        value
+    }
+
+    /// Receives a message if one is already queued, without waiting for it.
+    pub fn try_recv(&mut self) -> Option<M> {
+        let value = self.receiver.try_recv().ok();
+        if value.is_some() {
+            self.len.decrement();
+        }
+        value
+    }
+
+    /// Returns the number of messages queued in this channel, which can be
+    /// checked after the receiver is moved elsewhere.
+    pub fn queued(&self) -> ChannelLen {
+        self.len.clone()
     }
 }
 
@@ -76,6 +139,7 @@ impl<T> Clone for ChannelSender<T> {
         Self {
             name: self.name.clone(),
             sender: self.sender.clone(),
+            len: self.len.clone(),
         }
     }
 }
@@ -75,25 +75,52 @@ pub mod chan;
 // This module contains the implementaion on client-side and server-side logic.
 pub mod task;
 
+// This module contains the wire protocol between clients and processes.
+pub mod wire;
+
+// This module contains the definition of `CpuAffinity`.
+pub mod affinity;
+
+// Re-exports.
+pub use task::client::ClientOptions;
+
 const CONNECT_RETRIES: usize = 100;
 
+// on shutdown, the time given to clients to receive the results of their
+// pending commands
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
 use crate::client::Workload;
 use crate::config::Config;
-use crate::executor::Executor;
+use crate::executor::{Executor, ExecutorMetrics};
 use crate::hash_map::HashMap;
 use crate::id::{AtomicDotGen, ClientId, ProcessId, ShardId};
-use crate::info;
-use crate::protocol::Protocol;
-use color_eyre::Report;
+use crate::kvs::{Key, WatchNotification};
+use crate::protocol::{Protocol, ProtocolMetrics};
+use crate::{info, warn};
+use affinity::CpuAffinity;
+use color_eyre::eyre::{self, Report};
 use futures::stream::{FuturesUnordered, StreamExt};
 use prelude::*;
-use std::fmt::Debug;
+use rw::Compression;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
+use std::future::Future;
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::ToSocketAddrs;
-use tokio::sync::Semaphore;
-
+use task::server::health::{Health, Stage};
+use task::server::overload::Overload;
+use task::server::prometheus::Gauges;
+use task::server::rate_limit::RateLimiter;
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::{watch, Semaphore};
+use tokio::time;
+
+/// Runs a process until a ctrl-c (or, on unix, a SIGTERM) is received, at
+/// which point it's shutdown gracefully (see `process_with_shutdown`).
+#[allow(clippy::too_many_arguments)]
 pub async fn process<P, A>(
     process_id: ProcessId,
     shard_id: ShardId,
@@ -101,81 +128,227 @@ pub async fn process<P, A>(
     ip: IpAddr,
     port: u16,
     client_port: u16,
+    unix_socket_dir: Option<PathBuf>,
     addresses: Vec<(A, Option<Duration>)>,
     config: Config,
     tcp_nodelay: bool,
     tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
+    compression: Compression,
     process_channel_buffer_size: usize,
     client_channel_buffer_size: usize,
+    overload_threshold: Option<f64>,
+    submit_rate_limit: Option<u64>,
     workers: usize,
     executors: usize,
     multiplexing: usize,
+    cpu_affinity: Option<CpuAffinity>,
     execution_log: Option<String>,
+    executor_store_dir: Option<PathBuf>,
     ping_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
     metrics_file: Option<String>,
+    metrics_port: Option<u16>,
 ) -> Result<(), Report>
 where
     P: Protocol + Send + 'static, // TODO what does this 'static do?
-    A: ToSocketAddrs + Debug + Clone,
+    A: ToSocketAddrs + Debug + Display + Clone,
+{
+    process_with_shutdown::<P, A, _>(
+        process_id,
+        shard_id,
+        sorted_processes,
+        ip,
+        port,
+        client_port,
+        unix_socket_dir,
+        addresses,
+        config,
+        tcp_nodelay,
+        tcp_buffer_size,
+        tcp_flush_interval,
+        compression,
+        process_channel_buffer_size,
+        client_channel_buffer_size,
+        overload_threshold,
+        submit_rate_limit,
+        workers,
+        executors,
+        multiplexing,
+        cpu_affinity,
+        execution_log,
+        executor_store_dir,
+        ping_interval,
+        heartbeat_interval,
+        metrics_file,
+        metrics_port,
+        shutdown_signal(),
+    )
+    .await
+}
+
+/// Runs a process until `shutdown` completes. Then, the process:
+/// - stops accepting new client commands
+/// - waits until the results of pending client commands are sent (which
+///   requires other processes to still be running), for up to
+///   `SHUTDOWN_DRAIN_TIMEOUT`
+/// - stops workers and executors, which send their final metrics to the
+///   metrics logger (that dumps them before exiting)
+/// - waits for writers to flush their connections
+/// - aborts the tasks that only end once other processes do (e.g. readers)
+#[allow(clippy::too_many_arguments)]
+pub async fn process_with_shutdown<P, A, S>(
+    process_id: ProcessId,
+    shard_id: ShardId,
+    sorted_processes: Option<Vec<(ProcessId, ShardId)>>,
+    ip: IpAddr,
+    port: u16,
+    client_port: u16,
+    unix_socket_dir: Option<PathBuf>,
+    addresses: Vec<(A, Option<Duration>)>,
+    config: Config,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
+    tcp_flush_interval: Option<Duration>,
+    compression: Compression,
+    process_channel_buffer_size: usize,
+    client_channel_buffer_size: usize,
+    overload_threshold: Option<f64>,
+    submit_rate_limit: Option<u64>,
+    workers: usize,
+    executors: usize,
+    multiplexing: usize,
+    cpu_affinity: Option<CpuAffinity>,
+    execution_log: Option<String>,
+    executor_store_dir: Option<PathBuf>,
+    ping_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    metrics_file: Option<String>,
+    metrics_port: Option<u16>,
+    shutdown: S,
+) -> Result<(), Report>
+where
+    P: Protocol + Send + 'static,
+    A: ToSocketAddrs + Debug + Display + Clone,
+    S: Future<Output = ()>,
 {
     // create semaphore for callers that don't care about the connected
     // notification
     let semaphore = Arc::new(Semaphore::new(0));
-    process_with_notify_and_inspect::<P, A, ()>(
+    process_with_notify_and_inspect::<P, A, (), S>(
         process_id,
         shard_id,
         sorted_processes,
         ip,
         port,
         client_port,
+        unix_socket_dir,
         addresses,
         config,
         tcp_nodelay,
         tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         process_channel_buffer_size,
         client_channel_buffer_size,
+        overload_threshold,
+        submit_rate_limit,
         workers,
         executors,
         multiplexing,
+        cpu_affinity,
         execution_log,
+        executor_store_dir,
         ping_interval,
+        heartbeat_interval,
         metrics_file,
+        metrics_port,
         semaphore,
         None,
+        shutdown,
     )
     .await
 }
 
+/// Path of the unix domain socket a process started with a `unix_socket_dir`
+/// listens on for other processes (which connect to it at `unix://<path>`).
+pub fn process_unix_socket(
+    unix_socket_dir: &Path,
+    process_id: ProcessId,
+) -> PathBuf {
+    unix_socket_dir.join(format!("process_{}.sock", process_id))
+}
+
+/// Path of the unix domain socket a process started with a `unix_socket_dir`
+/// listens on for clients (which connect to it at `unix://<path>`).
+pub fn client_unix_socket(
+    unix_socket_dir: &Path,
+    process_id: ProcessId,
+) -> PathBuf {
+    unix_socket_dir.join(format!("client_{}.sock", process_id))
+}
+
+/// Completes once a ctrl-c (or, on unix, a SIGTERM) is received.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("couldn't listen for SIGTERM: {:?}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("couldn't listen for ctrl-c: {:?}", e);
+        // never shutdown
+        futures::future::pending::<()>().await
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
-async fn process_with_notify_and_inspect<P, A, R>(
+async fn process_with_notify_and_inspect<P, A, R, S>(
     process_id: ProcessId,
     shard_id: ShardId,
     sorted_processes: Option<Vec<(ProcessId, ShardId)>>,
     ip: IpAddr,
     port: u16,
     client_port: u16,
+    unix_socket_dir: Option<PathBuf>,
     addresses: Vec<(A, Option<Duration>)>,
     config: Config,
     tcp_nodelay: bool,
     tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
+    compression: Compression,
     process_channel_buffer_size: usize,
     client_channel_buffer_size: usize,
+    overload_threshold: Option<f64>,
+    submit_rate_limit: Option<u64>,
     workers: usize,
     executors: usize,
     multiplexing: usize,
+    cpu_affinity: Option<CpuAffinity>,
     execution_log: Option<String>,
+    executor_store_dir: Option<PathBuf>,
     ping_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
     metrics_file: Option<String>,
+    metrics_port: Option<u16>,
     connected: Arc<Semaphore>,
     inspect_chan: Option<InspectReceiver<P, R>>,
+    shutdown: S,
 ) -> Result<(), Report>
 where
     P: Protocol + Send + 'static, // TODO what does this 'static do?
-    A: ToSocketAddrs + Debug + Clone,
+    A: ToSocketAddrs + Debug + Display + Clone,
     R: Clone + Debug + Send + 'static,
+    S: Future<Output = ()>,
 {
     // panic if protocol is not parallel and we have more than one worker
     if workers > 1 && !P::parallel() {
@@ -202,10 +375,17 @@ where
 
     // ---------------------
     // start process listener
-    let listener = task::listen((ip, port)).await?;
+    let unix_socket = unix_socket_dir
+        .as_ref()
+        .map(|dir| process_unix_socket(dir, process_id));
+    let listener = task::listen((ip, port), unix_socket).await?;
 
-    // create forward channels: reader -> workers
-    let (reader_to_workers, reader_to_workers_rxs) = ReaderToWorkers::<P>::new(
+    // create forward channels: reader -> workers (see `ReaderToWorkers`)
+    let (
+        reader_to_workers,
+        reader_to_workers_rxs,
+        background_reader_to_workers_rxs,
+    ) = ReaderToWorkers::<P>::new(
         "reader_to_workers",
         process_channel_buffer_size,
         workers,
@@ -218,63 +398,153 @@ where
         executors,
     );
 
-    // connect to all processes
-    let (ips, to_writers) = task::server::connect_to_all::<A, P>(
-        process_id,
-        shard_id,
-        config,
-        listener,
-        addresses,
-        reader_to_workers.clone(),
-        to_executors.clone(),
-        CONNECT_RETRIES,
-        tcp_nodelay,
-        tcp_buffer_size,
-        tcp_flush_interval,
+    // create forward channels: accept task -> workers (for processes that join
+    // late)
+    let (joined_to_workers, joined_to_workers_rxs) = JoinedToWorkers::<P>::new(
+        "joined_to_workers",
         process_channel_buffer_size,
-        multiplexing,
-    )
-    .await?;
+        workers,
+    );
+
+    // create the layout of cores to which tasks are pinned (if any)
+    let cpu_affinity = Arc::new(cpu_affinity.unwrap_or_default());
+    if !cpu_affinity.is_empty() {
+        info!("cpu affinity: {:?}", cpu_affinity);
+    }
+
+    // create the gauges exported by the metrics endpoint (if any)
+    let gauges = Arc::new(Gauges::default());
+    gauges.register_channels("reader_to_workers", &reader_to_workers_rxs);
+    gauges.register_channels(
+        "reader_to_workers_background",
+        &background_reader_to_workers_rxs,
+    );
+    gauges.register_channels("to_executors", &to_executors_rxs);
+
+    // create the overload detector, which checks the channels to executors,
+    // writers and workers (see `Overload`)
+    let overload = Arc::new(Overload::new(overload_threshold));
+    overload.register_channels(&to_executors_rxs, process_channel_buffer_size);
+
+    // create the rate limiter of client submissions, which can be changed at
+    // runtime through the metrics endpoint (if any)
+    let rate_limiter = Arc::new(RateLimiter::new(submit_rate_limit));
+
+    // maybe start metrics endpoint (before connecting to all processes, so
+    // that it can tell that the process is not ready yet)
+    let health = Arc::new(Health::new(overload.clone()));
+    let mut prometheus_handle = None;
+    let to_prometheus = if let Some(metrics_port) = metrics_port {
+        let listener = TcpListener::bind((ip, metrics_port)).await?;
+        let metrics = (ProtocolMetrics::new(), ExecutorMetrics::new());
+        let (to_prometheus, metrics) = watch::channel(metrics);
+        info!("metrics endpoint listening on port {}", metrics_port);
+        prometheus_handle =
+            Some(task::spawn(task::server::prometheus::prometheus_task(
+                listener,
+                process_id,
+                shard_id,
+                metrics,
+                gauges.clone(),
+                rate_limiter.clone(),
+                health.clone(),
+            )));
+        Some(to_prometheus)
+    } else {
+        None
+    };
+
+    // create shutdown signals:
+    // - the first one stops client tasks from accepting new commands
+    // - the second one stops workers and executors
+    let (clients_shutdown_tx, clients_shutdown) = watch::channel(false);
+    let (workers_shutdown_tx, workers_shutdown) = watch::channel(false);
+
+    // create channel from readers to the failure detector (if any)
+    let (to_failure_detector, from_readers) = if heartbeat_interval.is_some() {
+        let (mut to_failure_detector, from_readers) =
+            chan::channel(process_channel_buffer_size);
+        to_failure_detector.set_name("to_failure_detector");
+        (Some(to_failure_detector), Some(from_readers))
+    } else {
+        (None, None)
+    };
+
+    // connect to all processes
+    let (ips, to_writers, reader_handles, writer_handles) =
+        task::server::connect_to_all::<A, P>(
+            process_id,
+            shard_id,
+            config,
+            listener,
+            addresses,
+            reader_to_workers.clone(),
+            to_executors.clone(),
+            joined_to_workers,
+            to_failure_detector,
+            gauges.clone(),
+            cpu_affinity.clone(),
+            CONNECT_RETRIES,
+            tcp_nodelay,
+            tcp_buffer_size,
+            tcp_flush_interval,
+            compression,
+            process_channel_buffer_size,
+            multiplexing,
+        )
+        .await?;
+
+    health.set_stage(Stage::Starting);
+
+    // check the channels to writers for overload (writers to processes that
+    // join late are not checked)
+    for writer in to_writers.values().flatten() {
+        overload.register_channel(writer.queued(), process_channel_buffer_size);
+    }
 
     // get sorted processes (maybe from ping task)
-    let sorted_processes = if let Some(sorted_processes) = sorted_processes {
+    let (ping_handle, sorted_processes) = if let Some(sorted_processes) =
+        sorted_processes
+    {
         // in this case, we already have the sorted processes, so simply span
         // the ping task without a parent and return what we have
-        task::spawn(task::server::ping::ping_task(
+        let ping_handle = task::spawn(task::server::ping::ping_task(
             ping_interval,
             process_id,
             shard_id,
             ips,
             None,
         ));
-        sorted_processes
+        (ping_handle, sorted_processes)
     } else {
         // when we don't have the sorted processes, spawn the ping task and ask
         // it for the sorted processes
-        let to_ping = task::spawn_consumer(process_channel_buffer_size, |rx| {
-            let parent = Some(rx);
-            task::server::ping::ping_task(
-                ping_interval,
-                process_id,
-                shard_id,
-                ips,
-                parent,
-            )
-        });
-        ask_ping_task(to_ping).await
+        let (to_ping, from_parent) = chan::channel(process_channel_buffer_size);
+        let ping_handle = task::spawn(task::server::ping::ping_task(
+            ping_interval,
+            process_id,
+            shard_id,
+            ips,
+            Some(from_parent),
+        ));
+        (ping_handle, ask_ping_task(to_ping).await)
     };
 
-    // check that we have n processes (all in my shard), plus one connection to
-    // each other shard
-    assert_eq!(
-        sorted_processes.len(),
-        config.n() + config.shard_count() - 1,
-        "sorted processes count should be n + shards - 1"
+    // check that we have at most n processes (all in my shard), plus one
+    // connection to each other shard (there can be less if some processes only
+    // join later)
+    assert!(
+        sorted_processes.len() < config.n() + config.shard_count(),
+        "sorted processes count should be at most n + shards - 1"
     );
 
     // ---------------------
     // start client listener
-    let client_listener = task::listen((ip, client_port)).await?;
+    let client_unix_socket = unix_socket_dir
+        .as_ref()
+        .map(|dir| client_unix_socket(dir, process_id));
+    let client_listener =
+        task::listen((ip, client_port), client_unix_socket).await?;
 
     // create atomic dot generator to be used by clients in case the protocol is
     // leaderless:
@@ -318,29 +588,44 @@ where
         executors,
     );
 
+    gauges.register_channels("periodic_to_workers", &periodic_to_workers_rxs);
+    gauges.register_channels("executors_to_workers", &executors_to_workers_rxs);
+    gauges.register_channels("client_to_workers", &client_to_workers_rxs);
+    gauges.register_channels("client_to_executors", &client_to_executors_rxs);
+    overload
+        .register_channels(&client_to_workers_rxs, client_channel_buffer_size);
+
     // start client listener
-    task::server::client::start_listener(
+    let client_listener_handle = task::server::client::start_listener(
         process_id,
         shard_id,
         client_listener,
         atomic_dot_gen,
         client_to_workers,
         client_to_executors,
+        P::Executor::store_index,
+        overload,
+        rate_limiter,
         tcp_nodelay,
         client_channel_buffer_size,
+        clients_shutdown,
     );
 
     // maybe create metrics logger
+    let mut metrics_logger_handle = None;
     let (worker_to_metrics_logger, executor_to_metrics_logger) =
-        if let Some(metrics_file) = metrics_file {
+        if metrics_file.is_some() || to_prometheus.is_some() {
             let (worker_to_metrics_logger, from_workers) =
                 chan::channel(process_channel_buffer_size);
             let (executor_to_metrics_logger, from_executors) =
                 chan::channel(process_channel_buffer_size);
-            task::spawn(task::server::metrics_logger::metrics_logger_task(
-                metrics_file,
-                from_workers,
-                from_executors,
+            metrics_logger_handle = Some(task::spawn(
+                task::server::metrics_logger::metrics_logger_task(
+                    metrics_file,
+                    to_prometheus,
+                    from_workers,
+                    from_executors,
+                ),
             ));
             (
                 Some(worker_to_metrics_logger),
@@ -353,13 +638,31 @@ where
     // create process
     let (mut process, process_events) = P::new(process_id, shard_id, config);
 
-    // discover processes
+    // discover processes; if not enough processes were discovered, the
+    // protocol can only make progress once the missing ones join
     let (connect_ok, closest_shard_process) =
-        process.discover(sorted_processes);
-    assert!(connect_ok, "process should have discovered successfully");
+        process.discover(sorted_processes.clone());
+    if !connect_ok {
+        info!("process {} waiting for more processes to join", process_id);
+    }
+
+    // maybe spawn failure detector task
+    let failure_detector_handle =
+        heartbeat_interval
+            .zip(from_readers)
+            .map(|(interval, from_readers)| {
+                task::spawn(
+                    task::server::failure_detector::failure_detector_task(
+                        interval,
+                        to_writers.clone(),
+                        from_readers,
+                        periodic_to_workers.clone(),
+                    ),
+                )
+            });
 
     // spawn periodic task
-    task::spawn(task::server::periodic::periodic_task(
+    let periodic_handle = task::spawn(task::server::periodic::periodic_task(
         process_events,
         periodic_to_workers,
         inspect_chan,
@@ -375,73 +678,159 @@ where
         shard_writers.insert(shard_id, writers);
     }
 
+    // create channel from workers and executors to this task, notified if
+    // some of them panics (see `supervisor::shutdown_on_panic`)
+    let (mut failures_tx, mut failures) = chan::channel(workers + executors);
+    failures_tx.set_name("failures");
+
+    // open a store per executor (if configured)
+    let executor_stores = task::server::executor::open_stores(
+        process_id,
+        executors,
+        executor_store_dir,
+    )?;
+
     // start executors
-    task::server::executor::start_executors::<P>(
+    let executor_handles = task::server::executor::start_executors::<P>(
         process_id,
         shard_id,
         config,
+        executor_stores,
         to_executors_rxs,
         client_to_executors_rxs,
         executors_to_workers,
         shard_writers,
         to_executors.clone(),
         executor_to_metrics_logger,
+        cpu_affinity.clone(),
+        failures_tx.clone(),
+        workers_shutdown.clone(),
     );
 
     // start process workers
-    let handles = task::server::process::start_processes::<P, R>(
+    let worker_handles = task::server::process::start_processes::<P, R>(
         process,
         reader_to_workers_rxs,
+        background_reader_to_workers_rxs,
         client_to_workers_rxs,
         periodic_to_workers_rxs,
         executors_to_workers_rxs,
+        joined_to_workers_rxs,
+        sorted_processes,
         to_writers,
         reader_to_workers,
         to_executors,
         process_channel_buffer_size,
         execution_log,
         worker_to_metrics_logger,
+        cpu_affinity,
+        failures_tx,
+        workers_shutdown,
     );
     info!("process {} started", process_id);
+    health.set_stage(Stage::Running);
 
     // notify parent that we're connected
     connected.add_permits(1);
 
-    let mut handles = handles.into_iter().collect::<FuturesUnordered<_>>();
-    while let Some(join_result) = handles.next().await {
-        let join_result = join_result?;
-        info!("process ended {:?}", join_result);
+    // run until the shutdown signal, or until some worker or executor panics
+    let mut worker_handles =
+        worker_handles.into_iter().collect::<FuturesUnordered<_>>();
+    let mut failed = None;
+    tokio::select! {
+        // failures are checked first, as the worker that failed also ends
+        biased;
+        Some(name) = failures.recv() => {
+            failed = Some(name);
+        }
+        _ = shutdown => {}
+        Some(join_result) = worker_handles.next() => {
+            // workers only end on shutdown
+            join_result?;
+            info!("process ended");
+        }
+    }
+    info!("process {} shutting down", process_id);
+    health.set_stage(Stage::ShuttingDown);
+
+    // stop accepting new client commands, and wait until the results of the
+    // pending ones are sent
+    let _ = clients_shutdown_tx.send(true);
+    match time::timeout(SHUTDOWN_DRAIN_TIMEOUT, client_listener_handle).await {
+        Ok(join_result) => join_result?,
+        Err(_) => warn!(
+            "process {} couldn't send the results of all pending commands",
+            process_id
+        ),
+    }
+
+    // stop workers and executors; once they're gone, the loggers dump what's
+    // left and writers flush their connections
+    let _ = workers_shutdown_tx.send(true);
+    // the failure detector also sends to writers, and thus it's stopped before
+    // waiting for them
+    if let Some(handle) = failure_detector_handle {
+        handle.abort();
+        let _ = handle.await;
+    }
+    worker_handles.extend(executor_handles);
+    worker_handles.extend(metrics_logger_handle);
+    worker_handles.extend(writer_handles);
+    while let Some(join_result) = worker_handles.next().await {
+        join_result?;
+    }
+
+    // abort the tasks that only end once other processes do
+    let handles = reader_handles
+        .into_iter()
+        .chain(vec![ping_handle, periodic_handle])
+        .chain(prometheus_handle);
+    for handle in handles {
+        handle.abort();
+        let _ = handle.await;
+    }
+    info!("process {} shut down", process_id);
+    if let Some(name) = failed {
+        eyre::bail!("process {} shut down since {} panicked", process_id, name);
     }
     Ok(())
 }
 
+/// Runs clients `ids` (see `task::client::client`).
 pub async fn client<A>(
     ids: Vec<ClientId>,
     addresses: Vec<A>,
-    interval: Option<Duration>,
     workload: Workload,
-    batch_max_size: usize,
-    batch_max_delay: Duration,
+    options: ClientOptions,
+) -> Result<(), Report>
+where
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
+{
+    task::client::client(ids, addresses, workload, options, CONNECT_RETRIES)
+        .await
+}
+
+/// Watches `keys` in the shard of the process at `address`, which should be the
+/// shard of all these keys. Returns a channel where each write on these keys
+/// is notified. Dropping the channel closes the connection, and thus removes
+/// the watches.
+pub async fn watch<A>(
+    client_id: ClientId,
+    address: A,
+    keys: Vec<Key>,
     tcp_nodelay: bool,
     channel_buffer_size: usize,
-    status_frequency: Option<usize>,
-    metrics_file: Option<String>,
-) -> Result<(), Report>
+) -> Result<chan::ChannelReceiver<WatchNotification>, Report>
 where
-    A: ToSocketAddrs + Clone + Debug + Send + 'static + Sync,
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
 {
-    task::client::client(
-        ids,
-        addresses,
-        interval,
-        workload,
-        batch_max_size,
-        batch_max_delay,
+    task::client::watch(
+        client_id,
+        address,
+        keys,
         CONNECT_RETRIES,
         tcp_nodelay,
         channel_buffer_size,
-        status_frequency,
-        metrics_file,
     )
     .await
 }
@@ -466,6 +855,7 @@ pub mod tests {
     use super::*;
     use crate::protocol::ProtocolMetricsKind;
     use crate::util;
+    use affinity::PinnedTask;
     use rand::Rng;
 
     #[tokio::test]
@@ -500,7 +890,39 @@ pub mod tests {
 
     #[test]
     fn run_basic_test() {
+        let late_processes = 0;
+        let unix_socket_dir = None;
+        run_basic(late_processes, unix_socket_dir);
+    }
+
+    #[test]
+    fn run_basic_late_join_test() {
+        // the last process only starts once the others are running; since all
+        // commands should still stabilize at all processes, the processes
+        // already running must have discovered it
+        let late_processes = 1;
+        let unix_socket_dir = None;
+        run_basic(late_processes, unix_socket_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_basic_unix_test() {
+        // processes and clients connect through unix domain sockets
+        let late_processes = 0;
+        let unix_socket_dir = std::env::temp_dir()
+            .join(format!("fantoch_run_basic_unix_{}", std::process::id()));
+        std::fs::create_dir_all(&unix_socket_dir)
+            .expect("creating unix socket dir should work");
+        run_basic(late_processes, Some(unix_socket_dir.clone()));
+        std::fs::remove_dir_all(&unix_socket_dir)
+            .expect("removing unix socket dir should work");
+    }
+
+    #[cfg(test)]
+    fn run_basic(late_processes: usize, unix_socket_dir: Option<PathBuf>) {
         use crate::client::KeyGen;
+        use crate::protocol::Basic;
 
         // config
         let n = 3;
@@ -539,17 +961,17 @@ pub mod tests {
 
         // run test and get total stable commands
         let total_stable_count = tokio_test_runtime()
-            .block_on(
-                run_test_with_inspect_fun::<crate::protocol::Basic, usize>(
-                    config,
-                    workload,
-                    clients_per_process,
-                    workers,
-                    executors,
-                    Some(inspect_stable_commands),
-                    extra_run_time,
-                ),
-            )
+            .block_on(run_test_with_late_processes::<Basic, usize>(
+                config,
+                workload,
+                clients_per_process,
+                workers,
+                executors,
+                Some(inspect_stable_commands),
+                extra_run_time,
+                late_processes,
+                unix_socket_dir,
+            ))
             .expect("run should complete successfully")
             .into_iter()
             .map(|(_, stable_counts)| stable_counts.into_iter().sum::<usize>())
@@ -581,6 +1003,42 @@ pub mod tests {
         inspect_fun: Option<fn(&P) -> R>,
         extra_run_time: Option<Duration>,
     ) -> Result<HashMap<ProcessId, Vec<R>>, Report>
+    where
+        P: Protocol + Send + 'static,
+        R: Clone + Debug + Send + 'static,
+    {
+        let late_processes = 0;
+        let unix_socket_dir = None;
+        run_test_with_late_processes(
+            config,
+            workload,
+            clients_per_process,
+            workers,
+            executors,
+            inspect_fun,
+            extra_run_time,
+            late_processes,
+            unix_socket_dir,
+        )
+        .await
+    }
+
+    // Runs a test in which the last `late_processes` processes only start
+    // (and join the others) once the remaining ones are connected. If
+    // `unix_socket_dir` is set, processes and clients connect through unix
+    // domain sockets in that directory.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_test_with_late_processes<P, R>(
+        config: Config,
+        workload: Workload,
+        clients_per_process: usize,
+        workers: usize,
+        executors: usize,
+        inspect_fun: Option<fn(&P) -> R>,
+        extra_run_time: Option<Duration>,
+        late_processes: usize,
+        unix_socket_dir: Option<PathBuf>,
+    ) -> Result<HashMap<ProcessId, Vec<R>>, Report>
     where
         P: Protocol + Send + 'static,
         R: Clone + Debug + Send + 'static,
@@ -596,8 +1054,11 @@ pub mod tests {
         let tcp_flush_interval = Some(Duration::from_millis(1));
         let process_channel_buffer_size = 10000;
         let client_channel_buffer_size = 10000;
+        let overload_threshold = Some(0.9);
+        let submit_rate_limit = None;
         let multiplexing = 2;
         let ping_interval = Some(Duration::from_secs(1));
+        let heartbeat_interval = Some(Duration::from_millis(500));
 
         // create processes ports and client ports
         let n = config.n();
@@ -614,13 +1075,46 @@ pub mod tests {
             .clone()
             .into_iter()
             .map(|(process_id, port)| {
-                let address = format!("localhost:{}", port);
+                let address = if let Some(dir) = &unix_socket_dir {
+                    let path = process_unix_socket(dir, process_id);
+                    format!("{}{}", rw::UNIX_PREFIX, path.display())
+                } else {
+                    format!("localhost:{}", port)
+                };
                 (process_id, address)
             })
             .collect();
 
+        // execution logs and metrics files are written into a temporary
+        // directory (unique to this run) that is removed once the test is over
+        let output_dir = std::env::temp_dir().join(format!(
+            "fantoch_run_test_{}_{}",
+            std::process::id(),
+            rand::thread_rng().gen::<u64>()
+        ));
+        std::fs::create_dir_all(&output_dir)
+            .expect("creating test output dir should work");
+        let output_file =
+            |name: String| output_dir.join(name).to_str().unwrap().to_string();
+
         let mut inspect_channels = HashMap::new();
 
+        // create shutdown signal (used once the test is over) and the list of
+        // process handles
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut process_handles = Vec::new();
+
+        // processes that join late only start after the others are connected;
+        // these are the last processes of the (single) shard
+        assert!(
+            late_processes == 0 || shard_count == 1,
+            "late processes are only supported with a single shard"
+        );
+        let is_late = |process_id: ProcessId| {
+            process_id as usize > config.n() - late_processes
+        };
+        let mut late = Vec::new();
+
         // the list of all ids that we can shuffle in order to set
         // `sorted_processes`
         let mut ids: Vec<_> = util::all_process_ids(shard_count, n).collect();
@@ -669,6 +1163,15 @@ pub mod tests {
             use rand::seq::SliceRandom;
             ids.shuffle(&mut rand::thread_rng());
 
+            // processes that don't join late can't connect to the ones that do
+            let ids: Vec<_> = ids
+                .iter()
+                .cloned()
+                .filter(|(peer_id, _)| {
+                    is_late(process_id) || !is_late(*peer_id)
+                })
+                .collect();
+
             // start `connect_to` will the processes within the same region
             // (i.e. one connection to each shard)
             let mut connect_to: Vec<_> =
@@ -719,46 +1222,103 @@ pub mod tests {
                 })
                 .collect();
 
+            // compress frames on odd processes (since compression is
+            // negotiated, only frames between odd processes are compressed)
+            let compression = if process_id % 2 == 1 {
+                Compression::Lz4
+            } else {
+                Compression::None
+            };
+
             // execution log
-            let execution_log = Some(format!("p{}.execution_log", process_id));
+            let execution_log =
+                Some(output_file(format!("p{}.execution_log", process_id)));
+
+            // start the metrics endpoint on the first process
+            let metrics_port = if process_id == 1 {
+                Some(get_available_port())
+            } else {
+                None
+            };
+
+            // pin all tasks of the first process to the first core
+            let cpu_affinity = if process_id == 1 {
+                let mut cpu_affinity = CpuAffinity::new();
+                for task in [
+                    PinnedTask::Worker,
+                    PinnedTask::Executor,
+                    PinnedTask::Reader,
+                    PinnedTask::Writer,
+                ] {
+                    cpu_affinity.set_cores(task, vec![0]);
+                }
+                Some(cpu_affinity)
+            } else {
+                None
+            };
 
             // create inspect channel and save sender side
             let (inspect_tx, inspect) = chan::channel(1);
             inspect_channels.insert(process_id, inspect_tx);
 
             // spawn processes
-            let metrics_file = format!(".metrics_process_{}", process_id);
-            tokio::task::spawn(
-                process_with_notify_and_inspect::<P, String, R>(
-                    process_id,
-                    shard_id,
-                    sorted_processes,
-                    localhost,
-                    port,
-                    client_port,
-                    addresses,
-                    config,
-                    tcp_nodelay,
-                    tcp_buffer_size,
-                    tcp_flush_interval,
-                    process_channel_buffer_size,
-                    client_channel_buffer_size,
-                    workers,
-                    executors,
-                    multiplexing,
-                    execution_log,
-                    ping_interval,
-                    Some(metrics_file),
-                    semaphore.clone(),
-                    Some(inspect),
-                ),
+            let metrics_file =
+                output_file(format!("metrics_process_{}", process_id));
+            let mut shutdown_rx = shutdown_rx.clone();
+            let shutdown = async move {
+                let _ = shutdown_rx.changed().await;
+            };
+            let process = process_with_notify_and_inspect::<P, String, R, _>(
+                process_id,
+                shard_id,
+                sorted_processes,
+                localhost,
+                port,
+                client_port,
+                unix_socket_dir.clone(),
+                addresses,
+                config,
+                tcp_nodelay,
+                tcp_buffer_size,
+                tcp_flush_interval,
+                compression,
+                process_channel_buffer_size,
+                client_channel_buffer_size,
+                overload_threshold,
+                submit_rate_limit,
+                workers,
+                executors,
+                multiplexing,
+                cpu_affinity,
+                execution_log,
+                None,
+                ping_interval,
+                heartbeat_interval,
+                Some(metrics_file),
+                metrics_port,
+                semaphore.clone(),
+                Some(inspect),
+                shutdown,
             );
+            if is_late(process_id) {
+                late.push(process);
+            } else {
+                process_handles.push(tokio::task::spawn(process));
+            }
         }
 
-        // wait that all processes are connected
+        // wait that all processes are connected (starting the ones that join
+        // late once the others are); permits are forgotten so that each
+        // process is waited for
         println!("[main] waiting that processes are connected");
-        for _ in util::all_process_ids(shard_count, n) {
-            let _ = semaphore.acquire().await;
+        for _ in 0..(n * shard_count - late.len()) {
+            let permit = semaphore.acquire().await;
+            permit.expect("acquire should work").forget();
+        }
+        for process in late {
+            process_handles.push(tokio::task::spawn(process));
+            let permit = semaphore.acquire().await;
+            permit.expect("acquire should work").forget();
         }
         println!("[main] processes are connected");
 
@@ -780,8 +1340,14 @@ pub mod tests {
                     // also connect to "self"
                     .chain(std::iter::once(process_id))
                     .map(|peer_id| {
-                        let client_port = *client_ports.get(&peer_id).unwrap();
-                        format!("localhost:{}", client_port)
+                        if let Some(dir) = &unix_socket_dir {
+                            let path = client_unix_socket(dir, peer_id);
+                            format!("{}{}", rw::UNIX_PREFIX, path.display())
+                        } else {
+                            let client_port =
+                                *client_ports.get(&peer_id).unwrap();
+                            format!("localhost:{}", client_port)
+                        }
                     })
                     .collect();
 
@@ -793,26 +1359,61 @@ pub mod tests {
                     1 => None,
                     _ => panic!("n mod 2 should be in [0,1]"),
                 };
+                let load_ramp = None;
+
+                // all clients share two connections to each process, and are
+                // driven by a single task
+                let connections = 2;
+                let client_tasks = 1;
 
                 // batching config
                 let batch_max_size = 1;
                 let batch_max_delay = Duration::from_millis(1);
+                let submit_batch_max_size = 1;
+
+                // commands are submitted to a single process
+                let submit_redundancy = 1;
+
+                // clients are given a single process of each shard, and thus
+                // can't fail over
+                let failover_timeout = None;
+
+                // commands don't time out
+                let cmd_timeout = None;
+                let cmd_retries = 0;
 
                 // spawn client
                 let status_frequency = None;
                 let metrics_file =
-                    Some(format!(".metrics_client_{}", process_id));
-                tokio::task::spawn(client(
-                    client_ids,
-                    addresses,
+                    Some(output_file(format!("metrics_client_{}", process_id)));
+                let metrics_report_file = None;
+                let metrics_tags = BTreeMap::new();
+                let trace_file = None;
+                let options = ClientOptions {
+                    connections,
+                    client_tasks,
                     interval,
-                    workload,
+                    load_ramp,
                     batch_max_size,
                     batch_max_delay,
+                    submit_batch_max_size,
+                    submit_redundancy,
+                    failover_timeout,
+                    cmd_timeout,
+                    cmd_retries,
                     tcp_nodelay,
-                    client_channel_buffer_size,
+                    channel_buffer_size: client_channel_buffer_size,
                     status_frequency,
                     metrics_file,
+                    metrics_report_file,
+                    metrics_tags,
+                    trace_file,
+                };
+                tokio::task::spawn(client(
+                    client_ids,
+                    addresses,
+                    workload.clone(),
+                    options,
                 ))
             })
             .collect();
@@ -845,6 +1446,14 @@ pub mod tests {
             }
         }
 
+        // shutdown all processes
+        let _ = shutdown_tx.send(true);
+        for process_handle in process_handles {
+            process_handle.await.expect("process should shutdown")?;
+        }
+        std::fs::remove_dir_all(&output_dir)
+            .expect("removing test output dir should work");
+
         Ok(result)
     }
 
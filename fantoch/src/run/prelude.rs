@@ -1,42 +1,150 @@
 use super::chan::{ChannelReceiver, ChannelSender};
 use super::pool;
-use crate::command::{Command, CommandResult};
-use crate::executor::{Executor, ExecutorMetrics, ExecutorResult};
+use super::rw::Compression;
+use crate::command::Command;
+use crate::config::Config;
+use crate::executor::{Executor, ExecutorMetrics, ExecutorResult, LocalRead};
 use crate::id::{ClientId, Dot, ProcessId, ShardId};
+use crate::kvs::{Key, WatchNotification};
 use crate::load_balance::*;
 use crate::protocol::{
-    CommittedAndExecuted, MessageIndex, Protocol, ProtocolMetrics,
+    CommittedAndExecuted, FailureDetection, MessageIndex, Protocol,
+    ProtocolMetrics,
 };
+use bytes::Bytes;
+use color_eyre::eyre::{self, Report};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Debug, Serialize, Deserialize)]
+// Re-exports.
+pub use super::wire::{ClientToServer, ServerToClient};
+
+// version of this crate
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessHi {
     pub process_id: ProcessId,
     pub shard_id: ShardId,
+    // port in which the process listens for other processes; it's only set
+    // on the first connections a process opens (and not, e.g., when it
+    // reconnects), so that processes already running can connect back to a
+    // process that joins late
+    pub listen_port: Option<u16>,
+    // compression the process is configured with (see `Compression::negotiate`)
+    pub compression: Compression,
+    // version of this crate, protocol and hash of the config the process was
+    // started with; processes only talk to processes that match them all
+    pub version: String,
+    pub protocol: String,
+    pub config_hash: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClientHi(pub Vec<ClientId>);
+impl ProcessHi {
+    pub fn new<P>(
+        process_id: ProcessId,
+        shard_id: ShardId,
+        listen_port: Option<u16>,
+        compression: Compression,
+        config: &Config,
+    ) -> Self
+    where
+        P: Protocol,
+    {
+        Self {
+            process_id,
+            shard_id,
+            listen_port,
+            compression,
+            version: VERSION.to_string(),
+            protocol: protocol_name::<P>(),
+            config_hash: config_hash(config),
+        }
+    }
 
-// If the command touches a single shard, then a `Submit` will be sent to that
-// shard. If the command touches more than on shard, a `Submit` will be sent to
-// one targetted shard and a `Register` will be sent to the remaining shards to
-// make sure that the client will eventually receive a `CommandResult` from all
-// shards.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ClientToServer {
-    Submit(Command),
-    Register(Command),
+    /// Checks that the process that sent this hi runs the same version of this
+    /// crate and the same protocol `P`, and that it was started with the same
+    /// `config`.
+    pub fn check<P>(&self, config: &Config) -> Result<(), Report>
+    where
+        P: Protocol,
+    {
+        if self.version != VERSION {
+            eyre::bail!(
+                "process {} runs version {} (expected version {})",
+                self.process_id,
+                self.version,
+                VERSION
+            );
+        }
+        let protocol = protocol_name::<P>();
+        if self.protocol != protocol {
+            eyre::bail!(
+                "process {} runs protocol {} (expected protocol {})",
+                self.process_id,
+                self.protocol,
+                protocol
+            );
+        }
+        if self.config_hash != config_hash(config) {
+            eyre::bail!(
+                "process {} was started with a different config (expected {:?})",
+                self.process_id,
+                config
+            );
+        }
+        Ok(())
+    }
+}
+
+fn protocol_name<P>() -> String {
+    std::any::type_name::<P>().to_string()
+}
+
+fn config_hash(config: &Config) -> u32 {
+    let bytes =
+        bincode::serialize(config).expect("[prelude] serialize should work");
+    crc32fast::hash(&bytes)
 }
 
 #[derive(Debug, Clone)]
 pub enum ClientToExecutor {
     // clients can register
-    Register(Vec<ClientId>, ExecutorResultSender),
+    Register(Vec<ClientId>, ExecutorToClientSender),
     // unregister
     Unregister(Vec<ClientId>),
+    // reads with eventual consistency, along with the index of the executor
+    // that should answer them (see `Executor::store_index`)
+    LocalRead(Option<(usize, usize)>, LocalRead),
+    // watch and unwatch keys, along with the index of the executor that holds
+    // them
+    Watch(Option<(usize, usize)>, ClientId, Key),
+    Unwatch(Option<(usize, usize)>, ClientId, Key),
+}
+
+// function returning the index of the executor whose store holds some key
+// (see `Executor::store_index`)
+pub type StoreIndex = fn(&Key) -> Option<(usize, usize)>;
+
+impl MessageIndex for ClientToExecutor {
+    fn index(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Register(..) | Self::Unregister(..) => None,
+            Self::LocalRead(index, _)
+            | Self::Watch(index, ..)
+            | Self::Unwatch(index, ..) => *index,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExecutorToClient {
+    // the result of a command (or partial command)
+    Result(ExecutorResult),
+    // a write on a key watched by some client
+    Notification(ClientId, WatchNotification),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,29 +156,46 @@ pub enum ClientToExecutor {
 pub enum POEMessage<P: Protocol> {
     Protocol(<P as Protocol>::Message),
     Executor(<<P as Protocol>::Executor as Executor>::ExecutionInfo),
+    // heartbeats of the failure detector (see `failure_detector_task`)
+    Heartbeat,
 }
 
 impl<P: Protocol> POEMessage<P> {
     pub fn to_executor(&self) -> bool {
         match self {
-            Self::Protocol(_) => false,
+            Self::Protocol(_) | Self::Heartbeat => false,
             Self::Executor(_) => true,
         }
     }
 }
 
+/// Message sent to a writer: either a message to be serialized by the writer or
+/// a message already serialized (so that a message sent to many processes is
+/// serialized only once).
+#[derive(Debug, Clone)]
+pub enum ToWriter<P: Protocol> {
+    Message(Arc<POEMessage<P>>),
+    Serialized(Bytes),
+}
+
+/// Message sent to a writer along with the instant it was sent at, so that
+/// writers can record how long messages wait to be written (see
+/// `ConnectionCounters::record_queue_wait`).
+pub type QueuedToWriter<P> = (Instant, ToWriter<P>);
+
 // list of channels used to communicate between tasks
 // TODO: remove most of these as it doesn't really help reading the code
-pub type ReaderReceiver<P> =
-    ChannelReceiver<(ProcessId, ShardId, <P as Protocol>::Message)>;
-pub type WriterReceiver<P> = ChannelReceiver<Arc<POEMessage<P>>>;
-pub type WriterSender<P> = ChannelSender<Arc<POEMessage<P>>>;
+pub type ReaderReceiver<P> = ChannelReceiver<ReaderMessage<P>>;
+pub type WriterReceiver<P> = ChannelReceiver<QueuedToWriter<P>>;
+pub type WriterSender<P> = ChannelSender<QueuedToWriter<P>>;
 pub type ClientToExecutorReceiver = ChannelReceiver<ClientToExecutor>;
 pub type ClientToServerReceiver = ChannelReceiver<ClientToServer>;
-pub type ServerToClientSender = ChannelSender<CommandResult>;
-pub type ExecutorResultReceiver = ChannelReceiver<ExecutorResult>;
-pub type ExecutorResultSender = ChannelSender<ExecutorResult>;
+pub type ServerToClientSender = ChannelSender<ServerToClient>;
+pub type ExecutorToClientReceiver = ChannelReceiver<ExecutorToClient>;
+pub type ExecutorToClientSender = ChannelSender<ExecutorToClient>;
 pub type ExecutedReceiver = ChannelReceiver<CommittedAndExecuted>;
+pub type Joined<P> = (ProcessId, ShardId, WriterSender<P>, ChannelSender<()>);
+pub type JoinedReceiver<P> = ChannelReceiver<Joined<P>>;
 pub type SubmitReceiver = ChannelReceiver<(Option<Dot>, Command)>;
 pub type ExecutionInfoReceiver<P> =
     ChannelReceiver<<<P as Protocol>::Executor as Executor>::ExecutionInfo>;
@@ -88,6 +213,12 @@ pub type ProtocolMetricsReceiver = ChannelReceiver<(usize, ProtocolMetrics)>;
 pub type ProtocolMetricsSender = ChannelSender<(usize, ProtocolMetrics)>;
 pub type ExecutorMetricsReceiver = ChannelReceiver<(usize, ExecutorMetrics)>;
 pub type ExecutorMetricsSender = ChannelSender<(usize, ExecutorMetrics)>;
+pub type ShutdownReceiver = tokio::sync::watch::Receiver<bool>;
+pub type HeartbeatReceiver = ChannelReceiver<ProcessId>;
+pub type HeartbeatSender = ChannelSender<ProcessId>;
+// unrecoverable tasks that panic notify the process (see
+// `supervisor::shutdown_on_panic`) with their name
+pub type FailureSender = ChannelSender<String>;
 
 // 1. workers receive messages from clients
 pub type ClientToWorkers = pool::ToPool<(Option<Dot>, Command)>;
@@ -104,9 +235,84 @@ impl pool::PoolIndex for (Option<Dot>, Command) {
     }
 }
 
-// 2. workers receive messages from readers
-pub type ReaderToWorkers<P> =
-    pool::ToPool<(ProcessId, ShardId, <P as Protocol>::Message)>;
+// 2. workers receive messages from readers; background messages (see
+// `MessageIndex::background`) are sent on a separate pool of channels, so that
+// workers can handle the latency-critical ones first
+pub type ReaderMessage<P> = (ProcessId, ShardId, <P as Protocol>::Message);
+pub struct ReaderToWorkers<P: Protocol> {
+    critical: pool::ToPool<ReaderMessage<P>>,
+    background: pool::ToPool<ReaderMessage<P>>,
+}
+
+impl<P> ReaderToWorkers<P>
+where
+    P: Protocol + 'static,
+{
+    /// Creates both pools with size `pool_size`, returning the receiver-side
+    /// of the critical channels and of the background channels.
+    pub fn new(
+        name: &str,
+        channel_buffer_size: usize,
+        pool_size: usize,
+    ) -> (Self, Vec<ReaderReceiver<P>>, Vec<ReaderReceiver<P>>) {
+        let (critical, critical_rxs) =
+            pool::ToPool::new(name, channel_buffer_size, pool_size);
+        let (background, background_rxs) = pool::ToPool::new(
+            format!("{}_background", name),
+            channel_buffer_size,
+            pool_size,
+        );
+        let to_workers = Self {
+            critical,
+            background,
+        };
+        (to_workers, critical_rxs, background_rxs)
+    }
+
+    /// Checks the index of the destination worker.
+    pub fn only_to_self(
+        &self,
+        msg: &ReaderMessage<P>,
+        worker_index: usize,
+    ) -> bool {
+        self.pool(msg).only_to_self(msg, worker_index)
+    }
+
+    /// Forwards message `msg` to its destination worker, either on a critical
+    /// or on a background channel.
+    pub async fn forward(
+        &mut self,
+        msg: ReaderMessage<P>,
+    ) -> Result<(), Report> {
+        if msg.2.background() {
+            self.background.forward(msg).await
+        } else {
+            self.critical.forward(msg).await
+        }
+    }
+
+    fn pool(&self, msg: &ReaderMessage<P>) -> &pool::ToPool<ReaderMessage<P>> {
+        if msg.2.background() {
+            &self.background
+        } else {
+            &self.critical
+        }
+    }
+}
+
+// `#[derive(Clone)]` would require `P: Clone`
+impl<P> Clone for ReaderToWorkers<P>
+where
+    P: Protocol,
+{
+    fn clone(&self) -> Self {
+        Self {
+            critical: self.critical.clone(),
+            background: self.background.clone(),
+        }
+    }
+}
+
 // The following allows e.g. (ProcessId, ShardId, <P as Protocol>::Message) to
 // be `ToPool::forward`
 impl<A> pool::PoolIndex for (ProcessId, ShardId, A)
@@ -127,6 +333,8 @@ where
 pub enum FromPeriodicMessage<P: Protocol, R> {
     Event(P::PeriodicEvent),
     Inspect(fn(&P) -> R, ChannelSender<R>),
+    // notification from the failure detector
+    Failure(ProcessId, FailureDetection),
 }
 
 impl<P, R> fmt::Debug for FromPeriodicMessage<P, R>
@@ -137,6 +345,11 @@ where
         match self {
             Self::Event(e) => write!(f, "FromPeriodicMessage::Event({:?})", e),
             Self::Inspect(_, _) => write!(f, "FromPeriodicMessage::Inspect"),
+            Self::Failure(process_id, detection) => write!(
+                f,
+                "FromPeriodicMessage::Failure({:?}, {:?})",
+                process_id, detection
+            ),
         }
     }
 }
@@ -151,6 +364,7 @@ where
         match self {
             Self::Event(e) => MessageIndex::index(e),
             Self::Inspect(_, _) => None, // send to all
+            Self::Failure(_, _) => None, // send to all
         }
     }
 }
@@ -180,3 +394,37 @@ where
         self.index()
     }
 }
+
+// 7. workers receive a writer to each connection opened to a process that
+// joins late (from the task accepting its connections); these are broadcast to
+// all workers, which acknowledge them through the `ChannelSender`
+pub type JoinedToWorkers<P> = pool::ToPool<Joined<P>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+
+    #[test]
+    fn process_hi_check() {
+        let config = Config::new(3, 1);
+        let hi =
+            ProcessHi::new::<Basic>(1, 0, None, Compression::None, &config);
+        assert!(hi.check::<Basic>(&config).is_ok());
+
+        // processes started with a different config are rejected
+        let mut other_config = config;
+        other_config.set_shard_count(2);
+        assert!(hi.check::<Basic>(&other_config).is_err());
+
+        // and so are processes running a different version or protocol
+        let mut other =
+            ProcessHi::new::<Basic>(1, 0, None, Compression::None, &config);
+        other.version = String::from("0.0.0");
+        assert!(other.check::<Basic>(&config).is_err());
+        let mut other =
+            ProcessHi::new::<Basic>(1, 0, None, Compression::None, &config);
+        other.protocol = String::from("Other");
+        assert!(other.check::<Basic>(&config).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+// size of the length header and of the checksum of each frame
+const LENGTH_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 4;
+pub const FRAME_HEADER_SIZE: usize = LENGTH_SIZE + CHECKSUM_SIZE;
+
+/// Delimits frames using a length header (as `LengthDelimitedCodec` does),
+/// and prefixes the payload of each frame with its CRC-32 checksum. Frames
+/// whose checksum doesn't match their payload are reported as an error, after
+/// which no more frames are read (since the length header of the following
+/// frames can't be trusted either).
+#[derive(Debug, Default)]
+pub struct ChecksumCodec {
+    frames: LengthDelimitedCodec,
+}
+
+impl ChecksumCodec {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Decoder for ChecksumCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<BytesMut>, io::Error> {
+        let mut frame = match self.frames.decode(src)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        if frame.len() < CHECKSUM_SIZE {
+            return Err(corrupted("frame is shorter than its checksum"));
+        }
+        let payload = frame.split_off(CHECKSUM_SIZE);
+        let mut checksum = [0; CHECKSUM_SIZE];
+        checksum.copy_from_slice(&frame);
+        if u32::from_be_bytes(checksum) != crc32fast::hash(&payload) {
+            return Err(corrupted("frame checksum mismatch"));
+        }
+        Ok(Some(payload))
+    }
+}
+
+impl Encoder<Bytes> for ChecksumCodec {
+    type Error = io::Error;
+
+    fn encode(
+        &mut self,
+        payload: Bytes,
+        dst: &mut BytesMut,
+    ) -> Result<(), io::Error> {
+        let len = CHECKSUM_SIZE + payload.len();
+        if len > self.frames.max_frame_length() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame exceeds max frame length",
+            ));
+        }
+        dst.reserve(LENGTH_SIZE + len);
+        dst.put_u32(len as u32);
+        dst.put_u32(crc32fast::hash(&payload));
+        dst.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+fn corrupted(error: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_codec() {
+        let mut codec = ChecksumCodec::new();
+        let payload = Bytes::from_static(b"fantoch");
+
+        // encode and decode a frame
+        let mut frame = BytesMut::new();
+        codec
+            .encode(payload.clone(), &mut frame)
+            .expect("encode should work");
+        assert_eq!(frame.len(), FRAME_HEADER_SIZE + payload.len());
+        let mut src = frame.clone();
+        let decoded = codec.decode(&mut src).expect("decode should work");
+        assert_eq!(decoded.as_deref(), Some(payload.as_ref()));
+
+        // incomplete frames are not decoded
+        let mut src = frame.clone();
+        let _ = src.split_off(frame.len() - 1);
+        assert!(codec
+            .decode(&mut src)
+            .expect("decode should work")
+            .is_none());
+
+        // frames with a corrupted payload are rejected
+        let mut src = frame.clone();
+        let last = src.len() - 1;
+        src[last] ^= 1;
+        let error = codec.decode(&mut src).expect_err("decode should fail");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,83 @@
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// zstd's compression level (the lowest levels are the fastest ones)
+const ZSTD_LEVEL: i32 = 1;
+
+/// Compression applied to each (serialized) frame sent on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// Computes the compression to be used on a connection, given the
+    /// compression configured at each of its ends: frames are only compressed
+    /// if both ends are configured with the same compression.
+    pub fn negotiate(self, peer: Self) -> Self {
+        if self == peer {
+            self
+        } else {
+            Self::None
+        }
+    }
+
+    pub fn compress(&self, bytes: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::None => bytes,
+            Self::Lz4 => lz4_flex::compress_prepend_size(&bytes),
+            Self::Zstd => zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL)
+                .expect("[rw] zstd compress should work"),
+        }
+    }
+
+    pub fn decompress<'a>(
+        &self,
+        bytes: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>, Report> {
+        let bytes = match self {
+            Self::None => Cow::Borrowed(bytes),
+            Self::Lz4 => {
+                Cow::Owned(lz4_flex::decompress_size_prepended(bytes)?)
+            }
+            Self::Zstd => Cow::Owned(zstd::decode_all(bytes)?),
+        };
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_decompress() {
+        let bytes = b"fantoch".repeat(100);
+        for compression in
+            [Compression::None, Compression::Lz4, Compression::Zstd]
+        {
+            let compressed = compression.compress(bytes.clone());
+            if compression != Compression::None {
+                // repeated payloads should compress well
+                assert!(compressed.len() < bytes.len() / 2);
+            }
+            let decompressed = compression
+                .decompress(&compressed)
+                .expect("decompress should work");
+            assert_eq!(decompressed, bytes.as_slice());
+        }
+    }
+
+    #[test]
+    fn negotiate() {
+        use Compression::*;
+        assert_eq!(Lz4.negotiate(Lz4), Lz4);
+        assert_eq!(Zstd.negotiate(Zstd), Zstd);
+        assert_eq!(Lz4.negotiate(Zstd), None);
+        assert_eq!(Lz4.negotiate(None), None);
+        assert_eq!(None.negotiate(Zstd), None);
+    }
+}
@@ -1,14 +1,26 @@
 use super::Rw;
-use std::net::IpAddr;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::time::Duration;
 
+/// Prefix of addresses of unix domain sockets.
+pub const UNIX_PREFIX: &str = "unix://";
+
 #[derive(Debug)]
 pub struct Connection {
-    ip_addr: Option<IpAddr>,
+    peer_addr: Option<SocketAddr>,
+    peer_path: Option<String>,
     delay: Option<Duration>,
-    rw: Rw<TcpStream>,
+    rw: Rw<Stream>,
 }
 
 impl Connection {
@@ -17,21 +29,62 @@ impl Connection {
         tcp_nodelay: bool,
         tcp_buffer_size: usize,
     ) -> Self {
-        // get ip addr
-        let ip_addr = stream.peer_addr().ok().map(|peer_addr| peer_addr.ip());
+        // get peer addr
+        let peer_addr = stream.peer_addr().ok();
         // configure stream
         configure(&stream, tcp_nodelay);
         // create rw
-        let rw = Rw::from(tcp_buffer_size, tcp_buffer_size, stream);
+        let rw =
+            Rw::from(tcp_buffer_size, tcp_buffer_size, Stream::Tcp(stream));
+        Self {
+            peer_addr,
+            peer_path: None,
+            delay: None,
+            rw,
+        }
+    }
+
+    /// Creates a connection on a unix domain socket. The `peer_path` is only
+    /// known for outgoing connections.
+    #[cfg(unix)]
+    pub fn new_unix(
+        stream: UnixStream,
+        peer_path: Option<PathBuf>,
+        buffer_size: usize,
+    ) -> Self {
+        let peer_path =
+            peer_path.map(|path| format!("{}{}", UNIX_PREFIX, path.display()));
+        // create rw
+        let rw = Rw::from(buffer_size, buffer_size, Stream::Unix(stream));
         Self {
-            ip_addr,
+            peer_addr: None,
+            peer_path,
             delay: None,
             rw,
         }
     }
 
+    /// Returns the ip address of the peer. Peers connected through a unix
+    /// domain socket run on the same machine, and thus their ip address is
+    /// the loopback one.
     pub fn ip_addr(&self) -> Option<IpAddr> {
-        self.ip_addr
+        match &self.rw.get_ref() {
+            Stream::Tcp(_) => self.peer_addr.map(|peer_addr| peer_addr.ip()),
+            #[cfg(unix)]
+            Stream::Unix(_) => Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns the address the peer can be connected to: either `ip:port` or,
+    /// for outgoing connections on a unix domain socket, `unix://path`.
+    pub fn peer_address(&self) -> Option<String> {
+        self.peer_path
+            .clone()
+            .or_else(|| self.peer_addr.map(|peer_addr| peer_addr.to_string()))
     }
 
     pub fn delay(&self) -> Option<Duration> {
@@ -71,8 +124,66 @@ fn configure(stream: &TcpStream, tcp_nodelay: bool) {
     // log!("SO_SNDBUF: {:?}", stream.send_buffer_size());
 }
 
+/// Stream of a connection: either a TCP stream or a unix domain socket stream.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
 impl Deref for Connection {
-    type Target = Rw<TcpStream>;
+    type Target = Rw<Stream>;
 
     fn deref(&self) -> &Self::Target {
         &self.rw
@@ -1,25 +1,118 @@
 // This module contains the definition of `Connection`.
 mod connection;
 
+// This module contains the definition of `Compression`.
+mod compression;
+
+// This module contains the definition of `ChecksumCodec`.
+mod codec;
+
 // Re-exports.
-pub use connection::Connection;
+pub use codec::FRAME_HEADER_SIZE;
+pub use compression::Compression;
+pub use connection::{Connection, Stream, UNIX_PREFIX};
 
 use crate::warn;
 use bytes::{Bytes, BytesMut};
+use codec::ChecksumCodec;
 use color_eyre::eyre::{Report, WrapErr};
 use futures::sink::{Sink, SinkExt};
 use futures::stream::StreamExt;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncWrite, BufStream};
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use tokio_util::codec::Framed;
 
-/// Delimits frames using a length header.
+/// Delimits frames using a length header, checking the integrity of each frame
+/// with a checksum (see `ChecksumCodec`).
 /// TODO take a look at async_bincode: https://docs.rs/async-bincode/0.5.1/async_bincode/index.html
 #[derive(Debug)]
 pub struct Rw<S> {
-    rw: Framed<BufStream<S>, LengthDelimitedCodec>,
+    rw: Framed<BufStream<S>, ChecksumCodec>,
+    compression: Compression,
+    counters: Arc<ConnectionCounters>,
+}
+
+/// Traffic on one or more connections: messages and bytes (after compression)
+/// sent and received, time spent serializing (and compressing) messages sent
+/// and deserializing (and decompressing) messages received, time spent by
+/// messages queued before being sent (see `record_queue_wait`), and messages
+/// dropped without being sent (see `record_dropped`).
+#[derive(Debug, Default)]
+pub struct ConnectionCounters {
+    sent_messages: AtomicU64,
+    received_messages: AtomicU64,
+    sent_bytes: AtomicU64,
+    received_bytes: AtomicU64,
+    serialization_nanos: AtomicU64,
+    deserialization_nanos: AtomicU64,
+    queue_wait_nanos: AtomicU64,
+    dropped_messages: AtomicU64,
+}
+
+impl ConnectionCounters {
+    pub fn sent_messages(&self) -> u64 {
+        self.sent_messages.load(Ordering::Relaxed)
+    }
+
+    pub fn received_messages(&self) -> u64 {
+        self.received_messages.load(Ordering::Relaxed)
+    }
+
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn serialization_time(&self) -> Duration {
+        Duration::from_nanos(self.serialization_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn deserialization_time(&self) -> Duration {
+        Duration::from_nanos(self.deserialization_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn queue_wait(&self) -> Duration {
+        Duration::from_nanos(self.queue_wait_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn dropped_messages(&self) -> u64 {
+        self.dropped_messages.load(Ordering::Relaxed)
+    }
+
+    /// Records that a message waited `wait` before being sent (e.g. in the
+    /// channel of the task writing to the connection).
+    pub fn record_queue_wait(&self, wait: Duration) {
+        add_nanos(&self.queue_wait_nanos, wait);
+    }
+
+    /// Records that a message was dropped without being sent (e.g. while
+    /// reconnecting, with the buffer of messages to be resent full).
+    pub fn record_dropped(&self) {
+        self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn sent(&self, bytes: usize) {
+        self.sent_messages.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn received(&self, bytes: usize) {
+        self.received_messages.fetch_add(1, Ordering::Relaxed);
+        self.received_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+fn add_nanos(counter: &AtomicU64, duration: Duration) {
+    counter.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
 }
 
 impl<S> Rw<S>
@@ -30,8 +123,37 @@ where
         // buffer rw
         let rw = BufStream::with_capacity(reader_capacity, writer_capacity, rw);
         // frame rw
-        let rw = Framed::new(rw, LengthDelimitedCodec::new());
-        Self { rw }
+        let rw = Framed::new(rw, ChecksumCodec::new());
+        Self {
+            rw,
+            compression: Compression::None,
+            counters: Arc::new(ConnectionCounters::default()),
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        self.rw.get_ref().get_ref()
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Sets the compression of frames sent and received from now on (frames
+    /// are not compressed by default).
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn counters(&self) -> Arc<ConnectionCounters> {
+        self.counters.clone()
+    }
+
+    /// Sets the counters updated from now on (which can be shared by many
+    /// connections).
+    pub fn set_counters(&mut self, counters: Arc<ConnectionCounters>) {
+        self.counters = counters;
     }
 
     pub async fn recv<V>(&mut self) -> Option<V>
@@ -40,11 +162,20 @@ where
     {
         match self.rw.next().await {
             Some(Ok(bytes)) => {
+                self.counters.received(bytes.len());
                 // if it is, and not an error, deserialize it
-                let value = deserialize(bytes);
-                Some(value)
+                let start = Instant::now();
+                let value = deserialize(bytes, self.compression);
+                add_nanos(
+                    &self.counters.deserialization_nanos,
+                    start.elapsed(),
+                );
+                value
             }
             Some(Err(e)) => {
+                // this includes corrupted frames, in which case the frame is
+                // dropped and the connection should be closed (and the peer
+                // will reconnect, if it's a process)
                 warn!("[rw] error while reading from stream: {:?}", e);
                 None
             }
@@ -56,18 +187,34 @@ where
     where
         V: Serialize,
     {
-        let bytes = serialize(value);
-        self.rw
-            .send(bytes)
-            .await
-            .wrap_err("error while sending to sink")
+        let bytes = self.serialize(value);
+        self.send_raw(bytes).await
     }
 
     pub async fn write<V>(&mut self, value: &V) -> Result<(), Report>
     where
         V: Serialize,
     {
-        let bytes = serialize(value);
+        let bytes = self.serialize(value);
+        self.write_raw(bytes).await
+    }
+
+    /// Same as `send` but for a value already serialized with `serialize`.
+    /// This allows a value sent on many connections to be serialized only
+    /// once.
+    pub async fn send_raw(&mut self, bytes: Bytes) -> Result<(), Report> {
+        let bytes = self.compress(bytes);
+        self.counters.sent(bytes.len());
+        self.rw
+            .send(bytes)
+            .await
+            .wrap_err("error while sending to sink")
+    }
+
+    /// Same as `write` but for a value already serialized with `serialize`.
+    pub async fn write_raw(&mut self, bytes: Bytes) -> Result<(), Report> {
+        let bytes = self.compress(bytes);
+        self.counters.sent(bytes.len());
         futures::future::poll_fn(|cx| Pin::new(&mut self.rw).poll_ready(cx))
             .await
             .wrap_err("error while polling sink ready")?;
@@ -81,16 +228,55 @@ where
             .await
             .wrap_err("error while flushing sink")
     }
+
+    fn serialize<V>(&self, value: &V) -> Bytes
+    where
+        V: Serialize,
+    {
+        let start = Instant::now();
+        let bytes = serialize(value);
+        add_nanos(&self.counters.serialization_nanos, start.elapsed());
+        bytes
+    }
+
+    fn compress(&self, bytes: Bytes) -> Bytes {
+        let start = Instant::now();
+        let bytes = match self.compression {
+            // avoid copying the bytes if there's no compression
+            Compression::None => bytes,
+            compression => Bytes::from(compression.compress(bytes.to_vec())),
+        };
+        add_nanos(&self.counters.serialization_nanos, start.elapsed());
+        bytes
+    }
 }
 
-fn deserialize<V>(bytes: BytesMut) -> V
+// Frames that can't be decompressed or deserialized are treated as a read
+// error, since they may be sent by programs other than fantoch (see
+// `run::wire`).
+fn deserialize<V>(bytes: BytesMut, compression: Compression) -> Option<V>
 where
     V: DeserializeOwned,
 {
-    bincode::deserialize(&bytes).expect("[rw] deserialize should work")
+    let bytes = match compression.decompress(&bytes) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("[rw] error while decompressing frame: {:?}", e);
+            return None;
+        }
+    };
+    match bincode::deserialize(&bytes) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("[rw] error while deserializing frame: {:?}", e);
+            None
+        }
+    }
 }
 
-fn serialize<V>(value: &V) -> Bytes
+/// Serializes a value to be sent with `Rw::send_raw` or `Rw::write_raw`.
+/// Compression, if any, is applied only when sending the value.
+pub fn serialize<V>(value: &V) -> Bytes
 where
     V: Serialize,
 {
@@ -98,3 +284,38 @@ where
     let bytes = bincode::serialize(value).expect("[rw] serialize should work");
     Bytes::from(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connection_counters() {
+        let (left, right) = tokio::io::duplex(1024);
+        let mut left = Rw::from(1024, 1024, left);
+        let mut right = Rw::from(1024, 1024, right);
+        let counters = Arc::new(ConnectionCounters::default());
+        left.set_counters(counters.clone());
+        right.set_counters(counters.clone());
+
+        // send two messages, one of them already serialized
+        left.send(&1u64).await.expect("send should work");
+        left.send_raw(serialize(&2u64))
+            .await
+            .expect("send should work");
+        assert_eq!(right.recv::<u64>().await, Some(1));
+        assert_eq!(right.recv::<u64>().await, Some(2));
+
+        // each message is sent and received once, taking 8 bytes
+        assert_eq!(counters.sent_messages(), 2);
+        assert_eq!(counters.received_messages(), 2);
+        assert_eq!(counters.sent_bytes(), 16);
+        assert_eq!(counters.received_bytes(), 16);
+
+        // queue wait is only recorded when reported
+        assert_eq!(counters.queue_wait(), Duration::ZERO);
+        counters.record_queue_wait(Duration::from_millis(10));
+        counters.record_queue_wait(Duration::from_millis(20));
+        assert_eq!(counters.queue_wait(), Duration::from_millis(30));
+    }
+}
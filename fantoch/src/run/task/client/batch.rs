@@ -1,13 +1,12 @@
-use crate::command::Command;
-use crate::id::{Rifl, ShardId};
+use crate::command::{Command, CommandBatch};
+use crate::id::ShardId;
 use crate::HashMap;
 use std::iter::FromIterator;
 use tokio::time::Instant;
 
 #[derive(Debug)]
 pub struct Batch {
-    cmd: Command,
-    rifls: Vec<Rifl>,
+    cmds: CommandBatch,
     deadline: Instant,
     // mapping from shard id to the number of times it was selected as the
     // target for the commands in this batch
@@ -16,10 +15,8 @@ pub struct Batch {
 
 impl Batch {
     pub fn new(target_shard: ShardId, cmd: Command, deadline: Instant) -> Self {
-        let rifl = cmd.rifl();
         Self {
-            cmd,
-            rifls: vec![rifl],
+            cmds: CommandBatch::new(cmd),
             deadline,
             target_shards: HashMap::from_iter(vec![(target_shard, 1)]),
         }
@@ -30,10 +27,7 @@ impl Batch {
         // command
         assert!(other.shards().any(|shard_id| shard_id == &target_shard));
 
-        let rifl = other.rifl();
-        self.cmd.merge(other);
-        // add this command's rifl to the list of rifls in this batch
-        self.rifls.push(rifl);
+        self.cmds.push(other);
         // update target shard counts
         let current_count = self.target_shards.entry(target_shard).or_default();
         *current_count += 1;
@@ -44,17 +38,17 @@ impl Batch {
     }
 
     #[cfg(test)]
-    pub fn rifls(&self) -> &Vec<Rifl> {
-        &self.rifls
+    pub fn rifls(&self) -> Vec<crate::id::Rifl> {
+        self.cmds.rifls().collect()
     }
 
     pub fn size(&self) -> usize {
-        self.rifls.len()
+        self.cmds.len()
     }
 
-    pub fn unpack(self) -> (ShardId, Command, Vec<Rifl>) {
+    pub fn unpack(self) -> (ShardId, CommandBatch) {
         let target_shard = self.target_shard();
-        (target_shard, self.cmd, self.rifls)
+        (target_shard, self.cmds)
     }
 
     /// Computes the target shard as the shard most selected as the target
@@ -76,6 +70,7 @@ impl Batch {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::id::Rifl;
     use crate::kvs::{KVOp, Key};
 
     #[test]
@@ -111,22 +106,22 @@ mod tests {
         let cmd5 = create_command(rifl5, shard2, key_c.clone());
 
         let mut batch = Batch::new(shard1, cmd1, Instant::now());
-        assert_eq!(batch.rifls(), &vec![rifl1]);
+        assert_eq!(batch.rifls(), vec![rifl1]);
         assert_eq!(batch.size(), 1);
         assert_eq!(batch.target_shard(), shard1);
 
         batch.merge(shard1, cmd2);
-        assert_eq!(batch.rifls(), &vec![rifl1, rifl2]);
+        assert_eq!(batch.rifls(), vec![rifl1, rifl2]);
         assert_eq!(batch.size(), 2);
         assert_eq!(batch.target_shard(), shard1);
 
         batch.merge(shard2, cmd3);
-        assert_eq!(batch.rifls(), &vec![rifl1, rifl2, rifl3]);
+        assert_eq!(batch.rifls(), vec![rifl1, rifl2, rifl3]);
         assert_eq!(batch.size(), 3);
         assert_eq!(batch.target_shard(), shard1);
 
         batch.merge(shard2, cmd4);
-        assert_eq!(batch.rifls(), &vec![rifl1, rifl2, rifl3, rifl4]);
+        assert_eq!(batch.rifls(), vec![rifl1, rifl2, rifl3, rifl4]);
         assert_eq!(batch.size(), 4);
         // at this point the target shard can be either as both have the same
         // count
@@ -135,18 +130,19 @@ mod tests {
         );
 
         batch.merge(shard2, cmd5);
-        assert_eq!(batch.rifls(), &vec![rifl1, rifl2, rifl3, rifl4, rifl5]);
+        assert_eq!(batch.rifls(), vec![rifl1, rifl2, rifl3, rifl4, rifl5]);
         assert_eq!(batch.size(), 5);
         assert_eq!(batch.target_shard(), shard2);
 
         // check that the merge has occurred
-        assert_eq!(batch.cmd.shard_count(), 2);
-        assert_eq!(batch.cmd.key_count(shard1), 1);
-        assert_eq!(batch.cmd.key_count(shard2), 2);
-        let shard1_keys: Vec<_> = batch.cmd.keys(shard1).collect();
+        let cmd = batch.cmds.command();
+        assert_eq!(cmd.shard_count(), 2);
+        assert_eq!(cmd.key_count(shard1), 1);
+        assert_eq!(cmd.key_count(shard2), 2);
+        let shard1_keys: Vec<_> = cmd.keys(shard1).collect();
         assert_eq!(shard1_keys.len(), 1);
         assert!(shard1_keys.contains(&&key_a));
-        let shard2_keys: Vec<_> = batch.cmd.keys(shard2).collect();
+        let shard2_keys: Vec<_> = cmd.keys(shard2).collect();
         assert_eq!(shard2_keys.len(), 2);
         assert!(shard2_keys.contains(&&key_b));
         assert!(shard2_keys.contains(&&key_c));
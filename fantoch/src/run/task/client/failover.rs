@@ -0,0 +1,204 @@
+use super::Session;
+use crate::command::Command;
+use crate::hash_map::HashMap;
+use crate::id::{ClientId, Rifl, ShardId};
+use crate::run::prelude::*;
+use crate::{info, warn};
+use std::fmt::{Debug, Display};
+use tokio::net::ToSocketAddrs;
+use tokio::time::{Duration, Instant};
+
+// number of times the connection to a failover candidate is retried before
+// moving on to the next one
+const FAILOVER_CONNECT_RETRIES: usize = 3;
+
+/// Tracks the commands sent to a process whose results are still missing, so
+/// that, if the process fails, they can be submitted again (with the same
+/// `Rifl`) to another process of the same shard. A process is considered
+/// failed once its connection is closed or, if a `timeout` is set, once no
+/// message is received from it for `timeout` while some command is in flight.
+///
+/// Commands that were already executed by the failed process may be executed
+/// again, unless executors deduplicate commands (see
+/// `Config::set_executor_dedup_window`).
+pub struct Failover<A> {
+    // addresses of the processes to try (in order) if the current one fails
+    candidates: Vec<A>,
+    timeout: Option<Duration>,
+    tcp_nodelay: bool,
+    in_flight: HashMap<Rifl, Command>,
+    last_received: Instant,
+}
+
+impl<A> Failover<A>
+where
+    A: ToSocketAddrs + Clone + Debug + Display,
+{
+    pub fn new(
+        candidates: Vec<A>,
+        timeout: Option<Duration>,
+        tcp_nodelay: bool,
+    ) -> Self {
+        Self {
+            candidates,
+            timeout,
+            tcp_nodelay,
+            in_flight: HashMap::new(),
+            last_received: Instant::now(),
+        }
+    }
+
+    /// Records a message sent to the process. Commands are only tracked if
+    /// there's some process to fail over to.
+    pub fn sent(&mut self, msg: &ClientToServer) {
+        if self.candidates.is_empty() {
+            return;
+        }
+        let cmds = match msg {
+            ClientToServer::Submit(cmd) | ClientToServer::Register(cmd) => {
+                std::slice::from_ref(cmd)
+            }
+            ClientToServer::SubmitMany(cmds) => cmds.as_slice(),
+            _ => return,
+        };
+        // the process is only expected to answer once there's some command in
+        // flight
+        if self.in_flight.is_empty() && !cmds.is_empty() {
+            self.last_received = Instant::now();
+        }
+        for cmd in cmds {
+            self.in_flight.insert(cmd.rifl(), cmd.clone());
+        }
+    }
+
+    /// Records a message received from the process.
+    pub fn received(&mut self, msg: &ServerToClient) {
+        self.last_received = Instant::now();
+        match msg {
+            ServerToClient::Result(cmd_result) => {
+                self.in_flight.remove(&cmd_result.rifl());
+            }
+            // overloaded commands are submitted again by the client, and
+            // thus they're no longer in flight
+            ServerToClient::Overloaded(rifl) => {
+                self.in_flight.remove(rifl);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the instant at which the process is considered unresponsive,
+    /// if there's a timeout and some command in flight.
+    pub fn deadline(&self) -> Option<Instant> {
+        let timeout = self.timeout?;
+        if self.candidates.is_empty() || self.in_flight.is_empty() {
+            None
+        } else {
+            Some(self.last_received + timeout)
+        }
+    }
+
+    /// Connects to the next candidate process of shard `shard_id` and submits
+    /// to it the commands in flight. Returns `None` if there's no such
+    /// process.
+    pub async fn failover(
+        &mut self,
+        shard_id: ShardId,
+        client_ids: &[ClientId],
+    ) -> Option<Session> {
+        while !self.candidates.is_empty() {
+            let address = self.candidates.remove(0);
+            let connect = Session::connect(
+                address.clone(),
+                client_ids.to_vec(),
+                self.tcp_nodelay,
+                FAILOVER_CONNECT_RETRIES,
+            );
+            let mut session = match connect.await {
+                Ok(session) if session.shard_id() == shard_id => session,
+                Ok(_) => {
+                    // the process replicates another shard
+                    continue;
+                }
+                Err(e) => {
+                    warn!(
+                        "[client_rw] couldn't fail over to {}: {:?}",
+                        address, e
+                    );
+                    continue;
+                }
+            };
+            info!(
+                "[client_rw] failing over to process {} with {} commands in flight",
+                session.process_id(),
+                self.in_flight.len()
+            );
+
+            // submit the commands in flight (even those that were only
+            // registered, as the failed process may have already executed
+            // them and thus the new process wouldn't)
+            self.last_received = Instant::now();
+            for cmd in self.in_flight.values() {
+                if let Err(e) = session.submit(cmd.clone()).await {
+                    warn!(
+                        "[client_rw] error while resubmitting command: {:?}",
+                        e
+                    );
+                }
+            }
+            return Some(session);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::CommandResult;
+    use crate::kvs::KVOp;
+
+    #[test]
+    fn failover_in_flight() {
+        let timeout = Duration::from_secs(1);
+        let rifl = Rifl::new(1, 1);
+        let cmd = Command::from(rifl, vec![(String::from("A"), KVOp::Get)]);
+
+        // without candidates, commands are not tracked
+        let mut failover =
+            Failover::new(Vec::<String>::new(), Some(timeout), false);
+        failover.sent(&ClientToServer::Submit(cmd.clone()));
+        assert!(failover.deadline().is_none());
+
+        // with candidates, there's a deadline while commands are in flight
+        let candidates = vec![String::from("127.0.0.1:3000")];
+        let mut failover = Failover::new(candidates, Some(timeout), false);
+        assert!(failover.deadline().is_none());
+        failover.sent(&ClientToServer::Submit(cmd.clone()));
+        assert!(failover.deadline().is_some());
+
+        // once the command is overloaded, it's no longer in flight
+        failover.received(&ServerToClient::Overloaded(rifl));
+        assert!(failover.deadline().is_none());
+
+        // and the same once its result is received
+        failover.sent(&ClientToServer::Register(cmd.clone()));
+        assert!(failover.deadline().is_some());
+        let result = CommandResult::new(rifl, HashMap::new());
+        failover.received(&ServerToClient::Result(result));
+        assert!(failover.deadline().is_none());
+
+        // commands submitted together are in flight until all their results
+        // are received
+        let other_rifl = Rifl::new(1, 2);
+        let other =
+            Command::from(other_rifl, vec![(String::from("B"), KVOp::Get)]);
+        failover.sent(&ClientToServer::SubmitMany(vec![cmd, other]));
+        let result = CommandResult::new(rifl, HashMap::new());
+        failover.received(&ServerToClient::Result(result));
+        assert!(failover.deadline().is_some());
+        let result = CommandResult::new(other_rifl, HashMap::new());
+        failover.received(&ServerToClient::Result(result));
+        assert!(failover.deadline().is_none());
+    }
+}
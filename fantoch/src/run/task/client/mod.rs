@@ -13,54 +13,185 @@ mod batcher;
 // Implementation of an unbatcher.
 mod unbatcher;
 
-use crate::client::{Client, ClientData, Workload};
-use crate::command::{Command, CommandResult};
+// Implementation of `Session`.
+mod session;
+
+// Implementation of `Failover`.
+mod failover;
+
+// Implementation of `ShardWriters`.
+mod writers;
+
+// Re-exports.
+pub use session::Session;
+
+use crate::client::{
+    Client, ClientData, LoadRamp, MetricsReport, Trace, Workload,
+};
+use crate::command::{Command, ExecutionTimestamps};
 use crate::hash_map::HashMap;
 use crate::id::{ClientId, ProcessId, Rifl, ShardId};
+use crate::kvs::{Key, WatchNotification};
 use crate::run::chan::{self, ChannelReceiver, ChannelSender};
 use crate::run::prelude::*;
-use crate::run::rw::Connection;
 use crate::run::task;
 use crate::time::{RunTime, SysTime};
 use crate::HashSet;
 use crate::{info, trace, warn};
-use color_eyre::Report;
+use color_eyre::eyre::{Report, WrapErr};
 use futures::stream::{FuturesUnordered, StreamExt};
-use std::fmt::Debug;
+use pending::BatchOutcome;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Display};
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
+use tokio::time::Instant;
+use writers::ShardWriters;
+
+/// Options of the clients run by `client`.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Maximum number of connections to each process.
+    pub connections: usize,
+    /// Number of tasks driving the clients (at most `connections`).
+    pub client_tasks: usize,
+    /// If set, each client issues a command per `interval` (open-loop).
+    pub interval: Option<Duration>,
+    /// If set, the load offered by all clients follows this ramp.
+    pub load_ramp: Option<LoadRamp>,
+    /// Maximum number of commands merged into a single one.
+    pub batch_max_size: usize,
+    /// Maximum time a command waits for its batch to be full.
+    pub batch_max_delay: Duration,
+    /// Maximum number of commands submitted in the same frame.
+    pub submit_batch_max_size: usize,
+    /// Number of processes of each shard each command is submitted to.
+    pub submit_redundancy: usize,
+    /// If set, time after which clients fail over from a process that
+    /// hasn't replied.
+    pub failover_timeout: Option<Duration>,
+    /// If set, time after which commands without a result are resubmitted.
+    pub cmd_timeout: Option<Duration>,
+    /// Number of times a command is resubmitted before clients give up.
+    pub cmd_retries: usize,
+    /// Whether connections are set with TCP_NODELAY.
+    pub tcp_nodelay: bool,
+    /// Size of the channels between the client tasks.
+    pub channel_buffer_size: usize,
+    /// If set, clients log their status every `status_frequency` commands.
+    pub status_frequency: Option<usize>,
+    /// If set, file to which the (compressed) client data is written.
+    pub metrics_file: Option<String>,
+    /// If set, file to which a JSON summary of the metrics is written.
+    pub metrics_report_file: Option<String>,
+    /// Tags of the metrics summary.
+    pub metrics_tags: BTreeMap<String, String>,
+    /// If set, file to which the commands issued are written.
+    pub trace_file: Option<String>,
+}
 
-const MAX_CLIENT_CONNECTIONS: usize = 32;
-
+/// Runs clients `ids`, configured with `options` (see `ClientOptions`), which
+/// share at most `connections` connections to each of the processes they use
+/// (each client being a session of one of them).
+/// Clients are driven by `client_tasks` tasks (at most `connections`), each
+/// driving its clients as lightweight state machines over its share of the
+/// connections, and so running many clients doesn't require as many tasks.
+/// Clients use the first process in `addresses` of each shard, and fail over
+/// to the next ones if it fails (see `Failover`). If `cmd_timeout` is set,
+/// commands (i.e. batches) without a result after `cmd_timeout` are submitted
+/// again up to `cmd_retries` times, after which clients give up on them; each
+/// timeout is recorded in the client metrics. Clients replaying a trace (see
+/// `Workload::from_trace`) wait until the time each command was issued in the
+/// trace before issuing it. If `trace_file` is set, the commands issued by all
+/// clients are recorded there (see `Trace`). Clients are open-loop if
+/// `interval` is set (each client issuing a command per `interval`), or if
+/// `load_ramp` is set, in which case the load offered by all clients follows
+/// the ramp (see `LoadRamp`). If `metrics_report_file` is set, a summary of
+/// the metrics of all clients, tagged with `metrics_tags`, is written there as
+/// JSON (see `MetricsReport`). Clients can't read their own writes (see
+/// `Workload::set_read_your_writes`) with batching, since the writes in a
+/// batch are executed with the identifier of the batch.
+///
+/// Batching (up to `batch_max_size` commands) merges commands into a single
+/// one, which is executed by the protocol as any other command. Commands can
+/// also be submitted in the same frame (up to `submit_batch_max_size` of them,
+/// 1 meaning one command per frame), which only amortizes the cost of sending
+/// them: each is still executed (and answered) on its own. Commands are only
+/// sent in the same frame if they're ready to be sent at the same time, and
+/// thus this never delays a command.
+///
+/// Commands are submitted to the first `submit_redundancy` processes in
+/// `addresses` of each shard (e.g. the closest f + 1), and clients take the
+/// first result of each. Since each of these processes submits the command,
+/// it's executed more than once, and so processes should deduplicate it (see
+/// `Config::set_executor_dedup_window`).
 pub async fn client<A>(
     ids: Vec<ClientId>,
     addresses: Vec<A>,
-    interval: Option<Duration>,
     workload: Workload,
-    batch_max_size: usize,
-    batch_max_delay: Duration,
+    options: ClientOptions,
     connect_retries: usize,
-    tcp_nodelay: bool,
-    channel_buffer_size: usize,
-    status_frequency: Option<usize>,
-    metrics_file: Option<String>,
 ) -> Result<(), Report>
 where
-    A: ToSocketAddrs + Clone + Debug + Send + 'static + Sync,
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
 {
-    // create client pool
-    let mut pool = Vec::with_capacity(MAX_CLIENT_CONNECTIONS);
+    let ClientOptions {
+        connections,
+        client_tasks,
+        interval,
+        load_ramp,
+        submit_redundancy,
+        batch_max_size,
+        ..
+    } = options;
+    assert!(connections > 0, "clients need at least one connection");
+    assert!(
+        client_tasks > 0 && client_tasks <= connections,
+        "clients need at least one task, and at most one per connection"
+    );
+    assert!(
+        submit_redundancy > 0,
+        "commands should be submitted to at least one process"
+    );
+    assert!(
+        batch_max_size == 1 || !workload.read_your_writes(),
+        "clients can't read their own writes with batching"
+    );
+
+    // record the commands issued if a trace file was provided
+    let mut workload = workload;
+    workload.set_record_trace(options.trace_file.is_some());
+
+    // if there's a load ramp, clients start with the interval of its first
+    // step
+    let client_count = ids.len();
+    let interval = load_ramp
+        .map(|load_ramp| load_ramp.interval(0, client_count))
+        .or(interval);
+    if let Some(load_ramp) = load_ramp {
+        info!("load ramp: {:?}", load_ramp);
+    }
+
+    // create client pool: each entry has the number of connections (to each
+    // process) of the client worker, and the clients assigned to it
+    let mut pool = Vec::with_capacity(client_tasks);
     // init each entry
-    pool.resize_with(MAX_CLIENT_CONNECTIONS, Vec::new);
+    pool.resize_with(client_tasks, || (0, Vec::new()));
+
+    // split the connections evenly among client workers
+    (0..connections).for_each(|index| {
+        let index = index % client_tasks;
+        pool[index].0 += 1;
+    });
 
     // assign each client to a client worker
     ids.into_iter().enumerate().for_each(|(index, client_id)| {
-        let index = index % MAX_CLIENT_CONNECTIONS;
-        pool[index].push(client_id);
+        let index = index % client_tasks;
+        pool[index].1.push(client_id);
     });
 
     // start each client worker in pool
-    let handles = pool.into_iter().filter_map(|client_ids| {
+    let handles = pool.into_iter().filter_map(|(connections, client_ids)| {
         // only start a client for this pool index if any client id was assigned
         // to it
         if !client_ids.is_empty() {
@@ -69,26 +200,22 @@ where
                 task::spawn(open_loop_client::<A>(
                     client_ids,
                     addresses.clone(),
+                    connections,
                     interval,
-                    workload,
-                    batch_max_size,
-                    batch_max_delay,
+                    load_ramp,
+                    client_count,
+                    workload.clone(),
+                    options.clone(),
                     connect_retries,
-                    tcp_nodelay,
-                    channel_buffer_size,
-                    status_frequency,
                 ))
             } else {
                 task::spawn(closed_loop_client::<A>(
                     client_ids,
                     addresses.clone(),
-                    workload,
-                    batch_max_size,
-                    batch_max_delay,
+                    connections,
+                    workload.clone(),
+                    options.clone(),
                     connect_retries,
-                    tcp_nodelay,
-                    channel_buffer_size,
-                    status_frequency,
                 ))
             };
             Some(handle)
@@ -97,8 +224,10 @@ where
         }
     });
 
-    // wait for all clients to complete and aggregate their metrics
+    // wait for all clients to complete and aggregate their metrics (and the
+    // commands they issued, if recorded)
     let mut data = ClientData::new();
+    let mut trace = Vec::new();
 
     let mut handles = handles.collect::<FuturesUnordered<_>>();
     while let Some(join_result) = handles.next().await {
@@ -106,32 +235,116 @@ where
         for client in clients {
             info!("client {} ended", client.id());
             data.merge(client.data());
+            trace.extend(client.trace().iter().cloned());
             info!("metrics from {} collected", client.id());
         }
     }
 
-    if let Some(file) = metrics_file {
+    info!("latency: {:?}", data.latency_histogram());
+    for (op_type, histogram) in data.op_type_latency_histograms() {
+        info!("{} latency: {:?}", op_type, histogram);
+    }
+    for fast_path in [true, false] {
+        let path = if fast_path { "fast" } else { "slow" };
+        for (op_type, histogram) in data.path_latency_histograms(fast_path) {
+            info!("{} latency ({} path): {:?}", op_type, path, histogram);
+        }
+    }
+
+    if let Some(file) = options.metrics_file {
         info!("will write client data to {}", file);
         task::util::serialize_and_compress(&data, &file)?;
     }
 
+    if let Some(file) = options.metrics_report_file {
+        info!("will write metrics report to {}", file);
+        MetricsReport::new(&data, client_count, options.metrics_tags)
+            .write(&file)?;
+    }
+
+    if let Some(file) = options.trace_file {
+        info!("will write trace to {}", file);
+        Trace::new(trace).write(&file)?;
+    }
+
     info!("all clients ended");
     Ok(())
 }
 
+pub async fn watch<A>(
+    client_id: ClientId,
+    address: A,
+    keys: Vec<Key>,
+    connect_retries: usize,
+    tcp_nodelay: bool,
+    channel_buffer_size: usize,
+) -> Result<ChannelReceiver<WatchNotification>, Report>
+where
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
+{
+    // connect to the process
+    let mut session = Session::connect(
+        address,
+        vec![client_id],
+        tcp_nodelay,
+        connect_retries,
+    )
+    .await
+    .wrap_err_with(|| format!("watcher {} couldn't connect", client_id))?;
+
+    // watch each key
+    for key in keys {
+        session.watch(client_id, key).await?;
+    }
+
+    // create channel where notifications will be written
+    let (mut notifications_tx, notifications_rx) =
+        chan::channel(channel_buffer_size);
+    notifications_tx.set_name(format!("watcher_{}", client_id));
+
+    task::spawn(watcher_task(session, notifications_tx));
+    Ok(notifications_rx)
+}
+
+async fn watcher_task(
+    mut session: Session,
+    mut to_parent: ChannelSender<WatchNotification>,
+) {
+    loop {
+        match session.recv().await {
+            Some(ServerToClient::Notification(_, notification)) => {
+                trace!("[watcher] notified: {:?}", notification);
+                if to_parent.send(notification).await.is_err() {
+                    // the parent is no longer interested in notifications,
+                    // and so we can exit the loop (which closes the
+                    // connection, and thus removes the watches)
+                    break;
+                }
+            }
+            Some(ServerToClient::Error(e)) => {
+                warn!("[watcher] error from server: {}", e);
+            }
+            Some(msg) => {
+                warn!("[watcher] unexpected message: {:?}", msg);
+            }
+            None => {
+                warn!("[watcher] error while receiving message from server");
+                break;
+            }
+        }
+    }
+}
+
 async fn closed_loop_client<A>(
     client_ids: Vec<ClientId>,
     addresses: Vec<A>,
+    connections: usize,
     workload: Workload,
-    batch_max_size: usize,
-    batch_max_delay: Duration,
+    options: ClientOptions,
     connect_retries: usize,
-    tcp_nodelay: bool,
-    channel_buffer_size: usize,
-    status_frequency: Option<usize>,
 ) -> Option<Vec<Client>>
 where
-    A: ToSocketAddrs + Clone + Debug + Send + 'static + Sync,
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
 {
     // create system time
     let time = RunTime;
@@ -140,13 +353,10 @@ where
     let (mut clients, mut unbatcher_rx, mut batcher_tx) = client_setup(
         client_ids,
         addresses,
+        connections,
         workload,
-        batch_max_size,
-        batch_max_delay,
+        &options,
         connect_retries,
-        tcp_nodelay,
-        channel_buffer_size,
-        status_frequency,
     )
     .await?;
 
@@ -154,26 +364,52 @@ where
     let mut finished = HashSet::with_capacity(clients.len());
     // track which clients are workload finished
     let mut workload_finished = HashSet::with_capacity(clients.len());
+    // track which clients are waiting to issue their next command (as they
+    // replay a trace), ordered by when they should issue it
+    let mut delayed = BTreeSet::new();
 
     // generate the first message of each client
     for client in clients.values_mut() {
-        cmd_send(client, &time, &mut batcher_tx, &mut workload_finished).await;
+        cmd_send_or_delay(
+            client,
+            &time,
+            &mut batcher_tx,
+            &mut workload_finished,
+            &mut delayed,
+        )
+        .await;
     }
 
     // wait for results and generate/submit new commands while there are
     // commands to be generated
     while finished.len() < clients.len() {
-        // and wait for next result
-        let from_unbatcher = unbatcher_rx.recv().await;
-        let ready_clients =
-            cmd_recv(&mut clients, &time, from_unbatcher, &mut finished);
-        for client_id in ready_clients {
-            let client = clients
-                .get_mut(&client_id)
-                .expect("[client] ready client should exist");
-            // if client hasn't finished, issue a new command
-            cmd_send(client, &time, &mut batcher_tx, &mut workload_finished)
-                .await;
+        let next_delayed = delayed.iter().next().map(|(deadline, _)| *deadline);
+        tokio::select! {
+            from_unbatcher = unbatcher_rx.recv() => {
+                let ready_clients =
+                    cmd_recv(&mut clients, &time, from_unbatcher, &mut finished);
+                for client_id in ready_clients {
+                    let client = clients
+                        .get_mut(&client_id)
+                        .expect("[client] ready client should exist");
+                    // if client hasn't finished, issue a new command
+                    cmd_send_or_delay(client, &time, &mut batcher_tx, &mut workload_finished, &mut delayed).await;
+                }
+            }
+            _ = tokio::time::sleep_until(next_delayed.unwrap_or_else(Instant::now)), if next_delayed.is_some() => {
+                // issue a new command for each client whose wait is over
+                let now = Instant::now();
+                while let Some(&(deadline, client_id)) = delayed.iter().next() {
+                    if deadline > now {
+                        break;
+                    }
+                    delayed.remove(&(deadline, client_id));
+                    let client = clients
+                        .get_mut(&client_id)
+                        .expect("[client] delayed client should exist");
+                    cmd_send(client, &time, &mut batcher_tx, &mut workload_finished).await;
+                }
+            }
         }
     }
     assert_eq!(workload_finished.len(), finished.len());
@@ -190,17 +426,16 @@ where
 async fn open_loop_client<A>(
     client_ids: Vec<ClientId>,
     addresses: Vec<A>,
+    connections: usize,
     interval: Duration,
+    load_ramp: Option<LoadRamp>,
+    client_count: usize,
     workload: Workload,
-    batch_max_size: usize,
-    batch_max_delay: Duration,
+    options: ClientOptions,
     connect_retries: usize,
-    tcp_nodelay: bool,
-    channel_buffer_size: usize,
-    status_frequency: Option<usize>,
 ) -> Option<Vec<Client>>
 where
-    A: ToSocketAddrs + Clone + Debug + Send + 'static + Sync,
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
 {
     // create system time
     let time = RunTime;
@@ -209,19 +444,32 @@ where
     let (mut clients, mut unbatcher_rx, mut batcher_tx) = client_setup(
         client_ids,
         addresses,
+        connections,
         workload,
-        batch_max_size,
-        batch_max_delay,
+        &options,
         connect_retries,
-        tcp_nodelay,
-        channel_buffer_size,
-        status_frequency,
     )
     .await?;
 
     // create interval
     let mut interval = tokio::time::interval(interval);
 
+    // if there's a load ramp, track its current step and when the load
+    // increases next; the interval is adjusted at each step so that all
+    // `client_count` clients offer the load of the step
+    let ramp_start = Instant::now();
+    let mut ramp_step = 0;
+    let mut next_ramp_step = load_ramp
+        .filter(|load_ramp| load_ramp.increases_after(ramp_step))
+        .map(|load_ramp| ramp_start + load_ramp.step_window(ramp_step).1);
+    if load_ramp.is_some() {
+        info!(
+            "[client] load ramp of {} clients started at {}",
+            clients.len(),
+            time.millis()
+        );
+    }
+
     // track which clients are finished (i.e. all their commands have completed)
     let mut finished = HashSet::with_capacity(clients.len());
     // track which clients are workload finished
@@ -242,11 +490,27 @@ where
                 // (if there are still commands to be generated)
                 for (client_id, client) in clients.iter_mut(){
                     // if the client hasn't finished, try to issue a new command
-                    if !workload_finished.contains(client_id) {
+                    // (unless it replays a trace and should wait to issue it)
+                    if !workload_finished.contains(client_id) && client.next_cmd_delay(&time).is_none() {
                         cmd_send(client, &time, &mut batcher_tx, &mut workload_finished).await;
+                        // record right away if the client has issued its last
+                        // command, as its results may arrive before the next
+                        // tick
+                        if client.workload_finished() {
+                            workload_finished.insert(*client_id);
+                        }
                     }
                 }
             }
+            _ = tokio::time::sleep_until(next_ramp_step.unwrap_or_else(Instant::now)), if next_ramp_step.is_some() => {
+                let load_ramp = load_ramp.expect("[client] there should be a load ramp");
+                ramp_step += 1;
+                interval = tokio::time::interval(load_ramp.interval(ramp_step, client_count));
+                next_ramp_step = load_ramp
+                    .increases_after(ramp_step)
+                    .then(|| ramp_start + load_ramp.step_window(ramp_step).1);
+                trace!("[client] load ramp at step {}: {} ops/s", ramp_step, load_ramp.load(ramp_step));
+            }
         }
     }
     assert_eq!(workload_finished.len(), finished.len());
@@ -263,84 +527,105 @@ where
 async fn client_setup<A>(
     client_ids: Vec<ClientId>,
     addresses: Vec<A>,
+    connections: usize,
     workload: Workload,
-    batch_max_size: usize,
-    batch_max_delay: Duration,
+    options: &ClientOptions,
     client_retries: usize,
-    tcp_nodelay: bool,
-    channel_buffer_size: usize,
-    status_frequency: Option<usize>,
 ) -> Option<(
     HashMap<ClientId, Client>,
-    ChannelReceiver<Vec<Rifl>>,
+    ChannelReceiver<BatchOutcome>,
     ChannelSender<(ShardId, Command)>,
 )>
 where
-    A: ToSocketAddrs + Clone + Debug + Send + 'static + Sync,
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
 {
-    let mut shard_to_process = HashMap::with_capacity(addresses.len());
-    let mut connections = Vec::with_capacity(addresses.len());
+    let ClientOptions {
+        batch_max_size,
+        batch_max_delay,
+        submit_batch_max_size,
+        submit_redundancy,
+        failover_timeout,
+        cmd_timeout,
+        cmd_retries,
+        tcp_nodelay,
+        channel_buffer_size,
+        status_frequency,
+        ..
+    } = *options;
+
+    // split clients into a group per connection: the clients of each group
+    // share a connection to each process
+    let mut groups = Vec::with_capacity(connections);
+    groups.resize_with(connections, Vec::new);
+    client_ids
+        .iter()
+        .enumerate()
+        .for_each(|(index, client_id)| {
+            groups[index % connections].push(*client_id);
+        });
+
+    // create server-to-client channel: although we keep one connection per
+    // process in each group, all rw tasks will write to the same channel; this
+    // means the client will read from a single channel (and potentially
+    // receive messages from any of the processes)
+    let (mut s2c_tx, read) = chan::channel(channel_buffer_size);
+    s2c_tx.set_name(format!(
+        "server_to_client_{}",
+        super::util::ids_repr(&client_ids)
+    ));
 
-    // connect to each address (one per shard)
-    let tcp_buffer_size = 0;
-    for address in addresses {
-        let connect = task::connect(
-            address,
-            tcp_nodelay,
-            tcp_buffer_size,
+    let mut shard_to_writer = ShardWriters::new();
+    for group_ids in groups.into_iter().filter(|ids| !ids.is_empty()) {
+        let (shard_to_processes, sessions, candidates) = connect(
+            &group_ids,
+            addresses.clone(),
+            workload.shard_count(),
+            submit_redundancy,
             client_retries,
+            tcp_nodelay,
+        )
+        .await;
+
+        // start client read-write tasks
+        let mut process_to_writer = rw::start_client_rw_tasks(
+            &group_ids,
+            channel_buffer_size,
+            s2c_tx.clone(),
+            sessions,
+            candidates,
+            failover_timeout,
+            tcp_nodelay,
+            submit_batch_max_size,
         );
-        let mut connection = match connect.await {
-            Ok(connection) => connection,
-            Err(e) => {
-                // TODO panicking here as not sure how to make error handling
-                // send + 'static (required by tokio::spawn) and
-                // still be able to use the ? operator
-                panic!(
-                    "[client] error connecting at clients {:?}: {:?}",
-                    client_ids, e
-                );
-            }
-        };
-
-        // say hi
-        let (process_id, shard_id) =
-            client_say_hi(client_ids.clone(), &mut connection).await?;
-
-        // update set of processes to be discovered by the client
-        assert!(shard_to_process.insert(shard_id, process_id).is_none(), "client shouldn't try to connect to the same shard more than once, only to the closest one");
 
-        // update list of connected processes
-        connections.push((process_id, connection));
+        // create mapping from shard id to client read-write tasks
+        let shard_to_writers = shard_to_processes
+            .into_iter()
+            .map(|(shard_id, process_ids)| {
+                let writers = process_ids
+                    .into_iter()
+                    .map(|process_id| {
+                        process_to_writer.remove(&process_id).expect(
+                            "a rw-task should exist for each process id",
+                        )
+                    })
+                    .collect();
+                (shard_id, writers)
+            })
+            .collect();
+        assert!(
+            process_to_writer.is_empty(),
+            "all rw-tasks should be associated with some shard"
+        );
+        shard_to_writer.add_group(&group_ids, shard_to_writers);
     }
 
-    // start client read-write task
-    let (read, mut process_to_writer) = rw::start_client_rw_tasks(
-        &client_ids,
-        channel_buffer_size,
-        connections,
-    );
-
-    // create mapping from shard id to client read-write task
-    let shard_to_write = shard_to_process
-        .into_iter()
-        .map(|(shard_id, process_id)| {
-            let writer = process_to_writer
-                .remove(&process_id)
-                .expect("a rw-task should exist for each process id");
-            (shard_id, writer)
-        })
-        .collect();
-    assert!(
-        process_to_writer.is_empty(),
-        "all rw-tasks should be associated with some shard"
-    );
-
     // create clients
     let clients = client_ids
         .iter()
         .map(|&client_id| {
-            let client = Client::new(client_id, workload, status_frequency);
+            let client =
+                Client::new(client_id, workload.clone(), status_frequency);
             // no need to discover as the `unbatcher` will do the job of
             // selecting the closest process
             (client_id, client)
@@ -354,22 +639,102 @@ where
         clients,
         channel_buffer_size,
         read,
-        shard_to_write,
+        shard_to_writer,
+        submit_redundancy,
+        cmd_timeout,
+        cmd_retries,
     )
     .await
 }
 
+/// Connects clients `client_ids` to the first `submit_redundancy` addresses of
+/// each shard. Returns the processes connected to in each shard (ordered by
+/// the position of their address), their sessions, and the remaining addresses
+/// (to fail over to).
+async fn connect<A>(
+    client_ids: &Vec<ClientId>,
+    addresses: Vec<A>,
+    shard_count: usize,
+    submit_redundancy: usize,
+    client_retries: usize,
+    tcp_nodelay: bool,
+) -> (HashMap<ShardId, Vec<ProcessId>>, Vec<Session>, Vec<A>)
+where
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
+{
+    // mapping from shard id to the processes of that shard to which commands
+    // are submitted (ordered by the position of their address)
+    let mut shard_to_processes: HashMap<ShardId, Vec<ProcessId>> =
+        HashMap::with_capacity(shard_count);
+    let mut sessions = Vec::with_capacity(shard_count * submit_redundancy);
+    // addresses to fail over to
+    let mut candidates = Vec::new();
+
+    // connect to the first `submit_redundancy` addresses of each shard
+    for address in addresses {
+        if sessions.len() == shard_count * submit_redundancy {
+            candidates.push(address);
+            continue;
+        }
+        let connect = Session::connect(
+            address.clone(),
+            client_ids.clone(),
+            tcp_nodelay,
+            client_retries,
+        );
+        let session = match connect.await {
+            Ok(session) => session,
+            Err(e) => {
+                warn!(
+                    "[client] error connecting at clients {:?} to {}: {:?}",
+                    client_ids, address, e
+                );
+                continue;
+            }
+        };
+
+        // if the shard already has enough processes, keep this one for
+        // failover
+        let processes =
+            shard_to_processes.entry(session.shard_id()).or_default();
+        if processes.len() == submit_redundancy {
+            candidates.push(address);
+            continue;
+        }
+
+        // update set of processes to be discovered by the client
+        processes.push(session.process_id());
+
+        // update list of connected processes
+        sessions.push(session);
+    }
+    if sessions.len() < shard_count * submit_redundancy {
+        // TODO panicking here as not sure how to make error handling
+        // send + 'static (required by tokio::spawn) and
+        // still be able to use the ? operator
+        panic!(
+            "[client] clients {:?} couldn't connect to {} processes of each shard",
+            client_ids, submit_redundancy
+        );
+    }
+
+    (shard_to_processes, sessions, candidates)
+}
+
 async fn spawn_batcher_and_unbatcher(
     client_ids: Vec<ClientId>,
     batch_max_size: usize,
     batch_max_delay: Duration,
     clients: HashMap<ClientId, Client>,
     channel_buffer_size: usize,
-    read: ChannelReceiver<CommandResult>,
-    shard_to_writer: HashMap<ShardId, ChannelSender<ClientToServer>>,
+    read: ChannelReceiver<ServerToClient>,
+    shard_to_writer: ShardWriters,
+    submit_redundancy: usize,
+    cmd_timeout: Option<Duration>,
+    cmd_retries: usize,
 ) -> Option<(
     HashMap<ClientId, Client>,
-    ChannelReceiver<Vec<Rifl>>,
+    ChannelReceiver<BatchOutcome>,
     ChannelSender<(ShardId, Command)>,
 )> {
     let (mut batcher_tx, batcher_rx) = chan::channel(channel_buffer_size);
@@ -399,6 +764,9 @@ async fn spawn_batcher_and_unbatcher(
         to_client_tx,
         read,
         shard_to_writer,
+        submit_redundancy,
+        cmd_timeout,
+        cmd_retries,
     ));
 
     // return clients and their means to communicate with the service
@@ -424,16 +792,39 @@ async fn cmd_send(
     }
 }
 
-/// Handles new ready rifls. Returns the client ids of clients with a new
-/// command finished.
+/// Generate the next command, unless the client replays a trace and should
+/// wait before issuing it, in which case the client is `delayed`.
+async fn cmd_send_or_delay(
+    client: &mut Client,
+    time: &dyn SysTime,
+    to_batcher: &mut ChannelSender<(ShardId, Command)>,
+    workload_finished: &mut HashSet<ClientId>,
+    delayed: &mut BTreeSet<(Instant, ClientId)>,
+) {
+    if let Some(delay) = client.next_cmd_delay(time) {
+        delayed.insert((task::util::deadline(delay), client.id()));
+    } else {
+        cmd_send(client, time, to_batcher, workload_finished).await;
+    }
+}
+
+/// Handles new ready (or timed out) rifls. Returns the client ids of clients
+/// with a new command finished.
 fn cmd_recv(
     clients: &mut HashMap<ClientId, Client>,
     time: &dyn SysTime,
-    from_unbatcher: Option<Vec<Rifl>>,
+    from_unbatcher: Option<BatchOutcome>,
     finished: &mut HashSet<ClientId>,
 ) -> Vec<ClientId> {
-    if let Some(rifls) = from_unbatcher {
-        do_cmd_recv(clients, time, rifls, finished)
+    if let Some(outcome) = from_unbatcher {
+        match outcome {
+            BatchOutcome::Result((rifls, timestamps)) => {
+                do_cmd_recv(clients, time, rifls, timestamps, finished)
+            }
+            BatchOutcome::Timeout(rifls, retried) => {
+                do_cmd_timeout(clients, time, rifls, retried, finished)
+            }
+        }
     } else {
         panic!("[client] error while receiving message from client read-write task");
     }
@@ -442,12 +833,13 @@ fn cmd_recv(
 fn do_cmd_recv(
     clients: &mut HashMap<ClientId, Client>,
     time: &dyn SysTime,
-    rifls: Vec<Rifl>,
+    rifls: Vec<(Rifl, bool)>,
+    timestamps: Option<ExecutionTimestamps>,
     finished: &mut HashSet<ClientId>,
 ) -> Vec<ClientId> {
     rifls
         .into_iter()
-        .map(move |rifl| {
+        .map(move |(rifl, failed)| {
             // find client that sent this command
             let client_id = rifl.source();
             let client = clients
@@ -455,7 +847,7 @@ fn do_cmd_recv(
                 .expect("[client] command result should belong to a client");
 
             // handle command results
-            client.cmd_recv(rifl, time);
+            client.cmd_recv(rifl, timestamps, failed, time);
 
             // check if client is finished
             if client.finished() {
@@ -468,32 +860,38 @@ fn do_cmd_recv(
         .collect()
 }
 
-async fn client_say_hi(
-    client_ids: Vec<ClientId>,
-    connection: &mut Connection,
-) -> Option<(ProcessId, ShardId)> {
-    trace!("[client] will say hi with ids {:?}", client_ids);
-    // say hi
-    let hi = ClientHi(client_ids.clone());
-    if let Err(e) = connection.send(&hi).await {
-        warn!("[client] error while sending hi: {:?}", e);
-    }
+fn do_cmd_timeout(
+    clients: &mut HashMap<ClientId, Client>,
+    time: &dyn SysTime,
+    rifls: Vec<Rifl>,
+    retried: bool,
+    finished: &mut HashSet<ClientId>,
+) -> Vec<ClientId> {
+    rifls
+        .into_iter()
+        .filter_map(move |rifl| {
+            // find client that sent this command
+            let client_id = rifl.source();
+            let client = clients
+                .get_mut(&client_id)
+                .expect("[client] timed out command should belong to a client");
 
-    // receive hi back
-    if let Some(ProcessHi {
-        process_id,
-        shard_id,
-    }) = connection.recv().await
-    {
-        trace!(
-            "[client] clients {:?} received hi from process {} with shard id {}",
-            client_ids,
-            process_id,
-            shard_id
-        );
-        Some((process_id, shard_id))
-    } else {
-        warn!("[client] clients {:?} couldn't receive process id from connected process", client_ids);
-        None
-    }
+            // handle command timeout
+            client.cmd_timeout(rifl, retried, time);
+
+            // if the command was submitted again, the client is still waiting
+            // for it
+            if retried {
+                return None;
+            }
+
+            // check if client is finished
+            if client.finished() {
+                // record that this client is finished
+                info!("client {:?} exited loop", client_id);
+                assert!(finished.insert(client_id));
+            }
+            Some(client_id)
+        })
+        .collect()
 }
@@ -1,48 +1,174 @@
-use crate::command::{Command, CommandResult};
+use crate::command::{
+    Command, CommandBatch, CommandResult, ExecutionTimestamps,
+};
 use crate::hash_map::{Entry, HashMap};
-use crate::id::Rifl;
+use crate::id::{Rifl, ShardId};
+use crate::run::task;
 use crate::trace;
+use crate::HashSet;
+use std::collections::VecDeque;
+use tokio::time::{Duration, Instant};
+
+// the rifls in a batch (each with a boolean indicating whether the command
+// failed), and the execution timestamps of the batch command (merged across
+// all the shards it accessed)
+pub type BatchResult = (Vec<(Rifl, bool)>, Option<ExecutionTimestamps>);
+
+// what the unbatcher reports to the client: either the result of a batch, or
+// that the deadline of a batch expired (along with the rifls in that batch and
+// whether the batch command was submitted again)
+#[derive(Debug)]
+pub enum BatchOutcome {
+    Result(BatchResult),
+    Timeout(Vec<Rifl>, bool),
+}
 
 struct Expected {
+    // shard to which the command was submitted
+    target_shard: ShardId,
     shard_count: usize,
     total_key_count: usize,
+    // number of times the command was submitted again after a timeout
+    retries: usize,
+    // number of processes that rejected the command since it was last
+    // submitted
+    rejections: usize,
 }
 
 pub struct ShardsPending {
     pending: HashMap<Rifl, (Expected, Vec<CommandResult>)>,
-    rifl_to_batch_rifls: HashMap<Rifl, Vec<Rifl>>,
+    rifl_to_batch: HashMap<Rifl, CommandBatch>,
+    // number of processes to which each command is submitted
+    submit_redundancy: usize,
+    // if set, commands are given this long to complete
+    cmd_timeout: Option<Duration>,
+    // deadline of each command, sorted by deadline (as all commands have the
+    // same timeout); entries of commands that are no longer pending are
+    // skipped
+    deadlines: VecDeque<(Instant, Rifl)>,
 }
 
 impl ShardsPending {
-    pub fn new() -> Self {
+    pub fn new(
+        cmd_timeout: Option<Duration>,
+        submit_redundancy: usize,
+    ) -> Self {
         Self {
             pending: Default::default(),
-            rifl_to_batch_rifls: Default::default(),
+            rifl_to_batch: Default::default(),
+            submit_redundancy,
+            cmd_timeout,
+            deadlines: Default::default(),
         }
     }
 
-    pub fn register(&mut self, cmd: &Command, batch_rifls: Vec<Rifl>) {
+    pub fn register(
+        &mut self,
+        cmd: &Command,
+        target_shard: ShardId,
+        batch: CommandBatch,
+    ) {
         let rifl = cmd.rifl();
         trace!("c{}: register {:?}", rifl.source(), rifl);
 
         // add command to pending
         let expected = Expected {
+            target_shard,
             shard_count: cmd.shard_count(),
             total_key_count: cmd.total_key_count(),
+            retries: 0,
+            rejections: 0,
         };
         let results = Vec::with_capacity(expected.shard_count);
         let res = self.pending.insert(rifl, (expected, results));
         assert!(res.is_none());
 
-        // update mapping rifl -> batch rifls
-        let res = self.rifl_to_batch_rifls.insert(rifl, batch_rifls);
+        // update mapping rifl -> batch
+        let res = self.rifl_to_batch.insert(rifl, batch);
         assert!(res.is_none());
+
+        // set the command deadline
+        if let Some(cmd_timeout) = self.cmd_timeout {
+            self.deadlines
+                .push_back((task::util::deadline(cmd_timeout), rifl));
+        }
+    }
+
+    // Returns the earliest deadline of a pending command (if any).
+    pub fn next_deadline(&mut self) -> Option<Instant> {
+        // skip the deadlines of commands that are no longer pending
+        while let Some((deadline, rifl)) = self.deadlines.front() {
+            if self.pending.contains_key(rifl) {
+                return Some(*deadline);
+            }
+            self.deadlines.pop_front();
+        }
+        None
+    }
+
+    // Handles the expiration of the earliest deadline. If the command was
+    // submitted again less than `cmd_retries` times, its deadline is renewed
+    // and it's returned (along with the shard to which it was submitted) so
+    // that it can be submitted again; otherwise, the command stops being
+    // pending. In both cases, the rifls in its batch are also returned.
+    pub fn timeout(
+        &mut self,
+        cmd_retries: usize,
+    ) -> Option<(Vec<Rifl>, Option<(ShardId, Command)>)> {
+        self.next_deadline()?;
+        let (_, rifl) = self.deadlines.pop_front().unwrap();
+        trace!("c{}: {:?} timed out", rifl.source(), rifl);
+
+        let (expected, _) = self
+            .pending
+            .get_mut(&rifl)
+            .expect("command with a deadline should be pending");
+        let batch = self
+            .rifl_to_batch
+            .get(&rifl)
+            .expect("each rifl should be mapped to their batch");
+        let batch_rifls = batch.rifls().collect();
+
+        let resubmit = if expected.retries < cmd_retries {
+            expected.retries += 1;
+            expected.rejections = 0;
+            // renew the deadline (which is now the latest one)
+            let cmd_timeout =
+                self.cmd_timeout.expect("commands should have a timeout");
+            self.deadlines
+                .push_back((task::util::deadline(cmd_timeout), rifl));
+            Some((expected.target_shard, batch.command().clone()))
+        } else {
+            self.pending.remove(&rifl);
+            self.rifl_to_batch.remove(&rifl);
+            None
+        };
+        Some((batch_rifls, resubmit))
+    }
+
+    // Records that the command with rifl `rifl` was rejected by an overloaded
+    // process. Once it's rejected by all the processes to which it was
+    // submitted, it's returned (along with the shard to which it was
+    // submitted) so that it can be submitted again. Rejections of commands
+    // that are no longer pending are ignored.
+    pub fn rejected(&mut self, rifl: &Rifl) -> Option<(ShardId, &Command)> {
+        let (expected, _) = self.pending.get_mut(rifl)?;
+        expected.rejections += 1;
+        if expected.rejections < self.submit_redundancy {
+            return None;
+        }
+        expected.rejections = 0;
+        let batch = self.rifl_to_batch.get(rifl)?;
+        Some((expected.target_shard, batch.command()))
     }
 
     // Add new `CommandResult`.
     // If some command got the `CommandResult`s from each of the shards
-    // accessed, then return all the `Rifl`s in that batch.
-    pub fn add(&mut self, result: CommandResult) -> Option<Vec<Rifl>> {
+    // accessed, then return all the `Rifl`s in that batch (along with whether
+    // each command failed and the execution timestamps of the batch).
+    // Results of commands that are no longer pending (e.g. because they timed
+    // out) are ignored.
+    pub fn add(&mut self, result: CommandResult) -> Option<BatchResult> {
         let rifl = result.rifl();
         trace!("c{}: received {:?}", rifl.source(), rifl);
 
@@ -50,6 +176,21 @@ impl ShardsPending {
         match self.pending.entry(rifl) {
             Entry::Occupied(mut entry) => {
                 let (expected, results) = entry.get_mut();
+                // ignore results for keys that already got a result (which
+                // happens if a command submitted again after a timeout ends
+                // up being executed twice, or if it's submitted to more than
+                // one process)
+                let duplicate = results.iter().any(|cmd_result| {
+                    cmd_result
+                        .results()
+                        .keys()
+                        .any(|key| result.results().contains_key(key))
+                });
+                if duplicate {
+                    trace!("c{}: duplicate result {:?}", rifl.source(), rifl);
+                    return None;
+                }
+
                 // add new result
                 results.push(result);
 
@@ -73,22 +214,43 @@ impl ShardsPending {
                         .sum();
                     assert_eq!(results_key_count, expected.total_key_count);
 
+                    // merge the execution timestamps reported by each shard
+                    let timestamps = results
+                        .iter()
+                        .filter_map(|cmd_result| cmd_result.timestamps())
+                        .reduce(|mut a, b| {
+                            a.merge(b);
+                            a
+                        });
+
                     // remove command from pending
-                    entry.remove();
-
-                    // return batch rifls associated with this rifl
-                    let batch_rifls =
-                        self.rifl_to_batch_rifls.remove(&rifl).expect(
-                            "each rifl should be mapped to their batch rifls",
-                        );
-                    Some(batch_rifls)
+                    let (_, results) = entry.remove();
+
+                    // split the results into the results of each command in
+                    // the batch to find which commands failed
+                    let batch = self
+                        .rifl_to_batch
+                        .remove(&rifl)
+                        .expect("each rifl should be mapped to their batch");
+                    let failed: HashSet<_> = results
+                        .into_iter()
+                        .flat_map(|cmd_result| batch.unbatch(cmd_result))
+                        .filter(|cmd_result| cmd_result.has_errors())
+                        .map(|cmd_result| cmd_result.rifl())
+                        .collect();
+                    let batch_rifls = batch
+                        .rifls()
+                        .map(|rifl| (rifl, failed.contains(&rifl)))
+                        .collect();
+                    Some((batch_rifls, timestamps))
                 } else {
                     None
                 }
             }
-            Entry::Vacant(_) => panic!(
-                "received command result about a rifl we didn't register for"
-            ),
+            Entry::Vacant(_) => {
+                trace!("c{}: late result {:?}", rifl.source(), rifl);
+                None
+            }
         }
     }
 }
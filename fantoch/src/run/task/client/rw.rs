@@ -1,32 +1,36 @@
-use crate::command::CommandResult;
+use super::failover::Failover;
+use super::Session;
 use crate::hash_map::HashMap;
 use crate::id::{ClientId, ProcessId};
-use crate::run::chan::{self, ChannelReceiver, ChannelSender};
+use crate::run::chan::{self, ChannelSender};
 use crate::run::prelude::*;
-use crate::run::rw::Connection;
 use crate::run::task;
 use crate::{trace, warn};
+use std::fmt::{Debug, Display};
+use tokio::net::ToSocketAddrs;
+use tokio::time::{self, Duration};
 
-pub fn start_client_rw_tasks(
+/// Starts a read-write task per session, all of them writing the messages
+/// received from their process to `s2c_tx`. Each task coalesces the commands
+/// submitted while it's busy (up to `submit_batch_max_size` of them) into a
+/// single `ClientToServer::SubmitMany`, so that these are sent in a single
+/// frame.
+pub fn start_client_rw_tasks<A>(
     client_ids: &Vec<ClientId>,
     channel_buffer_size: usize,
-    connections: Vec<(ProcessId, Connection)>,
-) -> (
-    ChannelReceiver<CommandResult>,
-    HashMap<ProcessId, ChannelSender<ClientToServer>>,
-) {
-    // create server-to-client channels: although we keep one connection per
-    // shard, we'll have all rw tasks will write to the same channel; this means
-    // the client will read from a single channel (and potentially receive
-    // messages from any of the shards)
-    let (mut s2c_tx, s2c_rx) = chan::channel(channel_buffer_size);
-    s2c_tx.set_name(format!(
-        "server_to_client_{}",
-        task::util::ids_repr(&client_ids)
-    ));
-
-    let mut process_to_tx = HashMap::with_capacity(connections.len());
-    for (process_id, connection) in connections {
+    s2c_tx: ChannelSender<ServerToClient>,
+    sessions: Vec<Session>,
+    candidates: Vec<A>,
+    failover_timeout: Option<Duration>,
+    tcp_nodelay: bool,
+    submit_batch_max_size: usize,
+) -> HashMap<ProcessId, ChannelSender<ClientToServer>>
+where
+    A: ToSocketAddrs + Clone + Debug + Display + Send + 'static + Sync,
+{
+    let mut process_to_tx = HashMap::with_capacity(sessions.len());
+    for session in sessions {
+        let process_id = session.process_id();
         // create client-to-server channels: since clients may send operations
         // to different shards, we create one client-to-rw channel per rw task
         let (mut c2s_tx, c2s_rx) = chan::channel(channel_buffer_size);
@@ -37,42 +41,184 @@ pub fn start_client_rw_tasks(
         ));
 
         // spawn rw task
-        task::spawn(client_rw_task(connection, s2c_tx.clone(), c2s_rx));
+        let failover =
+            Failover::new(candidates.clone(), failover_timeout, tcp_nodelay);
+        task::spawn(client_rw_task(
+            session,
+            client_ids.clone(),
+            failover,
+            submit_batch_max_size,
+            s2c_tx.clone(),
+            c2s_rx,
+        ));
         process_to_tx.insert(process_id, c2s_tx);
     }
-    (s2c_rx, process_to_tx)
+    process_to_tx
 }
 
-async fn client_rw_task(
-    mut connection: Connection,
+async fn client_rw_task<A>(
+    mut session: Session,
+    client_ids: Vec<ClientId>,
+    mut failover: Failover<A>,
+    submit_batch_max_size: usize,
     mut to_parent: ServerToClientSender,
     mut from_parent: ClientToServerReceiver,
-) {
+) where
+    A: ToSocketAddrs + Clone + Debug + Display,
+{
     loop {
-        tokio::select! {
-            to_client = connection.recv() => {
+        let deadline = failover.deadline();
+        // whether the process failed (and thus the client should fail over to
+        // another process of the same shard)
+        let failed = tokio::select! {
+            to_client = session.recv() => {
                 trace!("[client_rw] to client: {:?}", to_client);
                 if let Some(to_client) = to_client {
+                    failover.received(&to_client);
                     if let Err(e) = to_parent.send(to_client).await {
                         warn!("[client_rw] error while sending message from server to parent: {:?}", e);
                     }
+                    false
                 } else {
-                    warn!("[client_rw] error while receiving message from server to parent");
-                    break;
+                    warn!("[client_rw] error while receiving message from process {}", session.process_id());
+                    true
                 }
             }
             to_server = from_parent.recv() => {
                 trace!("[client_rw] from client: {:?}", to_server);
                 if let Some(to_server) = to_server {
-                    if let Err(e) = connection.send(&to_server).await {
-                        warn!("[client_rw] error while sending message to server: {:?}", e);
+                    let mut failed = false;
+                    for to_server in coalesce_submits(to_server, &mut from_parent, submit_batch_max_size) {
+                        failover.sent(&to_server);
+                        if let Err(e) = session.send(&to_server).await {
+                            warn!("[client_rw] error while sending message to process {}: {:?}", session.process_id(), e);
+                            failed = true;
+                            break;
+                        }
                     }
+                    failed
                 } else {
                     warn!("[client_rw] error while receiving message from parent to server");
                     // in this case it means that the parent (the client) is done, and so we can exit the loop
                     break;
                 }
             }
+            _ = time::sleep_until(deadline.unwrap_or_else(time::Instant::now)), if deadline.is_some() => {
+                warn!("[client_rw] process {} is unresponsive", session.process_id());
+                true
+            }
+        };
+
+        if failed {
+            match failover.failover(session.shard_id(), &client_ids).await {
+                Some(new_session) => session = new_session,
+                None => {
+                    warn!(
+                        "[client_rw] no process of shard {} to fail over to",
+                        session.shard_id()
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Coalesces a `ClientToServer::Submit` with the ones already queued (up to
+// `submit_batch_max_size` commands). Returns the messages to be sent, in
+// order: the submitted commands, followed by the first queued message that's
+// not a submit (if any).
+fn coalesce_submits(
+    msg: ClientToServer,
+    from_parent: &mut ClientToServerReceiver,
+    submit_batch_max_size: usize,
+) -> Vec<ClientToServer> {
+    let mut cmds = match msg {
+        ClientToServer::Submit(cmd) if submit_batch_max_size > 1 => vec![cmd],
+        msg => return vec![msg],
+    };
+    let mut next = None;
+    while cmds.len() < submit_batch_max_size {
+        match from_parent.try_recv() {
+            Some(ClientToServer::Submit(cmd)) => cmds.push(cmd),
+            Some(msg) => {
+                next = Some(msg);
+                break;
+            }
+            None => break,
+        }
+    }
+    trace!("[client_rw] coalesced {} submits", cmds.len());
+    let submit = if cmds.len() == 1 {
+        ClientToServer::Submit(cmds.pop().unwrap())
+    } else {
+        ClientToServer::SubmitMany(cmds)
+    };
+    std::iter::once(submit).chain(next).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::id::Rifl;
+    use crate::kvs::KVOp;
+
+    fn submit(sequence: u64) -> ClientToServer {
+        let rifl = Rifl::new(1, sequence);
+        let cmd = Command::from(rifl, vec![(String::from("A"), KVOp::Get)]);
+        ClientToServer::Submit(cmd)
+    }
+
+    fn rifls(msg: &ClientToServer) -> Vec<u64> {
+        match msg {
+            ClientToServer::Submit(cmd) => vec![cmd.rifl().sequence()],
+            ClientToServer::SubmitMany(cmds) => {
+                cmds.iter().map(|cmd| cmd.rifl().sequence()).collect()
+            }
+            msg => panic!("unexpected message: {:?}", msg),
         }
     }
+
+    #[tokio::test]
+    async fn coalesce_submits_test() {
+        let (mut tx, mut rx) = chan::channel(10);
+
+        // without batching, submits are sent as they are
+        tx.send(submit(2)).await.unwrap();
+        let msgs = coalesce_submits(submit(1), &mut rx, 1);
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(rifls(&msgs[0]), vec![1]);
+
+        // with batching, queued submits are coalesced (up to the max size)
+        tx.send(submit(3)).await.unwrap();
+        tx.send(submit(4)).await.unwrap();
+        let msgs = coalesce_submits(rx.try_recv().unwrap(), &mut rx, 2);
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0], ClientToServer::SubmitMany(_)));
+        assert_eq!(rifls(&msgs[0]), vec![2, 3]);
+
+        // a single submit is not coalesced
+        let msgs = coalesce_submits(rx.try_recv().unwrap(), &mut rx, 2);
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0], ClientToServer::Submit(_)));
+        assert_eq!(rifls(&msgs[0]), vec![4]);
+
+        // coalescing stops at the first message that's not a submit, which is
+        // sent after the submits
+        tx.send(submit(6)).await.unwrap();
+        tx.send(ClientToServer::Close(vec![1])).await.unwrap();
+        tx.send(submit(7)).await.unwrap();
+        let msgs = coalesce_submits(submit(5), &mut rx, 10);
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(rifls(&msgs[0]), vec![5, 6]);
+        assert!(matches!(msgs[1], ClientToServer::Close(_)));
+        assert_eq!(rifls(&rx.try_recv().unwrap()), vec![7]);
+
+        // other messages are not coalesced
+        tx.send(submit(8)).await.unwrap();
+        let msgs = coalesce_submits(ClientToServer::Open(vec![2]), &mut rx, 10);
+        assert_eq!(msgs.len(), 1);
+        assert!(matches!(msgs[0], ClientToServer::Open(_)));
+    }
 }
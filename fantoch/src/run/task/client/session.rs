@@ -0,0 +1,157 @@
+use crate::command::Command;
+use crate::id::{ClientId, ProcessId, ShardId};
+use crate::kvs::Key;
+use crate::run::rw::Connection;
+use crate::run::task;
+use crate::run::wire::{self, ClientToServer, ServerToClient};
+use crate::trace;
+use color_eyre::eyre::{eyre, Report};
+use std::fmt::{Debug, Display};
+use tokio::net::ToSocketAddrs;
+
+/// Connection of one or more clients to a process, speaking the protocol
+/// specified in `run::wire`.
+#[derive(Debug)]
+pub struct Session {
+    process_id: ProcessId,
+    shard_id: ShardId,
+    connection: Connection,
+}
+
+impl Session {
+    /// Connects to the process at `address` (see `task::connect`) and says
+    /// hello on behalf of `client_ids`.
+    pub async fn connect<A>(
+        address: A,
+        client_ids: Vec<ClientId>,
+        tcp_nodelay: bool,
+        connect_retries: usize,
+    ) -> Result<Self, Report>
+    where
+        A: ToSocketAddrs + Clone + Debug + Display,
+    {
+        let tcp_buffer_size = 0;
+        let mut connection = task::connect(
+            address,
+            tcp_nodelay,
+            tcp_buffer_size,
+            connect_retries,
+        )
+        .await?;
+
+        trace!("[session] will say hello with ids {:?}", client_ids);
+        let hello = ClientToServer::Hello {
+            version: wire::VERSION,
+            client_ids: client_ids.clone(),
+        };
+        connection.send(&hello).await?;
+
+        // receive hello back
+        match connection.recv().await {
+            Some(ServerToClient::Hello {
+                process_id,
+                shard_id,
+            }) => {
+                trace!(
+                    "[session] clients {:?} received hello from process {} with shard id {}",
+                    client_ids,
+                    process_id,
+                    shard_id
+                );
+                Ok(Self {
+                    process_id,
+                    shard_id,
+                    connection,
+                })
+            }
+            Some(ServerToClient::Error(e)) => Err(eyre!(
+                "process rejected clients {:?}: {}",
+                client_ids,
+                e
+            )),
+            Some(msg) => Err(eyre!(
+                "clients {:?} received unexpected message instead of hello: {:?}",
+                client_ids,
+                msg
+            )),
+            None => Err(eyre!(
+                "clients {:?} couldn't receive hello from connected process",
+                client_ids
+            )),
+        }
+    }
+
+    pub fn process_id(&self) -> ProcessId {
+        self.process_id
+    }
+
+    pub fn shard_id(&self) -> ShardId {
+        self.shard_id
+    }
+
+    /// Submits a command. Commands that access more than one shard should be
+    /// registered in all the other shards first (see `Session::register`).
+    /// If the process is overloaded, the command is rejected with a
+    /// `ServerToClient::Overloaded`, and should be submitted again later.
+    pub async fn submit(&mut self, cmd: Command) -> Result<(), Report> {
+        self.send(&ClientToServer::Submit(cmd)).await
+    }
+
+    /// Submits several commands in a single message (see `run::wire`), as if
+    /// each was submitted with `Session::submit`.
+    pub async fn submit_many(
+        &mut self,
+        cmds: Vec<Command>,
+    ) -> Result<(), Report> {
+        self.send(&ClientToServer::SubmitMany(cmds)).await
+    }
+
+    /// Registers a command submitted to another shard, so that the result of
+    /// this shard is also sent to the client.
+    pub async fn register(&mut self, cmd: Command) -> Result<(), Report> {
+        self.send(&ClientToServer::Register(cmd)).await
+    }
+
+    /// Opens sessions for more clients on this connection (see `run::wire`).
+    pub async fn open(
+        &mut self,
+        client_ids: Vec<ClientId>,
+    ) -> Result<(), Report> {
+        self.send(&ClientToServer::Open(client_ids)).await
+    }
+
+    /// Closes the sessions of some clients on this connection. Sessions should
+    /// only be closed once all their commands have completed.
+    pub async fn close(
+        &mut self,
+        client_ids: Vec<ClientId>,
+    ) -> Result<(), Report> {
+        self.send(&ClientToServer::Close(client_ids)).await
+    }
+
+    pub async fn watch(
+        &mut self,
+        client_id: ClientId,
+        key: Key,
+    ) -> Result<(), Report> {
+        self.send(&ClientToServer::Watch(client_id, key)).await
+    }
+
+    pub async fn unwatch(
+        &mut self,
+        client_id: ClientId,
+        key: Key,
+    ) -> Result<(), Report> {
+        self.send(&ClientToServer::Unwatch(client_id, key)).await
+    }
+
+    pub async fn send(&mut self, msg: &ClientToServer) -> Result<(), Report> {
+        self.connection.send(msg).await
+    }
+
+    /// Receives the next message from the process, returning `None` once the
+    /// connection is closed.
+    pub async fn recv(&mut self) -> Option<ServerToClient> {
+        self.connection.recv().await
+    }
+}
@@ -1,23 +1,33 @@
 use super::batch::Batch;
-use super::pending::ShardsPending;
+use super::pending::{BatchOutcome, ShardsPending};
+use super::writers::ShardWriters;
 use crate::command::CommandResult;
 use crate::id::{Rifl, ShardId};
 use crate::run::chan::{ChannelReceiver, ChannelSender};
-use crate::run::prelude::ClientToServer;
-use crate::warn;
-use crate::HashMap;
+use crate::run::prelude::{ClientToServer, ServerToClient};
+use crate::run::task;
+use crate::{trace, warn};
 use color_eyre::eyre::{eyre, Report};
+use tokio::time::{self, Duration};
+
+// commands rejected by an overloaded process are submitted again after this
+// delay
+const OVERLOADED_RETRY_DELAY: Duration = Duration::from_millis(10);
 
 pub async fn unbatcher(
     mut from: ChannelReceiver<Batch>,
-    mut to: ChannelSender<Vec<Rifl>>,
-    mut read: ChannelReceiver<CommandResult>,
-    mut shard_to_writer: HashMap<ShardId, ChannelSender<ClientToServer>>,
+    mut to: ChannelSender<BatchOutcome>,
+    mut read: ChannelReceiver<ServerToClient>,
+    mut shard_to_writer: ShardWriters,
+    submit_redundancy: usize,
+    cmd_timeout: Option<Duration>,
+    cmd_retries: usize,
 ) {
     // create pending
-    let mut pending = ShardsPending::new();
+    let mut pending = ShardsPending::new(cmd_timeout, submit_redundancy);
 
     loop {
+        let deadline = pending.next_deadline();
         tokio::select! {
             from_batcher = from.recv() => {
                 let handle_from_batcher = handle_from_batcher(from_batcher, &mut shard_to_writer, &mut pending).await;
@@ -27,19 +37,22 @@ pub async fn unbatcher(
                 }
             }
             from_server = read.recv() => {
-                let handle_from_server = handle_from_server(from_server, &mut to, &mut pending).await;
+                let handle_from_server = handle_from_server(from_server, &mut to, &shard_to_writer, &mut pending).await;
                 if let Err(e) = handle_from_server {
                     warn!("[unbatcher] {:?}", e);
                     break;
                 }
             }
+            _ = time::sleep_until(deadline.unwrap_or_else(time::Instant::now)), if deadline.is_some() => {
+                handle_timeout(&mut to, &mut shard_to_writer, &mut pending, cmd_retries).await;
+            }
         }
     }
 }
 
 async fn handle_from_batcher(
     batch: Option<Batch>,
-    shard_to_writer: &mut HashMap<ShardId, ChannelSender<ClientToServer>>,
+    shard_to_writer: &mut ShardWriters,
     pending: &mut ShardsPending,
 ) -> Result<(), Report> {
     if let Some(batch) = batch {
@@ -52,51 +65,74 @@ async fn handle_from_batcher(
 
 async fn handle_batch(
     batch: Batch,
-    shard_to_writer: &mut HashMap<ShardId, ChannelSender<ClientToServer>>,
+    shard_to_writer: &mut ShardWriters,
     pending: &mut ShardsPending,
 ) {
     // extract info from batch
-    let (target_shard, cmd, rifls) = batch.unpack();
+    let (target_shard, batch) = batch.unpack();
+    let cmd = batch.command().clone();
 
     // register command in pending (which will aggregate several
     // `CommandResult`s if the command acesses more than one shard)
-    pending.register(&cmd, rifls);
+    pending.register(&cmd, target_shard, batch);
 
     // 1. register the command in all shards but the target shard
     for shard in cmd.shards().filter(|shard| **shard != target_shard) {
         let msg = ClientToServer::Register(cmd.clone());
-        send_to_shard(shard_to_writer, shard, msg).await
+        send_to_shard(shard_to_writer, cmd.rifl(), shard, msg).await
     }
 
     // 2. submit the command to the target shard
+    let rifl = cmd.rifl();
     let msg = ClientToServer::Submit(cmd);
-    send_to_shard(shard_to_writer, &target_shard, msg).await
+    send_to_shard(shard_to_writer, rifl, &target_shard, msg).await
 }
 
 async fn send_to_shard(
-    shard_to_writer: &mut HashMap<ShardId, ChannelSender<ClientToServer>>,
+    shard_to_writer: &mut ShardWriters,
+    rifl: Rifl,
     shard_id: &ShardId,
     msg: ClientToServer,
 ) {
-    // find process writer
-    let writer = shard_to_writer
-        .get_mut(shard_id)
-        .expect("[unbatcher] dind't find writer for target shard");
-    if let Err(e) = writer.send(msg).await {
-        warn!(
-            "[unbatcher] error while sending message to client rw task: {:?}",
-            e
-        );
+    // find process writers (one per process to which commands are submitted)
+    // of the client that issued the command
+    let writers = shard_to_writer.get_mut(rifl.source(), shard_id);
+    for writer in writers {
+        if let Err(e) = writer.send(msg.clone()).await {
+            warn!(
+                "[unbatcher] error while sending message to client rw task: {:?}",
+                e
+            );
+        }
     }
 }
 
 async fn handle_from_server(
-    cmd_result: Option<CommandResult>,
-    to: &mut ChannelSender<Vec<Rifl>>,
+    from_server: Option<ServerToClient>,
+    to: &mut ChannelSender<BatchOutcome>,
+    shard_to_writer: &ShardWriters,
     pending: &mut ShardsPending,
 ) -> Result<(), Report> {
-    if let Some(cmd_result) = cmd_result {
-        handle_cmd_result(cmd_result, to, pending).await;
+    if let Some(from_server) = from_server {
+        match from_server {
+            ServerToClient::Result(cmd_result) => {
+                handle_cmd_result(cmd_result, to, pending).await;
+            }
+            ServerToClient::Notification(..) => {
+                // workload clients don't watch keys, and thus should never be
+                // notified
+                warn!("[unbatcher] unexpected notification");
+            }
+            ServerToClient::Error(e) => {
+                warn!("[unbatcher] error from server: {}", e);
+            }
+            ServerToClient::Overloaded(rifl) => {
+                handle_overloaded(rifl, shard_to_writer, pending);
+            }
+            ServerToClient::Hello { .. } => {
+                warn!("[unbatcher] unexpected hello");
+            }
+        }
         Ok(())
     } else {
         Err(eyre!("error receiving message from parent"))
@@ -105,12 +141,65 @@ async fn handle_from_server(
 
 async fn handle_cmd_result(
     cmd_result: CommandResult,
-    to: &mut ChannelSender<Vec<Rifl>>,
+    to: &mut ChannelSender<BatchOutcome>,
+    pending: &mut ShardsPending,
+) {
+    if let Some(batch_result) = pending.add(cmd_result) {
+        if let Err(e) = to.send(BatchOutcome::Result(batch_result)).await {
+            warn!("[unbatcher] error while sending message to client: {:?}", e);
+        }
+    }
+}
+
+async fn handle_timeout(
+    to: &mut ChannelSender<BatchOutcome>,
+    shard_to_writer: &mut ShardWriters,
     pending: &mut ShardsPending,
+    cmd_retries: usize,
 ) {
-    if let Some(rifls) = pending.add(cmd_result) {
-        if let Err(e) = to.send(rifls).await {
+    if let Some((rifls, resubmit)) = pending.timeout(cmd_retries) {
+        let retried = resubmit.is_some();
+        if let Some((target_shard, cmd)) = resubmit {
+            trace!("[unbatcher] {:?} timed out: submitting it again", rifls);
+            // submit the command again (only to the target shard, as it's
+            // still registered in the other shards)
+            let rifl = cmd.rifl();
+            let msg = ClientToServer::Submit(cmd);
+            send_to_shard(shard_to_writer, rifl, &target_shard, msg).await;
+        } else {
+            warn!("[unbatcher] {:?} timed out: giving up on them", rifls);
+        }
+        let outcome = BatchOutcome::Timeout(rifls, retried);
+        if let Err(e) = to.send(outcome).await {
             warn!("[unbatcher] error while sending message to client: {:?}", e);
         }
     }
 }
+
+fn handle_overloaded(
+    rifl: Rifl,
+    shard_to_writer: &ShardWriters,
+    pending: &mut ShardsPending,
+) {
+    trace!("[unbatcher] {:?} rejected by overloaded process", rifl);
+    // once all the processes to which the command was submitted rejected it,
+    // submit it again (only to the target shard, as it's still registered in
+    // the other shards) after some delay
+    if let Some((target_shard, cmd)) = pending.rejected(&rifl) {
+        let mut writers = shard_to_writer
+            .get(cmd.rifl().source(), &target_shard)
+            .clone();
+        let msg = ClientToServer::Submit(cmd.clone());
+        task::spawn(async move {
+            time::sleep(OVERLOADED_RETRY_DELAY).await;
+            for writer in writers.iter_mut() {
+                if let Err(e) = writer.send(msg.clone()).await {
+                    warn!(
+                        "[unbatcher] error while resending message to client rw task: {:?}",
+                        e
+                    );
+                }
+            }
+        });
+    }
+}
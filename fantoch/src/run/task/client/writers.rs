@@ -0,0 +1,70 @@
+use crate::hash_map::HashMap;
+use crate::id::{ClientId, ShardId};
+use crate::run::chan::ChannelSender;
+use crate::run::prelude::ClientToServer;
+
+/// Writers of the connections shared by the clients driven by a task. Clients
+/// are split into groups, each group with its own connection to each of the
+/// processes used (on which its clients said hello). Since a process only
+/// sends the results of a client through the connection the client said hello
+/// on, the commands of a client are sent through the connections of its group.
+#[derive(Default)]
+pub struct ShardWriters {
+    // mapping from client id to the index of its group
+    client_to_group: HashMap<ClientId, usize>,
+    // mapping from shard id to the writers of the processes of that shard to
+    // which commands are submitted, for each group
+    groups: Vec<HashMap<ShardId, Vec<ChannelSender<ClientToServer>>>>,
+}
+
+impl ShardWriters {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a group of clients, along with the writers of their connections.
+    pub fn add_group(
+        &mut self,
+        client_ids: &[ClientId],
+        shard_to_writers: HashMap<ShardId, Vec<ChannelSender<ClientToServer>>>,
+    ) {
+        let group = self.groups.len();
+        for client_id in client_ids {
+            let res = self.client_to_group.insert(*client_id, group);
+            assert!(res.is_none(), "each client should be in a single group");
+        }
+        self.groups.push(shard_to_writers);
+    }
+
+    /// Returns the writers through which commands of client `client_id` are
+    /// sent to shard `shard_id`.
+    pub fn get(
+        &self,
+        client_id: ClientId,
+        shard_id: &ShardId,
+    ) -> &Vec<ChannelSender<ClientToServer>> {
+        let group = self.group(client_id);
+        self.groups[group]
+            .get(shard_id)
+            .expect("[unbatcher] dind't find writer for target shard")
+    }
+
+    /// Same as `get`, but returns the writers mutably.
+    pub fn get_mut(
+        &mut self,
+        client_id: ClientId,
+        shard_id: &ShardId,
+    ) -> &mut Vec<ChannelSender<ClientToServer>> {
+        let group = self.group(client_id);
+        self.groups[group]
+            .get_mut(shard_id)
+            .expect("[unbatcher] dind't find writer for target shard")
+    }
+
+    fn group(&self, client_id: ClientId) -> usize {
+        *self
+            .client_to_group
+            .get(&client_id)
+            .expect("[unbatcher] client should belong to a group")
+    }
+}
@@ -7,12 +7,17 @@ pub mod server;
 pub mod client;
 
 use crate::run::chan::{self, ChannelReceiver, ChannelSender};
-use crate::run::rw::Connection;
+use crate::run::rw::{Connection, UNIX_PREFIX};
 use crate::{info, trace, warn};
 use color_eyre::Report;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
 use tokio::task::JoinHandle;
 use tokio::time::Duration;
 
@@ -101,7 +106,8 @@ where
     (rx1, tx2)
 }
 
-/// Connect to some address.
+/// Connect to some address. Addresses of the form `unix://<path>` are connected
+/// to through a unix domain socket.
 pub async fn connect<A>(
     address: A,
     tcp_nodelay: bool,
@@ -109,14 +115,20 @@ pub async fn connect<A>(
     connect_retries: usize,
 ) -> Result<Connection, Report>
 where
-    A: ToSocketAddrs + Clone + Debug,
+    A: ToSocketAddrs + Clone + Debug + Display,
 {
+    let unix_path = unix_socket_path(&address);
     let mut tries = 0;
     loop {
-        match TcpStream::connect(address.clone()).await {
-            Ok(stream) => {
-                let connection =
-                    Connection::new(stream, tcp_nodelay, tcp_buffer_size);
+        let result = if let Some(path) = &unix_path {
+            connect_unix(path, tcp_buffer_size).await
+        } else {
+            TcpStream::connect(address.clone()).await.map(|stream| {
+                Connection::new(stream, tcp_nodelay, tcp_buffer_size)
+            })
+        };
+        match result {
+            Ok(connection) => {
                 return Ok(connection);
             }
             Err(e) => {
@@ -138,32 +150,125 @@ where
     }
 }
 
-/// Listen on some address.
-pub async fn listen<A>(address: A) -> Result<TcpListener, Report>
+/// Returns the path of the unix domain socket if `address` is of the form
+/// `unix://<path>`.
+fn unix_socket_path<A>(address: &A) -> Option<PathBuf>
+where
+    A: Display,
+{
+    address
+        .to_string()
+        .strip_prefix(UNIX_PREFIX)
+        .map(PathBuf::from)
+}
+
+#[cfg(unix)]
+async fn connect_unix(
+    path: &Path,
+    buffer_size: usize,
+) -> Result<Connection, io::Error> {
+    let stream = UnixStream::connect(path).await?;
+    let connection =
+        Connection::new_unix(stream, Some(path.to_path_buf()), buffer_size);
+    Ok(connection)
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(
+    _path: &Path,
+    _buffer_size: usize,
+) -> Result<Connection, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "unix domain sockets are not supported on this platform",
+    ))
+}
+
+/// Listens for connections on a TCP address and, optionally, on a unix domain
+/// socket.
+#[derive(Debug)]
+pub struct Listener {
+    tcp: TcpListener,
+    #[cfg(unix)]
+    unix: Option<UnixListener>,
+}
+
+impl Listener {
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.tcp.local_addr()
+    }
+
+    async fn accept(
+        &self,
+        tcp_nodelay: bool,
+        tcp_buffer_size: usize,
+    ) -> Result<Connection, io::Error> {
+        #[cfg(unix)]
+        if let Some(unix) = &self.unix {
+            return tokio::select! {
+                accepted = self.tcp.accept() => accepted.map(|(stream, _)| {
+                    Connection::new(stream, tcp_nodelay, tcp_buffer_size)
+                }),
+                accepted = unix.accept() => accepted.map(|(stream, _)| {
+                    Connection::new_unix(stream, None, tcp_buffer_size)
+                }),
+            };
+        }
+        let (stream, _) = self.tcp.accept().await?;
+        Ok(Connection::new(stream, tcp_nodelay, tcp_buffer_size))
+    }
+}
+
+/// Listen on some address and, if `unix_path` is set, on a unix domain socket
+/// at that path (a socket file left there by a previous run is removed).
+pub async fn listen<A>(
+    address: A,
+    unix_path: Option<PathBuf>,
+) -> Result<Listener, Report>
 where
     A: ToSocketAddrs,
 {
-    Ok(TcpListener::bind(address).await?)
+    let tcp = TcpListener::bind(address).await?;
+
+    #[cfg(unix)]
+    let unix = if let Some(path) = unix_path {
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        Some(UnixListener::bind(path)?)
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if unix_path.is_some() {
+        color_eyre::eyre::bail!(
+            "unix domain sockets are not supported on this platform"
+        );
+    }
+
+    Ok(Listener {
+        tcp,
+        #[cfg(unix)]
+        unix,
+    })
 }
 
-/// Listen on new connections and send them to parent process.
+/// Listen on new connections and send them to parent process (until the parent
+/// is gone).
 async fn listener_task(
-    listener: TcpListener,
+    listener: Listener,
     tcp_nodelay: bool,
     tcp_buffer_size: usize,
     mut parent: ChannelSender<Connection>,
 ) {
     loop {
-        match listener.accept().await {
-            Ok((stream, _addr)) => {
-                trace!("[listener] new connection: {:?}", _addr);
-
-                // create connection
-                let connection =
-                    Connection::new(stream, tcp_nodelay, tcp_buffer_size);
+        match listener.accept(tcp_nodelay, tcp_buffer_size).await {
+            Ok(connection) => {
+                trace!("[listener] new connection: {:?}", connection);
 
                 if let Err(e) = parent.send(connection).await {
                     warn!("[listener] error sending stream to parent process: {:?}", e);
+                    return;
                 }
             }
             Err(e) => {
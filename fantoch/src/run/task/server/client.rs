@@ -1,23 +1,33 @@
 use crate::command::Command;
-use crate::executor::{AggregatePending, ExecutorResult};
+use crate::executor::{AggregatePending, LocalRead};
 use crate::id::{AtomicDotGen, ClientId, ProcessId, ShardId};
 use crate::run::chan;
 use crate::run::prelude::*;
 use crate::run::rw::Connection;
 use crate::run::task;
+use crate::run::task::server::overload::Overload;
+use crate::run::task::server::rate_limit::RateLimiter;
+use crate::run::wire;
+use crate::HashSet;
 use crate::{info, trace, warn};
-use tokio::net::TcpListener;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinHandle;
 
 pub fn start_listener(
     process_id: ProcessId,
     shard_id: ShardId,
-    listener: TcpListener,
+    listener: task::Listener,
     atomic_dot_gen: Option<AtomicDotGen>,
     client_to_workers: ClientToWorkers,
     client_to_executors: ClientToExecutors,
+    store_index: StoreIndex,
+    overload: Arc<Overload>,
+    rate_limiter: Arc<RateLimiter>,
     tcp_nodelay: bool,
     client_channel_buffer_size: usize,
-) {
+    shutdown: ShutdownReceiver,
+) -> JoinHandle<()> {
     task::spawn(client_listener_task(
         process_id,
         shard_id,
@@ -25,22 +35,31 @@ pub fn start_listener(
         atomic_dot_gen,
         client_to_workers,
         client_to_executors,
+        store_index,
+        overload,
+        rate_limiter,
         tcp_nodelay,
         client_channel_buffer_size,
-    ));
+        shutdown,
+    ))
 }
 
 /// Listen on new client connections and spawn a client task for each new
-/// connection.
+/// connection. On shutdown, it stops accepting new connections and ends once
+/// all client tasks have ended.
 async fn client_listener_task(
     process_id: ProcessId,
     shard_id: ShardId,
-    listener: TcpListener,
+    listener: task::Listener,
     atomic_dot_gen: Option<AtomicDotGen>,
     client_to_workers: ClientToWorkers,
     client_to_executors: ClientToExecutors,
+    store_index: StoreIndex,
+    overload: Arc<Overload>,
+    rate_limiter: Arc<RateLimiter>,
     tcp_nodelay: bool,
     client_channel_buffer_size: usize,
+    mut shutdown: ShutdownReceiver,
 ) {
     // start listener task
     let tcp_buffer_size = 0;
@@ -48,42 +67,70 @@ async fn client_listener_task(
         task::listener_task(listener, tcp_nodelay, tcp_buffer_size, tx)
     });
 
+    // handles of all client tasks
+    let mut client_handles = Vec::new();
+
     loop {
-        // handle new client connections
-        match rx.recv().await {
-            Some(connection) => {
-                trace!("[client_listener] new connection");
-                // start client server task and give it the producer-end of the
-                // channel in order for this client to notify
-                // parent
-                task::spawn(client_server_task(
-                    process_id,
-                    shard_id,
-                    atomic_dot_gen.clone(),
-                    client_to_workers.clone(),
-                    client_to_executors.clone(),
-                    client_channel_buffer_size,
-                    connection,
-                ));
-            }
-            None => {
-                warn!(
-                    "[client_listener] error receiving message from listener"
-                );
+        tokio::select! {
+            // handle new client connections
+            connection = rx.recv() => match connection {
+                Some(connection) => {
+                    trace!("[client_listener] new connection");
+                    // start client server task and give it the producer-end of
+                    // the channel in order for this client to notify parent
+                    client_handles.push(task::spawn(client_server_task(
+                        process_id,
+                        shard_id,
+                        atomic_dot_gen.clone(),
+                        client_to_workers.clone(),
+                        client_to_executors.clone(),
+                        store_index,
+                        overload.clone(),
+                        rate_limiter.clone(),
+                        client_channel_buffer_size,
+                        shutdown.clone(),
+                        connection,
+                    )));
+                }
+                None => {
+                    warn!(
+                        "[client_listener] error receiving message from listener"
+                    );
+                }
+            },
+            _ = shutdown.changed() => {
+                break;
             }
         }
     }
+
+    // wait for all client tasks
+    info!(
+        "[client_listener] shutting down: waiting for {} client tasks",
+        client_handles.len()
+    );
+    for handle in client_handles {
+        if let Err(e) = handle.await {
+            warn!("[client_listener] error joining client task: {:?}", e);
+        }
+    }
 }
 
 /// Client server-side task. Checks messages both from the client connection
-/// (new commands) and parent (new command results).
+/// (new commands) and parent (new command results). On shutdown, it stops
+/// receiving new commands and ends once the results of the pending ones are
+/// sent to the client.
 async fn client_server_task(
     process_id: ProcessId,
     shard_id: ShardId,
     atomic_dot_gen: Option<AtomicDotGen>,
     mut client_to_workers: ClientToWorkers,
     mut client_to_executors: ClientToExecutors,
+    store_index: StoreIndex,
+    overload: Arc<Overload>,
+    rate_limiter: Arc<RateLimiter>,
     client_channel_buffer_size: usize,
+    mut shutdown: ShutdownReceiver,
     mut connection: Connection,
 ) {
     let client = server_receive_hi(
@@ -98,58 +145,125 @@ async fn client_server_task(
         warn!("[client_server] giving up on new client {:?} since handshake failed:", connection);
         return;
     }
-    let (client_ids, mut executor_results) = client.unwrap();
+    let (client_ids, to_client_tx, mut from_executors) = client.unwrap();
+
+    // clients with a session on this connection
+    let mut sessions = Sessions {
+        client_ids: client_ids.into_iter().collect(),
+        to_client_tx,
+    };
 
     // create pending
     let mut pending = AggregatePending::new(process_id, shard_id);
 
+    // track whether we're shutting down
+    let mut shutting_down = false;
+
     loop {
+        // once shutting down, stop when there are no pending commands
+        if shutting_down && pending.is_empty() {
+            info!("[client_server] shutting down: no pending commands");
+            client_server_task_unregister(
+                sessions.client_ids(),
+                &mut client_to_executors,
+            )
+            .await;
+            return;
+        }
+
         tokio::select! {
-            executor_result = executor_results.recv() => {
-                trace!("[client_server] new executor result: {:?}", executor_result);
-                client_server_task_handle_executor_result(executor_result, &mut connection, &mut pending).await;
+            from_executor = from_executors.recv() => {
+                trace!("[client_server] from executor: {:?}", from_executor);
+                client_server_task_handle_from_executor(from_executor, &mut connection, &mut pending).await;
             }
-            from_client = connection.recv() => {
+            from_client = connection.recv(), if !shutting_down => {
                 trace!("[client_server] from client: {:?}", from_client);
-                if !client_server_task_handle_from_client(from_client, &client_ids, &atomic_dot_gen, &mut client_to_workers, &mut client_to_executors, &mut pending).await {
+                if !client_server_task_handle_from_client(shard_id, from_client, &mut connection, &mut sessions, &atomic_dot_gen, &mut client_to_workers, &mut client_to_executors, store_index, &overload, &rate_limiter, &mut pending).await {
                     return;
                 }
             }
+            _ = shutdown.changed(), if !shutting_down => {
+                shutting_down = true;
+            }
         }
     }
 }
 
+/// Clients with a session on a connection, along with the channel where
+/// executors write their results (and notifications of watched keys).
+struct Sessions {
+    client_ids: HashSet<ClientId>,
+    to_client_tx: ExecutorToClientSender,
+}
+
+impl Sessions {
+    fn contains(&self, client_id: ClientId) -> bool {
+        self.client_ids.contains(&client_id)
+    }
+
+    fn client_ids(&self) -> Vec<ClientId> {
+        self.client_ids.iter().cloned().collect()
+    }
+}
+
 async fn server_receive_hi(
     process_id: ProcessId,
     shard_id: ShardId,
     client_channel_buffer_size: usize,
     connection: &mut Connection,
     client_to_executors: &mut ClientToExecutors,
-) -> Option<(Vec<ClientId>, ExecutorResultReceiver)> {
-    // receive hi from client
-    let client_ids = if let Some(ClientHi(client_ids)) = connection.recv().await
-    {
-        trace!("[client_server] received hi from clients {:?}", client_ids);
-        client_ids
-    } else {
-        warn!(
-            "[client_server] couldn't receive client ids from connected client"
-        );
-        return None;
+) -> Option<(
+    Vec<ClientId>,
+    ExecutorToClientSender,
+    ExecutorToClientReceiver,
+)> {
+    // receive hello from client
+    let client_ids = match connection.recv().await {
+        Some(ClientToServer::Hello {
+            version,
+            client_ids,
+        }) if version == wire::VERSION => {
+            trace!(
+                "[client_server] received hello from clients {:?}",
+                client_ids
+            );
+            client_ids
+        }
+        Some(ClientToServer::Hello { version, .. }) => {
+            let error = format!(
+                "unsupported protocol version {} (expected {})",
+                version,
+                wire::VERSION
+            );
+            send_error(connection, error).await;
+            return None;
+        }
+        Some(msg) => {
+            let error = format!("expected hello but got {:?}", msg);
+            send_error(connection, error).await;
+            return None;
+        }
+        None => {
+            warn!(
+                "[client_server] couldn't receive client ids from connected client"
+            );
+            return None;
+        }
     };
 
-    // create channel where the executors will write executor results
-    let (mut executor_results_tx, executor_results_rx) =
+    // create channel where the executors will write executor results (and
+    // notifications of watched keys)
+    let (mut to_client_tx, to_client_rx) =
         chan::channel(client_channel_buffer_size);
 
     // set channels name
     let ids_repr = task::util::ids_repr(&client_ids);
-    executor_results_tx
+    to_client_tx
         .set_name(format!("client_server_executor_results_{}", ids_repr));
 
     // register clients in all executors
     let register =
-        ClientToExecutor::Register(client_ids.clone(), executor_results_tx);
+        ClientToExecutor::Register(client_ids.clone(), to_client_tx.clone());
     if let Err(e) = client_to_executors.broadcast(register).await {
         warn!(
             "[client_server] error while registering clients in executors: {:?}",
@@ -157,79 +271,354 @@ async fn server_receive_hi(
         );
     }
 
-    // say hi back
-    let hi = ProcessHi {
+    // say hello back
+    let hello = ServerToClient::Hello {
         process_id,
         shard_id,
     };
-    if let Err(e) = connection.send(&hi).await {
-        warn!("[client_server] error while sending hi: {:?}", e);
+    if let Err(e) = connection.send(&hello).await {
+        warn!("[client_server] error while sending hello: {:?}", e);
     }
 
-    // return client id and channel where client should read executor results
-    Some((client_ids, executor_results_rx))
+    // return client ids and channel where client should read executor results
+    // (along with its sender, so that more clients can be registered later)
+    Some((client_ids, to_client_tx, to_client_rx))
 }
 
 async fn client_server_task_handle_from_client(
+    shard_id: ShardId,
     from_client: Option<ClientToServer>,
-    client_ids: &Vec<ClientId>,
+    connection: &mut Connection,
+    sessions: &mut Sessions,
     atomic_dot_gen: &Option<AtomicDotGen>,
     client_to_workers: &mut ClientToWorkers,
     client_to_executors: &mut ClientToExecutors,
+    store_index: StoreIndex,
+    overload: &Overload,
+    rate_limiter: &RateLimiter,
     pending: &mut AggregatePending,
 ) -> bool {
     if let Some(from_client) = from_client {
         client_server_task_handle_cmd(
+            shard_id,
             from_client,
+            connection,
+            sessions,
             atomic_dot_gen,
             client_to_workers,
+            client_to_executors,
+            store_index,
+            overload,
+            rate_limiter,
             pending,
         )
         .await;
         true
     } else {
         info!("[client_server] client disconnected.");
-        // unregister client in all executors
-        if let Err(e) = client_to_executors
-            .broadcast(ClientToExecutor::Unregister(client_ids.clone()))
-            .await
-        {
-            warn!(
-                "[client_server] error while unregistering client in executors: {:?}",
-                e
-            );
-        }
+        client_server_task_unregister(
+            sessions.client_ids(),
+            client_to_executors,
+        )
+        .await;
         false
     }
 }
 
+async fn client_server_task_unregister(
+    client_ids: Vec<ClientId>,
+    client_to_executors: &mut ClientToExecutors,
+) {
+    // unregister client in all executors
+    if let Err(e) = client_to_executors
+        .broadcast(ClientToExecutor::Unregister(client_ids))
+        .await
+    {
+        warn!(
+            "[client_server] error while unregistering client in executors: {:?}",
+            e
+        );
+    }
+}
+
 async fn client_server_task_handle_cmd(
+    shard_id: ShardId,
     from_client: ClientToServer,
+    connection: &mut Connection,
+    sessions: &mut Sessions,
     atomic_dot_gen: &Option<AtomicDotGen>,
     client_to_workers: &mut ClientToWorkers,
+    client_to_executors: &mut ClientToExecutors,
+    store_index: StoreIndex,
+    overload: &Overload,
+    rate_limiter: &RateLimiter,
     pending: &mut AggregatePending,
 ) {
+    // commands and watches can only be sent on behalf of clients with a
+    // session on this connection
+    let client_ids = match &from_client {
+        ClientToServer::Submit(cmd) | ClientToServer::Register(cmd) => {
+            vec![cmd.rifl().source()]
+        }
+        ClientToServer::SubmitMany(cmds) => {
+            cmds.iter().map(|cmd| cmd.rifl().source()).collect()
+        }
+        ClientToServer::Watch(client_id, _)
+        | ClientToServer::Unwatch(client_id, _) => vec![*client_id],
+        _ => Vec::new(),
+    };
+    if let Some(client_id) = client_ids
+        .into_iter()
+        .find(|client_id| !sessions.contains(*client_id))
+    {
+        let error = format!("client {} has no session", client_id);
+        send_error(connection, error).await;
+        return;
+    }
+
     match from_client {
+        ClientToServer::Hello { .. } => {
+            // clients can only say hello once
+            let error = String::from("unexpected hello");
+            send_error(connection, error).await;
+        }
         ClientToServer::Register(cmd) => {
-            // only register the command
+            // register the command
             client_server_task_register_cmd(&cmd, pending).await;
+
+            // reads with eventual consistency are not submitted, and thus
+            // each shard answers them as soon as they're registered
+            if cmd.local_read() {
+                client_server_task_local_read(
+                    shard_id,
+                    cmd,
+                    client_to_executors,
+                    store_index,
+                )
+                .await;
+            }
         }
         ClientToServer::Submit(cmd) => {
-            // register the command and submit it
-            client_server_task_register_cmd(&cmd, pending).await;
-
-            // create dot for this command (if we have a dot gen)
-            let dot = atomic_dot_gen
-                .as_ref()
-                .map(|atomic_dot_gen| atomic_dot_gen.next_id());
-            // forward command to worker process
-            if let Err(e) = client_to_workers.forward((dot, cmd)).await {
+            client_server_task_submit(
+                shard_id,
+                cmd,
+                connection,
+                atomic_dot_gen,
+                client_to_workers,
+                client_to_executors,
+                store_index,
+                overload,
+                rate_limiter,
+                pending,
+            )
+            .await;
+        }
+        ClientToServer::SubmitMany(cmds) => {
+            for cmd in cmds {
+                client_server_task_submit(
+                    shard_id,
+                    cmd,
+                    connection,
+                    atomic_dot_gen,
+                    client_to_workers,
+                    client_to_executors,
+                    store_index,
+                    overload,
+                    rate_limiter,
+                    pending,
+                )
+                .await;
+            }
+        }
+        ClientToServer::Watch(client_id, key) => {
+            // forward the watch to the executor holding the key
+            let index = store_index(&key);
+            let watch = ClientToExecutor::Watch(index, client_id, key);
+            if let Err(e) = client_to_executors.forward(watch).await {
+                warn!(
+                    "[client_server] error while sending watch to executor: {:?}",
+                    e
+                );
+            }
+        }
+        ClientToServer::Unwatch(client_id, key) => {
+            let index = store_index(&key);
+            let unwatch = ClientToExecutor::Unwatch(index, client_id, key);
+            if let Err(e) = client_to_executors.forward(unwatch).await {
                 warn!(
-                    "[client_server] error while sending new command to protocol worker: {:?}",
+                    "[client_server] error while sending unwatch to executor: {:?}",
                     e
                 );
             }
         }
+        ClientToServer::Open(client_ids) => {
+            client_server_task_open(
+                client_ids,
+                connection,
+                sessions,
+                client_to_executors,
+            )
+            .await;
+        }
+        ClientToServer::Close(client_ids) => {
+            client_server_task_close(
+                client_ids,
+                connection,
+                sessions,
+                client_to_executors,
+                pending,
+            )
+            .await;
+        }
+    }
+}
+
+async fn client_server_task_submit(
+    shard_id: ShardId,
+    cmd: Command,
+    connection: &mut Connection,
+    atomic_dot_gen: &Option<AtomicDotGen>,
+    client_to_workers: &mut ClientToWorkers,
+    client_to_executors: &mut ClientToExecutors,
+    store_index: StoreIndex,
+    overload: &Overload,
+    rate_limiter: &RateLimiter,
+    pending: &mut AggregatePending,
+) {
+    // wait until the command is allowed by the rate limiter (reads
+    // with eventual consistency are never limited)
+    if !cmd.local_read() {
+        if let Some(delay) = rate_limiter.reserve(Instant::now()) {
+            trace!(
+                "[client_server] rate limited: delaying {:?} by {:?}",
+                cmd.rifl(),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // reject the command if the process is overloaded (reads with
+    // eventual consistency are still accepted, as they're not
+    // submitted)
+    if !cmd.local_read() && overload.is_overloaded() {
+        trace!("[client_server] overloaded: rejecting {:?}", cmd);
+        let overloaded = ServerToClient::Overloaded(cmd.rifl());
+        if let Err(e) = connection.send(&overloaded).await {
+            warn!(
+                "[client_server] error while sending overloaded to client: {:?}",
+                e
+            );
+        }
+        return;
+    }
+
+    // register the command and submit it
+    client_server_task_register_cmd(&cmd, pending).await;
+
+    // reads with eventual consistency are answered by the local
+    // executors
+    if cmd.local_read() {
+        client_server_task_local_read(
+            shard_id,
+            cmd,
+            client_to_executors,
+            store_index,
+        )
+        .await;
+        return;
+    }
+
+    // create dot for this command (if we have a dot gen)
+    let dot = atomic_dot_gen
+        .as_ref()
+        .map(|atomic_dot_gen| atomic_dot_gen.next_id());
+    // forward command to worker process
+    if let Err(e) = client_to_workers.forward((dot, cmd)).await {
+        warn!(
+            "[client_server] error while sending new command to protocol worker: {:?}",
+            e
+        );
+    }
+}
+
+async fn client_server_task_open(
+    client_ids: Vec<ClientId>,
+    connection: &mut Connection,
+    sessions: &mut Sessions,
+    client_to_executors: &mut ClientToExecutors,
+) {
+    // each client can only have one session
+    if let Some(client_id) = client_ids
+        .iter()
+        .find(|client_id| sessions.contains(**client_id))
+    {
+        let error = format!("client {} already has a session", client_id);
+        send_error(connection, error).await;
+        return;
+    }
+    trace!("[client_server] opening sessions {:?}", client_ids);
+
+    // register clients in all executors
+    sessions.client_ids.extend(client_ids.iter().cloned());
+    let register =
+        ClientToExecutor::Register(client_ids, sessions.to_client_tx.clone());
+    if let Err(e) = client_to_executors.broadcast(register).await {
+        warn!(
+            "[client_server] error while registering clients in executors: {:?}",
+            e
+        );
+    }
+}
+
+async fn client_server_task_close(
+    client_ids: Vec<ClientId>,
+    connection: &mut Connection,
+    sessions: &mut Sessions,
+    client_to_executors: &mut ClientToExecutors,
+    pending: &AggregatePending,
+) {
+    // sessions can only be closed once all their commands have completed
+    for client_id in client_ids.iter() {
+        let error = if !sessions.contains(*client_id) {
+            format!("client {} has no session", client_id)
+        } else if pending.has_pending(*client_id) {
+            format!("client {} has pending commands", client_id)
+        } else {
+            continue;
+        };
+        send_error(connection, error).await;
+        return;
+    }
+    trace!("[client_server] closing sessions {:?}", client_ids);
+
+    for client_id in client_ids.iter() {
+        sessions.client_ids.remove(client_id);
+    }
+    client_server_task_unregister(client_ids, client_to_executors).await;
+}
+
+async fn client_server_task_local_read(
+    shard_id: ShardId,
+    cmd: Command,
+    client_to_executors: &mut ClientToExecutors,
+    store_index: StoreIndex,
+) {
+    let rifl = cmd.rifl();
+    let session = cmd.session().clone();
+    for (key, ops) in cmd.into_iter(shard_id) {
+        // forward the read on each key to the executor that should answer it
+        // (along with the write on the key it should observe, if any)
+        let index = store_index(&key);
+        let after = session.get(&key).cloned();
+        let read = LocalRead::new(rifl, key, ops, after);
+        let read = ClientToExecutor::LocalRead(index, read);
+        if let Err(e) = client_to_executors.forward(read).await {
+            warn!(
+                "[client_server] error while sending local read to executor: {:?}",
+                e
+            );
+        }
     }
 }
 
@@ -242,19 +631,39 @@ async fn client_server_task_register_cmd(
     pending.wait_for(&cmd);
 }
 
-async fn client_server_task_handle_executor_result(
-    executor_result: Option<ExecutorResult>,
+async fn send_error(connection: &mut Connection, error: String) {
+    warn!("[client_server] sending error to client: {}", error);
+    if let Err(e) = connection.send(&ServerToClient::Error(error)).await {
+        warn!(
+            "[client_server] error while sending error to client: {:?}",
+            e
+        );
+    }
+}
+
+async fn client_server_task_handle_from_executor(
+    from_executor: Option<ExecutorToClient>,
     connection: &mut Connection,
     pending: &mut AggregatePending,
 ) {
-    if let Some(executor_result) = executor_result {
-        if let Some(cmd_result) = pending.add_executor_result(executor_result) {
-            if let Err(e) = connection.send(&cmd_result).await {
-                warn!(
-                    "[client_server] error while sending command results: {:?}",
-                    e
-                );
+    if let Some(from_executor) = from_executor {
+        let to_client = match from_executor {
+            ExecutorToClient::Result(executor_result) => {
+                match pending.add_executor_result(executor_result) {
+                    Some(cmd_result) => ServerToClient::Result(cmd_result),
+                    // the command still has partial results missing
+                    None => return,
+                }
+            }
+            ExecutorToClient::Notification(client_id, notification) => {
+                ServerToClient::Notification(client_id, notification)
             }
+        };
+        if let Err(e) = connection.send(&to_client).await {
+            warn!(
+                "[client_server] error while sending message to client: {:?}",
+                e
+            );
         }
     } else {
         warn!("[client_server] error while receiving new executor result from executor");
@@ -14,12 +14,15 @@ pub async fn delay_task<M>(
     // important to use VecDeque here since we want to always pop the first
     // element
     let mut queue = VecDeque::new();
-    let mut error_shown = false;
+    // track whether the parent is gone, in which case we exit once all
+    // messages are forwarded
+    let mut parent_gone = false;
     loop {
         match queue.front() {
+            None if parent_gone => break,
             None => {
                 let msg = from.recv().await;
-                enqueue(msg, delay, &mut queue, &mut error_shown);
+                enqueue(msg, delay, &mut queue, &mut parent_gone);
             }
             Some((next_instant, _)) => {
                 tokio::select! {
@@ -30,8 +33,8 @@ pub async fn delay_task<M>(
                             break;
                         }
                     }
-                    msg = from.recv() => {
-                        enqueue(msg, delay, &mut queue, &mut error_shown);
+                    msg = from.recv(), if !parent_gone => {
+                        enqueue(msg, delay, &mut queue, &mut parent_gone);
                     }
                 }
             }
@@ -43,16 +46,12 @@ fn enqueue<M>(
     msg: Option<M>,
     delay: Duration,
     queue: &mut VecDeque<(Instant, M)>,
-    error_shown: &mut bool,
+    parent_gone: &mut bool,
 ) {
     if let Some(msg) = msg {
         queue.push_back((task::util::deadline(delay), msg));
     } else {
-        // TODO: replace this with an error, as in `batcher.rs`
-        if !*error_shown {
-            *error_shown = true;
-            warn!("[delay_task] error receiving message from parent");
-        }
+        *parent_gone = true;
     }
 }
 
@@ -1,33 +1,50 @@
+use crate::executor::Executor;
+use crate::id::Dot;
 use crate::protocol::Protocol;
 use crate::run::prelude::*;
-use crate::run::rw::Rw;
+use crate::run::rw::{self, Rw, FRAME_HEADER_SIZE};
 use crate::{info, trace, warn};
-use tokio::fs::File;
+use color_eyre::eyre::{Report, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, SeekFrom};
+use tokio::fs::{self, File};
+use tokio::io::AsyncSeekExt;
 use tokio::time::{self, Duration};
 
 const EXECUTION_LOGGER_FLUSH_INTERVAL: Duration = Duration::from_secs(1); // flush every second
 const EXECUTION_LOGGER_BUFFER_SIZE: usize = 8 * 1024; // 8KB
+pub const EXECUTION_LOG_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64MB
 
+/// Entry of the index of an execution log: the execution info of `dot` was
+/// logged at `offset` of segment `segment`. A dot may be indexed more than
+/// once (e.g. once per key accessed by its command).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub dot: Dot,
+    pub segment: usize,
+    pub offset: u64,
+}
+
+/// Logs the execution infos received from workers. The log is split into
+/// segments of at most `segment_size` bytes (see `segment_path`), each being a
+/// sequence of frames as sent by `Rw`: a length header, a checksum, and an
+/// execution info serialized with bincode. Execution infos about a dot (see
+/// `Executor::execution_info_dot`) are also indexed (see `index_path`), so
+/// that they can be read without reading the whole log (see `open_segment`).
 pub async fn execution_logger_task<P>(
     execution_log: String,
+    segment_size: u64,
     mut from_executors: ExecutionInfoReceiver<P>,
 ) where
     P: Protocol,
 {
     info!("[execution_logger] started with log {}", execution_log);
 
-    // create execution log file (truncating it if already exists)
-    let file = File::create(execution_log)
+    // create execution log (truncating it if already exists)
+    let mut logger = ExecutionLogger::create(execution_log, segment_size)
         .await
         .expect("it should be possible to create execution log file");
 
-    // create file logger
-    let mut logger = Rw::from(
-        EXECUTION_LOGGER_BUFFER_SIZE,
-        EXECUTION_LOGGER_BUFFER_SIZE,
-        file,
-    );
-
     // create interval
     let mut interval = time::interval(EXECUTION_LOGGER_FLUSH_INTERVAL);
 
@@ -37,11 +54,18 @@ pub async fn execution_logger_task<P>(
                 trace!("[executor_logger] from parent: {:?}", execution_info);
                 if let Some(execution_info) = execution_info {
                     // write execution info to file
-                    if let Err(e) = logger.write(&execution_info).await {
+                    let dot = P::Executor::execution_info_dot(&execution_info);
+                    if let Err(e) = logger.write(dot, &execution_info).await {
                         warn!("[executor_logger] error when writing to the logger file: {:?}", e);
                     }
                 } else {
-                    warn!("[executor_logger] error while receiving execution info from parent");
+                    // all workers are gone (e.g. due to a shutdown): flush
+                    // and exit
+                    info!("[executor_logger] parent is gone; exiting");
+                    if let Err(e) = logger.flush().await {
+                        warn!("[executor_logger] error when flushing to the logger file: {:?}", e);
+                    }
+                    return;
                 }
             }
             _ = interval.tick()  => {
@@ -53,3 +77,227 @@ pub async fn execution_logger_task<P>(
         }
     }
 }
+
+/// Returns the path of segment `segment` of an execution log. The first
+/// segment is stored at the execution log path itself.
+pub fn segment_path(execution_log: &str, segment: usize) -> String {
+    if segment == 0 {
+        execution_log.to_string()
+    } else {
+        format!("{}.{}", execution_log, segment)
+    }
+}
+
+/// Returns the path of the index of an execution log, a sequence of frames
+/// (as in the log segments) each with an `IndexEntry`.
+pub fn index_path(execution_log: &str) -> String {
+    format!("{}.index", execution_log)
+}
+
+/// Opens segment `segment` of an execution log at `offset`, from where its
+/// execution infos can be read with `Rw::recv`. Returns `None` if the segment
+/// doesn't exist.
+pub async fn open_segment(
+    execution_log: &str,
+    segment: usize,
+    offset: u64,
+) -> Result<Option<Rw<File>>, Report> {
+    let mut file = match File::open(segment_path(execution_log, segment)).await
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).wrap_err("error opening execution log"),
+    };
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .wrap_err("error seeking execution log")?;
+    Ok(Some(Rw::from(
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        file,
+    )))
+}
+
+/// Reads the index of an execution log.
+pub async fn read_index(
+    execution_log: &str,
+) -> Result<Vec<IndexEntry>, Report> {
+    let file = File::open(index_path(execution_log))
+        .await
+        .wrap_err("error opening execution log index")?;
+    let mut index = Rw::from(
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        file,
+    );
+    let mut entries = Vec::new();
+    while let Some(entry) = index.recv().await {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+struct ExecutionLogger {
+    execution_log: String,
+    segment_size: u64,
+    // current segment and the number of bytes written to it
+    segment: usize,
+    offset: u64,
+    log: Rw<File>,
+    index: Rw<File>,
+}
+
+impl ExecutionLogger {
+    async fn create(
+        execution_log: String,
+        segment_size: u64,
+    ) -> Result<Self, Report> {
+        // remove the segments left by a previous log, if any, so that they're
+        // not mistaken as part of this one
+        for segment in 1.. {
+            match fs::remove_file(segment_path(&execution_log, segment)).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => break,
+                Err(e) => return Err(e).wrap_err("error removing old segment"),
+            }
+        }
+        let log = create(&segment_path(&execution_log, 0)).await?;
+        let index = create(&index_path(&execution_log)).await?;
+        Ok(Self {
+            execution_log,
+            segment_size,
+            segment: 0,
+            offset: 0,
+            log,
+            index,
+        })
+    }
+
+    async fn write<V>(
+        &mut self,
+        dot: Option<Dot>,
+        value: &V,
+    ) -> Result<(), Report>
+    where
+        V: Serialize,
+    {
+        let bytes = rw::serialize(value);
+        let size = (FRAME_HEADER_SIZE + bytes.len()) as u64;
+
+        // start a new segment if this one would become too large (unless
+        // it's empty, as a value larger than the segment size still has to
+        // be written somewhere)
+        if self.offset > 0 && self.offset + size > self.segment_size {
+            self.log.flush().await?;
+            self.segment += 1;
+            self.offset = 0;
+            self.log = create(&segment_path(&self.execution_log, self.segment))
+                .await?;
+            info!(
+                "[execution_logger] rotated to segment {} of log {}",
+                self.segment, self.execution_log
+            );
+        }
+
+        if let Some(dot) = dot {
+            let entry = IndexEntry {
+                dot,
+                segment: self.segment,
+                offset: self.offset,
+            };
+            self.index.write(&entry).await?;
+        }
+        self.log.write_raw(bytes).await?;
+        self.offset += size;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Report> {
+        self.log.flush().await?;
+        self.index.flush().await
+    }
+}
+
+async fn create(path: &str) -> Result<Rw<File>, Report> {
+    let file = File::create(path)
+        .await
+        .wrap_err_with(|| format!("error creating {}", path))?;
+    Ok(Rw::from(
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        EXECUTION_LOGGER_BUFFER_SIZE,
+        file,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn execution_log() {
+        let dir = std::env::temp_dir()
+            .join(format!("fantoch_execution_log_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("creating dir should work");
+        let execution_log = dir
+            .join("p1.execution_log")
+            .to_str()
+            .expect("path should be valid")
+            .to_string();
+
+        // log 10 values, each taking 8 bytes of frame header and 8 bytes of
+        // value, in segments of at most 50 bytes (i.e. 3 values per segment);
+        // only even values are indexed
+        let mut logger = ExecutionLogger::create(execution_log.clone(), 50)
+            .await
+            .expect("creating log should work");
+        for value in 0..10u64 {
+            let dot = (value % 2 == 0).then(|| Dot::new(1, value));
+            logger.write(dot, &value).await.expect("write should work");
+        }
+        logger.flush().await.expect("flush should work");
+
+        // all values can be read by reading all segments in order
+        let mut values = Vec::new();
+        for segment in 0.. {
+            match open_segment(&execution_log, segment, 0).await {
+                Ok(Some(mut log)) => {
+                    while let Some(value) = log.recv::<u64>().await {
+                        values.push(value);
+                    }
+                }
+                Ok(None) => {
+                    assert_eq!(segment, 4);
+                    break;
+                }
+                Err(e) => panic!("open segment failed: {:?}", e),
+            }
+        }
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+
+        // each indexed value can be read directly
+        let index = read_index(&execution_log)
+            .await
+            .expect("read index should work");
+        assert_eq!(index.len(), 5);
+        for entry in index {
+            let mut log =
+                open_segment(&execution_log, entry.segment, entry.offset)
+                    .await
+                    .expect("open segment should work")
+                    .expect("segment should exist");
+            let value = log.recv::<u64>().await;
+            assert_eq!(value, Some(entry.dot.sequence()));
+        }
+
+        // old segments are removed when a log is created again
+        ExecutionLogger::create(execution_log.clone(), 50)
+            .await
+            .expect("creating log should work");
+        assert!(open_segment(&execution_log, 1, 0)
+            .await
+            .expect("open segment should work")
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).expect("removing dir should work");
+    }
+}
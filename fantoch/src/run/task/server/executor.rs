@@ -1,42 +1,98 @@
 use crate::config::Config;
 use crate::executor::Executor;
 use crate::id::{ClientId, ProcessId, ShardId};
+use crate::kvs::{Key, Store};
 use crate::protocol::Protocol;
+use crate::run::affinity::{CpuAffinity, PinnedTask};
 use crate::run::prelude::*;
-use crate::run::task;
+use crate::run::task::server::supervisor;
 use crate::time::RunTime;
-use crate::HashMap;
-use crate::{debug, trace, warn};
+use crate::util;
+use crate::{debug, info, trace, warn};
+use crate::{HashMap, HashSet};
+use color_eyre::Report;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::task::JoinHandle;
 use tokio::time;
+use tracing::{Instrument, Span};
 
-/// Starts executors.
+/// Opens a `SledStore` per executor in `dir` (if set), so that executors
+/// (which are clones of each other) don't share the same database.
+pub fn open_stores(
+    process_id: ProcessId,
+    executors: usize,
+    dir: Option<PathBuf>,
+) -> Result<Option<Vec<Box<dyn Store>>>, Report> {
+    let dir = if let Some(dir) = dir {
+        dir
+    } else {
+        return Ok(None);
+    };
+    #[cfg(feature = "sled")]
+    {
+        let stores = (0..executors)
+            .map(|executor_index| {
+                let path = dir.join(format!(
+                    "p{}_executor_{}",
+                    process_id, executor_index
+                ));
+                let store = crate::kvs::SledStore::open(path)?;
+                Ok(Box::new(store) as Box<dyn Store>)
+            })
+            .collect::<Result<_, Report>>()?;
+        Ok(Some(stores))
+    }
+    #[cfg(not(feature = "sled"))]
+    {
+        let _ = (process_id, executors);
+        color_eyre::eyre::bail!(
+            "executor store dir {:?} set but the sled feature is disabled",
+            dir
+        )
+    }
+}
+
+/// Starts executors. If `stores` are provided, each executor is set to use
+/// its own store.
 pub fn start_executors<P>(
     process_id: ProcessId,
     shard_id: ShardId,
     config: Config,
+    stores: Option<Vec<Box<dyn Store>>>,
     to_executors_rxs: Vec<ExecutionInfoReceiver<P>>,
     client_to_executors_rxs: Vec<ClientToExecutorReceiver>,
     executors_to_workers: ExecutorsToWorkers,
     shard_writers: HashMap<ShardId, Vec<WriterSender<P>>>,
     to_executors: ToExecutors<P>,
     to_metrics_logger: Option<ExecutorMetricsSender>,
-) where
+    cpu_affinity: Arc<CpuAffinity>,
+    failures: FailureSender,
+    shutdown: ShutdownReceiver,
+) -> Vec<JoinHandle<()>>
+where
     P: Protocol + 'static,
 {
+    // create executor
+    let mut executor = P::Executor::new(process_id, shard_id, config);
+    executor.set_executor_count(to_executors_rxs.len());
+
     // zip rxs'
     let incoming = to_executors_rxs
         .into_iter()
         .zip(client_to_executors_rxs.into_iter());
-
-    // create executor
-    let executor = P::Executor::new(process_id, shard_id, config);
+    let mut stores = stores.map(Vec::into_iter);
 
     // create executor workers
+    let mut handles = Vec::new();
     for (executor_index, (from_workers, from_clients)) in incoming.enumerate() {
-        task::spawn(executor_task::<P>(
+        let mut executor = executor.clone();
+        if let Some(store) = stores.as_mut().and_then(Iterator::next) {
+            executor.set_store(store);
+        }
+        let task = executor_task::<P>(
             executor_index,
-            executor.clone(),
+            executor,
             shard_id,
             config,
             from_workers,
@@ -45,8 +101,24 @@ pub fn start_executors<P>(
             shard_writers.clone(),
             to_executors.clone(),
             to_metrics_logger.clone(),
-        ));
+            shutdown.clone(),
+        );
+        let span = tracing::info_span!(
+            "executor",
+            process = process_id,
+            index = executor_index
+        );
+        let task = task.instrument(span);
+        // executors can't be restarted, and thus the process shuts down if
+        // one panics
+        let task = supervisor::shutdown_on_panic(
+            format!("executor {}", executor_index),
+            task,
+            failures.clone(),
+        );
+        handles.push(cpu_affinity.spawn(PinnedTask::Executor, task));
     }
+    handles
 }
 
 async fn executor_task<P>(
@@ -60,6 +132,7 @@ async fn executor_task<P>(
     mut shard_writers: HashMap<ShardId, Vec<WriterSender<P>>>,
     mut to_executors: ToExecutors<P>,
     mut to_metrics_logger: Option<ExecutorMetricsSender>,
+    mut shutdown: ShutdownReceiver,
 ) where
     P: Protocol + 'static,
 {
@@ -107,7 +180,7 @@ async fn executor_task<P>(
                     handle_execution_info(execution_info, &mut executor, shard_id, &mut shard_writers, &mut to_executors, &mut to_clients, &time).await;
                 }
                 from_client = from_clients.recv() => {
-                    handle_from_client::<P>(from_client, &mut to_clients).await;
+                    handle_from_client::<P>(from_client, &mut executor, &mut to_clients).await;
                 }
                 _ = &mut cleanup_delay => {
                     cleanup_tick(&mut executor, shard_id, &mut shard_writers, &mut to_executors, &mut to_clients, &time).await;
@@ -121,6 +194,9 @@ async fn executor_task<P>(
                     metrics_tick::<P>(executor_index, &mut executor, &mut to_metrics_logger).await;
                     metrics_delay = gen_metrics_delay();
                 }
+                _ = shutdown.changed() => {
+                    break;
+                }
             }
         }
     } else {
@@ -130,7 +206,7 @@ async fn executor_task<P>(
                     handle_execution_info(execution_info, &mut executor, shard_id, &mut shard_writers, &mut to_executors, &mut to_clients, &time).await;
                 }
                 from_client = from_clients.recv() => {
-                    handle_from_client::<P>(from_client, &mut to_clients).await;
+                    handle_from_client::<P>(from_client, &mut executor, &mut to_clients).await;
                 }
                 _ = &mut cleanup_delay => {
                     cleanup_tick(&mut executor, shard_id, &mut shard_writers, &mut to_executors, &mut to_clients, &time).await;
@@ -144,9 +220,17 @@ async fn executor_task<P>(
                     metrics_tick::<P>(executor_index, &mut executor, &mut to_metrics_logger).await;
                     metrics_delay = gen_metrics_delay();
                 }
+                _ = shutdown.changed() => {
+                    break;
+                }
             }
         }
     }
+
+    // send the final metrics before exiting
+    metrics_tick::<P>(executor_index, &mut executor, &mut to_metrics_logger)
+        .await;
+    info!("[executor] executor {} shutting down", executor_index);
 }
 
 async fn handle_execution_info<P>(
@@ -162,7 +246,14 @@ async fn handle_execution_info<P>(
 {
     trace!("[executor] from workers: {:?}", execution_info);
     if let Some(execution_info) = execution_info {
-        executor.handle(execution_info, time);
+        let span = P::Executor::execution_info_dot(&execution_info)
+            .as_ref()
+            .map(util::dot_span)
+            .unwrap_or_else(Span::none);
+        span.in_scope(|| {
+            let _execute = tracing::debug_span!("execute").entered();
+            executor.handle(execution_info, time)
+        });
         fetch_results(
             executor,
             shard_id,
@@ -208,8 +299,9 @@ async fn fetch_new_command_results<P>(
         let client_id = executor_result.rifl.source();
 
         // send executor result to client (in case it is registered)
-        if let Some(executor_results_tx) = to_clients.to_client(&client_id) {
-            if let Err(e) = executor_results_tx.send(executor_result).await {
+        if let Some(to_client_tx) = to_clients.to_client(&client_id) {
+            let msg = ExecutorToClient::Result(executor_result);
+            if let Err(e) = to_client_tx.send(msg).await {
                 warn!(
                     "[executor] error while sending executor result to client {}: {:?}",
                     client_id, e
@@ -217,6 +309,24 @@ async fn fetch_new_command_results<P>(
             }
         }
     }
+
+    // forward writes on watched keys to the clients watching them
+    for notification in executor.to_watchers() {
+        for client_id in to_clients.watchers(&notification.key) {
+            if let Some(to_client_tx) = to_clients.to_client(&client_id) {
+                let msg = ExecutorToClient::Notification(
+                    client_id,
+                    notification.clone(),
+                );
+                if let Err(e) = to_client_tx.send(msg).await {
+                    warn!(
+                        "[executor] error while sending notification to client {}: {:?}",
+                        client_id, e
+                    );
+                }
+            }
+        }
+    }
 }
 
 async fn fetch_info_to_executors<P>(
@@ -240,7 +350,9 @@ async fn fetch_info_to_executors<P>(
                 warn!("[executor] error while notifying other executors with new execution info: {:?}", e);
             }
         } else {
-            let msg_to_send = Arc::new(POEMessage::Executor(execution_info));
+            let msg_to_send = ToWriter::Message(Arc::new(
+                POEMessage::Executor(execution_info),
+            ));
             if let Some(channels) = shard_writers.get_mut(&target_shard) {
                 crate::run::task::server::process::send_to_one_writer::<P>(
                     "executor",
@@ -259,6 +371,7 @@ async fn fetch_info_to_executors<P>(
 
 async fn handle_from_client<P>(
     from_client: Option<ClientToExecutor>,
+    executor: &mut P::Executor,
     to_clients: &mut ToClients,
 ) where
     P: Protocol,
@@ -266,11 +379,30 @@ async fn handle_from_client<P>(
     trace!("[executor] from client: {:?}", from_client);
     if let Some(from_client) = from_client {
         match from_client {
-            ClientToExecutor::Register(client_ids, executor_results_tx) => {
-                to_clients.register(client_ids, executor_results_tx);
+            ClientToExecutor::Register(client_ids, to_client_tx) => {
+                to_clients.register(client_ids, to_client_tx);
             }
             ClientToExecutor::Unregister(client_ids) => {
-                to_clients.unregister(client_ids);
+                // stop watching the keys no longer watched by any client
+                for key in to_clients.unregister(client_ids) {
+                    executor.unwatch(&key);
+                }
+            }
+            ClientToExecutor::LocalRead(_, read) => {
+                executor.local_read(read);
+                fetch_new_command_results::<P>(executor, to_clients).await;
+            }
+            ClientToExecutor::Watch(_, client_id, key) => {
+                // only start watching the key on its first watcher
+                if to_clients.watch(client_id, key.clone()) {
+                    executor.watch(key);
+                }
+            }
+            ClientToExecutor::Unwatch(_, client_id, key) => {
+                // only stop watching the key once it has no watchers
+                if to_clients.unwatch(client_id, &key) {
+                    executor.unwatch(&key);
+                }
             }
         }
     } else {
@@ -332,12 +464,14 @@ async fn metrics_tick<P>(
 }
 
 struct ToClients {
-    /// since many `ClientId` can share the same `ExecutorResultSender`, in
+    /// since many `ClientId` can share the same `ExecutorToClientSender`, in
     /// order to avoid cloning these senders we'll have this additional index
     /// that tells us which `ToClient` to use for each `ClientId`
     next_id: usize,
     index: HashMap<ClientId, usize>,
-    to_clients: HashMap<usize, ExecutorResultSender>,
+    to_clients: HashMap<usize, ExecutorToClientSender>,
+    /// mapping from each watched key to the clients watching it
+    watchers: HashMap<Key, HashSet<ClientId>>,
 }
 
 impl ToClients {
@@ -346,13 +480,14 @@ impl ToClients {
             next_id: 0,
             index: HashMap::new(),
             to_clients: HashMap::new(),
+            watchers: HashMap::new(),
         }
     }
 
     fn register(
         &mut self,
         client_ids: Vec<ClientId>,
-        executor_results_tx: ExecutorResultSender,
+        to_client_tx: ExecutorToClientSender,
     ) {
         // compute id for this set of clients
         let id = self.next_id;
@@ -367,29 +502,84 @@ impl ToClients {
             );
         }
 
-        // save executor result sender
-        assert!(self.to_clients.insert(id, executor_results_tx).is_none());
+        // save executor to client sender
+        assert!(self.to_clients.insert(id, to_client_tx).is_none());
     }
 
-    fn unregister(&mut self, client_ids: Vec<ClientId>) {
+    /// Unregisters clients, returning the keys that are no longer watched by
+    /// any client. Clients registered together can be unregistered separately
+    /// (e.g. when a session of a connection is closed).
+    fn unregister(&mut self, client_ids: Vec<ClientId>) -> Vec<Key> {
         let mut ids: Vec<_> = client_ids
-            .into_iter()
+            .iter()
             .filter_map(|client_id| {
                 trace!("[executor] clients {} unregistered", client_id);
-                self.index.remove(&client_id)
+                self.index.remove(client_id)
             })
             .collect();
         ids.sort();
         ids.dedup();
-        assert_eq!(ids.len(), 1, "id indexing client ids should be the same");
 
-        assert!(self.to_clients.remove(&ids[0]).is_some());
+        // remove the senders no longer used by any client
+        for id in ids {
+            if !self.index.values().any(|other| *other == id) {
+                assert!(self.to_clients.remove(&id).is_some());
+            }
+        }
+
+        // remove the watches of these clients
+        let mut unwatched = Vec::new();
+        self.watchers.retain(|key, watchers| {
+            watchers.retain(|client_id| !client_ids.contains(client_id));
+            if watchers.is_empty() {
+                unwatched.push(key.clone());
+                false
+            } else {
+                true
+            }
+        });
+        unwatched
+    }
+
+    /// Records that `client_id` watches `key`. Returns whether it's the first
+    /// watcher of the key.
+    fn watch(&mut self, client_id: ClientId, key: Key) -> bool {
+        trace!("[executor] client {} watches key {}", client_id, key);
+        let watchers = self.watchers.entry(key).or_default();
+        watchers.insert(client_id);
+        watchers.len() == 1
+    }
+
+    /// Records that `client_id` no longer watches `key`. Returns whether the
+    /// key has no watchers left.
+    fn unwatch(&mut self, client_id: ClientId, key: &Key) -> bool {
+        trace!("[executor] client {} unwatches key {}", client_id, key);
+        match self.watchers.get_mut(key) {
+            Some(watchers) => {
+                watchers.remove(&client_id);
+                if watchers.is_empty() {
+                    self.watchers.remove(key);
+                    true
+                } else {
+                    false
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the clients watching `key`.
+    fn watchers(&self, key: &Key) -> Vec<ClientId> {
+        self.watchers
+            .get(key)
+            .map(|watchers| watchers.iter().cloned().collect())
+            .unwrap_or_default()
     }
 
     fn to_client(
         &mut self,
         client_id: &ClientId,
-    ) -> Option<&mut ExecutorResultSender> {
+    ) -> Option<&mut ExecutorToClientSender> {
         // search index
         if let Some(id) = self.index.get(client_id) {
             // get client channel
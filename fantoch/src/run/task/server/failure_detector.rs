@@ -0,0 +1,268 @@
+use crate::id::ProcessId;
+use crate::protocol::{FailureDetection, Protocol};
+use crate::run::prelude::*;
+use crate::run::rw;
+use crate::HashMap;
+use crate::{info, trace, warn};
+use std::collections::VecDeque;
+use std::time::Instant;
+use tokio::time::{self, Duration};
+
+// number of heartbeat inter-arrival times kept for each process
+const WINDOW_SIZE: usize = 100;
+// a process is suspected once its phi is above this threshold
+const PHI_THRESHOLD: f64 = 8.0;
+// minimum standard deviation of the inter-arrival times, so that a process
+// that sends heartbeats at a very regular pace isn't suspected as soon as a
+// heartbeat is slightly delayed
+const MIN_STD_DEVIATION: Duration = Duration::from_millis(100);
+
+/// Sends a heartbeat to every process every `heartbeat_interval`, and detects
+/// failures from the heartbeats received (which are forwarded by readers).
+/// Workers are notified each time a process is suspected, and each time a
+/// suspected process is trusted again (see `Protocol::handle_failure`).
+///
+/// Processes are only monitored once a first heartbeat is received from them;
+/// since heartbeats are only sent to the processes connected on startup,
+/// processes that join late are not monitored by the processes already
+/// running.
+pub async fn failure_detector_task<P, R>(
+    heartbeat_interval: Duration,
+    mut to_writers: HashMap<ProcessId, Vec<WriterSender<P>>>,
+    mut from_readers: HeartbeatReceiver,
+    mut periodic_to_workers: PeriodicToWorkers<P, R>,
+) where
+    P: Protocol + 'static,
+    R: Clone + 'static,
+{
+    // heartbeats are the same for all processes, and thus serialized once
+    let heartbeat =
+        ToWriter::Serialized(rw::serialize(&POEMessage::<P>::Heartbeat));
+    let mut detector = FailureDetector::new(heartbeat_interval);
+    let mut interval = time::interval(heartbeat_interval);
+    loop {
+        tokio::select! {
+            peer_id = from_readers.recv() => {
+                let peer_id = match peer_id {
+                    Some(peer_id) => peer_id,
+                    None => {
+                        warn!("[failure_detector] error receiving heartbeat from readers");
+                        return;
+                    }
+                };
+                trace!("[failure_detector] heartbeat from {}", peer_id);
+                if let Some(detection) = detector.heartbeat(peer_id, Instant::now()) {
+                    notify_workers(&mut periodic_to_workers, peer_id, detection).await;
+                }
+            }
+            _ = interval.tick() => {
+                // send heartbeat on the first connection to each process
+                for writers in to_writers.values_mut() {
+                    if let Some(writer) = writers.first_mut() {
+                        if let Err(e) = writer.send((Instant::now(), heartbeat.clone())).await {
+                            warn!("[failure_detector] error sending heartbeat to writer: {:?}", e);
+                        }
+                    }
+                }
+                // check which processes should now be suspected
+                for peer_id in detector.suspect(Instant::now()) {
+                    notify_workers(&mut periodic_to_workers, peer_id, FailureDetection::Suspected).await;
+                }
+            }
+        }
+    }
+}
+
+async fn notify_workers<P, R>(
+    periodic_to_workers: &mut PeriodicToWorkers<P, R>,
+    peer_id: ProcessId,
+    detection: FailureDetection,
+) where
+    P: Protocol + 'static,
+    R: Clone + 'static,
+{
+    info!("[failure_detector] process {}: {:?}", peer_id, detection);
+    let msg = FromPeriodicMessage::Failure(peer_id, detection);
+    if let Err(e) = periodic_to_workers.forward(msg).await {
+        warn!(
+            "[failure_detector] error notifying workers of failure detection: {:?}",
+            e
+        );
+    }
+}
+
+/// φ accrual failure detector (Hayashibara et al., SRDS'04). For each process,
+/// the inter-arrival times of its last heartbeats are assumed to follow a
+/// normal distribution. From this distribution, the time elapsed since the
+/// last heartbeat is converted into a suspicion level φ: a process is
+/// suspected once φ is above `PHI_THRESHOLD`, which means that the
+/// probability of a heartbeat still arriving is below 10^-8.
+#[derive(Debug)]
+pub struct FailureDetector {
+    heartbeat_interval: Duration,
+    processes: HashMap<ProcessId, Arrivals>,
+}
+
+impl FailureDetector {
+    pub fn new(heartbeat_interval: Duration) -> Self {
+        Self {
+            heartbeat_interval,
+            processes: HashMap::new(),
+        }
+    }
+
+    /// Records a heartbeat from process `process_id` received at `now`. If the
+    /// process was suspected, it's trusted again.
+    pub fn heartbeat(
+        &mut self,
+        process_id: ProcessId,
+        now: Instant,
+    ) -> Option<FailureDetection> {
+        let heartbeat_interval = self.heartbeat_interval;
+        let arrivals = self
+            .processes
+            .entry(process_id)
+            .or_insert_with(|| Arrivals::new(heartbeat_interval, now));
+        arrivals.add(now);
+        if arrivals.suspected {
+            arrivals.suspected = false;
+            Some(FailureDetection::Trusted)
+        } else {
+            None
+        }
+    }
+
+    /// Computes the suspicion level of process `process_id` at `now`, if the
+    /// process is being monitored.
+    pub fn phi(&self, process_id: ProcessId, now: Instant) -> Option<f64> {
+        self.processes
+            .get(&process_id)
+            .map(|arrivals| arrivals.phi(now))
+    }
+
+    /// Returns the processes that are suspected at `now` and that weren't
+    /// before.
+    pub fn suspect(&mut self, now: Instant) -> Vec<ProcessId> {
+        self.processes
+            .iter_mut()
+            .filter(|(_, arrivals)| {
+                !arrivals.suspected && arrivals.phi(now) > PHI_THRESHOLD
+            })
+            .map(|(process_id, arrivals)| {
+                arrivals.suspected = true;
+                *process_id
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct Arrivals {
+    // last inter-arrival times (in milliseconds), along with their sum and
+    // the sum of their squares
+    intervals: VecDeque<f64>,
+    sum: f64,
+    squares_sum: f64,
+    last: Instant,
+    suspected: bool,
+}
+
+impl Arrivals {
+    fn new(heartbeat_interval: Duration, now: Instant) -> Self {
+        let mut arrivals = Self {
+            intervals: VecDeque::with_capacity(WINDOW_SIZE),
+            sum: 0.0,
+            squares_sum: 0.0,
+            last: now,
+            suspected: false,
+        };
+        // start with the expected inter-arrival time, as the first heartbeat
+        // has no previous one
+        arrivals.push(millis(heartbeat_interval));
+        arrivals
+    }
+
+    fn add(&mut self, now: Instant) {
+        let interval = millis(now.saturating_duration_since(self.last));
+        self.last = now;
+        // the first heartbeat only sets `last`
+        if interval > 0.0 {
+            self.push(interval);
+        }
+    }
+
+    fn push(&mut self, interval: f64) {
+        if self.intervals.len() == WINDOW_SIZE {
+            let oldest = self.intervals.pop_front().unwrap();
+            self.sum -= oldest;
+            self.squares_sum -= oldest * oldest;
+        }
+        self.intervals.push_back(interval);
+        self.sum += interval;
+        self.squares_sum += interval * interval;
+    }
+
+    fn phi(&self, now: Instant) -> f64 {
+        let elapsed = millis(now.saturating_duration_since(self.last));
+        let count = self.intervals.len() as f64;
+        let mean = self.sum / count;
+        let variance = (self.squares_sum / count - mean * mean).max(0.0);
+        let std_deviation = variance.sqrt().max(millis(MIN_STD_DEVIATION));
+
+        // approximation of the normal cumulative distribution function (used
+        // by e.g. Akka and Cassandra)
+        let y = (elapsed - mean) / std_deviation;
+        let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+        if elapsed > mean {
+            -(e / (1.0 + e)).log10()
+        } else {
+            -(1.0 - 1.0 / (1.0 + e)).log10()
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_detector() {
+        let heartbeat_interval = Duration::from_millis(100);
+        let mut detector = FailureDetector::new(heartbeat_interval);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // processes are only monitored after their first heartbeat
+        assert!(detector.phi(1, start).is_none());
+        assert!(detector.suspect(at(10_000)).is_empty());
+
+        // heartbeats arrive regularly
+        for i in 0..10 {
+            assert!(detector.heartbeat(1, at(i * 100)).is_none());
+        }
+        let last = 900;
+
+        // the suspicion level grows with the time since the last heartbeat
+        let phi_early = detector.phi(1, at(last + 100)).unwrap();
+        let phi_late = detector.phi(1, at(last + 500)).unwrap();
+        assert!(phi_early < phi_late);
+
+        // the process is not suspected right after an expected heartbeat is
+        // missed, but it is after several are
+        assert!(detector.suspect(at(last + 150)).is_empty());
+        assert_eq!(detector.suspect(at(last + 1000)), vec![1]);
+        // it's only reported once
+        assert!(detector.suspect(at(last + 2000)).is_empty());
+
+        // once a heartbeat arrives, the process is trusted again
+        assert_eq!(
+            detector.heartbeat(1, at(last + 2000)),
+            Some(FailureDetection::Trusted)
+        );
+        assert!(detector.heartbeat(1, at(last + 2100)).is_none());
+    }
+}
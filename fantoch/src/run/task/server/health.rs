@@ -0,0 +1,98 @@
+use crate::run::task::server::overload::Overload;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Stages a process goes through, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // connecting to all processes and exchanging hi with them
+    Connecting,
+    // starting workers, executors and the client listener
+    Starting,
+    // accepting client commands
+    Running,
+    // no longer accepting client commands
+    ShuttingDown,
+}
+
+impl Stage {
+    fn from_u8(stage: u8) -> Self {
+        match stage {
+            0 => Self::Connecting,
+            1 => Self::Starting,
+            2 => Self::Running,
+            3 => Self::ShuttingDown,
+            _ => panic!("invalid stage {}", stage),
+        }
+    }
+}
+
+/// Health of a process, served by the metrics endpoint (see `prometheus_task`)
+/// so that orchestration tools know when the process is ready for clients.
+#[derive(Debug)]
+pub struct Health {
+    stage: AtomicU8,
+    overload: Arc<Overload>,
+}
+
+impl Health {
+    pub fn new(overload: Arc<Overload>) -> Self {
+        Self {
+            stage: AtomicU8::new(Stage::Connecting as u8),
+            overload,
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        Stage::from_u8(self.stage.load(Ordering::Relaxed))
+    }
+
+    pub fn set_stage(&self, stage: Stage) {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+    }
+
+    /// Checks whether the process is ready for clients: it's connected to all
+    /// processes, it's running and it's keeping up with its channels (see
+    /// `Overload`). If not, the reason is returned.
+    pub fn ready(&self) -> Result<(), String> {
+        match self.stage() {
+            Stage::Running if self.overload.is_overloaded() => {
+                Err(String::from("overloaded"))
+            }
+            Stage::Running => Ok(()),
+            stage => Err(format!("{:?}", stage)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::chan;
+
+    #[tokio::test]
+    async fn health_test() {
+        let capacity = 2;
+        let (mut tx, rx) = chan::channel(capacity);
+        let rxs = vec![rx];
+        let overload = Arc::new(Overload::new(Some(0.5)));
+        overload.register_channels(&rxs, capacity);
+        let health = Health::new(overload);
+
+        // the process is only ready once it's running
+        assert_eq!(health.stage(), Stage::Connecting);
+        assert!(health.ready().is_err());
+        health.set_stage(Stage::Starting);
+        assert!(health.ready().is_err());
+        health.set_stage(Stage::Running);
+        assert!(health.ready().is_ok());
+
+        // and it's not ready while overloaded
+        tx.send(()).await.expect("send should work");
+        assert_eq!(health.ready(), Err(String::from("overloaded")));
+
+        // or after starting to shut down
+        health.set_stage(Stage::ShuttingDown);
+        assert_eq!(health.ready(), Err(String::from("ShuttingDown")));
+    }
+}
@@ -2,6 +2,7 @@ use crate::executor::ExecutorMetrics;
 use crate::protocol::ProtocolMetrics;
 use crate::run::prelude::*;
 use crate::run::task;
+use crate::run::task::server::prometheus::MetricsSender;
 use crate::HashMap;
 use crate::{info, trace, warn};
 use serde::{Deserialize, Serialize};
@@ -40,12 +41,15 @@ impl ProcessMetrics {
     }
 }
 
+/// Periodically dumps the metrics received from workers and executors to
+/// `metrics_file`, and publishes them to the Prometheus endpoint (if any).
 pub async fn metrics_logger_task(
-    metrics_file: String,
+    metrics_file: Option<String>,
+    to_prometheus: Option<MetricsSender>,
     mut from_workers: ProtocolMetricsReceiver,
     mut from_executors: ExecutorMetricsReceiver,
 ) {
-    info!("[metrics_logger] started with log {}", metrics_file);
+    info!("[metrics_logger] started with log {:?}", metrics_file);
 
     // create metrics
     let mut global_metrics = ProcessMetrics::new();
@@ -53,39 +57,74 @@ pub async fn metrics_logger_task(
     // create interval
     let mut interval = time::interval(METRICS_INTERVAL);
 
+    // track whether workers and executors are gone (e.g. due to a shutdown)
+    let mut workers_gone = false;
+    let mut executors_gone = false;
+
     loop {
         tokio::select! {
-            metrics = from_workers.recv() => {
+            metrics = from_workers.recv(), if !workers_gone => {
                 trace!("[metrics_logger] from protocol worker: {:?}", metrics);
                 if let Some((index, protocol_metrics)) = metrics  {
                     // update metrics for this worker
                     global_metrics.workers.insert(index, protocol_metrics);
                 } else {
-                    warn!("[metrics_logger] error while receiving metrics from protocol worker");
+                    workers_gone = true;
                 }
             }
-            metrics = from_executors.recv() => {
+            metrics = from_executors.recv(), if !executors_gone => {
                 trace!("[metrics_logger] from executor: {:?}", metrics);
                 if let Some((index, executor_metrics)) = metrics  {
                     // update metrics for this executor
                     global_metrics.executors.insert(index, executor_metrics);
                 } else {
-                    warn!("[metrics_logger] error while receiving metrics from executor");
+                    executors_gone = true;
                 }
             }
             _ = interval.tick()  => {
-                // First serialize to a temporary file, and then rename it. This makes it more
-                // likely we won't end up with a corrupted file if we're shutdown in the middle
-                // of this.
-                let tmp = format!("{}_tmp", metrics_file);
-                if let Err(e) = task::util::serialize_and_compress(&global_metrics, &tmp) {
-                    panic!("[metrics_logger] couldn't serialize metrics: {:?}", e);
+                if let Some(metrics_file) = &metrics_file {
+                    dump(&global_metrics, metrics_file);
                 }
-                // rename file
-                if let Err(e) = std::fs::rename(&tmp, &metrics_file) {
-                    warn!("[metrics_logger] coudn't rename temporary metrics file: {:?}", e);
+                if let Some(to_prometheus) = &to_prometheus {
+                    publish(&global_metrics, to_prometheus);
                 }
             }
         }
+
+        if workers_gone && executors_gone {
+            // dump the final metrics and exit
+            info!("[metrics_logger] workers and executors are gone; exiting");
+            if let Some(metrics_file) = &metrics_file {
+                dump(&global_metrics, metrics_file);
+            }
+            return;
+        }
+    }
+}
+
+fn publish(global_metrics: &ProcessMetrics, to_prometheus: &MetricsSender) {
+    let metrics = (
+        global_metrics.protocol_metrics(),
+        global_metrics.executor_metrics(),
+    );
+    if let Err(e) = to_prometheus.send(metrics) {
+        warn!("[metrics_logger] couldn't publish metrics: {:?}", e);
+    }
+}
+
+fn dump(global_metrics: &ProcessMetrics, metrics_file: &str) {
+    // First serialize to a temporary file, and then rename it. This makes it
+    // more likely we won't end up with a corrupted file if we're shutdown in
+    // the middle of this.
+    let tmp = format!("{}_tmp", metrics_file);
+    if let Err(e) = task::util::serialize_and_compress(global_metrics, &tmp) {
+        panic!("[metrics_logger] couldn't serialize metrics: {:?}", e);
+    }
+    // rename file
+    if let Err(e) = std::fs::rename(&tmp, metrics_file) {
+        warn!(
+            "[metrics_logger] coudn't rename temporary metrics file: {:?}",
+            e
+        );
     }
 }
@@ -2,7 +2,7 @@
 pub mod executor;
 
 // This module contains execution logger's implementation.
-mod execution_logger;
+pub mod execution_logger;
 
 // This module contains process's implementation.
 pub mod process;
@@ -22,56 +22,100 @@ pub mod delay;
 // This module contains periodic metrics's implementation.
 pub mod metrics_logger;
 
+// This module contains the implementation of the Prometheus metrics endpoint.
+pub mod prometheus;
+
+// This module contains the definition of `Overload`.
+pub mod overload;
+
+// This module contains the implementation of the failure detector.
+pub mod failure_detector;
+
+// This module contains the definition of `RateLimiter`.
+pub mod rate_limit;
+
+// This module contains the definition of `Health`.
+pub mod health;
+
+// This module contains the implementation of task supervision.
+pub mod supervisor;
+
 use crate::config::Config;
 use crate::id::{ProcessId, ShardId};
 use crate::protocol::Protocol;
-use crate::run::chan;
+use crate::run::affinity::{CpuAffinity, PinnedTask};
+use crate::run::chan::{self, ChannelReceiver};
 use crate::run::prelude::*;
-use crate::run::rw::Connection;
+use crate::run::rw::{Compression, Connection, ConnectionCounters};
 use crate::run::task;
+use crate::run::task::server::prometheus::Gauges;
 use crate::HashMap;
-use crate::{trace, warn};
-use color_eyre::Report;
-use std::fmt::Debug;
-use std::net::IpAddr;
-use tokio::net::{TcpListener, ToSocketAddrs};
+use crate::{info, trace, warn};
+use color_eyre::eyre::{self, Report};
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tokio::time::{self, Duration};
 
+// when a connection to a process breaks, its writer tries to reconnect after
+// `RECONNECT_INITIAL_BACKOFF`, doubling the backoff after each failed attempt
+// (up to `RECONNECT_MAX_BACKOFF`)
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+// maximum number of messages a writer coalesces into a single flush
+const WRITER_MAX_BATCH: usize = 128;
+
 pub async fn connect_to_all<A, P>(
     process_id: ProcessId,
     shard_id: ShardId,
     config: Config,
-    listener: TcpListener,
+    listener: task::Listener,
     addresses: Vec<(A, Option<Duration>)>,
     to_workers: ReaderToWorkers<P>,
     to_executors: ToExecutors<P>,
+    joined_to_workers: JoinedToWorkers<P>,
+    to_failure_detector: Option<HeartbeatSender>,
+    gauges: Arc<Gauges>,
+    cpu_affinity: Arc<CpuAffinity>,
     connect_retries: usize,
     tcp_nodelay: bool,
     tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
+    compression: Compression,
     channel_buffer_size: usize,
     multiplexing: usize,
 ) -> Result<
     (
         HashMap<ProcessId, (ShardId, IpAddr, Option<Duration>)>,
         HashMap<ProcessId, Vec<WriterSender<P>>>,
+        Vec<JoinHandle<()>>,
+        Vec<JoinHandle<()>>,
     ),
     Report,
 >
 where
-    A: ToSocketAddrs + Debug,
+    A: ToSocketAddrs + Debug + Display,
     P: Protocol + 'static,
 {
-    // check that (n-1 + shards-1) addresses were set
+    // check that at most (n-1 + shards-1) addresses were set (there can be
+    // less if some processes only join later)
     let total = config.n() - 1 + config.shard_count() - 1;
-    assert_eq!(
-        addresses.len(),
-        total,
-        "addresses count should be (n-1 + shards-1)"
+    assert!(
+        addresses.len() <= total,
+        "addresses count should be at most (n-1 + shards-1)"
     );
 
     // compute the number of expected connections
-    let total_connections = total * multiplexing;
+    let total_connections = addresses.len() * multiplexing;
+
+    // get the port we're listening on, so that processes already running can
+    // connect back to us (in case we're joining late)
+    let listen_port = listener.local_addr()?.port();
 
     // spawn listener
     let mut from_listener = task::spawn_producer(channel_buffer_size, |tx| {
@@ -106,6 +150,17 @@ where
         }
     }
 
+    // say hi to all before waiting for incoming connections: processes
+    // already running only connect back to us after receiving our hi
+    let hi = ProcessHi::new::<P>(
+        process_id,
+        shard_id,
+        Some(listen_port),
+        compression,
+        &config,
+    );
+    say_hi(&hi, &mut outgoing).await;
+
     // receive from listener all connected (incoming)
     for _ in 0..total_connections {
         let connection = from_listener
@@ -115,91 +170,150 @@ where
         incoming.push(connection);
     }
 
-    let res = handshake::<P>(
+    let (ips, writers, mut reader_handles, writer_handles) = handshake::<P>(
         process_id,
         shard_id,
-        to_workers,
-        to_executors,
+        config,
+        to_workers.clone(),
+        to_executors.clone(),
+        to_failure_detector.clone(),
+        tcp_nodelay,
+        tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         channel_buffer_size,
+        &gauges,
+        &cpu_affinity,
         incoming,
         outgoing,
     )
-    .await;
-    Ok(res)
+    .await?;
+
+    // keep accepting connections from processes that reconnect (after their
+    // connection to us broke) or that join late
+    reader_handles.push(task::spawn(accept_task::<P>(
+        process_id,
+        shard_id,
+        config,
+        to_workers,
+        to_executors,
+        joined_to_workers,
+        to_failure_detector,
+        gauges,
+        cpu_affinity,
+        connect_retries,
+        tcp_nodelay,
+        tcp_buffer_size,
+        tcp_flush_interval,
+        compression,
+        channel_buffer_size,
+        from_listener,
+    )));
+    Ok((ips, writers, reader_handles, writer_handles))
 }
 
 async fn handshake<P>(
     process_id: ProcessId,
     shard_id: ShardId,
+    config: Config,
     to_workers: ReaderToWorkers<P>,
     to_executors: ToExecutors<P>,
+    to_failure_detector: Option<HeartbeatSender>,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
+    compression: Compression,
     channel_buffer_size: usize,
+    gauges: &Gauges,
+    cpu_affinity: &CpuAffinity,
     mut connections_0: Vec<Connection>,
-    mut connections_1: Vec<Connection>,
-) -> (
-    HashMap<ProcessId, (ShardId, IpAddr, Option<Duration>)>,
-    HashMap<ProcessId, Vec<WriterSender<P>>>,
-)
+    connections_1: Vec<Connection>,
+) -> Result<
+    (
+        HashMap<ProcessId, (ShardId, IpAddr, Option<Duration>)>,
+        HashMap<ProcessId, Vec<WriterSender<P>>>,
+        Vec<JoinHandle<()>>,
+        Vec<JoinHandle<()>>,
+    ),
+    Report,
+>
 where
     P: Protocol + 'static,
 {
-    // say hi to all on both connections
-    say_hi(process_id, shard_id, &mut connections_0).await;
-    say_hi(process_id, shard_id, &mut connections_1).await;
+    // say hi to all on the first connections (hi was already said on the
+    // second ones, see `connect_to_all`)
+    let hi =
+        ProcessHi::new::<P>(process_id, shard_id, None, compression, &config);
+    say_hi(&hi, &mut connections_0).await;
     trace!("said hi to all processes");
 
     // receive hi from all on both connections
-    let id_to_connection_0 = receive_hi(connections_0).await;
-    let id_to_connection_1 = receive_hi(connections_1).await;
+    let id_to_connection_0 =
+        receive_hi::<P>(&config, compression, gauges, connections_0).await?;
+    let id_to_connection_1 =
+        receive_hi::<P>(&config, compression, gauges, connections_1).await?;
 
     // start readers and writers
-    start_readers::<P>(to_workers, to_executors, id_to_connection_0);
-    start_writers::<P>(
+    let reader_handles = start_readers::<P>(
+        to_workers,
+        to_executors,
+        to_failure_detector,
+        cpu_affinity,
+        id_to_connection_0,
+    );
+    let (ips, writers, writer_handles) = start_writers::<P>(
+        process_id,
         shard_id,
+        config,
+        tcp_nodelay,
+        tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         channel_buffer_size,
+        cpu_affinity,
         id_to_connection_1,
     )
-    .await
+    .await;
+    Ok((ips, writers, reader_handles, writer_handles))
 }
 
-async fn say_hi(
-    process_id: ProcessId,
-    shard_id: ShardId,
-    connections: &mut Vec<Connection>,
-) {
-    let hi = ProcessHi {
-        process_id,
-        shard_id,
-    };
+async fn say_hi(hi: &ProcessHi, connections: &mut [Connection]) {
     // send hi on each connection
     for connection in connections.iter_mut() {
-        if let Err(e) = connection.send(&hi).await {
+        if let Err(e) = connection.send(hi).await {
             warn!("error while sending hi to connection: {:?}", e)
         }
     }
 }
 
-async fn receive_hi(
+/// Receives hi from each connection, failing if some process doesn't match
+/// this one (see `ProcessHi::check`). Since hi is exchanged in both directions
+/// before any other message, the compression negotiated with each process
+/// starts being used on its connection. The bytes exchanged on the connection
+/// are counted from then on.
+async fn receive_hi<P>(
+    config: &Config,
+    compression: Compression,
+    gauges: &Gauges,
     connections: Vec<Connection>,
-) -> Vec<(ProcessId, ShardId, Connection)> {
+) -> Result<Vec<(ProcessId, ShardId, Connection)>, Report>
+where
+    P: Protocol,
+{
     let mut id_to_connection = Vec::with_capacity(connections.len());
 
     // receive hi from each connection
     for mut connection in connections {
-        if let Some(ProcessHi {
-            process_id,
-            shard_id,
-        }) = connection.recv().await
-        {
-            id_to_connection.push((process_id, shard_id, connection));
-        } else {
-            panic!("error receiving hi");
-        }
+        let hi = match connection.recv::<ProcessHi>().await {
+            Some(hi) => hi,
+            None => eyre::bail!("error receiving hi from {:?}", connection),
+        };
+        hi.check::<P>(config)?;
+        connection.set_compression(compression.negotiate(hi.compression));
+        connection.set_counters(gauges.connection_counters(hi.process_id));
+        id_to_connection.push((hi.process_id, hi.shard_id, connection));
     }
-    id_to_connection
+    Ok(id_to_connection)
 }
 
 /// Starts a reader task per connection received. A `ReaderToWorkers` is passed
@@ -208,29 +322,48 @@ async fn receive_hi(
 fn start_readers<P>(
     to_workers: ReaderToWorkers<P>,
     to_executors: ToExecutors<P>,
+    to_failure_detector: Option<HeartbeatSender>,
+    cpu_affinity: &CpuAffinity,
     connections: Vec<(ProcessId, ShardId, Connection)>,
-) where
+) -> Vec<JoinHandle<()>>
+where
     P: Protocol + 'static,
 {
-    for (process_id, shard_id, connection) in connections {
-        task::spawn(reader_task::<P>(
-            to_workers.clone(),
-            to_executors.clone(),
-            process_id,
-            shard_id,
-            connection,
-        ));
-    }
+    connections
+        .into_iter()
+        .map(|(process_id, shard_id, connection)| {
+            let task = reader_task::<P>(
+                to_workers.clone(),
+                to_executors.clone(),
+                to_failure_detector.clone(),
+                process_id,
+                shard_id,
+                connection,
+            );
+            let task = supervisor::log_panic(
+                format!("reader from process {}", process_id),
+                task,
+            );
+            cpu_affinity.spawn(PinnedTask::Reader, task)
+        })
+        .collect()
 }
 
 async fn start_writers<P>(
+    process_id: ProcessId,
     shard_id: ShardId,
+    config: Config,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
+    compression: Compression,
     channel_buffer_size: usize,
+    cpu_affinity: &CpuAffinity,
     connections: Vec<(ProcessId, ShardId, Connection)>,
 ) -> (
     HashMap<ProcessId, (ShardId, IpAddr, Option<Duration>)>,
     HashMap<ProcessId, Vec<WriterSender<P>>>,
+    Vec<JoinHandle<()>>,
 )
 where
     P: Protocol + 'static,
@@ -238,6 +371,8 @@ where
     let mut ips = HashMap::with_capacity(connections.len());
     // mapping from process id to channel broadcast writer should write to
     let mut writers = HashMap::with_capacity(connections.len());
+    // handles of writer (and delay) tasks
+    let mut handles = Vec::with_capacity(connections.len());
 
     // start on writer task per connection
     for (peer_id, peer_shard_id, connection) in connections {
@@ -270,12 +405,43 @@ where
             tcp_flush_interval
         };
 
-        // spawn the writer task
-        task::spawn(writer_task::<P>(
+        // spawn the writer task; if the connection breaks, the writer
+        // reconnects and says hi again, buffering up to `channel_buffer_size`
+        // messages in the meantime
+        let hi = ProcessHi::new::<P>(
+            process_id,
+            shard_id,
+            None,
+            compression,
+            &config,
+        );
+        let writer = Writer {
+            hi,
+            address: connection
+                .peer_address()
+                .expect("peer address should be set for outgoing connection"),
+            compression: connection.compression(),
+            counters: connection.counters(),
+            tcp_nodelay,
+            tcp_buffer_size,
             tcp_flush_interval,
-            connection,
-            writer_rx,
-        ));
+            buffer_size: channel_buffer_size,
+        };
+        // if the writer panics, it's restarted on a new connection (the
+        // messages it didn't send are lost)
+        let writer_rx = Arc::new(Mutex::new(writer_rx));
+        let mut connection = Some(connection);
+        let task = supervisor::restart_on_panic(
+            format!("writer to process {}", peer_id),
+            move || {
+                writer_task::<P>(
+                    writer.clone(),
+                    connection.take(),
+                    writer_rx.clone(),
+                )
+            },
+        );
+        handles.push(cpu_affinity.spawn(PinnedTask::Writer, task));
 
         let tx = if let Some(delay) = connection_delay {
             // if connection has a delay, spawn a delay task for this writer
@@ -289,7 +455,9 @@ where
             ));
 
             // spawn delay task
-            task::spawn(delay::delay_task(delay_rx, writer_tx, delay));
+            handles.push(task::spawn(delay::delay_task(
+                delay_rx, writer_tx, delay,
+            )));
 
             // in this case, messages are first forward to the delay task, which
             // then forwards them to the writer task
@@ -304,13 +472,14 @@ where
         txs.push(tx);
     }
 
-    (ips, writers)
+    (ips, writers, handles)
 }
 
 /// Reader task.
 async fn reader_task<P>(
     mut reader_to_workers: ReaderToWorkers<P>,
     mut to_executors: ToExecutors<P>,
+    mut to_failure_detector: Option<HeartbeatSender>,
     process_id: ProcessId,
     shard_id: ShardId,
     mut connection: Connection,
@@ -335,6 +504,14 @@ async fn reader_task<P>(
                         warn!("[reader] error while notifying executor with new execution info: {:?}", e);
                     }
                 }
+                POEMessage::Heartbeat => {
+                    // heartbeats are ignored if there's no failure detector
+                    if let Some(tx) = to_failure_detector.as_mut() {
+                        if let Err(e) = tx.send(process_id).await {
+                            warn!("[reader] error while notifying failure detector with new heartbeat: {:?}", e);
+                        }
+                    }
+                }
             },
             None => {
                 warn!("[reader] error receiving message from connection");
@@ -344,18 +521,303 @@ async fn reader_task<P>(
     }
 }
 
-/// Writer task.
-async fn writer_task<P>(
+/// Accepts connections from processes that reconnect or that join late,
+/// starting a reader for each of them after receiving their hi.
+#[allow(clippy::too_many_arguments)]
+async fn accept_task<P>(
+    process_id: ProcessId,
+    shard_id: ShardId,
+    config: Config,
+    to_workers: ReaderToWorkers<P>,
+    to_executors: ToExecutors<P>,
+    joined_to_workers: JoinedToWorkers<P>,
+    to_failure_detector: Option<HeartbeatSender>,
+    gauges: Arc<Gauges>,
+    cpu_affinity: Arc<CpuAffinity>,
+    connect_retries: usize,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
     tcp_flush_interval: Option<Duration>,
-    mut connection: Connection,
-    mut parent: WriterReceiver<P>,
+    compression: Compression,
+    channel_buffer_size: usize,
+    mut from_listener: ChannelReceiver<Connection>,
+) where
+    P: Protocol + 'static,
+{
+    while let Some(mut connection) = from_listener.recv().await {
+        let to_workers = to_workers.clone();
+        let to_executors = to_executors.clone();
+        let mut joined_to_workers = joined_to_workers.clone();
+        let to_failure_detector = to_failure_detector.clone();
+        let gauges = gauges.clone();
+        let cpu_affinity = cpu_affinity.clone();
+        // receive hi in a new task so that a slow process doesn't prevent
+        // others from reconnecting
+        task::spawn(async move {
+            if let Some(hi) = connection.recv::<ProcessHi>().await {
+                // processes that don't match this one are not accepted
+                if let Err(e) = hi.check::<P>(&config) {
+                    warn!("[accept] rejecting connection: {:?}", e);
+                    return;
+                }
+                let ProcessHi {
+                    process_id: peer_id,
+                    shard_id: peer_shard_id,
+                    listen_port,
+                    compression: peer_compression,
+                    ..
+                } = hi;
+                if let Some(listen_port) = listen_port {
+                    // only processes connecting for the first time set their
+                    // listen port, and thus this process is joining
+                    info!("[accept] process {} joined", peer_id);
+                    join::<P>(
+                        process_id,
+                        shard_id,
+                        config,
+                        peer_id,
+                        peer_shard_id,
+                        listen_port,
+                        connect_retries,
+                        tcp_nodelay,
+                        tcp_buffer_size,
+                        tcp_flush_interval,
+                        compression,
+                        channel_buffer_size,
+                        &gauges,
+                        &cpu_affinity,
+                        &mut connection,
+                        &mut joined_to_workers,
+                    )
+                    .await;
+                } else {
+                    info!("[accept] process {} reconnected", peer_id);
+                }
+                // only now that hi was (maybe) said back, start using the
+                // negotiated compression
+                connection
+                    .set_compression(compression.negotiate(peer_compression));
+                connection.set_counters(gauges.connection_counters(peer_id));
+                let task = reader_task::<P>(
+                    to_workers,
+                    to_executors,
+                    to_failure_detector,
+                    peer_id,
+                    peer_shard_id,
+                    connection,
+                );
+                let task = supervisor::log_panic(
+                    format!("reader from process {}", peer_id),
+                    task,
+                );
+                cpu_affinity.spawn(PinnedTask::Reader, task);
+            } else {
+                warn!("[accept] error receiving hi from new connection");
+            }
+        });
+    }
+    warn!("[accept] error receiving connection from listener");
+}
+
+/// Completes the handshake with a process that joined late on one of its
+/// connections: opens a connection to the process (which will be used for
+/// writing) and says hi back. Since the process opens `multiplexing`
+/// connections, this ends up opening as many connections back. The writer of
+/// the new connection is sent to all workers, which then `discover` the new
+/// process.
+///
+/// Hi is only said back once all workers have the new writer: since the
+/// joining process only starts after receiving it, no message to it is lost.
+#[allow(clippy::too_many_arguments)]
+async fn join<P>(
+    process_id: ProcessId,
+    shard_id: ShardId,
+    config: Config,
+    peer_id: ProcessId,
+    peer_shard_id: ShardId,
+    listen_port: u16,
+    connect_retries: usize,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
+    tcp_flush_interval: Option<Duration>,
+    compression: Compression,
+    channel_buffer_size: usize,
+    gauges: &Gauges,
+    cpu_affinity: &CpuAffinity,
+    connection: &mut Connection,
+    joined_to_workers: &mut JoinedToWorkers<P>,
 ) where
     P: Protocol + 'static,
 {
-    // track whether there's been a flush error on this connection
-    let mut flush_error = false;
+    // connect to the process, say hi and wait for its hi back
+    let ip = connection
+        .ip_addr()
+        .expect("ip address should be set for incoming connection");
+    let address = SocketAddr::new(ip, listen_port);
+    let mut outgoing = match task::connect(
+        address,
+        tcp_nodelay,
+        tcp_buffer_size,
+        connect_retries,
+    )
+    .await
+    {
+        Ok(connection) => vec![connection],
+        Err(e) => {
+            warn!("[accept] error connecting to {}: {:?}", peer_id, e);
+            return;
+        }
+    };
+    let hi =
+        ProcessHi::new::<P>(process_id, shard_id, None, compression, &config);
+    say_hi(&hi, &mut outgoing).await;
+    let id_to_connection =
+        match receive_hi::<P>(&config, compression, gauges, outgoing).await {
+            Ok(id_to_connection) => id_to_connection,
+            Err(e) => {
+                warn!("[accept] error receiving hi from {}: {:?}", peer_id, e);
+                return;
+            }
+        };
+
+    // start the writer and send it to all workers (the writer task is never
+    // awaited, but it ends once the workers are gone, as all other writers)
+    let workers = joined_to_workers.pool_size();
+    let (ack_tx, mut ack_rx) = chan::channel(workers);
+    let (_, writers, _) = start_writers::<P>(
+        process_id,
+        shard_id,
+        config,
+        tcp_nodelay,
+        tcp_buffer_size,
+        tcp_flush_interval,
+        compression,
+        channel_buffer_size,
+        cpu_affinity,
+        id_to_connection,
+    )
+    .await;
+    for writer in writers.into_values().flatten() {
+        let joined = (peer_id, peer_shard_id, writer, ack_tx.clone());
+        if let Err(e) = joined_to_workers.broadcast(joined).await {
+            warn!(
+                "[accept] error while notifying workers that process {} joined: {:?}",
+                peer_id, e
+            );
+            return;
+        }
+    }
+
+    // wait for all workers and then say hi back
+    for _ in 0..workers {
+        if ack_rx.recv().await.is_none() {
+            warn!("[accept] error while waiting for workers' ack");
+            return;
+        }
+    }
+    if let Err(e) = connection.send(&hi).await {
+        warn!("[accept] error while sending hi to {}: {:?}", peer_id, e);
+    }
+}
+
+/// Writer task. If the connection breaks, the writer reconnects to the same
+/// process (see `reconnect`) and resends the messages that couldn't be sent.
+/// Messages whose write succeeded before the failure was detected are not
+/// resent.
+async fn writer_task<P>(
+    writer: Writer,
+    mut connection: Option<Connection>,
+    parent: Arc<Mutex<WriterReceiver<P>>>,
+) where
+    P: Protocol + 'static,
+{
+    let mut parent = parent.lock().await;
+    // messages not yet sent due to a broken connection
+    let mut unsent = VecDeque::new();
+    loop {
+        // reconnect if there's no connection (the connection broke or the
+        // writer was restarted), buffering new messages in the meantime
+        let mut connection = match connection.take() {
+            Some(connection) => connection,
+            None => match reconnect::<P>(
+                &writer.address,
+                &writer.hi,
+                writer.tcp_nodelay,
+                writer.tcp_buffer_size,
+                writer.buffer_size,
+                &writer.counters,
+                &mut parent,
+                &mut unsent,
+            )
+            .await
+            {
+                Some(mut connection) => {
+                    // the process negotiates the same compression as before
+                    // (as the compression configured at each process doesn't
+                    // change)
+                    connection.set_compression(writer.compression);
+                    connection.set_counters(writer.counters.clone());
+                    connection
+                }
+                None => break,
+            },
+        };
+
+        // write until the connection breaks
+        let broken = write_until_broken::<P>(
+            writer.tcp_flush_interval,
+            &mut connection,
+            &mut parent,
+            &mut unsent,
+        )
+        .await;
+        if !broken {
+            break;
+        }
+    }
+    info!("[writer] parent is gone; exiting");
+}
+
+/// Everything a writer needs to reconnect to a process.
+#[derive(Debug, Clone)]
+struct Writer {
+    hi: ProcessHi,
+    address: String,
+    compression: Compression,
+    counters: Arc<ConnectionCounters>,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
+    tcp_flush_interval: Option<Duration>,
+    buffer_size: usize,
+}
+
+/// Writes messages from the parent until the connection breaks, in which case
+/// `true` is returned (and `false` if the parent is gone, after flushing the
+/// connection). Messages in `unsent` are sent first.
+async fn write_until_broken<P>(
+    tcp_flush_interval: Option<Duration>,
+    connection: &mut Connection,
+    parent: &mut WriterReceiver<P>,
+    unsent: &mut VecDeque<QueuedToWriter<P>>,
+) -> bool
+where
+    P: Protocol + 'static,
+{
+    // resend messages that couldn't be sent before
+    while let Some(msg) = unsent.front() {
+        if let Err(e) = write_to_connection(connection, msg).await {
+            warn!("[writer] error resending message in connection: {:?}", e);
+            return true;
+        }
+        unsent.pop_front();
+    }
+    if let Err(e) = connection.flush().await {
+        warn!("[writer] error flushing connection: {:?}", e);
+        return true;
+    }
+
     // if flush interval higher than 0, then flush periodically; otherwise,
-    // flush on every write
+    // flush after every batch of messages (see `write_batch`)
     if let Some(tcp_flush_interval) = tcp_flush_interval {
         // create interval
         let mut interval = time::interval(tcp_flush_interval);
@@ -363,23 +825,23 @@ async fn writer_task<P>(
             tokio::select! {
                 msg = parent.recv() => {
                     if let Some(msg) = msg {
-                        // connection write *doesn't* flush
-                        if let Err(e) = connection.write(&*msg).await {
-                            warn!("[writer] error writing message in connection: {:?}", e);
+                        // batch write *doesn't* flush
+                        if write_batch(connection, parent, unsent, msg, false).await {
+                            return true;
                         }
                     } else {
-                        warn!("[writer] error receiving message from parent");
-                        break;
+                        // flush what's left before exiting
+                        if let Err(e) = connection.flush().await {
+                            warn!("[writer] error flushing connection: {:?}", e);
+                        }
+                        return false;
                     }
                 }
                 _ = interval.tick() => {
                     // flush socket
                     if let Err(e) = connection.flush().await {
-                        // make sure we only log the error once
-                        if !flush_error {
-                            warn!("[writer] error flushing connection: {:?}", e);
-                            flush_error = true;
-                        }
+                        warn!("[writer] error flushing connection: {:?}", e);
+                        return true;
                     }
                 }
             }
@@ -387,18 +849,234 @@ async fn writer_task<P>(
     } else {
         loop {
             if let Some(msg) = parent.recv().await {
-                // connection write *does* flush
-                if let Err(e) = connection.send(&*msg).await {
-                    warn!(
-                        "[writer] error sending message to connection: {:?}",
-                        e
-                    );
+                // batch write *does* flush
+                if write_batch(connection, parent, unsent, msg, true).await {
+                    return true;
                 }
             } else {
-                warn!("[writer] error receiving message from parent");
-                break;
+                return false;
             }
         }
     }
-    warn!("[writer] exiting after failure");
+}
+
+/// Writes `msg` in the connection, followed by the messages already queued by
+/// the parent (up to `WRITER_MAX_BATCH` messages in total), and then flushes
+/// the connection if `flush` is set. This way, when the parent is idle, each
+/// message is flushed as soon as it's written, while a backlog of messages is
+/// coalesced into a single flush (instead of one per message). Returns `true`
+/// if the connection breaks, in which case the message that couldn't be
+/// written is added to `unsent`.
+async fn write_batch<P>(
+    connection: &mut Connection,
+    parent: &mut WriterReceiver<P>,
+    unsent: &mut VecDeque<QueuedToWriter<P>>,
+    msg: QueuedToWriter<P>,
+    flush: bool,
+) -> bool
+where
+    P: Protocol,
+{
+    let mut next = Some(msg);
+    let mut written = 0;
+    while let Some(msg) = next {
+        if let Err(e) = write_to_connection(connection, &msg).await {
+            warn!("[writer] error writing message in connection: {:?}", e);
+            unsent.push_back(msg);
+            return true;
+        }
+        written += 1;
+        next = if written < WRITER_MAX_BATCH {
+            parent.try_recv()
+        } else {
+            None
+        };
+    }
+    if flush {
+        if let Err(e) = connection.flush().await {
+            warn!("[writer] error flushing connection: {:?}", e);
+            return true;
+        }
+    }
+    false
+}
+
+/// Writes a message in the connection (without flushing it). If it's written,
+/// the time since it was sent to the writer is recorded as queue wait (which
+/// includes the time spent in `unsent`, or in a delay task, if any).
+async fn write_to_connection<P>(
+    connection: &mut Connection,
+    (queued_at, msg): &QueuedToWriter<P>,
+) -> Result<(), Report>
+where
+    P: Protocol,
+{
+    let wait = queued_at.elapsed();
+    match msg {
+        ToWriter::Message(msg) => connection.write(&**msg).await?,
+        // cloning `Bytes` is cheap as the buffer is shared
+        ToWriter::Serialized(bytes) => {
+            connection.write_raw(bytes.clone()).await?
+        }
+    }
+    connection.counters().record_queue_wait(wait);
+    Ok(())
+}
+
+/// Reconnects to `address` with exponential backoff, saying hi on the new
+/// connection. While disconnected, messages from the parent are buffered in
+/// `unsent` (up to `buffer_size`, with newer messages being dropped after
+/// that). Returns `None` if the parent is gone.
+///
+/// Since protocols assume reliable FIFO channels, dropping messages may stall
+/// them, and so each dropped message is recorded in `counters` (and a warning
+/// logged when the buffer first fills). The parent is not blocked instead, as
+/// the process may never come back (e.g. if it crashed), in which case the
+/// workers sending to this writer would block forever.
+async fn reconnect<P>(
+    address: &str,
+    hi: &ProcessHi,
+    tcp_nodelay: bool,
+    tcp_buffer_size: usize,
+    buffer_size: usize,
+    counters: &ConnectionCounters,
+    parent: &mut WriterReceiver<P>,
+    unsent: &mut VecDeque<QueuedToWriter<P>>,
+) -> Option<Connection>
+where
+    P: Protocol + 'static,
+{
+    let mut backoff = RECONNECT_INITIAL_BACKOFF;
+    let mut dropped = 0;
+    loop {
+        // buffer messages until it's time to try again
+        let sleep = time::sleep(backoff);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                msg = parent.recv() => {
+                    let msg = msg?;
+                    if unsent.len() < buffer_size {
+                        unsent.push_back(msg);
+                    } else {
+                        if dropped == 0 {
+                            warn!(
+                                "[writer] buffer of messages to {} is full ({} messages); dropping new messages until reconnected",
+                                address, buffer_size
+                            );
+                        }
+                        dropped += 1;
+                        counters.record_dropped();
+                    }
+                }
+                _ = &mut sleep => break,
+            }
+        }
+
+        // try to reconnect (only once, as retries are done here)
+        match task::connect(address, tcp_nodelay, tcp_buffer_size, 1).await {
+            Ok(mut connection) => match connection.send(hi).await {
+                Ok(()) => {
+                    if dropped > 0 {
+                        warn!(
+                            "[writer] reconnected to {} ({} messages dropped)",
+                            address, dropped
+                        );
+                    } else {
+                        info!("[writer] reconnected to {}", address);
+                    }
+                    return Some(connection);
+                }
+                Err(e) => {
+                    warn!("[writer] error sending hi to {}: {:?}", address, e)
+                }
+            },
+            Err(e) => {
+                info!("[writer] failed to reconnect to {}: {}", address, e)
+            }
+        }
+        backoff = std::cmp::min(backoff * 2, RECONNECT_MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+    use bytes::Bytes;
+    use std::time::Instant;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn reconnect_drops_messages_when_buffer_is_full() {
+        // the process to reconnect to
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind should work");
+        let address = listener
+            .local_addr()
+            .expect("address should be set")
+            .to_string();
+        let hi = ProcessHi::new::<Basic>(
+            1,
+            0,
+            None,
+            Compression::None,
+            &Config::new(3, 1),
+        );
+
+        // the parent sends more messages than can be buffered
+        let tcp_nodelay = true;
+        let tcp_buffer_size = 1024;
+        let buffer_size = 4;
+        let sent = 10;
+        let (mut tx, mut rx) = chan::channel(sent);
+        for i in 0..sent {
+            let msg = ToWriter::Serialized(Bytes::from(vec![i as u8]));
+            tx.send((Instant::now(), msg))
+                .await
+                .expect("send should work");
+        }
+
+        // the oldest messages are buffered, and the others are dropped
+        let counters = ConnectionCounters::default();
+        let mut unsent = VecDeque::new();
+        let connection = reconnect::<Basic>(
+            &address,
+            &hi,
+            tcp_nodelay,
+            tcp_buffer_size,
+            buffer_size,
+            &counters,
+            &mut rx,
+            &mut unsent,
+        )
+        .await;
+        assert!(connection.is_some());
+        assert_eq!(unsent.len(), buffer_size);
+        let buffered: Vec<_> = unsent
+            .iter()
+            .map(|(_, msg)| match msg {
+                ToWriter::Serialized(bytes) => bytes[0],
+                ToWriter::Message(_) => panic!("only serialized messages sent"),
+            })
+            .collect();
+        assert_eq!(buffered, vec![0, 1, 2, 3]);
+        assert_eq!(counters.dropped_messages(), (sent - buffer_size) as u64);
+
+        // if the parent is gone, there's no reconnection
+        drop(tx);
+        let connection = reconnect::<Basic>(
+            &address,
+            &hi,
+            tcp_nodelay,
+            tcp_buffer_size,
+            buffer_size,
+            &counters,
+            &mut rx,
+            &mut unsent,
+        )
+        .await;
+        assert!(connection.is_none());
+    }
 }
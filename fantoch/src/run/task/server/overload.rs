@@ -0,0 +1,97 @@
+use crate::run::chan::{ChannelLen, ChannelReceiver};
+use parking_lot::Mutex;
+
+/// Detects when a process is overloaded, so that new client commands can be
+/// rejected (with a `ServerToClient::Overloaded`) instead of piling up.
+///
+/// All channels between tasks are bounded: when executors or writers can't
+/// keep up, their channels fill up and the workers sending to them block,
+/// which fills up the channels from clients to workers; at this point, client
+/// tasks block as well and stop reading new commands from their connections.
+/// Besides this backpressure, the process is considered overloaded once some
+/// of the channels registered here (those to executors, writers and workers)
+/// has queued more than `threshold` of its capacity.
+#[derive(Debug)]
+pub struct Overload {
+    // fraction of its capacity a channel has to queue for the process to be
+    // overloaded; if not set, the process is never overloaded
+    threshold: Option<f64>,
+    // channels registered, along with the number of queued messages above
+    // which they're overloaded
+    channels: Mutex<Vec<(ChannelLen, usize)>>,
+}
+
+impl Overload {
+    pub fn new(threshold: Option<f64>) -> Self {
+        if let Some(threshold) = threshold {
+            assert!(
+                threshold > 0.0 && threshold <= 1.0,
+                "overload threshold should be in (0, 1]"
+            );
+        }
+        Self {
+            threshold,
+            channels: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers the channels of a pool, each with capacity `capacity`.
+    pub fn register_channels<M>(
+        &self,
+        rxs: &[ChannelReceiver<M>],
+        capacity: usize,
+    ) {
+        for rx in rxs {
+            self.register_channel(rx.queued(), capacity);
+        }
+    }
+
+    /// Registers a channel with capacity `capacity`, given the number of
+    /// messages queued in it.
+    pub fn register_channel(&self, queued: ChannelLen, capacity: usize) {
+        if let Some(threshold) = self.threshold {
+            let limit = (threshold * capacity as f64).ceil() as usize;
+            self.channels.lock().push((queued, limit));
+        }
+    }
+
+    /// Checks whether the process is overloaded.
+    pub fn is_overloaded(&self) -> bool {
+        self.channels
+            .lock()
+            .iter()
+            .any(|(queued, limit)| queued.get() >= *limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::chan;
+
+    #[tokio::test]
+    async fn overload_test() {
+        let capacity = 4;
+        let (mut tx, rx) = chan::channel(capacity);
+        let mut rxs = vec![rx];
+
+        // with no threshold, the process is never overloaded
+        let disabled = Overload::new(None);
+        disabled.register_channels(&rxs, capacity);
+
+        let overload = Overload::new(Some(0.5));
+        overload.register_channels(&rxs, capacity);
+        assert!(!overload.is_overloaded());
+
+        // the process is overloaded once half of the channel is queued
+        tx.send(1).await.expect("send should work");
+        assert!(!overload.is_overloaded());
+        tx.send(2).await.expect("send should work");
+        assert!(overload.is_overloaded());
+        assert!(!disabled.is_overloaded());
+
+        // and it's no longer overloaded once the channel drains
+        assert_eq!(rxs[0].recv().await, Some(1));
+        assert!(!overload.is_overloaded());
+    }
+}
@@ -1,41 +1,61 @@
-use super::execution_logger;
+use super::{execution_logger, supervisor};
 use crate::command::Command;
+use crate::executor::Executor;
 use crate::id::{Dot, ProcessId, ShardId};
-use crate::protocol::{Action, CommittedAndExecuted, Protocol};
+use crate::protocol::{
+    Action, CommittedAndExecuted, MessageIndex, Protocol, ProtocolMetrics,
+};
+use crate::run::affinity::{CpuAffinity, PinnedTask};
+use crate::run::chan;
 use crate::run::prelude::*;
+use crate::run::rw;
 use crate::run::task;
 use crate::time::RunTime;
+use crate::util;
 use crate::HashMap;
-use crate::{trace, warn};
+use crate::{info, trace, warn};
 use rand::Rng;
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::task::JoinHandle;
 use tokio::time;
+use tracing::{Instrument, Span};
 
 /// Starts process workers.
 pub fn start_processes<P, R>(
     process: P,
     reader_to_workers_rxs: Vec<ReaderReceiver<P>>,
+    background_reader_to_workers_rxs: Vec<ReaderReceiver<P>>,
     client_to_workers_rxs: Vec<SubmitReceiver>,
     periodic_to_workers_rxs: Vec<PeriodicEventReceiver<P, R>>,
     executors_to_workers_rxs: Vec<ExecutedReceiver>,
+    joined_to_workers_rxs: Vec<JoinedReceiver<P>>,
+    sorted_processes: Vec<(ProcessId, ShardId)>,
     to_writers: HashMap<ProcessId, Vec<WriterSender<P>>>,
     reader_to_workers: ReaderToWorkers<P>,
     to_executors: ToExecutors<P>,
     process_channel_buffer_size: usize,
     execution_log: Option<String>,
     to_metrics_logger: Option<ProtocolMetricsSender>,
+    cpu_affinity: Arc<CpuAffinity>,
+    failures: FailureSender,
+    shutdown: ShutdownReceiver,
 ) -> Vec<JoinHandle<()>>
 where
     P: Protocol + Send + 'static,
     R: Debug + Clone + Send + 'static,
 {
+    let mut execution_logger_handle = None;
     let to_execution_logger = execution_log.map(|execution_log| {
         // if the execution log was set, then start the execution logger
-        let mut tx = task::spawn_consumer(process_channel_buffer_size, |rx| {
-            execution_logger::execution_logger_task::<P>(execution_log, rx)
-        });
+        let (mut tx, rx) = chan::channel(process_channel_buffer_size);
+        execution_logger_handle =
+            Some(task::spawn(execution_logger::execution_logger_task::<P>(
+                execution_log,
+                execution_logger::EXECUTION_LOG_SEGMENT_SIZE,
+                rx,
+            )));
         tx.set_name("to_execution_logger");
         tx
     });
@@ -43,68 +63,89 @@ where
     // zip rxs'
     let incoming = reader_to_workers_rxs
         .into_iter()
-        .zip(client_to_workers_rxs.into_iter())
-        .zip(periodic_to_workers_rxs.into_iter())
-        .zip(executors_to_workers_rxs.into_iter());
+        .zip(background_reader_to_workers_rxs)
+        .zip(client_to_workers_rxs)
+        .zip(periodic_to_workers_rxs)
+        .zip(executors_to_workers_rxs)
+        .zip(joined_to_workers_rxs);
 
     // create executor workers
-    incoming
+    let mut handles: Vec<_> = incoming
         .enumerate()
         .map(
             |(
                 worker_index,
-                (((from_readers, from_clients), from_periodic), from_executors),
+                (
+                    (
+                        (
+                            (
+                                (from_readers, from_readers_background),
+                                from_clients,
+                            ),
+                            from_periodic,
+                        ),
+                        from_executors,
+                    ),
+                    from_joined,
+                ),
             )| {
                 // create task
                 let task = process_task::<P, R>(
                     worker_index,
                     process.clone(),
                     from_readers,
+                    from_readers_background,
                     from_clients,
                     from_periodic,
                     from_executors,
+                    from_joined,
+                    sorted_processes.clone(),
                     to_writers.clone(),
                     reader_to_workers.clone(),
                     to_executors.clone(),
                     to_execution_logger.clone(),
                     to_metrics_logger.clone(),
+                    shutdown.clone(),
                 );
-                task::spawn(task)
-                // // if this is a reserved worker, run it on its own runtime
-                // if worker_index < super::INDEXES_RESERVED {
-                //     let thread_name =
-                //         format!("worker_{}_runtime", worker_index);
-                //     tokio::task::spawn_blocking(|| {
-                //         // create tokio runtime
-                //         let mut runtime = tokio::runtime::Builder::new()
-                //             .threaded_scheduler()
-                //             .core_threads(1)
-                //             .thread_name(thread_name)
-                //             .build()
-                //             .expect("tokio runtime build should work");
-                //         runtime.block_on(task)
-                //     });
-                //     None
-                // } else {
-                //     Some(task::spawn(task))
-                // }
+                let span = tracing::info_span!(
+                    "worker",
+                    process = process.id(),
+                    index = worker_index
+                );
+                let task = task.instrument(span);
+                // workers can't be restarted, and thus the process shuts
+                // down if one panics
+                let task = supervisor::shutdown_on_panic(
+                    format!("worker {}", worker_index),
+                    task,
+                    failures.clone(),
+                );
+                cpu_affinity.spawn(PinnedTask::Worker, task)
             },
         )
-        .collect()
+        .collect();
+
+    // the execution logger ends once all workers end
+    handles.extend(execution_logger_handle);
+    handles
 }
 
 async fn process_task<P, R>(
     worker_index: usize,
     mut process: P,
     mut from_readers: ReaderReceiver<P>,
+    mut from_readers_background: ReaderReceiver<P>,
     mut from_clients: SubmitReceiver,
     mut from_periodic: PeriodicEventReceiver<P, R>,
     mut from_executors: ExecutedReceiver,
+    mut from_joined: JoinedReceiver<P>,
+    mut sorted_processes: Vec<(ProcessId, ShardId)>,
     mut to_writers: HashMap<ProcessId, Vec<WriterSender<P>>>,
     mut reader_to_workers: ReaderToWorkers<P>,
     mut to_executors: ToExecutors<P>,
     mut to_execution_logger: Option<ExecutionInfoSender<P>>,
     mut to_metrics_logger: Option<ProtocolMetricsSender>,
+    mut shutdown: ShutdownReceiver,
 ) where
     P: Protocol + 'static,
     R: Debug + 'static,
@@ -116,33 +157,65 @@ async fn process_task<P, R>(
     let mut interval = time::interval(super::metrics_logger::METRICS_INTERVAL);
 
     loop {
-        // TODO maybe used select_biased
+        // channels are polled in order, so that latency-critical messages
+        // (from other processes and from clients) are handled before
+        // background ones (garbage collection, periodic events and metrics);
+        // background messages can't be starved forever since, once their
+        // channels fill up, readers stop reading new messages
         tokio::select! {
+            biased;
+
+            _ = shutdown.changed() => {
+                // send the final metrics before exiting
+                metrics_tick(worker_index, process.metrics(), &mut to_metrics_logger).await;
+                info!("[server] worker {} shutting down", worker_index);
+                return;
+            }
+            joined = from_joined.recv() => {
+                selected_from_joined(worker_index, joined, &mut process, &mut sorted_processes, &mut to_writers).await
+            }
             msg = from_readers.recv() => {
                 selected_from_processes(worker_index, msg, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
             }
-            event = from_periodic.recv() => {
-                selected_from_periodic_task(worker_index, event, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
+            cmd = from_clients.recv() => {
+                selected_from_clients(worker_index, cmd, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
+            }
+            msg = from_readers_background.recv() => {
+                selected_from_processes(worker_index, msg, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
             }
             executed = from_executors.recv() => {
                 selected_from_executors(worker_index, executed, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
             }
-            cmd = from_clients.recv() => {
-                selected_from_clients(worker_index, cmd, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
+            event = from_periodic.recv() => {
+                selected_from_periodic_task(worker_index, event, &mut process, &mut to_writers, &mut reader_to_workers, &mut to_executors, &mut to_execution_logger, &time).await
             }
             _ = interval.tick()  => {
-                if let Some(to_metrics_logger) = to_metrics_logger.as_mut() {
-                    // send metrics to logger (in case there's one)
-                    let protocol_metrics = process.metrics().clone();
-                    if let Err(e) = to_metrics_logger.send((worker_index, protocol_metrics)).await {
-                        warn!("[server] error while sending metrics to metrics logger: {:?}", e);
-                    }
-                }
+                metrics_tick(worker_index, process.metrics(), &mut to_metrics_logger).await;
             }
         }
     }
 }
 
+async fn metrics_tick(
+    worker_index: usize,
+    protocol_metrics: &ProtocolMetrics,
+    to_metrics_logger: &mut Option<ProtocolMetricsSender>,
+) {
+    if let Some(to_metrics_logger) = to_metrics_logger.as_mut() {
+        // send metrics to logger (in case there's one)
+        let protocol_metrics = protocol_metrics.clone();
+        if let Err(e) = to_metrics_logger
+            .send((worker_index, protocol_metrics))
+            .await
+        {
+            warn!(
+                "[server] error while sending metrics to metrics logger: {:?}",
+                e
+            );
+        }
+    }
+}
+
 async fn selected_from_processes<P>(
     worker_index: usize,
     msg: Option<(ProcessId, ShardId, P::Message)>,
@@ -191,8 +264,22 @@ async fn handle_from_processes<P>(
 ) where
     P: Protocol + 'static,
 {
-    // handle message in process and potentially new actions
-    process.handle(from_id, from_shard_id, msg, time);
+    // handle message in process (in the span of the command it's about, if
+    // any) and potentially new actions
+    match msg
+        .dot()
+        .map(util::dot_span)
+        .filter(|span| !span.is_disabled())
+    {
+        Some(span) => span.in_scope(|| {
+            let name = util::message_name(&msg);
+            let _handle =
+                tracing::debug_span!("handle", from = from_id, msg = %name)
+                    .entered();
+            process.handle(from_id, from_shard_id, msg, time)
+        }),
+        None => process.handle(from_id, from_shard_id, msg, time),
+    }
     send_to_processes_and_executors(
         worker_index,
         process,
@@ -233,9 +320,19 @@ async fn send_to_processes_and_executors<P>(
                     .await;
                 }
 
-                // prevent unnecessary cloning of messages, since send only
-                // requires a reference to the message
-                let msg_to_send = Arc::new(POEMessage::Protocol(msg));
+                let msg = POEMessage::Protocol(msg);
+                let to_send =
+                    to_writers.keys().filter(|to| target.contains(to)).count();
+                let msg_to_send = if to_send > 1 {
+                    // if the message is sent to more than one process,
+                    // serialize it only once, sharing the serialized message
+                    // with all writers
+                    ToWriter::Serialized(rw::serialize(&msg))
+                } else {
+                    // prevent unnecessary cloning of messages, since send
+                    // only requires a reference to the message
+                    ToWriter::Message(Arc::new(msg))
+                };
 
                 // send message to writers in target
                 for (to, channels) in to_writers.iter_mut() {
@@ -265,6 +362,9 @@ async fn send_to_processes_and_executors<P>(
 
     // notify executors
     for execution_info in process.to_executors_iter() {
+        if let Some(dot) = P::Executor::execution_info_dot(&execution_info) {
+            tracing::debug!(parent: &util::dot_span(&dot), "commit");
+        }
         // if there's an execution logger, then also send execution info to it
         if let Some(to_execution_logger) = to_execution_logger {
             if let Err(e) =
@@ -308,7 +408,7 @@ async fn handle_message_from_self<P>(
 
 pub async fn send_to_one_writer<P>(
     tag: &'static str,
-    msg: Arc<POEMessage<P>>,
+    msg: ToWriter<P>,
     writers: &mut Vec<WriterSender<P>>,
 ) where
     P: Protocol + 'static,
@@ -316,7 +416,7 @@ pub async fn send_to_one_writer<P>(
     // pick a random one
     let writer_index = rand::thread_rng().gen_range(0..writers.len());
 
-    if let Err(e) = writers[writer_index].send(msg).await {
+    if let Err(e) = writers[writer_index].send((Instant::now(), msg)).await {
         warn!(
             "[{}] error while sending to writer {}: {:?}",
             tag, writer_index, e
@@ -368,8 +468,13 @@ async fn handle_from_clients<P>(
 ) where
     P: Protocol + 'static,
 {
-    // submit command in process
-    process.submit(dot, cmd, time);
+    // submit command in process (in the span of the command, if its dot is
+    // already known)
+    let span = dot.as_ref().map(util::dot_span).unwrap_or_else(Span::none);
+    span.in_scope(|| {
+        let _submit = tracing::debug_span!("submit").entered();
+        process.submit(dot, cmd, time)
+    });
     send_to_processes_and_executors(
         worker_index,
         process,
@@ -447,6 +552,20 @@ async fn handle_from_periodic_task<P, R>(
                 warn!("[server] error while sending inspect result: {:?}", e);
             }
         }
+        FromPeriodicMessage::Failure(process_id, detection) => {
+            // handle failure detection in process
+            process.handle_failure(process_id, detection, time);
+            send_to_processes_and_executors(
+                worker_index,
+                process,
+                to_writers,
+                reader_to_workers,
+                to_executors,
+                to_execution_logger,
+                time,
+            )
+            .await;
+        }
     }
 }
 
@@ -504,3 +623,64 @@ async fn handle_from_executors<P>(
     )
     .await;
 }
+
+async fn selected_from_joined<P>(
+    worker_index: usize,
+    joined: Option<Joined<P>>,
+    process: &mut P,
+    sorted_processes: &mut Vec<(ProcessId, ShardId)>,
+    to_writers: &mut HashMap<ProcessId, Vec<WriterSender<P>>>,
+) where
+    P: Protocol + 'static,
+{
+    trace!("[server] from joined: {:?}", joined);
+    if let Some((peer_id, peer_shard_id, writer, mut ack)) = joined {
+        handle_from_joined(
+            worker_index,
+            peer_id,
+            peer_shard_id,
+            writer,
+            process,
+            sorted_processes,
+            to_writers,
+        );
+        if let Err(e) = ack.send(()).await {
+            warn!("[server] error while sending joined ack: {:?}", e);
+        }
+    } else {
+        warn!("[server] error while receiving new writer to joined process");
+    }
+}
+
+fn handle_from_joined<P>(
+    worker_index: usize,
+    peer_id: ProcessId,
+    peer_shard_id: ShardId,
+    writer: WriterSender<P>,
+    process: &mut P,
+    sorted_processes: &mut Vec<(ProcessId, ShardId)>,
+    to_writers: &mut HashMap<ProcessId, Vec<WriterSender<P>>>,
+) where
+    P: Protocol + 'static,
+{
+    // save the new writer (there's one per connection to the process)
+    to_writers.entry(peer_id).or_default().push(writer);
+
+    // only discover the process once; processes from other shards are only
+    // discovered if no process from that shard is known yet, as we only
+    // connect to the closest process from each shard
+    let known = sorted_processes.iter().any(|(process_id, shard_id)| {
+        *process_id == peer_id
+            || (*shard_id == peer_shard_id && *shard_id != process.shard_id())
+    });
+    if !known {
+        // since the distance to the new process is unknown, consider it the
+        // farthest one
+        sorted_processes.push((peer_id, peer_shard_id));
+        let (connect_ok, _) = process.discover(sorted_processes.clone());
+        info!(
+            "[server] worker {} discovered process {} (connected: {})",
+            worker_index, peer_id, connect_ok
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use crate::executor::ExecutorMetrics;
+use crate::id::{ProcessId, ShardId};
+use crate::metrics::Metrics;
+use crate::protocol::ProtocolMetrics;
+use crate::run::chan::{ChannelLen, ChannelReceiver};
+use crate::run::rw::ConnectionCounters;
+use crate::run::task;
+use crate::run::task::server::health::Health;
+use crate::run::task::server::rate_limit::RateLimiter;
+use crate::HashMap;
+use crate::{info, trace, warn};
+use color_eyre::eyre::{self, Report};
+use parking_lot::Mutex;
+use std::fmt::{self, Debug, Write};
+use std::hash::Hash;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+// quantiles exported for each collected metric
+const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+// maximum size of a request's head
+const MAX_REQUEST_SIZE: usize = 4096;
+
+/// Latest protocol and executor metrics (merged from all workers and all
+/// executors), published by the metrics logger.
+pub type MetricsReceiver = watch::Receiver<(ProtocolMetrics, ExecutorMetrics)>;
+pub type MetricsSender = watch::Sender<(ProtocolMetrics, ExecutorMetrics)>;
+
+/// Values exported besides protocol and executor metrics. These are read
+/// every time the metrics endpoint is scraped.
+#[derive(Debug, Default)]
+pub struct Gauges {
+    // traffic with each process (on all connections to it)
+    connections: Mutex<HashMap<ProcessId, Arc<ConnectionCounters>>>,
+    // messages queued in each channel registered
+    channels: Mutex<Vec<(String, ChannelLen)>>,
+}
+
+impl Gauges {
+    /// Returns the counters of traffic with process `peer_id`, which should
+    /// be set on every connection to that process.
+    pub fn connection_counters(
+        &self,
+        peer_id: ProcessId,
+    ) -> Arc<ConnectionCounters> {
+        self.connections.lock().entry(peer_id).or_default().clone()
+    }
+
+    /// Registers the channels of a pool, exported as `name_index`.
+    pub fn register_channels<M>(&self, name: &str, rxs: &[ChannelReceiver<M>]) {
+        let mut channels = self.channels.lock();
+        for (index, rx) in rxs.iter().enumerate() {
+            channels.push((format!("{}_{}", name, index), rx.queued()));
+        }
+    }
+}
+
+/// Serves the metrics of this process at `/metrics`, in the Prometheus text
+/// format. Liveness and readiness probes are served at `/healthz` and
+/// `/readyz` (see `Health::ready`). The rate limit of client submissions is served at `/rate_limit`,
+/// and can be changed with `PUT /rate_limit?rate=N` (or `rate=none`, to
+/// disable it).
+pub async fn prometheus_task(
+    listener: TcpListener,
+    process_id: ProcessId,
+    shard_id: ShardId,
+    metrics: MetricsReceiver,
+    gauges: Arc<Gauges>,
+    rate_limiter: Arc<RateLimiter>,
+    health: Arc<Health>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _addr)) => {
+                trace!("[prometheus] new connection: {:?}", _addr);
+                let metrics = metrics.clone();
+                let gauges = gauges.clone();
+                let rate_limiter = rate_limiter.clone();
+                let health = health.clone();
+                task::spawn(async move {
+                    if let Err(e) = handle_request(
+                        stream,
+                        process_id,
+                        shard_id,
+                        &metrics,
+                        &gauges,
+                        &rate_limiter,
+                        &health,
+                    )
+                    .await
+                    {
+                        warn!("[prometheus] error handling request: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                warn!("[prometheus] couldn't accept new connection: {:?}", e)
+            }
+        }
+    }
+}
+
+async fn handle_request(
+    mut stream: TcpStream,
+    process_id: ProcessId,
+    shard_id: ShardId,
+    metrics: &MetricsReceiver,
+    gauges: &Gauges,
+    rate_limiter: &RateLimiter,
+    health: &Health,
+) -> Result<(), Report> {
+    // read the request's head (its body, if any, is ignored)
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            eyre::bail!("incomplete request");
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    // only `GET /metrics`, `GET /healthz`, `GET /readyz`, `GET /rate_limit`
+    // and `PUT /rate_limit` are supported
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.split_whitespace();
+    let method = request_line.next();
+    let (path, query) = match request_line.next() {
+        Some(target) => match target.split_once('?') {
+            Some((path, query)) => (Some(path), query),
+            None => (Some(target), ""),
+        },
+        None => (None, ""),
+    };
+    let response = match (method, path) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = {
+                let metrics = metrics.borrow();
+                let (protocol_metrics, executor_metrics) = &*metrics;
+                render(
+                    process_id,
+                    shard_id,
+                    protocol_metrics,
+                    executor_metrics,
+                    gauges,
+                )
+            };
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        // the process is alive as long as it answers
+        (Some("GET"), Some("/healthz")) => text_response("200 OK", "ok"),
+        (Some("GET"), Some("/readyz")) => match health.ready() {
+            Ok(()) => text_response("200 OK", "ready"),
+            Err(reason) => text_response("503 Service Unavailable", &reason),
+        },
+        (Some("GET"), Some("/rate_limit")) => {
+            rate_limit_response(rate_limiter.rate())
+        }
+        (Some("PUT"), Some("/rate_limit")) => match parse_rate(query) {
+            Some(rate) => {
+                info!("[prometheus] setting rate limit to {:?}", rate);
+                rate_limiter.set_rate(rate);
+                rate_limit_response(rate)
+            }
+            None => String::from(
+                "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            ),
+        },
+        _ => String::from(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// Parses the `rate` parameter of a query: either a positive number of commands
+// per second, or `none`.
+fn parse_rate(query: &str) -> Option<Option<u64>> {
+    let rate = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("rate="))?;
+    match rate {
+        "none" => Some(None),
+        rate => rate.parse().ok().filter(|rate| *rate > 0).map(Some),
+    }
+}
+
+fn rate_limit_response(rate: Option<u64>) -> String {
+    match rate {
+        Some(rate) => text_response("200 OK", &rate.to_string()),
+        None => text_response("200 OK", "none"),
+    }
+}
+
+fn text_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+        status,
+        body.len() + 1,
+        body
+    )
+}
+
+/// Renders all metrics in the Prometheus text format.
+fn render(
+    process_id: ProcessId,
+    shard_id: ShardId,
+    protocol_metrics: &ProtocolMetrics,
+    executor_metrics: &ExecutorMetrics,
+    gauges: &Gauges,
+) -> String {
+    let labels = format!("process=\"{}\",shard=\"{}\"", process_id, shard_id);
+    let mut out = String::new();
+    write_metrics(&mut out, "protocol", &labels, protocol_metrics)
+        .and_then(|_| {
+            write_metrics(&mut out, "executor", &labels, executor_metrics)
+        })
+        .and_then(|_| write_gauges(&mut out, &labels, gauges))
+        .expect("[prometheus] writing metrics to a string should work");
+    out
+}
+
+fn write_metrics<K>(
+    out: &mut String,
+    prefix: &str,
+    labels: &str,
+    metrics: &Metrics<K>,
+) -> fmt::Result
+where
+    K: Debug + Eq + Hash + Copy,
+{
+    // sort metrics by name so that the output is deterministic
+    let name = |kind| format!("fantoch_{}_{:?}", prefix, kind);
+    let mut aggregated: Vec<_> = metrics
+        .aggregated()
+        .map(|(kind, value)| (name(kind), *value))
+        .collect();
+    aggregated.sort();
+    let mut collected: Vec<_> = metrics
+        .collected()
+        .map(|(kind, histogram)| (name(kind), histogram))
+        .collect();
+    collected.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // aggregated metrics are exported as gauges, as some of them don't only
+    // grow (e.g. memory usage)
+    for (name, value) in aggregated {
+        writeln!(out, "# TYPE {} gauge", name)?;
+        writeln!(out, "{}{{{}}} {}", name, labels, value)?;
+    }
+
+    // collected metrics are exported as summaries
+    for (name, histogram) in collected {
+        writeln!(out, "# TYPE {} summary", name)?;
+        for quantile in QUANTILES {
+            let value = histogram.percentile(quantile).value();
+            writeln!(
+                out,
+                "{}{{{},quantile=\"{}\"}} {}",
+                name, labels, quantile, value
+            )?;
+        }
+        let sum: u64 = histogram
+            .inner()
+            .iter()
+            .map(|(value, count)| value * *count as u64)
+            .sum();
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, sum)?;
+        writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count())?;
+    }
+    Ok(())
+}
+
+fn write_gauges(
+    out: &mut String,
+    labels: &str,
+    gauges: &Gauges,
+) -> fmt::Result {
+    // traffic with each process
+    let mut connections: Vec<_> = gauges
+        .connections
+        .lock()
+        .iter()
+        .map(|(peer_id, counters)| (*peer_id, counters.clone()))
+        .collect();
+    connections.sort_by_key(|(peer_id, _)| *peer_id);
+    let counters: [(&str, fn(&ConnectionCounters) -> f64); 8] = [
+        ("sent_messages", |c| c.sent_messages() as f64),
+        ("received_messages", |c| c.received_messages() as f64),
+        ("sent_bytes", |c| c.sent_bytes() as f64),
+        ("received_bytes", |c| c.received_bytes() as f64),
+        ("serialization_seconds", |c| {
+            c.serialization_time().as_secs_f64()
+        }),
+        ("deserialization_seconds", |c| {
+            c.deserialization_time().as_secs_f64()
+        }),
+        ("queue_wait_seconds", |c| c.queue_wait().as_secs_f64()),
+        ("dropped_messages", |c| c.dropped_messages() as f64),
+    ];
+    for (counter, value) in counters.iter() {
+        writeln!(out, "# TYPE fantoch_connection_{} counter", counter)?;
+        for (peer_id, counters) in &connections {
+            writeln!(
+                out,
+                "fantoch_connection_{}{{{},peer=\"{}\"}} {}",
+                counter,
+                labels,
+                peer_id,
+                value(counters)
+            )?;
+        }
+    }
+
+    // messages queued in each channel
+    writeln!(out, "# TYPE fantoch_channel_queued_messages gauge")?;
+    for (channel, len) in gauges.channels.lock().iter() {
+        writeln!(
+            out,
+            "fantoch_channel_queued_messages{{{},channel=\"{}\"}} {}",
+            labels,
+            channel,
+            len.get()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ProtocolMetricsKind;
+    use crate::run::chan;
+    use crate::run::task::server::health::Stage;
+    use crate::run::task::server::overload::Overload;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn metrics_endpoint() {
+        // create metrics
+        let mut protocol_metrics = ProtocolMetrics::new();
+        protocol_metrics.aggregate(ProtocolMetricsKind::FastPath, 3);
+        protocol_metrics.collect(ProtocolMetricsKind::CommitLatency, 10);
+        protocol_metrics.collect(ProtocolMetricsKind::CommitLatency, 20);
+        let (_tx, metrics) =
+            watch::channel((protocol_metrics, ExecutorMetrics::new()));
+
+        // create gauges, with a message queued in a channel
+        let gauges = Arc::new(Gauges::default());
+        gauges
+            .connection_counters(2)
+            .record_queue_wait(Duration::from_millis(1500));
+        let (mut tx, rx) = chan::channel(10);
+        tx.send(()).await.expect("send should work");
+        gauges.register_channels("to_executors", &[rx]);
+
+        // start endpoint
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind should work");
+        let address = listener.local_addr().expect("address should be set");
+        let rate_limiter = Arc::new(RateLimiter::new(Some(1000)));
+        let health = Arc::new(Health::new(Arc::new(Overload::new(None))));
+        task::spawn(prometheus_task(
+            listener,
+            1,
+            0,
+            metrics,
+            gauges,
+            rate_limiter.clone(),
+            health.clone(),
+        ));
+
+        let request = |method: &'static str, path: &'static str| async move {
+            let mut stream = TcpStream::connect(address)
+                .await
+                .expect("connect should work");
+            let request =
+                format!("{} {} HTTP/1.1\r\nHost: test\r\n\r\n", method, path);
+            stream
+                .write_all(request.as_bytes())
+                .await
+                .expect("write should work");
+            let mut response = String::new();
+            stream
+                .read_to_string(&mut response)
+                .await
+                .expect("read should work");
+            response
+        };
+
+        let get = |path| request("GET", path);
+        let put = |path| request("PUT", path);
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let labels = "process=\"1\",shard=\"0\"";
+        for line in [
+            format!("fantoch_protocol_fast_path{{{}}} 3", labels),
+            format!("fantoch_protocol_commit_latency_sum{{{}}} 30", labels),
+            format!("fantoch_protocol_commit_latency_count{{{}}} 2", labels),
+            format!(
+                "fantoch_connection_sent_bytes{{{},peer=\"2\"}} 0",
+                labels
+            ),
+            format!(
+                "fantoch_connection_queue_wait_seconds{{{},peer=\"2\"}} 1.5",
+                labels
+            ),
+            format!(
+                "fantoch_connection_dropped_messages{{{},peer=\"2\"}} 0",
+                labels
+            ),
+            format!(
+                "fantoch_channel_queued_messages{{{},channel=\"to_executors_0\"}} 1",
+                labels
+            ),
+        ] {
+            assert!(response.lines().any(|l| l == line), "missing {}", line);
+        }
+
+        let response = get("/other").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+
+        // the rate limit can be read and changed
+        let response = get("/rate_limit").await;
+        assert!(response.ends_with("\r\n\r\n1000\n"));
+        let response = put("/rate_limit?rate=500").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(rate_limiter.rate(), Some(500));
+        put("/rate_limit?rate=none").await;
+        assert_eq!(rate_limiter.rate(), None);
+        for path in ["/rate_limit", "/rate_limit?rate=0", "/rate_limit?rate=x"]
+        {
+            let response = put(path).await;
+            assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+        }
+        assert_eq!(rate_limiter.rate(), None);
+
+        // the process is alive but only ready once it's running
+        let response = get("/healthz").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let response = get("/readyz").await;
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+        assert!(response.ends_with("\r\n\r\nConnecting\n"));
+        health.set_stage(Stage::Running);
+        let response = get("/readyz").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+}
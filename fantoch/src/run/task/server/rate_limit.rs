@@ -0,0 +1,123 @@
+use parking_lot::Mutex;
+use std::time::Instant;
+use tokio::time::Duration;
+
+// commands that can be submitted in a burst, as a fraction of a second's
+// worth of commands
+const BURST: f64 = 0.1;
+
+/// Token bucket limiting the number of commands per second that clients can
+/// submit to a process. Once the bucket is empty, clients have to wait for new
+/// tokens before their commands are submitted; since each client task handles
+/// its commands in order, this also delays the following commands of that
+/// client.
+///
+/// The rate is set on startup and can be changed at runtime (through the
+/// `/rate_limit` endpoint served by `prometheus_task`).
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // commands per second; if not set, commands are never limited
+    rate: Option<u64>,
+    // tokens available; this is negative when some commands are waiting for
+    // tokens
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        let limiter = Self {
+            bucket: Mutex::new(Bucket {
+                rate: None,
+                tokens: 0.0,
+                last: Instant::now(),
+            }),
+        };
+        limiter.set_rate(rate);
+        limiter
+    }
+
+    /// Returns the current rate (in commands per second).
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket.lock().rate
+    }
+
+    /// Changes the rate (in commands per second). If not set, commands are no
+    /// longer limited.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        assert_ne!(rate, Some(0), "rate limit should be positive");
+        let mut bucket = self.bucket.lock();
+        bucket.rate = rate;
+        // start with a full bucket
+        bucket.tokens = rate.map(burst).unwrap_or_default();
+        bucket.last = Instant::now();
+    }
+
+    /// Takes a token for a new command submitted at `now`, and returns how
+    /// long the command has to wait for it (if at all).
+    pub fn reserve(&self, now: Instant) -> Option<Duration> {
+        let mut bucket = self.bucket.lock();
+        let rate = bucket.rate? as f64;
+
+        // refill the bucket with the tokens generated since the last command
+        let elapsed = now.saturating_duration_since(bucket.last);
+        bucket.last = bucket.last.max(now);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate)
+            .min(burst(rate as u64));
+
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-bucket.tokens / rate))
+        }
+    }
+}
+
+fn burst(rate: u64) -> f64 {
+    (rate as f64 * BURST).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter() {
+        // with no rate, commands never wait
+        let limiter = RateLimiter::new(None);
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.reserve(now).is_none());
+        }
+
+        // with 100 commands per second, a burst of 10 commands is allowed
+        let limiter = RateLimiter::new(Some(100));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        for _ in 0..10 {
+            assert!(limiter.reserve(start).is_none());
+        }
+        // after which each command waits 10ms more than the previous one
+        assert_eq!(limiter.reserve(start), Some(Duration::from_millis(10)));
+        assert_eq!(limiter.reserve(start), Some(Duration::from_millis(20)));
+
+        // once enough time passes, commands no longer wait
+        assert!(limiter.reserve(at(30)).is_none());
+        assert!(limiter.reserve(at(1000)).is_none());
+
+        // the rate can be changed
+        limiter.set_rate(Some(10));
+        assert_eq!(limiter.rate(), Some(10));
+        let start = Instant::now();
+        assert!(limiter.reserve(start).is_none());
+        assert_eq!(limiter.reserve(start), Some(Duration::from_millis(100)));
+        limiter.set_rate(None);
+        assert!(limiter.reserve(start).is_none());
+    }
+}
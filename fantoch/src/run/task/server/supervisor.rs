@@ -0,0 +1,120 @@
+use crate::run::prelude::*;
+use crate::warn;
+use futures::future::FutureExt;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use tokio::time::{self, Duration};
+
+// when a restartable task panics, it's restarted after
+// `RESTART_INITIAL_BACKOFF`, doubling the backoff after each panic (up to
+// `RESTART_MAX_BACKOFF`)
+const RESTART_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const RESTART_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Runs the task created by `task`, creating and running it again each time
+/// it panics. This is used for tasks whose state survives a panic (e.g.
+/// writers, whose channel from workers is kept by `task`). Ends once the task
+/// ends without panicking.
+pub async fn restart_on_panic<F, T>(name: String, mut task: F)
+where
+    F: FnMut() -> T,
+    T: Future<Output = ()>,
+{
+    let mut backoff = RESTART_INITIAL_BACKOFF;
+    loop {
+        match AssertUnwindSafe(task()).catch_unwind().await {
+            Ok(()) => return,
+            Err(panic) => {
+                warn!(
+                    "[supervisor] {} panicked: {}; restarting it in {:?}",
+                    name,
+                    panic_message(&panic),
+                    backoff
+                );
+                time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, RESTART_MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Runs `task`, notifying `failures` if it panics so that the process shuts
+/// down. This is used for tasks whose state is lost on a panic (e.g. workers
+/// and executors), and thus can't be restarted.
+pub async fn shutdown_on_panic<T>(
+    name: String,
+    task: T,
+    mut failures: FailureSender,
+) where
+    T: Future<Output = ()>,
+{
+    if let Err(panic) = AssertUnwindSafe(task).catch_unwind().await {
+        warn!(
+            "[supervisor] {} panicked: {}; shutting down",
+            name,
+            panic_message(&panic)
+        );
+        if let Err(e) = failures.send(name).await {
+            warn!("[supervisor] error while notifying failure: {:?}", e);
+        }
+    }
+}
+
+/// Runs `task`, logging if it panics. This is used for readers: once a reader
+/// panics its connection is closed, and thus a new reader is started once the
+/// process on the other end reconnects.
+pub async fn log_panic<T>(name: String, task: T)
+where
+    T: Future<Output = ()>,
+{
+    if let Err(panic) = AssertUnwindSafe(task).catch_unwind().await {
+        warn!("[supervisor] {} panicked: {}", name, panic_message(&panic));
+    }
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::chan;
+
+    #[tokio::test]
+    async fn supervisor_test() {
+        // a restartable task is restarted until it no longer panics
+        let mut runs = 0;
+        restart_on_panic(String::from("task"), || {
+            runs += 1;
+            let runs = runs;
+            async move {
+                if runs < 3 {
+                    panic!("run {}", runs);
+                }
+            }
+        })
+        .await;
+        assert_eq!(runs, 3);
+
+        // unrecoverable tasks notify their failure when they panic
+        let (failures_tx, mut failures) = chan::channel(1);
+        shutdown_on_panic(String::from("ok"), async {}, failures_tx.clone())
+            .await;
+        shutdown_on_panic(
+            String::from("failed"),
+            async { panic!("failed") },
+            failures_tx,
+        )
+        .await;
+        assert_eq!(failures.recv().await, Some(String::from("failed")));
+        assert_eq!(failures.recv().await, None);
+    }
+}
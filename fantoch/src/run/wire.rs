@@ -0,0 +1,186 @@
+// Wire protocol spoken between clients and processes.
+//
+// This is the protocol used by `task::client::Session` (and thus by all
+// clients in this crate), and it's specified here so that programs not
+// linked with this crate can also talk to a fantoch deployment.
+//
+// # Framing
+//
+// Each message is sent in its own frame: a 4-byte big-endian length header
+// followed by that many bytes, which are the CRC-32 (IEEE) checksum of the
+// payload (4 bytes, big-endian) followed by the payload. Frames between
+// clients and processes are never compressed. Frames whose checksum doesn't
+// match their payload close the connection.
+//
+// # Encoding
+//
+// Payloads are encoded with `bincode` (version 1, default configuration):
+// - integers are encoded in little-endian with their fixed size (`usize` is
+//   encoded as a `u64`), and booleans as a single byte (`0` or `1`)
+// - strings, byte strings (e.g. `Value`), sequences and maps are encoded as
+//   their length (a `u64`) followed by each of their elements (maps as
+//   key-value pairs)
+// - `Option`s are encoded as a byte (`0` for `None`, `1` for `Some`)
+//   followed by the value, if any
+// - structs are encoded as each of their fields, in declaration order
+// - enums are encoded as the index of the variant (a `u32`, starting at 0)
+//   followed by the fields of the variant, if any
+//
+// The layout of each payload follows from its definition: messages are
+// `ClientToServer` and `ServerToClient`, and these carry `Command`,
+// `CommandResult` and `WatchNotification` (along with the types they refer
+// to). For example, the `Rifl` of a command is encoded as the id of the
+// client (a `u64`) followed by the sequence number of the command (a `u64`).
+//
+// # Session
+//
+// 1. After connecting, the client sends a `ClientToServer::Hello` with the
+//    version of the protocol it speaks (`VERSION`) and the ids of the clients
+//    that will share the connection.
+// 2. The process answers with a `ServerToClient::Hello`, containing its id and
+//    the shard it replicates, or with a `ServerToClient::Error` (after which
+//    it closes the connection) if the client's `Hello` is not accepted.
+// 3. The client then submits commands (and watches keys); these are answered
+//    with `ServerToClient::Result`s (and `ServerToClient::Notification`s).
+//    Results are not necessarily sent in the order commands were submitted.
+// 4. If the process is overloaded, it may reject a `Submit` with a
+//    `ServerToClient::Overloaded` holding the `Rifl` of the command. The
+//    command was not submitted, and the client should submit it again later
+//    (`Register`s sent to other shards remain valid, and don't have to be sent
+//    again).
+// 5. To amortize the cost of each frame, the client may submit several
+//    commands in a single `ClientToServer::SubmitMany`, which is handled as a
+//    `Submit` of each of them (in order). Each command is still answered with
+//    its own `ServerToClient::Result` (or `ServerToClient::Overloaded`). If
+//    any of them is on behalf of a client without a session, the message is
+//    answered with an error, and none of them is submitted.
+//
+// Each client id is a session of the connection: commands (and watches) can
+// only be sent on behalf of a client that has a session, and the commands of
+// all sessions can be interleaved. Besides the ones in the `Hello`, sessions
+// can be opened with a `ClientToServer::Open`, and closed with a
+// `ClientToServer::Close` (which also removes the watches of the client). A
+// client id can only have one session per process, and a session can only be
+// closed once all its commands have completed. An `Open` (or `Close`) with
+// any id that can't be opened (or closed) is answered with an error, and
+// opens (or closes) no session.
+//
+// A command that accesses more than one shard is sent to a process of each
+// of these shards (using one connection per shard): as a `Submit` to one of
+// them and as a `Register` to the others. The client then receives a
+// `CommandResult` from each shard, holding the results of the keys in that
+// shard. The `Register`s should be sent before the `Submit`.
+//
+// Messages that are not expected by the process (e.g. a second `Hello`) are
+// answered with a `ServerToClient::Error`; in this case, the connection is
+// not closed. Frames that can't be decoded close the connection.
+
+use crate::command::{Command, CommandResult};
+use crate::id::{ClientId, ProcessId, Rifl, ShardId};
+use crate::kvs::{Key, WatchNotification};
+use serde::{Deserialize, Serialize};
+
+/// Version of the protocol specified in this module. It should be bumped every
+/// time the encoding of the messages changes.
+pub const VERSION: u32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientToServer {
+    // the first message sent by clients
+    Hello {
+        version: u32,
+        client_ids: Vec<ClientId>,
+    },
+    // submit a command to be executed
+    Submit(Command),
+    // register a command submitted to another shard
+    Register(Command),
+    // watch and unwatch keys in the shard of the process; each write on a
+    // watched key is notified to the client (until it unwatches the key or
+    // disconnects)
+    Watch(ClientId, Key),
+    Unwatch(ClientId, Key),
+    // open and close sessions for more clients on this connection
+    Open(Vec<ClientId>),
+    Close(Vec<ClientId>),
+    // submit several commands to be executed, as if each was submitted with
+    // its own `Submit`
+    SubmitMany(Vec<Command>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerToClient {
+    // the answer to the client's `Hello`
+    Hello {
+        process_id: ProcessId,
+        shard_id: ShardId,
+    },
+    // the result of a command
+    Result(CommandResult),
+    // a write on a key watched by some client
+    Notification(ClientId, WatchNotification),
+    // an error that occurred while handling a message from the client
+    Error(String),
+    // a command rejected because the process is overloaded
+    Overloaded(Rifl),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs::KVOp;
+    use crate::run::rw;
+
+    #[test]
+    fn encoding() {
+        // check that a `Hello` is encoded as specified
+        let hello = ClientToServer::Hello {
+            version: VERSION,
+            client_ids: vec![7],
+        };
+        let mut expected = Vec::new();
+        // variant index
+        expected.extend_from_slice(&0u32.to_le_bytes());
+        // version
+        expected.extend_from_slice(&VERSION.to_le_bytes());
+        // client ids
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(rw::serialize(&hello).as_ref(), expected.as_slice());
+
+        // check that a `Submit` starts with its variant index and its rifl
+        let rifl = Rifl::new(7, 1);
+        let cmd = Command::from(rifl, vec![(String::from("A"), KVOp::Get)]);
+        let submit = rw::serialize(&ClientToServer::Submit(cmd.clone()));
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        assert!(submit.starts_with(&expected));
+
+        // check that an `Open` is encoded as its variant index followed by the
+        // client ids
+        let open = ClientToServer::Open(vec![8, 9]);
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&5u32.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&8u64.to_le_bytes());
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        assert_eq!(rw::serialize(&open).as_ref(), expected.as_slice());
+
+        // check that a `SubmitMany` starts with its variant index, the number
+        // of commands and the rifl of the first one
+        let other = Command::from(
+            Rifl::new(7, 2),
+            vec![(String::from("B"), KVOp::Get)],
+        );
+        let submit_many =
+            rw::serialize(&ClientToServer::SubmitMany(vec![cmd, other]));
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&7u32.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        assert!(submit_many.starts_with(&expected));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::protocol::Protocol;
+use crate::sim::{Runner, Scenario};
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Checkpoint of the simulation of a `Scenario`, from which the simulation
+/// can be resumed (see `Checkpoint::restore`).
+///
+/// Since simulations are deterministic, a checkpoint doesn't store the state of
+/// the processes, executors, schedule and clients; instead, it stores the
+/// scenario and the number of events processed, and the state is restored by
+/// replaying those events. This means that checkpoints are small and don't
+/// depend on the protocol, but that restoring one takes as long as simulating
+/// up to it (without the stop conditions, the event trace and the history
+/// recorded by the runner, which are not part of the checkpoint).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    scenario: Scenario,
+    // number of events processed by the runner (see `Runner::events`)
+    events: u64,
+    // simulated time (in milliseconds) and commands completed at the
+    // checkpoint, used to detect a replay that diverged from the simulation
+    time: u64,
+    commands_completed: u64,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint of `runner`, which should have been created with
+    /// `Scenario::runner` for `scenario`.
+    pub fn new<P: Protocol>(scenario: &Scenario, runner: &Runner<P>) -> Self {
+        Self {
+            scenario: scenario.clone(),
+            events: runner.events(),
+            time: runner.sim_time(),
+            commands_completed: runner.commands_completed(),
+        }
+    }
+
+    /// Returns the scenario being simulated.
+    pub fn scenario(&self) -> &Scenario {
+        &self.scenario
+    }
+
+    /// Returns the number of events processed at the checkpoint.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Returns the simulated time at the checkpoint.
+    pub fn time(&self) -> Duration {
+        Duration::from_millis(self.time)
+    }
+
+    /// Brings `runner`, created with `Scenario::runner` for the scenario of
+    /// the checkpoint (and not run yet), to the state of the simulation at the
+    /// checkpoint. Calling `Runner::run` afterwards resumes the simulation.
+    pub fn restore<P: Protocol>(
+        &self,
+        runner: &mut Runner<P>,
+    ) -> Result<(), Report> {
+        if runner.events() != 0 {
+            eyre::bail!("the runner to restore has already run");
+        }
+        let replayed =
+            runner.replay(self.events, self.scenario.extra_sim_time());
+        if !replayed
+            || runner.sim_time() > self.time
+            || runner.commands_completed() != self.commands_completed
+        {
+            eyre::bail!(
+                "the simulation diverged from the checkpoint at {}ms (was it taken with a different version?)",
+                self.time
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes the checkpoint to file `path` (as JSON). The file is replaced
+    /// atomically, so that a previous checkpoint in `path` survives a crash
+    /// while writing.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let file = std::fs::File::create(&tmp)
+            .wrap_err_with(|| format!("create checkpoint {:?}", tmp))?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .wrap_err("serialize checkpoint")?;
+        writer.flush().wrap_err("write checkpoint")?;
+        std::fs::rename(&tmp, path)
+            .wrap_err_with(|| format!("replace checkpoint {:?}", path))
+    }
+
+    /// Loads the checkpoint stored in file `path` (see `Checkpoint::write`).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("read checkpoint {:?}", path))?;
+        serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("parse checkpoint {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+
+    const SCENARIO: &str = r#"{
+        "protocol": "Basic",
+        "process_regions": ["asia-east1", "us-central1", "us-west1"],
+        "client_regions": ["us-west1", "us-west2"],
+        "clients_per_region": 2,
+        "config": {
+            "n": 3,
+            "f": 1,
+            "gc_interval": {"secs": 0, "nanos": 100000000}
+        },
+        "workload": {
+            "key_gen": {"ConflictPool": {"conflict_rate": 10, "pool_size": 1}},
+            "keys_per_command": 1,
+            "commands_per_client": 100
+        },
+        "faults": [
+            {"type": "crash", "process_id": 1, "at": 2000}
+        ],
+        "extra_sim_time": 1000,
+        "seed": 7
+    }"#;
+
+    #[test]
+    fn checkpoint_and_restore() {
+        let scenario: Scenario = serde_json::from_str(SCENARIO).unwrap();
+        let expected = scenario.run::<Basic>().unwrap();
+
+        // stop the simulation after 3 seconds and checkpoint it
+        let mut runner = scenario.runner::<Basic>().unwrap();
+        runner.set_max_sim_time(Duration::from_secs(3));
+        runner.run(scenario.extra_sim_time());
+        assert!(runner.stopped().is_some());
+        let checkpoint = Checkpoint::new(&scenario, &runner);
+        assert!(checkpoint.events() > 0);
+        assert!(checkpoint.time() > Duration::from_secs(3));
+
+        // write and load the checkpoint
+        let path = std::env::temp_dir().join("fantoch_checkpoint_test");
+        checkpoint.write(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, checkpoint);
+
+        // resuming the simulation from the checkpoint is the same as never
+        // stopping it
+        let mut restored = loaded.scenario().runner::<Basic>().unwrap();
+        loaded.restore(&mut restored).unwrap();
+        assert_eq!(restored.events(), checkpoint.events());
+        restored.run(scenario.extra_sim_time());
+        assert_eq!(restored.stopped(), None);
+        assert_eq!(restored.simulation_metrics(), expected);
+
+        // and so is continuing the stopped simulation
+        runner.set_max_sim_time(Duration::from_secs(3600));
+        runner.run(scenario.extra_sim_time());
+        assert_eq!(runner.stopped(), None);
+        assert_eq!(runner.simulation_metrics(), expected);
+
+        // a runner that has already run can't be restored
+        assert!(checkpoint.restore(&mut runner).is_err());
+
+        // a checkpoint past the end of the simulation can't be restored
+        let mut diverged = checkpoint.clone();
+        diverged.events = runner.events() + 1;
+        let mut fresh = scenario.runner::<Basic>().unwrap();
+        assert!(diverged.restore(&mut fresh).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::planet::Region;
+use crate::sim::{RegionMetrics, Scenario, SimulationMetrics};
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+#[cfg(feature = "parallel-sim")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+/// Comparison of several protocols in the same `Scenario`. Since all
+/// protocols are simulated with the scenario, they are simulated with the same
+/// clients, workload, faults and seed, and so each client submits the same
+/// commands in all of them.
+pub struct Comparison {
+    scenario: Scenario,
+    protocols: Vec<String>,
+}
+
+impl Comparison {
+    /// Creates a new `Comparison` of `protocols` in `scenario` (whose own
+    /// protocol is ignored).
+    pub fn new<S>(scenario: Scenario, protocols: Vec<S>) -> Self
+    where
+        S: Into<String>,
+    {
+        let protocols = protocols.into_iter().map(Into::into).collect();
+        Self {
+            scenario,
+            protocols,
+        }
+    }
+
+    /// Returns the names of the protocols compared.
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols
+    }
+
+    /// Simulates the scenario with each protocol. Since protocols are only
+    /// names, `run` is called with the scenario of each protocol (see
+    /// `Scenario::protocol`) and should simulate it (e.g. with
+    /// `Scenario::run`).
+    pub fn run<F>(&self, run: F) -> Result<ComparisonReport, Report>
+    where
+        F: Fn(&Scenario) -> Result<SimulationMetrics, Report> + Sync,
+    {
+        let simulate = |protocol: &String| {
+            let mut scenario = self.scenario.clone();
+            scenario.set_protocol(protocol.clone());
+            let metrics = run(&scenario)
+                .wrap_err_with(|| format!("simulate {}", protocol))?;
+            Ok((protocol.clone(), metrics))
+        };
+
+        #[cfg(feature = "parallel-sim")]
+        let metrics: Result<Vec<_>, Report> =
+            self.protocols.par_iter().map(simulate).collect();
+        #[cfg(not(feature = "parallel-sim"))]
+        let metrics: Result<Vec<_>, Report> =
+            self.protocols.iter().map(simulate).collect();
+
+        Ok(ComparisonReport::new(metrics?))
+    }
+}
+
+/// Latency (in milliseconds) observed by the clients in some region with some
+/// protocol, in a `ComparisonReport`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonRow {
+    protocol: String,
+    // if not set, the row is about the clients in all regions
+    region: Option<Region>,
+    issued_commands: usize,
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    p99_9: f64,
+}
+
+impl ComparisonRow {
+    fn new(
+        protocol: &str,
+        region: Option<Region>,
+        issued_commands: usize,
+        metrics: &RegionMetrics,
+    ) -> Self {
+        let latency = metrics.latency();
+        Self {
+            protocol: protocol.to_string(),
+            region,
+            issued_commands,
+            mean: latency.mean().value(),
+            p50: latency.percentile(0.5).value(),
+            p95: latency.percentile(0.95).value(),
+            p99: latency.percentile(0.99).value(),
+            p99_9: latency.percentile(0.999).value(),
+        }
+    }
+
+    /// Returns the name of the protocol.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Returns the region of the clients, or `None` if the row is about the
+    /// clients in all regions.
+    pub fn region(&self) -> Option<&Region> {
+        self.region.as_ref()
+    }
+
+    /// Returns the number of commands issued by the clients.
+    pub fn issued_commands(&self) -> usize {
+        self.issued_commands
+    }
+
+    /// Returns the mean latency.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the 50th percentile of the latency.
+    pub fn p50(&self) -> f64 {
+        self.p50
+    }
+
+    /// Returns the 95th percentile of the latency.
+    pub fn p95(&self) -> f64 {
+        self.p95
+    }
+
+    /// Returns the 99th percentile of the latency.
+    pub fn p99(&self) -> f64 {
+        self.p99
+    }
+
+    /// Returns the 99.9th percentile of the latency.
+    pub fn p99_9(&self) -> f64 {
+        self.p99_9
+    }
+}
+
+/// The results of a `Comparison`: the metrics of each protocol, and the
+/// latency of the clients in each region with each protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    // sorted by region (with the rows about all regions last) and then by the
+    // order in which protocols were given
+    rows: Vec<ComparisonRow>,
+    metrics: Vec<(String, SimulationMetrics)>,
+}
+
+impl ComparisonReport {
+    fn new(metrics: Vec<(String, SimulationMetrics)>) -> Self {
+        let mut rows = Vec::new();
+        for (index, (protocol, simulation_metrics)) in
+            metrics.iter().enumerate()
+        {
+            // only regions with clients have latencies
+            for (region, region_metrics) in simulation_metrics.regions() {
+                let issued_commands = region_metrics.issued_commands();
+                if issued_commands > 0 {
+                    let row = ComparisonRow::new(
+                        protocol,
+                        Some(region.clone()),
+                        issued_commands,
+                        region_metrics,
+                    );
+                    rows.push((index, row));
+                }
+            }
+            let total = simulation_metrics.total();
+            let row = ComparisonRow::new(
+                protocol,
+                None,
+                total.issued_commands(),
+                &total,
+            );
+            rows.push((index, row));
+        }
+        rows.sort_by(|(a_index, a), (b_index, b)| {
+            let a_key = (a.region.is_none(), &a.region, a_index);
+            let b_key = (b.region.is_none(), &b.region, b_index);
+            a_key.cmp(&b_key)
+        });
+        let rows = rows.into_iter().map(|(_, row)| row).collect();
+        Self { rows, metrics }
+    }
+
+    /// Returns the metrics of protocol `protocol` (if it was compared).
+    pub fn get(&self, protocol: &str) -> Option<&SimulationMetrics> {
+        self.metrics
+            .iter()
+            .find(|(name, _)| name == protocol)
+            .map(|(_, metrics)| metrics)
+    }
+
+    /// Returns the latency of the clients in each region (and in all regions)
+    /// with each protocol.
+    pub fn rows(&self) -> &[ComparisonRow] {
+        &self.rows
+    }
+
+    /// Writes the report to file `path` (as JSON).
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create comparison report {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .wrap_err("serialize comparison report")?;
+        writer.flush().wrap_err("write comparison report")
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:<10} {:>8} {:>7} {:>7} {:>7} {:>7} {:>7}",
+            "region", "protocol", "issued", "avg", "p50", "p95", "p99", "p99.9"
+        )?;
+        for row in &self.rows {
+            let region = row.region.as_ref().map_or("all", |r| r.name());
+            writeln!(
+                f,
+                "{:<24} {:<10} {:>8} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                region,
+                row.protocol,
+                row.issued_commands,
+                row.mean.round(),
+                row.p50.round(),
+                row.p95.round(),
+                row.p99.round(),
+                row.p99_9.round(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+    use color_eyre::eyre;
+
+    const SCENARIO: &str = r#"{
+        "protocol": "Tempo",
+        "process_regions": ["asia-east1", "us-central1", "us-west1"],
+        "client_regions": ["us-west1", "us-west2"],
+        "clients_per_region": 2,
+        "config": {
+            "n": 3,
+            "f": 1,
+            "gc_interval": {"secs": 0, "nanos": 100000000}
+        },
+        "workload": {
+            "key_gen": {"ConflictPool": {"conflict_rate": 10, "pool_size": 1}},
+            "keys_per_command": 1,
+            "commands_per_client": 50
+        },
+        "seed": 5
+    }"#;
+
+    #[test]
+    fn comparison_report() {
+        let scenario: Scenario = serde_json::from_str(SCENARIO).unwrap();
+        let comparison = Comparison::new(scenario, vec!["Basic", "Copy"]);
+        assert_eq!(comparison.protocols(), ["Basic", "Copy"]);
+
+        // simulate both protocols with `Basic`
+        let report = comparison
+            .run(|scenario| match scenario.protocol() {
+                "Basic" | "Copy" => scenario.run::<Basic>(),
+                protocol => eyre::bail!("unsupported protocol {}", protocol),
+            })
+            .unwrap();
+        let basic = report.get("Basic").unwrap();
+        assert_eq!(basic.total().issued_commands(), 50 * 2 * 2);
+        assert_eq!(report.get("Copy"), Some(basic));
+        assert!(report.get("Tempo").is_none());
+
+        // two regions with clients plus all regions, for each protocol, with
+        // the protocols side by side
+        let rows: Vec<_> = report
+            .rows()
+            .iter()
+            .map(|row| {
+                (row.region().map(|r| r.name().as_str()), row.protocol())
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (Some("us-west1"), "Basic"),
+                (Some("us-west1"), "Copy"),
+                (Some("us-west2"), "Basic"),
+                (Some("us-west2"), "Copy"),
+                (None, "Basic"),
+                (None, "Copy"),
+            ]
+        );
+        let all = &report.rows()[4];
+        assert_eq!(all.issued_commands(), 50 * 2 * 2);
+        assert!(all.p50() <= all.p95() && all.p95() <= all.p99());
+        assert!(all.p99() <= all.p99_9());
+        assert!(all.mean() > 0.0);
+        assert_eq!(report.to_string().lines().count(), 1 + rows.len());
+
+        // errors are reported with the protocol that failed
+        let comparison = Comparison::new(
+            serde_json::from_str(SCENARIO).unwrap(),
+            vec!["Basic", "Unknown"],
+        );
+        let error = comparison
+            .run(|scenario| match scenario.protocol() {
+                "Basic" => scenario.run::<Basic>(),
+                protocol => eyre::bail!("unsupported protocol {}", protocol),
+            })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "simulate Unknown");
+    }
+}
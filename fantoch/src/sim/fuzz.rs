@@ -0,0 +1,406 @@
+use crate::command::Command;
+use crate::config::Config;
+use crate::executor::Executor;
+use crate::id::{ProcessId, Rifl, ShardId};
+use crate::kvs::{KVOpResult, Key};
+use crate::protocol::{Action, Protocol};
+use crate::sim::Simulation;
+use crate::util;
+use crate::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+// Something that can be delivered to a process by the fuzzer.
+enum Delivery<Message> {
+    Submit(ProcessId, Command),
+    Message(ProcessId, ShardId, ProcessId, Message),
+}
+
+/// A violation of the invariants checked by the `Fuzzer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Two processes returned different results for command `rifl` on key
+    /// `key`.
+    Results { rifl: Rifl, key: Key },
+    /// Two processes executed the commands on key `key` in different orders.
+    Order { key: Key },
+    /// Process `process_id` didn't execute all commands.
+    Convergence { process_id: ProcessId },
+}
+
+/// A fuzzer that runs the processes of a `Protocol` (in a single shard) by
+/// delivering their messages in a random order, possibly more than once, and
+/// after a random number of steps. Each step either delivers a message (or a
+/// command submitted with `Fuzzer::submit`) picked at random from the ones in
+/// flight, or triggers a periodic event (or an executed notification) of a
+/// random process. All these choices are derived from the seed of the
+/// fuzzer, and so runs with the same seed make the same choices.
+///
+/// Messages are only delivered after being sent (and each of their copies as
+/// well), and messages from a process to itself are delivered immediately,
+/// as in `Runner`.
+pub struct Fuzzer<P: Protocol> {
+    simulation: Simulation<P>,
+    // processes' identifiers, sorted
+    process_ids: Vec<ProcessId>,
+    // periodic events of each process
+    periodic_events: HashMap<ProcessId, Vec<P::PeriodicEvent>>,
+    // messages (and commands) not yet delivered
+    in_flight: Vec<Delivery<P::Message>>,
+    // keys accessed by each command submitted
+    submitted: HashMap<Rifl, Vec<Key>>,
+    // results of the commands executed by each process
+    results: HashMap<ProcessId, HashMap<(Rifl, Key), Vec<KVOpResult>>>,
+    // probability of a message delivered staying in flight (and thus being
+    // delivered again)
+    duplicate_probability: f64,
+    // probability of a step triggering a periodic event (when there are
+    // messages in flight)
+    event_probability: f64,
+    rng: StdRng,
+}
+
+impl<P> Fuzzer<P>
+where
+    P: Protocol,
+{
+    /// Creates a new `Fuzzer` with `config.n()` processes. By default,
+    /// messages are not duplicated and 10% of the steps trigger a periodic
+    /// event.
+    pub fn new(config: Config, seed: u64) -> Self {
+        assert_eq!(config.shard_count(), 1, "only one shard is supported");
+        let shard_id = 0;
+        let process_ids: Vec<_> =
+            util::process_ids(shard_id, config.n()).collect();
+
+        let mut simulation = Simulation::new();
+        let mut periodic_events = HashMap::new();
+        for process_id in process_ids.iter().cloned() {
+            let (mut process, events) = P::new(process_id, shard_id, config);
+            periodic_events.insert(
+                process_id,
+                events.into_iter().map(|(event, _)| event).collect(),
+            );
+
+            // discover all processes: self first, and then the others by the
+            // order of their identifiers
+            let processes = std::iter::once(process_id)
+                .chain(
+                    process_ids.iter().cloned().filter(|id| *id != process_id),
+                )
+                .map(|process_id| (process_id, shard_id))
+                .collect();
+            let (connect_ok, _) = process.discover(processes);
+            assert!(connect_ok);
+
+            let executor =
+                <P::Executor as Executor>::new(process_id, shard_id, config);
+            simulation.register_process(process, executor);
+        }
+
+        let results = process_ids
+            .iter()
+            .map(|process_id| (*process_id, HashMap::new()))
+            .collect();
+        Self {
+            simulation,
+            process_ids,
+            periodic_events,
+            in_flight: Vec::new(),
+            submitted: HashMap::new(),
+            results,
+            duplicate_probability: 0.0,
+            event_probability: 0.1,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Delivers each message again (after a random number of steps) with
+    /// probability `probability`, and so a message can be delivered any
+    /// number of times.
+    pub fn duplicate_messages(&mut self, probability: f64) {
+        assert!((0.0..1.0).contains(&probability));
+        self.duplicate_probability = probability;
+    }
+
+    /// Sets the probability of each step triggering a periodic event.
+    pub fn set_event_probability(&mut self, probability: f64) {
+        assert!((0.0..=1.0).contains(&probability));
+        self.event_probability = probability;
+    }
+
+    /// Submits `cmd` to process `process_id`. As messages, commands are
+    /// submitted after a random number of steps.
+    pub fn submit(&mut self, process_id: ProcessId, cmd: Command) {
+        assert!(self.process_ids.contains(&process_id));
+        let keys = cmd.keys(0).cloned().collect();
+        let res = self.submitted.insert(cmd.rifl(), keys);
+        assert!(res.is_none(), "commands should only be submitted once");
+        self.in_flight.push(Delivery::Submit(process_id, cmd));
+    }
+
+    /// Runs the fuzzer for at most `max_steps` steps, or until there are no
+    /// messages in flight and all processes executed all commands submitted.
+    /// After each step, it checks that processes returned the same results
+    /// for the same commands, and once the run ends, that all processes
+    /// executed all commands, and in the same order (if executors monitor
+    /// the execution order). Returns the number of steps taken.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize, Violation> {
+        let mut steps = 0;
+        while steps < max_steps
+            && !(self.in_flight.is_empty() && self.converged())
+        {
+            steps += 1;
+            self.simulation.time().add_millis(1);
+
+            // if there are no messages in flight, only periodic events can
+            // make progress
+            let event = self.in_flight.is_empty()
+                || self.rng.gen_bool(self.event_probability);
+            if event {
+                self.trigger_event()?;
+            } else {
+                self.deliver()?;
+            }
+        }
+
+        // check that all processes executed all commands
+        if let Some(process_id) = self
+            .process_ids
+            .iter()
+            .find(|process_id| !self.executed_all(process_id))
+        {
+            return Err(Violation::Convergence {
+                process_id: *process_id,
+            });
+        }
+        self.check_order()?;
+        Ok(steps)
+    }
+
+    /// Returns the results of the commands executed by process `process_id`,
+    /// per key.
+    pub fn results(
+        &self,
+        process_id: ProcessId,
+    ) -> &HashMap<(Rifl, Key), Vec<KVOpResult>> {
+        self.results
+            .get(&process_id)
+            .expect("process results should exist")
+    }
+
+    // Delivers a message (or command) picked at random from the ones in
+    // flight, (maybe) keeping a copy of it.
+    fn deliver(&mut self) -> Result<(), Violation> {
+        let index = self.rng.gen_range(0..self.in_flight.len());
+        let delivery = self.in_flight.swap_remove(index);
+        match delivery {
+            Delivery::Submit(process_id, cmd) => {
+                let (process, _, _, time) =
+                    self.simulation.get_process(process_id);
+                process.submit(None, cmd, time);
+                self.handle_actions(process_id)
+            }
+            Delivery::Message(from, from_shard_id, to, msg) => {
+                if self.rng.gen_bool(self.duplicate_probability) {
+                    let copy =
+                        Delivery::Message(from, from_shard_id, to, msg.clone());
+                    self.in_flight.push(copy);
+                }
+                let (process, _, _, time) = self.simulation.get_process(to);
+                process.handle(from, from_shard_id, msg, time);
+                self.handle_actions(to)
+            }
+        }
+    }
+
+    // Triggers a periodic event (or an executed notification) of a process
+    // picked at random.
+    fn trigger_event(&mut self) -> Result<(), Violation> {
+        let process_id =
+            self.process_ids[self.rng.gen_range(0..self.process_ids.len())];
+        let events = self
+            .periodic_events
+            .get(&process_id)
+            .expect("process periodic events should exist");
+        // the last index is the executed notification
+        let index = self.rng.gen_range(0..=events.len());
+        let event = events.get(index).cloned();
+
+        let (process, executor, _, time) =
+            self.simulation.get_process(process_id);
+        match event {
+            Some(event) => process.handle_event(event, time),
+            None => {
+                if let Some(executed) = executor.executed(time) {
+                    process.handle_executed(executed, time);
+                }
+            }
+        }
+        self.handle_actions(process_id)
+    }
+
+    // Handles the new actions of process `process_id`: messages to itself are
+    // delivered immediately, messages to other processes are put in flight,
+    // and new execution info is executed.
+    fn handle_actions(
+        &mut self,
+        process_id: ProcessId,
+    ) -> Result<(), Violation> {
+        let mut to_self = VecDeque::new();
+        loop {
+            let (process, executor, _, time) =
+                self.simulation.get_process(process_id);
+            let shard_id = process.shard_id();
+
+            for action in process.to_processes_iter() {
+                match action {
+                    Action::ToSend { target, msg } => {
+                        let mut target: Vec<_> = target.into_iter().collect();
+                        target.sort_unstable();
+                        for to in target {
+                            if to == process_id {
+                                to_self.push_back(msg.clone());
+                            } else {
+                                self.in_flight.push(Delivery::Message(
+                                    process_id,
+                                    shard_id,
+                                    to,
+                                    msg.clone(),
+                                ));
+                            }
+                        }
+                    }
+                    Action::ToForward { msg } => to_self.push_back(msg),
+                }
+            }
+
+            let mut executor_results = Vec::new();
+            for info in process.to_executors_iter() {
+                executor.handle(info, time);
+                // handling executor messages to self may lead to new ones
+                while let Some((_, info)) = executor.to_executors() {
+                    executor.handle(info, time);
+                }
+                executor_results.extend(executor.to_clients_iter());
+            }
+
+            // check that the results are the same as the ones returned by
+            // other processes
+            for result in executor_results {
+                let rifl = result.rifl;
+                let key = result.key;
+                let agree = self.results.values().all(|results| {
+                    results
+                        .get(&(rifl, key.clone()))
+                        .is_none_or(|other| other == &result.partial_results)
+                });
+                if !agree {
+                    return Err(Violation::Results { rifl, key });
+                }
+                self.results
+                    .get_mut(&process_id)
+                    .expect("process results should exist")
+                    .insert((rifl, key), result.partial_results);
+            }
+
+            match to_self.pop_front() {
+                Some(msg) => {
+                    let (process, _, _, time) =
+                        self.simulation.get_process(process_id);
+                    process.handle(process_id, shard_id, msg, time);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Checks whether process `process_id` executed all commands submitted.
+    fn executed_all(&self, process_id: &ProcessId) -> bool {
+        let results = self
+            .results
+            .get(process_id)
+            .expect("process results should exist");
+        self.submitted.iter().all(|(rifl, keys)| {
+            keys.iter()
+                .all(|key| results.contains_key(&(*rifl, key.clone())))
+        })
+    }
+
+    // Checks whether all processes executed all commands submitted.
+    fn converged(&self) -> bool {
+        self.process_ids
+            .iter()
+            .all(|process_id| self.executed_all(process_id))
+    }
+
+    // Checks that all processes executed the commands on each key in the
+    // same order (if executors monitor the execution order).
+    fn check_order(&mut self) -> Result<(), Violation> {
+        let mut monitors = Vec::with_capacity(self.process_ids.len());
+        for process_id in self.process_ids.clone() {
+            let (_, executor, _, _) = self.simulation.get_process(process_id);
+            if let Some(monitor) = executor.monitor() {
+                monitors.push(monitor);
+            }
+        }
+        if let Some((first, others)) = monitors.split_first() {
+            for key in first.keys() {
+                let order = first.get_order(key);
+                if others.iter().any(|other| other.get_order(key) != order) {
+                    return Err(Violation::Order { key: key.clone() });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::RiflGen;
+    use crate::kvs::KVOp;
+    use crate::protocol::Basic;
+
+    fn add(rifl: Rifl, key: &str) -> Command {
+        Command::from(rifl, vec![(String::from(key), KVOp::Add(1))])
+    }
+
+    #[test]
+    fn fuzz_basic_non_conflicting() {
+        let config = Config::new(3, 1);
+        let mut rifl_gen = RiflGen::new(1);
+        // with commands on different keys, processes always agree
+        for seed in 0..20 {
+            let mut fuzzer = Fuzzer::<Basic>::new(config, seed);
+            for (process_id, key) in [(1, "A"), (2, "B"), (3, "C")] {
+                fuzzer.submit(process_id, add(rifl_gen.next_id(), key));
+            }
+            let result = fuzzer.run(1000);
+            assert!(result.is_ok(), "seed {}: {:?}", seed, result);
+        }
+    }
+
+    #[test]
+    fn fuzz_basic_conflicting() {
+        let config = Config::new(3, 1);
+
+        // `Basic` doesn't order conflicting commands, and so some seed should
+        // lead processes to execute them in different orders
+        let violations = (0..20)
+            .filter(|seed| {
+                let mut fuzzer = Fuzzer::<Basic>::new(config, *seed);
+                let mut rifl_gen_a = RiflGen::new(1);
+                let mut rifl_gen_b = RiflGen::new(2);
+                fuzzer.submit(1, add(rifl_gen_a.next_id(), "A"));
+                fuzzer.submit(2, add(rifl_gen_b.next_id(), "A"));
+                let result = fuzzer.run(1000);
+                assert!(!matches!(result, Err(Violation::Convergence { .. })));
+                result.is_err()
+            })
+            .count();
+        assert!(violations > 0);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::executor::ExecutionOrderMonitor;
+use crate::id::ProcessId;
+use crate::sim::trace::{EventTrace, TraceEvent};
+use crate::HashMap;
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Environment variable that, when set, makes `Golden::verify` (re)write
+/// golden histories instead of checking them.
+pub const UPDATE_GOLDEN_ENV: &str = "FANTOCH_UPDATE_GOLDEN";
+
+/// History of a seeded simulation: the events in its trace (see
+/// `Runner::record_trace`) and a digest of the final state of each process,
+/// i.e. the order in which it executed commands on each key (see
+/// `Config::set_executor_monitor_execution_order`).
+///
+/// Golden histories are recorded once and then compared with the history of
+/// the same simulation in the current code (see `Golden::verify`), which
+/// detects unintended changes in the behavior of protocols and executors.
+///
+/// As the order of the events that happen at the same time depends on the
+/// iteration order of hash maps, which changes across runs, those events are
+/// sorted, and the identifiers of messages are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Golden {
+    digests: BTreeMap<ProcessId, u64>,
+    events: Vec<TraceEvent>,
+}
+
+impl Golden {
+    /// Creates the history of a simulation given its event trace and the
+    /// execution order monitors of its processes (as returned by
+    /// `Runner::run`). Processes without a monitor have no digest.
+    pub fn new(
+        trace: &EventTrace,
+        monitors: &HashMap<ProcessId, Option<ExecutionOrderMonitor>>,
+    ) -> Self {
+        let digests = monitors
+            .iter()
+            .filter_map(|(process_id, monitor)| {
+                monitor
+                    .as_ref()
+                    .map(|monitor| (*process_id, Self::digest(monitor)))
+            })
+            .collect();
+        let mut events: Vec<_> = trace
+            .events()
+            .iter()
+            .cloned()
+            .map(|mut event| {
+                if let TraceEvent::Send { id, .. }
+                | TraceEvent::Receive { id, .. } = &mut event
+                {
+                    *id = 0;
+                }
+                event
+            })
+            .collect();
+        events
+            .sort_by_cached_key(|event| (event.time(), format!("{:?}", event)));
+        Self { digests, events }
+    }
+
+    /// Returns the events in the history.
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Returns the digest of the final state of process `process_id` (if it
+    /// monitored the execution order).
+    pub fn digest_of(&self, process_id: ProcessId) -> Option<u64> {
+        self.digests.get(&process_id).cloned()
+    }
+
+    /// Checks that `current` is the same history as this one, returning an
+    /// error that describes the first divergence otherwise.
+    pub fn check(&self, current: &Self) -> Result<(), Report> {
+        let diverged = self
+            .events
+            .iter()
+            .zip(current.events.iter())
+            .enumerate()
+            .find(|(_, (golden, current))| golden != current);
+        if let Some((index, (golden, current))) = diverged {
+            eyre::bail!(
+                "event {} diverged: expected {:?}, got {:?}",
+                index,
+                golden,
+                current
+            );
+        }
+        if self.events.len() != current.events.len() {
+            eyre::bail!(
+                "expected {} events, got {}",
+                self.events.len(),
+                current.events.len()
+            );
+        }
+        if self.digests != current.digests {
+            eyre::bail!(
+                "final state diverged: expected digests {:?}, got {:?}",
+                self.digests,
+                current.digests
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks this history against the golden one stored in file `path`. If
+    /// `FANTOCH_UPDATE_GOLDEN` is set, the golden history is (re)written
+    /// instead.
+    pub fn verify(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            return self.write(path);
+        }
+        let golden = Self::load(path).wrap_err_with(|| {
+            format!(
+                "load golden history (set {} to record it)",
+                UPDATE_GOLDEN_ENV
+            )
+        })?;
+        golden.check(self).wrap_err_with(|| {
+            format!(
+                "history diverged from {:?} (set {} if the change is intended)",
+                path, UPDATE_GOLDEN_ENV
+            )
+        })
+    }
+
+    /// Writes the history to file `path`, as JSON with the digests in the
+    /// first line and then one event per line.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create golden history {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &self.digests)
+            .wrap_err("serialize digests")?;
+        writeln!(writer).wrap_err("write golden history")?;
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, event)
+                .wrap_err("serialize trace event")?;
+            writeln!(writer).wrap_err("write golden history")?;
+        }
+        writer.flush().wrap_err("write golden history")
+    }
+
+    /// Loads the history stored in file `path` (see `Golden::write`).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .wrap_err_with(|| format!("open golden history {:?}", path))?;
+        let mut lines = std::io::BufReader::new(file).lines();
+        let digests = match lines.next() {
+            Some(line) => serde_json::from_str(&line.wrap_err("read digests")?)
+                .wrap_err("parse digests")?,
+            None => eyre::bail!("golden history {:?} is empty", path),
+        };
+        let mut events = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line.wrap_err("read golden history")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = serde_json::from_str(&line).wrap_err_with(|| {
+                format!("parse line {} of golden history", index + 2)
+            })?;
+            events.push(event);
+        }
+        Ok(Self { digests, events })
+    }
+
+    // Computes the FNV-1a hash of the execution order on each key (sorted by
+    // key), which, unlike `DefaultHasher`, is stable across Rust releases.
+    fn digest(monitor: &ExecutionOrderMonitor) -> u64 {
+        let mut keys: Vec<_> = monitor.keys().collect();
+        keys.sort();
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut update = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        for key in keys {
+            update(key.as_bytes());
+            let order = monitor.get_order(key).expect("key should exist");
+            for rifl in order {
+                update(&rifl.source().to_le_bytes());
+                update(&rifl.sequence().to_le_bytes());
+            }
+            // separate the order on each key
+            update(&[0]);
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::Rifl;
+
+    #[test]
+    fn check_and_verify() {
+        let mut monitor = ExecutionOrderMonitor::new();
+        monitor.add(&String::from("A"), false, Rifl::new(1, 1));
+        monitor.add(&String::from("A"), false, Rifl::new(2, 1));
+        let mut monitors = HashMap::new();
+        monitors.insert(1, Some(monitor));
+        monitors.insert(2, None);
+
+        let mut trace = EventTrace::new();
+        let rifl = Rifl::new(1, 1);
+        trace.record(TraceEvent::Submit {
+            time: 0,
+            rifl,
+            process_id: 1,
+        });
+        trace.record(TraceEvent::Result { time: 10, rifl });
+        let golden = Golden::new(&trace, &monitors);
+        assert!(golden.digest_of(1).is_some());
+        assert!(golden.digest_of(2).is_none());
+        assert!(golden.check(&golden).is_ok());
+
+        // a different event diverges
+        let mut current = golden.clone();
+        current.events[1] = TraceEvent::Result { time: 20, rifl };
+        assert!(golden.check(&current).is_err());
+
+        // and so does a missing event
+        current.events.pop();
+        assert!(golden.check(&current).is_err());
+
+        // and a different execution order
+        let mut monitor = ExecutionOrderMonitor::new();
+        monitor.add(&String::from("A"), false, Rifl::new(2, 1));
+        monitor.add(&String::from("A"), false, Rifl::new(1, 1));
+        monitors.insert(1, Some(monitor));
+        let current = Golden::new(&trace, &monitors);
+        assert_ne!(golden.digest_of(1), current.digest_of(1));
+        assert!(golden.check(&current).is_err());
+
+        // check that the history is the same after writing and loading it
+        let path = std::env::temp_dir().join("fantoch_golden_test");
+        golden
+            .write(&path)
+            .expect("golden history should be written");
+        let loaded = Golden::load(&path).expect("golden should be loaded");
+        assert_eq!(loaded, golden);
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_none() {
+            assert!(golden.verify(&path).is_ok());
+            assert!(current.verify(&path).is_err());
+        }
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::command::{Command, CommandResult, Consistency};
+use crate::id::Rifl;
+use crate::kvs::{self, KVOp, KVOpError, KVOpResult, Key, Value};
+use crate::{HashMap, HashSet};
+
+/// History of the operations submitted by clients in a simulation: for each
+/// command, when it was invoked and when it returned (if it did), along with
+/// its ops and their results. The history can then be checked for
+/// linearizability (see `History::check_linearizability`).
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    // logical time of the last event recorded (invocations and responses are
+    // ordered by this time, and so two events never happen at the same time)
+    time: u64,
+    // mapping from command identifier to its operation
+    operations: HashMap<Rifl, Operation>,
+    // keys accessed by some command with ops that can't be checked, i.e. ops
+    // whose results depend on something other than the key's value (see
+    // `History::supported`)
+    unsupported: HashSet<Key>,
+}
+
+#[derive(Debug, Clone)]
+struct Operation {
+    invocation: u64,
+    response: Option<u64>,
+    ops: HashMap<Key, Vec<KVOp>>,
+    results: Option<HashMap<Key, Vec<KVOpResult>>>,
+}
+
+impl History {
+    /// Creates an empty `History`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the invocation of a command. Commands submitted again (e.g.
+    /// after a timeout) keep their first invocation. Reads with eventual
+    /// consistency are not recorded, since they're not meant to be
+    /// linearizable.
+    pub fn invoke(&mut self, cmd: &Command) {
+        if cmd.consistency() == Consistency::Eventual
+            || self.operations.contains_key(&cmd.rifl())
+        {
+            return;
+        }
+        let ops: HashMap<_, _> = cmd
+            .shards()
+            .flat_map(|shard_id| cmd.iter(*shard_id))
+            .map(|(key, ops)| (key.clone(), ops.as_ref().clone()))
+            .collect();
+        // if some op of the command can't be checked, none of the keys it
+        // accesses can; and the same if the command has more than one key and
+        // some op that may fail, since then the results on each key depend on
+        // the others (see `Command::execute`)
+        let conditional =
+            ops.len() > 1 && ops.values().flatten().any(KVOp::is_conditional);
+        if conditional || !ops.values().flatten().all(Self::supported) {
+            self.unsupported.extend(ops.keys().cloned());
+        }
+        self.time += 1;
+        self.operations.insert(
+            cmd.rifl(),
+            Operation {
+                invocation: self.time,
+                response: None,
+                ops,
+                results: None,
+            },
+        );
+    }
+
+    /// Records the response of a command. Only the first response of each
+    /// command is recorded.
+    pub fn respond(&mut self, cmd_result: &CommandResult) {
+        self.time += 1;
+        if let Some(operation) = self.operations.get_mut(&cmd_result.rifl()) {
+            if operation.response.is_none() {
+                operation.response = Some(self.time);
+                operation.results = Some(cmd_result.results().clone());
+            }
+        }
+    }
+
+    /// Returns the number of commands recorded.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Checks if no command was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Checks whether the history is linearizable, using the algorithm by Wing
+    /// and Gong (with the state caching by Lowe). Since linearizability is
+    /// compositional, each key is checked separately, and the ops of a command
+    /// on a key are seen as a single operation on that key. Commands that
+    /// didn't return may or may not have taken effect.
+    ///
+    /// Keys accessed by commands with ops whose results depend on more than
+    /// the value of the key (i.e. scans, validations, versioned reads, opaque
+    /// ops and puts with a TTL) are not checked.
+    ///
+    /// Returns the number of keys checked, or the first key (by order) whose
+    /// history is not linearizable.
+    pub fn check_linearizability(&self) -> Result<usize, Key> {
+        // split operations per key
+        let mut key_to_operations: HashMap<&Key, Vec<KeyOperation<'_>>> =
+            HashMap::new();
+        for operation in self.operations.values() {
+            for (key, ops) in operation.ops.iter() {
+                if self.unsupported.contains(key) {
+                    continue;
+                }
+                let results = operation.results.as_ref().map(|results| {
+                    results.get(key).expect("there should be a result per key")
+                });
+                key_to_operations
+                    .entry(key)
+                    .or_default()
+                    .push(KeyOperation {
+                        invocation: operation.invocation,
+                        response: operation.response.unwrap_or(u64::MAX),
+                        ops,
+                        results,
+                    });
+            }
+        }
+
+        let mut keys: Vec<_> = key_to_operations.keys().cloned().collect();
+        keys.sort_unstable();
+        for key in keys.iter() {
+            let mut operations = key_to_operations
+                .remove(key)
+                .expect("key should have operations");
+            operations.sort_unstable_by_key(|operation| operation.invocation);
+            if !linearizable(&operations) {
+                return Err((*key).clone());
+            }
+        }
+        Ok(keys.len())
+    }
+
+    // Checks if the result of `op` depends only on the value of its key.
+    fn supported(op: &KVOp) -> bool {
+        matches!(
+            op,
+            KVOp::Get
+                | KVOp::Put(_)
+                | KVOp::Delete
+                | KVOp::Cas { .. }
+                | KVOp::PutIfAbsent(_)
+                | KVOp::Add(_)
+                | KVOp::Append(_)
+        )
+    }
+}
+
+// The ops of a command on some key.
+struct KeyOperation<'a> {
+    invocation: u64,
+    // commands that didn't return never return
+    response: u64,
+    ops: &'a Vec<KVOp>,
+    results: Option<&'a Vec<KVOpResult>>,
+}
+
+// Checks if the operations on a key (sorted by invocation) are linearizable,
+// i.e. if there's a sequential order of the operations that returned (and
+// maybe some of the ones that didn't) that respects their real-time order and
+// produces the same results. The search linearizes one operation at a time,
+// and backtracks whenever no operation can be linearized next; the
+// combinations of operations linearized and the value they produce are cached
+// so that they're never explored twice.
+//
+// Reads that return the current value are linearized as soon as possible and
+// are never backtracked: since they don't change the value, and since they can
+// only be linearized once invoked before all other operations returned,
+// linearizing them later never allows more operations to be linearized.
+fn linearizable(operations: &[KeyOperation<'_>]) -> bool {
+    let returned = operations
+        .iter()
+        .filter(|operation| operation.results.is_some())
+        .count();
+    let mut linearized = vec![false; operations.len()];
+    let mut linearized_bits = vec![0u64; operations.len().div_ceil(64)];
+    let mut linearized_returned = 0;
+    let mut cache = HashSet::new();
+    let mut value = None;
+    // stack with the operations linearized, the value before each of them,
+    // and whether they were linearized eagerly
+    let mut stack: Vec<(usize, Option<Value>, bool)> = Vec::new();
+    // index of the first operation to be tried next
+    let mut next = 0;
+
+    while linearized_returned < returned {
+        // operations can only be linearized if invoked before all the other
+        // operations (not yet linearized) returned
+        let min_response = operations
+            .iter()
+            .zip(linearized.iter())
+            .filter(|(_, linearized)| !**linearized)
+            .map(|(operation, _)| operation.response)
+            .min()
+            .expect("there should be operations to linearize");
+        let candidates: Vec<_> = operations
+            .iter()
+            .enumerate()
+            .take_while(|(_, operation)| operation.invocation < min_response)
+            .filter(|(index, _)| !linearized[*index])
+            .filter_map(|(index, operation)| {
+                let (new_value, results) = apply(&value, operation.ops);
+                // operations that didn't return can have any result
+                let valid = operation
+                    .results
+                    .is_none_or(|expected| *expected == results);
+                let read = operation.ops.iter().all(|op| op == &KVOp::Get);
+                valid.then_some((index, new_value, read))
+            })
+            .collect();
+
+        // checks if linearizing an operation leads to a combination not yet
+        // explored, caching it if so
+        let mut unexplored = |index: usize, new_value: &Option<Value>| {
+            let mut bits = linearized_bits.clone();
+            bits[index / 64] |= 1 << (index % 64);
+            cache.insert((bits, new_value.clone()))
+        };
+
+        // if some read can be linearized, linearize it (unless that was
+        // already explored, in which case there's nothing else to explore);
+        // otherwise, linearize the next operation that can be linearized
+        let found = match candidates.iter().find(|(_, _, read)| *read) {
+            Some((index, new_value, _)) => {
+                if unexplored(*index, new_value) {
+                    Some((*index, new_value.clone(), true))
+                } else {
+                    None
+                }
+            }
+            None => candidates
+                .into_iter()
+                .filter(|(index, _, _)| *index >= next)
+                .find(|(index, new_value, _)| unexplored(*index, new_value)),
+        };
+
+        match found {
+            Some((index, new_value, eager)) => {
+                linearized[index] = true;
+                linearized_bits[index / 64] |= 1 << (index % 64);
+                if operations[index].results.is_some() {
+                    linearized_returned += 1;
+                }
+                stack.push((
+                    index,
+                    std::mem::replace(&mut value, new_value),
+                    eager,
+                ));
+                next = 0;
+            }
+            None => {
+                // undo the operations linearized up to the last one that
+                // wasn't linearized eagerly, and try the operation after it
+                loop {
+                    let (index, previous, eager) = match stack.pop() {
+                        Some(entry) => entry,
+                        None => return false,
+                    };
+                    linearized[index] = false;
+                    linearized_bits[index / 64] &= !(1 << (index % 64));
+                    if operations[index].results.is_some() {
+                        linearized_returned -= 1;
+                    }
+                    value = previous;
+                    if !eager {
+                        next = index + 1;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+// Applies `ops` to a key with value `value`, returning the new value and the
+// results of the ops (as `KVStore` would). If some op fails, none of them is
+// applied.
+fn apply(
+    value: &Option<Value>,
+    ops: &[KVOp],
+) -> (Option<Value>, Vec<KVOpResult>) {
+    let previous = value;
+    let mut value = value.clone();
+    let results: Vec<_> = ops
+        .iter()
+        .map(|op| {
+            let result = match op {
+                KVOp::Get => value.clone(),
+                KVOp::Put(new) => {
+                    value = Some(new.clone());
+                    None
+                }
+                KVOp::Delete => value.take(),
+                KVOp::Cas { expected, new } => {
+                    if value.as_ref() != Some(expected) {
+                        return KVOpResult::Error(KVOpError::CasMismatch {
+                            current: value.clone(),
+                        });
+                    }
+                    value.replace(new.clone())
+                }
+                KVOp::PutIfAbsent(new) => {
+                    if let Some(current) = value.as_ref() {
+                        return KVOpResult::Error(KVOpError::KeyExists {
+                            current: current.clone(),
+                        });
+                    }
+                    value = Some(new.clone());
+                    None
+                }
+                KVOp::Add(delta) => {
+                    let counter = match value.as_ref() {
+                        Some(current) => match kvs::value_to_counter(current) {
+                            Some(counter) => counter,
+                            None => {
+                                return KVOpResult::Error(
+                                    KVOpError::TypeMismatch {
+                                        current: current.clone(),
+                                    },
+                                );
+                            }
+                        },
+                        None => 0,
+                    };
+                    let new =
+                        kvs::counter_to_value(counter.wrapping_add(*delta));
+                    value = Some(new.clone());
+                    Some(new)
+                }
+                KVOp::Append(suffix) => {
+                    value = Some(match value.take() {
+                        Some(current) => {
+                            Value::from([current.as_ref(), suffix].concat())
+                        }
+                        None => suffix.clone(),
+                    });
+                    None
+                }
+                op => panic!("op {:?} can't be checked", op),
+            };
+            KVOpResult::Value(result)
+        })
+        .collect();
+    let failure = results
+        .iter()
+        .enumerate()
+        .find_map(|(index, result)| Some((index, result.error()?.clone())));
+    match failure {
+        Some(failure) => {
+            (previous.clone(), kvs::aborted(ops.len(), Some(failure)))
+        }
+        None => (value, results),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(rifl: Rifl, key: &str, op: KVOp) -> Command {
+        Command::from(rifl, vec![(String::from(key), op)])
+    }
+
+    fn result(rifl: Rifl, key: &str, result: Option<&str>) -> CommandResult {
+        let result = KVOpResult::Value(
+            result.map(|value| Value::from(value.to_string())),
+        );
+        let mut results = HashMap::new();
+        results.insert(String::from(key), vec![result]);
+        CommandResult::new(rifl, results)
+    }
+
+    fn put(value: &str) -> KVOp {
+        KVOp::Put(Value::from(value.to_string()))
+    }
+
+    #[test]
+    fn sequential_history() {
+        let (a, b, c) = (Rifl::new(1, 1), Rifl::new(1, 2), Rifl::new(1, 3));
+        let mut history = History::new();
+        history.invoke(&cmd(a, "A", put("x")));
+        history.respond(&result(a, "A", None));
+        history.invoke(&cmd(b, "A", KVOp::Get));
+        history.respond(&result(b, "A", Some("x")));
+        history.invoke(&cmd(c, "B", KVOp::Get));
+        history.respond(&result(c, "B", None));
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.check_linearizability(), Ok(2));
+
+        // reading a stale value after the write returned is not linearizable
+        let d = Rifl::new(2, 1);
+        history.invoke(&cmd(d, "A", KVOp::Get));
+        history.respond(&result(d, "A", None));
+        assert_eq!(history.check_linearizability(), Err(String::from("A")));
+    }
+
+    #[test]
+    fn concurrent_history() {
+        let (a, b, c) = (Rifl::new(1, 1), Rifl::new(2, 1), Rifl::new(3, 1));
+
+        // a read concurrent with a write can read either value
+        for read in [None, Some("x")] {
+            let mut history = History::new();
+            history.invoke(&cmd(a, "A", put("x")));
+            history.invoke(&cmd(b, "A", KVOp::Get));
+            history.respond(&result(b, "A", read));
+            history.respond(&result(a, "A", None));
+            assert_eq!(history.check_linearizability(), Ok(1));
+        }
+
+        // two reads can't observe two concurrent writes in different orders
+        let (d, e) = (Rifl::new(4, 1), Rifl::new(5, 1));
+        let mut history = History::new();
+        history.invoke(&cmd(a, "A", put("x")));
+        history.invoke(&cmd(b, "A", put("y")));
+        history.respond(&result(a, "A", None));
+        history.respond(&result(b, "A", None));
+        history.invoke(&cmd(c, "A", KVOp::Get));
+        history.respond(&result(c, "A", Some("x")));
+        history.invoke(&cmd(d, "A", KVOp::Get));
+        history.respond(&result(d, "A", Some("y")));
+        assert_eq!(history.check_linearizability(), Err(String::from("A")));
+
+        // a write that never returned may or may not take effect
+        let mut history = History::new();
+        history.invoke(&cmd(a, "A", put("x")));
+        history.invoke(&cmd(b, "A", KVOp::Get));
+        history.respond(&result(b, "A", None));
+        history.invoke(&cmd(c, "A", KVOp::Get));
+        history.respond(&result(c, "A", Some("x")));
+        history.invoke(&cmd(e, "A", KVOp::Get));
+        history.respond(&result(e, "A", Some("x")));
+        assert_eq!(history.check_linearizability(), Ok(1));
+    }
+
+    #[test]
+    fn unsupported_history() {
+        let a = Rifl::new(1, 1);
+        let mut history = History::new();
+        history.invoke(&cmd(a, "A", KVOp::GetVersioned));
+        history.respond(&result(a, "A", Some("x")));
+        // keys with unsupported ops are not checked
+        assert_eq!(history.check_linearizability(), Ok(0));
+    }
+}
@@ -0,0 +1,148 @@
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal, Normal};
+
+/// Distribution of the latency of the messages sent on a link, around the
+/// latency between the regions of its endpoints (i.e. half the ping latency
+/// in `Planet`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatencyDistribution {
+    /// All messages have the same latency.
+    Fixed,
+    /// Latencies are normally distributed around the latency of the link, with
+    /// standard deviation `stddev` (in milliseconds).
+    Normal { stddev: f64 },
+    /// Latencies are log-normally distributed with median equal to the latency
+    /// of the link, where `sigma` is the standard deviation of their logarithm
+    /// (and thus the higher it is, the longer the tail).
+    LogNormal { sigma: f64 },
+}
+
+/// Model of the latency of the messages sent on a link: each message has a
+/// latency sampled from some distribution, and may be delayed further by a
+/// latency spike. Since latencies are sampled for each message, messages may
+/// be reordered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyModel {
+    distribution: LatencyDistribution,
+    // probability of a latency spike, and how much (in milliseconds) it delays
+    // the message
+    spikes: Option<(f64, u64)>,
+}
+
+impl LatencyModel {
+    /// Creates a model where all messages have the latency of the link.
+    pub fn fixed() -> Self {
+        Self::new(LatencyDistribution::Fixed)
+    }
+
+    /// Creates a model with latencies distributed by `distribution`.
+    pub fn new(distribution: LatencyDistribution) -> Self {
+        match distribution {
+            LatencyDistribution::Fixed => {}
+            LatencyDistribution::Normal { stddev } => {
+                assert!(stddev >= 0.0, "stddev should be non-negative");
+            }
+            LatencyDistribution::LogNormal { sigma } => {
+                assert!(sigma >= 0.0, "sigma should be non-negative");
+            }
+        }
+        Self {
+            distribution,
+            spikes: None,
+        }
+    }
+
+    /// Delays each message by `delay` milliseconds with probability
+    /// `probability`.
+    pub fn with_spikes(mut self, probability: f64, delay: u64) -> Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.spikes = Some((probability, delay));
+        self
+    }
+
+    /// Samples the latency (in milliseconds) of a message sent on a link with
+    /// latency `latency`.
+    pub fn sample<R: Rng>(&self, latency: u64, rng: &mut R) -> u64 {
+        let mean = latency as f64;
+        let sampled = match self.distribution {
+            LatencyDistribution::Fixed => mean,
+            LatencyDistribution::Normal { stddev } => Normal::new(mean, stddev)
+                .expect("normal distribution should be valid")
+                .sample(rng),
+            LatencyDistribution::LogNormal { sigma } => {
+                // a link with no latency has no jitter
+                if latency == 0 {
+                    0.0
+                } else {
+                    LogNormal::new(mean.ln(), sigma)
+                        .expect("log-normal distribution should be valid")
+                        .sample(rng)
+                }
+            }
+        };
+        // latencies can't be negative
+        let mut latency = sampled.max(0.0).round() as u64;
+
+        if let Some((probability, delay)) = self.spikes {
+            if rng.gen_bool(probability) {
+                latency += delay;
+            }
+        }
+        latency
+    }
+}
+
+impl Default for LatencyModel {
+    fn default() -> Self {
+        Self::fixed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn samples(model: LatencyModel, latency: u64, seed: u64) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..1000).map(|_| model.sample(latency, &mut rng)).collect()
+    }
+
+    fn mean(samples: &[u64]) -> f64 {
+        samples.iter().sum::<u64>() as f64 / samples.len() as f64
+    }
+
+    #[test]
+    fn latency_model() {
+        // fixed latencies
+        let fixed = samples(LatencyModel::fixed(), 50, 0);
+        assert!(fixed.iter().all(|latency| *latency == 50));
+
+        // normally distributed latencies vary around the latency of the link
+        let normal =
+            LatencyModel::new(LatencyDistribution::Normal { stddev: 5.0 });
+        let normal_samples = samples(normal, 50, 0);
+        assert!(normal_samples.iter().any(|latency| *latency != 50));
+        assert!((mean(&normal_samples) - 50.0).abs() < 1.0);
+
+        // log-normally distributed latencies have the latency of the link as
+        // their median, and a longer tail above it
+        let lognormal =
+            LatencyModel::new(LatencyDistribution::LogNormal { sigma: 0.5 });
+        let mut lognormal_samples = samples(lognormal, 50, 0);
+        lognormal_samples.sort_unstable();
+        let median = lognormal_samples[lognormal_samples.len() / 2];
+        assert!((45..=55).contains(&median));
+        assert!(mean(&lognormal_samples) > 50.0);
+
+        // all messages are delayed by spikes that always occur
+        let spikes =
+            samples(LatencyModel::fixed().with_spikes(1.0, 100), 50, 0);
+        assert!(spikes.iter().all(|latency| *latency == 150));
+
+        // the same seed gives the same latencies
+        assert_eq!(normal_samples, samples(normal, 50, 0));
+        assert_ne!(normal_samples, samples(normal, 50, 1));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::id::{Dot, ProcessId, Rifl};
+use crate::sim::EventTrace;
+use crate::HashMap;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::Path;
+
+/// A message about a dot received by the coordinator of the dot (e.g. a reply
+/// from a quorum member).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DotReply {
+    /// Time at which the message was received.
+    pub time: u64,
+    /// Process that sent the message.
+    pub from: ProcessId,
+    /// Type of the message (e.g. `MCollectAck`).
+    pub kind: String,
+}
+
+/// Lifecycle of a command (identified by its dot) in a simulation, recorded
+/// with `Runner::record_dots`. Times are in milliseconds of simulated time.
+/// The coordinator of a dot is the process that assigned it (i.e.
+/// `dot.source()`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DotLifecycle {
+    dot: Dot,
+    // command submitted by a client that was assigned the dot, and when it was
+    // submitted (if the dot was assigned while the command was submitted)
+    rifl: Option<Rifl>,
+    submit: Option<u64>,
+    replies: Vec<DotReply>,
+    // mapping from process to the time at which it committed the dot (i.e.
+    // sent it to its executor), and at which it executed the dot
+    commits: BTreeMap<ProcessId, u64>,
+    executions: BTreeMap<ProcessId, u64>,
+    // time at which the result of the command arrived at its client
+    result: Option<u64>,
+}
+
+impl DotLifecycle {
+    fn new(dot: Dot) -> Self {
+        Self {
+            dot,
+            rifl: None,
+            submit: None,
+            replies: Vec::new(),
+            commits: BTreeMap::new(),
+            executions: BTreeMap::new(),
+            result: None,
+        }
+    }
+
+    /// Returns the dot.
+    pub fn dot(&self) -> Dot {
+        self.dot
+    }
+
+    /// Returns the command that was assigned the dot (if known).
+    pub fn rifl(&self) -> Option<Rifl> {
+        self.rifl
+    }
+
+    /// Returns when the command was submitted at the coordinator (if known).
+    pub fn submit(&self) -> Option<u64> {
+        self.submit
+    }
+
+    /// Returns the messages about the dot that the coordinator received from
+    /// other processes, in the order they were received.
+    pub fn replies(&self) -> &[DotReply] {
+        &self.replies
+    }
+
+    /// Returns when the coordinator received `count` messages of type `kind`
+    /// about the dot (e.g. when a quorum of `MCollectAck`s was gathered).
+    pub fn replies_at(&self, kind: &str, count: usize) -> Option<u64> {
+        self.replies
+            .iter()
+            .filter(|reply| reply.kind == kind)
+            .nth(count.checked_sub(1)?)
+            .map(|reply| reply.time)
+    }
+
+    /// Returns when process `process_id` committed the dot (if it did).
+    pub fn commit(&self, process_id: ProcessId) -> Option<u64> {
+        self.commits.get(&process_id).cloned()
+    }
+
+    /// Returns when each process committed the dot.
+    pub fn commits(&self) -> &BTreeMap<ProcessId, u64> {
+        &self.commits
+    }
+
+    /// Returns when process `process_id` executed the dot (if it did).
+    pub fn execution(&self, process_id: ProcessId) -> Option<u64> {
+        self.executions.get(&process_id).cloned()
+    }
+
+    /// Returns when each process executed the dot.
+    pub fn executions(&self) -> &BTreeMap<ProcessId, u64> {
+        &self.executions
+    }
+
+    /// Returns when the result of the command arrived at its client (if it
+    /// did).
+    pub fn result(&self) -> Option<u64> {
+        self.result
+    }
+}
+
+/// The lifecycles of all dots in a simulation (see `Runner::record_dots`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DotLifecycles {
+    dots: HashMap<Dot, DotLifecycle>,
+    // mapping from command to its dot
+    rifl_to_dot: HashMap<Rifl, Dot>,
+    // command being submitted (and when), to be assigned the next new dot of
+    // its process
+    submitting: Option<(ProcessId, Rifl, u64)>,
+}
+
+impl DotLifecycles {
+    /// Creates empty `DotLifecycles`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the lifecycle of dot `dot` (if it was recorded).
+    pub fn get(&self, dot: &Dot) -> Option<&DotLifecycle> {
+        self.dots.get(dot)
+    }
+
+    /// Returns the lifecycle of the dot assigned to command `rifl` (if it was
+    /// recorded).
+    pub fn get_by_rifl(&self, rifl: &Rifl) -> Option<&DotLifecycle> {
+        self.rifl_to_dot
+            .get(rifl)
+            .and_then(|dot| self.dots.get(dot))
+    }
+
+    /// Returns the lifecycles of all dots, sorted by dot.
+    pub fn sorted(&self) -> Vec<&DotLifecycle> {
+        let mut dots: Vec<_> = self.dots.values().collect();
+        dots.sort_by_key(|lifecycle| lifecycle.dot);
+        dots
+    }
+
+    /// Returns the number of dots recorded.
+    pub fn len(&self) -> usize {
+        self.dots.len()
+    }
+
+    /// Checks if no dot was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.dots.is_empty()
+    }
+
+    /// Writes the lifecycles to file `path` (as JSON, one dot per line, sorted
+    /// by dot).
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create dot lifecycles {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        for lifecycle in self.sorted() {
+            serde_json::to_writer(&mut writer, lifecycle)
+                .wrap_err("serialize dot lifecycle")?;
+            writeln!(writer).wrap_err("write dot lifecycles")?;
+        }
+        writer.flush().wrap_err("write dot lifecycles")
+    }
+
+    // Records that command `rifl` is being submitted at process `process_id`,
+    // so that the next new dot of that process is assigned to it (until
+    // `DotLifecycles::submitted` is called).
+    pub(crate) fn submitting(
+        &mut self,
+        process_id: ProcessId,
+        rifl: Rifl,
+        time: u64,
+    ) {
+        self.submitting = Some((process_id, rifl, time));
+    }
+
+    // Records that the command being submitted is done being submitted.
+    pub(crate) fn submitted(&mut self) {
+        self.submitting = None;
+    }
+
+    // Records that dot `dot` was seen (e.g. in a message sent).
+    pub(crate) fn see(&mut self, dot: Dot) -> &mut DotLifecycle {
+        let submitting = &mut self.submitting;
+        let rifl_to_dot = &mut self.rifl_to_dot;
+        self.dots.entry(dot).or_insert_with(|| {
+            let mut lifecycle = DotLifecycle::new(dot);
+            // a new dot of the process submitting a command is assigned to
+            // that command
+            if let Some((process_id, rifl, time)) = *submitting {
+                if dot.source() == process_id {
+                    lifecycle.rifl = Some(rifl);
+                    lifecycle.submit = Some(time);
+                    rifl_to_dot.insert(rifl, dot);
+                    *submitting = None;
+                }
+            }
+            lifecycle
+        })
+    }
+
+    // Records that process `to` received message `msg` about dot `dot` from
+    // process `from`.
+    pub(crate) fn receive<M: Debug>(
+        &mut self,
+        time: u64,
+        dot: Dot,
+        from: ProcessId,
+        to: ProcessId,
+        msg: &M,
+    ) {
+        if dot.source() == to && from != to {
+            let kind = EventTrace::kind(msg);
+            self.see(dot).replies.push(DotReply { time, from, kind });
+        }
+    }
+
+    // Records that process `process_id` committed dot `dot`.
+    pub(crate) fn commit(
+        &mut self,
+        time: u64,
+        dot: Dot,
+        process_id: ProcessId,
+    ) {
+        self.see(dot).commits.entry(process_id).or_insert(time);
+    }
+
+    // Records that process `process_id` executed (part of) command `rifl`.
+    pub(crate) fn execute(
+        &mut self,
+        time: u64,
+        rifl: Rifl,
+        process_id: ProcessId,
+    ) {
+        if let Some(dot) = self.rifl_to_dot.get(&rifl) {
+            let lifecycle = self.dots.get_mut(dot).expect("dot should exist");
+            lifecycle.executions.insert(process_id, time);
+        }
+    }
+
+    // Records that the result of command `rifl` arrived at its client.
+    pub(crate) fn result(&mut self, time: u64, rifl: Rifl) {
+        if let Some(dot) = self.rifl_to_dot.get(&rifl) {
+            let lifecycle = self.dots.get_mut(dot).expect("dot should exist");
+            lifecycle.result = Some(time);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use crate::metrics::Histogram;
+use crate::planet::Region;
+use crate::HashMap;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Metrics of the processes and clients in some region, collected by
+/// `Runner::simulation_metrics`. All latencies are in milliseconds.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionMetrics {
+    // number of commands committed in the fast (resp. slow) path by the
+    // processes in the region
+    fast_paths: u64,
+    slow_paths: u64,
+    // number of commands issued by the clients in the region
+    issued_commands: usize,
+    // latency observed by the clients in the region
+    latency: Histogram,
+    // commit latency (i.e. latency minus execution delay) and execution delay
+    // (i.e. time between commit and execution) of the commands of the clients
+    // in the region (only for commands whose executors reported execution
+    // timestamps)
+    commit_latency: Histogram,
+    execution_delay: Histogram,
+}
+
+impl RegionMetrics {
+    /// Returns the number of commands committed in the fast path.
+    pub fn fast_paths(&self) -> u64 {
+        self.fast_paths
+    }
+
+    /// Returns the number of commands committed in the slow path.
+    pub fn slow_paths(&self) -> u64 {
+        self.slow_paths
+    }
+
+    /// Returns the fraction of commands committed in the fast path (if any
+    /// command was committed).
+    pub fn fast_path_ratio(&self) -> Option<f64> {
+        let total = self.fast_paths + self.slow_paths;
+        if total == 0 {
+            None
+        } else {
+            Some(self.fast_paths as f64 / total as f64)
+        }
+    }
+
+    /// Returns the number of commands issued by clients.
+    pub fn issued_commands(&self) -> usize {
+        self.issued_commands
+    }
+
+    /// Returns the latency observed by clients.
+    pub fn latency(&self) -> &Histogram {
+        &self.latency
+    }
+
+    /// Returns the commit latency of the commands of clients.
+    pub fn commit_latency(&self) -> &Histogram {
+        &self.commit_latency
+    }
+
+    /// Returns the execution delay of the commands of clients.
+    pub fn execution_delay(&self) -> &Histogram {
+        &self.execution_delay
+    }
+
+    /// Returns the percentiles of the latency observed by clients (if clients
+    /// completed any command).
+    pub fn latency_percentiles(&self) -> Option<LatencyPercentiles> {
+        LatencyPercentiles::new(&self.latency)
+    }
+
+    /// Records the number of commands committed in the fast and slow path by
+    /// some process.
+    pub(crate) fn record_paths(&mut self, fast_paths: u64, slow_paths: u64) {
+        self.fast_paths += fast_paths;
+        self.slow_paths += slow_paths;
+    }
+
+    /// Records the number of commands issued by some client.
+    pub(crate) fn record_issued_commands(&mut self, issued_commands: usize) {
+        self.issued_commands += issued_commands;
+    }
+
+    /// Records the latency of a command.
+    pub(crate) fn record_latency(&mut self, latency: u64) {
+        self.latency.increment(latency);
+    }
+
+    /// Records the commit latency and execution delay of a command.
+    pub(crate) fn record_breakdown(
+        &mut self,
+        commit_latency: u64,
+        execution_delay: u64,
+    ) {
+        self.commit_latency.increment(commit_latency);
+        self.execution_delay.increment(execution_delay);
+    }
+
+    /// Merges the metrics of another region.
+    pub fn merge(&mut self, other: &Self) {
+        self.fast_paths += other.fast_paths;
+        self.slow_paths += other.slow_paths;
+        self.issued_commands += other.issued_commands;
+        self.latency.merge(&other.latency);
+        self.commit_latency.merge(&other.commit_latency);
+        self.execution_delay.merge(&other.execution_delay);
+    }
+}
+
+/// Mean and tail percentiles of a latency `Histogram`. All latencies are in
+/// milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyPercentiles {
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+}
+
+impl LatencyPercentiles {
+    /// Computes the percentiles of `latency` (if it is not empty).
+    pub fn new(latency: &Histogram) -> Option<Self> {
+        if latency.count() == 0 {
+            return None;
+        }
+        Some(Self {
+            mean: latency.mean().value(),
+            p50: latency.percentile(0.5).value(),
+            p95: latency.percentile(0.95).value(),
+            p99: latency.percentile(0.99).value(),
+        })
+    }
+
+    /// Returns the mean latency.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the 50th percentile of the latency.
+    pub fn p50(&self) -> f64 {
+        self.p50
+    }
+
+    /// Returns the 95th percentile of the latency.
+    pub fn p95(&self) -> f64 {
+        self.p95
+    }
+
+    /// Returns the 99th percentile of the latency.
+    pub fn p99(&self) -> f64 {
+        self.p99
+    }
+}
+
+impl fmt::Display for LatencyPercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean={:<5} p50={:<5} p95={:<5} p99={:<5}",
+            self.mean.round(),
+            self.p50.round(),
+            self.p95.round(),
+            self.p99.round()
+        )
+    }
+}
+
+/// Metrics of a simulation, per region.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationMetrics {
+    regions: HashMap<Region, RegionMetrics>,
+}
+
+impl SimulationMetrics {
+    /// Creates empty `SimulationMetrics`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metrics of region `region` (if there were processes or
+    /// clients in that region).
+    pub fn region(&self, region: &Region) -> Option<&RegionMetrics> {
+        self.regions.get(region)
+    }
+
+    /// Returns the metrics of all regions.
+    pub fn regions(&self) -> impl Iterator<Item = (&Region, &RegionMetrics)> {
+        self.regions.iter()
+    }
+
+    /// Returns the metrics of all regions merged.
+    pub fn total(&self) -> RegionMetrics {
+        self.regions.values().fold(
+            RegionMetrics::default(),
+            |mut total, metrics| {
+                total.merge(metrics);
+                total
+            },
+        )
+    }
+
+    /// Returns the latency percentiles of each region with clients (sorted by
+    /// region), followed by the ones of all regions merged.
+    pub fn latency_percentiles(
+        &self,
+    ) -> Vec<(Option<Region>, LatencyPercentiles)> {
+        let mut regions: Vec<_> = self.regions.iter().collect();
+        regions.sort_by_key(|(region, _)| *region);
+        let total = self.total();
+        regions
+            .into_iter()
+            .map(|(region, metrics)| (Some(region.clone()), metrics))
+            .chain(std::iter::once((None, &total)))
+            .filter_map(|(region, metrics)| {
+                metrics
+                    .latency_percentiles()
+                    .map(|percentiles| (region, percentiles))
+            })
+            .collect()
+    }
+
+    /// Writes the metrics to file `path` (as JSON).
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path).wrap_err_with(|| {
+            format!("create simulation metrics {:?}", path)
+        })?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .wrap_err("serialize simulation metrics")?;
+        writer.flush().wrap_err("write simulation metrics")
+    }
+
+    /// Returns a mutable reference to the metrics of region `region`.
+    pub(crate) fn region_mut(&mut self, region: &Region) -> &mut RegionMetrics {
+        self.regions.entry(region.clone()).or_default()
+    }
+}
+
+/// Impact of a failover (see `Runner::failover`) on the latency of clients.
+/// All times are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailoverMetrics {
+    // simulated time of the failover
+    at: u64,
+    // highest latency of the commands that ended before the failover
+    latency_before: u64,
+    // highest latency of the commands that ended after the failover
+    latency_spike: u64,
+    // time from the failover until the end of the last command with a
+    // latency higher than `latency_before`
+    recovery_time: u64,
+}
+
+impl FailoverMetrics {
+    /// Computes the impact of a failover at simulated time `at`, given the
+    /// end time and the latency of each command.
+    pub(crate) fn new(at: u64, latencies: Vec<(u64, Duration)>) -> Self {
+        let max_latency = |after: bool| {
+            latencies
+                .iter()
+                .filter(|(end_time, _)| (*end_time >= at) == after)
+                .map(|(_, latency)| latency.as_millis() as u64)
+                .max()
+                .unwrap_or_default()
+        };
+        let latency_before = max_latency(false);
+        let latency_spike = max_latency(true);
+        let recovery_time = latencies
+            .iter()
+            .filter(|(end_time, latency)| {
+                *end_time >= at && latency.as_millis() as u64 > latency_before
+            })
+            .map(|(end_time, _)| end_time - at)
+            .max()
+            .unwrap_or_default();
+        Self {
+            at,
+            latency_before,
+            latency_spike,
+            recovery_time,
+        }
+    }
+
+    /// Returns the simulated time of the failover.
+    pub fn at(&self) -> u64 {
+        self.at
+    }
+
+    /// Returns the highest latency observed before the failover.
+    pub fn latency_before(&self) -> u64 {
+        self.latency_before
+    }
+
+    /// Returns the highest latency observed after the failover.
+    pub fn latency_spike(&self) -> u64 {
+        self.latency_spike
+    }
+
+    /// Returns how long it took for latencies to be back to (at most) the ones
+    /// observed before the failover.
+    pub fn recovery_time(&self) -> u64 {
+        self.recovery_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::F64;
+
+    #[test]
+    fn simulation_metrics() {
+        let a = Region::new("a");
+        let b = Region::new("b");
+        let mut metrics = SimulationMetrics::new();
+
+        // region a has a process that committed 3 commands in the fast path
+        // and 1 in the slow path, and a client that issued 2 commands
+        let region_a = metrics.region_mut(&a);
+        region_a.record_paths(3, 1);
+        region_a.record_issued_commands(2);
+        region_a.record_latency(10);
+        region_a.record_breakdown(8, 2);
+        region_a.record_latency(20);
+        region_a.record_breakdown(20, 0);
+        assert_eq!(region_a.fast_path_ratio(), Some(0.75));
+
+        // region b only has a process, which committed no command
+        metrics.region_mut(&b).record_paths(0, 0);
+        assert_eq!(metrics.region(&b).unwrap().fast_path_ratio(), None);
+
+        // check metrics of region a
+        let region_a = metrics.region(&a).unwrap();
+        assert_eq!(region_a.issued_commands(), 2);
+        assert_eq!(region_a.latency().mean(), F64::new(15.0));
+        assert_eq!(region_a.commit_latency().mean(), F64::new(14.0));
+        assert_eq!(region_a.execution_delay().mean(), F64::new(1.0));
+
+        // check total
+        let total = metrics.total();
+        assert_eq!(total.fast_paths(), 3);
+        assert_eq!(total.slow_paths(), 1);
+        assert_eq!(total.issued_commands(), 2);
+        assert_eq!(total.latency().count(), 2);
+        assert_eq!(metrics.regions().count(), 2);
+
+        // only region a has latencies, and so it's the only region with
+        // percentiles besides the total
+        let percentiles = metrics.latency_percentiles();
+        assert_eq!(percentiles.len(), 2);
+        let (region, region_a) = &percentiles[0];
+        assert_eq!(region, &Some(a));
+        assert_eq!(region_a.mean(), 15.0);
+        assert_eq!(region_a.p50(), 15.0);
+        assert_eq!(region_a.p99(), 20.0);
+        assert_eq!(percentiles[1], (None, *region_a));
+        assert_eq!(metrics.region(&b).unwrap().latency_percentiles(), None);
+    }
+
+    #[test]
+    fn failover_metrics() {
+        let ms = Duration::from_millis;
+        // before the failover at 100, latencies are at most 20; after it, a
+        // command takes 150, and the last command slower than 20 ends at 230
+        let latencies = vec![
+            (50, ms(10)),
+            (90, ms(20)),
+            (230, ms(150)),
+            (200, ms(40)),
+            (300, ms(20)),
+        ];
+        let metrics = FailoverMetrics::new(100, latencies);
+        assert_eq!(metrics.at(), 100);
+        assert_eq!(metrics.latency_before(), 20);
+        assert_eq!(metrics.latency_spike(), 150);
+        assert_eq!(metrics.recovery_time(), 130);
+
+        // no command slower than before the failover
+        let metrics =
+            FailoverMetrics::new(100, vec![(50, ms(10)), (150, ms(5))]);
+        assert_eq!(metrics.latency_spike(), 5);
+        assert_eq!(metrics.recovery_time(), 0);
+    }
+}
@@ -7,7 +7,54 @@ pub mod schedule;
 // This module contains the definition of `Runner`.
 pub mod runner;
 
+// This module contains the definition of `LatencyModel`.
+pub mod latency;
+
+// This module contains the definition of `SimulationMetrics` and
+// `FailoverMetrics`.
+pub mod metrics;
+
+// This module contains the definition of `History`.
+pub mod history;
+
+// This module contains the definition of `Fuzzer`.
+pub mod fuzz;
+
+// This module contains the definition of `Scenario`.
+pub mod scenario;
+
+// This module contains the definition of `DotLifecycles`.
+pub mod lifecycle;
+
+// This module contains the definition of `EventTrace`.
+pub mod trace;
+
+// This module contains the definition of `Checkpoint`.
+pub mod checkpoint;
+
+// This module contains the definition of `Golden`.
+pub mod golden;
+
+// This module contains the definition of `Comparison`.
+pub mod comparison;
+
+// This module contains the definition of `Sweep`.
+pub mod sweep;
+
 // Re-exports.
-pub use runner::Runner;
+pub use checkpoint::Checkpoint;
+pub use comparison::{Comparison, ComparisonReport, ComparisonRow};
+pub use fuzz::{Fuzzer, Violation};
+pub use golden::Golden;
+pub use history::History;
+pub use latency::{LatencyDistribution, LatencyModel};
+pub use lifecycle::{DotLifecycle, DotLifecycles, DotReply};
+pub use metrics::{
+    FailoverMetrics, LatencyPercentiles, RegionMetrics, SimulationMetrics,
+};
+pub use runner::{Recovery, Runner, StopCondition};
+pub use scenario::Scenario;
 pub use schedule::Schedule;
 pub use simulation::Simulation;
+pub use sweep::{Sweep, SweepPoint, SweepResults, SweepRun};
+pub use trace::{EventTrace, TraceEvent};
@@ -1,29 +1,71 @@
 use crate::client::{Client, Workload};
 use crate::command::{Command, CommandResult, DEFAULT_SHARD_ID};
 use crate::config::Config;
-use crate::executor::{ExecutionOrderMonitor, Executor, ExecutorMetrics};
+use crate::executor::{
+    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult, LocalRead,
+};
 use crate::id::{ClientId, ProcessId, ShardId};
 use crate::metrics::Histogram;
 use crate::planet::{Planet, Region};
-use crate::protocol::{Action, Protocol, ProtocolMetrics};
-use crate::sim::{Schedule, Simulation};
-use crate::time::SysTime;
+use crate::protocol::{
+    Action, FailureDetection, MessageIndex, Protocol, ProtocolMetrics,
+};
+use crate::sim::{
+    DotLifecycles, EventTrace, FailoverMetrics, History, LatencyModel,
+    Schedule, Simulation, SimulationMetrics, TraceEvent,
+};
+use crate::time::{ClockSkew, SysTime};
 use crate::util;
-use crate::HashMap;
-use rand::Rng;
+use crate::{info, HashMap, HashSet};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::Debug;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(PartialEq, Eq)]
 enum ScheduleAction<Message, PeriodicEvent> {
     SubmitToProc(ProcessId, Command),
-    SendToProc(ProcessId, ShardId, ProcessId, Message),
+    // the last field is the identifier of the message in the event trace (if
+    // one is recorded)
+    SendToProc(ProcessId, ShardId, ProcessId, Message, Option<u64>),
     SendToClient(ClientId, CommandResult),
     PeriodicProcessEvent(ProcessId, PeriodicEvent, Duration),
     PeriodicExecutedNotification(ProcessId, Duration),
+    Crash(ProcessId),
+    Recover(ProcessId, Recovery),
+    Suspect(ProcessId),
+    // a message (or command) to a slow process, once the process is done
+    // processing it (see `Runner::slow_down`)
+    Processed(Box<ScheduleAction<Message, PeriodicEvent>>),
 }
-#[derive(Clone)]
+
+/// State with which a crashed process recovers (see `Runner::recover`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recovery {
+    /// The process restarts from scratch, as if it had no stable storage.
+    Empty,
+    /// The process resumes with the state it had when it crashed, as if all
+    /// its state was persisted to stable storage.
+    Persisted,
+}
+
+/// Condition that stopped a simulation before all clients were done (see
+/// `Runner::set_max_sim_time` and `Runner::set_max_wall_time`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopCondition {
+    /// The maximum simulated time was reached.
+    SimTime,
+    /// The maximum wall-clock time was reached.
+    WallTime,
+}
+
+// number of events processed between each check of the wall-clock time (for
+// the maximum wall-clock time and the progress output)
+const WALL_CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
 enum MessageRegion {
     Process(ProcessId),
     Client(ClientId),
@@ -31,10 +73,13 @@ enum MessageRegion {
 
 pub struct Runner<P: Protocol> {
     planet: Planet,
+    config: Config,
     simulation: Simulation<P>,
     schedule: Schedule<ScheduleAction<P::Message, P::PeriodicEvent>>,
     // mapping from process identifier to its region
     process_to_region: HashMap<ProcessId, Region>,
+    // processes (and their shard and region) to be discovered by a process
+    to_discover: Vec<(ProcessId, ShardId, Region)>,
     // mapping from client identifier to its region
     client_to_region: HashMap<ClientId, Region>,
     // total number of clients
@@ -44,6 +89,214 @@ pub struct Runner<P: Protocol> {
     make_distances_symmetric: bool,
     // boolean indicating whether the runner should reoder messages
     reorder_messages: bool,
+    // random number generator reordering messages
+    reorder_rng: StdRng,
+    // messages between processes dropped by the runner
+    message_loss: MessageLoss<P::Message>,
+    // partitions of the processes (see `Runner::partition`)
+    partitions: Vec<Partition>,
+    // latency model of all links but the ones in `link_latency_models`
+    latency_model: LatencyModel,
+    // mapping from link (i.e. pair of regions) to its latency model
+    link_latency_models: HashMap<(Region, Region), LatencyModel>,
+    // random number generator sampling latencies
+    latency_rng: StdRng,
+    // set of processes currently crashed
+    crashed: HashSet<ProcessId>,
+    // bandwidth of the links (see `Runner::set_bandwidth`)
+    bandwidth: Bandwidth,
+    // mapping from process identifier to its slowdown (see
+    // `Runner::slow_down`)
+    stragglers: HashMap<ProcessId, Straggler>,
+    // if set, the history of the commands submitted by clients is recorded
+    history: Option<History>,
+    // if set, the events in the simulation are recorded
+    trace: Option<EventTrace>,
+    // if set, the lifecycle of each dot is recorded
+    dots: Option<DotLifecycles>,
+    // simulated time (in milliseconds) of the last failover (see
+    // `Runner::failover`)
+    failover: Option<u64>,
+    // if set, the simulation stops once the simulated time (in milliseconds)
+    // is past it
+    max_sim_time: Option<u64>,
+    // if set, the simulation stops once it has been running for longer than
+    // this (in wall-clock time)
+    max_wall_time: Option<Duration>,
+    // if set, progress is shown with this (wall-clock) interval
+    progress_interval: Option<Duration>,
+    // condition that stopped the simulation (if any)
+    stopped: Option<StopCondition>,
+    // state of the simulation loop, kept across calls to `Runner::run` so
+    // that a stopped simulation can be continued
+    started: bool,
+    status: SimulationStatus,
+    clients_done: usize,
+    simulation_final_time: u64,
+    // number of events processed and commands completed so far
+    events: u64,
+    commands_completed: u64,
+}
+
+// A partition of the processes into groups, during which the messages between
+// processes in different groups are held until it heals.
+struct Partition {
+    // mapping from process identifier to the index of its group
+    process_to_group: HashMap<ProcessId, usize>,
+    // simulated time (in milliseconds) at which the partition starts
+    from: u64,
+    // simulated time (in milliseconds) at which the partition heals
+    to: u64,
+}
+
+impl Partition {
+    // Returns how long (in milliseconds) a message sent by process `from` to
+    // process `to` at time `now` should be held, i.e. the time until the
+    // partition heals if the processes are partitioned.
+    fn delay(&self, from: ProcessId, to: ProcessId, now: u64) -> u64 {
+        let partitioned =
+            self.process_to_group.get(&from) != self.process_to_group.get(&to);
+        if partitioned && self.from <= now && now < self.to {
+            self.to - now
+        } else {
+            0
+        }
+    }
+}
+
+// Configuration of the messages dropped by the runner (see
+// `Runner::drop_messages`).
+struct MessageLoss<Message> {
+    // probability of dropping each message
+    probability: f64,
+    // probability of dropping each message sent by a process to another
+    // (overriding `probability`)
+    link_probabilities: HashMap<(ProcessId, ProcessId), f64>,
+    // if set, only the messages for which it returns true may be dropped
+    filter: Option<fn(&Message) -> bool>,
+    // random number generator deciding which messages are dropped
+    rng: StdRng,
+    // number of messages dropped
+    dropped: usize,
+}
+
+impl<Message> MessageLoss<Message> {
+    fn new() -> Self {
+        Self {
+            probability: 0.0,
+            link_probabilities: HashMap::new(),
+            filter: None,
+            rng: StdRng::seed_from_u64(0),
+            dropped: 0,
+        }
+    }
+
+    // Decides whether a message sent by process `from` to process `to` should
+    // be dropped.
+    fn drop(&mut self, from: ProcessId, to: ProcessId, msg: &Message) -> bool {
+        let probability = self
+            .link_probabilities
+            .get(&(from, to))
+            .cloned()
+            .unwrap_or(self.probability);
+        if probability == 0.0 || !self.filter.is_none_or(|f| f(msg)) {
+            return false;
+        }
+        let drop = self.rng.gen_bool(probability);
+        if drop {
+            self.dropped += 1;
+        }
+        drop
+    }
+}
+
+// Configuration of the bandwidth of the links (see `Runner::set_bandwidth`),
+// and the state of their queues.
+struct Bandwidth {
+    // bandwidth (in bytes per second) of all links but the ones in
+    // `link_bandwidths`; if not set, messages are transmitted instantly
+    bandwidth: Option<u64>,
+    // mapping from link (i.e. pair of regions) to its bandwidth
+    link_bandwidths: HashMap<(Region, Region), u64>,
+    // mapping from sender and receiver of messages to the simulated time (in
+    // microseconds) until which the link between them is busy transmitting
+    busy_until: HashMap<(MessageRegion, MessageRegion), u64>,
+}
+
+impl Bandwidth {
+    fn new() -> Self {
+        Self {
+            bandwidth: None,
+            link_bandwidths: HashMap::new(),
+            busy_until: HashMap::new(),
+        }
+    }
+
+    // Returns true if some link has limited bandwidth.
+    fn is_limited(&self) -> bool {
+        self.bandwidth.is_some() || !self.link_bandwidths.is_empty()
+    }
+
+    // Returns how long (in microseconds) a message with `size` bytes, sent by
+    // `from` (in region `from_region`) to `to` (in region `to_region`) at time
+    // `start` (in microseconds), takes to be transmitted, including the time
+    // it waits for the messages queued before it on the link.
+    fn delay(
+        &mut self,
+        (from, from_region): (MessageRegion, &Region),
+        (to, to_region): (MessageRegion, &Region),
+        size: u64,
+        start: u64,
+    ) -> u64 {
+        let bandwidth = self
+            .link_bandwidths
+            .get(&(from_region.clone(), to_region.clone()))
+            .cloned()
+            .or(self.bandwidth);
+        let bandwidth = match bandwidth {
+            Some(bandwidth) => bandwidth,
+            None => return 0,
+        };
+        // the message is transmitted once the link is free
+        let busy_until = self.busy_until.entry((from, to)).or_default();
+        let transmission_start = std::cmp::max(start, *busy_until);
+        let transmission = size * 1_000_000 / bandwidth;
+        *busy_until = transmission_start + transmission;
+        *busy_until - start
+    }
+}
+
+// The periods in which a process is slow (see `Runner::slow_down`), and the
+// state of its queue of messages.
+#[derive(Default)]
+struct Straggler {
+    // simulated time (in milliseconds) at which each period starts and ends,
+    // and the time (in microseconds) the process takes to process each message
+    // in that period
+    periods: Vec<(u64, u64, u64)>,
+    // simulated time (in microseconds) until which the process is busy
+    // processing messages
+    busy_until: u64,
+}
+
+impl Straggler {
+    // Returns how long (in microseconds) a message arriving at the process at
+    // time `now` (in microseconds) takes to be processed, including the time
+    // it waits for the messages queued before it.
+    fn delay(&mut self, now: u64) -> u64 {
+        let now_millis = now / 1000;
+        let processing = self
+            .periods
+            .iter()
+            .filter(|(from, to, _)| *from <= now_millis && now_millis < *to)
+            .map(|(_, _, processing)| *processing)
+            .max()
+            .unwrap_or_default();
+        // the message is processed once the process is free
+        let processing_start = std::cmp::max(now, self.busy_until);
+        self.busy_until = processing_start + processing;
+        self.busy_until - now
+    }
 }
 
 #[derive(PartialEq)]
@@ -61,6 +314,11 @@ where
     /// regions:
     /// - `process_regions`: list of regions where processes are located
     /// - `client_regions`: list of regions where clients are located
+    ///
+    /// All the randomness in the simulation (i.e. the commands generated by
+    /// clients, the reordering of messages and the order of the actions
+    /// scheduled at the same time) is derived from `seed`, and so runs with
+    /// the same seed are the same.
     pub fn new(
         planet: Planet,
         config: Config,
@@ -68,6 +326,25 @@ where
         clients_per_process: usize,
         process_regions: Vec<Region>,
         client_regions: Vec<Region>,
+        seed: u64,
+    ) -> Self {
+        let clients = client_regions
+            .into_iter()
+            .map(|region| (region, clients_per_process, workload.clone()))
+            .collect();
+        Self::with_clients(planet, config, process_regions, clients, seed)
+    }
+
+    /// Create a new `Runner` as with `Runner::new`, but where the clients are
+    /// given by `clients`: a list of regions, each with its number of clients
+    /// and their workload. A region can appear more than once (e.g. to have
+    /// clients with different workloads in the same region).
+    pub fn with_clients(
+        planet: Planet,
+        config: Config,
+        process_regions: Vec<Region>,
+        clients: Vec<(Region, usize, Workload)>,
+        seed: u64,
     ) -> Self {
         // check that we have the correct number of `process_regions`
         assert_eq!(process_regions.len(), config.n());
@@ -119,20 +396,13 @@ where
 
         // register processes
         processes.into_iter().for_each(|(region, mut process)| {
-            // discover
-            let sorted = util::sort_processes_by_distance(
+            // discover and create executor for this process
+            let executor = Self::start_process(
+                &mut process,
                 &region,
                 &planet,
-                to_discover.clone(),
-            );
-            let (connect_ok, _) = process.discover(sorted);
-            assert!(connect_ok);
-
-            // create executor for this process
-            let executor = <P::Executor as Executor>::new(
-                process.id(),
-                process.shard_id(),
                 config,
+                &to_discover,
             );
 
             // and register both
@@ -142,13 +412,15 @@ where
         // register clients and create client to region mapping
         let mut client_id = 0;
         let mut client_to_region = HashMap::new();
-        for region in client_regions {
-            for _ in 1..=clients_per_process {
+        for (region, client_count, mut workload) in clients {
+            // make the commands generated by clients reproducible
+            workload.set_seed(seed);
+            for _ in 1..=client_count {
                 // create client
                 client_id += 1;
                 let status_frequency = None;
                 let mut client =
-                    Client::new(client_id, workload, status_frequency);
+                    Client::new(client_id, workload.clone(), status_frequency);
                 // discover
                 let closest = util::closest_process_per_shard(
                     &region,
@@ -165,15 +437,40 @@ where
         // create runner
         let mut runner = Self {
             planet,
+            config,
             simulation,
-            schedule: Schedule::new(),
+            schedule: Schedule::with_seed(seed),
             process_to_region,
+            to_discover,
             client_to_region,
             // since we start ids in 1, the last id is the same as the number of
             // clients
             client_count: client_id as usize,
             make_distances_symmetric: false,
             reorder_messages: false,
+            reorder_rng: StdRng::seed_from_u64(seed),
+            message_loss: MessageLoss::new(),
+            partitions: Vec::new(),
+            latency_model: LatencyModel::fixed(),
+            link_latency_models: HashMap::new(),
+            latency_rng: StdRng::seed_from_u64(0),
+            crashed: HashSet::new(),
+            bandwidth: Bandwidth::new(),
+            stragglers: HashMap::new(),
+            history: None,
+            trace: None,
+            dots: None,
+            failover: None,
+            max_sim_time: None,
+            max_wall_time: None,
+            progress_interval: None,
+            stopped: None,
+            started: false,
+            status: SimulationStatus::ClientsRunning,
+            clients_done: 0,
+            simulation_final_time: 0,
+            events: 0,
+            commands_completed: 0,
         };
 
         // schedule periodic process events
@@ -189,6 +486,27 @@ where
         runner
     }
 
+    // Makes `process` discover the other processes and creates its executor.
+    fn start_process(
+        process: &mut P,
+        region: &Region,
+        planet: &Planet,
+        config: Config,
+        to_discover: &[(ProcessId, ShardId, Region)],
+    ) -> P::Executor {
+        // discover
+        let sorted = util::sort_processes_by_distance(
+            region,
+            planet,
+            to_discover.to_vec(),
+        );
+        let (connect_ok, _) = process.discover(sorted);
+        assert!(connect_ok);
+
+        // create executor for this process
+        <P::Executor as Executor>::new(process.id(), process.shard_id(), config)
+    }
+
     pub fn make_distances_symmetric(&mut self) {
         self.make_distances_symmetric = true;
     }
@@ -197,8 +515,298 @@ where
         self.reorder_messages = true;
     }
 
+    /// Drops each message sent by a process to another with probability
+    /// `probability`. Which messages are dropped is decided by a random number
+    /// generator seeded with `seed`, and so runs with the same seed drop the
+    /// same messages.
+    pub fn drop_messages(&mut self, probability: f64, seed: u64) {
+        assert!((0.0..=1.0).contains(&probability));
+        self.message_loss.probability = probability;
+        self.message_loss.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Drops each message sent by process `from` to process `to` with
+    /// probability `probability` (instead of the one set with
+    /// `Runner::drop_messages`).
+    pub fn drop_link_messages(
+        &mut self,
+        from: ProcessId,
+        to: ProcessId,
+        probability: f64,
+    ) {
+        assert!((0.0..=1.0).contains(&probability));
+        self.message_loss
+            .link_probabilities
+            .insert((from, to), probability);
+    }
+
+    /// Only drops the messages for which `filter` returns true (e.g. messages
+    /// of some type).
+    pub fn drop_messages_if(&mut self, filter: fn(&P::Message) -> bool) {
+        self.message_loss.filter = Some(filter);
+    }
+
+    /// Sets the latency model of all links (by default, all messages on a link
+    /// have the latency of that link). Latencies are sampled from a random
+    /// number generator seeded with `seed`, and so runs with the same seed
+    /// have the same latencies.
+    pub fn set_latency_model(&mut self, model: LatencyModel, seed: u64) {
+        self.latency_model = model;
+        self.latency_rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the latency model of the link from region `from` to region `to`
+    /// (instead of the one set with `Runner::set_latency_model`).
+    pub fn set_link_latency_model(
+        &mut self,
+        from: Region,
+        to: Region,
+        model: LatencyModel,
+    ) {
+        self.link_latency_models.insert((from, to), model);
+    }
+
+    /// Limits the bandwidth of all links to `bandwidth` bytes per second (by
+    /// default, messages are transmitted instantly). Each message then takes
+    /// time proportional to its serialized size to be transmitted, and the
+    /// messages between a sender and a receiver are transmitted one at a time,
+    /// and so they queue up when sent faster than the link can transmit them.
+    pub fn set_bandwidth(&mut self, bandwidth: u64) {
+        assert!(bandwidth > 0, "bandwidth should be positive");
+        self.bandwidth.bandwidth = Some(bandwidth);
+    }
+
+    /// Limits the bandwidth of the link from region `from` to region `to` to
+    /// `bandwidth` bytes per second (instead of the one set with
+    /// `Runner::set_bandwidth`).
+    pub fn set_link_bandwidth(
+        &mut self,
+        from: Region,
+        to: Region,
+        bandwidth: u64,
+    ) {
+        assert!(bandwidth > 0, "bandwidth should be positive");
+        self.bandwidth.link_bandwidths.insert((from, to), bandwidth);
+    }
+
+    /// Partitions the processes into `groups` from simulated time `from` until
+    /// `to` (both in milliseconds). While partitioned, the messages between
+    /// processes in different groups are held, and only delivered once the
+    /// partition heals (i.e. as if sent at `to`). Each process should be in
+    /// exactly one group.
+    pub fn partition(
+        &mut self,
+        groups: Vec<Vec<ProcessId>>,
+        from: u64,
+        to: u64,
+    ) {
+        assert!(from < to, "partitions should heal after they start");
+        let mut process_to_group = HashMap::new();
+        for (group, process_ids) in groups.into_iter().enumerate() {
+            for process_id in process_ids {
+                let res = process_to_group.insert(process_id, group);
+                assert!(res.is_none(), "each process should be in one group");
+            }
+        }
+        assert!(
+            self.process_to_region
+                .keys()
+                .all(|process_id| process_to_group.contains_key(process_id)),
+            "each process should be in one group"
+        );
+        self.partitions.push(Partition {
+            process_to_group,
+            from,
+            to,
+        });
+    }
+
+    /// Slows down process `process_id` from simulated time `from` until `to`
+    /// (both in milliseconds): while slow, the process takes `delay` to
+    /// process each message (and command submitted by a client) it receives.
+    /// Messages are processed one at a time, and so they queue up when they
+    /// arrive faster than the process can handle them. A delay that varies
+    /// over time can be modeled by slowing down the process several times,
+    /// with different periods and delays (if periods overlap, the largest
+    /// delay applies).
+    pub fn slow_down(
+        &mut self,
+        process_id: ProcessId,
+        delay: Duration,
+        from: u64,
+        to: u64,
+    ) {
+        assert!(from < to, "slowdowns should end after they start");
+        assert!(
+            self.process_to_region.contains_key(&process_id),
+            "process {} should exist",
+            process_id
+        );
+        self.stragglers
+            .entry(process_id)
+            .or_default()
+            .periods
+            .push((from, to, delay.as_micros() as u64));
+    }
+
+    /// Crashes process `process_id` at simulated time `at` (in milliseconds).
+    /// When it crashes, the messages in flight from and to the process are
+    /// dropped. While crashed, the process handles no messages nor periodic
+    /// events, and the messages sent to it (including commands submitted by
+    /// clients) are dropped. Since clients don't fail over to other processes,
+    /// clients connected to a process that crashes may never finish.
+    pub fn crash(&mut self, process_id: ProcessId, at: u64) {
+        let action = ScheduleAction::Crash(process_id);
+        self.schedule_event(process_id, at, action);
+    }
+
+    /// Recovers process `process_id` (crashed with `Runner::crash`) at
+    /// simulated time `at` (in milliseconds), with the state given by
+    /// `recovery`.
+    pub fn recover(
+        &mut self,
+        process_id: ProcessId,
+        at: u64,
+        recovery: Recovery,
+    ) {
+        let action = ScheduleAction::Recover(process_id, recovery);
+        self.schedule_event(process_id, at, action);
+    }
+
+    /// Notifies all processes (but the ones crashed) at simulated time `at` (in
+    /// milliseconds) that process `process_id` is suspected of having failed,
+    /// as a failure detector would (see `Protocol::handle_failure`).
+    pub fn suspect(&mut self, process_id: ProcessId, at: u64) {
+        let action = ScheduleAction::Suspect(process_id);
+        self.schedule_event(process_id, at, action);
+    }
+
+    /// Crashes process `process_id` (e.g. the leader of a leader-based
+    /// protocol) at simulated time `at`, and has it suspected by the remaining
+    /// processes `detection_delay` milliseconds later (see `Runner::suspect`),
+    /// so that they can elect a new leader. The impact of the failover on the
+    /// latency of clients can then be measured with `Runner::failover_metrics`.
+    pub fn failover(
+        &mut self,
+        process_id: ProcessId,
+        at: u64,
+        detection_delay: u64,
+    ) {
+        self.crash(process_id, at);
+        self.suspect(process_id, at + detection_delay);
+        self.failover = Some(at);
+    }
+
+    /// Skews the clock of process `process_id` with `skew` (by default, the
+    /// clocks of all processes show the simulation time). Clients always see
+    /// the simulation time, and so their latencies are not skewed.
+    pub fn set_clock_skew(&mut self, process_id: ProcessId, skew: ClockSkew) {
+        assert!(
+            self.process_to_region.contains_key(&process_id),
+            "process {} should exist",
+            process_id
+        );
+        self.simulation.set_clock_skew(process_id, skew);
+    }
+
+    /// Records the history of the commands submitted by clients (i.e. when
+    /// they're submitted, when their results arrive, and their results), so
+    /// that it can be checked for linearizability once the simulation ends
+    /// (see `History::check_linearizability`).
+    pub fn record_history(&mut self) {
+        self.history = Some(History::new());
+    }
+
+    /// Returns the history recorded so far (if `Runner::record_history` was
+    /// called).
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Records the events in the simulation (i.e. commands arriving at
+    /// processes, messages sent and received, commits, executions and results
+    /// arriving at clients), so that they can be exported once the simulation
+    /// ends (see `EventTrace::write`).
+    pub fn record_trace(&mut self) {
+        self.trace = Some(EventTrace::new());
+    }
+
+    /// Records, for each dot (i.e. command), when it was submitted, when its
+    /// coordinator received each message about it (e.g. the replies of a
+    /// quorum), and when each process committed and executed it, so that the
+    /// phase that made a command slow can be found once the simulation ends
+    /// (see `DotLifecycles`). Only messages about a single command (see
+    /// `MessageIndex::dot`) and executors that know the dot of what they
+    /// execute (see `Executor::execution_info_dot`) are recorded.
+    pub fn record_dots(&mut self) {
+        self.dots = Some(DotLifecycles::new());
+    }
+
+    /// Returns the lifecycles of the dots recorded so far (if
+    /// `Runner::record_dots` was called).
+    pub fn dots(&self) -> Option<&DotLifecycles> {
+        self.dots.as_ref()
+    }
+
+    /// Returns the event trace recorded so far (if `Runner::record_trace` was
+    /// called).
+    pub fn trace(&self) -> Option<&EventTrace> {
+        self.trace.as_ref()
+    }
+
+    /// Stops the simulation once the simulated time is past `max_sim_time`, even
+    /// if clients are not done (see `Runner::stopped`).
+    pub fn set_max_sim_time(&mut self, max_sim_time: Duration) {
+        self.max_sim_time = Some(max_sim_time.as_millis() as u64);
+    }
+
+    /// Stops the simulation once it has been running for longer than
+    /// `max_wall_time` (in wall-clock time), even if clients are not done (see
+    /// `Runner::stopped`).
+    pub fn set_max_wall_time(&mut self, max_wall_time: Duration) {
+        self.max_wall_time = Some(max_wall_time);
+    }
+
+    /// Shows the progress of the simulation every `interval` (in wall-clock
+    /// time): the simulated time, the number of commands completed, and the
+    /// number of events processed per second.
+    pub fn show_progress(&mut self, interval: Duration) {
+        self.progress_interval = Some(interval);
+    }
+
+    /// Returns the condition that stopped the simulation before all clients
+    /// were done (if any).
+    pub fn stopped(&self) -> Option<StopCondition> {
+        self.stopped
+    }
+
+    /// Returns the number of events (i.e. scheduled actions) processed so far.
+    /// Since simulations are deterministic, a new `Runner` created with the
+    /// same arguments reaches the same state after processing the same number
+    /// of events (see `Checkpoint`).
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    // Returns the simulated time (in milliseconds).
+    pub(crate) fn sim_time(&self) -> u64 {
+        self.simulation.millis()
+    }
+
+    // Returns the number of commands completed so far.
+    pub(crate) fn commands_completed(&self) -> u64 {
+        self.commands_completed
+    }
+
+    /// Returns the number of messages dropped so far.
+    pub fn dropped_messages(&self) -> usize {
+        self.message_loss.dropped
+    }
+
     /// Run the simulation. `extra_sim_time` indicates how much longer should
-    /// the simulation run after clients are finished.
+    /// the simulation run after clients are finished. The simulation may stop
+    /// before that (see `Runner::stopped`), in which case calling `run` again
+    /// continues it (e.g. after raising the stop conditions).
     pub fn run(
         &mut self,
         extra_sim_time: Option<Duration>,
@@ -207,20 +815,9 @@ where
         HashMap<ProcessId, Option<ExecutionOrderMonitor>>,
         HashMap<Region, (usize, Histogram)>,
     ) {
-        // start clients
-        self.simulation.start_clients().into_iter().for_each(
-            |(client_id, process_id, cmd)| {
-                // schedule client commands
-                self.schedule_submit(
-                    MessageRegion::Client(client_id),
-                    process_id,
-                    cmd,
-                )
-            },
-        );
-
         // run simulation loop
-        self.simulation_loop(extra_sim_time);
+        self.stopped = None;
+        self.simulation_loop(extra_sim_time, None);
 
         // return metrics and client latencies
         (
@@ -230,16 +827,97 @@ where
         )
     }
 
-    fn simulation_loop(&mut self, extra_sim_time: Option<Duration>) {
-        let mut simulation_status = SimulationStatus::ClientsRunning;
-        let mut clients_done = 0;
-        let mut simulation_final_time = 0;
+    // Replays the first `events` events of the simulation (see
+    // `Runner::events`), returning whether the simulation had that many
+    // events. Calling `Runner::run` afterwards continues the simulation.
+    pub(crate) fn replay(
+        &mut self,
+        events: u64,
+        extra_sim_time: Option<Duration>,
+    ) -> bool {
+        self.simulation_loop(extra_sim_time, Some(events));
+        self.events == events
+    }
+
+    // Runs the simulation until it's done, it's stopped, or `until` events
+    // (if set) have been processed.
+    fn simulation_loop(
+        &mut self,
+        extra_sim_time: Option<Duration>,
+        until: Option<u64>,
+    ) {
+        if !self.started {
+            self.started = true;
+            // start clients
+            self.simulation.start_clients().into_iter().for_each(
+                |(client_id, process_id, cmd)| {
+                    // schedule client commands
+                    self.schedule_submit(
+                        MessageRegion::Client(client_id),
+                        process_id,
+                        cmd,
+                    )
+                },
+            );
+        }
+
+        // when (in wall-clock time) the simulation (re)started and progress
+        // was last shown
+        let start = Instant::now();
+        let mut last_progress = (start, self.events);
+
+        while self.status != SimulationStatus::Done {
+            if until == Some(self.events) {
+                break;
+            }
+            if self.events.is_multiple_of(WALL_CLOCK_CHECK_INTERVAL)
+                && self.events > 0
+            {
+                let wall_now = Instant::now();
+                if self
+                    .max_wall_time
+                    .is_some_and(|max| wall_now.duration_since(start) > max)
+                {
+                    self.stopped = Some(StopCondition::WallTime);
+                    break;
+                }
+                if let Some(interval) = self.progress_interval {
+                    let (last_time, last_events) = last_progress;
+                    let elapsed = wall_now.duration_since(last_time);
+                    if elapsed >= interval {
+                        let events_per_sec = (self.events - last_events) as f64
+                            / elapsed.as_secs_f64();
+                        info!(
+                            "simulated time: {}ms | commands completed: {} | events/s: {:.0}",
+                            self.simulation.time().millis(),
+                            self.commands_completed,
+                            events_per_sec
+                        );
+                        last_progress = (wall_now, self.events);
+                    }
+                }
+            }
 
-        while simulation_status != SimulationStatus::Done {
             let action = self.schedule
                 .next_action(self.simulation.time())
                 .expect("there should be a new action since stability is always running");
 
+            // check if the simulation should stop; if yes, the action is
+            // kept so that the simulation can be continued
+            let now = self.simulation.time().millis();
+            if self.max_sim_time.is_some_and(|max| now > max) {
+                self.schedule.put_back(action);
+                self.stopped = Some(StopCondition::SimTime);
+                break;
+            }
+            self.events += 1;
+
+            // messages to slow processes are only handled once processed
+            let action = match self.process_message(action) {
+                Some(action) => action,
+                None => continue,
+            };
+
             match action {
                 ScheduleAction::PeriodicProcessEvent(
                     process_id,
@@ -261,7 +939,25 @@ where
                     from_shard_id,
                     process_id,
                     msg,
+                    trace_id,
                 ) => {
+                    // record the reception of the message (unless it's lost)
+                    if let (Some(trace), Some(id)) =
+                        (self.trace.as_mut(), trace_id)
+                    {
+                        if !self.crashed.contains(&process_id) {
+                            let time = self.simulation.time().millis();
+                            trace.receive(time, id, from, process_id, &msg);
+                        }
+                    }
+                    if let (Some(dots), Some(dot)) =
+                        (self.dots.as_mut(), msg.dot())
+                    {
+                        if !self.crashed.contains(&process_id) {
+                            let time = self.simulation.time().millis();
+                            dots.receive(time, *dot, from, process_id, &msg);
+                        }
+                    }
                     self.handle_send_to_proc(
                         from,
                         from_shard_id,
@@ -269,7 +965,34 @@ where
                         msg,
                     );
                 }
+                ScheduleAction::Crash(process_id) => {
+                    self.handle_crash(process_id);
+                }
+                ScheduleAction::Recover(process_id, recovery) => {
+                    self.handle_recover(process_id, recovery);
+                }
+                ScheduleAction::Suspect(process_id) => {
+                    self.handle_suspect(process_id);
+                }
+                ScheduleAction::Processed(_) => {
+                    unreachable!("processed messages should be unwrapped")
+                }
                 ScheduleAction::SendToClient(client_id, cmd_result) => {
+                    self.commands_completed += 1;
+                    // record the response of the command
+                    if let Some(history) = self.history.as_mut() {
+                        history.respond(&cmd_result);
+                    }
+                    if let Some(trace) = self.trace.as_mut() {
+                        trace.record(TraceEvent::Result {
+                            time: self.simulation.time().millis(),
+                            rifl: cmd_result.rifl(),
+                        });
+                    }
+                    if let Some(dots) = self.dots.as_mut() {
+                        let time = self.simulation.time().millis();
+                        dots.result(time, cmd_result.rifl());
+                    }
                     // handle new command result in client
                     let submit = self.simulation.forward_to_client(cmd_result);
                     if let Some((process_id, cmd)) = submit {
@@ -279,14 +1002,14 @@ where
                             cmd,
                         );
                     } else {
-                        clients_done += 1;
+                        self.clients_done += 1;
                         // if all clients are done, enter the next phase
-                        if clients_done == self.client_count {
-                            simulation_status = match extra_sim_time {
+                        if self.clients_done == self.client_count {
+                            self.status = match extra_sim_time {
                                 Some(extra) => {
                                     // if there's extra time, compute the
                                     // final simulation time
-                                    simulation_final_time =
+                                    self.simulation_final_time =
                                         self.simulation.time().millis()
                                             + extra.as_millis() as u64;
                                     SimulationStatus::ExtraSimulationTime
@@ -303,27 +1026,60 @@ where
 
             // check if we're in extra simulation time; if yes, finish the
             // simulation if we're past the final simulation time
-            let should_end_sim = simulation_status
+            let should_end_sim = self.status
                 == SimulationStatus::ExtraSimulationTime
-                && self.simulation.time().millis() > simulation_final_time;
+                && self.simulation.time().millis() > self.simulation_final_time;
             if should_end_sim {
-                simulation_status = SimulationStatus::Done;
+                self.status = SimulationStatus::Done;
             }
         }
     }
 
+    // If `action` is a message to a slow process, schedules it to be handled
+    // once the process is done processing it (and returns `None`); otherwise,
+    // returns the action to be handled now.
+    fn process_message(
+        &mut self,
+        action: ScheduleAction<P::Message, P::PeriodicEvent>,
+    ) -> Option<ScheduleAction<P::Message, P::PeriodicEvent>> {
+        let process_id = match action {
+            ScheduleAction::Processed(action) => return Some(*action),
+            ScheduleAction::SubmitToProc(process_id, _)
+            | ScheduleAction::SendToProc(_, _, process_id, _, _) => process_id,
+            _ => return Some(action),
+        };
+        let now = self.simulation.time().micros();
+        let delay = match self.stragglers.get_mut(&process_id) {
+            Some(straggler) => straggler.delay(now),
+            None => return Some(action),
+        };
+        if delay == 0 {
+            return Some(action);
+        }
+        let action = ScheduleAction::Processed(Box::new(action));
+        self.schedule.schedule(
+            self.simulation.time(),
+            Duration::from_micros(delay),
+            action,
+        );
+        None
+    }
+
     fn handle_periodic_process_event(
         &mut self,
         process_id: ProcessId,
         event: P::PeriodicEvent,
         delay: Duration,
     ) {
-        // get process
-        let (process, _, _, time) = self.simulation.get_process(process_id);
+        // crashed processes skip periodic events
+        if !self.crashed.contains(&process_id) {
+            // get process
+            let (process, _, _, time) = self.simulation.get_process(process_id);
 
-        // handle event adn schedule new actions
-        process.handle_event(event.clone(), time);
-        self.send_to_processes_and_executors(process_id);
+            // handle event adn schedule new actions
+            process.handle_event(event.clone(), time);
+            self.send_to_processes_and_executors(process_id);
+        }
 
         // schedule the next periodic event
         self.schedule_periodic_process_event(process_id, event, delay);
@@ -334,14 +1090,17 @@ where
         process_id: ProcessId,
         delay: Duration,
     ) {
-        // get process and executor
-        let (process, executor, _, time) =
-            self.simulation.get_process(process_id);
-
-        // handle executed and schedule new actions
-        if let Some(executed) = executor.executed(time) {
-            process.handle_executed(executed, time);
-            self.send_to_processes_and_executors(process_id);
+        // crashed processes skip periodic events
+        if !self.crashed.contains(&process_id) {
+            // get process and executor
+            let (process, executor, _, time) =
+                self.simulation.get_process(process_id);
+
+            // handle executed and schedule new actions
+            if let Some(executed) = executor.executed(time) {
+                process.handle_executed(executed, time);
+                self.send_to_processes_and_executors(process_id);
+            }
         }
 
         // schedule the next periodic event
@@ -349,16 +1108,65 @@ where
     }
 
     fn handle_submit_to_proc(&mut self, process_id: ProcessId, cmd: Command) {
+        // commands submitted to crashed processes are lost
+        if self.crashed.contains(&process_id) {
+            return;
+        }
+
+        // record the arrival of the command
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(TraceEvent::Submit {
+                time: self.simulation.time().millis(),
+                rifl: cmd.rifl(),
+                process_id,
+            });
+        }
+
         // get process and executor
-        let (process, _executor, pending, time) =
+        let (process, executor, pending, time) =
             self.simulation.get_process(process_id);
 
         // register command in pending
         pending.wait_for(&cmd);
 
+        // reads with eventual consistency are answered immediately by the
+        // local executor
+        if cmd.local_read() {
+            let rifl = cmd.rifl();
+            let session = cmd.session().clone();
+            for (key, ops) in cmd.into_iter(process.shard_id()) {
+                let after = session.get(&key).cloned();
+                executor.local_read(LocalRead::new(rifl, key, ops, after));
+            }
+            let ready: Vec<_> = executor
+                .to_clients_iter()
+                .filter_map(|executor_result| {
+                    pending.add_executor_result(executor_result)
+                })
+                .collect();
+
+            // schedule new command results
+            ready.into_iter().for_each(|cmd_result| {
+                self.schedule_to_client(
+                    MessageRegion::Process(process_id),
+                    cmd_result,
+                )
+            });
+            return;
+        }
+
+        // the next new dot of the process is assigned to the command
+        if let Some(dots) = self.dots.as_mut() {
+            dots.submitting(process_id, cmd.rifl(), time.millis());
+        }
+
         // submit to process and schedule new actions
         process.submit(None, cmd, time);
         self.send_to_processes_and_executors(process_id);
+
+        if let Some(dots) = self.dots.as_mut() {
+            dots.submitted();
+        }
     }
 
     fn handle_send_to_proc(
@@ -368,6 +1176,11 @@ where
         process_id: ProcessId,
         msg: P::Message,
     ) {
+        // messages sent to crashed processes are lost
+        if self.crashed.contains(&process_id) {
+            return;
+        }
+
         // get process and executor
         let (process, _, _, time) = self.simulation.get_process(process_id);
 
@@ -376,6 +1189,77 @@ where
         self.send_to_processes_and_executors(process_id);
     }
 
+    fn handle_crash(&mut self, process_id: ProcessId) {
+        let new = self.crashed.insert(process_id);
+        assert!(new, "process {} is already crashed", process_id);
+
+        // drop the messages in flight from and to the process, as well as the
+        // ones queued at the process (if it's slow)
+        self.schedule.retain(|action| match action {
+            ScheduleAction::SendToProc(from, _, to, _, _) => {
+                *from != process_id && *to != process_id
+            }
+            ScheduleAction::Processed(action) => !matches!(
+                **action,
+                ScheduleAction::SubmitToProc(to, _)
+                | ScheduleAction::SendToProc(_, _, to, _, _)
+                if to == process_id
+            ),
+            _ => true,
+        });
+        if let Some(straggler) = self.stragglers.get_mut(&process_id) {
+            straggler.busy_until = 0;
+        }
+    }
+
+    fn handle_recover(&mut self, process_id: ProcessId, recovery: Recovery) {
+        let removed = self.crashed.remove(&process_id);
+        assert!(removed, "process {} is not crashed", process_id);
+
+        if recovery == Recovery::Empty {
+            // create a new process; its periodic events are already scheduled
+            // (as the ones of the crashed process are never cancelled)
+            let (_, shard_id, region) = self
+                .to_discover
+                .iter()
+                .find(|(id, _, _)| *id == process_id)
+                .cloned()
+                .expect("process should be known");
+            let (mut process, _) = P::new(process_id, shard_id, self.config);
+            let executor = Self::start_process(
+                &mut process,
+                &region,
+                &self.planet,
+                self.config,
+                &self.to_discover,
+            );
+            self.simulation.restart_process(process, executor);
+        }
+    }
+
+    fn handle_suspect(&mut self, suspected: ProcessId) {
+        // notify processes by the order of their identifiers, so that runs
+        // with the same seed are the same
+        let mut process_ids: Vec<_> = self
+            .process_to_region
+            .keys()
+            .filter(|process_id| {
+                **process_id != suspected && !self.crashed.contains(process_id)
+            })
+            .copied()
+            .collect();
+        process_ids.sort_unstable();
+        for process_id in process_ids {
+            let (process, _, _, time) = self.simulation.get_process(process_id);
+            process.handle_failure(
+                suspected,
+                FailureDetection::Suspected,
+                time,
+            );
+            self.send_to_processes_and_executors(process_id);
+        }
+    }
+
     // (maybe) Schedules a new submit from a client.
     fn schedule_submit(
         &mut self,
@@ -383,6 +1267,10 @@ where
         process_id: ProcessId,
         cmd: Command,
     ) {
+        // record the invocation of the command
+        if let Some(history) = self.history.as_mut() {
+            history.invoke(&cmd);
+        }
         // create action and schedule it
         let action = ScheduleAction::SubmitToProc(process_id, cmd);
         self.schedule_message(
@@ -403,20 +1291,48 @@ where
         let protocol_actions = process.to_processes_iter().collect();
 
         // handle new execution info in the executor
-        let ready: Vec<_> = process
-            .to_executors_iter()
+        let trace = &mut self.trace;
+        let dots = &mut self.dots;
+        let infos: Vec<_> = process.to_executors_iter().collect();
+        // record the commits of the dots of the execution infos
+        if let Some(dots) = dots.as_mut() {
+            let dots_committed =
+                infos.iter().filter_map(P::Executor::execution_info_dot);
+            for dot in dots_committed {
+                dots.commit(time.millis(), dot, process_id);
+            }
+        }
+        let ready: Vec<_> = infos
+            .into_iter()
             .flat_map(|info| {
                 executor.handle(info, time);
-                // handle executor messages to self
-                let to_executors =
-                    executor.to_executors_iter().collect::<Vec<_>>();
-                for (shard_id, info) in to_executors {
+                // handle executor messages to self (which may lead to new
+                // ones)
+                while let Some((shard_id, info)) = executor.to_executors() {
                     assert_eq!(shard_id, DEFAULT_SHARD_ID);
                     executor.handle(info, time);
                 }
                 // TODO remove collect
                 executor.to_clients_iter().collect::<Vec<_>>()
             })
+            // record commits and executions
+            .inspect(|executor_result| {
+                if let Some(trace) = trace.as_mut() {
+                    Self::trace_execution(
+                        trace,
+                        process_id,
+                        time.millis(),
+                        executor_result,
+                    );
+                }
+                if let Some(dots) = dots.as_mut() {
+                    dots.execute(
+                        time.millis(),
+                        executor_result.rifl,
+                        process_id,
+                    );
+                }
+            })
             // handle all partial results in pending
             .filter_map(|executor_result| {
                 pending.add_executor_result(executor_result)
@@ -449,9 +1365,24 @@ where
         protocol_actions: Vec<Action<P>>,
     ) {
         for protocol_action in protocol_actions {
+            // record the dot the message is about
+            if let Some(dots) = self.dots.as_mut() {
+                let msg = match &protocol_action {
+                    Action::ToSend { msg, .. } | Action::ToForward { msg } => {
+                        msg
+                    }
+                };
+                if let Some(dot) = msg.dot() {
+                    dots.see(*dot);
+                }
+            }
             match protocol_action {
                 Action::ToSend { target, msg } => {
-                    // for each process in target, schedule message delivery
+                    // for each process in target (by the order of their
+                    // identifiers, so that runs with the same seed are the
+                    // same), schedule message delivery
+                    let mut target: Vec<_> = target.into_iter().collect();
+                    target.sort_unstable();
                     target.into_iter().for_each(|to| {
                         // if message to self, deliver immediately
                         if to == process_id {
@@ -460,14 +1391,23 @@ where
                                 shard_id,
                                 process_id,
                                 msg.clone(),
-                            )
-                        } else {
-                            // otherwise, create action and schedule it
+                            );
+                            return;
+                        }
+                        // record the message sent
+                        let time = self.simulation.time().millis();
+                        let trace_id = self.trace.as_mut().map(|trace| {
+                            trace.send(time, process_id, to, &msg)
+                        });
+                        if !self.message_loss.drop(process_id, to, &msg) {
+                            // unless the message is dropped, create action and
+                            // schedule it
                             let action = ScheduleAction::SendToProc(
                                 process_id,
                                 shard_id,
                                 to,
                                 msg.clone(),
+                                trace_id,
                             );
                             self.schedule_message(
                                 from_region.clone(),
@@ -487,6 +1427,32 @@ where
         }
     }
 
+    // Records the commit (if its time is known) and execution of a command on
+    // a key at some process.
+    fn trace_execution(
+        trace: &mut EventTrace,
+        process_id: ProcessId,
+        now: u64,
+        executor_result: &ExecutorResult,
+    ) {
+        let rifl = executor_result.rifl;
+        let key = &executor_result.key;
+        if let Some(timestamps) = &executor_result.timestamps {
+            trace.record(TraceEvent::Commit {
+                time: timestamps.commit,
+                rifl,
+                key: key.clone(),
+                process_id,
+            });
+        }
+        trace.record(TraceEvent::Execute {
+            time: now,
+            rifl,
+            key: key.clone(),
+            process_id,
+        });
+    }
+
     /// Schedules a new command result.
     fn schedule_to_client(
         &mut self,
@@ -510,25 +1476,106 @@ where
         to_region: MessageRegion,
         action: ScheduleAction<P::Message, P::PeriodicEvent>,
     ) {
+        // if processes are partitioned, the message is held until the
+        // partition heals
+        let held = match (&from_region, &to_region) {
+            (MessageRegion::Process(from), MessageRegion::Process(to)) => {
+                let now = self.simulation.time().millis();
+                self.partitions
+                    .iter()
+                    .map(|partition| partition.delay(*from, *to, now))
+                    .max()
+                    .unwrap_or_default()
+            }
+            _ => 0,
+        };
+
+        // if links have limited bandwidth, the message is further delayed
+        // until it is transmitted (after the messages queued before it)
+        let transmission = if self.bandwidth.is_limited() {
+            let size = Self::message_size(&action);
+            let start = self.simulation.time().micros() + held * 1000;
+            let from = self.compute_region(from_region.clone()).clone();
+            let to = self.compute_region(to_region.clone()).clone();
+            self.bandwidth.delay(
+                (from_region.clone(), &from),
+                (to_region.clone(), &to),
+                size,
+                start,
+            )
+        } else {
+            0
+        };
+
         // get actual regions
         let from = self.compute_region(from_region);
         let to = self.compute_region(to_region);
         // compute distance between regions
-        let mut distance = self.distance(from, to);
+        let distance = self.distance(from, to);
+
+        // sample the latency of the message from the latency model of the link
+        let model = if self.link_latency_models.is_empty() {
+            self.latency_model
+        } else {
+            self.link_latency_models
+                .get(&(from.clone(), to.clone()))
+                .cloned()
+                .unwrap_or(self.latency_model)
+        };
+        let mut distance = model.sample(distance, &mut self.latency_rng);
 
         // check if we should reorder messages
         if self.reorder_messages {
             // if so, multiply distance by some random number between 0 and 10
-            let multiplier: f64 = rand::thread_rng().gen_range(0.0..10.0);
+            let multiplier: f64 = self.reorder_rng.gen_range(0.0..10.0);
             distance = (distance as f64 * multiplier) as u64;
         }
 
         // schedule action
-        let distance = Duration::from_millis(distance);
+        let distance = Duration::from_millis(held + distance)
+            + Duration::from_micros(transmission);
         self.schedule
             .schedule(self.simulation.time(), distance, action);
     }
 
+    /// Computes the serialized size (in bytes) of the message in `action`.
+    fn message_size(
+        action: &ScheduleAction<P::Message, P::PeriodicEvent>,
+    ) -> u64 {
+        let size = match action {
+            ScheduleAction::SubmitToProc(_, cmd) => {
+                bincode::serialized_size(cmd)
+            }
+            ScheduleAction::SendToProc(_, _, _, msg, _) => {
+                bincode::serialized_size(msg)
+            }
+            ScheduleAction::SendToClient(_, cmd_result) => {
+                bincode::serialized_size(cmd_result)
+            }
+            action => panic!("action {:?} is not a message", action),
+        };
+        size.expect("messages should be serializable")
+    }
+
+    /// Schedules a crash or recovery of some process at simulated time `at`.
+    fn schedule_event(
+        &mut self,
+        process_id: ProcessId,
+        at: u64,
+        action: ScheduleAction<P::Message, P::PeriodicEvent>,
+    ) {
+        assert!(
+            self.process_to_region.contains_key(&process_id),
+            "process {} should exist",
+            process_id
+        );
+        let now = self.simulation.time().millis();
+        assert!(at >= now, "events can't be scheduled in the past");
+        let delay = Duration::from_millis(at - now);
+        self.schedule
+            .schedule(self.simulation.time(), delay, action);
+    }
+
     /// Schedules the next periodic process event.
     fn schedule_periodic_process_event(
         &mut self,
@@ -570,32 +1617,92 @@ where
         }
     }
 
-    /// Computes the distance between two regions which is half the ping
-    /// latency.
+    /// Computes the distance between two regions which is the one-way delay
+    /// from `from` to `to` (by default, half the ping latency).
     fn distance(&self, from: &Region, to: &Region) -> u64 {
-        let from_to = self
-            .planet
-            .ping_latency(from, to)
-            .expect("both regions should exist on the planet");
-
-        // compute ping time (maybe make it symmetric)
-        let ping = if self.make_distances_symmetric {
+        if self.make_distances_symmetric {
+            let from_to = self
+                .planet
+                .ping_latency(from, to)
+                .expect("both regions should exist on the planet");
             let to_from = self
                 .planet
                 .ping_latency(to, from)
                 .expect("both regions should exist on the planet");
-            (from_to + to_from) / 2
+
+            // distance is half the symmetric ping latency
+            (from_to + to_from) / 2 / 2
         } else {
-            from_to
-        };
+            self.planet
+                .one_way_latency(from, to)
+                .expect("both regions should exist on the planet")
+        }
+    }
+
+    /// Returns the metrics of the simulation per region (i.e. the number of
+    /// commands committed in the fast and slow path by the processes in each
+    /// region, and the latency, commit latency and execution delay of the
+    /// commands of the clients in each region). This should be called after
+    /// `Runner::run`.
+    pub fn simulation_metrics(&mut self) -> SimulationMetrics {
+        let mut simulation_metrics = SimulationMetrics::new();
+
+        // fast and slow paths of each process
+        let paths = self.check_processes_and_executors(|process, _| {
+            let metrics = process.metrics();
+            (metrics.fast_paths(), metrics.slow_paths())
+        });
+        for (process_id, (fast_paths, slow_paths)) in paths {
+            let region = self
+                .process_to_region
+                .get(&process_id)
+                .expect("process region should be known");
+            simulation_metrics
+                .region_mut(region)
+                .record_paths(fast_paths, slow_paths);
+        }
 
-        // distance is half the ping latency
-        let ms = ping / 2;
-        ms
+        // latencies of each client
+        for (&client_id, region) in self.client_to_region.iter() {
+            let metrics = simulation_metrics.region_mut(region);
+            let (client, _) = self.simulation.get_client(client_id);
+            metrics.record_issued_commands(client.issued_commands());
+            // since the simulation assumes WAN, use milliseconds for latency
+            // precision
+            let data = client.data();
+            for latency in data.latency_data() {
+                metrics.record_latency(latency.as_millis() as u64);
+            }
+            for (commit_latency, execution_delay) in
+                data.commit_latency_data().zip(data.execution_delay_data())
+            {
+                metrics.record_breakdown(
+                    commit_latency.as_millis() as u64,
+                    execution_delay.as_millis() as u64,
+                );
+            }
+        }
+        simulation_metrics
     }
 
     /// Get metrics from processes and executors.
     /// TODO does this need to be mut?
+    /// Returns the impact on the latency of clients of the last failover (if
+    /// `Runner::failover` was called).
+    pub fn failover_metrics(&mut self) -> Option<FailoverMetrics> {
+        let at = self.failover?;
+        let simulation = &mut self.simulation;
+        let latencies = self
+            .client_to_region
+            .keys()
+            .flat_map(|&client_id| {
+                let (client, _) = simulation.get_client(client_id);
+                client.data().latency_data_by_end_time().collect::<Vec<_>>()
+            })
+            .collect();
+        Some(FailoverMetrics::new(at, latencies))
+    }
+
     fn metrics(
         &mut self,
     ) -> HashMap<ProcessId, (ProtocolMetrics, ExecutorMetrics)> {
@@ -694,6 +1801,7 @@ impl<Message: Debug, PeriodicEvent: Debug> fmt::Debug
                 from_shard_id,
                 to,
                 msg,
+                _,
             ) => write!(
                 f,
                 "SendToProc({}, {}, {}, {:?})",
@@ -716,6 +1824,18 @@ impl<Message: Debug, PeriodicEvent: Debug> fmt::Debug
                     process_id, delay
                 )
             }
+            ScheduleAction::Crash(process_id) => {
+                write!(f, "Crash({})", process_id)
+            }
+            ScheduleAction::Recover(process_id, recovery) => {
+                write!(f, "Recover({}, {:?})", process_id, recovery)
+            }
+            ScheduleAction::Suspect(process_id) => {
+                write!(f, "Suspect({})", process_id)
+            }
+            ScheduleAction::Processed(action) => {
+                write!(f, "Processed({:?})", action)
+            }
         }
     }
 }
@@ -726,8 +1846,35 @@ mod tests {
     use crate::client::KeyGen;
     use crate::metrics::F64;
     use crate::protocol::{Basic, ProtocolMetricsKind};
+    use crate::sim::LatencyDistribution;
+
+    type BasicMessage = <Basic as Protocol>::Message;
 
-    fn run(f: usize, clients_per_process: usize) -> (Histogram, Histogram) {
+    const COMMANDS_PER_CLIENT: usize = 1000;
+
+    fn new_runner(
+        f: usize,
+        clients_per_process: usize,
+        local_reads: bool,
+        client_regions: Vec<Region>,
+    ) -> Runner<Basic> {
+        let seed = 0;
+        new_runner_with_seed(
+            f,
+            clients_per_process,
+            local_reads,
+            client_regions,
+            seed,
+        )
+    }
+
+    fn new_runner_with_seed(
+        f: usize,
+        clients_per_process: usize,
+        local_reads: bool,
+        client_regions: Vec<Region>,
+        seed: u64,
+    ) -> Runner<Basic> {
         // planet
         let planet = Planet::new();
 
@@ -747,15 +1894,21 @@ mod tests {
             pool_size,
             conflict_rate,
         };
-        let commands_per_client = 1000;
+        let commands_per_client = COMMANDS_PER_CLIENT;
         let payload_size = 100;
-        let workload = Workload::new(
+        let mut workload = Workload::new(
             shard_count,
             key_gen,
             keys_per_command,
             commands_per_client,
             payload_size,
         );
+        // if local reads are enabled, all commands are reads with eventual
+        // consistency
+        if local_reads {
+            workload.set_read_only_percentage(100);
+            workload.set_eventual_read_percentage(100);
+        }
 
         // process regions
         let process_regions = vec![
@@ -764,19 +1917,29 @@ mod tests {
             Region::new("us-west1"),
         ];
 
-        // client regions
-        let client_regions =
-            vec![Region::new("us-west1"), Region::new("us-west2")];
-
         // create runner
-        let mut runner: Runner<Basic> = Runner::new(
+        Runner::new(
             planet,
             config,
             workload,
             clients_per_process,
             process_regions,
             client_regions,
-        );
+            seed,
+        )
+    }
+
+    fn run(
+        f: usize,
+        clients_per_process: usize,
+        local_reads: bool,
+    ) -> (Histogram, Histogram) {
+        // client regions
+        let client_regions =
+            vec![Region::new("us-west1"), Region::new("us-west2")];
+
+        let mut runner =
+            new_runner(f, clients_per_process, local_reads, client_regions);
 
         // run simulation until the clients end + another second second
         let (metrics, _executors_monitors, mut clients_latencies) =
@@ -791,23 +1954,27 @@ mod tests {
             .expect("there should stats from us-west2 region");
 
         // check the number of issued commands
-        let expected = commands_per_client * clients_per_process;
+        let expected = COMMANDS_PER_CLIENT * clients_per_process;
         assert_eq!(us_west1_issued, expected);
         assert_eq!(us_west2_issued, expected);
 
         // check process stats
         metrics.values().into_iter().for_each(
             |(process_metrics, _executor_metrics)| {
-                // check stability has run
-                let stable_count = process_metrics
-                    .get_aggregated(ProtocolMetricsKind::Stable)
-                    .expect("stability should have happened");
-
                 // check that all commands were gc-ed:
                 // - since we have clients in two regions, the total number of
                 //   commands is two times the expected per region
-                let total_commands = (expected * 2) as u64;
-                assert!(*stable_count == total_commands)
+                // - local reads are never submitted to the protocol
+                let total_commands = if local_reads {
+                    0
+                } else {
+                    (expected * 2) as u64
+                };
+                let stable_count = process_metrics
+                    .get_aggregated(ProtocolMetricsKind::Stable)
+                    .cloned()
+                    .unwrap_or_default();
+                assert_eq!(stable_count, total_commands)
             },
         );
 
@@ -831,13 +1998,13 @@ mod tests {
 
         // f = 0
         let f = 0;
-        let (us_west1, us_west2) = run(f, clients_per_process);
+        let (us_west1, us_west2) = run(f, clients_per_process, false);
         assert_eq!(us_west1.mean(), F64::new(0.0));
         assert_eq!(us_west2.mean(), F64::new(24.0));
 
         // f = 1
         let f = 1;
-        let (us_west1, us_west2) = run(f, clients_per_process);
+        let (us_west1, us_west2) = run(f, clients_per_process, false);
         assert_eq!(us_west1.mean(), F64::new(34.0));
         assert_eq!(us_west2.mean(), F64::new(58.0));
     }
@@ -848,13 +2015,13 @@ mod tests {
         let f = 1;
         let clients_per_process = 1;
         let (us_west1_with_one, us_west2_with_one) =
-            run(f, clients_per_process);
+            run(f, clients_per_process, false);
 
         // 10 clients per region
         let f = 1;
         let clients_per_process = 10;
         let (us_west1_with_ten, us_west2_with_ten) =
-            run(f, clients_per_process);
+            run(f, clients_per_process, false);
 
         // check stats are the same
         assert_eq!(us_west1_with_one.mean(), us_west1_with_ten.mean());
@@ -862,4 +2029,621 @@ mod tests {
         assert_eq!(us_west2_with_one.mean(), us_west2_with_ten.mean());
         assert_eq!(us_west2_with_one.cov(), us_west2_with_ten.cov());
     }
+
+    #[test]
+    fn runner_local_reads() {
+        // expected stats: since local reads are answered by the coordinator,
+        // from client's perspective the latency should be the latency of
+        // accessing the coordinator (0ms for us-west1, and 12ms + 12ms for
+        // us-west2), independently of `f`
+        let clients_per_process = 1;
+        let local_reads = true;
+        for f in 0..=1 {
+            let (us_west1, us_west2) = run(f, clients_per_process, local_reads);
+            assert_eq!(us_west1.mean(), F64::new(0.0));
+            assert_eq!(us_west2.mean(), F64::new(24.0));
+        }
+    }
+
+    fn client_regions() -> Vec<Region> {
+        vec![Region::new("us-west1"), Region::new("us-west2")]
+    }
+
+    fn is_gc(msg: &BasicMessage) -> bool {
+        matches!(msg, BasicMessage::MGarbageCollection { .. })
+    }
+
+    // Runs the simulation and returns the number of commands issued by the
+    // clients of each region, and the number of stable commands at each
+    // process.
+    fn run_with_loss(
+        mut runner: Runner<Basic>,
+    ) -> (Vec<usize>, HashMap<ProcessId, u64>, usize) {
+        let (metrics, _executors_monitors, clients_latencies) =
+            runner.run(Some(Duration::from_secs(1)));
+        let issued = clients_latencies
+            .values()
+            .map(|(issued, _)| *issued)
+            .collect();
+        let stable = metrics
+            .into_iter()
+            .map(|(process_id, (process_metrics, _executor_metrics))| {
+                let stable_count = process_metrics
+                    .get_aggregated(ProtocolMetricsKind::Stable)
+                    .cloned()
+                    .unwrap_or_default();
+                (process_id, stable_count)
+            })
+            .collect();
+        (issued, stable, runner.dropped_messages())
+    }
+
+    #[test]
+    fn runner_message_loss() {
+        let f = 1;
+        let clients_per_process = 1;
+        let total_commands = (COMMANDS_PER_CLIENT * 2) as u64;
+
+        // dropping all garbage collection messages doesn't prevent clients from
+        // completing their commands, but no command is ever stable
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.drop_messages(1.0, 0);
+        runner.drop_messages_if(is_gc);
+        let (issued, stable, dropped) = run_with_loss(runner);
+        assert_eq!(issued, vec![COMMANDS_PER_CLIENT; 2]);
+        assert!(stable.values().all(|stable_count| *stable_count == 0));
+        assert!(dropped > 0);
+
+        // dropping the garbage collection messages sent by the coordinator of
+        // all commands (the process in us-west1) to the process in asia-east1
+        // only prevents the latter from considering commands stable
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.drop_link_messages(3, 1, 1.0);
+        runner.drop_messages_if(is_gc);
+        let (issued, stable, dropped) = run_with_loss(runner);
+        assert_eq!(issued, vec![COMMANDS_PER_CLIENT; 2]);
+        assert_eq!(stable.get(&1), Some(&0));
+        assert_eq!(stable.get(&2), Some(&total_commands));
+        assert_eq!(stable.get(&3), Some(&total_commands));
+        assert!(dropped > 0);
+
+        // runs with the same seed drop the same messages
+        let dropped: Vec<_> = (0..2)
+            .map(|_| {
+                let mut runner =
+                    new_runner(f, clients_per_process, false, client_regions());
+                runner.drop_messages(0.5, 42);
+                runner.drop_messages_if(is_gc);
+                let (issued, _, dropped) = run_with_loss(runner);
+                assert_eq!(issued, vec![COMMANDS_PER_CLIENT; 2]);
+                dropped
+            })
+            .collect();
+        assert!(dropped[0] > 0);
+        assert_eq!(dropped[0], dropped[1]);
+    }
+
+    #[test]
+    fn runner_partition() {
+        // clients in asia-east1 (process 1) and us-west1 (process 3)
+        let f = 1;
+        let clients_per_process = 1;
+        let client_regions =
+            vec![Region::new("asia-east1"), Region::new("us-west1")];
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions);
+
+        // partition process 1 from processes 2 and 3 during the first 10
+        // seconds
+        let heal = 10_000;
+        runner.partition(vec![vec![1], vec![2, 3]], 0, heal);
+        let (metrics, _executors_monitors, mut clients_latencies) =
+            runner.run(Some(Duration::from_secs(1)));
+
+        // all clients complete their commands
+        let (asia_east1_issued, asia_east1) = clients_latencies
+            .remove(&Region::new("asia-east1"))
+            .expect("there should stats from asia-east1 region");
+        let (us_west1_issued, us_west1) = clients_latencies
+            .remove(&Region::new("us-west1"))
+            .expect("there should stats from us-west1 region");
+        assert_eq!(asia_east1_issued, COMMANDS_PER_CLIENT);
+        assert_eq!(us_west1_issued, COMMANDS_PER_CLIENT);
+
+        // the minority partition stalls until the partition heals, while the
+        // majority partition makes progress as if there was no partition
+        assert!(asia_east1.max() >= F64::new(heal as f64));
+        assert_eq!(us_west1.mean(), F64::new(34.0));
+
+        // once the partition heals, all processes converge, and all commands
+        // become stable
+        let total_commands = (COMMANDS_PER_CLIENT * 2) as u64;
+        metrics.values().into_iter().for_each(
+            |(process_metrics, _executor_metrics)| {
+                let stable_count = process_metrics
+                    .get_aggregated(ProtocolMetricsKind::Stable)
+                    .cloned()
+                    .unwrap_or_default();
+                assert_eq!(stable_count, total_commands)
+            },
+        );
+    }
+
+    #[test]
+    fn runner_slow_down() {
+        // clients in us-west1 (process 3), whose commands are replicated at
+        // process 2 (in us-central1)
+        let f = 1;
+        let clients_per_process = 1;
+        let client_regions = vec![Region::new("us-west1")];
+        let run = |slow_down: Option<(Duration, u64)>| {
+            let mut runner = new_runner(
+                f,
+                clients_per_process,
+                false,
+                client_regions.clone(),
+            );
+            if let Some((delay, to)) = slow_down {
+                runner.slow_down(2, delay, 0, to);
+            }
+            let (_, _, mut clients_latencies) = runner.run(None);
+            let (issued, latency) = clients_latencies
+                .remove(&Region::new("us-west1"))
+                .expect("there should stats from us-west1 region");
+            assert_eq!(issued, COMMANDS_PER_CLIENT);
+            latency
+        };
+
+        // without slowdowns, all commands take 34ms
+        let latency = run(None);
+        assert_eq!(latency.min(), F64::new(34.0));
+        assert_eq!(latency.max(), F64::new(34.0));
+
+        // if process 2 takes 10ms to process each message during the first 5
+        // seconds, the commands submitted then take at least 10ms more (and
+        // more than that when messages queue up at process 2)
+        let latency = run(Some((Duration::from_millis(10), 5_000)));
+        assert_eq!(latency.min(), F64::new(34.0));
+        assert!(latency.max() >= F64::new(44.0));
+        assert!(latency.mean() > F64::new(34.0));
+
+        // and if it's slow during the whole simulation, all commands are
+        let latency = run(Some((Duration::from_millis(10), u64::MAX)));
+        assert!(latency.min() >= F64::new(44.0));
+    }
+
+    #[test]
+    fn runner_crash() {
+        let f = 1;
+        let clients_per_process = 1;
+        let total_commands = (COMMANDS_PER_CLIENT * 2) as u64;
+
+        for recovery in [None, Some(Recovery::Empty), Some(Recovery::Persisted)]
+        {
+            // crash the process in asia-east1 (process 1), which is not part of
+            // the quorum of the coordinator of all commands (the process in
+            // us-west1), and maybe recover it after 5 seconds
+            let mut runner =
+                new_runner(f, clients_per_process, false, client_regions());
+            runner.crash(1, 0);
+            if let Some(recovery) = recovery {
+                runner.recover(1, 5_000, recovery);
+            }
+            let (metrics, _executors_monitors, mut clients_latencies) =
+                runner.run(Some(Duration::from_secs(1)));
+
+            // clients complete their commands with the same latency as without
+            // crashes (see `runner_single_client_per_process`)
+            let (us_west1_issued, us_west1) = clients_latencies
+                .remove(&Region::new("us-west1"))
+                .expect("there should stats from us-west1 region");
+            let (us_west2_issued, us_west2) = clients_latencies
+                .remove(&Region::new("us-west2"))
+                .expect("there should stats from us-west2 region");
+            assert_eq!(us_west1_issued, COMMANDS_PER_CLIENT);
+            assert_eq!(us_west2_issued, COMMANDS_PER_CLIENT);
+            assert_eq!(us_west1.mean(), F64::new(34.0));
+            assert_eq!(us_west2.mean(), F64::new(58.0));
+
+            // since the crashed process misses the commits of the commands
+            // submitted while it's crashed, these never become stable
+            metrics.values().into_iter().for_each(
+                |(process_metrics, _executor_metrics)| {
+                    let stable_count = process_metrics
+                        .get_aggregated(ProtocolMetricsKind::Stable)
+                        .cloned()
+                        .unwrap_or_default();
+                    assert!(stable_count < total_commands)
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn runner_latency_model() {
+        let us_west1 = Region::new("us-west1");
+        let us_west2 = Region::new("us-west2");
+
+        // returns the latencies of the client in us-west2 when the links
+        // between it and its coordinator (the process in us-west1) follow
+        // `model`
+        let run_with_model = |model: LatencyModel, seed: u64| {
+            let f = 1;
+            let clients_per_process = 1;
+            let mut runner =
+                new_runner(f, clients_per_process, false, client_regions());
+            runner.set_latency_model(LatencyModel::fixed(), seed);
+            runner.set_link_latency_model(
+                us_west2.clone(),
+                us_west1.clone(),
+                model,
+            );
+            runner.set_link_latency_model(
+                us_west1.clone(),
+                us_west2.clone(),
+                model,
+            );
+            let (_metrics, _executors_monitors, mut clients_latencies) =
+                runner.run(Some(Duration::from_secs(1)));
+            let (_, us_west2_latencies) = clients_latencies
+                .remove(&us_west2)
+                .expect("there should stats from us-west2 region");
+            us_west2_latencies
+        };
+
+        // with fixed latencies, the latency is the one of accessing the
+        // coordinator (12ms + 12ms) plus the one of accessing the closest
+        // quorum (34ms), as in `runner_single_client_per_process`
+        let fixed = run_with_model(LatencyModel::fixed(), 0);
+        assert_eq!(fixed.mean(), F64::new(58.0));
+
+        // spikes that always occur delay both the submit and the result
+        let spikes =
+            run_with_model(LatencyModel::fixed().with_spikes(1.0, 100), 0);
+        assert_eq!(spikes.mean(), F64::new(258.0));
+
+        // with jitter, latencies vary around the fixed latency
+        let normal =
+            LatencyModel::new(LatencyDistribution::Normal { stddev: 5.0 });
+        let jitter = run_with_model(normal, 0);
+        assert!(jitter.cov() > F64::zero());
+        assert!((jitter.mean().value() - 58.0).abs() < 1.0);
+
+        // runs with the same seed have the same latencies
+        assert!(run_with_model(normal, 0) == jitter);
+        assert!(run_with_model(normal, 1) != jitter);
+    }
+
+    #[test]
+    fn runner_seed() {
+        // returns the latencies of the clients in each region when messages
+        // are reordered
+        let run_with_seed = |seed: u64| {
+            let f = 1;
+            let clients_per_process = 2;
+            let mut runner = new_runner_with_seed(
+                f,
+                clients_per_process,
+                false,
+                client_regions(),
+                seed,
+            );
+            runner.reorder_messages();
+            let (_metrics, _executors_monitors, clients_latencies) =
+                runner.run(Some(Duration::from_secs(1)));
+            let mut latencies: Vec<_> = clients_latencies.into_iter().collect();
+            latencies.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            latencies
+        };
+
+        // runs with the same seed are the same
+        let latencies = run_with_seed(42);
+        assert!(run_with_seed(42) == latencies);
+        assert!(run_with_seed(43) != latencies);
+    }
+
+    #[test]
+    fn runner_clock_skew() {
+        let f = 1;
+        let clients_per_process = 1;
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+
+        // skew the clock of process 1
+        let skew = ClockSkew::new(10_000, 100.0);
+        runner.set_clock_skew(1, skew);
+        let _ = runner.run(Some(Duration::from_secs(1)));
+
+        // process 1 sees the time shown by its skewed clock, while the other
+        // processes see the simulation time
+        let now = runner.simulation.time().micros();
+        let (_, _, _, time) = runner.simulation.get_process(1);
+        assert_eq!(time.micros(), skew.skew(now));
+        let (_, _, _, time) = runner.simulation.get_process(2);
+        assert_eq!(time.micros(), now);
+    }
+
+    #[test]
+    fn runner_bandwidth() {
+        let us_west2 = Region::new("us-west2");
+
+        // returns the mean latency of the clients in us-west2 when all links
+        // have bandwidth `bandwidth`
+        let run_with_bandwidth =
+            |bandwidth: Option<u64>, clients_per_process: usize| {
+                let f = 1;
+                let mut runner =
+                    new_runner(f, clients_per_process, false, client_regions());
+                if let Some(bandwidth) = bandwidth {
+                    runner.set_bandwidth(bandwidth);
+                }
+                let (_metrics, _executors_monitors, mut clients_latencies) =
+                    runner.run(Some(Duration::from_secs(1)));
+                let (_, us_west2_latencies) = clients_latencies
+                    .remove(&us_west2)
+                    .expect("there should stats from us-west2 region");
+                us_west2_latencies.mean()
+            };
+
+        // without bandwidth limits, the latency is the one in
+        // `runner_single_client_per_process`
+        assert_eq!(run_with_bandwidth(None, 1), F64::new(58.0));
+
+        // the lower the bandwidth, the longer messages take to be transmitted
+        let high = run_with_bandwidth(Some(100_000), 1);
+        let low = run_with_bandwidth(Some(10_000), 1);
+        assert!(F64::new(58.0) < high);
+        assert!(high < low);
+
+        // with more clients, messages queue up in the links
+        let queued = run_with_bandwidth(Some(10_000), 10);
+        assert!(low < queued);
+    }
+
+    #[test]
+    fn runner_simulation_metrics() {
+        let f = 1;
+        let clients_per_process = 1;
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        let _ = runner.run(Some(Duration::from_secs(1)));
+        let metrics = runner.simulation_metrics();
+
+        // latencies are the ones in `runner_single_client_per_process`; since
+        // basic executes commands as soon as they're committed, the commit
+        // latency is the latency
+        let us_west1 = metrics
+            .region(&Region::new("us-west1"))
+            .expect("there should be metrics from us-west1 region");
+        assert_eq!(us_west1.issued_commands(), COMMANDS_PER_CLIENT);
+        assert_eq!(us_west1.latency().mean(), F64::new(34.0));
+        assert_eq!(us_west1.commit_latency().mean(), F64::new(34.0));
+        assert_eq!(us_west1.execution_delay().mean(), F64::zero());
+        let us_west2 = metrics
+            .region(&Region::new("us-west2"))
+            .expect("there should be metrics from us-west2 region");
+        assert_eq!(us_west2.latency().mean(), F64::new(58.0));
+
+        // there are only processes in asia-east1, and basic doesn't report
+        // the path taken by commands
+        let asia_east1 = metrics
+            .region(&Region::new("asia-east1"))
+            .expect("there should be metrics from asia-east1 region");
+        assert_eq!(asia_east1.issued_commands(), 0);
+        assert_eq!(asia_east1.fast_path_ratio(), None);
+
+        let total = metrics.total();
+        assert_eq!(total.issued_commands(), COMMANDS_PER_CLIENT * 2);
+        assert_eq!(total.latency().mean(), F64::new(46.0));
+    }
+
+    #[test]
+    fn runner_history() {
+        let f = 1;
+        let clients_per_process = 2;
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.record_history();
+        let _ = runner.run(Some(Duration::from_secs(1)));
+
+        // all commands are recorded, and the history is linearizable
+        let history = runner.history().expect("history should be recorded");
+        assert_eq!(history.len(), COMMANDS_PER_CLIENT * 4);
+        assert!(history.check_linearizability().is_ok());
+    }
+
+    #[test]
+    fn runner_trace() {
+        let f = 1;
+        let clients_per_process = 1;
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.record_trace();
+        let _ = runner.run(Some(Duration::from_secs(1)));
+
+        let trace = runner.trace().expect("trace should be recorded");
+        let count = |kind: fn(&TraceEvent) -> bool| {
+            trace.events().iter().filter(|event| kind(event)).count()
+        };
+        let commands = COMMANDS_PER_CLIENT * 2;
+        let processes = 3;
+
+        // each command arrives at a process, is committed and executed by all
+        // processes, and its result arrives at the client
+        assert_eq!(count(|e| matches!(e, TraceEvent::Submit { .. })), commands);
+        assert_eq!(
+            count(|e| matches!(e, TraceEvent::Commit { .. })),
+            commands * processes
+        );
+        assert_eq!(
+            count(|e| matches!(e, TraceEvent::Execute { .. })),
+            commands * processes
+        );
+        assert_eq!(count(|e| matches!(e, TraceEvent::Result { .. })), commands);
+
+        // messages are received after being sent
+        let mut sent = HashMap::new();
+        for event in trace.events() {
+            match event {
+                TraceEvent::Send { time, id, .. } => {
+                    sent.insert(*id, *time);
+                }
+                TraceEvent::Receive { time, id, .. } => {
+                    let sent = sent.remove(id).expect("message should be sent");
+                    assert!(sent <= *time);
+                }
+                _ => {}
+            }
+        }
+
+        // events are recorded in the order they happen
+        let times: Vec<_> = trace
+            .events()
+            .iter()
+            .filter(|e| !matches!(e, TraceEvent::Commit { .. }))
+            .map(TraceEvent::time)
+            .collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn runner_dots() {
+        // clients in us-west1 (process 3), whose commands are replicated at
+        // process 2 (in us-central1)
+        let f = 1;
+        let clients_per_process = 1;
+        let client_regions = vec![Region::new("us-west1")];
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions);
+        runner.record_dots();
+        runner.run(Some(Duration::from_secs(1)));
+        let dots = runner.dots().expect("dots should be recorded");
+        assert_eq!(dots.len(), COMMANDS_PER_CLIENT);
+
+        for lifecycle in dots.sorted() {
+            // all dots are coordinated by process 3
+            let dot = lifecycle.dot();
+            assert_eq!(dot.source(), 3);
+            let rifl = lifecycle.rifl().expect("dot should have a command");
+            assert_eq!(dots.get_by_rifl(&rifl), Some(lifecycle));
+
+            // the coordinator gets the reply from process 2 after a round
+            // trip (34ms), and the client gets the result right away (since
+            // it's in the same region)
+            let submit = lifecycle.submit().expect("dot should be submitted");
+            let ack = lifecycle.replies_at("MStoreAck", 1);
+            assert_eq!(ack, Some(submit + 34));
+            assert_eq!(lifecycle.replies_at("MStoreAck", 2), None);
+            assert!(lifecycle.replies().iter().all(|reply| reply.from == 2));
+            assert_eq!(lifecycle.execution(3), Some(submit + 34));
+            assert_eq!(lifecycle.result(), Some(submit + 34));
+
+            // the commit is sent to all processes
+            assert_eq!(lifecycle.executions().len(), 3);
+
+            // the basic executor doesn't know the dots of what it executes
+            assert!(lifecycle.commits().is_empty());
+        }
+    }
+
+    #[test]
+    fn runner_stop_conditions() {
+        let f = 1;
+        let clients_per_process = 1;
+        let issued =
+            |clients_latencies: HashMap<Region, (usize, Histogram)>| {
+                clients_latencies
+                    .values()
+                    .map(|(issued, _)| issued)
+                    .sum::<usize>()
+            };
+
+        // without stop conditions, clients are done after ~34 seconds (see
+        // `runner_single_client_per_process`); stop after 5 seconds
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.set_max_sim_time(Duration::from_secs(5));
+        runner.show_progress(Duration::from_millis(1));
+        let (_, _, clients_latencies) = runner.run(None);
+        assert_eq!(runner.stopped(), Some(StopCondition::SimTime));
+        assert!(runner.simulation.time().millis() > 5_000);
+        let issued_in_5_secs = issued(clients_latencies);
+        assert!(issued_in_5_secs > 0);
+        assert!(issued_in_5_secs < COMMANDS_PER_CLIENT * 2);
+
+        // stop as soon as the wall-clock time is checked
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.set_max_wall_time(Duration::ZERO);
+        let (_, _, clients_latencies) = runner.run(None);
+        assert_eq!(runner.stopped(), Some(StopCondition::WallTime));
+        assert!(issued(clients_latencies) < COMMANDS_PER_CLIENT * 2);
+
+        // stop conditions that are never reached
+        let mut runner =
+            new_runner(f, clients_per_process, false, client_regions());
+        runner.set_max_sim_time(Duration::from_secs(3600));
+        runner.set_max_wall_time(Duration::from_secs(3600));
+        let (_, _, clients_latencies) = runner.run(None);
+        assert_eq!(runner.stopped(), None);
+        assert_eq!(issued(clients_latencies), COMMANDS_PER_CLIENT * 2);
+    }
+
+    #[test]
+    fn runner_with_clients() {
+        // config
+        let n = 3;
+        let f = 1;
+        let mut config = Config::new(n, f);
+        config.set_gc_interval(Duration::from_millis(100));
+
+        // two workloads, one with more commands and reads
+        let workload = |commands_per_client, read_only_percentage| {
+            let key_gen = KeyGen::ConflictPool {
+                pool_size: 1,
+                conflict_rate: 50,
+            };
+            let mut workload =
+                Workload::new(1, key_gen, 1, commands_per_client, 100);
+            workload.set_read_only_percentage(read_only_percentage);
+            workload
+        };
+
+        // skewed clients: many in us-west1, and a few in us-west2 with a
+        // different workload
+        let us_west1 = Region::new("us-west1");
+        let us_west2 = Region::new("us-west2");
+        let clients = vec![
+            (us_west1.clone(), 4, workload(20, 0)),
+            (us_west2.clone(), 1, workload(50, 50)),
+            (us_west2.clone(), 1, workload(10, 0)),
+        ];
+        let process_regions = vec![
+            Region::new("asia-east1"),
+            Region::new("us-central1"),
+            Region::new("us-west1"),
+        ];
+        let seed = 0;
+        let mut runner: Runner<Basic> = Runner::with_clients(
+            Planet::new(),
+            config,
+            process_regions,
+            clients,
+            seed,
+        );
+        let (_, _, clients_latencies) = runner.run(None);
+
+        // check the commands issued in each region
+        let issued = |region| {
+            let (issued, histogram) = clients_latencies
+                .get(region)
+                .expect("region should have clients");
+            assert_eq!(*issued, histogram.count());
+            *issued
+        };
+        assert_eq!(issued(&us_west1), 4 * 20);
+        assert_eq!(issued(&us_west2), 50 + 10);
+    }
 }
@@ -0,0 +1,411 @@
+use crate::client::{KeyGen, Workload};
+use crate::config::Config;
+use crate::id::ProcessId;
+use crate::planet::{LatencyDataset, Planet, Region};
+use crate::protocol::Protocol;
+use crate::sim::{Recovery, Runner, SimulationMetrics};
+use crate::HashMap;
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::time::Duration;
+
+/// Latencies between the regions of a `Scenario`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanetSpec {
+    /// The latencies between GCP regions (see `Planet::new`).
+    #[default]
+    Gcp,
+    /// The latencies in the dat files of some directory (see `Planet::from`).
+    LatencyDir(String),
+    /// The latencies in the dataset of some cloud provider (see
+    /// `Planet::from_dataset`).
+    Dataset(LatencyDataset),
+    /// The latency matrix in some CSV file (see `Planet::from_csv`).
+    Csv(String),
+    /// The latency matrix in some JSON file (see `Planet::from_json`).
+    Json(String),
+    /// The latencies (in milliseconds) from each region to every other region
+    /// (see `Planet::from_latencies`).
+    Latencies(HashMap<Region, HashMap<Region, u64>>),
+}
+
+impl PlanetSpec {
+    fn planet(&self) -> Result<Planet, Report> {
+        let planet = match self {
+            Self::Gcp => Planet::new(),
+            Self::LatencyDir(dir) => Planet::from(dir),
+            Self::Dataset(dataset) => Planet::from_dataset(dataset)?,
+            Self::Csv(path) => Planet::from_csv(path)?,
+            Self::Json(path) => Planet::from_json(path)?,
+            Self::Latencies(latencies) => {
+                Planet::from_latencies(latencies.clone())
+            }
+        };
+        Ok(planet)
+    }
+}
+
+/// `Config` of a `Scenario`: the one created by `Config::new` with the
+/// settings given changed (e.g. `"gc_interval": {"secs": 0, "nanos":
+/// 100000000}`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSpec {
+    n: usize,
+    f: usize,
+    #[serde(flatten)]
+    settings: Map<String, Value>,
+}
+
+/// `Workload` of a `Scenario`: the one created by `Workload::new` with the
+/// settings given changed (e.g. `"read_only_percentage": 50`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkloadSpec {
+    #[serde(default = "default_shard_count")]
+    shard_count: usize,
+    key_gen: KeyGen,
+    keys_per_command: usize,
+    commands_per_client: usize,
+    #[serde(default)]
+    payload_size: usize,
+    #[serde(flatten)]
+    settings: Map<String, Value>,
+}
+
+fn default_shard_count() -> usize {
+    1
+}
+
+/// A fault injected in the simulation of a `Scenario`. Times are in
+/// milliseconds of simulated time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fault {
+    /// See `Runner::crash`.
+    Crash { process_id: ProcessId, at: u64 },
+    /// See `Runner::recover`.
+    Recover {
+        process_id: ProcessId,
+        at: u64,
+        recovery: Recovery,
+    },
+    /// See `Runner::failover`.
+    Failover {
+        process_id: ProcessId,
+        at: u64,
+        detection_delay: u64,
+    },
+    /// See `Runner::partition`.
+    Partition {
+        groups: Vec<Vec<ProcessId>>,
+        from: u64,
+        to: u64,
+    },
+    /// See `Runner::slow_down` (with `delay` in milliseconds).
+    SlowDown {
+        process_id: ProcessId,
+        delay: u64,
+        from: u64,
+        to: u64,
+    },
+    /// See `Runner::drop_messages`.
+    DropMessages { probability: f64, seed: u64 },
+    /// See `Runner::drop_link_messages`.
+    DropLinkMessages {
+        from: ProcessId,
+        to: ProcessId,
+        probability: f64,
+    },
+}
+
+/// A simulation scenario, loaded from a JSON or TOML file (see
+/// `Scenario::load`), so that simulations can be shared and reproduced without
+/// sharing code.
+///
+/// Since the protocol is only a name, whoever runs the scenario is the one
+/// mapping it to a protocol (see `Scenario::run`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    protocol: String,
+    #[serde(default)]
+    planet: PlanetSpec,
+    process_regions: Vec<Region>,
+    client_regions: Vec<Region>,
+    clients_per_region: usize,
+    config: ConfigSpec,
+    workload: WorkloadSpec,
+    #[serde(default)]
+    faults: Vec<Fault>,
+    // simulated time (in milliseconds) the simulation keeps running after
+    // all clients are done
+    #[serde(default)]
+    extra_sim_time: Option<u64>,
+    #[serde(default)]
+    seed: u64,
+}
+
+impl Scenario {
+    /// Loads the scenario stored in file `path`, as TOML if the file has the
+    /// `toml` extension, and as JSON otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("read scenario file {:?}", path))?;
+        let toml = path.extension().is_some_and(|ext| ext == "toml");
+        Self::parse(&contents, toml)
+            .wrap_err_with(|| format!("parse scenario file {:?}", path))
+    }
+
+    fn parse(contents: &str, toml: bool) -> Result<Self, Report> {
+        if toml {
+            toml::from_str(contents).wrap_err("invalid TOML scenario")
+        } else {
+            serde_json::from_str(contents).wrap_err("invalid JSON scenario")
+        }
+    }
+
+    /// Returns the name of the protocol simulated.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Changes the protocol simulated to `protocol`.
+    pub fn set_protocol<S: Into<String>>(&mut self, protocol: S) {
+        self.protocol = protocol.into();
+    }
+
+    /// Returns the config of the simulation.
+    pub fn config(&self) -> Result<Config, Report> {
+        let ConfigSpec { n, f, settings } = &self.config;
+        if *f > n / 2 {
+            eyre::bail!("f={} is larger than a minority with n={}", f, n);
+        }
+        with_settings(&Config::new(*n, *f), settings, "config")
+    }
+
+    /// Returns the workload of the clients.
+    pub fn workload(&self) -> Result<Workload, Report> {
+        let spec = &self.workload;
+        let workload = Workload::new(
+            spec.shard_count,
+            spec.key_gen,
+            spec.keys_per_command,
+            spec.commands_per_client,
+            spec.payload_size,
+        );
+        with_settings(&workload, &spec.settings, "workload")
+    }
+
+    /// Creates the `Runner` of the scenario, with protocol `P`.
+    pub fn runner<P: Protocol>(&self) -> Result<Runner<P>, Report> {
+        let config = self.config()?;
+        let workload = self.workload()?;
+        if self.process_regions.len() != config.n() {
+            eyre::bail!(
+                "there should be {} process regions; found {}",
+                config.n(),
+                self.process_regions.len()
+            );
+        }
+        if config.gc_interval().is_none() {
+            eyre::bail!("the config should set gc_interval");
+        }
+
+        let mut runner = Runner::new(
+            self.planet.planet()?,
+            config,
+            workload,
+            self.clients_per_region,
+            self.process_regions.clone(),
+            self.client_regions.clone(),
+            self.seed,
+        );
+        for fault in &self.faults {
+            match fault.clone() {
+                Fault::Crash { process_id, at } => runner.crash(process_id, at),
+                Fault::Recover {
+                    process_id,
+                    at,
+                    recovery,
+                } => runner.recover(process_id, at, recovery),
+                Fault::Failover {
+                    process_id,
+                    at,
+                    detection_delay,
+                } => runner.failover(process_id, at, detection_delay),
+                Fault::Partition { groups, from, to } => {
+                    runner.partition(groups, from, to)
+                }
+                Fault::SlowDown {
+                    process_id,
+                    delay,
+                    from,
+                    to,
+                } => runner.slow_down(
+                    process_id,
+                    Duration::from_millis(delay),
+                    from,
+                    to,
+                ),
+                Fault::DropMessages { probability, seed } => {
+                    runner.drop_messages(probability, seed)
+                }
+                Fault::DropLinkMessages {
+                    from,
+                    to,
+                    probability,
+                } => runner.drop_link_messages(from, to, probability),
+            }
+        }
+        Ok(runner)
+    }
+
+    /// Simulates the scenario with protocol `P`, returning its metrics.
+    pub fn run<P: Protocol>(&self) -> Result<SimulationMetrics, Report> {
+        let mut runner = self.runner::<P>()?;
+        runner.run(self.extra_sim_time());
+        Ok(runner.simulation_metrics())
+    }
+
+    /// Returns how much longer the simulation runs after clients are done.
+    pub fn extra_sim_time(&self) -> Option<Duration> {
+        self.extra_sim_time.map(Duration::from_millis)
+    }
+}
+
+// Changes the fields of `value` (once serialized) to the ones in `settings`,
+// failing if some setting is not one of its fields.
+fn with_settings<T>(
+    value: &T,
+    settings: &Map<String, Value>,
+    what: &str,
+) -> Result<T, Report>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut value = serde_json::to_value(value)
+        .wrap_err_with(|| format!("serialize {}", what))?;
+    let fields = value
+        .as_object_mut()
+        .expect("value should be serialized as an object");
+    for (name, setting) in settings {
+        match fields.get_mut(name) {
+            Some(field) => *field = setting.clone(),
+            None => eyre::bail!("unknown {} setting {:?}", what, name),
+        }
+    }
+    serde_json::from_value(value)
+        .wrap_err_with(|| format!("invalid {} settings", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+
+    const JSON: &str = r#"{
+        "protocol": "Basic",
+        "process_regions": ["asia-east1", "us-central1", "us-west1"],
+        "client_regions": ["us-west1", "us-west2"],
+        "clients_per_region": 2,
+        "config": {
+            "n": 3,
+            "f": 1,
+            "gc_interval": {"secs": 0, "nanos": 100000000}
+        },
+        "workload": {
+            "key_gen": {"ConflictPool": {"conflict_rate": 10, "pool_size": 1}},
+            "keys_per_command": 1,
+            "commands_per_client": 100,
+            "read_only_percentage": 20
+        },
+        "faults": [
+            {"type": "partition", "groups": [[1], [2, 3]], "from": 0, "to": 50}
+        ],
+        "extra_sim_time": 1000,
+        "seed": 3
+    }"#;
+
+    const TOML: &str = r#"
+        protocol = "Basic"
+        process_regions = ["asia-east1", "us-central1", "us-west1"]
+        client_regions = ["us-west1", "us-west2"]
+        clients_per_region = 2
+        extra_sim_time = 1000
+        seed = 3
+
+        [config]
+        n = 3
+        f = 1
+        gc_interval = { secs = 0, nanos = 100000000 }
+
+        [workload]
+        key_gen = { ConflictPool = { conflict_rate = 10, pool_size = 1 } }
+        keys_per_command = 1
+        commands_per_client = 100
+        read_only_percentage = 20
+
+        [[faults]]
+        type = "partition"
+        groups = [[1], [2, 3]]
+        from = 0
+        to = 50
+    "#;
+
+    #[test]
+    fn scenario_json_and_toml() {
+        let json = Scenario::parse(JSON, false).expect("valid JSON scenario");
+        let toml = Scenario::parse(TOML, true).expect("valid TOML scenario");
+
+        // check the settings changed
+        let config = json.config().unwrap();
+        assert_eq!(config.gc_interval(), Some(Duration::from_millis(100)));
+        let workload = json.workload().unwrap();
+        assert_eq!(workload.read_only_percentage(), 20);
+
+        // both formats describe the same simulation
+        assert_eq!(config, toml.config().unwrap());
+        let json_metrics = json.run::<Basic>().unwrap();
+        let toml_metrics = toml.run::<Basic>().unwrap();
+        let expected = 100 * 2 * 2;
+        assert_eq!(json_metrics.total().issued_commands(), expected);
+        assert_eq!(toml_metrics.total().issued_commands(), expected);
+    }
+
+    #[test]
+    fn scenario_errors() {
+        // unknown setting
+        let scenario = JSON.replace("read_only_percentage", "read_only");
+        let scenario = Scenario::parse(&scenario, false).unwrap();
+        assert!(scenario.workload().is_err());
+
+        // wrong number of process regions
+        let scenario = JSON.replace(r#""n": 3"#, r#""n": 5"#);
+        let scenario = Scenario::parse(&scenario, false).unwrap();
+        assert!(scenario.runner::<Basic>().is_err());
+
+        // latency dataset that doesn't exist
+        let scenario = JSON.replace(
+            r#""protocol": "Basic","#,
+            r#""protocol": "Basic",
+            "planet": {"dataset": {"provider": "azure", "dir": "none"}},"#,
+        );
+        let scenario = Scenario::parse(&scenario, false).unwrap();
+        assert!(scenario.runner::<Basic>().is_err());
+        let scenario = JSON.replace(
+            r#""protocol": "Basic","#,
+            r#""protocol": "Basic", "planet": {"csv": "none.csv"},"#,
+        );
+        let scenario = Scenario::parse(&scenario, false).unwrap();
+        assert!(scenario.runner::<Basic>().is_err());
+
+        // unknown field
+        let scenario = JSON.replace("extra_sim_time", "duration");
+        assert!(Scenario::parse(&scenario, false).is_err());
+    }
+}
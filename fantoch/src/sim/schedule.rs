@@ -1,36 +1,48 @@
 use crate::time::{SimTime, SysTime};
-use std::cmp::{Ordering, Reverse};
+use crate::HashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
 use std::time::Duration;
 
+/// Queue of the actions scheduled in a simulation. Actions scheduled at the
+/// same time are kept together in a bucket, and so the priority queue only
+/// orders the (much fewer) distinct times at which actions are scheduled. When
+/// the simulation reaches a time, all its actions are extracted at once.
 pub struct Schedule<A> {
-    queue: BinaryHeap<Reverse<QueueEntry<A>>>,
+    // times (in milliseconds) at which there are actions scheduled
+    times: BinaryHeap<Reverse<u64>>,
+    // mapping from time to the actions scheduled at that time
+    buckets: HashMap<u64, Vec<A>>,
+    // actions scheduled at the current time yet to be returned (in reverse
+    // order)
+    batch: Vec<A>,
+    // empty buckets, reused so that scheduling actions doesn't allocate
+    free: Vec<Vec<A>>,
+    // random number generator breaking ties between actions scheduled at the
+    // same time
+    rng: StdRng,
 }
 
-#[derive(PartialEq, Eq)]
-struct QueueEntry<A> {
-    schedule_time: u64,
-    action: A,
-}
-
-impl<A: Eq> Ord for QueueEntry<A> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // simply compare their schedule time
-        self.schedule_time.cmp(&other.schedule_time)
-    }
-}
-
-impl<A: Eq> PartialOrd for QueueEntry<A> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl<A: Eq> Schedule<A> {
+impl<A> Schedule<A> {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// Creates a new `Schedule` where ties between actions scheduled at the
+    /// same time are broken by a random number generator seeded with `seed`.
+    /// Given the same sequence of scheduled actions, schedules with the same
+    /// seed return the actions in the same order.
+    pub fn with_seed(seed: u64) -> Self {
         Self {
-            queue: BinaryHeap::new(),
+            times: BinaryHeap::new(),
+            buckets: HashMap::new(),
+            batch: Vec::new(),
+            free: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
@@ -39,24 +51,59 @@ impl<A: Eq> Schedule<A> {
         // compute schedule time
         let schedule_time = time.millis() + delay.as_millis() as u64;
 
-        // create new queue entry
-        let entry = QueueEntry {
-            schedule_time,
-            action,
-        };
-        // push new entry to the queue
-        self.queue.push(Reverse(entry));
+        // add action to the bucket of its schedule time (creating it, if
+        // there's none)
+        let times = &mut self.times;
+        let free = &mut self.free;
+        self.buckets
+            .entry(schedule_time)
+            .or_insert_with(|| {
+                times.push(Reverse(schedule_time));
+                free.pop().unwrap_or_default()
+            })
+            .push(action);
+    }
+
+    /// Removes the scheduled actions for which `f` returns false.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&A) -> bool,
+    {
+        self.batch.retain(|action| f(action));
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|action| f(action));
+            !bucket.is_empty()
+        });
+        let buckets = &self.buckets;
+        self.times
+            .retain(|Reverse(time)| buckets.contains_key(time));
     }
 
     /// Retrieve the next scheduled action.
     pub fn next_action(&mut self, time: &mut SimTime) -> Option<A> {
-        // get the next actions
-        self.queue.pop().map(|entry| {
+        if self.batch.is_empty() {
+            // extract all the actions scheduled at the next time
+            let Reverse(next_time) = self.times.pop()?;
+            let mut bucket = self
+                .buckets
+                .remove(&next_time)
+                .expect("scheduled times should have a bucket");
             // advance simulation time
-            time.set_millis(entry.0.schedule_time);
-            // return only the action
-            entry.0.action
-        })
+            time.set_millis(next_time);
+            // break ties randomly
+            bucket.shuffle(&mut self.rng);
+            // the current batch (now empty) can be reused
+            let batch = std::mem::replace(&mut self.batch, bucket);
+            self.free.push(batch);
+        }
+        self.batch.pop()
+    }
+
+    /// Returns `action`, the last action retrieved with
+    /// `Schedule::next_action`, to the schedule, so that it's the next action
+    /// retrieved.
+    pub fn put_back(&mut self, action: A) {
+        self.batch.push(action);
     }
 }
 
@@ -117,5 +164,65 @@ mod tests {
             .expect("there should be a next action");
         assert!(next == String::from("b") || next == String::from("e"));
         assert_eq!(time.millis(), 17);
+
+        // schedule "f", "g" and "h", and then remove "g"
+        schedule.schedule(&time, Duration::from_millis(1), String::from("f"));
+        schedule.schedule(&time, Duration::from_millis(2), String::from("g"));
+        schedule.schedule(&time, Duration::from_millis(3), String::from("h"));
+        schedule.retain(|action| action != "g");
+
+        // check "f" and "h" are the next actions
+        let next = schedule
+            .next_action(&mut time)
+            .expect("there should be a next action");
+        assert_eq!(next, String::from("f"));
+        let next = schedule
+            .next_action(&mut time)
+            .expect("there should be a next action");
+        assert_eq!(next, String::from("h"));
+        assert_eq!(time.millis(), 20);
+        assert!(schedule.next_action(&mut time).is_none());
+    }
+
+    #[test]
+    fn schedule_ties() {
+        // returns the order in which actions scheduled at the same time are
+        // returned by a schedule with seed `seed`
+        let order = |seed: u64| {
+            let mut time = SimTime::new();
+            let mut schedule: Schedule<usize> = Schedule::with_seed(seed);
+            for action in 0..100 {
+                schedule.schedule(&time, Duration::from_millis(10), action);
+            }
+            // schedule another action later
+            schedule.schedule(&time, Duration::from_millis(11), 100);
+
+            let mut order = Vec::new();
+            while let Some(action) = schedule.next_action(&mut time) {
+                if action < 100 {
+                    assert_eq!(time.millis(), 10);
+                    // actions scheduled at the current time are returned
+                    // after the ones already there
+                    if action == 0 {
+                        schedule.schedule(&time, Duration::ZERO, 101);
+                    }
+                } else if action == 101 {
+                    assert_eq!(time.millis(), 10);
+                    assert_eq!(order.len(), 100);
+                } else {
+                    assert_eq!(time.millis(), 11);
+                }
+                order.push(action);
+            }
+            order
+        };
+
+        // all actions are returned, and ties are broken the same way with the
+        // same seed
+        let a = order(0);
+        assert_eq!(a.len(), 102);
+        assert_eq!(a[101], 100);
+        assert_eq!(order(0), a);
+        assert_ne!(order(1), a);
     }
 }
@@ -3,7 +3,7 @@ use crate::command::{Command, CommandResult};
 use crate::executor::AggregatePending;
 use crate::id::{ClientId, ProcessId};
 use crate::protocol::{Action, Protocol};
-use crate::time::SimTime;
+use crate::time::{ClockSkew, SimTime, SysTime};
 use crate::HashMap;
 use std::cell::Cell;
 
@@ -11,6 +11,9 @@ pub struct Simulation<P: Protocol> {
     time: SimTime,
     processes: HashMap<ProcessId, Cell<(P, P::Executor, AggregatePending)>>,
     clients: HashMap<ClientId, Cell<Client>>,
+    // mapping from process identifier to the skew of its clock (and the time
+    // it shows); processes not in this mapping see the simulation time
+    clocks: HashMap<ProcessId, (ClockSkew, SimTime)>,
 }
 
 impl<P> Simulation<P>
@@ -24,6 +27,7 @@ where
             time: SimTime::new(),
             processes: HashMap::new(),
             clients: HashMap::new(),
+            clocks: HashMap::new(),
         }
     }
 
@@ -32,6 +36,11 @@ where
         &mut self.time
     }
 
+    // Return the simulation time (in milliseconds).
+    pub fn millis(&self) -> u64 {
+        self.time.millis()
+    }
+
     /// Registers a `Process` in the `Simulation` by storing it in a `Cell`.
     pub fn register_process(&mut self, process: P, executor: P::Executor) {
         // get identifier
@@ -48,6 +57,33 @@ where
         assert!(res.is_none());
     }
 
+    /// Replaces a registered `Process` (and its `Executor`) by new ones, as if
+    /// it restarted with an empty state. The commands pending in the process
+    /// are lost.
+    pub fn restart_process(&mut self, process: P, executor: P::Executor) {
+        // get identifier
+        let process_id = process.id();
+        let shard_id = process.shard_id();
+
+        // create pending
+        let pending = AggregatePending::new(process_id, shard_id);
+
+        // replace process and check it has been registered before
+        let res = self
+            .processes
+            .insert(process_id, Cell::new((process, executor, pending)));
+        assert!(res.is_some());
+    }
+
+    /// Skews the clock of process `process_id` with `skew`. From then on, the
+    /// process (and its executor) see the time shown by its skewed clock,
+    /// while clients keep seeing the simulation time.
+    pub fn set_clock_skew(&mut self, process_id: ProcessId, skew: ClockSkew) {
+        let mut time = SimTime::new();
+        time.set_micros(skew.skew(self.time.micros()));
+        self.clocks.insert(process_id, (skew, time));
+    }
+
     /// Registers a `Client` in the `Simulation` by storing it in a `Cell`.
     pub fn register_client(&mut self, client: Client) {
         // get identifier
@@ -61,8 +97,12 @@ where
     /// Starts all clients registered in the router.
     pub fn start_clients(&mut self) -> Vec<(ClientId, ProcessId, Command)> {
         let time = &self.time;
-        self.clients
-            .iter_mut()
+        // start clients by the order of their identifiers, so that runs with
+        // the same seed are the same
+        let mut clients: Vec<_> = self.clients.iter_mut().collect();
+        clients.sort_unstable_by_key(|(client_id, _)| **client_id);
+        clients
+            .into_iter()
             .map(|(_, client)| {
                 let client = client.get_mut();
                 // start client
@@ -140,7 +180,12 @@ where
         // handle command result
         // TODO: we should aggregate command results if we have more than one
         // shard in simulation
-        client.cmd_recv(cmd_result.rifl(), time);
+        client.cmd_recv(
+            cmd_result.rifl(),
+            cmd_result.timestamps(),
+            cmd_result.has_errors(),
+            time,
+        );
         // and generate the next command
         client.cmd_send(time).map(|(target_shard, cmd)| {
             let target = client.shard_process(&target_shard);
@@ -164,7 +209,16 @@ where
                 );
             })
             .get_mut();
-        (process, executor, pending, &self.time)
+        // if the clock of the process is skewed, advance it to the current
+        // simulation time
+        let time = match self.clocks.get_mut(&process_id) {
+            Some((skew, time)) => {
+                time.set_micros(skew.skew(self.time.micros()));
+                time
+            }
+            None => &self.time,
+        };
+        (process, executor, pending, time)
     }
 
     /// Returns the client registered with this identifier.
@@ -0,0 +1,438 @@
+use crate::client::{KeyGen, Workload};
+use crate::config::Config;
+use crate::id::ProcessId;
+use crate::planet::{Planet, Region};
+use crate::protocol::Protocol;
+use crate::sim::{Runner, SimulationMetrics};
+#[cfg(feature = "parallel-sim")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A point in the parameter space explored by a `Sweep`.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct SweepPoint {
+    protocol: String,
+    n: usize,
+    f: usize,
+    conflict_rate: usize,
+    clients_per_region: usize,
+    leader: Option<ProcessId>,
+}
+
+impl SweepPoint {
+    /// Returns the name of the protocol.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Returns the number of processes.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Returns the number of faults tolerated.
+    pub fn f(&self) -> usize {
+        self.f
+    }
+
+    /// Returns the conflict rate of the workload.
+    pub fn conflict_rate(&self) -> usize {
+        self.conflict_rate
+    }
+
+    /// Returns the number of clients in each region.
+    pub fn clients_per_region(&self) -> usize {
+        self.clients_per_region
+    }
+
+    /// Returns the leader (if set with `Sweep::set_leaders`).
+    pub fn leader(&self) -> Option<ProcessId> {
+        self.leader
+    }
+}
+
+/// A sweep over the cross-product of a grid of parameters: protocols, number
+/// of processes `n`, number of faults tolerated `f`, conflict rates, clients
+/// per region and leaders. Each point is simulated with a `Runner` where the
+/// processes (and the clients) are in the first `n` regions of the sweep.
+/// With feature `parallel-sim`, points are simulated in parallel (in the
+/// global rayon thread pool).
+pub struct Sweep {
+    planet: Planet,
+    regions: Vec<Region>,
+    // config and workload of every point (with `n`, `f` and the conflict rate
+    // replaced by the ones of the point)
+    config: Config,
+    workload: Workload,
+    pool_size: usize,
+    seed: u64,
+    // grid of parameters
+    protocols: Vec<String>,
+    ns: Vec<usize>,
+    fs: Vec<usize>,
+    conflict_rates: Vec<usize>,
+    clients_per_region: Vec<usize>,
+    leaders: Vec<Option<ProcessId>>,
+}
+
+impl Sweep {
+    /// Creates a new `Sweep`. Initially, the grid only has the `n` and `f` of
+    /// `config`, the conflict rate of `workload` (which must use the
+    /// `KeyGen::ConflictPool` key generator), a single client per region, the
+    /// leader of `config`, and no protocol.
+    ///
+    /// As in `Runner`, points with the same parameters and `seed` are the
+    /// same.
+    pub fn new(
+        planet: Planet,
+        regions: Vec<Region>,
+        config: Config,
+        workload: Workload,
+        seed: u64,
+    ) -> Self {
+        let (conflict_rate, pool_size) = match workload.key_gen() {
+            KeyGen::ConflictPool {
+                conflict_rate,
+                pool_size,
+            } => (conflict_rate, pool_size),
+            key_gen => panic!(
+                "sweeps only support the conflict pool key generator; found {:?}",
+                key_gen
+            ),
+        };
+        let mut sweep = Self {
+            planet,
+            regions,
+            config,
+            workload,
+            pool_size,
+            seed,
+            protocols: Vec::new(),
+            ns: Vec::new(),
+            fs: vec![config.f()],
+            conflict_rates: vec![conflict_rate],
+            clients_per_region: vec![1],
+            leaders: vec![config.leader()],
+        };
+        sweep.set_ns(vec![config.n()]);
+        sweep
+    }
+
+    /// Sets the protocols to be simulated. These are only names: `Sweep::run`
+    /// is the one mapping them to protocols.
+    pub fn set_protocols<S>(&mut self, protocols: Vec<S>)
+    where
+        S: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+    }
+
+    /// Sets the number of processes to be simulated.
+    pub fn set_ns(&mut self, ns: Vec<usize>) {
+        if let Some(n) = ns.iter().find(|&&n| n > self.regions.len()) {
+            panic!(
+                "n={} is larger than the number of regions {}",
+                n,
+                self.regions.len()
+            );
+        }
+        self.ns = ns;
+    }
+
+    /// Sets the number of faults tolerated to be simulated. Combinations of
+    /// `n` and `f` where `f` is larger than a minority are skipped.
+    pub fn set_fs(&mut self, fs: Vec<usize>) {
+        self.fs = fs;
+    }
+
+    /// Sets the conflict rates to be simulated.
+    pub fn set_conflict_rates(&mut self, conflict_rates: Vec<usize>) {
+        self.conflict_rates = conflict_rates;
+    }
+
+    /// Sets the number of clients per region to be simulated.
+    pub fn set_clients_per_region(&mut self, clients_per_region: Vec<usize>) {
+        self.clients_per_region = clients_per_region;
+    }
+
+    /// Sets the leaders to be simulated (for leader-based protocols): leader
+    /// `i` is the process in the `i`-th region of the sweep. Leaders that are
+    /// not one of the `n` processes of a point are skipped.
+    pub fn set_leaders(&mut self, leaders: Vec<ProcessId>) {
+        self.leaders = leaders.into_iter().map(Some).collect();
+    }
+
+    /// Returns the points in the cross-product of the grid of parameters.
+    pub fn points(&self) -> Vec<SweepPoint> {
+        let mut points = Vec::new();
+        for protocol in &self.protocols {
+            for &n in &self.ns {
+                let leaders: Vec<_> = self
+                    .leaders
+                    .iter()
+                    .filter(|leader| {
+                        leader.is_none_or(|leader| leader as usize <= n)
+                    })
+                    .collect();
+                for &f in self.fs.iter().filter(|&&f| f <= n / 2) {
+                    for &conflict_rate in &self.conflict_rates {
+                        for &clients_per_region in &self.clients_per_region {
+                            for &&leader in &leaders {
+                                points.push(SweepPoint {
+                                    protocol: protocol.clone(),
+                                    n,
+                                    f,
+                                    conflict_rate,
+                                    clients_per_region,
+                                    leader,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        points
+    }
+
+    /// Simulates all the points of the sweep. Since protocols are only names,
+    /// `run` is called with each point to be simulated and should pick the
+    /// protocol to simulate it with (see `SweepRun::run`).
+    pub fn run<F>(&self, run: F) -> SweepResults
+    where
+        F: Fn(SweepRun<'_>) -> SimulationMetrics + Sync,
+    {
+        let points = self.points();
+        let simulate = |point: SweepPoint| {
+            let metrics = run(self.sweep_run(&point));
+            (point, metrics)
+        };
+
+        #[cfg(feature = "parallel-sim")]
+        let mut results: Vec<_> =
+            points.into_par_iter().map(simulate).collect();
+        #[cfg(not(feature = "parallel-sim"))]
+        let mut results: Vec<_> = points.into_iter().map(simulate).collect();
+
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        SweepResults { results }
+    }
+
+    fn sweep_run<'a>(&'a self, point: &'a SweepPoint) -> SweepRun<'a> {
+        let mut config = self.config;
+        config.set_n_f(point.n, point.f);
+        if let Some(leader) = point.leader {
+            config.set_leader(leader);
+        }
+        let mut workload = self.workload.clone();
+        workload.set_key_gen(KeyGen::ConflictPool {
+            conflict_rate: point.conflict_rate,
+            pool_size: self.pool_size,
+        });
+        SweepRun {
+            point,
+            planet: &self.planet,
+            regions: &self.regions[..point.n],
+            config,
+            workload,
+            seed: self.seed,
+        }
+    }
+}
+
+/// The simulation of a point of a `Sweep`.
+pub struct SweepRun<'a> {
+    point: &'a SweepPoint,
+    planet: &'a Planet,
+    regions: &'a [Region],
+    config: Config,
+    workload: Workload,
+    seed: u64,
+}
+
+impl<'a> SweepRun<'a> {
+    /// Returns the point to be simulated.
+    pub fn point(&self) -> &SweepPoint {
+        self.point
+    }
+
+    /// Returns a mutable reference to the config of the simulation, so that
+    /// protocol-specific settings (e.g. the leader) can be changed.
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Simulates the point with protocol `P` until all clients are done.
+    pub fn run<P: Protocol>(self) -> SimulationMetrics {
+        let regions = self.regions.to_vec();
+        let clients_per_region = self.point.clients_per_region;
+        let expected_commands = self.workload.commands_per_client()
+            * clients_per_region
+            * regions.len();
+
+        let mut runner: Runner<P> = Runner::new(
+            self.planet.clone(),
+            self.config,
+            self.workload,
+            clients_per_region,
+            regions.clone(),
+            regions,
+            self.seed,
+        );
+        runner.run(None);
+        let metrics = runner.simulation_metrics();
+
+        // check that all commands were issued
+        let issued_commands = metrics.total().issued_commands();
+        if issued_commands != expected_commands {
+            panic!(
+                "{:?}: only issued {} out of {} commands",
+                self.point, issued_commands, expected_commands,
+            );
+        }
+        metrics
+    }
+}
+
+/// The results of a `Sweep`, sorted by point.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepResults {
+    results: Vec<(SweepPoint, SimulationMetrics)>,
+}
+
+impl SweepResults {
+    /// Returns the metrics of point `point` (if it was simulated).
+    pub fn get(&self, point: &SweepPoint) -> Option<&SimulationMetrics> {
+        self.results
+            .binary_search_by(|(p, _)| p.cmp(point))
+            .ok()
+            .map(|index| &self.results[index].1)
+    }
+
+    /// Returns the metrics of all points.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&SweepPoint, &SimulationMetrics)> {
+        self.results.iter().map(|(point, metrics)| (point, metrics))
+    }
+
+    /// Returns the number of points simulated.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns whether no point was simulated.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Basic;
+    use std::time::Duration;
+
+    const COMMANDS_PER_CLIENT: usize = 10;
+
+    fn sweep() -> Sweep {
+        // planet and regions
+        let planet = Planet::new();
+        let regions = vec![
+            Region::new("asia-east1"),
+            Region::new("us-central1"),
+            Region::new("us-west1"),
+            Region::new("europe-west1"),
+            Region::new("southamerica-east1"),
+        ];
+
+        // config
+        let mut config = Config::new(3, 1);
+        config.set_gc_interval(Duration::from_millis(100));
+
+        // clients workload
+        let shard_count = 1;
+        let key_gen = KeyGen::ConflictPool {
+            conflict_rate: 0,
+            pool_size: 1,
+        };
+        let keys_per_command = 1;
+        let payload_size = 100;
+        let workload = Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            COMMANDS_PER_CLIENT,
+            payload_size,
+        );
+
+        let seed = 0;
+        Sweep::new(planet, regions, config, workload, seed)
+    }
+
+    #[test]
+    fn sweep_points() {
+        let mut sweep = sweep();
+        // no protocol, no points
+        assert!(sweep.points().is_empty());
+
+        sweep.set_protocols(vec!["Basic", "Other"]);
+        sweep.set_ns(vec![3, 5]);
+        sweep.set_fs(vec![1, 2]);
+        sweep.set_conflict_rates(vec![0, 10, 100]);
+        sweep.set_clients_per_region(vec![1, 2]);
+
+        // (n, f) = (3, 2) is skipped
+        let points = sweep.points();
+        assert_eq!(points.len(), 2 * 3 * 3 * 2);
+        assert!(points.iter().all(|point| point.f() <= point.n() / 2));
+        assert!(points.iter().all(|point| point.leader().is_none()));
+
+        // leader 5 is skipped with n = 3
+        sweep.set_leaders(vec![1, 5]);
+        let points = sweep.points();
+        assert_eq!(points.len(), 2 * (1 + 2 * 2) * 3 * 2);
+        assert!(points
+            .iter()
+            .all(|point| point.leader().unwrap() as usize <= point.n()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn sweep_not_enough_regions() {
+        let mut sweep = sweep();
+        sweep.set_ns(vec![7]);
+    }
+
+    #[test]
+    fn sweep_run() {
+        let mut sweep = sweep();
+        sweep.set_protocols(vec!["Basic"]);
+        sweep.set_ns(vec![3, 5]);
+        sweep.set_fs(vec![1, 2]);
+        sweep.set_conflict_rates(vec![0, 100]);
+        sweep.set_clients_per_region(vec![1, 2]);
+
+        let results = sweep.run(|run| match run.point().protocol() {
+            "Basic" => run.run::<Basic>(),
+            protocol => panic!("unsupported protocol {:?}", protocol),
+        });
+        assert_eq!(results.len(), sweep.points().len());
+
+        // results are sorted by point
+        let points: Vec<_> = results.iter().map(|(point, _)| point).collect();
+        assert!(points.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // check the commands issued at each point
+        for (point, metrics) in results.iter() {
+            assert_eq!(metrics.regions().count(), point.n());
+            assert_eq!(
+                metrics.total().issued_commands(),
+                COMMANDS_PER_CLIENT * point.clients_per_region() * point.n()
+            );
+            assert_eq!(results.get(point), Some(metrics));
+        }
+    }
+}
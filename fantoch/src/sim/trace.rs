@@ -0,0 +1,232 @@
+use crate::id::{ProcessId, Rifl};
+use crate::kvs::Key;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// An event in a simulation. Times are in milliseconds of simulated time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// Command `rifl` (from client `rifl.source()`) arrived at process
+    /// `process_id`.
+    Submit {
+        time: u64,
+        rifl: Rifl,
+        process_id: ProcessId,
+    },
+    /// Process `from` sent message `id` (of type `kind`) to process `to`.
+    /// Messages that are lost (or sent to crashed processes) have no `Receive`
+    /// event.
+    Send {
+        time: u64,
+        id: u64,
+        from: ProcessId,
+        to: ProcessId,
+        kind: String,
+    },
+    /// Process `to` received message `id` from process `from`.
+    Receive {
+        time: u64,
+        id: u64,
+        from: ProcessId,
+        to: ProcessId,
+        kind: String,
+    },
+    /// Process `process_id` committed command `rifl` on key `key` (at the time
+    /// shown by its clock). Only recorded for executors that report when
+    /// commands are committed.
+    Commit {
+        time: u64,
+        rifl: Rifl,
+        key: Key,
+        process_id: ProcessId,
+    },
+    /// Process `process_id` executed command `rifl` on key `key`.
+    Execute {
+        time: u64,
+        rifl: Rifl,
+        key: Key,
+        process_id: ProcessId,
+    },
+    /// The result of command `rifl` arrived at its client.
+    Result { time: u64, rifl: Rifl },
+}
+
+impl TraceEvent {
+    /// Returns the time of the event.
+    pub fn time(&self) -> u64 {
+        match self {
+            Self::Submit { time, .. }
+            | Self::Send { time, .. }
+            | Self::Receive { time, .. }
+            | Self::Commit { time, .. }
+            | Self::Execute { time, .. }
+            | Self::Result { time, .. } => *time,
+        }
+    }
+}
+
+/// Trace of the events in a simulation (see `Runner::record_trace`), in the
+/// order they happened, that can be rendered e.g. as a space-time diagram.
+/// Messages from a process to itself are not recorded.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EventTrace {
+    events: Vec<TraceEvent>,
+    // identifier of the next message sent
+    next_message_id: u64,
+}
+
+impl EventTrace {
+    /// Creates an empty `EventTrace`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event.
+    pub fn record(&mut self, event: TraceEvent) {
+        self.events.push(event);
+    }
+
+    /// Records that message `msg` was sent, returning its identifier.
+    pub fn send<M: Debug>(
+        &mut self,
+        time: u64,
+        from: ProcessId,
+        to: ProcessId,
+        msg: &M,
+    ) -> u64 {
+        let id = self.next_message_id;
+        self.next_message_id += 1;
+        self.record(TraceEvent::Send {
+            time,
+            id,
+            from,
+            to,
+            kind: Self::kind(msg),
+        });
+        id
+    }
+
+    /// Records that message `id` was received.
+    pub fn receive<M: Debug>(
+        &mut self,
+        time: u64,
+        id: u64,
+        from: ProcessId,
+        to: ProcessId,
+        msg: &M,
+    ) {
+        self.record(TraceEvent::Receive {
+            time,
+            id,
+            from,
+            to,
+            kind: Self::kind(msg),
+        });
+    }
+
+    /// Returns the events recorded.
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Returns the number of events recorded.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Checks if no event was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Writes the trace to file `path`, as JSON with one event per line.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("create event trace {:?}", path))?;
+        let mut writer = std::io::BufWriter::new(file);
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, event)
+                .wrap_err("serialize trace event")?;
+            writeln!(writer).wrap_err("write event trace")?;
+        }
+        writer.flush().wrap_err("write event trace")
+    }
+
+    /// Loads the trace stored in file `path` (see `EventTrace::write`).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Report> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .wrap_err_with(|| format!("open event trace {:?}", path))?;
+        let mut trace = Self::new();
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.wrap_err("read event trace")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = serde_json::from_str(&line).wrap_err_with(|| {
+                format!("parse line {} of event trace", index + 1)
+            })?;
+            trace.record(event);
+        }
+        Ok(trace)
+    }
+
+    // The type of a message is the name of its variant, i.e. its debug
+    // representation up to the first delimiter.
+    pub(crate) fn kind<M: Debug>(msg: &M) -> String {
+        let debug = format!("{:?}", msg);
+        debug
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    enum Message {
+        Collect { dot: u64 },
+        Commit(u64),
+        Ping,
+    }
+
+    #[test]
+    fn message_kind() {
+        assert_eq!(EventTrace::kind(&Message::Collect { dot: 1 }), "Collect");
+        assert_eq!(EventTrace::kind(&Message::Commit(1)), "Commit");
+        assert_eq!(EventTrace::kind(&Message::Ping), "Ping");
+    }
+
+    #[test]
+    fn write_and_load() {
+        let mut trace = EventTrace::new();
+        let rifl = Rifl::new(1, 1);
+        trace.record(TraceEvent::Submit {
+            time: 0,
+            rifl,
+            process_id: 1,
+        });
+        let id = trace.send(0, 1, 2, &Message::Ping);
+        trace.receive(10, id, 1, 2, &Message::Ping);
+        trace.record(TraceEvent::Result { time: 20, rifl });
+        assert_eq!(trace.len(), 4);
+
+        let path = std::env::temp_dir().join("fantoch_event_trace_test");
+        trace.write(&path).expect("trace should be written");
+        let loaded = EventTrace::load(&path).expect("trace should be loaded");
+        assert_eq!(loaded.events(), trace.events());
+        assert_eq!(loaded.events()[2].time(), 10);
+        let _ = std::fs::remove_file(path);
+    }
+}
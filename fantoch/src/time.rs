@@ -44,7 +44,11 @@ impl SimTime {
 
     /// Sets simulation time.
     pub fn set_millis(&mut self, new_time_millis: u64) {
-        let new_time_micros = Self::millis_to_micros(new_time_millis);
+        self.set_micros(Self::millis_to_micros(new_time_millis));
+    }
+
+    /// Sets simulation time (in microseconds).
+    pub fn set_micros(&mut self, new_time_micros: u64) {
         // make sure time is monotonic
         assert!(self.micros <= new_time_micros);
         self.micros = new_time_micros;
@@ -69,6 +73,33 @@ impl SysTime for SimTime {
     }
 }
 
+/// Model of the skew of a simulated clock with respect to the simulation time:
+/// the clock is `offset` microseconds ahead of the simulation time (or behind,
+/// if negative), and drifts from it by `drift` microseconds per second (i.e.
+/// parts per million).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClockSkew {
+    offset: i64,
+    drift: f64,
+}
+
+impl ClockSkew {
+    /// Creates a new clock skew model.
+    pub fn new(offset: i64, drift: f64) -> Self {
+        // make sure skewed clocks are monotonic
+        assert!(drift > -1_000_000.0, "clocks can't go backwards");
+        Self { offset, drift }
+    }
+
+    /// Returns the time (in microseconds) shown by a skewed clock when the
+    /// simulation time is `micros`. Clocks can't show a time before zero.
+    pub fn skew(&self, micros: u64) -> u64 {
+        let drift = micros as f64 * self.drift / 1_000_000.0;
+        let skewed = micros as f64 + self.offset as f64 + drift;
+        skewed.max(0.0) as u64
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +139,31 @@ mod tests {
         // should panic!
         time.set_millis(19);
     }
+
+    #[test]
+    fn clock_skew() {
+        // no skew
+        let skew = ClockSkew::default();
+        assert_eq!(skew.skew(0), 0);
+        assert_eq!(skew.skew(1_000_000), 1_000_000);
+
+        // 10ms ahead
+        let skew = ClockSkew::new(10_000, 0.0);
+        assert_eq!(skew.skew(0), 10_000);
+        assert_eq!(skew.skew(1_000_000), 1_010_000);
+
+        // 10ms behind (and never before zero)
+        let skew = ClockSkew::new(-10_000, 0.0);
+        assert_eq!(skew.skew(0), 0);
+        assert_eq!(skew.skew(1_000_000), 990_000);
+
+        // 100us faster per second
+        let skew = ClockSkew::new(0, 100.0);
+        assert_eq!(skew.skew(1_000_000), 1_000_100);
+        assert_eq!(skew.skew(10_000_000), 10_001_000);
+
+        // 100us slower per second
+        let skew = ClockSkew::new(0, -100.0);
+        assert_eq!(skew.skew(1_000_000), 999_900);
+    }
 }
@@ -73,7 +73,10 @@ macro_rules! elapsed {
 pub fn init_tracing_subscriber(
     log_file: Option<impl AsRef<std::path::Path> + std::fmt::Debug>,
     tracing_directives: Option<&'static str>,
+    otlp: Option<crate::otlp::OtlpConfig>,
 ) -> tracing_appender::non_blocking::WorkerGuard {
+    use tracing_subscriber::prelude::*;
+
     // create log format
     let format = tracing_subscriber::fmt::format()
         .without_time()
@@ -102,15 +105,53 @@ pub fn init_tracing_subscriber(
         None => builder.finish(std::io::stdout()),
     };
 
-    tracing_subscriber::fmt()
+    // the env filter only applies to logs, so that the spans of the sampled
+    // commands are exported even if debug logs are disabled
+    let fmt_layer = tracing_subscriber::fmt::layer()
         .event_format(format)
         .with_writer(non_blocking_appender)
-        .with_env_filter(env_filter)
+        .with_filter(env_filter);
+    let otlp_layer = otlp.clone().map(|config| {
+        crate::otlp::layer(config)
+            .with_filter(tracing_subscriber::filter::LevelFilter::DEBUG)
+    });
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(otlp_layer)
         .init();
+    tracing::info!("otlp: {:?}", otlp);
 
     guard
 }
 
+/// Creates the span of the command with dot `dot`. Each stage of a command
+/// (its submission, the handling of messages about it, its commit and its
+/// execution) is traced in a child span (or event) of such a span, so that
+/// logs can be filtered by dot (e.g. with
+/// `RUST_LOG='[dot{source=1,sequence=42}]=trace'`) and the time spent in each
+/// stage can be analyzed. If an OTLP exporter is set up, only the commands
+/// sampled (see `otlp::sampled`) have a span.
+pub fn dot_span(dot: &Dot) -> tracing::Span {
+    if !crate::otlp::sampled(dot) {
+        return tracing::Span::none();
+    }
+    tracing::debug_span!(
+        "dot",
+        source = dot.source(),
+        sequence = dot.sequence()
+    )
+}
+
+/// Returns the name of a message from its `Debug` representation (e.g.
+/// `MCollect` for `MCollect { dot: .., .. }`).
+pub fn message_name(msg: &impl std::fmt::Debug) -> String {
+    format!("{:?}", msg)
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
 type DefaultHasher = ahash::AHasher;
 
 /// Compute the hash of a key.
@@ -0,0 +1,206 @@
+use crate::search::ConfigAndStats;
+use crate::{ClientWeights, SearchParams};
+use fantoch::planet::{Planet, Region};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+// bump this whenever the cached configurations (or how they're scored)
+// change, so that old cache entries are no longer used
+const CACHE_VERSION: u64 = 1;
+
+/// On-disk cache of the configurations scored by a search, with one entry per
+/// set of clients and configuration size `n`. Entries are keyed by a hash of
+/// the latencies in the planet, the regions searched, the clients, the
+/// constraints of the search (e.g. the required leader) and `n`, so that:
+/// - a search with other sizes (e.g. a larger `max_n`) or other ranking
+///   params reuses the entries of previous searches
+/// - an interrupted search resumes from the entries already saved
+pub(crate) struct ConfigCache {
+    dir: PathBuf,
+    // hash of what's common to all entries: the planet and the constraints
+    prefix: u64,
+}
+
+impl ConfigCache {
+    pub(crate) fn new(
+        dir: impl AsRef<Path>,
+        planet: &Planet,
+        params: &SearchParams,
+    ) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).expect("couldn't create search cache dir");
+
+        let mut hasher = Fnv::new();
+        hasher.write_u64(CACHE_VERSION);
+        let mut regions = planet.regions();
+        regions.sort();
+        for from in &regions {
+            for to in &regions {
+                hasher.write_str(from.name());
+                hasher.write_str(to.name());
+                hasher.write_u64(planet.ping_latency(from, to).unwrap_or(0));
+            }
+        }
+        let leader = params.leader().map(|leader| leader.name().as_str());
+        hasher.write_str(leader.unwrap_or(""));
+        let mut required: Vec<_> = params.required().collect();
+        required.sort();
+        hasher.write_u64(required.len() as u64);
+        for region in required {
+            hasher.write_str(region.name());
+        }
+        let mut excluded: Vec<_> = params.excluded().iter().collect();
+        excluded.sort();
+        hasher.write_u64(excluded.len() as u64);
+        for region in excluded {
+            hasher.write_str(region.name());
+        }
+        Self {
+            dir,
+            prefix: hasher.finish(),
+        }
+    }
+
+    /// Returns the configurations of size `n` cached for these `servers` and
+    /// `clients`, if any. Entries that can't be read are ignored.
+    pub(crate) fn get(
+        &self,
+        servers: &[Region],
+        clients: &ClientWeights,
+        n: usize,
+    ) -> Option<Vec<ConfigAndStats>> {
+        let path = self.path(servers, clients, n);
+        File::open(path)
+            .ok()
+            .map(BufReader::new)
+            .and_then(|reader| bincode::deserialize_from(reader).ok())
+    }
+
+    /// Caches the configurations of size `n` for these `servers` and
+    /// `clients`.
+    pub(crate) fn put(
+        &self,
+        servers: &[Region],
+        clients: &ClientWeights,
+        n: usize,
+        configs: &[ConfigAndStats],
+    ) {
+        let path = self.path(servers, clients, n);
+        // write to a temporary file first and then rename it, so that an
+        // interrupted search never leaves a partial entry behind
+        let tmp = path.with_extension("tmp");
+        let file = File::create(&tmp).expect("couldn't create cache entry");
+        bincode::serialize_into(BufWriter::new(file), configs)
+            .expect("error serializing cache entry");
+        fs::rename(&tmp, &path).expect("couldn't save cache entry");
+    }
+
+    fn path(
+        &self,
+        servers: &[Region],
+        clients: &ClientWeights,
+        n: usize,
+    ) -> PathBuf {
+        let mut hasher = Fnv::new();
+        hasher.write_u64(self.prefix);
+        hasher.write_u64(servers.len() as u64);
+        for region in servers {
+            hasher.write_str(region.name());
+        }
+        for region in clients.regions() {
+            hasher.write_str(region.name());
+            hasher.write_u64(clients.weight(&region) as u64);
+        }
+        hasher.write_u64(n as u64);
+        self.dir.join(format!("{:016x}.data", hasher.finish()))
+    }
+}
+
+// FNV-1a hasher: unlike `DefaultHasher`, its output is the same across
+// versions of Rust, so cache entries remain valid
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_str(&mut self, value: &str) {
+        // the length makes sure that consecutive strings can't be confused
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Search;
+    use crate::Bote;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn put_and_get() {
+        let dir = std::env::temp_dir().join("fantoch_bote_cache_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let servers = vec![
+            Region::new("europe-west2"),
+            Region::new("us-east1"),
+            Region::new("asia-east1"),
+        ];
+        let clients = ClientWeights::uniform(&servers);
+        let bote = Bote::new();
+        let stats = Search::compute_stats(&servers, &clients, None, &bote);
+        let configs =
+            vec![(servers.iter().cloned().collect::<BTreeSet<_>>(), stats)];
+
+        let planet = Planet::new();
+        let cache = ConfigCache::new(&dir, &planet, &SearchParams::new());
+        assert_eq!(cache.get(&servers, &clients, 3), None);
+        cache.put(&servers, &clients, 3, &configs);
+        assert_eq!(cache.get(&servers, &clients, 3), Some(configs));
+
+        // other sizes, servers, clients and leaders have other entries
+        assert_eq!(cache.get(&servers, &clients, 5), None);
+        assert_eq!(cache.get(&servers[..2], &clients, 3), None);
+        let weighted = ClientWeights::new(vec![(servers[0].clone(), 2)]);
+        assert_eq!(cache.get(&servers, &weighted, 3), None);
+        let mut params = SearchParams::new();
+        params.set_leader(servers[0].clone());
+        let cache_leader = ConfigCache::new(&dir, &planet, &params);
+        assert_eq!(cache_leader.get(&servers, &clients, 3), None);
+        let mut params = SearchParams::new();
+        params.include(servers[0].clone());
+        let cache_include = ConfigCache::new(&dir, &planet, &params);
+        assert_eq!(cache_include.get(&servers, &clients, 3), None);
+
+        // and so do other planets
+        let (_, equidistant) = Planet::equidistant(10, 3);
+        let cache_planet =
+            ConfigCache::new(&dir, &equidistant, &SearchParams::new());
+        assert_eq!(cache_planet.get(&servers, &clients, 3), None);
+
+        // entries that can't be read are ignored
+        let path = cache.path(&servers, &clients, 3);
+        fs::write(&path, "corrupted").unwrap();
+        assert_eq!(cache.get(&servers, &clients, 3), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
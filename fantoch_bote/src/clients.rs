@@ -0,0 +1,101 @@
+use fantoch::metrics::Histogram;
+use fantoch::planet::Region;
+use serde::{Deserialize, Serialize};
+
+/// Regions where clients are deployed, each with a weight (e.g. its request
+/// volume) that sets how much the latency perceived by the clients in that
+/// region counts in the latency stats.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientWeights {
+    // the order of the regions is the one in which they were given
+    weights: Vec<(Region, usize)>,
+}
+
+impl ClientWeights {
+    /// Creates client weights from a mapping from region to its weight.
+    pub fn new(weights: impl IntoIterator<Item = (Region, usize)>) -> Self {
+        let mut result = Vec::<(Region, usize)>::new();
+        for (region, weight) in weights {
+            assert!(
+                !result.iter().any(|(other, _)| other == &region),
+                "region {:?} has more than one weight",
+                region
+            );
+            result.push((region, weight));
+        }
+        Self { weights: result }
+    }
+
+    /// Creates client weights where all `regions` have the same weight.
+    pub fn uniform(regions: &[Region]) -> Self {
+        Self::new(regions.iter().map(|region| (region.clone(), 1)))
+    }
+
+    /// Returns the regions where clients are deployed.
+    pub fn regions(&self) -> Vec<Region> {
+        self.weights
+            .iter()
+            .map(|(region, _)| region.clone())
+            .collect()
+    }
+
+    /// Returns the weight of `region` (0 if no clients are deployed there).
+    pub fn weight(&self, region: &Region) -> usize {
+        self.weights
+            .iter()
+            .find(|(other, _)| other == region)
+            .map(|(_, weight)| *weight)
+            .unwrap_or_default()
+    }
+
+    /// Creates an histogram with the latency perceived by the clients in each
+    /// region, counted as many times as the weight of the region.
+    pub fn histogram<'a>(
+        &self,
+        latencies: impl IntoIterator<Item = (&'a Region, u64)>,
+    ) -> Histogram {
+        let mut histogram = Histogram::new();
+        for (region, latency) in latencies {
+            histogram.increment_by(latency, self.weight(region));
+        }
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights() {
+        let w1 = Region::new("europe-west1");
+        let w2 = Region::new("europe-west2");
+        let w3 = Region::new("europe-west3");
+
+        let uniform = ClientWeights::uniform(&[w2.clone(), w1.clone()]);
+        assert_eq!(uniform.regions(), vec![w2.clone(), w1.clone()]);
+        assert_eq!(uniform.weight(&w1), 1);
+        assert_eq!(uniform.weight(&w3), 0);
+        let latencies = vec![(&w1, 10), (&w2, 20)];
+        assert_eq!(uniform.histogram(latencies.clone()).mean().round(), "15.0");
+
+        // with weights, the latency in w2 counts three times as much
+        let weighted =
+            ClientWeights::new(vec![(w1.clone(), 1), (w2.clone(), 3)]);
+        let histogram = weighted.histogram(latencies);
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.mean().round(), "17.5");
+
+        // regions without clients are ignored
+        let histogram = weighted.histogram(vec![(&w1, 10), (&w3, 100)]);
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.mean().round(), "10.0");
+    }
+
+    #[test]
+    #[should_panic]
+    fn repeated_region() {
+        let w1 = Region::new("europe-west1");
+        ClientWeights::new(vec![(w1.clone(), 1), (w1, 2)]);
+    }
+}
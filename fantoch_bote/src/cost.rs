@@ -0,0 +1,104 @@
+use fantoch::metrics::F64;
+use fantoch::planet::Region;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// Pricing of a region: the cost of running a server there (per hour), and the
+/// cost of the traffic sent from there to other regions (per GB).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RegionCost {
+    pub instance: f64,
+    pub egress: f64,
+}
+
+/// Pricing of each region, used to compute the cost (per hour) of a
+/// configuration, assuming that the server in each region sends `egress_gb`
+/// GB per hour to other regions.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CostTable {
+    egress_gb: f64,
+    costs: HashMap<Region, RegionCost>,
+}
+
+impl CostTable {
+    /// Creates an empty cost table, where each server sends `egress_gb` GB per
+    /// hour to other regions.
+    pub fn new(egress_gb: f64) -> Self {
+        Self {
+            egress_gb,
+            costs: HashMap::new(),
+        }
+    }
+
+    /// Sets the pricing of `region`.
+    pub fn set(&mut self, region: Region, instance: f64, egress: f64) {
+        self.costs.insert(region, RegionCost { instance, egress });
+    }
+
+    /// Returns the pricing of `region`, if known.
+    pub fn get(&self, region: &Region) -> Option<&RegionCost> {
+        self.costs.get(region)
+    }
+
+    /// Computes the cost (per hour) of a configuration with a server in each
+    /// of its regions.
+    pub fn cost(&self, config: &BTreeSet<Region>) -> F64 {
+        let cost = config
+            .iter()
+            .map(|region| {
+                let cost = self.get(region).unwrap_or_else(|| {
+                    panic!("cost of region {:?} should be known", region)
+                });
+                // a single server (i.e. with no other regions to send traffic
+                // to) has no egress traffic
+                let egress_gb = if config.len() > 1 {
+                    self.egress_gb
+                } else {
+                    0.0
+                };
+                cost.instance + cost.egress * egress_gb
+            })
+            .sum();
+        F64::new(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost() {
+        let w1 = Region::new("europe-west1");
+        let w2 = Region::new("europe-west2");
+        let w3 = Region::new("europe-west3");
+
+        let mut costs = CostTable::new(10.0);
+        costs.set(w1.clone(), 1.0, 0.1);
+        costs.set(w2.clone(), 2.0, 0.2);
+        assert_eq!(
+            costs.get(&w1),
+            Some(&RegionCost {
+                instance: 1.0,
+                egress: 0.1
+            })
+        );
+        assert_eq!(costs.get(&w3), None);
+
+        // 1 + 0.1 * 10 + 2 + 0.2 * 10
+        let config = vec![w1.clone(), w2].into_iter().collect();
+        assert_eq!(costs.cost(&config), F64::new(6.0));
+
+        // a single server has no egress traffic
+        let config = vec![w1].into_iter().collect();
+        assert_eq!(costs.cost(&config), F64::new(1.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_cost() {
+        let costs = CostTable::new(10.0);
+        let config = vec![Region::new("europe-west1")].into_iter().collect();
+        costs.cost(&config);
+    }
+}
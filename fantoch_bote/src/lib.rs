@@ -1,14 +1,29 @@
 #![deny(rust_2018_idioms)]
 
+// This module contains the definition of `ConfigCache`.
+mod cache;
+
+// This module contains the definition of `ClientWeights`.
+pub mod clients;
+
+// This module contains the definition of `CostTable` and `RegionCost`.
+pub mod cost;
+
 // This module contains the definition of `Protocol`, `ClientPlacement` and
 // `ProtocolStats`.
 pub mod protocol;
 
+// This module contains the definition of `SearchResult`.
+pub mod result;
+
 // This module contains the definition of `Search`.
 pub mod search;
 
 // Re-exports.
-pub use search::{FTMetric, RankingParams, Search, SearchInput};
+pub use clients::ClientWeights;
+pub use cost::{CostTable, RegionCost};
+pub use result::SearchResult;
+pub use search::{FTMetric, RankingParams, Search, SearchInput, SearchParams};
 
 use fantoch::metrics::{Histogram, Stats};
 use fantoch::planet::{Planet, Region};
@@ -58,6 +73,48 @@ impl Bote {
             .collect()
     }
 
+    /// Computes stats for Tempo with a given `fast_quorum_size` and
+    /// `stability_threshold`.
+    ///
+    /// Each client submits its command to the closest region, which commits
+    /// it once it hears from its closest fast quorum. The command is executed
+    /// once it's stable, i.e. once `stability_threshold` processes promised
+    /// clocks up to its timestamp; this assumes that processes promise clocks
+    /// as soon as they see the command (as with real-time clock bumps).
+    ///
+    /// Takes as input two lists of regions:
+    /// - one list being the regions where `servers` are
+    /// - one list being the regions where `clients` are
+    pub fn tempo<'a>(
+        &self,
+        servers: &[Region],
+        clients: &'a [Region],
+        fast_quorum_size: usize,
+        stability_threshold: usize,
+    ) -> Vec<(&'a Region, u64)> {
+        clients
+            .iter()
+            .map(|client| {
+                // compute the latency from this client to the closest region
+                let (client_to_closest, closest) =
+                    self.nth_closest(1, client, servers);
+
+                // compute the latency from such region to its closest fast
+                // quorum, and to the closest processes that make the command
+                // stable
+                let commit =
+                    self.quorum_latency(closest, servers, fast_quorum_size);
+                let stable =
+                    self.quorum_latency(closest, servers, stability_threshold);
+
+                // client perceived latency is the sum of the latency to the
+                // closest region and the latency until the command is both
+                // committed and stable
+                (client, client_to_closest + std::cmp::max(commit, stable))
+            })
+            .collect()
+    }
+
     /// Computes stats for a leader-based protocol with a given `quorum_size`
     /// for some `leader`.
     ///
@@ -91,15 +148,14 @@ impl Bote {
     /// Computes the best leader (for some criteria) and its stats for a
     /// leader-based protocol with a given `quorum_size`.
     ///
-    /// Takes as input two lists of regions:
-    /// - one list being the regions where `servers` are
-    /// - one list being the regions where `clients` are
+    /// Takes as input the list of regions where `servers` are, and the
+    /// regions where `clients` are (each with a weight in the stats).
     ///
     /// The best leader is select based on sort criteria `stats_sort_by`.
     pub fn best_leader<'a>(
         &self,
         servers: &'a [Region],
-        clients: &[Region],
+        clients: &ClientWeights,
         quorum_size: usize,
         stats_sort_by: Stats,
     ) -> (&'a Region, Histogram) {
@@ -123,27 +179,23 @@ impl Bote {
     /// Computes stats for a leader-based protocol with a given `quorum_size`
     /// for each possible leader.
     ///
-    /// Takes as input two lists of regions:
-    /// - one list being the regions where `servers` are
-    /// - one list being the regions where `clients` are
+    /// Takes as input the list of regions where `servers` are, and the
+    /// regions where `clients` are (each with a weight in the stats).
     fn all_leaders_stats<'a>(
         &self,
         servers: &'a [Region],
-        clients: &[Region],
+        clients: &ClientWeights,
         quorum_size: usize,
     ) -> Vec<(&'a Region, Histogram)> {
+        let client_regions = clients.regions();
         // compute stats for each possible leader
         servers
             .iter()
             .map(|leader| {
                 // compute stats
                 let latency_per_client =
-                    self.leader(leader, servers, clients, quorum_size);
-                let stats = Histogram::from(
-                    latency_per_client
-                        .into_iter()
-                        .map(|(_client, latency)| latency),
-                );
+                    self.leader(leader, servers, &client_regions, quorum_size);
+                let stats = clients.histogram(latency_per_client);
                 (leader, stats)
             })
             .collect()
@@ -151,15 +203,34 @@ impl Bote {
 
     /// Computes the latency to closest quorum of size `quorum_size`.
     /// It takes as input the considered source region `from` and all available
-    /// `regions`.
+    /// `regions`. The latency to each region is the time it takes for a message
+    /// from `from` to reach it plus the time for its reply to come back, which
+    /// may differ (see `Bote::round_trip`).
     fn quorum_latency(
         &self,
         from: &Region,
         regions: &[Region],
         quorum_size: usize,
     ) -> u64 {
-        let (latency, _) = self.nth_closest(quorum_size, &from, &regions);
-        *latency
+        let mut latencies: Vec<_> =
+            regions.iter().map(|to| self.round_trip(from, to)).collect();
+        latencies.sort_unstable();
+        latencies[quorum_size - 1]
+    }
+
+    /// Computes the round-trip time from `from` to `to`: the one-way delay from
+    /// `from` to `to` plus the one-way delay from `to` back to `from`. If the
+    /// planet has no one-way delays, the latency measured at `from` is used
+    /// instead (as splitting it in two halves would round it down).
+    fn round_trip(&self, from: &Region, to: &Region) -> u64 {
+        let latency = if self.planet.has_one_way_latencies() {
+            let there = self.planet.one_way_latency(from, to);
+            let back = self.planet.one_way_latency(to, from);
+            there.zip(back).map(|(there, back)| there + back)
+        } else {
+            self.planet.ping_latency(from, to)
+        };
+        latency.expect("latency between regions should be known")
     }
 
     /// Compute the latency to the nth closest region.
@@ -221,6 +292,86 @@ mod tests {
         assert_eq!(bote.quorum_latency(&w6, &regions, quorum_size), 14);
     }
 
+    #[test]
+    fn asymmetric_latencies() {
+        // the latencies measured at a, b and c differ
+        let a = Region::new("a");
+        let b = Region::new("b");
+        let c = Region::new("c");
+        let latencies = vec![
+            (
+                a.clone(),
+                vec![(a.clone(), 0), (b.clone(), 10), (c.clone(), 50)],
+            ),
+            (
+                b.clone(),
+                vec![(b.clone(), 0), (a.clone(), 30), (c.clone(), 40)],
+            ),
+            (
+                c.clone(),
+                vec![(c.clone(), 0), (a.clone(), 20), (b.clone(), 40)],
+            ),
+        ]
+        .into_iter()
+        .map(|(from, entries)| (from, entries.into_iter().collect()))
+        .collect();
+        let bote = Bote::from(Planet::from_latencies(latencies));
+        let regions = vec![a.clone(), b.clone(), c.clone()];
+
+        // quorum latencies use the latencies from the source region
+        let quorum_size = 2;
+        assert_eq!(bote.quorum_latency(&a, &regions, quorum_size), 10);
+        assert_eq!(bote.quorum_latency(&b, &regions, quorum_size), 30);
+        assert_eq!(bote.quorum_latency(&c, &regions, quorum_size), 20);
+
+        // and so do the latencies from clients to the leader
+        let clients = vec![c.clone()];
+        let stats = bote.leader(&a, &regions, &clients, quorum_size);
+        assert_eq!(stats, vec![(&c, 20 + 10)]);
+        let stats = bote.leader(&b, &regions, &clients, quorum_size);
+        assert_eq!(stats, vec![(&c, 40 + 30)]);
+    }
+
+    #[test]
+    fn one_way_latencies() {
+        // a message from a to b takes 10, but its reply takes 50
+        let a = Region::new("a");
+        let b = Region::new("b");
+        let c = Region::new("c");
+        let one_way = vec![
+            (
+                a.clone(),
+                vec![(a.clone(), 0), (b.clone(), 10), (c.clone(), 30)],
+            ),
+            (
+                b.clone(),
+                vec![(b.clone(), 0), (a.clone(), 50), (c.clone(), 20)],
+            ),
+            (
+                c.clone(),
+                vec![(c.clone(), 0), (a.clone(), 30), (b.clone(), 20)],
+            ),
+        ]
+        .into_iter()
+        .map(|(from, entries)| (from, entries.into_iter().collect()))
+        .collect();
+        let planet = Planet::from_one_way_latencies(one_way);
+        assert_eq!(planet.one_way_latency(&a, &b), Some(10));
+        assert_eq!(planet.one_way_latency(&b, &a), Some(50));
+        let bote = Bote::from(planet);
+        let regions = vec![a.clone(), b.clone(), c.clone()];
+
+        // reaching b from a takes the delay there plus the delay back, and
+        // not twice the delay there
+        let quorum_size = 2;
+        assert_eq!(bote.quorum_latency(&a, &regions, quorum_size), 10 + 50);
+        assert_eq!(bote.quorum_latency(&b, &regions, quorum_size), 20 + 20);
+        assert_eq!(bote.quorum_latency(&c, &regions, quorum_size), 20 + 20);
+
+        // with a single-region quorum, the latency is 0
+        assert_eq!(bote.quorum_latency(&a, &regions, 1), 0);
+    }
+
     #[test]
     fn leaderless() {
         // create bote
@@ -339,8 +490,9 @@ mod tests {
 
         // quorum size 2:
         let quorum_size = 2;
+        let clients = ClientWeights::uniform(&regions);
         let leader_to_stats: HashMap<_, _> = bote
-            .all_leaders_stats(&regions, &regions, quorum_size)
+            .all_leaders_stats(&regions, &clients, quorum_size)
             .into_iter()
             .collect();
 
@@ -386,7 +538,11 @@ mod tests {
         // subset of clients: w1 w2
         let clients = vec![w1.clone(), w2.clone()];
         let leader_to_stats: HashMap<_, _> = bote
-            .all_leaders_stats(&servers, &clients, quorum_size)
+            .all_leaders_stats(
+                &servers,
+                &ClientWeights::uniform(&clients),
+                quorum_size,
+            )
             .into_iter()
             .collect();
 
@@ -414,7 +570,11 @@ mod tests {
         // subset of clients: w1 w3 w6
         let clients = vec![w1.clone(), w3.clone(), w6.clone()];
         let leader_to_stats: HashMap<_, _> = bote
-            .all_leaders_stats(&servers, &clients, quorum_size)
+            .all_leaders_stats(
+                &servers,
+                &ClientWeights::uniform(&clients),
+                quorum_size,
+            )
             .into_iter()
             .collect();
 
@@ -456,11 +616,71 @@ mod tests {
 
         // quorum size 2:
         let quorum_size = 2;
+        let clients = ClientWeights::uniform(&regions);
         let (_, stats) =
-            bote.best_leader(&regions, &regions, quorum_size, Stats::Mean);
+            bote.best_leader(&regions, &clients, quorum_size, Stats::Mean);
 
         assert_eq!(stats.mean().round(), "14.0");
         assert_eq!(stats.cov().round(), "0.3");
         assert_eq!(stats.mdtm().round(), "2.8");
     }
+
+    #[test]
+    fn weighted_leader() {
+        // create bote
+        let bote = Bote::new();
+
+        // considered regions
+        let w1 = Region::new("europe-west1");
+        let w2 = Region::new("europe-west2");
+        let w3 = Region::new("europe-west3");
+        let servers = vec![w1.clone(), w2.clone(), w3];
+
+        // quorum size 2:
+        // - with the same weight in w1 and w2, w1 is the best leader
+        // - with most clients in w2, w2 is the best leader
+        let quorum_size = 2;
+        let clients = ClientWeights::uniform(&[w1.clone(), w2.clone()]);
+        let (leader, _) =
+            bote.best_leader(&servers, &clients, quorum_size, Stats::Mean);
+        assert_eq!(leader, &w1);
+        let clients = ClientWeights::new(vec![(w1, 1), (w2.clone(), 10)]);
+        let (leader, stats) =
+            bote.best_leader(&servers, &clients, quorum_size, Stats::Mean);
+        assert_eq!(leader, &w2);
+        assert_eq!(stats.count(), 11);
+    }
+
+    #[test]
+    fn tempo() {
+        // create bote
+        let bote = Bote::new();
+
+        // considered regions
+        let w1 = Region::new("europe-west1");
+        let w2 = Region::new("europe-west2");
+        let w3 = Region::new("europe-west3");
+        let w4 = Region::new("europe-west4");
+        let w6 = Region::new("europe-west6");
+        let regions =
+            vec![w1.clone(), w2.clone(), w3.clone(), w4.clone(), w6.clone()];
+
+        // with a stability threshold not larger than the fast quorum, Tempo
+        // has the same latency as a leaderless protocol with such quorum
+        let quorum_size = 3;
+        let tempo = bote.tempo(&regions, &regions, quorum_size, 3);
+        let leaderless = bote.leaderless(&regions, &regions, quorum_size);
+        assert_eq!(tempo, leaderless);
+
+        // tiny quorums with f = 1: fast quorum of size 2 and stability
+        // threshold of 4, i.e. the latency to the quorum of size 4
+        let tempo = bote.tempo(&regions, &regions, 2, 4);
+        let leaderless = bote.leaderless(&regions, &regions, 4);
+        assert_eq!(tempo, leaderless);
+        let histogram = Histogram::from(
+            tempo.into_iter().map(|(_client, latency)| latency),
+        );
+        // w1 -> 11, w2 -> 14, w3 -> 9, w4 -> 10, w6 -> 15
+        assert_eq!(histogram.mean().round(), "10.8");
+    }
 }
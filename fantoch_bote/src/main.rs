@@ -1,5 +1,8 @@
 use fantoch::planet::{Planet, Region};
-use fantoch_bote::{FTMetric, RankingParams, Search, SearchInput};
+use fantoch_bote::{
+    FTMetric, RankingParams, Search, SearchInput, SearchParams, SearchResult,
+};
+use std::fs::File;
 
 fn main() {
     distance_table();
@@ -37,7 +40,14 @@ fn search() {
     let save_search = true;
 
     // create search
-    let search = Search::new(min_n, max_n, search_input, save_search, None);
+    let search = Search::new(
+        min_n,
+        max_n,
+        search_input,
+        save_search,
+        None,
+        &SearchParams::new(),
+    );
 
     // define search params:
     // originally 30 was used for the `min_mean_improv`;
@@ -61,7 +71,7 @@ fn search() {
     );
 
     // select the best config
-    let (score, css, _clients) = search
+    let (score, css, clients) = search
         .sorted_evolving_configs(&params)
         .into_iter()
         .take(1) // take only the best one
@@ -70,6 +80,13 @@ fn search() {
 
     println!("score: {:?}", score);
 
+    // save it as JSON and CSV
+    let results = vec![SearchResult::new(score, &css, clients)];
+    let json = File::create("search.json").expect("file should be created");
+    SearchResult::write_json(&results, json).expect("results should be saved");
+    let csv = File::create("search.csv").expect("file should be created");
+    SearchResult::write_csv(&results, csv).expect("results should be saved");
+
     // the final sorted config
     let mut sorted_config = Vec::new();
     for (config, stats) in css {
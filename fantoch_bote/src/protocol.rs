@@ -1,19 +1,42 @@
 use fantoch::metrics::Histogram;
+use fantoch::planet::Region;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+#[derive(Clone, Copy)]
 pub enum Protocol {
     FPaxos,
     EPaxos,
     Atlas,
+    /// Tempo, either with tiny quorums (i.e. fast quorums of size 2f) or with
+    /// fast quorums of size n/2 + f (see `Config::tempo_quorum_sizes`).
+    Tempo {
+        tiny_quorums: bool,
+    },
 }
 
 impl Protocol {
+    pub fn name(&self) -> &str {
+        match self {
+            Protocol::FPaxos => "fpaxos",
+            Protocol::EPaxos => "epaxos",
+            Protocol::Atlas => "atlas",
+            Protocol::Tempo {
+                tiny_quorums: false,
+            } => "tempo",
+            Protocol::Tempo { tiny_quorums: true } => "tempo_tiny",
+        }
+    }
+
     pub fn short_name(&self) -> &str {
         match self {
             Protocol::FPaxos => "f",
             Protocol::EPaxos => "e",
             Protocol::Atlas => "a",
+            Protocol::Tempo {
+                tiny_quorums: false,
+            } => "t",
+            Protocol::Tempo { tiny_quorums: true } => "tt",
         }
     }
 
@@ -27,6 +50,29 @@ impl Protocol {
                 f + ((f + 1) / 2 as usize)
             }
             Protocol::Atlas => Self::minority(n) + f,
+            Protocol::Tempo { tiny_quorums } => {
+                if *tiny_quorums {
+                    2 * f
+                } else {
+                    Self::minority(n) + f
+                }
+            }
+        }
+    }
+
+    /// Returns the number of processes that have to promise clocks before a
+    /// command is stable, for protocols where that's needed (i.e. Tempo).
+    pub fn stability_threshold(&self, n: usize, f: usize) -> Option<usize> {
+        match self {
+            Protocol::Tempo { tiny_quorums } => {
+                let threshold = if *tiny_quorums {
+                    n - f
+                } else {
+                    Self::minority(n) + 1
+                };
+                Some(threshold)
+            }
+            _ => None,
         }
     }
 
@@ -42,6 +88,13 @@ pub enum ClientPlacement {
 }
 
 impl ClientPlacement {
+    pub fn name(&self) -> &str {
+        match self {
+            ClientPlacement::Input => "input",
+            ClientPlacement::Colocated => "colocated",
+        }
+    }
+
     pub fn short_name(&self) -> &str {
         match self {
             ClientPlacement::Input => "",
@@ -54,9 +107,13 @@ impl ClientPlacement {
     }
 }
 
-/// Mapping from protocol name to its stats.
+/// Mapping from protocol name to its stats, and the leader used by the
+/// leader-based protocol (i.e. FPaxos).
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
-pub struct ProtocolStats(BTreeMap<String, Histogram>);
+pub struct ProtocolStats {
+    stats: BTreeMap<String, Histogram>,
+    leader: Option<Region>,
+}
 
 impl ProtocolStats {
     pub fn new() -> ProtocolStats {
@@ -81,7 +138,15 @@ impl ProtocolStats {
         stats: Histogram,
     ) {
         let key = Self::key(protocol, f, placement);
-        self.0.insert(key, stats);
+        self.stats.insert(key, stats);
+    }
+
+    pub fn leader(&self) -> Option<&Region> {
+        self.leader.as_ref()
+    }
+
+    pub fn set_leader(&mut self, leader: Region) {
+        self.leader = Some(leader);
     }
 
     pub fn fmt(
@@ -105,7 +170,7 @@ impl ProtocolStats {
     }
 
     fn get_and_unwrap(&self, key: &str) -> &Histogram {
-        self.0.get(key).unwrap_or_else(|| {
+        self.stats.get(key).unwrap_or_else(|| {
             panic!("stats with key {} not found", key);
         })
     }
@@ -131,6 +196,19 @@ mod tests {
         assert_eq!(Protocol::Atlas.quorum_size(3, 1), 2);
         assert_eq!(Protocol::Atlas.quorum_size(5, 1), 3);
         assert_eq!(Protocol::Atlas.quorum_size(5, 2), 4);
+        let tempo = Protocol::Tempo {
+            tiny_quorums: false,
+        };
+        assert_eq!(tempo.quorum_size(5, 1), 3);
+        assert_eq!(tempo.quorum_size(5, 2), 4);
+        assert_eq!(tempo.stability_threshold(5, 1), Some(3));
+        assert_eq!(tempo.stability_threshold(5, 2), Some(3));
+        let tempo_tiny = Protocol::Tempo { tiny_quorums: true };
+        assert_eq!(tempo_tiny.quorum_size(5, 1), 2);
+        assert_eq!(tempo_tiny.quorum_size(5, 2), 4);
+        assert_eq!(tempo_tiny.stability_threshold(5, 1), Some(4));
+        assert_eq!(tempo_tiny.stability_threshold(5, 2), Some(3));
+        assert_eq!(Protocol::Atlas.stability_threshold(5, 1), None);
     }
 
     #[test]
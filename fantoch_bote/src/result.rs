@@ -0,0 +1,251 @@
+use crate::protocol::ClientPlacement;
+use crate::protocol::Protocol::{self, Atlas, EPaxos, FPaxos, Tempo};
+use crate::search::{ConfigAndStats, Search};
+use crate::ClientWeights;
+use fantoch::metrics::{Histogram, F64};
+use fantoch::planet::Region;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+/// Result of a search: an evolving configuration (as returned by
+/// `Search::sorted_evolving_configs`), its score, and the stats of each
+/// protocol in each of its configurations.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub score: f64,
+    pub clients: ClientWeights,
+    pub configs: Vec<ConfigResult>,
+}
+
+/// Configuration with `n` regions in a `SearchResult`, and its FPaxos leader.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConfigResult {
+    pub n: usize,
+    pub regions: Vec<Region>,
+    pub leader: Option<Region>,
+    pub stats: Vec<StatsResult>,
+}
+
+/// Latency stats (in milliseconds) of a protocol tolerating `f` faults, with
+/// clients either in the input regions or colocated with the servers. As
+/// EPaxos always tolerates a minority of faults, its `f` is 0.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StatsResult {
+    pub protocol: String,
+    pub f: usize,
+    pub placement: String,
+    pub mean: f64,
+    pub stddev: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub p99_9: f64,
+    pub p99_99: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+// protocols with stats for each f (EPaxos always tolerates a minority)
+const PROTOCOLS_PER_F: [Protocol; 4] = [
+    Atlas,
+    FPaxos,
+    Tempo {
+        tiny_quorums: false,
+    },
+    Tempo { tiny_quorums: true },
+];
+
+// header of the CSV output: one line per `StatsResult`
+const CSV_HEADER: &str = concat!(
+    "rank,score,n,regions,leader,protocol,f,placement,",
+    "mean,stddev,p95,p99,p99.9,p99.99,min,max"
+);
+
+impl SearchResult {
+    pub fn new(
+        score: F64,
+        css: &[&ConfigAndStats],
+        clients: &ClientWeights,
+    ) -> Self {
+        let configs = css
+            .iter()
+            .map(|(config, stats)| {
+                let n = config.len();
+                let leader = stats.leader().cloned();
+                // same order as in `Search::stats_fmt`, with tempo after
+                // fpaxos
+                let stats = ClientPlacement::all()
+                    .flat_map(|placement| {
+                        (1..=Search::max_f(n))
+                            .flat_map(move |f| {
+                                PROTOCOLS_PER_F.iter().map(move |protocol| {
+                                    (*protocol, f, placement)
+                                })
+                            })
+                            .chain(std::iter::once((EPaxos, 0, placement)))
+                    })
+                    .map(|(protocol, f, placement)| {
+                        let histogram = stats.get(protocol, f, placement);
+                        StatsResult::new(protocol, f, placement, histogram)
+                    })
+                    .collect();
+                ConfigResult {
+                    n,
+                    regions: config.iter().cloned().collect(),
+                    leader,
+                    stats,
+                }
+            })
+            .collect();
+        Self {
+            score: score.value(),
+            clients: clients.clone(),
+            configs,
+        }
+    }
+
+    /// Writes `results` as a JSON array.
+    pub fn write_json(
+        results: &[SearchResult],
+        writer: impl Write,
+    ) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, results).map_err(io::Error::from)
+    }
+
+    /// Writes `results` as CSV, with one line per protocol stats in each
+    /// configuration, where `rank` is the position of the result in `results`,
+    /// the regions of a configuration are separated by spaces, and the leader
+    /// is empty if unknown.
+    pub fn write_csv(
+        results: &[SearchResult],
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+        for (rank, result) in results.iter().enumerate() {
+            for config in &result.configs {
+                let regions: Vec<_> = config
+                    .regions
+                    .iter()
+                    .map(|region| region.name().as_str())
+                    .collect();
+                let regions = regions.join(" ");
+                let leader = config
+                    .leader
+                    .as_ref()
+                    .map(|leader| leader.name().as_str())
+                    .unwrap_or_default();
+                for stats in &config.stats {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                        rank,
+                        result.score,
+                        config.n,
+                        regions,
+                        leader,
+                        stats.protocol,
+                        stats.f,
+                        stats.placement,
+                        stats.mean,
+                        stats.stddev,
+                        stats.p95,
+                        stats.p99,
+                        stats.p99_9,
+                        stats.p99_99,
+                        stats.min,
+                        stats.max,
+                    )?;
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+impl StatsResult {
+    fn new(
+        protocol: Protocol,
+        f: usize,
+        placement: ClientPlacement,
+        histogram: &Histogram,
+    ) -> Self {
+        Self {
+            protocol: protocol.name().to_string(),
+            f,
+            placement: placement.name().to_string(),
+            mean: histogram.mean().value(),
+            stddev: histogram.stddev().value(),
+            p95: histogram.percentile(0.95).value(),
+            p99: histogram.percentile(0.99).value(),
+            p99_9: histogram.percentile(0.999).value(),
+            p99_99: histogram.percentile(0.9999).value(),
+            min: histogram.min().value(),
+            max: histogram.max().value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bote;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn json_and_csv() {
+        let config = vec![
+            Region::new("europe-west2"),
+            Region::new("us-east1"),
+            Region::new("asia-east1"),
+        ];
+        let bote = Bote::new();
+        let clients = ClientWeights::uniform(&config);
+        let stats = Search::compute_stats(&config, &clients, None, &bote);
+        let cs = (config.iter().cloned().collect::<BTreeSet<_>>(), stats);
+        let result = SearchResult::new(F64::new(10.5), &[&cs], &clients);
+        assert_eq!(result.score, 10.5);
+        assert_eq!(result.configs.len(), 1);
+
+        // with n = 3, there's atlas, fpaxos and tempo (with and without tiny
+        // quorums) with f = 1, and epaxos, for each client placement
+        let config_result = &result.configs[0];
+        assert_eq!(config_result.n, 3);
+        assert_eq!(config_result.stats.len(), 10);
+        assert_eq!(config_result.leader.as_ref(), cs.1.leader());
+        let atlas = &config_result.stats[0];
+        assert_eq!(atlas.protocol, "atlas");
+        assert_eq!(atlas.f, 1);
+        assert_eq!(atlas.placement, "input");
+        let histogram = cs.1.get(Atlas, 1, ClientPlacement::Input);
+        assert_eq!(atlas.mean, histogram.mean().value());
+        assert_eq!(config_result.stats[2].protocol, "tempo");
+        assert_eq!(config_result.stats[3].protocol, "tempo_tiny");
+        let epaxos = &config_result.stats[9];
+        assert_eq!(epaxos.protocol, "epaxos");
+        assert_eq!(epaxos.f, 0);
+        assert_eq!(epaxos.placement, "colocated");
+
+        // json can be parsed back
+        let results = vec![result.clone(), result];
+        let mut json = Vec::new();
+        SearchResult::write_json(&results, &mut json).unwrap();
+        let parsed: Vec<SearchResult> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed, results);
+
+        // csv has a header and a line per stats in each result
+        let mut csv = Vec::new();
+        SearchResult::write_csv(&results, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 * 10);
+        assert_eq!(lines[0], CSV_HEADER);
+        let columns = CSV_HEADER.split(',').count();
+        assert!(lines.iter().all(|line| line.split(',').count() == columns));
+        let leader = cs.1.leader().expect("leader should be set").name();
+        let expected = format!(
+            "0,10.5,3,asia-east1 europe-west2 us-east1,{},atlas,1,input,",
+            leader
+        );
+        assert!(lines[1].starts_with(&expected));
+        assert!(lines[20].starts_with("1,10.5,3,"));
+    }
+}
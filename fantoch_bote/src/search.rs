@@ -1,9 +1,11 @@
-use crate::protocol::Protocol::{Atlas, EPaxos, FPaxos};
+use crate::cache::ConfigCache;
+use crate::cost::CostTable;
+use crate::protocol::Protocol::{self, Atlas, EPaxos, FPaxos, Tempo};
 use crate::protocol::{ClientPlacement, ProtocolStats};
-use crate::Bote;
+use crate::{Bote, ClientWeights};
 use fantoch::elapsed;
-use fantoch::metrics::{Histogram, Stats, F64};
-use fantoch::planet::{Planet, Region};
+use fantoch::metrics::{Stats, F64};
+use fantoch::planet::{LatencyDataset, Planet, Region};
 use permutator::Combination;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -21,14 +23,17 @@ macro_rules! timed {
     }};
 }
 
+// directory where the configurations scored are cached if `save_search`
+const CACHE_DIR: &str = "search_cache";
+
 // config and stats
-type ConfigAndStats = (BTreeSet<Region>, ProtocolStats);
+pub(crate) type ConfigAndStats = (BTreeSet<Region>, ProtocolStats);
 
 // configs: mapping from `n` to list of configurations of such size
 type Configs = HashMap<usize, Vec<ConfigAndStats>>;
 
-// all configs: mapping from clients to `Config`
-type AllConfigs = Vec<(Vec<Region>, Configs)>;
+// all configs: mapping from clients (and their weights) to `Config`
+type AllConfigs = Vec<(ClientWeights, Configs)>;
 
 // ranked: mapping from `n` to list of configurations of such size
 // - these configurations are already a subset of all configurations that passed
@@ -36,7 +41,7 @@ type AllConfigs = Vec<(Vec<Region>, Configs)>;
 type Ranked<'a> = HashMap<usize, Vec<(F64, &'a ConfigAndStats)>>;
 
 // all ranked: mapping from clients to `Ranked`
-type AllRanked<'a> = Vec<(&'a Vec<Region>, Ranked<'a>)>;
+type AllRanked<'a> = Vec<(&'a ClientWeights, Ranked<'a>)>;
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct Search {
@@ -49,16 +54,19 @@ impl Search {
         max_n: usize,
         search_input: SearchInput,
         save_search: bool,
-        lat_dir: Option<&str>,
+        dataset: Option<&LatencyDataset>,
+        params: &SearchParams,
     ) -> Self {
         // get filename
-        let filename = Self::filename(min_n, max_n, &search_input);
+        let filename =
+            Self::filename(min_n, max_n, &search_input, dataset, params);
 
         timed!("get saved search", Self::get_saved_search(&filename))
             .unwrap_or_else(|| {
                 // create planet
-                let planet = if let Some(lat_dir) = lat_dir {
-                    Planet::from(lat_dir)
+                let planet = if let Some(dataset) = dataset {
+                    Planet::from_dataset(dataset)
+                        .expect("latency dataset should be loaded")
                 } else {
                     Planet::new()
                 };
@@ -67,6 +75,14 @@ impl Search {
                 let (servers, clients) =
                     search_input.get_inputs(max_n, &planet);
 
+                // if `save_search`, reuse (and save) the configurations
+                // already scored by previous (maybe interrupted) searches
+                let cache = if save_search {
+                    Some(ConfigCache::new(CACHE_DIR, &planet, params))
+                } else {
+                    None
+                };
+
                 // create bote
                 let bote = Bote::from(planet);
 
@@ -74,7 +90,13 @@ impl Search {
                 let all_configs = timed!(
                     "compute all configs",
                     Self::compute_all_configs(
-                        min_n, max_n, servers, clients, bote
+                        min_n,
+                        max_n,
+                        servers,
+                        clients,
+                        params,
+                        bote,
+                        cache.as_ref(),
                     )
                 );
 
@@ -97,7 +119,7 @@ impl Search {
     pub fn sorted_evolving_configs(
         &self,
         p: &RankingParams,
-    ) -> Vec<(F64, Vec<&ConfigAndStats>, &Vec<Region>)> {
+    ) -> Vec<(F64, Vec<&ConfigAndStats>, &ClientWeights)> {
         assert_eq!(p.min_n, 3);
         assert_eq!(p.max_n, 13);
 
@@ -113,60 +135,36 @@ impl Search {
             .sum();
         println!("config count: {}", count);
 
+        // compute the evolving configs for each set of clients
+        let all_evolving: Vec<_> = all_ranked
+            // PARALLEL
+            .into_par_iter()
+            .map(|(clients, ranked)| {
+                let indexed = IndexedRanked::new(ranked);
+                let mut evolving = Vec::new();
+                for &(score, cs, bitset) in indexed.get(p.min_n) {
+                    Self::evolve(
+                        &indexed,
+                        p.min_n + 2,
+                        (score, vec![cs], bitset),
+                        p,
+                        &mut evolving,
+                    );
+                }
+                (clients, evolving)
+            })
+            .collect();
+
         // create result variable
         let mut configs = BTreeMap::new();
-
-        // TODO Transform what's below in an iterator.
-        // With access to `p.min_n` and `p.max_n` it should be possible.
-
-        let mut i = 0;
-        let count = all_ranked.len();
-        all_ranked.into_iter().for_each(|(clients, ranked)| {
-            i += 1;
-            if i % 100 == 0 {
-                println!("{} of {}", i, count);
+        for (clients, evolving) in all_evolving {
+            for (score, css) in evolving {
+                configs
+                    .entry(score)
+                    .or_insert_with(Vec::new)
+                    .push((score, css, clients));
             }
-
-            Self::configs(&ranked, 3).for_each(|(score3, cs3)| {
-                Self::super_configs(&ranked, 5, cs3, p).for_each(
-                    |(score5, cs5)| {
-                        Self::super_configs(&ranked, 7, cs5, p).for_each(
-                            |(score7, cs7)| {
-                                Self::super_configs(&ranked, 9, cs7, p)
-                                    .for_each(|(score9, cs9)| {
-                                        Self::super_configs(
-                                            &ranked, 11, cs9, p,
-                                        )
-                                        .for_each(|(score11, cs11)| {
-                                            Self::super_configs(
-                                                &ranked, 13, cs11, p,
-                                            )
-                                            .for_each(|(score13, cs13)| {
-                                                let score = score3
-                                                    + score5
-                                                    + score7
-                                                    + score9
-                                                    + score11
-                                                    + score13;
-                                                let css = vec![
-                                                    cs3, cs5, cs7, cs9, cs11,
-                                                    cs13,
-                                                ];
-                                                configs
-                                                    .entry(score)
-                                                    .or_insert_with(Vec::new)
-                                                    .push((
-                                                        score, css, clients,
-                                                    ));
-                                            });
-                                        });
-                                    });
-                            },
-                        );
-                    },
-                );
-            });
-        });
+        }
 
         // `configs` is sorted ASC
         configs
@@ -177,6 +175,86 @@ impl Search {
             .collect()
     }
 
+    /// returns, for each set of clients, the Pareto frontier of the ranked
+    /// configurations of each size `n`, i.e. the configurations for which
+    /// there's no other configuration with lower or equal mean latency,
+    /// fairness (COV) and tail latency (p99) of the protocol ranked (for each f
+    /// considered), and at least one of them lower
+    /// - unlike `sorted_evolving_configs`, configurations are not given a
+    ///   score: the filters in `params` are still applied, and each frontier
+    ///   is sorted by mean latency ASC
+    pub fn pareto_frontiers(
+        &self,
+        p: &RankingParams,
+    ) -> Vec<(&ClientWeights, BTreeMap<usize, Vec<&ConfigAndStats>>)> {
+        timed!("rank all", self.rank_all(p))
+            // PARALLEL
+            .into_par_iter()
+            .map(|(clients, ranked)| {
+                let frontiers = ranked
+                    .into_iter()
+                    .map(|(n, css)| {
+                        let css = css.into_iter().map(|(_, cs)| cs);
+                        (n, Self::pareto_frontier(css, p))
+                    })
+                    .collect();
+                (clients, frontiers)
+            })
+            .collect()
+    }
+
+    fn pareto_frontier<'a>(
+        css: impl Iterator<Item = &'a ConfigAndStats>,
+        params: &RankingParams,
+    ) -> Vec<&'a ConfigAndStats> {
+        // sort configurations by their objectives: this way, a configuration
+        // can only be dominated by the ones before it and, if it is, it's also
+        // dominated by one in the frontier
+        let mut css: Vec<_> =
+            css.map(|cs| (Self::objectives(cs, params), cs)).collect();
+        css.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut frontier: Vec<(Vec<F64>, &ConfigAndStats)> = Vec::new();
+        for (objectives, cs) in css {
+            let dominated = frontier
+                .iter()
+                .any(|(other, _)| Self::dominates(other, &objectives));
+            if !dominated {
+                frontier.push((objectives, cs));
+            }
+        }
+        frontier.into_iter().map(|(_, cs)| cs).collect()
+    }
+
+    /// objectives to be minimized by configurations in the Pareto frontier:
+    /// mean latency, COV and p99 of the protocol ranked, for each f considered,
+    /// and the cost of the configuration (if there's a cost table)
+    fn objectives(
+        (config, stats): &ConfigAndStats,
+        params: &RankingParams,
+    ) -> Vec<F64> {
+        let n = config.len();
+        let placement = ClientPlacement::Input;
+        let mut objectives: Vec<_> = params
+            .ft_metric
+            .fs(n)
+            .into_iter()
+            .flat_map(|f| {
+                let ranked = stats.get(params.protocol, f, placement);
+                vec![ranked.mean(), ranked.cov(), ranked.percentile(0.99)]
+            })
+            .collect();
+        if let Some(costs) = &params.costs {
+            objectives.push(costs.cost(config));
+        }
+        objectives
+    }
+
+    /// checks whether objectives `a` dominate objectives `b`
+    fn dominates(a: &[F64], b: &[F64]) -> bool {
+        a.iter().zip(b).all(|(a, b)| a <= b) && a != b
+    }
+
     pub fn stats_fmt(stats: &ProtocolStats, n: usize) -> String {
         ClientPlacement::all()
             .map(|placement| {
@@ -200,8 +278,10 @@ impl Search {
         min_n: usize,
         max_n: usize,
         servers: Option<Vec<Region>>,
-        all_clients: Vec<Vec<Region>>,
+        all_clients: Vec<ClientWeights>,
+        params: &SearchParams,
         bote: Bote,
+        cache: Option<&ConfigCache>,
     ) -> AllConfigs {
         // get the count of client configurations
         let clients_count = all_clients.len();
@@ -219,11 +299,12 @@ impl Search {
             .map(|(_, clients)| {
                 // compute servers: if we have something, use what we got,
                 // otherwise use the set of clients
-                let servers = servers.as_ref().unwrap_or(&clients);
+                let servers =
+                    servers.clone().unwrap_or_else(|| clients.regions());
 
                 // compute `Configs` for this set of clients
                 let configs = Self::compute_configs(
-                    min_n, max_n, &servers, &clients, &bote,
+                    min_n, max_n, &servers, &clients, params, &bote, cache,
                 );
 
                 (clients, configs)
@@ -235,18 +316,54 @@ impl Search {
         min_n: usize,
         max_n: usize,
         regions: &[Region],
-        clients: &[Region],
+        clients: &ClientWeights,
+        params: &SearchParams,
         bote: &Bote,
+        cache: Option<&ConfigCache>,
     ) -> Configs {
+        // remove the regions excluded
+        let regions: Vec<_> = regions
+            .iter()
+            .filter(|region| !params.exclude.contains(region))
+            .cloned()
+            .collect();
+        let regions = &regions[..];
+        let leader = params.leader.as_ref();
+
         (min_n..=max_n)
             .step_by(2)
             .map(|n| {
-                let configs = regions
-                    .combination(n)
-                    .map(vec_cloned)
+                // reuse the configurations of size `n` if they're cached
+                if let Some(configs) =
+                    cache.and_then(|cache| cache.get(regions, clients, n))
+                {
+                    return (n, configs);
+                }
+
+                // there are no configurations if there are not enough regions
+                // (e.g. because some were excluded)
+                let configs: Vec<_> = if n <= regions.len() {
+                    regions
+                        .combination(n)
+                        .map(vec_cloned)
+                        // only keep the configurations with the regions
+                        // required (including the leader, if any)
+                        .filter(|config| {
+                            params
+                                .required()
+                                .all(|region| config.contains(region))
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let configs: Vec<_> = configs
+                    // PARALLEL
+                    .into_par_iter()
                     .map(|config| {
                         // compute stats
-                        let stats = Self::compute_stats(&config, clients, bote);
+                        let stats =
+                            Self::compute_stats(&config, clients, leader, bote);
 
                         // turn config into a `BTreeSet`
                         let config = BTreeSet::from_iter(config.into_iter());
@@ -254,63 +371,93 @@ impl Search {
                         (config, stats)
                     })
                     .collect();
+
+                // cache them, so that other searches don't have to compute
+                // them again
+                if let Some(cache) = cache {
+                    cache.put(regions, clients, n, &configs);
+                }
                 (n, configs)
             })
             .collect()
     }
 
+    /// Computes the stats of each protocol in `config`. If `leader` is set,
+    /// it's the FPaxos leader (and it should be in `config`); otherwise, the
+    /// leader is the one with the best stats.
     pub fn compute_stats(
         config: &[Region],
-        all_clients: &[Region],
+        all_clients: &ClientWeights,
+        leader: Option<&Region>,
         bote: &Bote,
     ) -> ProtocolStats {
         // compute n
         let n = config.len();
         let mut stats = ProtocolStats::new();
 
-        // compute best cov fpaxos f=1 leader
-        // - this leader will then be used for both f=1 and f=2 stats
-        let f = 1;
-        let quorum_size = FPaxos.quorum_size(n, f);
-        let (leader, _) =
-            bote.best_leader(config, all_clients, quorum_size, Stats::COV);
+        let leader = if let Some(leader) = leader {
+            assert!(
+                config.contains(leader),
+                "leader {:?} should be in the configuration",
+                leader
+            );
+            leader
+        } else {
+            // compute best cov fpaxos f=1 leader
+            // - this leader will then be used for both f=1 and f=2 stats
+            let f = 1;
+            let quorum_size = FPaxos.quorum_size(n, f);
+            let (leader, _) =
+                bote.best_leader(config, all_clients, quorum_size, Stats::COV);
+            leader
+        };
+        stats.set_leader(leader.clone());
 
         // compute stats for both `clients` and colocated clients i.e. `config`
+        let colocated = ClientWeights::uniform(config);
         let which_clients = vec![
             (ClientPlacement::Input, all_clients),
-            (ClientPlacement::Colocated, config),
+            (ClientPlacement::Colocated, &colocated),
         ];
         for (placement, clients) in which_clients {
+            let regions = clients.regions();
             for f in 1..=Self::max_f(n) {
                 // compute altas quorum size
                 let quorum_size = Atlas.quorum_size(n, f);
 
                 // compute atlas stats
-                let atlas = bote.leaderless(config, clients, quorum_size);
-                let atlas = Histogram::from(
-                    atlas.into_iter().map(|(_client, latency)| latency),
-                );
+                let atlas = bote.leaderless(config, &regions, quorum_size);
+                let atlas = clients.histogram(atlas);
                 stats.insert(Atlas, f, placement, atlas);
 
                 // compute fpaxos quorum size
                 let quorum_size = FPaxos.quorum_size(n, f);
 
                 // // compute best mean fpaxos stats
-                let fpaxos = bote.leader(leader, config, clients, quorum_size);
-                let fpaxos = Histogram::from(
-                    fpaxos.into_iter().map(|(_client, latency)| latency),
-                );
+                let fpaxos = bote.leader(leader, config, &regions, quorum_size);
+                let fpaxos = clients.histogram(fpaxos);
                 stats.insert(FPaxos, f, placement, fpaxos);
+
+                // compute tempo stats, with and without tiny quorums
+                for tiny_quorums in [false, true] {
+                    let protocol = Tempo { tiny_quorums };
+                    let quorum_size = protocol.quorum_size(n, f);
+                    let threshold = protocol
+                        .stability_threshold(n, f)
+                        .expect("tempo should have a stability threshold");
+                    let tempo =
+                        bote.tempo(config, &regions, quorum_size, threshold);
+                    let tempo = clients.histogram(tempo);
+                    stats.insert(protocol, f, placement, tempo);
+                }
             }
 
             // compute epaxos quorum size
             let quorum_size = EPaxos.quorum_size(n, 0);
 
             // compute epaxos stats
-            let epaxos = bote.leaderless(config, clients, quorum_size);
-            let epaxos = Histogram::from(
-                epaxos.into_iter().map(|(_client, latency)| latency),
-            );
+            let epaxos = bote.leaderless(config, &regions, quorum_size);
+            let epaxos = clients.histogram(epaxos);
             stats.insert(EPaxos, 0, placement, epaxos);
         }
 
@@ -335,11 +482,8 @@ impl Search {
                     let css = css
                         .iter()
                         .filter_map(|cs| {
-                            // get stats
-                            let stats = &cs.1;
-
                             // only keep valid configurations
-                            match Self::compute_score(n, stats, params) {
+                            match Self::compute_score(cs, params) {
                                 (true, score) => Some((score, cs)),
                                 _ => None,
                             }
@@ -353,53 +497,43 @@ impl Search {
             .collect()
     }
 
-    // TODO `configs` and `super_configs` are super similar
-    #[allow(clippy::iter_cloned_collect)]
-    fn configs<'a>(
-        ranked: &Ranked<'a>,
+    /// extends the evolving configuration `css` (with score `score`, and
+    /// where the last configuration has regions `bitset`) with the ranked
+    /// configurations of size `n` that are a superset of the last one, until
+    /// `params.max_n`
+    fn evolve<'a>(
+        indexed: &IndexedRanked<'a>,
         n: usize,
-    ) -> impl Iterator<Item = (F64, &'a ConfigAndStats)> {
-        ranked
-            .get(&n)
-            .unwrap_or_else(|| {
-                panic!("configs for n = {} should be ranked!", n)
-            })
-            .iter()
-            .cloned()
-            // TODO can we avoid collecting here?
-            // I wasn't able to do it due to lifetime issues
-            .collect::<Vec<_>>()
-            .into_iter()
-    }
-
-    /// return ranked configurations such that:
-    /// - their size is `n`
-    /// - are a superset of `previous_config`
-    fn super_configs<'a>(
-        ranked: &Ranked<'a>,
-        n: usize,
-        (prev_config, prev_stats): &ConfigAndStats,
+        (score, css, bitset): (F64, Vec<&'a ConfigAndStats>, Bitset),
         params: &RankingParams,
-    ) -> impl Iterator<Item = (F64, &'a ConfigAndStats)> {
-        ranked
-            .get(&n)
-            .unwrap_or_else(|| {
-                panic!("super configs for n = {} should be ranked!", n)
-            })
-            .iter()
-            .filter(|(_, (config, stats))| {
-                config.is_superset(prev_config)
-                    && Self::min_mean_decrease(stats, prev_stats, n, params)
+        evolving: &mut Vec<(F64, Vec<&'a ConfigAndStats>)>,
+    ) {
+        if n > params.max_n {
+            evolving.push((score, css));
+            return;
+        }
+
+        // there's always a previous configuration
+        let (_, prev_stats) = css.last().unwrap();
+        for (super_score, cs, super_bitset) in
+            indexed.supersets(n, bitset).filter(|(_, (_, stats), _)| {
+                Self::min_mean_decrease(stats, prev_stats, n, params)
             })
-            .cloned()
-            // TODO can we avoid collecting here?
-            // I wasn't able to do it due to lifetime issues
-            .collect::<Vec<_>>()
-            .into_iter()
+        {
+            let mut css = css.clone();
+            css.push(cs);
+            Self::evolve(
+                indexed,
+                n + 2,
+                (score + super_score, css, super_bitset),
+                params,
+                evolving,
+            );
+        }
     }
 
-    /// Compute the mean latency decrease for Atlas f = 1 when the number of
-    /// sites increases.
+    /// Compute the mean latency decrease for the protocol ranked when the
+    /// number of sites increases.
     fn min_mean_decrease(
         stats: &ProtocolStats,
         prev_stats: &ProtocolStats,
@@ -412,17 +546,18 @@ impl Search {
         let placement = ClientPlacement::Input;
 
         params.ft_metric.fs(n).into_iter().all(|f| {
-            let atlas = stats.get(Atlas, f, placement);
-            let prev_atlas = prev_stats.get(Atlas, f, placement);
-            prev_atlas.mean_improv(atlas) >= params.min_mean_decrease
+            let ranked = stats.get(params.protocol, f, placement);
+            let prev_ranked = prev_stats.get(params.protocol, f, placement);
+            prev_ranked.mean_improv(ranked) >= params.min_mean_decrease
         })
     }
 
     fn compute_score(
-        n: usize,
-        stats: &ProtocolStats,
+        (config, stats): &ConfigAndStats,
         params: &RankingParams,
     ) -> (bool, F64) {
+        let n = config.len();
+
         // compute score and check if it is a valid configuration
         let mut valid = true;
         let mut score = F64::zero();
@@ -434,15 +569,15 @@ impl Search {
         let placement = ClientPlacement::Input;
 
         for f in fs {
-            // get atlas and fpaxos stats
-            let atlas = stats.get(Atlas, f, placement);
+            // get stats of the protocol ranked (atlas by default) and fpaxos
+            let ranked = stats.get(params.protocol, f, placement);
             let fpaxos = stats.get(FPaxos, f, placement);
 
-            // compute mean latency improvement of atlas wrto to fpaxos
-            let fpaxos_mean_improv = fpaxos.mean_improv(atlas);
+            // compute mean latency improvement wrto to fpaxos
+            let fpaxos_mean_improv = fpaxos.mean_improv(ranked);
 
-            // compute fairness improvement of atlas wrto to cov fpaxos
-            let fpaxos_fairness_improv = fpaxos.cov_improv(atlas);
+            // compute fairness improvement wrto to cov fpaxos
+            let fpaxos_fairness_improv = fpaxos.cov_improv(ranked);
 
             // check if it's a valid config, i.e. there's enough:
             // - `min_mean_improv`
@@ -454,8 +589,8 @@ impl Search {
             // get epaxos stats
             let epaxos = stats.get(EPaxos, 0, placement);
 
-            // compute mean latency improvement of atlas wrto to epaxos
-            let epaxos_mean_improv = epaxos.mean_improv(atlas);
+            // compute mean latency improvement wrto to epaxos
+            let epaxos_mean_improv = epaxos.mean_improv(ranked);
 
             // make sure we improve on EPaxos for large n
             if n == 11 || n == 13 {
@@ -468,10 +603,20 @@ impl Search {
             score += fpaxos_mean_improv + (weight * epaxos_mean_improv);
         }
 
+        // if there's a cost table, check the configuration is within budget,
+        // and update score: the cost is subtracted according to its weight
+        if let Some(costs) = &params.costs {
+            let cost = costs.cost(config);
+            if let Some(budget) = params.budget {
+                valid = valid && cost <= budget;
+            }
+            score = score - params.cost_weight * cost;
+        }
+
         (valid, score)
     }
 
-    fn max_f(n: usize) -> usize {
+    pub(crate) fn max_f(n: usize) -> usize {
         let max_f = 2;
         std::cmp::min(n / 2 as usize, max_f)
     }
@@ -480,8 +625,42 @@ impl Search {
         min_n: usize,
         max_n: usize,
         search_input: &SearchInput,
+        dataset: Option<&LatencyDataset>,
+        params: &SearchParams,
     ) -> String {
-        format!("{}_{}_{}.data", min_n, max_n, search_input)
+        // searches with a fixed leader, or with regions included or excluded
+        // are saved in other files
+        let join = |regions: &[Region]| {
+            let names: Vec<_> = regions
+                .iter()
+                .map(|region| region.name().as_str())
+                .collect();
+            names.join("+")
+        };
+        let mut constraints = String::new();
+        if let Some(leader) = &params.leader {
+            constraints.push_str(&format!("_L{}", leader.name()));
+        }
+        if !params.include.is_empty() {
+            constraints.push_str(&format!("_I{}", join(&params.include)));
+        }
+        if !params.exclude.is_empty() {
+            constraints.push_str(&format!("_X{}", join(&params.exclude)));
+        }
+        let filename =
+            format!("{}_{}_{}{}.data", min_n, max_n, search_input, constraints);
+        match dataset {
+            // searches on other datasets are saved in other files
+            Some(dataset) => {
+                let dir = dataset
+                    .dir()
+                    .replace(|c: char| !c.is_alphanumeric(), "_")
+                    .trim_matches('_')
+                    .to_string();
+                format!("{}_{}_{}", dataset.provider(), dir, filename)
+            }
+            None => filename,
+        }
     }
 
     fn get_saved_search(name: &str) -> Option<Search> {
@@ -524,6 +703,13 @@ pub enum SearchInput {
     /// - e.g. if the max number of regions is 11, clients are deployed in
     ///   those 11 regions
     R17CMaxN,
+    /// search within the regions with clients, where the latency perceived by
+    /// the clients in each region is weighted by its weight; `name` identifies
+    /// the search (e.g. when it's saved)
+    Weighted {
+        name: String,
+        clients: ClientWeights,
+    },
 }
 
 impl fmt::Display for SearchInput {
@@ -533,6 +719,7 @@ impl fmt::Display for SearchInput {
             SearchInput::R17C17 => write!(f, "R17C17"),
             SearchInput::R20C20 => write!(f, "R20C20"),
             SearchInput::R17CMaxN => write!(f, "R17CMaxN"),
+            SearchInput::Weighted { name, .. } => write!(f, "W{}", name),
         }
     }
 }
@@ -540,12 +727,12 @@ impl fmt::Display for SearchInput {
 impl SearchInput {
     /// It returns a tuple where the:
     /// - 1st component is the set of regions where to look for a configuration
-    /// - 2nd component is a list of client locations
+    /// - 2nd component is a list of client locations (and their weights)
     fn get_inputs(
         &self,
         max_n: usize,
         planet: &Planet,
-    ) -> (Option<Vec<Region>>, Vec<Vec<Region>>) {
+    ) -> (Option<Vec<Region>>, Vec<ClientWeights>) {
         // selected 13-regions
         let regions13 = vec![
             Region::new("asia-southeast1"),
@@ -590,31 +777,204 @@ impl SearchInput {
 
         match self {
             SearchInput::R13C13 => {
-                let clients = vec![regions13.clone()];
+                let clients = vec![ClientWeights::uniform(&regions13)];
                 (Some(regions13), clients)
             }
             SearchInput::R17C17 => {
-                let clients = vec![regions17.clone()];
+                let clients = vec![ClientWeights::uniform(&regions17)];
                 (Some(regions17), clients)
             }
             SearchInput::R20C20 => {
-                let clients = vec![all_regions.clone()];
+                let clients = vec![ClientWeights::uniform(&all_regions)];
                 (Some(all_regions), clients)
             }
             SearchInput::R17CMaxN => {
-                let clients =
-                    regions17.combination(max_n).map(vec_cloned).collect();
+                let clients = regions17
+                    .combination(max_n)
+                    .map(|clients| ClientWeights::uniform(&vec_cloned(clients)))
+                    .collect();
                 (None, clients)
             }
+            SearchInput::Weighted { clients, .. } => {
+                (Some(clients.regions()), vec![clients.clone()])
+            }
         }
     }
 }
 
+// set of regions, where each region is identified by its position in
+// `IndexedRanked.regions`
+type Bitset = u64;
+
+/// ranked configurations indexed by the set of their regions, so that the
+/// supersets of a configuration can be found by looking up each set of regions
+/// that extends it, instead of checking every configuration
+struct IndexedRanked<'a> {
+    // regions in the configurations
+    regions: Vec<Region>,
+    // mapping from `n` to list of ranked configurations of such size (with
+    // their set of regions), and from each set of regions to its position in
+    // that list
+    configs: HashMap<usize, (Vec<IndexedConfig<'a>>, HashMap<Bitset, usize>)>,
+}
+
+// ranked configuration and its set of regions
+type IndexedConfig<'a> = (F64, &'a ConfigAndStats, Bitset);
+
+impl<'a> IndexedRanked<'a> {
+    fn new(ranked: Ranked<'a>) -> Self {
+        let regions: BTreeSet<_> = ranked
+            .values()
+            .flat_map(|css| css.iter().flat_map(|(_, (config, _))| config))
+            .cloned()
+            .collect();
+        let regions: Vec<_> = regions.into_iter().collect();
+        assert!(
+            regions.len() <= Bitset::BITS as usize,
+            "can't index configurations with more than {} regions",
+            Bitset::BITS
+        );
+
+        let configs = ranked
+            .into_iter()
+            .map(|(n, css)| {
+                let css: Vec<_> = css
+                    .into_iter()
+                    .map(|(score, cs)| {
+                        let bitset = cs.0.iter().fold(0, |bitset, region| {
+                            // all regions have been found above
+                            let index = regions.binary_search(region).unwrap();
+                            bitset | 1 << index
+                        });
+                        (score, cs, bitset)
+                    })
+                    .collect();
+                let positions = css
+                    .iter()
+                    .enumerate()
+                    .map(|(position, (_, _, bitset))| (*bitset, position))
+                    .collect();
+                (n, (css, positions))
+            })
+            .collect();
+        Self { regions, configs }
+    }
+
+    /// returns the ranked configurations of size `n`
+    fn get(&self, n: usize) -> &[IndexedConfig<'a>] {
+        &self.configs_of_size(n).0
+    }
+
+    /// returns the ranked configurations of size `n` that are a superset of
+    /// `bitset`, in the order in which they were ranked
+    fn supersets(
+        &self,
+        n: usize,
+        bitset: Bitset,
+    ) -> impl Iterator<Item = IndexedConfig<'a>> + '_ {
+        let (css, positions) = self.configs_of_size(n);
+        let missing: Vec<Bitset> = (0..self.regions.len())
+            .map(|index| 1 << index)
+            .filter(|region| bitset & region == 0)
+            .collect();
+        let extra = n - bitset.count_ones() as usize;
+        let mut found: Vec<_> = if extra <= missing.len() {
+            missing
+                .combination(extra)
+                .filter_map(|regions| {
+                    let superset = regions
+                        .into_iter()
+                        .fold(bitset, |bitset, region| bitset | region);
+                    positions.get(&superset).cloned()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        found.sort_unstable();
+        found.into_iter().map(move |position| css[position])
+    }
+
+    fn configs_of_size(
+        &self,
+        n: usize,
+    ) -> &(Vec<IndexedConfig<'a>>, HashMap<Bitset, usize>) {
+        self.configs.get(&n).unwrap_or_else(|| {
+            panic!("configs for n = {} should be ranked!", n)
+        })
+    }
+}
+
 fn vec_cloned<T: Clone>(vec: Vec<&T>) -> Vec<T> {
     vec.into_iter().cloned().collect()
 }
 
+/// constraints on the configurations considered by a search
+#[derive(Debug, Clone, Default)]
+pub struct SearchParams {
+    leader: Option<Region>,
+    include: Vec<Region>,
+    exclude: Vec<Region>,
+}
+
+impl SearchParams {
+    /// by default, there are no constraints
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// requires the FPaxos leader to be `leader`, and thus `leader` to be in
+    /// every configuration
+    pub fn set_leader(&mut self, leader: Region) {
+        assert!(
+            !self.exclude.contains(&leader),
+            "leader {:?} should not be excluded",
+            leader
+        );
+        self.leader = Some(leader);
+    }
+
+    /// requires `region` to be in every configuration
+    pub fn include(&mut self, region: Region) {
+        assert!(
+            !self.exclude.contains(&region),
+            "region {:?} can't be both included and excluded",
+            region
+        );
+        if !self.include.contains(&region) {
+            self.include.push(region);
+        }
+    }
+
+    /// requires `region` not to be in any configuration
+    pub fn exclude(&mut self, region: Region) {
+        assert!(
+            !self.include.contains(&region)
+                && self.leader.as_ref() != Some(&region),
+            "region {:?} can't be both included and excluded",
+            region
+        );
+        if !self.exclude.contains(&region) {
+            self.exclude.push(region);
+        }
+    }
+
+    pub(crate) fn leader(&self) -> Option<&Region> {
+        self.leader.as_ref()
+    }
+
+    /// regions required to be in every configuration
+    pub(crate) fn required(&self) -> impl Iterator<Item = &Region> {
+        self.leader.iter().chain(self.include.iter())
+    }
+
+    pub(crate) fn excluded(&self) -> &[Region] {
+        &self.exclude
+    }
+}
+
 pub struct RankingParams {
+    protocol: Protocol,
     min_mean_fpaxos_improv: F64,
     min_mean_epaxos_improv: F64,
     min_fairness_fpaxos_improv: F64,
@@ -622,6 +982,9 @@ pub struct RankingParams {
     min_n: usize,
     max_n: usize,
     ft_metric: FTMetric,
+    costs: Option<CostTable>,
+    budget: Option<F64>,
+    cost_weight: F64,
 }
 
 impl RankingParams {
@@ -634,7 +997,11 @@ impl RankingParams {
         max_n: usize,
         ft_metric: FTMetric,
     ) -> Self {
+        // by default, `protocol = Atlas` and the cost of configurations is
+        // not considered
+        let protocol = Atlas;
         Self {
+            protocol,
             min_mean_fpaxos_improv: F64::new(min_mean_fpaxos_improv as f64),
             min_mean_epaxos_improv: F64::new(min_mean_epaxos_improv as f64),
             min_fairness_fpaxos_improv: F64::new(
@@ -644,8 +1011,32 @@ impl RankingParams {
             min_n,
             max_n,
             ft_metric,
+            costs: None,
+            budget: None,
+            cost_weight: F64::zero(),
         }
     }
+
+    /// Changes the protocol whose configurations are ranked (e.g. Tempo),
+    /// which is compared with FPaxos and EPaxos.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// Considers the cost of configurations, as given by `costs`: the cost of
+    /// each configuration is subtracted from its score after being multiplied
+    /// by `cost_weight`, and configurations that cost more than `budget` (if
+    /// any) are not valid. The cost is also an objective in Pareto frontiers.
+    pub fn set_costs(
+        &mut self,
+        costs: CostTable,
+        budget: Option<f64>,
+        cost_weight: f64,
+    ) {
+        self.costs = Some(costs);
+        self.budget = budget.map(F64::new);
+        self.cost_weight = F64::new(cost_weight);
+    }
 }
 
 /// metric considered for fault tolerance
@@ -678,7 +1069,14 @@ mod tests {
         let save_search = false;
 
         // create search
-        let search = Search::new(min_n, max_n, search_input, save_search, None);
+        let search = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
 
         // define search params:
         // originally 30 was used for the `min_mean_improv`;
@@ -750,17 +1148,89 @@ mod tests {
         assert_eq!(sorted_config, expected_config);
     }
 
+    #[test]
+    fn search_pareto() {
+        let min_n = 3;
+        let max_n = 5;
+        let search_input = SearchInput::R13C13;
+        let save_search = false;
+        let search = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+
+        // keep all configurations
+        let params = RankingParams::new(
+            -1000,
+            -1000,
+            -1000,
+            -1000,
+            min_n,
+            max_n,
+            FTMetric::F1F2,
+        );
+        let frontiers = search.pareto_frontiers(&params);
+        assert_eq!(frontiers.len(), 1);
+        let (_, frontiers) = &frontiers[0];
+        let (_, configs) = &search.all_configs[0];
+
+        for n in [3, 5] {
+            let frontier = &frontiers[&n];
+            assert!(!frontier.is_empty());
+            assert!(frontier.len() < configs[&n].len());
+            let objectives = |cs| Search::objectives(cs, &params);
+
+            // the frontier is sorted by mean latency
+            let means: Vec<_> =
+                frontier.iter().map(|cs| objectives(cs)[0]).collect();
+            assert!(means.windows(2).all(|w| w[0] <= w[1]));
+
+            // configurations in the frontier are not dominated, and the
+            // others are dominated by some configuration in the frontier
+            for cs in &configs[&n] {
+                let in_frontier = frontier.iter().any(|other| *other == cs);
+                let dominated_by_frontier = frontier.iter().any(|other| {
+                    Search::dominates(&objectives(other), &objectives(cs))
+                });
+                let dominated = configs[&n].iter().any(|other| {
+                    Search::dominates(&objectives(other), &objectives(cs))
+                });
+                assert_eq!(in_frontier, !dominated);
+                assert_eq!(dominated, dominated_by_frontier);
+            }
+        }
+    }
+
     #[test]
     fn search_save() {
         let min_n = 3;
         let max_n = 5;
         let search_input = SearchInput::R17C17;
-        let filename = Search::filename(min_n, max_n, &search_input);
+        let filename = Search::filename(
+            min_n,
+            max_n,
+            &search_input,
+            None,
+            &SearchParams::new(),
+        );
+
+        // only remove the cache at the end if it's created by this test
+        let cache_existed = std::path::Path::new(CACHE_DIR).exists();
 
         // create search and save it
         let save_search = true;
-        let expected =
-            Search::new(min_n, max_n, search_input, save_search, None);
+        let expected = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
 
         // get saved search and assert it is the same search
         let saved = Search::get_saved_search(&filename);
@@ -769,5 +1239,357 @@ mod tests {
 
         // remove search file
         assert!(std::fs::remove_file(filename).is_ok());
+        if !cache_existed {
+            assert!(std::fs::remove_dir_all(CACHE_DIR).is_ok());
+        }
+    }
+
+    #[test]
+    fn search_weighted() {
+        let min_n = 3;
+        let max_n = 5;
+        let regions = vec![
+            Region::new("asia-southeast1"),
+            Region::new("europe-west4"),
+            Region::new("southamerica-east1"),
+            Region::new("australia-southeast1"),
+            Region::new("europe-west2"),
+            Region::new("asia-south1"),
+        ];
+        let save_search = false;
+
+        // with the same weight in all regions, the search is the same as
+        // searching with those clients
+        let name = String::from("uniform");
+        let clients = ClientWeights::uniform(&regions);
+        let input = SearchInput::Weighted { name, clients };
+        let uniform = Search::new(
+            min_n,
+            max_n,
+            input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+        let bote = Bote::new();
+        let expected = Search::compute_all_configs(
+            min_n,
+            max_n,
+            Some(regions.clone()),
+            vec![ClientWeights::uniform(&regions)],
+            &SearchParams::new(),
+            bote,
+            None,
+        );
+        assert_eq!(uniform.all_configs, expected);
+
+        // with most clients in some region, the stats change
+        let name = String::from("weighted");
+        let clients = ClientWeights::new(
+            regions
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, region)| (region, if i == 0 { 100 } else { 1 })),
+        );
+        let input = SearchInput::Weighted { name, clients };
+        let weighted = Search::new(
+            min_n,
+            max_n,
+            input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+        assert_ne!(weighted.all_configs, uniform.all_configs);
+    }
+
+    #[test]
+    fn search_cache() {
+        let dir = std::env::temp_dir().join("fantoch_bote_search_cache_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let planet = Planet::new();
+        let cache = ConfigCache::new(&dir, &planet, &SearchParams::new());
+        let bote = Bote::from(planet);
+        let servers = vec![
+            Region::new("europe-west2"),
+            Region::new("us-east1"),
+            Region::new("asia-east1"),
+            Region::new("us-west1"),
+            Region::new("asia-south1"),
+        ];
+        let clients = ClientWeights::uniform(&servers);
+        let compute = |min_n, max_n, cache| {
+            Search::compute_configs(
+                min_n,
+                max_n,
+                &servers,
+                &clients,
+                &SearchParams::new(),
+                &bote,
+                cache,
+            )
+        };
+
+        // the cache doesn't change the configurations computed
+        let expected = compute(3, 5, None);
+        assert_eq!(compute(3, 3, Some(&cache)), compute(3, 3, None));
+
+        // a search with a larger `max_n` (or one that was interrupted after
+        // computing the configurations of size 3) reuses the configurations
+        // cached: to check it, change the ones cached for n = 3
+        cache.put(&servers, &clients, 3, &[]);
+        let configs = compute(3, 5, Some(&cache));
+        assert!(configs[&3].is_empty());
+        assert_eq!(configs[&5], expected[&5]);
+
+        // and the configurations of size 5 are now cached as well
+        assert_eq!(
+            cache.get(&servers, &clients, 5).as_ref(),
+            Some(&expected[&5])
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn search_constraints() {
+        let min_n = 3;
+        let max_n = 5;
+        let search_input = SearchInput::R13C13;
+        let save_search = false;
+        let us_e1 = Region::new("us-east1");
+        let eu_w2 = Region::new("europe-west2");
+        let as_e1 = Region::new("asia-east1");
+        let mut params = SearchParams::new();
+        params.include(us_e1.clone());
+        params.include(eu_w2.clone());
+        params.exclude(as_e1.clone());
+
+        // searches with constraints are saved in other files
+        let filename =
+            Search::filename(min_n, max_n, &search_input, None, &params);
+        assert_eq!(
+            filename,
+            "3_5_R13C13_Ius-east1+europe-west2_Xasia-east1.data"
+        );
+
+        // all configurations have the regions included, and none has the
+        // region excluded
+        let search =
+            Search::new(min_n, max_n, search_input, save_search, None, &params);
+        let (clients, configs) = &search.all_configs[0];
+        // clients are still deployed in the region excluded
+        assert!(clients.regions().contains(&as_e1));
+        for n in [3, 5] {
+            // there are 10 choose n - 2 configurations with the regions
+            // included, as one of the 13 regions is excluded
+            let expected = if n == 3 { 10 } else { 120 };
+            assert_eq!(configs[&n].len(), expected);
+            for (config, _) in &configs[&n] {
+                assert!(config.contains(&us_e1));
+                assert!(config.contains(&eu_w2));
+                assert!(!config.contains(&as_e1));
+            }
+        }
+
+        // if there are not enough regions, there are no configurations
+        let regions = vec![us_e1.clone(), eu_w2.clone(), as_e1];
+        let clients = ClientWeights::uniform(&regions);
+        let configs = Search::compute_configs(
+            3,
+            3,
+            &regions,
+            &clients,
+            &params,
+            &Bote::new(),
+            None,
+        );
+        assert!(configs[&3].is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn search_constraints_conflict() {
+        let region = Region::new("us-east1");
+        let mut params = SearchParams::new();
+        params.include(region.clone());
+        params.exclude(region);
+    }
+
+    #[test]
+    fn search_leader() {
+        let min_n = 3;
+        let max_n = 5;
+        let search_input = SearchInput::R13C13;
+        let save_search = false;
+        let leader = Region::new("asia-south1");
+        let mut params = SearchParams::new();
+        params.set_leader(leader.clone());
+
+        // all configurations have the leader required
+        let search =
+            Search::new(min_n, max_n, search_input, save_search, None, &params);
+        let (_, configs) = &search.all_configs[0];
+        for n in [3, 5] {
+            // there are 12 choose n - 1 configurations with the leader
+            let expected = if n == 3 { 66 } else { 495 };
+            assert_eq!(configs[&n].len(), expected);
+            for (config, stats) in &configs[&n] {
+                assert!(config.contains(&leader));
+                assert_eq!(stats.leader(), Some(&leader));
+            }
+        }
+
+        // without it, the leader is the best one in each configuration
+        let search_input = SearchInput::R13C13;
+        let search = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+        let (_, configs) = &search.all_configs[0];
+        assert_eq!(configs[&3].len(), 286);
+        assert!(configs[&3].iter().all(|(config, stats)| {
+            stats
+                .leader()
+                .map_or(false, |leader| config.contains(leader))
+        }));
+        assert!(configs[&3]
+            .iter()
+            .any(|(_, stats)| stats.leader() != Some(&leader)));
+    }
+
+    #[test]
+    fn search_cost() {
+        let min_n = 3;
+        let max_n = 5;
+        let search_input = SearchInput::R13C13;
+        let save_search = false;
+        let search = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+        let (clients, configs) = &search.all_configs[0];
+
+        // a server costs 1 + 0.01 * 100 = 2 per hour, except in asia-east1,
+        // where it costs 10 + 0.01 * 100 = 11 per hour
+        let expensive = Region::new("asia-east1");
+        let mut costs = CostTable::new(100.0);
+        for region in clients.regions() {
+            let instance = if region == expensive { 10.0 } else { 1.0 };
+            costs.set(region, instance, 0.01);
+        }
+
+        // keep all configurations
+        let ranking_params = || {
+            RankingParams::new(
+                -1000,
+                -1000,
+                -1000,
+                -1000,
+                min_n,
+                max_n,
+                FTMetric::F1,
+            )
+        };
+        let ranked = Search::rank(configs, &ranking_params());
+        assert_eq!(ranked[&3].len(), 286);
+        assert_eq!(ranked[&5].len(), 1287);
+
+        // with a budget of 10 per hour, configurations with 3 servers can't
+        // have one in asia-east1 (and there are 12 choose 2 such
+        // configurations), and the same for configurations with 5 servers
+        // (12 choose 4)
+        let mut params = ranking_params();
+        params.set_costs(costs.clone(), Some(10.0), 0.0);
+        let within_budget = Search::rank(configs, &params);
+        assert_eq!(within_budget[&3].len(), 286 - 66);
+        assert_eq!(within_budget[&5].len(), 1287 - 495);
+        assert!(within_budget
+            .values()
+            .flatten()
+            .all(|(_, (config, _))| !config.contains(&expensive)));
+
+        // with a cost weight, the score decreases with the cost
+        let cost_weight = 2.0;
+        let mut params = ranking_params();
+        params.set_costs(costs.clone(), None, cost_weight);
+        let weighted = Search::rank(configs, &params);
+        for n in [3, 5] {
+            assert_eq!(weighted[&n].len(), ranked[&n].len());
+            for ((score, (config, _)), (weighted_score, _)) in
+                ranked[&n].iter().zip(&weighted[&n])
+            {
+                let cost = costs.cost(config);
+                let expected = *score - F64::new(cost_weight) * cost;
+                assert_eq!(*weighted_score, expected);
+            }
+        }
+
+        // and it's an objective in Pareto frontiers
+        let objectives = Search::objectives(&configs[&3][0], &params);
+        assert_eq!(objectives.len(), 3 + 1);
+        assert_eq!(objectives[3], costs.cost(&configs[&3][0].0));
+    }
+
+    #[test]
+    fn search_tempo() {
+        let min_n = 3;
+        let max_n = 5;
+        let search_input = SearchInput::R13C13;
+        let save_search = false;
+        let search = Search::new(
+            min_n,
+            max_n,
+            search_input,
+            save_search,
+            None,
+            &SearchParams::new(),
+        );
+        let (_, configs) = &search.all_configs[0];
+
+        // rank configurations for tempo with tiny quorums
+        let min_mean_fpaxos_improv = 80;
+        let ranking_params = || {
+            RankingParams::new(
+                min_mean_fpaxos_improv,
+                0,
+                0,
+                0,
+                min_n,
+                max_n,
+                FTMetric::F1,
+            )
+        };
+        let mut params = ranking_params();
+        let tempo = Tempo { tiny_quorums: true };
+        params.set_protocol(tempo);
+        let ranked = Search::rank(configs, &params);
+
+        // tempo improves enough on fpaxos in all configurations ranked (i.e.
+        // the stats of tempo were the ones ranked)
+        let placement = ClientPlacement::Input;
+        let min_improv = F64::new(min_mean_fpaxos_improv as f64);
+        assert!(!ranked[&3].is_empty());
+        for (_, (_, stats)) in ranked.values().flatten() {
+            let tempo = stats.get(tempo, 1, placement);
+            let fpaxos = stats.get(FPaxos, 1, placement);
+            assert!(fpaxos.mean_improv(tempo) >= min_improv);
+        }
+
+        // and the configurations ranked are different from the ones for
+        // atlas
+        let atlas_ranked = Search::rank(configs, &ranking_params());
+        let count = |ranked: &Ranked<'_>| -> usize {
+            ranked.values().map(|css| css.len()).sum()
+        };
+        assert_ne!(count(&ranked), count(&atlas_ranked));
     }
 }
@@ -139,7 +139,7 @@ pub async fn bench_experiment(
                             protocol,
                             config,
                             clients,
-                            *workload,
+                            workload.clone(),
                             *batch_max_size,
                             batch_max_delay,
                             cpus,
@@ -229,7 +229,7 @@ async fn run_experiment(
     // run clients
     let run_clients = run_clients(
         clients_per_region,
-        workload,
+        workload.clone(),
         batch_max_size,
         batch_max_delay,
         machines,
@@ -478,7 +478,7 @@ async fn run_clients(
             id_start,
             id_end,
             ips,
-            workload,
+            workload.clone(),
             batch_max_size,
             batch_max_delay,
             metrics_file,
@@ -170,6 +170,22 @@ impl ProtocolConfig {
                 interval.as_millis()
             ]);
         }
+        args.extend(args![
+            "--executor_speculative",
+            self.config.executor_speculative()
+        ]);
+        if let Some(capacity) = self.config.executor_store_capacity() {
+            args.extend(args!["--executor_store_capacity", capacity]);
+        }
+        if let Some(dedup_window) = self.config.executor_dedup_window() {
+            args.extend(args!["--executor_dedup_window", dedup_window]);
+        }
+        if let Some(hot_keys) = self.config.executor_hot_keys() {
+            args.extend(args!["--executor_hot_keys", hot_keys]);
+        }
+        if let Some(threshold) = self.config.executor_parallel_scc_threshold() {
+            args.extend(args!["--executor_parallel_scc_threshold", threshold]);
+        }
         if let Some(interval) = self.config.gc_interval() {
             args.extend(args!["--gc_interval", interval.as_millis()]);
         }
@@ -347,6 +363,22 @@ impl ClientConfig {
             self.workload.payload_size(),
             "--read_only_percentage",
             self.workload.read_only_percentage(),
+            "--rmw_percentage",
+            self.workload.rmw_percentage(),
+            "--eventual_read_percentage",
+            self.workload.eventual_read_percentage(),
+            "--read_your_writes",
+            self.workload.read_your_writes(),
+            "--metadata_table_keys",
+            self.workload.metadata_table_keys(),
+            "--metadata_table_percentage",
+            self.workload.metadata_table_percentage(),
+            "--warmup_commands",
+            self.workload.warmup_commands(),
+            "--warmup",
+            self.workload.warmup().as_millis(),
+            "--latency_per_op_type",
+            self.workload.latency_per_op_type(),
             "--batch_max_size",
             self.batch_max_size,
             "--batch_max_delay",
@@ -358,6 +390,19 @@ impl ClientConfig {
             "--metrics_file",
             self.metrics_file,
         ];
+        if let Some(op_mix) = self.workload.op_mix() {
+            let op_mix = format!(
+                "{},{},{},{},{}",
+                op_mix.get, op_mix.put, op_mix.cas, op_mix.scan, op_mix.delete
+            );
+            args.extend(args!["--op_mix", op_mix]);
+        }
+        if let Some(measurement_window) = self.workload.measurement_window() {
+            args.extend(args![
+                "--measurement_window",
+                measurement_window.as_millis()
+            ]);
+        }
         if let Some(status_frequency) = self.status_frequency {
             args.extend(args!["--status_frequency", status_frequency]);
         }
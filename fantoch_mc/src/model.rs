@@ -0,0 +1,554 @@
+use fantoch::command::Command;
+use fantoch::config::Config;
+use fantoch::executor::Executor;
+use fantoch::id::{ProcessId, Rifl, ShardId};
+use fantoch::kvs::{KVOpResult, Key};
+use fantoch::protocol::{Action, Protocol};
+use fantoch::time::SimTime;
+use fantoch::HashMap;
+use stateright::actor::{Actor, ActorModel, Id, Network, Out};
+use stateright::Expectation;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Creates a stateright model where each of the `config.n()` processes is a
+/// `ProtocolActor`, and process `process_id` submits the commands `commands`
+/// when it starts. Messages can be reordered, but are never lost or
+/// duplicated. The model has two properties:
+/// - "agreement": processes that executed the same command on some key
+///   returned the same results
+/// - "stability": eventually, all processes executed all commands
+///
+/// Only commands on a single shard are supported.
+pub fn protocol_model<P>(
+    config: Config,
+    commands: Vec<(ProcessId, Command)>,
+) -> ActorModel<ProtocolActor<P>>
+where
+    P: Protocol,
+{
+    assert_eq!(config.shard_count(), 1, "only one shard is supported");
+    let n = config.n() as ProcessId;
+    let actors = (1..=n).map(|process_id| {
+        let process_commands = commands
+            .iter()
+            .filter(|(id, _)| *id == process_id)
+            .map(|(_, cmd)| cmd.clone())
+            .collect();
+        ProtocolActor::new(process_id, config, process_commands)
+    });
+    ActorModel::new((), ())
+        .actors(actors)
+        .init_network(Network::new_unordered_nonduplicating([]))
+        .property(Expectation::Always, "agreement", |_, state| {
+            let mut results = HashMap::new();
+            state.actor_states.iter().all(|process| {
+                process.results.iter().all(|(rifl_key, result)| {
+                    results.entry(rifl_key).or_insert(result) == &result
+                })
+            })
+        })
+        .property(Expectation::Eventually, "stability", |model, state| {
+            let expected: usize = model
+                .actors
+                .iter()
+                .flat_map(|actor| actor.commands.iter())
+                .map(|cmd| cmd.key_count(actor_shard_id()))
+                .sum();
+            state
+                .actor_states
+                .iter()
+                .all(|process| process.results.len() == expected)
+        })
+}
+
+// All processes in the model are in the same shard.
+fn actor_shard_id() -> ShardId {
+    0
+}
+
+/// A stateright actor that runs a process (and its executor) of a `Protocol`.
+/// Periodic events are modeled as timers, which can fire at any time; a
+/// periodic event that doesn't lead the process to send any message nor to
+/// execute any command is discarded, so that it doesn't create new states.
+///
+/// Since processes can't be hashed (and some executors share their state
+/// between clones), the state of the actor is the sequence of messages and
+/// periodic events handled by the process, and the process is rebuilt from it
+/// (by handling them again) every time it handles something new. This relies
+/// on processes being deterministic.
+pub struct ProtocolActor<P> {
+    process_id: ProcessId,
+    config: Config,
+    // commands submitted when the process starts
+    commands: Vec<Command>,
+    _phantom: PhantomData<fn() -> P>,
+}
+
+impl<P> ProtocolActor<P>
+where
+    P: Protocol,
+{
+    /// Creates a new `ProtocolActor` for process `process_id` (which should be
+    /// the stateright actor with index `process_id - 1`), that submits
+    /// `commands` when it starts.
+    pub fn new(
+        process_id: ProcessId,
+        config: Config,
+        commands: Vec<Command>,
+    ) -> Self {
+        Self {
+            process_id,
+            config,
+            commands,
+            _phantom: PhantomData,
+        }
+    }
+
+    // Creates the process and its executor, and submits the commands. Messages
+    // to other processes are only sent if `o` is set.
+    fn start(
+        &self,
+        results: &mut Results,
+        mut o: Option<&mut Out<Self>>,
+    ) -> (P, P::Executor, Vec<(P::PeriodicEvent, Duration)>) {
+        let shard_id = actor_shard_id();
+        let (mut process, events) =
+            P::new(self.process_id, shard_id, self.config);
+
+        // discover all processes: self first, and then the others by the
+        // order of their identifiers
+        let n = self.config.n() as ProcessId;
+        let processes = std::iter::once(self.process_id)
+            .chain((1..=n).filter(|process_id| *process_id != self.process_id))
+            .map(|process_id| (process_id, shard_id))
+            .collect();
+        let (connect_ok, _) = process.discover(processes);
+        assert!(connect_ok);
+
+        let mut executor = <P::Executor as Executor>::new(
+            self.process_id,
+            shard_id,
+            self.config,
+        );
+
+        // submit commands
+        let time = SimTime::new();
+        for cmd in self.commands.iter() {
+            process.submit(None, cmd.clone(), &time);
+            self.drain(&mut process, &mut executor, results, o.as_deref_mut());
+        }
+        (process, executor, events)
+    }
+
+    // Rebuilds the process (and its executor) by handling all the inputs in
+    // `state`, without sending any message.
+    fn rebuild(
+        &self,
+        state: &ProcessState<P>,
+    ) -> (P, P::Executor, Vec<(P::PeriodicEvent, Duration)>, Results) {
+        let mut results = HashMap::new();
+        let (mut process, mut executor, events) =
+            self.start(&mut results, None);
+        let time = SimTime::new();
+        for input in state.inputs.iter() {
+            match input {
+                Input::Message(from, msg) => process.handle(
+                    *from,
+                    msg.from_shard_id,
+                    msg.msg.clone(),
+                    &time,
+                ),
+                Input::Event(index) => {
+                    let (event, _) = &events[*index];
+                    process.handle_event(event.clone(), &time)
+                }
+            }
+            self.drain(&mut process, &mut executor, &mut results, None);
+        }
+        (process, executor, events, results)
+    }
+
+    // Handles the messages to self and the new actions of the process, and
+    // executes the new execution info, until there's nothing else to handle.
+    // Messages to other processes are only sent if `o` is set.
+    fn drain(
+        &self,
+        process: &mut P,
+        executor: &mut P::Executor,
+        results: &mut Results,
+        mut o: Option<&mut Out<Self>>,
+    ) {
+        let time = SimTime::new();
+        let shard_id = actor_shard_id();
+        let mut to_self = VecDeque::new();
+        loop {
+            for action in process.to_processes_iter() {
+                match action {
+                    Action::ToSend { target, msg } => {
+                        // send by the order of process identifiers
+                        let mut target: Vec<_> = target.into_iter().collect();
+                        target.sort_unstable();
+                        for to in target {
+                            if to == self.process_id {
+                                to_self.push_back(msg.clone());
+                            } else if let Some(o) = o.as_deref_mut() {
+                                let msg =
+                                    ProtocolMessage::new(shard_id, msg.clone());
+                                o.send(process_id_to_actor(to), msg);
+                            }
+                        }
+                    }
+                    Action::ToForward { msg } => to_self.push_back(msg),
+                }
+            }
+
+            for info in process.to_executors_iter() {
+                executor.handle(info, &time);
+                let to_executors: Vec<_> =
+                    executor.to_executors_iter().collect();
+                for (_, info) in to_executors {
+                    executor.handle(info, &time);
+                }
+                while let Some(result) = executor.to_clients() {
+                    let key = (result.rifl, result.key);
+                    results.insert(key, result.partial_results);
+                }
+            }
+
+            match to_self.pop_front() {
+                Some(msg) => {
+                    process.handle(self.process_id, shard_id, msg, &time)
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+impl<P> Actor for ProtocolActor<P>
+where
+    P: Protocol,
+{
+    type Msg = ProtocolMessage<P>;
+    // periodic events are identified by their index in the list of periodic
+    // events returned by `Protocol::new`
+    type Timer = usize;
+    type State = ProcessState<P>;
+    type Storage = ();
+    type Random = ();
+
+    fn on_start(
+        &self,
+        id: Id,
+        _storage: &Option<Self::Storage>,
+        o: &mut Out<Self>,
+    ) -> Self::State {
+        assert_eq!(id, process_id_to_actor(self.process_id));
+        let mut results = HashMap::new();
+        let (_, _, events) = self.start(&mut results, Some(o));
+        for (index, (_, interval)) in events.into_iter().enumerate() {
+            o.set_timer(index, timer_range(interval));
+        }
+        ProcessState {
+            inputs: Vec::new(),
+            results,
+        }
+    }
+
+    fn on_msg(
+        &self,
+        _id: Id,
+        state: &mut Cow<'_, Self::State>,
+        src: Id,
+        msg: Self::Msg,
+        o: &mut Out<Self>,
+    ) {
+        let (mut process, mut executor, _, mut results) = self.rebuild(state);
+
+        // handle the new message
+        let from = actor_to_process_id(src);
+        let time = SimTime::new();
+        process.handle(from, msg.from_shard_id, msg.msg.clone(), &time);
+        self.drain(&mut process, &mut executor, &mut results, Some(o));
+
+        let state = state.to_mut();
+        state.inputs.push(Input::Message(from, msg));
+        state.results = results;
+    }
+
+    fn on_timeout(
+        &self,
+        _id: Id,
+        state: &mut Cow<'_, Self::State>,
+        timer: &Self::Timer,
+        o: &mut Out<Self>,
+    ) {
+        let (mut process, mut executor, events, mut results) =
+            self.rebuild(state);
+
+        // handle the periodic event
+        let (event, interval) = &events[*timer];
+        let time = SimTime::new();
+        process.handle_event(event.clone(), &time);
+        let mut event_out = Out::new();
+        self.drain(
+            &mut process,
+            &mut executor,
+            &mut results,
+            Some(&mut event_out),
+        );
+
+        // only keep the event if it had any effect
+        if !event_out.is_empty() || results != state.results {
+            o.append(&mut event_out);
+            let state = state.to_mut();
+            state.inputs.push(Input::Event(*timer));
+            state.results = results;
+        }
+        o.set_timer(*timer, timer_range(*interval));
+    }
+}
+
+fn timer_range(interval: Duration) -> Range<Duration> {
+    interval..interval * 2
+}
+
+fn process_id_to_actor(process_id: ProcessId) -> Id {
+    Id::from(process_id as usize - 1)
+}
+
+fn actor_to_process_id(id: Id) -> ProcessId {
+    (usize::from(id) + 1) as ProcessId
+}
+
+type Results = HashMap<(Rifl, Key), Vec<KVOpResult>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Input<M> {
+    Message(ProcessId, M),
+    Event(usize),
+}
+
+/// The state of a `ProtocolActor`: the messages and periodic events handled by
+/// the process (by the order they were handled) and the results of the
+/// commands it executed. States reached by handling the same inputs in a
+/// different order are never seen as the same state.
+#[derive(Clone)]
+pub struct ProcessState<P: Protocol> {
+    inputs: Vec<Input<ProtocolMessage<P>>>,
+    // results of the commands executed, per key
+    results: Results,
+}
+
+impl<P> ProcessState<P>
+where
+    P: Protocol,
+{
+    /// Returns the number of messages and periodic events handled by the
+    /// process.
+    pub fn handled(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the results of the commands executed, per key.
+    pub fn results(&self) -> &HashMap<(Rifl, Key), Vec<KVOpResult>> {
+        &self.results
+    }
+}
+
+impl<P: Protocol> fmt::Debug for ProcessState<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessState")
+            .field("inputs", &self.inputs)
+            .field("results", &self.results)
+            .finish()
+    }
+}
+
+// since processes are deterministic, the results are determined by the inputs
+impl<P: Protocol> PartialEq for ProcessState<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inputs == other.inputs
+    }
+}
+
+impl<P: Protocol> Hash for ProcessState<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inputs.hash(state);
+    }
+}
+
+/// A message sent by a `ProtocolActor`. Since protocol messages can't be
+/// hashed nor ordered, messages are compared by their serialization.
+#[derive(Clone)]
+pub struct ProtocolMessage<P: Protocol> {
+    from_shard_id: ShardId,
+    msg: P::Message,
+    bytes: Arc<Vec<u8>>,
+}
+
+impl<P> ProtocolMessage<P>
+where
+    P: Protocol,
+{
+    fn new(from_shard_id: ShardId, msg: P::Message) -> Self {
+        let bytes =
+            bincode::serialize(&msg).expect("message should be serializable");
+        Self {
+            from_shard_id,
+            msg,
+            bytes: Arc::new(bytes),
+        }
+    }
+}
+
+impl<P: Protocol> fmt::Debug for ProtocolMessage<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.msg)
+    }
+}
+
+impl<P: Protocol> PartialEq for ProtocolMessage<P> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.from_shard_id, &self.bytes) == (other.from_shard_id, &other.bytes)
+    }
+}
+
+impl<P: Protocol> Eq for ProtocolMessage<P> {}
+
+impl<P: Protocol> PartialOrd for ProtocolMessage<P> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: Protocol> Ord for ProtocolMessage<P> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.from_shard_id, &self.bytes)
+            .cmp(&(other.from_shard_id, &other.bytes))
+    }
+}
+
+impl<P: Protocol> Hash for ProtocolMessage<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.from_shard_id.hash(state);
+        self.bytes.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fantoch::id::RiflGen;
+    use fantoch::kvs::KVOp;
+    use fantoch::protocol::Basic;
+    use fantoch_ps::protocol::{
+        AtlasSequential, CaesarLocked, EPaxosSequential, FPaxos,
+        TempoSequential,
+    };
+    use stateright::{Checker, Model};
+
+    fn add(rifl: Rifl) -> Command {
+        Command::from(rifl, vec![(String::from("A"), KVOp::Add(1))])
+    }
+
+    #[test]
+    fn basic_single_command() {
+        let config = Config::new(3, 1);
+        let mut rifl_gen = RiflGen::new(1);
+        let commands = vec![(1, add(rifl_gen.next_id()))];
+
+        // with a single command, processes always agree
+        let checker = protocol_model::<Basic>(config, commands)
+            .checker()
+            .spawn_bfs()
+            .join();
+        checker.assert_properties();
+    }
+
+    #[test]
+    fn basic_conflicting_commands() {
+        let config = Config::new(3, 1);
+        let mut rifl_gen_a = RiflGen::new(1);
+        let mut rifl_gen_b = RiflGen::new(2);
+        let commands = vec![
+            (1, add(rifl_gen_a.next_id())),
+            (2, add(rifl_gen_b.next_id())),
+        ];
+
+        // `Basic` doesn't order conflicting commands, and so processes may
+        // execute them in different orders
+        let checker = protocol_model::<Basic>(config, commands)
+            .checker()
+            .spawn_bfs()
+            .join();
+        assert!(checker.discovery("agreement").is_some());
+        assert!(checker.discovery("stability").is_none());
+    }
+
+    #[test]
+    fn model_check_tempo_3_1_test() {
+        let mut config = Config::new(3, 1);
+        config.set_tempo_detached_send_interval(Duration::from_millis(100));
+        // with two commands, the detached votes (sent periodically) make the
+        // state space too large to be explored in a test
+        model_check_test::<TempoSequential>(config, 1);
+    }
+
+    #[test]
+    fn model_check_atlas_3_1_test() {
+        model_check_test::<AtlasSequential>(Config::new(3, 1), 2);
+    }
+
+    #[test]
+    fn model_check_epaxos_3_1_test() {
+        model_check_test::<EPaxosSequential>(Config::new(3, 1), 2);
+    }
+
+    #[test]
+    fn model_check_caesar_3_1_test() {
+        let mut config = Config::new(3, 1);
+        config.set_caesar_wait_condition(true);
+        model_check_test::<CaesarLocked>(config, 2);
+    }
+
+    #[test]
+    fn model_check_fpaxos_3_1_test() {
+        let leader = 1;
+        let mut config = Config::new(3, 1);
+        config.set_leader(leader);
+        model_check_test::<FPaxos>(config, 2);
+    }
+
+    fn model_check_test<P>(config: Config, command_count: usize)
+    where
+        P: Protocol + 'static,
+    {
+        // conflicting commands, each submitted by a different process, whose
+        // results depend on the order in which they're executed
+        let key = String::from("A");
+        let commands = (1..=command_count)
+            .map(|i| {
+                let mut rifl_gen = RiflGen::new(i as u64);
+                let ops = vec![(key.clone(), KVOp::Add(i as i64))];
+                let cmd = Command::from(rifl_gen.next_id(), ops);
+                (i as ProcessId, cmd)
+            })
+            .collect();
+
+        // explore all interleavings of messages
+        let checker = protocol_model::<P>(config, commands)
+            .checker()
+            .spawn_bfs()
+            .join();
+        println!("unique states: {}", checker.unique_state_count());
+        checker.assert_properties();
+    }
+}
@@ -3,7 +3,7 @@ use crate::db::exp_data::ExperimentData;
 use crate::Search;
 use color_eyre::eyre::{self, WrapErr};
 use color_eyre::Report;
-use fantoch::client::ClientData;
+use fantoch::client::{ClientData, PayloadSize};
 use fantoch::planet::Region;
 use fantoch::run::task::server::metrics_logger::ProcessMetrics;
 use fantoch_exp::{ExperimentConfig, ProcessType, SerializationFormat};
@@ -242,7 +242,9 @@ impl ResultsDB {
                 // filter out configurations with different payload_size (if
                 // set)
                 if let Some(payload_size) = search.payload_size {
-                    if exp_config.workload.payload_size() != payload_size {
+                    if exp_config.workload.payload_size()
+                        != PayloadSize::Constant(payload_size)
+                    {
                         return false;
                     }
                 }
@@ -0,0 +1,105 @@
+use criterion::{
+    criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion,
+};
+use fantoch::command::Command;
+use fantoch::config::Config;
+use fantoch::executor::Executor;
+use fantoch::id::{ClientId, Dot, ProcessId, Rifl, ShardId};
+use fantoch::kvs::{KVOp, Value};
+use fantoch::time::RunTime;
+use fantoch::HashSet;
+use fantoch_ps::executor::{GraphExecutionInfo, GraphExecutor};
+use fantoch_ps::protocol::common::graph::Dependency;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+
+const PROCESS_ID: ProcessId = 1;
+const SHARD_ID: ShardId = 0;
+const N: usize = 5;
+const F: usize = 1;
+
+// Generates the commands of a high-conflict workload (all commands access the
+// same key), as delivered to an executor in reverse order: this way, most
+// commands are pending until the first ones arrive, at which point SCCs are
+// searched in a large pending graph.
+fn reverse_order_adds(events_per_process: u64) -> Vec<GraphExecutionInfo> {
+    // use a fixed seed so that all runs see the same graph
+    let mut rng = StdRng::seed_from_u64(0);
+    let processes = 1..=N as ProcessId;
+    let shards: BTreeSet<_> = std::iter::once(SHARD_ID).collect();
+    let dep = |dot| Dependency {
+        dot,
+        shards: Some(shards.clone()),
+    };
+
+    let mut infos = Vec::new();
+    for event in 1..=events_per_process {
+        for process_id in processes.clone() {
+            let dot = Dot::new(process_id, event);
+            // each command depends on the previous command by the same
+            // process and on a command by each other process with a close
+            // sequence number, which creates SCCs of different sizes
+            let mut deps = HashSet::new();
+            if event > 1 {
+                deps.insert(dep(Dot::new(process_id, event - 1)));
+            }
+            for other in processes.clone().filter(|other| *other != process_id)
+            {
+                let other_event = event + rng.gen_range(0..=2) - 1;
+                if other_event >= 1 && other_event <= events_per_process {
+                    deps.insert(dep(Dot::new(other, other_event)));
+                }
+            }
+
+            let rifl = Rifl::new(process_id as ClientId, event);
+            let key = String::from("CONF");
+            let mut cmd =
+                Command::from(rifl, vec![(key, KVOp::Put(Value::new()))]);
+            cmd.set_logical_time(event);
+            infos.push(GraphExecutionInfo::add(dot, cmd, deps));
+        }
+    }
+    infos.reverse();
+    infos
+}
+
+fn execute(
+    parallel_scc_threshold: Option<usize>,
+    infos: Vec<GraphExecutionInfo>,
+) {
+    let mut config = Config::new(N, F);
+    config.set_executor_parallel_scc_threshold(parallel_scc_threshold);
+    let mut executor = GraphExecutor::new(PROCESS_ID, SHARD_ID, config);
+    let time = RunTime;
+    for info in infos {
+        executor.handle(info, &time);
+        executor.to_clients_iter().for_each(drop);
+    }
+}
+
+fn scc_finders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("graph_executor");
+    for events_per_process in [100, 1000] {
+        let infos = reverse_order_adds(events_per_process);
+        let commands = infos.len();
+        for (finder, threshold) in [("sequential", None), ("parallel", Some(1))]
+        {
+            group.bench_with_input(
+                BenchmarkId::new(finder, commands),
+                &infos,
+                |b, infos| {
+                    b.iter_batched(
+                        || infos.clone(),
+                        |infos| execute(threshold, infos),
+                        BatchSize::LargeInput,
+                    )
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scc_finders);
+criterion_main!(benches);
@@ -0,0 +1,268 @@
+mod common;
+
+use clap::{Arg, Command};
+use color_eyre::eyre::{self, eyre, Report, WrapErr};
+use fantoch::command::{Command as KVCommand, CommandResult, ShardMap};
+use fantoch::id::{ClientId, RiflGen, ShardId};
+use fantoch::kvs::{self, KVOp, KVOpResult, Key, Value};
+use fantoch::run::task::client::Session;
+use fantoch::run::wire::ServerToClient;
+use fantoch::HashMap;
+use std::io::Write;
+use std::iter::FromIterator;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+// defaults are kept as strings so that they can be shown in `--help`
+const DEFAULT_ID: &str = "0";
+
+const CONNECT_RETRIES: usize = 100;
+
+// commands rejected by an overloaded process are submitted again after this
+// delay
+const OVERLOADED_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+const HELP: &str = "\
+commands:
+  get KEY
+  put KEY VALUE
+  cas KEY EXPECTED NEW
+  delete KEY
+  add KEY DELTA
+  help
+  quit";
+
+type CliArgs = (ClientId, Vec<String>, bool, usize, Option<usize>);
+
+fn main() -> Result<(), Report> {
+    let (args, _guard) = parse_args();
+    let (id, addresses, tcp_nodelay, stack_size, cpus) = args;
+
+    common::tokio_runtime(stack_size, cpus).block_on(repl(
+        id,
+        addresses,
+        tcp_nodelay,
+    ))
+}
+
+/// Connects to a process of each shard, and then submits each operation typed
+/// by the user (as a single-key command) and shows its result and latency.
+async fn repl(
+    client_id: ClientId,
+    addresses: Vec<String>,
+    tcp_nodelay: bool,
+) -> Result<(), Report> {
+    // connect to the process of each shard
+    let mut shard_to_session = HashMap::new();
+    for address in addresses {
+        let session = Session::connect(
+            address.clone(),
+            vec![client_id],
+            tcp_nodelay,
+            CONNECT_RETRIES,
+        )
+        .await
+        .wrap_err_with(|| format!("couldn't connect to {}", address))?;
+        let shard_id = session.shard_id();
+        println!(
+            "connected to process {} of shard {} at {}",
+            session.process_id(),
+            shard_id,
+            address
+        );
+        if shard_to_session.insert(shard_id, session).is_some() {
+            eyre::bail!("more than one address of shard {}", shard_id);
+        }
+    }
+    let shard_count = shard_to_session.len();
+    if (0..shard_count as ShardId).any(|id| !shard_to_session.contains_key(&id))
+    {
+        eyre::bail!("addresses should include a process of each shard");
+    }
+    let shard_map = ShardMap::new(shard_count);
+
+    let mut rifl_gen = RiflGen::new(client_id);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    prompt();
+    while let Some(line) = lines.next_line().await? {
+        match line.trim() {
+            "" => {}
+            "help" => println!("{}", HELP),
+            "quit" | "exit" => break,
+            line => match parse_op(line) {
+                Ok((key, op)) => {
+                    let is_add = matches!(op, KVOp::Add(_));
+                    let shard_id = shard_map.shard_id(&key);
+                    let session = shard_to_session
+                        .get_mut(&shard_id)
+                        .expect("there should be a session for each shard");
+                    let ops = HashMap::from_iter(vec![(key.clone(), vec![op])]);
+                    let cmd = KVCommand::new(
+                        rifl_gen.next_id(),
+                        HashMap::from_iter(vec![(shard_id, ops)]),
+                    );
+
+                    let start = Instant::now();
+                    let result = submit(session, cmd).await?;
+                    let latency = start.elapsed();
+                    let op_result = result
+                        .results()
+                        .get(&key)
+                        .and_then(|results| results.first())
+                        .expect("there should be a result for the key");
+                    println!(
+                        "{} ({:?})",
+                        show_result(op_result, is_add),
+                        latency
+                    );
+                }
+                Err(e) => println!("{}", e),
+            },
+        }
+        prompt();
+    }
+    Ok(())
+}
+
+// Submits `cmd` and waits for its result. If the process is overloaded, the
+// command is submitted again after some delay.
+async fn submit(
+    session: &mut Session,
+    cmd: KVCommand,
+) -> Result<CommandResult, Report> {
+    let rifl = cmd.rifl();
+    session.submit(cmd.clone()).await?;
+    loop {
+        match session.recv().await {
+            Some(ServerToClient::Result(result)) if result.rifl() == rifl => {
+                return Ok(result);
+            }
+            Some(ServerToClient::Overloaded(overloaded))
+                if overloaded == rifl =>
+            {
+                println!("process overloaded: submitting again");
+                tokio::time::sleep(OVERLOADED_RETRY_DELAY).await;
+                session.submit(cmd.clone()).await?;
+            }
+            Some(ServerToClient::Error(e)) => {
+                return Err(eyre!("error from process: {}", e));
+            }
+            Some(msg) => {
+                println!("ignoring unexpected message: {:?}", msg);
+            }
+            None => {
+                return Err(eyre!(
+                    "connection to process {} closed",
+                    session.process_id()
+                ));
+            }
+        }
+    }
+}
+
+fn prompt() {
+    print!("> ");
+    // a prompt that's not shown is harmless
+    let _ = std::io::stdout().flush();
+}
+
+// Parses an operation typed by the user (see `HELP`).
+fn parse_op(line: &str) -> Result<(Key, KVOp), Report> {
+    let parts: Vec<_> = line.split_whitespace().collect();
+    let (key, op) = match parts.as_slice() {
+        ["get", key] => (key, KVOp::Get),
+        ["put", key, value] => (key, KVOp::Put(value_from(value))),
+        ["cas", key, expected, new] => (
+            key,
+            KVOp::Cas {
+                expected: value_from(expected),
+                new: value_from(new),
+            },
+        ),
+        ["delete", key] => (key, KVOp::Delete),
+        ["add", key, delta] => {
+            let delta = delta
+                .parse::<i64>()
+                .wrap_err_with(|| format!("invalid delta {:?}", delta))?;
+            (key, KVOp::Add(delta))
+        }
+        _ => {
+            return Err(eyre!(
+                "invalid command {:?} (type 'help' to see the commands)",
+                line
+            ))
+        }
+    };
+    Ok((key.to_string(), op))
+}
+
+fn value_from(value: &str) -> Value {
+    Value::from(value.to_string())
+}
+
+fn show_result(result: &KVOpResult, is_add: bool) -> String {
+    match result {
+        KVOpResult::Value(Some(value)) if is_add => {
+            kvs::value_to_counter(value)
+                .map(|counter| counter.to_string())
+                .unwrap_or_else(|| format!("{:?}", value))
+        }
+        KVOpResult::Value(Some(value)) => {
+            format!("{:?}", String::from_utf8_lossy(value))
+        }
+        KVOpResult::Value(None) => String::from("(nil)"),
+        KVOpResult::Error(e) => format!("error: {}", e),
+        result => format!("{:?}", result),
+    }
+}
+
+fn parse_args() -> (CliArgs, tracing_appender::non_blocking::WorkerGuard) {
+    let command = Command::new("fantoch-cli")
+        .version("0.1")
+        .author("Vitor Enes <vitorenesduarte@gmail.com>")
+        .about("Runs an interactive client that submits the operations typed by the user to a running deployment.")
+        .arg(
+            Arg::new("id")
+                .long("id")
+                .value_name("ID")
+                .help("client identifier; it should be different from the identifiers of any other client connected to the same processes")
+                .default_value(DEFAULT_ID)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("addresses")
+                .long("addresses")
+                .value_name("ADDRESSES")
+                .help("comma-separated list of addresses to connect to (in the form IP:PORT e.g. 127.0.0.1:3000, or unix://PATH to connect through a unix domain socket), one of a process of each shard")
+                .required(true)
+                .takes_value(true),
+        );
+    let matches = common::get_matches(command);
+
+    let tracing_directives = None;
+    let guard = fantoch::util::init_tracing_subscriber(
+        matches.value_of("log_file"),
+        tracing_directives,
+        None,
+    );
+
+    // parse arguments
+    let id = matches
+        .value_of("id")
+        .unwrap_or(DEFAULT_ID)
+        .parse::<ClientId>()
+        .expect("id should be a number");
+    let addresses = matches
+        .value_of("addresses")
+        .expect("addresses should be set")
+        .split(common::protocol::LIST_SEP)
+        .map(|address| address.to_string())
+        .collect();
+    let tcp_nodelay =
+        common::parse_tcp_nodelay(matches.value_of("tcp_nodelay"));
+    let stack_size = common::parse_stack_size(matches.value_of("stack_size"));
+    let cpus = common::parse_cpus(matches.value_of("cpus"));
+
+    let args = (id, addresses, tcp_nodelay, stack_size, cpus);
+    (args, guard)
+}
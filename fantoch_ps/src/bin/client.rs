@@ -1,36 +1,59 @@
 mod common;
 
-use clap::{Command, Arg};
+use clap::{Arg, Command};
 use color_eyre::Report;
-use fantoch::client::{KeyGen, Workload};
+use fantoch::client::{KeyGen, LoadRamp, OpMix, PayloadSize, Workload};
 use fantoch::id::ClientId;
 use fantoch::info;
+use fantoch::run::ClientOptions;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 const RANGE_SEP: &str = "-";
-const DEFAULT_KEYS_PER_COMMAND: usize = 1;
-const DEFAULT_SHARD_COUNT: usize = 1;
-const DEFAULT_KEY_GEN: KeyGen = KeyGen::ConflictPool {
-    conflict_rate: 100,
-    pool_size: 1,
-};
-const DEFAULT_COMMANDS_PER_CLIENT: usize = 1000;
-const DEFAULT_READ_ONLY_PERCENTAGE: usize = 0;
-const DEFAULT_PAYLOAD_SIZE: usize = 100;
-const DEFAULT_BATCH_MAX_SIZE: usize = 1;
-const DEFAULT_BATCH_MAX_DELAY: Duration = Duration::from_millis(5);
+// defaults are kept as strings so that they can be shown in `--help`
+const DEFAULT_KEYS_PER_COMMAND: &str = "1";
+const DEFAULT_SHARD_COUNT: &str = "1";
+const DEFAULT_KEY_GEN: &str = "conflict_pool,100,1";
+const DEFAULT_COMMANDS_PER_CLIENT: &str = "1000";
+const DEFAULT_READ_ONLY_PERCENTAGE: &str = "0";
+const DEFAULT_RMW_PERCENTAGE: &str = "0";
+const DEFAULT_EVENTUAL_READ_PERCENTAGE: &str = "0";
+const DEFAULT_READ_YOUR_WRITES: &str = "false";
+const DEFAULT_METADATA_TABLE_KEYS: &str = "0";
+const DEFAULT_METADATA_TABLE_PERCENTAGE: &str = "0";
+const DEFAULT_PAYLOAD_SIZE: &str = "100"; // 100 bytes
+const DEFAULT_WARMUP_COMMANDS: &str = "0";
+const DEFAULT_WARMUP: &str = "0"; // 0ms
+const DEFAULT_LATENCY_PER_OP_TYPE: &str = "false";
+const DEFAULT_BATCH_MAX_SIZE: &str = "1";
+const DEFAULT_BATCH_MAX_DELAY: &str = "5"; // 5ms
+const DEFAULT_SUBMIT_BATCH_MAX_SIZE: &str = "1";
+const DEFAULT_SUBMIT_REDUNDANCY: &str = "1";
+const DEFAULT_CONNECTIONS: &str = "32";
+const DEFAULT_CMD_RETRIES: &str = "0";
 
 type ClientArgs = (
     Vec<ClientId>,
     Vec<String>,
+    usize,
+    usize,
     Option<Duration>,
+    Option<LoadRamp>,
     Workload,
     usize,
     Duration,
+    usize,
+    usize,
+    Option<Duration>,
+    Option<Duration>,
+    usize,
     bool,
     usize,
     Option<usize>,
     Option<String>,
+    Option<String>,
+    BTreeMap<String, String>,
+    Option<String>,
     usize,
     Option<usize>,
 );
@@ -40,34 +63,55 @@ fn main() -> Result<(), Report> {
     let (
         ids,
         addresses,
+        connections,
+        client_tasks,
         interval,
+        load_ramp,
         workload,
         batch_max_size,
         batch_max_delay,
+        submit_batch_max_size,
+        submit_redundancy,
+        failover_timeout,
+        cmd_timeout,
+        cmd_retries,
         tcp_nodelay,
         channel_buffer_size,
         status_frequency,
         metrics_file,
+        metrics_report_file,
+        metrics_tags,
+        record_trace,
         stack_size,
         cpus,
     ) = args;
 
-    common::tokio_runtime(stack_size, cpus).block_on(fantoch::run::client(
-        ids,
-        addresses,
+    let options = ClientOptions {
+        connections,
+        client_tasks,
         interval,
-        workload,
+        load_ramp,
         batch_max_size,
         batch_max_delay,
+        submit_batch_max_size,
+        submit_redundancy,
+        failover_timeout,
+        cmd_timeout,
+        cmd_retries,
         tcp_nodelay,
         channel_buffer_size,
         status_frequency,
         metrics_file,
-    ))
+        metrics_report_file,
+        metrics_tags,
+        trace_file: record_trace,
+    };
+    common::tokio_runtime(stack_size, cpus)
+        .block_on(fantoch::run::client(ids, addresses, workload, options))
 }
 
 fn parse_args() -> (ClientArgs, tracing_appender::non_blocking::WorkerGuard) {
-    let matches = Command::new("client")
+    let command = Command::new("client")
         .version("0.1")
         .author("Vitor Enes <vitorenesduarte@gmail.com>")
         .about("Runs a client that will connect to some instance of a protocol.")
@@ -83,10 +127,25 @@ fn parse_args() -> (ClientArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("addresses")
                 .long("addresses")
                 .value_name("ADDRESSES")
-                .help("comma-separated list of addresses to connect to (in the form IP:PORT e.g. 127.0.0.1:3000)")
+                .help("comma-separated list of addresses to connect to (in the form IP:PORT e.g. 127.0.0.1:3000, or unix://PATH to connect through a unix domain socket), sorted by preference; clients use the first process of each shard, and fail over to the next processes of that shard if it fails")
                 .required(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("connections")
+                .long("connections")
+                .value_name("CONNECTIONS")
+                .help("maximum number of connections to each address; clients are spread across these connections, each client being a session of one of them")
+                .default_value(DEFAULT_CONNECTIONS)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("client_tasks")
+                .long("client_tasks")
+                .value_name("CLIENT_TASKS")
+                .help("number of tasks driving clients (at most the number of connections), each task driving its clients over its share of the connections; if not set, there's a task per connection")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("interval")
                 .long("interval")
@@ -94,74 +153,199 @@ fn parse_args() -> (ClientArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .help("if this value is set, an open-loop client will be created (by default is closed-loop) and the value set is used as the interval (in milliseconds) between submitted commands")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("load_ramp")
+                .long("load_ramp")
+                .value_name("LOAD_RAMP")
+                .help("if this value is set, open-loop clients will be created whose load (in commands per second, across all clients) follows a ramp; the ramp is given as START,STEP,STEP_DURATION[,MAX], e.g. '1000,500,10000,5000' starts at 1000 ops/s and increases by 500 ops/s every 10000 milliseconds up to 5000 ops/s; overrides --interval")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("shard_count")
                 .long("shard_count")
                 .value_name("SHARD_COUNT")
-                .help("number of shards accessed in the system; default: 1")
+                .help("number of shards accessed in the system")
+                .default_value(DEFAULT_SHARD_COUNT)
                 .takes_value(true),
         )
         .arg(
             Arg::new("key_gen")
                 .long("key_gen")
                 .value_name("KEY_GEN")
-                .help("representation of a key generator; possible values 'conflict_pool,100,1' where 100 is the conflict rate and 1 the pool size, or 'zipf,1.3,10000' where 1.3 is the zipf coefficient (which should be non-zero) and 10000 the number of keys (per shard) in the distribution; default: 'conflict_rate,100,1'")
+                .help("representation of a key generator; possible values 'conflict_pool,100,1' where 100 is the conflict rate and 1 the pool size, or 'zipf,1.3,10000' where 1.3 is the zipf coefficient (which should be non-zero) and 10000 the number of keys (per shard) in the distribution")
+                .default_value(DEFAULT_KEY_GEN)
                 .takes_value(true),
         )
         .arg(
             Arg::new("keys_per_command")
                 .long("keys_per_command")
                 .value_name("KEYS_PER_COMMAND")
-                .help("number of keys accessed by each command to be issued by each client; default: 1")
+                .help("number of keys accessed by each command to be issued by each client")
+                .default_value(DEFAULT_KEYS_PER_COMMAND)
                 .takes_value(true),
         )
         .arg(
             Arg::new("commands_per_client")
                 .long("commands_per_client")
                 .value_name("COMMANDS_PER_CLIENT")
-                .help("number of commands to be issued by each client; default: 1000")
+                .help("number of commands to be issued by each client")
+                .default_value(DEFAULT_COMMANDS_PER_CLIENT)
                 .takes_value(true),
         )
         .arg(
             Arg::new("read_only_percentage")
                 .long("read_only_percentage")
                 .value_name("READ_ONLY_PERCENTAGE")
-                .help("percentage of read-only commands; default: 0")
+                .help("percentage of read-only commands")
+                .default_value(DEFAULT_READ_ONLY_PERCENTAGE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("rmw_percentage")
+                .long("rmw_percentage")
+                .value_name("RMW_PERCENTAGE")
+                .help("percentage of (non read-only) commands that are read-modify-writes")
+                .default_value(DEFAULT_RMW_PERCENTAGE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("op_mix")
+                .long("op_mix")
+                .value_name("OP_MIX")
+                .help("percentage of commands with each type of operation, represented as GET,PUT,CAS,SCAN,DELETE (e.g. '90,10,0,0,0'); these should add up to 100, and can't be combined with the read-only and read-modify-write percentages; by default commands are puts, and only the read-only and read-modify-write percentages apply")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("eventual_read_percentage")
+                .long("eventual_read_percentage")
+                .value_name("EVENTUAL_READ_PERCENTAGE")
+                .help("percentage of read-only commands with eventual consistency (answered from the local store)")
+                .default_value(DEFAULT_EVENTUAL_READ_PERCENTAGE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("read_your_writes")
+                .long("read_your_writes")
+                .value_name("READ_YOUR_WRITES")
+                .help("boolean indicating whether read-only commands with eventual consistency should observe the previous writes of the client on the keys read (waiting for them if needed); this can't be combined with batching")
+                .default_value(DEFAULT_READ_YOUR_WRITES)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("metadata_table_keys")
+                .long("metadata_table_keys")
+                .value_name("METADATA_TABLE_KEYS")
+                .help("number of keys in the metadata table")
+                .default_value(DEFAULT_METADATA_TABLE_KEYS)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("metadata_table_percentage")
+                .long("metadata_table_percentage")
+                .value_name("METADATA_TABLE_PERCENTAGE")
+                .help("percentage of keys accessed in the metadata table (if non-zero, keys are namespaced by a 'metadata' or a 'data' table)")
+                .default_value(DEFAULT_METADATA_TABLE_PERCENTAGE)
                 .takes_value(true),
         )
         .arg(
             Arg::new("payload_size")
                 .long("payload_size")
                 .value_name("PAYLOAD_SIZE")
-                .help("size of the command payload; default: 100 (bytes)")
+                .help("size of the command payload (in bytes); either a constant (e.g. '100'), 'uniform,10,1000' where sizes are uniformly distributed between 10 and 1000 (inclusive), or 'lognormal,5,1' where the natural logarithm of sizes is normally distributed with mean 5 and standard deviation 1")
+                .default_value(DEFAULT_PAYLOAD_SIZE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("warmup_commands")
+                .long("warmup_commands")
+                .value_name("WARMUP_COMMANDS")
+                .help("number of commands (the first ones issued by each client) excluded from the metrics")
+                .default_value(DEFAULT_WARMUP_COMMANDS)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("warmup")
+                .long("warmup")
+                .value_name("WARMUP")
+                .help("number of milliseconds (since each client issued its first command) during which started commands are excluded from the metrics")
+                .default_value(DEFAULT_WARMUP)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("measurement_window")
+                .long("measurement_window")
+                .value_name("MEASUREMENT_WINDOW")
+                .help("if set, only commands ending within this number of milliseconds after the warmup are included in the metrics; by default, all commands after the warmup are included")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("latency_per_op_type")
+                .long("latency_per_op_type")
+                .value_name("LATENCY_PER_OP_TYPE")
+                .help("boolean indicating whether latencies should also be recorded per type of command (e.g. get, put), and per path taken (fast or slow) if the protocol reports it")
+                .default_value(DEFAULT_LATENCY_PER_OP_TYPE)
                 .takes_value(true),
         )
         .arg(
             Arg::new("batch_max_size")
                 .long("batch_max_size")
                 .value_name("BATCH_MAX_SIZE")
-                .help("max size of the batch; default: 1 (i.e., no batching)")
+                .help("max size of the batch (1 means no batching)")
+                .default_value(DEFAULT_BATCH_MAX_SIZE)
                 .takes_value(true),
         )
         .arg(
             Arg::new("batch_max_delay")
                 .long("batch_max_delay")
                 .value_name("BATCH_MAX_DELAY")
-                .help("max delay of a batch; default: 5 (milliseconds)")
+                .help("max delay of a batch (in milliseconds)")
+                .default_value(DEFAULT_BATCH_MAX_DELAY)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("submit_batch_max_size")
+                .long("submit_batch_max_size")
+                .value_name("SUBMIT_BATCH_MAX_SIZE")
+                .help("max number of commands sent to a process in the same message (1 means one command per message); unlike batch_max_size, each command is still executed on its own, and commands are never delayed to be sent together")
+                .default_value(DEFAULT_SUBMIT_BATCH_MAX_SIZE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("submit_redundancy")
+                .long("submit_redundancy")
+                .value_name("SUBMIT_REDUNDANCY")
+                .help("number of processes of each shard (the first ones in addresses, e.g. the closest f + 1) to which each command is submitted; clients take the first result of each command, and processes should deduplicate commands (see executor_dedup_window) as these are executed more than once")
+                .default_value(DEFAULT_SUBMIT_REDUNDANCY)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("failover_timeout")
+                .long("failover_timeout")
+                .value_name("FAILOVER_TIMEOUT")
+                .help("number of milliseconds without a message from a process (while some command is in flight) after which the process is considered failed, and clients fail over to another process of its shard; by default processes are only considered failed once their connection is closed")
                 .takes_value(true),
         )
         .arg(
-            Arg::new("tcp_nodelay")
-                .long("tcp_nodelay")
-                .value_name("TCP_NODELAY")
-                .help("set TCP_NODELAY; default: true")
+            Arg::new("cmd_timeout")
+                .long("cmd_timeout")
+                .value_name("CMD_TIMEOUT")
+                .help("number of milliseconds after which a command without a result times out; by default commands never time out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("cmd_retries")
+                .long("cmd_retries")
+                .value_name("CMD_RETRIES")
+                .help("number of times a command that timed out is submitted again before the client gives up on it (and moves on to the next command)")
+                .default_value(DEFAULT_CMD_RETRIES)
                 .takes_value(true),
         )
         .arg(
             Arg::new("channel_buffer_size")
                 .long("channel_buffer_size")
                 .value_name("CHANNEL_BUFFER_SIZE")
-                .help("set the size of the buffer in each channel used for task communication; default: 10000")
+                .help("set the size of the buffer in each channel used for task communication")
+                .default_value(common::DEFAULT_CHANNEL_BUFFER_SIZE)
                 .takes_value(true),
         )
         .arg(
@@ -179,52 +363,83 @@ fn parse_args() -> (ClientArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .takes_value(true),
         )
         .arg(
-            Arg::new("stack_size")
-                .long("stack_size")
-                .value_name("STACK_SIZE")
-                .help("stack size (in bytes) of each tokio thread; default: 2 * 1024 * 1024 (bytes)")
+            Arg::new("metrics_report_file")
+                .long("metrics_report_file")
+                .value_name("METRICS_REPORT_FILE")
+                .help("file in which a summary of the metrics (latency percentiles, throughput over time, error and timeout counts) is written to as JSON, once all clients end")
                 .takes_value(true),
         )
         .arg(
-            Arg::new("cpus")
-                .long("cpus")
-                .value_name("CPUS")
-                .help("number of cpus to be used by tokio; by default all available cpus are used")
+            Arg::new("metrics_tags")
+                .long("metrics_tags")
+                .value_name("METRICS_TAGS")
+                .help("comma-separated list of KEY=VALUE tags (e.g. region=eu-west-1) that identify these clients in the metrics report")
                 .takes_value(true),
         )
         .arg(
-            Arg::new("log_file")
-                .long("log_file")
-                .value_name("LOG_FILE")
-                .help("file to which logs will be written to; if not set, logs will be redirect to the stdout")
+            Arg::new("trace")
+                .long("trace")
+                .value_name("TRACE")
+                .help("file with a trace (e.g. recorded with --record_trace) to be replayed: each client issues the commands issued by the client with the same identifier in the trace, instead of generating them; if set, the options that configure the generation of commands (e.g. --key_gen) are ignored")
                 .takes_value(true),
         )
-        .get_matches();
+        .arg(
+            Arg::new("record_trace")
+                .long("record_trace")
+                .value_name("RECORD_TRACE")
+                .help("file in which the commands issued by all clients are recorded, so that they can be replayed with --trace; by default commands are not recorded")
+                .takes_value(true),
+        );
+    let matches = common::get_matches(command);
 
     let tracing_directives = None;
     let guard = fantoch::util::init_tracing_subscriber(
         matches.value_of("log_file"),
         tracing_directives,
+        None,
     );
 
     // parse arguments
     let ids = parse_id_range(matches.value_of("ids"));
     let addresses = parse_addresses(matches.value_of("addresses"));
+    let connections = parse_connections(matches.value_of("connections"));
+    let client_tasks =
+        parse_client_tasks(matches.value_of("client_tasks"), connections);
     let interval = parse_interval(matches.value_of("interval"));
+    let load_ramp = parse_load_ramp(matches.value_of("load_ramp"));
     let workload = parse_workload(
         matches.value_of("shard_count"),
         matches.value_of("key_gen"),
         matches.value_of("keys_per_command"),
         matches.value_of("commands_per_client"),
         matches.value_of("read_only_percentage"),
+        matches.value_of("rmw_percentage"),
+        matches.value_of("op_mix"),
+        matches.value_of("eventual_read_percentage"),
+        matches.value_of("read_your_writes"),
+        matches.value_of("metadata_table_keys"),
+        matches.value_of("metadata_table_percentage"),
         matches.value_of("payload_size"),
+        matches.value_of("warmup_commands"),
+        matches.value_of("warmup"),
+        matches.value_of("measurement_window"),
+        matches.value_of("latency_per_op_type"),
+        matches.value_of("trace"),
     );
 
     let batch_max_size =
         parse_batch_max_size(matches.value_of("batch_max_size"));
     let batch_max_delay =
         parse_batch_max_delay(matches.value_of("batch_max_delay"));
+    let submit_batch_max_size =
+        parse_submit_batch_max_size(matches.value_of("submit_batch_max_size"));
+    let submit_redundancy =
+        parse_submit_redundancy(matches.value_of("submit_redundancy"));
 
+    let failover_timeout =
+        parse_failover_timeout(matches.value_of("failover_timeout"));
+    let cmd_timeout = parse_cmd_timeout(matches.value_of("cmd_timeout"));
+    let cmd_retries = parse_cmd_retries(matches.value_of("cmd_retries"));
     let tcp_nodelay =
         common::parse_tcp_nodelay(matches.value_of("tcp_nodelay"));
     let channel_buffer_size = common::parse_channel_buffer_size(
@@ -233,32 +448,58 @@ fn parse_args() -> (ClientArgs, tracing_appender::non_blocking::WorkerGuard) {
     let status_frequency =
         parse_status_frequency(matches.value_of("status_frequency"));
     let metrics_file = parse_metrics_file(matches.value_of("metrics_file"));
+    let metrics_report_file =
+        parse_metrics_report_file(matches.value_of("metrics_report_file"));
+    let metrics_tags = parse_metrics_tags(matches.value_of("metrics_tags"));
+    let record_trace = parse_record_trace(matches.value_of("record_trace"));
     let stack_size = common::parse_stack_size(matches.value_of("stack_size"));
     let cpus = common::parse_cpus(matches.value_of("cpus"));
 
     info!("ids: {}-{}", ids.first().unwrap(), ids.last().unwrap());
     info!("client number: {}", ids.len());
     info!("addresses: {:?}", addresses);
+    info!("connections: {:?}", connections);
+    info!("client tasks: {:?}", client_tasks);
+    info!("load ramp: {:?}", load_ramp);
     info!("workload: {:?}", workload);
     info!("batch_max_size: {:?}", batch_max_size);
     info!("batch_max_delay: {:?}", batch_max_delay);
+    info!("submit_batch_max_size: {:?}", submit_batch_max_size);
+    info!("submit_redundancy: {:?}", submit_redundancy);
+    info!("failover_timeout: {:?}", failover_timeout);
+    info!("cmd_timeout: {:?}", cmd_timeout);
+    info!("cmd_retries: {:?}", cmd_retries);
     info!("tcp_nodelay: {:?}", tcp_nodelay);
     info!("channel buffer size: {:?}", channel_buffer_size);
     info!("status frequency: {:?}", status_frequency);
     info!("metrics file: {:?}", metrics_file);
+    info!("metrics report file: {:?}", metrics_report_file);
+    info!("metrics tags: {:?}", metrics_tags);
+    info!("record trace: {:?}", record_trace);
     info!("stack size: {:?}", stack_size);
 
     let args = (
         ids,
         addresses,
+        connections,
+        client_tasks,
         interval,
+        load_ramp,
         workload,
         batch_max_size,
         batch_max_delay,
+        submit_batch_max_size,
+        submit_redundancy,
+        failover_timeout,
+        cmd_timeout,
+        cmd_retries,
         tcp_nodelay,
         channel_buffer_size,
         status_frequency,
         metrics_file,
+        metrics_report_file,
+        metrics_tags,
+        record_trace,
         stack_size,
         cpus,
     );
@@ -304,134 +545,364 @@ fn parse_interval(interval: Option<&str>) -> Option<Duration> {
     parse_millis_duration(interval)
 }
 
+fn parse_load_ramp(load_ramp: Option<&str>) -> Option<LoadRamp> {
+    load_ramp.map(|load_ramp| {
+        let values: Vec<_> = load_ramp
+            .split(',')
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .expect("load ramp values should be numbers")
+            })
+            .collect();
+        match values[..] {
+            [start, step, step_duration] => {
+                LoadRamp::new(start, step, Duration::from_millis(step_duration))
+            }
+            [start, step, step_duration, max] => {
+                let mut load_ramp = LoadRamp::new(
+                    start,
+                    step,
+                    Duration::from_millis(step_duration),
+                );
+                load_ramp.set_max(max);
+                load_ramp
+            }
+            _ => panic!("invalid specification of load ramp: {:?}", load_ramp),
+        }
+    })
+}
+
 fn parse_workload(
     shard_count: Option<&str>,
     key_gen: Option<&str>,
     keys_per_command: Option<&str>,
     commands_per_client: Option<&str>,
     read_only_percentage: Option<&str>,
+    rmw_percentage: Option<&str>,
+    op_mix: Option<&str>,
+    eventual_read_percentage: Option<&str>,
+    read_your_writes: Option<&str>,
+    metadata_table_keys: Option<&str>,
+    metadata_table_percentage: Option<&str>,
     payload_size: Option<&str>,
+    warmup_commands: Option<&str>,
+    warmup: Option<&str>,
+    measurement_window: Option<&str>,
+    latency_per_op_type: Option<&str>,
+    trace: Option<&str>,
 ) -> Workload {
     let shard_count = parse_shard_count(shard_count);
     let key_gen = parse_key_gen(key_gen);
     let keys_per_command = parse_keys_per_command(keys_per_command);
     let commands_per_client = parse_commands_per_client(commands_per_client);
     let read_only_percentage = parse_read_only_percentage(read_only_percentage);
+    let rmw_percentage = parse_rmw_percentage(rmw_percentage);
+    let op_mix = parse_op_mix(op_mix);
+    let eventual_read_percentage =
+        parse_eventual_read_percentage(eventual_read_percentage);
+    let read_your_writes = parse_read_your_writes(read_your_writes);
+    let metadata_table_keys = parse_metadata_table_keys(metadata_table_keys);
+    let metadata_table_percentage =
+        parse_metadata_table_percentage(metadata_table_percentage);
     let payload_size = parse_payload_size(payload_size);
-    let mut workload = Workload::new(
-        shard_count,
-        key_gen,
-        keys_per_command,
-        commands_per_client,
-        payload_size,
-    );
+    let warmup_commands = parse_warmup_commands(warmup_commands);
+    let warmup = parse_warmup(warmup);
+    let measurement_window = parse_millis_duration(measurement_window);
+    let latency_per_op_type = parse_latency_per_op_type(latency_per_op_type);
+    // the payload size is set below, as it may be a distribution
+    let mut workload = match trace {
+        Some(trace) => Workload::from_trace(shard_count, trace)
+            .expect("trace should be loaded"),
+        None => Workload::new(
+            shard_count,
+            key_gen,
+            keys_per_command,
+            commands_per_client,
+            0,
+        ),
+    };
+    workload.set_payload_size(payload_size);
+    workload.set_warmup(warmup_commands, warmup);
+    if let Some(measurement_window) = measurement_window {
+        workload.set_measurement_window(measurement_window);
+    }
+    workload.set_latency_per_op_type(latency_per_op_type);
     workload.set_read_only_percentage(read_only_percentage);
+    workload.set_rmw_percentage(rmw_percentage);
+    if let Some(op_mix) = op_mix {
+        workload.set_op_mix(op_mix);
+    }
+    workload.set_eventual_read_percentage(eventual_read_percentage);
+    workload.set_read_your_writes(read_your_writes);
+    workload.set_metadata_table(metadata_table_keys, metadata_table_percentage);
     workload
 }
 
 fn parse_keys_per_command(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
-                .parse::<usize>()
-                .expect("keys per command should be a number")
-        })
         .unwrap_or(DEFAULT_KEYS_PER_COMMAND)
+        .parse::<usize>()
+        .expect("keys per command should be a number")
 }
 
 fn parse_shard_count(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
-                .parse::<usize>()
-                .expect("shard count should be a number")
-        })
         .unwrap_or(DEFAULT_SHARD_COUNT)
+        .parse::<usize>()
+        .expect("shard count should be a number")
 }
 
 fn parse_key_gen(key_gen: Option<&str>) -> KeyGen {
-    key_gen
-        .map(|key_gen| {
-            let parts: Vec<_>= key_gen.split(',').collect();
-            match parts.len() {
-                2 | 3 => (),
-                _ => panic!("invalid specification of key generator: {:?}", key_gen)
-            };
-            match parts[0] {
-                "conflict_pool" => {
-                    if parts.len() != 3 {
-                        panic!("conflict_pool key generator takes two arguments");
-                    }
-                    let conflict_rate = parts[1]
-                        .parse::<usize>()
-                        .expect("conflict rate should be a number");
-                    let pool_size = parts[2]
-                        .parse::<usize>()
-                        .expect("pool size should be a number");
-                    KeyGen::ConflictPool { conflict_rate, pool_size }
-                }
-                "zipf" => {
-                    if parts.len() != 3 {
-                        panic!("zipf key generator takes two arguments");
-                    }
-                    let coefficient = parts[1]
-                        .parse::<f64>()
-                        .expect("zipf coefficient should be a float");
-                    let keys_per_shard = parts[2]
-                        .parse::<usize>()
-                        .expect("number of keys (per shard) in the zipf distribution should be a number");
-                        KeyGen::Zipf {
-                            coefficient, total_keys_per_shard: keys_per_shard
-                        }
-                }
-                kgen => panic!("invalid key generator type: {}", kgen),
+    let key_gen = key_gen.unwrap_or(DEFAULT_KEY_GEN);
+    let parts: Vec<_> = key_gen.split(',').collect();
+    match parts.len() {
+        2 | 3 => (),
+        _ => panic!("invalid specification of key generator: {:?}", key_gen),
+    };
+    match parts[0] {
+        "conflict_pool" => {
+            if parts.len() != 3 {
+                panic!("conflict_pool key generator takes two arguments");
             }
-        })
-        .unwrap_or(DEFAULT_KEY_GEN)
+            let conflict_rate = parts[1]
+                .parse::<usize>()
+                .expect("conflict rate should be a number");
+            let pool_size = parts[2]
+                .parse::<usize>()
+                .expect("pool size should be a number");
+            KeyGen::ConflictPool {
+                conflict_rate,
+                pool_size,
+            }
+        }
+        "zipf" => {
+            if parts.len() != 3 {
+                panic!("zipf key generator takes two arguments");
+            }
+            let coefficient = parts[1]
+                .parse::<f64>()
+                .expect("zipf coefficient should be a float");
+            let keys_per_shard = parts[2].parse::<usize>().expect(
+                "number of keys (per shard) in the zipf distribution should be a number",
+            );
+            KeyGen::Zipf {
+                coefficient,
+                total_keys_per_shard: keys_per_shard,
+            }
+        }
+        kgen => panic!("invalid key generator type: {}", kgen),
+    }
 }
 
 fn parse_commands_per_client(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
-                .parse::<usize>()
-                .expect("commands per client should be a number")
-        })
         .unwrap_or(DEFAULT_COMMANDS_PER_CLIENT)
+        .parse::<usize>()
+        .expect("commands per client should be a number")
 }
 
 fn parse_read_only_percentage(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
-                .parse::<usize>()
-                .expect("read only percentage should be a number")
-        })
         .unwrap_or(DEFAULT_READ_ONLY_PERCENTAGE)
+        .parse::<usize>()
+        .expect("read only percentage should be a number")
+}
+
+fn parse_rmw_percentage(number: Option<&str>) -> usize {
+    number
+        .unwrap_or(DEFAULT_RMW_PERCENTAGE)
+        .parse::<usize>()
+        .expect("read-modify-write percentage should be a number")
 }
 
-fn parse_payload_size(number: Option<&str>) -> usize {
+fn parse_op_mix(op_mix: Option<&str>) -> Option<OpMix> {
+    op_mix.map(|op_mix| {
+        let percentages: Vec<_> = op_mix
+            .split(',')
+            .map(|percentage| {
+                percentage
+                    .parse::<usize>()
+                    .expect("op mix percentage should be a number")
+            })
+            .collect();
+        match percentages[..] {
+            [get, put, cas, scan, delete] => OpMix {
+                get,
+                put,
+                cas,
+                scan,
+                delete,
+            },
+            _ => panic!("invalid specification of op mix: {:?}", op_mix),
+        }
+    })
+}
+
+fn parse_eventual_read_percentage(number: Option<&str>) -> usize {
+    number
+        .unwrap_or(DEFAULT_EVENTUAL_READ_PERCENTAGE)
+        .parse::<usize>()
+        .expect("eventual read percentage should be a number")
+}
+
+fn parse_read_your_writes(read_your_writes: Option<&str>) -> bool {
+    read_your_writes
+        .unwrap_or(DEFAULT_READ_YOUR_WRITES)
+        .parse::<bool>()
+        .expect("read_your_writes should be a boolean")
+}
+
+fn parse_metadata_table_keys(number: Option<&str>) -> usize {
+    number
+        .unwrap_or(DEFAULT_METADATA_TABLE_KEYS)
+        .parse::<usize>()
+        .expect("metadata table keys should be a number")
+}
+
+fn parse_metadata_table_percentage(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
+        .unwrap_or(DEFAULT_METADATA_TABLE_PERCENTAGE)
+        .parse::<usize>()
+        .expect("metadata table percentage should be a number")
+}
+
+fn parse_payload_size(payload_size: Option<&str>) -> PayloadSize {
+    let payload_size = payload_size.unwrap_or(DEFAULT_PAYLOAD_SIZE);
+    let parts: Vec<_> = payload_size.split(',').collect();
+    match parts[..] {
+        [size] => PayloadSize::Constant(
+            size.parse::<usize>()
+                .expect("payload size should be a number"),
+        ),
+        ["uniform", min, max] => {
+            let min = min
                 .parse::<usize>()
-                .expect("payload size should be a number")
-        })
-        .unwrap_or(DEFAULT_PAYLOAD_SIZE)
+                .expect("minimum payload size should be a number");
+            let max = max
+                .parse::<usize>()
+                .expect("maximum payload size should be a number");
+            PayloadSize::Uniform { min, max }
+        }
+        ["lognormal", mu, sigma] => {
+            let mu = mu.parse::<f64>().expect("mu should be a float");
+            let sigma = sigma.parse::<f64>().expect("sigma should be a float");
+            PayloadSize::LogNormal { mu, sigma }
+        }
+        _ => {
+            panic!("invalid specification of payload size: {:?}", payload_size)
+        }
+    }
 }
 
-fn parse_batch_max_size(number: Option<&str>) -> usize {
+fn parse_warmup_commands(number: Option<&str>) -> usize {
     number
-        .map(|number| {
-            number
+        .unwrap_or(DEFAULT_WARMUP_COMMANDS)
+        .parse::<usize>()
+        .expect("warmup commands should be a number")
+}
+
+fn parse_warmup(duration: Option<&str>) -> Duration {
+    let millis = duration
+        .unwrap_or(DEFAULT_WARMUP)
+        .parse::<u64>()
+        .expect("warmup should be a number");
+    Duration::from_millis(millis)
+}
+
+fn parse_latency_per_op_type(latency_per_op_type: Option<&str>) -> bool {
+    latency_per_op_type
+        .unwrap_or(DEFAULT_LATENCY_PER_OP_TYPE)
+        .parse::<bool>()
+        .expect("latency_per_op_type should be a boolean")
+}
+
+fn parse_connections(connections: Option<&str>) -> usize {
+    let connections = connections
+        .unwrap_or(DEFAULT_CONNECTIONS)
+        .parse::<usize>()
+        .expect("connections should be a number");
+    assert!(connections > 0, "connections should be > 0");
+    connections
+}
+
+fn parse_client_tasks(client_tasks: Option<&str>, connections: usize) -> usize {
+    let client_tasks = client_tasks
+        .map(|client_tasks| {
+            client_tasks
                 .parse::<usize>()
-                .expect("batch max size should be a number")
+                .expect("client tasks should be a number")
         })
+        .unwrap_or(connections);
+    assert!(
+        client_tasks > 0 && client_tasks <= connections,
+        "client tasks should be > 0 and at most the number of connections"
+    );
+    client_tasks
+}
+
+fn parse_failover_timeout(timeout: Option<&str>) -> Option<Duration> {
+    timeout.map(|timeout| {
+        let millis = timeout
+            .parse::<u64>()
+            .expect("failover_timeout should be a number");
+        Duration::from_millis(millis)
+    })
+}
+
+fn parse_cmd_timeout(timeout: Option<&str>) -> Option<Duration> {
+    timeout.map(|timeout| {
+        let millis = timeout
+            .parse::<u64>()
+            .expect("cmd_timeout should be a number");
+        Duration::from_millis(millis)
+    })
+}
+
+fn parse_cmd_retries(number: Option<&str>) -> usize {
+    number
+        .unwrap_or(DEFAULT_CMD_RETRIES)
+        .parse::<usize>()
+        .expect("cmd_retries should be a number")
+}
+
+fn parse_batch_max_size(number: Option<&str>) -> usize {
+    number
         .unwrap_or(DEFAULT_BATCH_MAX_SIZE)
+        .parse::<usize>()
+        .expect("batch max size should be a number")
 }
 
 fn parse_batch_max_delay(duration: Option<&str>) -> Duration {
-    parse_millis_duration(duration).unwrap_or(DEFAULT_BATCH_MAX_DELAY)
+    let millis = duration
+        .unwrap_or(DEFAULT_BATCH_MAX_DELAY)
+        .parse::<u64>()
+        .expect("batch max delay should be a number");
+    Duration::from_millis(millis)
+}
+
+fn parse_submit_batch_max_size(number: Option<&str>) -> usize {
+    let submit_batch_max_size = number
+        .unwrap_or(DEFAULT_SUBMIT_BATCH_MAX_SIZE)
+        .parse::<usize>()
+        .expect("submit batch max size should be a number");
+    assert!(
+        submit_batch_max_size > 0,
+        "submit batch max size should be at least 1"
+    );
+    submit_batch_max_size
+}
+
+fn parse_submit_redundancy(number: Option<&str>) -> usize {
+    let submit_redundancy = number
+        .unwrap_or(DEFAULT_SUBMIT_REDUNDANCY)
+        .parse::<usize>()
+        .expect("submit redundancy should be a number");
+    assert!(
+        submit_redundancy > 0,
+        "submit redundancy should be at least 1"
+    );
+    submit_redundancy
 }
 
 fn parse_status_frequency(status_frequency: Option<&str>) -> Option<usize> {
@@ -445,3 +916,30 @@ fn parse_status_frequency(status_frequency: Option<&str>) -> Option<usize> {
 pub fn parse_metrics_file(metrics_file: Option<&str>) -> Option<String> {
     metrics_file.map(String::from)
 }
+
+fn parse_metrics_report_file(
+    metrics_report_file: Option<&str>,
+) -> Option<String> {
+    metrics_report_file.map(String::from)
+}
+
+fn parse_metrics_tags(tags: Option<&str>) -> BTreeMap<String, String> {
+    tags.map(|tags| {
+        tags.split(common::protocol::LIST_SEP)
+            .map(|tag| {
+                let parts: Vec<_> = tag.splitn(2, '=').collect();
+                assert_eq!(
+                    parts.len(),
+                    2,
+                    "each metrics tag should have the form 'KEY=VALUE'"
+                );
+                (parts[0].to_string(), parts[1].to_string())
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn parse_record_trace(record_trace: Option<&str>) -> Option<String> {
+    record_trace.map(String::from)
+}
@@ -2,13 +2,106 @@
 #[allow(dead_code)]
 pub mod protocol;
 
+use clap::{Arg, ArgMatches, Command};
 use fantoch::info;
 use std::time::Duration;
 
-const DEFAULT_TCP_NODELAY: bool = true;
-const DEFAULT_TCP_BUFFER_SIZE: usize = 8 * 1024; // 8 KBs
-const DEFAULT_CHANNEL_BUFFER_SIZE: usize = 10000;
-const DEFAULT_STACK_SIZE: usize = 8 * 1024 * 1024; // 8MBs
+// defaults are kept as strings so that they can be shown in `--help`
+pub const DEFAULT_TCP_NODELAY: &str = "true";
+pub const DEFAULT_TCP_BUFFER_SIZE: &str = "8192"; // 8 KBs
+pub const DEFAULT_TCP_FLUSH_INTERVAL: &str = "0";
+pub const DEFAULT_CHANNEL_BUFFER_SIZE: &str = "10000";
+const DEFAULT_STACK_SIZE: &str = "8388608"; // 8MBs
+
+/// Parses the arguments of `command` extended with the arguments shared by the
+/// process and client binaries. Arguments can also be set in a file passed
+/// with `--config` (see `with_config_file`), in which case the ones set in
+/// the command line take precedence.
+#[allow(dead_code)]
+pub fn get_matches(command: Command<'static>) -> ArgMatches {
+    command
+        // allow arguments set in the config file to be set again
+        .args_override_self(true)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("CONFIG")
+                .help("file with one 'OPTION = VALUE' per line, where OPTION is the (long) name of any other option, e.g. 'workers = 4'; empty lines and lines starting with '#' are ignored; options set in the command line take precedence over the ones set in this file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("tcp_nodelay")
+                .long("tcp_nodelay")
+                .value_name("TCP_NODELAY")
+                .help("set TCP_NODELAY")
+                .default_value(DEFAULT_TCP_NODELAY)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("stack_size")
+                .long("stack_size")
+                .value_name("STACK_SIZE")
+                .help("stack size (in bytes) of each tokio thread")
+                .default_value(DEFAULT_STACK_SIZE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("cpus")
+                .long("cpus")
+                .value_name("CPUS")
+                .help("number of cpus to be used by tokio; by default all available cpus are used")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("log_file")
+                .long("log_file")
+                .value_name("LOG_FILE")
+                .help("file to which logs will be written to; if not set, logs will be redirect to the stdout")
+                .takes_value(true),
+        )
+        .get_matches_from(with_config_file(std::env::args().collect()))
+}
+
+/// If `--config FILE` is in `args`, the options set in `FILE` are inserted
+/// before all other arguments (so that these override the ones in the file).
+#[allow(dead_code)]
+fn with_config_file(args: Vec<String>) -> Vec<String> {
+    let config = args.iter().enumerate().find_map(|(index, arg)| {
+        if arg == "--config" {
+            args.get(index + 1).cloned()
+        } else {
+            arg.strip_prefix("--config=").map(String::from)
+        }
+    });
+    let config = match config {
+        Some(config) => config,
+        None => return args,
+    };
+    let contents = std::fs::read_to_string(&config).unwrap_or_else(|e| {
+        panic!("config file {:?} should be readable: {:?}", config, e)
+    });
+
+    let mut args = args.into_iter();
+    // keep the binary name first
+    let mut expanded: Vec<_> = args.next().into_iter().collect();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (option, value) = line.split_once('=').unwrap_or_else(|| {
+            panic!(
+                "line {} of config file {:?} should have the form 'OPTION = VALUE'",
+                index + 1,
+                config
+            )
+        });
+        expanded.push(format!("--{}", option.trim()));
+        expanded.push(value.trim().trim_matches('"').to_string());
+    }
+    expanded.extend(args);
+    expanded
+}
 
 #[allow(dead_code)]
 pub fn tokio_runtime(
@@ -33,12 +126,9 @@ pub fn tokio_runtime(
 
 pub fn parse_tcp_nodelay(tcp_nodelay: Option<&str>) -> bool {
     tcp_nodelay
-        .map(|tcp_nodelay| {
-            tcp_nodelay
-                .parse::<bool>()
-                .expect("tcp_nodelay should be a boolean")
-        })
         .unwrap_or(DEFAULT_TCP_NODELAY)
+        .parse::<bool>()
+        .expect("tcp_nodelay should be a boolean")
 }
 
 pub fn parse_tcp_buffer_size(buffer_size: Option<&str>) -> usize {
@@ -46,36 +136,31 @@ pub fn parse_tcp_buffer_size(buffer_size: Option<&str>) -> usize {
 }
 
 pub fn parse_tcp_flush_interval(interval: Option<&str>) -> Option<Duration> {
-    interval.map(|interval| {
-        let millis = interval
-            .parse::<u64>()
-            .expect("flush interval should be a number");
-        Duration::from_millis(millis)
-    })
+    let millis = interval
+        .unwrap_or(DEFAULT_TCP_FLUSH_INTERVAL)
+        .parse::<u64>()
+        .expect("flush interval should be a number");
+    // if 0, there's no periodic flush (and connections are flushed after
+    // every batch of messages written instead)
+    (millis > 0).then(|| Duration::from_millis(millis))
 }
 
 pub fn parse_channel_buffer_size(buffer_size: Option<&str>) -> usize {
     parse_buffer_size(buffer_size, DEFAULT_CHANNEL_BUFFER_SIZE)
 }
 
-fn parse_buffer_size(buffer_size: Option<&str>, default: usize) -> usize {
+fn parse_buffer_size(buffer_size: Option<&str>, default: &str) -> usize {
     buffer_size
-        .map(|buffer_size| {
-            buffer_size
-                .parse::<usize>()
-                .expect("buffer size should be a number")
-        })
         .unwrap_or(default)
+        .parse::<usize>()
+        .expect("buffer size should be a number")
 }
 
 pub fn parse_stack_size(stack_size: Option<&str>) -> usize {
     stack_size
-        .map(|stack_size| {
-            stack_size
-                .parse::<usize>()
-                .expect("stack size should be a number")
-        })
         .unwrap_or(DEFAULT_STACK_SIZE)
+        .parse::<usize>()
+        .expect("stack size should be a number")
 }
 
 pub fn parse_cpus(cpus: Option<&str>) -> Option<usize> {
@@ -3,34 +3,44 @@ use color_eyre::Report;
 use fantoch::config::Config;
 use fantoch::id::{ProcessId, ShardId};
 use fantoch::info;
+use fantoch::otlp::OtlpConfig;
 use fantoch::protocol::Protocol;
+use fantoch::run::affinity::{CpuAffinity, PinnedTask};
+use fantoch::run::rw::Compression;
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 pub const LIST_SEP: &str = ",";
 
-const DEFAULT_SHARDS: usize = 1;
-const DEFAULT_SHARD_ID: ShardId = 0;
+// defaults are kept as strings so that they can be shown in `--help`
+const DEFAULT_SHARDS: &str = "1";
+const DEFAULT_SHARD_ID: &str = "0";
 
 const DEFAULT_IP: &str = "127.0.0.1";
-const DEFAULT_PORT: u16 = 3000;
-const DEFAULT_CLIENT_PORT: u16 = 4000;
+const DEFAULT_PORT: &str = "3000";
+const DEFAULT_CLIENT_PORT: &str = "4000";
 
-const DEFAULT_EXECUTE_AT_COMMIT: bool = false;
-const DEFAULT_EXECUTOR_CLEANUP_INTERVAL: Duration = Duration::from_millis(5);
+const DEFAULT_EXECUTE_AT_COMMIT: &str = "false";
+const DEFAULT_EXECUTOR_SPECULATIVE: &str = "false";
+const DEFAULT_EXECUTOR_CLEANUP_INTERVAL: &str = "5"; // 5ms
 
-const DEFAULT_WORKERS: usize = 1;
-const DEFAULT_EXECUTORS: usize = 1;
-const DEFAULT_MULTIPLEXING: usize = 1;
+const DEFAULT_WORKERS: &str = "1";
+const DEFAULT_EXECUTORS: &str = "1";
+const DEFAULT_MULTIPLEXING: &str = "1";
+const DEFAULT_COMPRESSION: &str = "none";
 
-const DEFAULT_NFR: bool = false;
+const DEFAULT_NFR: &str = "false";
 
 // tempo's config
-const DEFAULT_TEMPO_TINY_QUORUMS: bool = false;
-const DEFAULT_TEMPO_DETACHED_SEND_INTERVAL: Duration = Duration::from_millis(5);
+const DEFAULT_TEMPO_TINY_QUORUMS: &str = "false";
+const DEFAULT_TEMPO_DETACHED_SEND_INTERVAL: &str = "5"; // 5ms
 
 // protocol's config
-const DEFAULT_SKIP_FAST_ACK: bool = false;
+const DEFAULT_SKIP_FAST_ACK: &str = "false";
+
+// tracing config
+const DEFAULT_OTLP_SAMPLE_RATE: &str = "0.01";
 
 #[global_allocator]
 #[cfg(feature = "jemalloc")]
@@ -43,19 +53,27 @@ type ProtocolArgs = (
     IpAddr,
     u16,
     u16,
+    Option<PathBuf>,
     Vec<(String, Option<Duration>)>,
     Config,
     bool,
     usize,
     Option<Duration>,
+    Compression,
     usize,
     usize,
+    Option<f64>,
+    Option<u64>,
     usize,
     usize,
     usize,
+    Option<CpuAffinity>,
     Option<String>,
+    Option<PathBuf>,
+    Option<Duration>,
     Option<Duration>,
     Option<String>,
+    Option<u16>,
     usize,
     Option<usize>,
 );
@@ -73,19 +91,27 @@ where
         ip,
         port,
         client_port,
+        unix_socket_dir,
         addresses,
         config,
         tcp_nodelay,
         tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         process_channel_buffer_size,
         client_channel_buffer_size,
+        overload_threshold,
+        submit_rate_limit,
         workers,
         executors,
         multiplexing,
+        cpu_affinity,
         execution_log,
+        executor_store_dir,
         ping_interval,
+        heartbeat_interval,
         metrics_file,
+        metrics_port,
         stack_size,
         cpus,
     ) = args;
@@ -97,26 +123,34 @@ where
         ip,
         port,
         client_port,
+        unix_socket_dir,
         addresses,
         config,
         tcp_nodelay,
         tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         process_channel_buffer_size,
         client_channel_buffer_size,
+        overload_threshold,
+        submit_rate_limit,
         workers,
         executors,
         multiplexing,
+        cpu_affinity,
         execution_log,
+        executor_store_dir,
         ping_interval,
+        heartbeat_interval,
         metrics_file,
+        metrics_port,
     );
 
     super::tokio_runtime(stack_size, cpus).block_on(process)
 }
 
 fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
-    let matches = Command::new("process")
+    let command = Command::new("process")
         .version("0.1")
         .author("Vitor Enes <vitorenesduarte@gmail.com>")
         .about("Runs an instance of some protocol.")
@@ -132,8 +166,8 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("shard_id")
                 .long("shard_id")
                 .value_name("SHARD_ID")
-                .help("shard identifier; default: 0")
-                .required(true)
+                .help("shard identifier")
+                .default_value(DEFAULT_SHARD_ID)
                 .takes_value(true),
         )
         .arg(
@@ -147,28 +181,38 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("ip")
                 .long("ip")
                 .value_name("IP")
-                .help("ip to bind to; default: 127.0.0.1")
+                .help("ip to bind to")
+                .default_value(DEFAULT_IP)
                 .takes_value(true),
         )
         .arg(
             Arg::new("port")
                 .long("port")
                 .value_name("PORT")
-                .help("port to bind to; default: 3000")
+                .help("port to bind to")
+                .default_value(DEFAULT_PORT)
                 .takes_value(true),
         )
         .arg(
             Arg::new("client_port")
                 .long("client_port")
                 .value_name("CLIENT_PORT")
-                .help("client port to bind to; default: 4000")
+                .help("client port to bind to")
+                .default_value(DEFAULT_CLIENT_PORT)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("unix_socket_dir")
+                .long("unix_socket_dir")
+                .value_name("UNIX_SOCKET_DIR")
+                .help("if set, the process also listens on unix domain sockets in this directory: process_ID.sock for other processes and client_ID.sock for clients; these can then be connected to with addresses of the form unix://PATH")
                 .takes_value(true),
         )
         .arg(
             Arg::new("addresses")
                 .long("addresses")
                 .value_name("ADDRESSES")
-                .help("comma-separated list of addresses to connect to; if a delay (in milliseconds) is to be injected, the address should be of the form IP:PORT-DELAY; for example, 127.0.0.1:3000-120 injects a delay of 120 milliseconds before sending a message to the process at the 127.0.0.1:3000 address")
+                .help("comma-separated list of addresses to connect to; if a delay (in milliseconds) is to be injected, the address should be of the form IP:PORT-DELAY; for example, 127.0.0.1:3000-120 injects a delay of 120 milliseconds before sending a message to the process at the 127.0.0.1:3000 address; addresses of the form unix://PATH connect through a unix domain socket")
                 .required(true)
                 .takes_value(true),
         )
@@ -192,22 +236,24 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("shard_count")
                 .long("shard_count")
                 .value_name("SHARDS_COUNT")
-                .help("number of shards; default: 1")
-                .required(true)
+                .help("number of shards")
+                .default_value(DEFAULT_SHARDS)
                 .takes_value(true),
         )
         .arg(
             Arg::new("execute_at_commit")
                 .long("execute_at_commit")
                 .value_name("EXECUTE_AT_COMMIT")
-                .help("bool indicating whether execution should be skipped; default: false")
+                .help("bool indicating whether execution should be skipped")
+                .default_value(DEFAULT_EXECUTE_AT_COMMIT)
                 .takes_value(true),
         )
         .arg(
             Arg::new("executor_cleanup_interval")
                 .long("executor_cleanup_interval")
                 .value_name("EXECUTOR_CLEANUP_INTERVAL")
-                .help("executor cleanup interval (in milliseconds); default: 5")
+                .help("executor cleanup interval (in milliseconds)")
+                .default_value(DEFAULT_EXECUTOR_CLEANUP_INTERVAL)
                 .takes_value(true),
         )
         .arg(
@@ -217,6 +263,42 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .help("executor monitor pending interval (in milliseconds); if no value if set, pending commands are not monitored")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("executor_speculative")
+                .long("executor_speculative")
+                .value_name("EXECUTOR_SPECULATIVE")
+                .help("bool indicating whether commands should be speculatively executed as soon as they are committed")
+                .default_value(DEFAULT_EXECUTOR_SPECULATIVE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("executor_store_capacity")
+                .long("executor_store_capacity")
+                .value_name("EXECUTOR_STORE_CAPACITY")
+                .help("maximum number of bytes used by keys and values in each executor store (above which the least recently used keys are evicted); if no value is set, stores are not bounded")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("executor_dedup_window")
+                .long("executor_dedup_window")
+                .value_name("EXECUTOR_DEDUP_WINDOW")
+                .help("number of commands per key whose results are cached by executors, so that retried commands are not executed again; if no value is set, commands are not deduplicated")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("executor_hot_keys")
+                .long("executor_hot_keys")
+                .value_name("EXECUTOR_HOT_KEYS")
+                .help("number of hottest keys (by accesses and by conflicts) reported in executor metrics; if no value is set, executors don't track per-key counts")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("executor_parallel_scc_threshold")
+                .long("executor_parallel_scc_threshold")
+                .value_name("EXECUTOR_PARALLEL_SCC_THRESHOLD")
+                .help("number of pending commands from which graph executors search for SCCs in parallel; if no value is set, SCCs are always searched sequentially")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("gc_interval")
                 .long("gc_interval")
@@ -235,14 +317,16 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("nfr")
                 .long("nfr")
                 .value_name("NFR")
-                .help("boolean indicating whether NFR is enabled; default: false")
+                .help("boolean indicating whether NFR is enabled")
+                .default_value(DEFAULT_NFR)
                 .takes_value(true),
         )
         .arg(
             Arg::new("tempo_tiny_quorums")
                 .long("tempo_tiny_quorums")
                 .value_name("TEMPO_TINY_QUORUMS")
-                .help("boolean indicating whether tempo's tiny quorums are enabled; default: false")
+                .help("boolean indicating whether tempo's tiny quorums are enabled")
+                .default_value(DEFAULT_TEMPO_TINY_QUORUMS)
                 .takes_value(true),
         )
         .arg(
@@ -256,74 +340,101 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
             Arg::new("tempo_detached_send_interval")
                 .long("tempo_detached_send_interval")
                 .value_name("TEMPO_DETACHED_SEND_INTERVAL")
-                .help("number indicating the interval (in milliseconds) between mdetached messages are sent; default: 5")
+                .help("number indicating the interval (in milliseconds) between detached messages are sent")
+                .default_value(DEFAULT_TEMPO_DETACHED_SEND_INTERVAL)
                 .takes_value(true),
         )
         .arg(
             Arg::new("skip_fast_ack")
                 .long("skip_fast_ack")
                 .value_name("SKIP_FAST_ACK")
-                .help("boolean indicating whether protocols should try to enable the skip fast ack optimization; default: false")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::new("tcp_nodelay")
-                .long("tcp_nodelay")
-                .value_name("TCP_NODELAY")
-                .help("TCP_NODELAY; default: true")
+                .help("boolean indicating whether protocols should try to enable the skip fast ack optimization")
+                .default_value(DEFAULT_SKIP_FAST_ACK)
                 .takes_value(true),
         )
         .arg(
             Arg::new("tcp_buffer_size")
                 .long("tcp_buffer_size")
                 .value_name("TCP_BUFFER_SIZE")
-                .help("size of the TCP buffer; default: 8192 (bytes)")
+                .help("size of the TCP buffer (in bytes)")
+                .default_value(super::DEFAULT_TCP_BUFFER_SIZE)
                 .takes_value(true),
         )
         .arg(
             Arg::new("tcp_flush_interval")
                 .long("tcp_flush_interval")
                 .value_name("TCP_FLUSH_INTERVAL")
-                .help("TCP flush interval (in milliseconds); if 0, then flush occurs on every send; default: 0")
+                .help("TCP flush interval (in milliseconds); if 0, connections are flushed after every batch of messages written")
+                .default_value(super::DEFAULT_TCP_FLUSH_INTERVAL)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("compression")
+                .long("compression")
+                .value_name("COMPRESSION")
+                .help("compression of messages sent to other processes (none, lz4 or zstd); messages are only compressed between processes configured with the same compression")
+                .default_value(DEFAULT_COMPRESSION)
                 .takes_value(true),
         )
         .arg(
             Arg::new("process_channel_buffer_size")
                 .long("process_channel_buffer_size")
                 .value_name("PROCESS_CHANNEL_BUFFER_SIZE")
-                .help(
-                    "size of the buffer in each channel used for task communication related to the processes; default: 100",
-                )
+                .help("size of the buffer in each channel used for task communication related to the processes")
+                .default_value(super::DEFAULT_CHANNEL_BUFFER_SIZE)
                 .takes_value(true),
         )
         .arg(
             Arg::new("client_channel_buffer_size")
                 .long("client_channel_buffer_size")
                 .value_name("CLIENT_CHANNEL_BUFFER_SIZE")
-                .help(
-                    "size of the buffer in each channel used for task communication related to the clients; default: 100",
-                )
+                .help("size of the buffer in each channel used for task communication related to the clients")
+                .default_value(super::DEFAULT_CHANNEL_BUFFER_SIZE)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("overload_threshold")
+                .long("overload_threshold")
+                .value_name("OVERLOAD_THRESHOLD")
+                .help("fraction (in (0, 1]) of the capacity of the channels to executors, writers and workers above which the process is overloaded and rejects new client commands (clients retry them later); by default commands are never rejected")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("submit_rate_limit")
+                .long("submit_rate_limit")
+                .value_name("SUBMIT_RATE_LIMIT")
+                .help("maximum number of commands per second submitted by clients to this process (commands above it are delayed); it can be changed at runtime with `PUT /rate_limit?rate=N` on the metrics endpoint (or `rate=none` to disable it); by default commands are never limited")
                 .takes_value(true),
         )
         .arg(
             Arg::new("workers")
                 .long("workers")
                 .value_name("WORKERS")
-                .help("number of protocol workers; default: 1")
+                .help("number of protocol workers")
+                .default_value(DEFAULT_WORKERS)
                 .takes_value(true),
         )
         .arg(
             Arg::new("executors")
                 .long("executors")
                 .value_name("EXECUTORS")
-                .help("number of executors; default: 1")
+                .help("number of executors")
+                .default_value(DEFAULT_EXECUTORS)
                 .takes_value(true),
         )
         .arg(
             Arg::new("multiplexing")
                 .long("multiplexing")
                 .value_name("MULTIPLEXING")
-                .help("number of connections between replicas; default: 1")
+                .help("number of connections between replicas")
+                .default_value(DEFAULT_MULTIPLEXING)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("cpu_affinity")
+                .long("cpu_affinity")
+                .value_name("CPU_AFFINITY")
+                .help("cores to which tasks are pinned, e.g. 'workers=0-3;executors=4,5;readers=6;writers=7' (tasks of each kind are assigned to their cores in a round-robin fashion); by default tasks are not pinned")
                 .takes_value(true),
         )
         .arg(
@@ -333,6 +444,13 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .help("log file in which execution info should be written to; by default this information is not logged")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("executor_store_dir")
+                .long("executor_store_dir")
+                .value_name("EXECUTOR_STORE_DIR")
+                .help("if set, each executor keeps its key-value store in its own sled database in this directory (requires the sled feature); by default stores are kept in memory")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("ping_interval")
                 .long("ping_interval")
@@ -340,6 +458,13 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .help("number indicating the interval (in milliseconds) between pings between processes; by default there's no pinging; if set, this value should be > 0")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("heartbeat_interval")
+                .long("heartbeat_interval")
+                .value_name("HEARTBEAT_INTERVAL")
+                .help("number indicating the interval (in milliseconds) between the heartbeats sent to other processes, from which failures are detected; by default there's no failure detection; if set, this value should be > 0")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("metrics_file")
                 .long("metrics_file")
@@ -348,32 +473,38 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
                 .takes_value(true),
         )
         .arg(
-            Arg::new("stack_size")
-                .long("stack_size")
-                .value_name("STACK_SIZE")
-                .help("stack size (in bytes) of each tokio thread; default: 2 * 1024 * 1024 (bytes)")
+            Arg::new("metrics_port")
+                .long("metrics_port")
+                .value_name("METRICS_PORT")
+                .help("port on which metrics are served at /metrics in the Prometheus format (updated every 5s), along with liveness and readiness probes at /healthz and /readyz; by default metrics are not served")
                 .takes_value(true),
         )
         .arg(
-            Arg::new("cpus")
-                .long("cpus")
-                .value_name("CPUS")
-                .help("number of cpus to be used by tokio; by default all available cpus are used")
+            Arg::new("otlp_endpoint")
+                .long("otlp_endpoint")
+                .value_name("OTLP_ENDPOINT")
+                .help("address (HOST:PORT) of an OpenTelemetry collector (e.g. Jaeger, usually on port 4318) to which the traces of the sampled commands (their submit, the handling of messages about them, their commit and their execution) are exported with OTLP/HTTP; by default traces are not exported")
                 .takes_value(true),
         )
         .arg(
-            Arg::new("log_file")
-                .long("log_file")
-                .value_name("LOG_FILE")
-                .help("file to which logs will be written to; if not set, logs will be redirect to the stdout")
+            Arg::new("otlp_sample_rate")
+                .long("otlp_sample_rate")
+                .value_name("OTLP_SAMPLE_RATE")
+                .help("fraction (in [0, 1]) of the commands whose traces are exported; all processes sample the same commands")
+                .default_value(DEFAULT_OTLP_SAMPLE_RATE)
                 .takes_value(true),
-        )
-        .get_matches();
+        );
+    let matches = super::get_matches(command);
 
     let tracing_directives = None;
+    let otlp = parse_otlp(
+        matches.value_of("otlp_endpoint"),
+        matches.value_of("otlp_sample_rate"),
+    );
     let guard = fantoch::util::init_tracing_subscriber(
         matches.value_of("log_file"),
         tracing_directives,
+        otlp,
     );
 
     // parse arguments
@@ -384,6 +515,8 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
     let ip = parse_ip(matches.value_of("ip"));
     let port = parse_port(matches.value_of("port"));
     let client_port = parse_client_port(matches.value_of("client_port"));
+    let unix_socket_dir =
+        parse_unix_socket_dir(matches.value_of("unix_socket_dir"));
     let addresses = parse_addresses(matches.value_of("addresses"));
 
     // parse config
@@ -398,6 +531,15 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
         parse_executor_monitor_pending_interval(
             matches.value_of("executor_monitor_pending_interval"),
         ),
+        parse_executor_speculative(matches.value_of("executor_speculative")),
+        parse_executor_store_capacity(
+            matches.value_of("executor_store_capacity"),
+        ),
+        parse_executor_dedup_window(matches.value_of("executor_dedup_window")),
+        parse_executor_hot_keys(matches.value_of("executor_hot_keys")),
+        parse_executor_parallel_scc_threshold(
+            matches.value_of("executor_parallel_scc_threshold"),
+        ),
         parse_gc_interval(matches.value_of("gc_interval")),
         parse_leader(matches.value_of("leader")),
         parse_nfr(matches.value_of("nfr")),
@@ -416,6 +558,7 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
         super::parse_tcp_buffer_size(matches.value_of("tcp_buffer_size"));
     let tcp_flush_interval =
         super::parse_tcp_flush_interval(matches.value_of("tcp_flush_interval"));
+    let compression = parse_compression(matches.value_of("compression"));
 
     let process_channel_buffer_size = super::parse_channel_buffer_size(
         matches.value_of("process_channel_buffer_size"),
@@ -423,12 +566,22 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
     let client_channel_buffer_size = super::parse_channel_buffer_size(
         matches.value_of("client_channel_buffer_size"),
     );
+    let overload_threshold =
+        parse_overload_threshold(matches.value_of("overload_threshold"));
+    let submit_rate_limit =
+        parse_submit_rate_limit(matches.value_of("submit_rate_limit"));
     let workers = parse_workers(matches.value_of("workers"));
     let executors = parse_executors(matches.value_of("executors"));
     let multiplexing = parse_multiplexing(matches.value_of("multiplexing"));
+    let cpu_affinity = parse_cpu_affinity(matches.value_of("cpu_affinity"));
     let execution_log = parse_execution_log(matches.value_of("execution_log"));
+    let executor_store_dir =
+        parse_executor_store_dir(matches.value_of("executor_store_dir"));
     let ping_interval = parse_ping_interval(matches.value_of("ping_interval"));
+    let heartbeat_interval =
+        parse_heartbeat_interval(matches.value_of("heartbeat_interval"));
     let metrics_file = parse_metrics_file(matches.value_of("metrics_file"));
+    let metrics_port = parse_metrics_port(matches.value_of("metrics_port"));
     let stack_size = super::parse_stack_size(matches.value_of("stack_size"));
     let cpus = super::parse_cpus(matches.value_of("cpus"));
 
@@ -437,11 +590,13 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
     info!("ip: {:?}", ip);
     info!("port: {}", port);
     info!("client port: {}", client_port);
+    info!("unix socket dir: {:?}", unix_socket_dir);
     info!("addresses: {:?}", addresses);
     info!("config: {:?}", config);
     info!("tcp_nodelay: {:?}", tcp_nodelay);
     info!("tcp buffer size: {:?}", tcp_buffer_size);
     info!("tcp flush interval: {:?}", tcp_flush_interval);
+    info!("compression: {:?}", compression);
     info!(
         "process channel buffer size: {:?}",
         process_channel_buffer_size
@@ -450,12 +605,18 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
         "client channel buffer size: {:?}",
         client_channel_buffer_size
     );
+    info!("overload threshold: {:?}", overload_threshold);
+    info!("submit rate limit: {:?}", submit_rate_limit);
     info!("workers: {:?}", workers);
     info!("executors: {:?}", executors);
     info!("multiplexing: {:?}", multiplexing);
+    info!("cpu affinity: {:?}", cpu_affinity);
     info!("execution log: {:?}", execution_log);
+    info!("executor store dir: {:?}", executor_store_dir);
     info!("ping_interval: {:?}", ping_interval);
+    info!("heartbeat interval: {:?}", heartbeat_interval);
     info!("metrics file: {:?}", metrics_file);
+    info!("metrics port: {:?}", metrics_port);
     info!("stack size: {:?}", stack_size);
 
     let args = (
@@ -465,19 +626,27 @@ fn parse_args() -> (ProtocolArgs, tracing_appender::non_blocking::WorkerGuard) {
         ip,
         port,
         client_port,
+        unix_socket_dir,
         addresses,
         config,
         tcp_nodelay,
         tcp_buffer_size,
         tcp_flush_interval,
+        compression,
         process_channel_buffer_size,
         client_channel_buffer_size,
+        overload_threshold,
+        submit_rate_limit,
         workers,
         executors,
         multiplexing,
+        cpu_affinity,
         execution_log,
+        executor_store_dir,
         ping_interval,
+        heartbeat_interval,
         metrics_file,
+        metrics_port,
         stack_size,
         cpus,
     );
@@ -489,9 +658,7 @@ fn parse_process_id(id: Option<&str>) -> ProcessId {
 }
 
 fn parse_shard_id(shard_id: Option<&str>) -> ShardId {
-    shard_id
-        .map(|id| parse_id::<ShardId>(id))
-        .unwrap_or(DEFAULT_SHARD_ID)
+    parse_id::<ShardId>(shard_id.unwrap_or(DEFAULT_SHARD_ID))
 }
 
 fn parse_id<I>(id: &str) -> I
@@ -525,15 +692,19 @@ fn parse_ip(ip: Option<&str>) -> IpAddr {
 }
 
 fn parse_port(port: Option<&str>) -> u16 {
-    port.map(|port| port.parse::<u16>().expect("port should be a number"))
-        .unwrap_or(DEFAULT_PORT)
+    port.unwrap_or(DEFAULT_PORT)
+        .parse::<u16>()
+        .expect("port should be a number")
 }
 
 fn parse_client_port(port: Option<&str>) -> u16 {
-    port.map(|port| {
-        port.parse::<u16>().expect("client port should be a number")
-    })
-    .unwrap_or(DEFAULT_CLIENT_PORT)
+    port.unwrap_or(DEFAULT_CLIENT_PORT)
+        .parse::<u16>()
+        .expect("client port should be a number")
+}
+
+fn parse_unix_socket_dir(unix_socket_dir: Option<&str>) -> Option<PathBuf> {
+    unix_socket_dir.map(PathBuf::from)
 }
 
 fn parse_addresses(addresses: Option<&str>) -> Vec<(String, Option<Duration>)> {
@@ -570,6 +741,11 @@ pub fn build_config(
     execute_at_commit: bool,
     executor_cleanup_interval: Duration,
     executor_monitor_pending_interval: Option<Duration>,
+    executor_speculative: bool,
+    executor_store_capacity: Option<usize>,
+    executor_dedup_window: Option<usize>,
+    executor_hot_keys: Option<usize>,
+    executor_parallel_scc_threshold: Option<usize>,
     gc_interval: Option<Duration>,
     leader: Option<ProcessId>,
     nfr: bool,
@@ -586,6 +762,11 @@ pub fn build_config(
     if let Some(interval) = executor_monitor_pending_interval {
         config.set_executor_monitor_pending_interval(interval);
     }
+    config.set_executor_speculative(executor_speculative);
+    config.set_executor_store_capacity(executor_store_capacity);
+    config.set_executor_dedup_window(executor_dedup_window);
+    config.set_executor_hot_keys(executor_hot_keys);
+    config.set_executor_parallel_scc_threshold(executor_parallel_scc_threshold);
     if let Some(interval) = gc_interval {
         config.set_gc_interval(interval);
     }
@@ -619,31 +800,24 @@ pub fn parse_f(f: Option<&str>) -> usize {
 
 pub fn parse_shard_count(shards: Option<&str>) -> usize {
     shards
-        .map(|shards| {
-            shards.parse::<usize>().expect("shards should be a number")
-        })
         .unwrap_or(DEFAULT_SHARDS)
+        .parse::<usize>()
+        .expect("shards should be a number")
 }
 
 pub fn parse_execute_at_commit(execute_at_commit: Option<&str>) -> bool {
     execute_at_commit
-        .map(|execute_at_commit| {
-            execute_at_commit
-                .parse::<bool>()
-                .expect("execute_at_commit should be a bool")
-        })
         .unwrap_or(DEFAULT_EXECUTE_AT_COMMIT)
+        .parse::<bool>()
+        .expect("execute_at_commit should be a bool")
 }
 
 pub fn parse_executor_cleanup_interval(interval: Option<&str>) -> Duration {
-    interval
-        .map(|interval| {
-            let ms = interval
-                .parse::<u64>()
-                .expect("executor_cleanup_interval should be a number");
-            Duration::from_millis(ms)
-        })
+    let ms = interval
         .unwrap_or(DEFAULT_EXECUTOR_CLEANUP_INTERVAL)
+        .parse::<u64>()
+        .expect("executor_cleanup_interval should be a number");
+    Duration::from_millis(ms)
 }
 
 pub fn parse_executor_monitor_pending_interval(
@@ -657,6 +831,49 @@ pub fn parse_executor_monitor_pending_interval(
     })
 }
 
+pub fn parse_executor_speculative(executor_speculative: Option<&str>) -> bool {
+    executor_speculative
+        .unwrap_or(DEFAULT_EXECUTOR_SPECULATIVE)
+        .parse::<bool>()
+        .expect("executor_speculative should be a bool")
+}
+
+pub fn parse_executor_store_capacity(capacity: Option<&str>) -> Option<usize> {
+    capacity.map(|capacity| {
+        capacity
+            .parse::<usize>()
+            .expect("executor_store_capacity should be a number")
+    })
+}
+
+pub fn parse_executor_dedup_window(
+    dedup_window: Option<&str>,
+) -> Option<usize> {
+    dedup_window.map(|dedup_window| {
+        dedup_window
+            .parse::<usize>()
+            .expect("executor_dedup_window should be a number")
+    })
+}
+
+pub fn parse_executor_hot_keys(hot_keys: Option<&str>) -> Option<usize> {
+    hot_keys.map(|hot_keys| {
+        hot_keys
+            .parse::<usize>()
+            .expect("executor_hot_keys should be a number")
+    })
+}
+
+pub fn parse_executor_parallel_scc_threshold(
+    threshold: Option<&str>,
+) -> Option<usize> {
+    threshold.map(|threshold| {
+        threshold
+            .parse::<usize>()
+            .expect("executor_parallel_scc_threshold should be a number")
+    })
+}
+
 pub fn parse_gc_interval(gc_interval: Option<&str>) -> Option<Duration> {
     gc_interval.map(|gc_interval| {
         let ms = gc_interval
@@ -671,18 +888,16 @@ fn parse_leader(leader: Option<&str>) -> Option<ProcessId> {
 }
 
 fn parse_nfr(nfr: Option<&str>) -> bool {
-    nfr.map(|nfr| nfr.parse::<bool>().expect("nfr should be a bool"))
-        .unwrap_or(DEFAULT_NFR)
+    nfr.unwrap_or(DEFAULT_NFR)
+        .parse::<bool>()
+        .expect("nfr should be a bool")
 }
 
 fn parse_tempo_tiny_quorums(tempo_tiny_quorums: Option<&str>) -> bool {
     tempo_tiny_quorums
-        .map(|tempo_tiny_quorums| {
-            tempo_tiny_quorums
-                .parse::<bool>()
-                .expect("tempo_tiny_quorums should be a bool")
-        })
         .unwrap_or(DEFAULT_TEMPO_TINY_QUORUMS)
+        .parse::<bool>()
+        .expect("tempo_tiny_quorums should be a bool")
 }
 
 fn parse_tempo_clock_bump_interval(interval: Option<&str>) -> Option<Duration> {
@@ -695,60 +910,107 @@ fn parse_tempo_clock_bump_interval(interval: Option<&str>) -> Option<Duration> {
 }
 
 fn parse_tempo_detached_send_interval(interval: Option<&str>) -> Duration {
-    interval
-        .map(|interval| {
-            let ms = interval
-                .parse::<u64>()
-                .expect("tempo_detached_send_interval should be a number");
-            Duration::from_millis(ms)
-        })
+    let ms = interval
         .unwrap_or(DEFAULT_TEMPO_DETACHED_SEND_INTERVAL)
+        .parse::<u64>()
+        .expect("tempo_detached_send_interval should be a number");
+    Duration::from_millis(ms)
 }
 
 pub fn parse_skip_fast_ack(skip_fast_ack: Option<&str>) -> bool {
     skip_fast_ack
-        .map(|skip_fast_ack| {
-            skip_fast_ack
-                .parse::<bool>()
-                .expect("skip_fast_ack should be a boolean")
-        })
         .unwrap_or(DEFAULT_SKIP_FAST_ACK)
+        .parse::<bool>()
+        .expect("skip_fast_ack should be a boolean")
 }
 
 fn parse_workers(workers: Option<&str>) -> usize {
     workers
-        .map(|workers| {
-            workers
-                .parse::<usize>()
-                .expect("workers should be a number")
-        })
         .unwrap_or(DEFAULT_WORKERS)
+        .parse::<usize>()
+        .expect("workers should be a number")
 }
 
 fn parse_executors(executors: Option<&str>) -> usize {
     executors
-        .map(|executors| {
-            executors
-                .parse::<usize>()
-                .expect("executors should be a number")
-        })
         .unwrap_or(DEFAULT_EXECUTORS)
+        .parse::<usize>()
+        .expect("executors should be a number")
+}
+
+fn parse_overload_threshold(overload_threshold: Option<&str>) -> Option<f64> {
+    overload_threshold.map(|overload_threshold| {
+        overload_threshold
+            .parse::<f64>()
+            .expect("overload threshold should be a number")
+    })
+}
+
+fn parse_submit_rate_limit(submit_rate_limit: Option<&str>) -> Option<u64> {
+    submit_rate_limit.map(|submit_rate_limit| {
+        submit_rate_limit
+            .parse::<u64>()
+            .expect("submit rate limit should be a number")
+    })
 }
 
 fn parse_multiplexing(multiplexing: Option<&str>) -> usize {
     multiplexing
-        .map(|multiplexing| {
-            multiplexing
-                .parse::<usize>()
-                .expect("multiplexing should be a number")
-        })
         .unwrap_or(DEFAULT_MULTIPLEXING)
+        .parse::<usize>()
+        .expect("multiplexing should be a number")
+}
+
+fn parse_cpu_affinity(cpu_affinity: Option<&str>) -> Option<CpuAffinity> {
+    cpu_affinity.map(|cpu_affinity| {
+        let mut affinity = CpuAffinity::new();
+        for entry in cpu_affinity.split(';') {
+            let (task, cores) = entry.split_once('=').expect(
+                "cpu affinity entries should be of the form TASKS=CORES",
+            );
+            let task = match task {
+                "workers" => PinnedTask::Worker,
+                "executors" => PinnedTask::Executor,
+                "readers" => PinnedTask::Reader,
+                "writers" => PinnedTask::Writer,
+                _ => panic!("invalid tasks in cpu affinity: {:?}", task),
+            };
+            let cores = cores.split(LIST_SEP).flat_map(parse_cores).collect();
+            affinity.set_cores(task, cores);
+        }
+        affinity
+    })
+}
+
+// parses a core (e.g. "4") or a range of cores (e.g. "0-3")
+fn parse_cores(cores: &str) -> Vec<usize> {
+    let parse =
+        |core: &str| core.parse::<usize>().expect("core should be a number");
+    match cores.split_once('-') {
+        Some((start, end)) => (parse(start)..=parse(end)).collect(),
+        None => vec![parse(cores)],
+    }
+}
+
+fn parse_compression(compression: Option<&str>) -> Compression {
+    match compression.unwrap_or(DEFAULT_COMPRESSION) {
+        "none" => Compression::None,
+        "lz4" => Compression::Lz4,
+        "zstd" => Compression::Zstd,
+        compression => panic!("invalid compression: {:?}", compression),
+    }
 }
 
 pub fn parse_execution_log(execution_log: Option<&str>) -> Option<String> {
     execution_log.map(String::from)
 }
 
+fn parse_executor_store_dir(
+    executor_store_dir: Option<&str>,
+) -> Option<PathBuf> {
+    executor_store_dir.map(PathBuf::from)
+}
+
 fn parse_ping_interval(interval: Option<&str>) -> Option<Duration> {
     interval.map(|interval| {
         let millis = interval
@@ -758,6 +1020,38 @@ fn parse_ping_interval(interval: Option<&str>) -> Option<Duration> {
     })
 }
 
+fn parse_heartbeat_interval(interval: Option<&str>) -> Option<Duration> {
+    interval.map(|interval| {
+        let millis = interval
+            .parse::<u64>()
+            .expect("heartbeat_interval should be a number");
+        Duration::from_millis(millis)
+    })
+}
+
 pub fn parse_metrics_file(metrics_file: Option<&str>) -> Option<String> {
     metrics_file.map(String::from)
 }
+
+fn parse_metrics_port(port: Option<&str>) -> Option<u16> {
+    port.map(|port| {
+        port.parse::<u16>()
+            .expect("metrics port should be a number")
+    })
+}
+
+fn parse_otlp(
+    endpoint: Option<&str>,
+    sample_rate: Option<&str>,
+) -> Option<OtlpConfig> {
+    endpoint.map(|endpoint| {
+        let sample_rate = sample_rate
+            .unwrap_or(DEFAULT_OTLP_SAMPLE_RATE)
+            .parse::<f64>()
+            .expect("otlp_sample_rate should be a number");
+        OtlpConfig {
+            endpoint: endpoint.to_string(),
+            sample_rate,
+        }
+    })
+}
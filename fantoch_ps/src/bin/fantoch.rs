@@ -0,0 +1,365 @@
+use clap::{Arg, Command};
+use color_eyre::eyre::{self, WrapErr};
+use color_eyre::Report;
+use fantoch::protocol::{Basic, Protocol};
+use fantoch::sim::{
+    Checkpoint, Comparison, Scenario, SimulationMetrics, StopCondition,
+};
+use fantoch_ps::protocol::{
+    AtlasSequential, CaesarLocked, EPaxosSequential, FPaxos, TempoSequential,
+};
+use std::time::{Duration, Instant};
+
+fn main() -> Result<(), Report> {
+    let matches = Command::new("fantoch")
+        .version("0.1")
+        .author("Vitor Enes <vitorenesduarte@gmail.com>")
+        .about("Runs fantoch tools.")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("sim")
+                .about("Simulates the scenario described in a JSON or TOML file.")
+                .arg(
+                    Arg::new("scenario")
+                        .value_name("SCENARIO")
+                        .help("scenario file; it's parsed as TOML if it has the toml extension, and as JSON otherwise")
+                        .required_unless_present("resume")
+                        .conflicts_with("resume")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("resume")
+                        .long("resume")
+                        .value_name("RESUME")
+                        .help("checkpoint file from which the simulation is resumed (instead of simulating a scenario from the start)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("checkpoint")
+                        .long("checkpoint")
+                        .value_name("CHECKPOINT")
+                        .help("file where a checkpoint of the simulation is written (as JSON) if it stops before clients are done")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("checkpoint_interval")
+                        .long("checkpoint-interval")
+                        .value_name("CHECKPOINT_INTERVAL")
+                        .help("interval (in simulated milliseconds) with which the checkpoint is written")
+                        .requires("checkpoint")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .help("file where the metrics of the simulation are written (as JSON)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("trace")
+                        .long("trace")
+                        .value_name("TRACE")
+                        .help("file where the events of the simulation (commands submitted, messages sent and received, commits, executions and results) are written (as JSON, one event per line)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("max_sim_time")
+                        .long("max-sim-time")
+                        .value_name("MAX_SIM_TIME")
+                        .help("simulated time (in milliseconds) after which the simulation stops, even if clients are not done")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("max_wall_time")
+                        .long("max-wall-time")
+                        .value_name("MAX_WALL_TIME")
+                        .help("wall-clock time (in seconds) after which the simulation stops, even if clients are not done")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("progress")
+                        .long("progress")
+                        .value_name("PROGRESS")
+                        .help("interval (in seconds) with which the progress of the simulation is shown")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Simulates the scenario described in a JSON or TOML file with several protocols, and compares the latency of their clients.")
+                .arg(
+                    Arg::new("scenario")
+                        .value_name("SCENARIO")
+                        .help("scenario file; it's parsed as TOML if it has the toml extension, and as JSON otherwise")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("protocols")
+                        .long("protocols")
+                        .value_name("PROTOCOLS")
+                        .help("comma-separated list of the protocols compared (instead of the one in the scenario)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .help("file where the comparison report is written (as JSON)")
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        Some(("sim", matches)) => {
+            let (scenario, resume) = match matches.value_of("resume") {
+                Some(path) => {
+                    let checkpoint = Checkpoint::load(path)?;
+                    (checkpoint.scenario().clone(), Some(checkpoint))
+                }
+                None => {
+                    let path = matches
+                        .value_of("scenario")
+                        .expect("scenario should be set");
+                    (Scenario::load(path)?, None)
+                }
+            };
+            let checkpoint = Checkpoints {
+                path: matches.value_of("checkpoint"),
+                interval: parse_u64(matches.value_of("checkpoint_interval"))?
+                    .map(Duration::from_millis),
+                resume,
+            };
+            let limits = Limits {
+                max_sim_time: parse_u64(matches.value_of("max_sim_time"))?
+                    .map(Duration::from_millis),
+                max_wall_time: parse_u64(matches.value_of("max_wall_time"))?
+                    .map(Duration::from_secs),
+                progress: parse_u64(matches.value_of("progress"))?
+                    .map(Duration::from_secs),
+            };
+            sim(
+                scenario,
+                matches.value_of("output"),
+                matches.value_of("trace"),
+                limits,
+                checkpoint,
+            )
+        }
+        Some(("compare", matches)) => {
+            let scenario = matches
+                .value_of("scenario")
+                .expect("scenario should be set");
+            let protocols = matches
+                .value_of("protocols")
+                .expect("protocols should be set")
+                .split(',')
+                .map(str::trim)
+                .collect();
+            compare(scenario, protocols, matches.value_of("output"))
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+// Stop conditions and progress interval of a simulation.
+#[derive(Clone, Copy)]
+struct Limits {
+    max_sim_time: Option<Duration>,
+    max_wall_time: Option<Duration>,
+    progress: Option<Duration>,
+}
+
+// Checkpoint to resume the simulation from, and where and with which interval
+// (in simulated time) the simulation is checkpointed.
+struct Checkpoints<'a> {
+    path: Option<&'a str>,
+    interval: Option<Duration>,
+    resume: Option<Checkpoint>,
+}
+
+fn parse_u64(value: Option<&str>) -> Result<Option<u64>, Report> {
+    value
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .wrap_err_with(|| format!("invalid number {:?}", value))
+        })
+        .transpose()
+}
+
+fn sim(
+    scenario: Scenario,
+    output: Option<&str>,
+    trace: Option<&str>,
+    limits: Limits,
+    checkpoint: Checkpoints<'_>,
+) -> Result<(), Report> {
+    // progress is shown as info logs
+    let _guard = limits.progress.map(|_| {
+        fantoch::util::init_tracing_subscriber(None::<&str>, Some("info"), None)
+    });
+
+    let scenario = &scenario;
+    let checkpoint = &checkpoint;
+    let metrics = match scenario.protocol() {
+        "Basic" => simulate::<Basic>(scenario, trace, limits, checkpoint),
+        "Atlas" => {
+            simulate::<AtlasSequential>(scenario, trace, limits, checkpoint)
+        }
+        "EPaxos" => {
+            simulate::<EPaxosSequential>(scenario, trace, limits, checkpoint)
+        }
+        "FPaxos" => simulate::<FPaxos>(scenario, trace, limits, checkpoint),
+        "Tempo" => {
+            simulate::<TempoSequential>(scenario, trace, limits, checkpoint)
+        }
+        "Caesar" => {
+            simulate::<CaesarLocked>(scenario, trace, limits, checkpoint)
+        }
+        protocol => eyre::bail!("unsupported protocol {:?}", protocol),
+    }?;
+    show_metrics(&metrics);
+
+    if let Some(output) = output {
+        metrics.write(output)?;
+    }
+    Ok(())
+}
+
+// Simulates the scenario with protocol `P`, writing its event trace to file
+// `trace` (if set). If the simulation is resumed from a checkpoint, the event
+// trace only has the events after it.
+fn simulate<P: Protocol>(
+    scenario: &Scenario,
+    trace: Option<&str>,
+    limits: Limits,
+    checkpoint: &Checkpoints<'_>,
+) -> Result<SimulationMetrics, Report> {
+    let mut runner = scenario.runner::<P>()?;
+    let mut step_end = Duration::ZERO;
+    if let Some(resume) = &checkpoint.resume {
+        println!("resuming simulation at {:?}", resume.time());
+        resume.restore(&mut runner)?;
+        step_end = resume.time();
+    }
+    if trace.is_some() {
+        runner.record_trace();
+    }
+    if let Some(interval) = limits.progress {
+        runner.show_progress(interval);
+    }
+
+    // the simulation runs in steps of `checkpoint.interval` (if set), at the
+    // end of which it's checkpointed
+    let start = Instant::now();
+    loop {
+        let mut max_sim_time = limits.max_sim_time;
+        if let Some(interval) = checkpoint.interval {
+            step_end += interval;
+            max_sim_time =
+                Some(max_sim_time.map_or(step_end, |max| max.min(step_end)));
+        }
+        if let Some(max_sim_time) = max_sim_time {
+            runner.set_max_sim_time(max_sim_time);
+        }
+        if let Some(max_wall_time) = limits.max_wall_time {
+            runner.set_max_wall_time(
+                max_wall_time.saturating_sub(start.elapsed()),
+            );
+        }
+        runner.run(scenario.extra_sim_time());
+        if runner.stopped().is_none() {
+            break;
+        }
+        if let Some(path) = checkpoint.path {
+            Checkpoint::new(scenario, &runner).write(path)?;
+        }
+        let step_done = runner.stopped() == Some(StopCondition::SimTime)
+            && max_sim_time != limits.max_sim_time;
+        if !step_done {
+            break;
+        }
+    }
+    if let Some(stopped) = runner.stopped() {
+        println!("simulation stopped before clients were done: {:?}", stopped);
+    }
+    if let Some(failover) = runner.failover_metrics() {
+        println!(
+            "failover at {}ms | latency before: {}ms | latency spike: {}ms | recovery time: {}ms",
+            failover.at(),
+            failover.latency_before(),
+            failover.latency_spike(),
+            failover.recovery_time()
+        );
+    }
+    if let Some(path) = trace {
+        runner
+            .trace()
+            .expect("trace should be recorded")
+            .write(path)?;
+    }
+    Ok(runner.simulation_metrics())
+}
+
+fn compare(
+    scenario: &str,
+    protocols: Vec<&str>,
+    output: Option<&str>,
+) -> Result<(), Report> {
+    let scenario = Scenario::load(scenario)?;
+    let comparison = Comparison::new(scenario, protocols);
+    let report = comparison.run(|scenario| match scenario.protocol() {
+        "Basic" => scenario.run::<Basic>(),
+        "Atlas" => scenario.run::<AtlasSequential>(),
+        "EPaxos" => scenario.run::<EPaxosSequential>(),
+        "FPaxos" => {
+            // leaderless protocols ignore the leader, and so it can be set in
+            // the scenario for all protocols
+            if scenario.config()?.leader().is_none() {
+                eyre::bail!("FPaxos requires the config to set leader");
+            }
+            scenario.run::<FPaxos>()
+        }
+        "Tempo" => scenario.run::<TempoSequential>(),
+        "Caesar" => scenario.run::<CaesarLocked>(),
+        protocol => eyre::bail!("unsupported protocol {:?}", protocol),
+    })?;
+    print!("{}", report);
+
+    if let Some(output) = output {
+        report.write(output)?;
+    }
+    Ok(())
+}
+
+fn show_metrics(metrics: &SimulationMetrics) {
+    for (region, percentiles) in metrics.latency_percentiles() {
+        let (name, issued) = match &region {
+            Some(region) => (
+                region.name().as_str(),
+                metrics
+                    .region(region)
+                    .expect("region should have metrics")
+                    .issued_commands(),
+            ),
+            None => ("all", metrics.total().issued_commands()),
+        };
+        println!(
+            "region = {:<14} | issued: {:<6} | latency: {}",
+            name, issued, percentiles
+        );
+    }
+    let total = metrics.total();
+    let fp_percentage = total
+        .fast_path_ratio()
+        .map(|ratio| ratio * 100f64)
+        .unwrap_or(f64::NAN);
+    println!("commit latency   : {:?}", total.commit_latency());
+    println!("latency          : {:?}", total.latency());
+    println!("execution delay  : {:?}", total.execution_delay());
+    println!("fast path rate   : {:<7.1}", fp_percentage);
+}
@@ -3,12 +3,9 @@ mod common;
 use clap::{Command, Arg};
 use fantoch::config::Config;
 use fantoch::executor::Executor;
-use fantoch::run::rw::Rw;
+use fantoch::run::task::server::execution_logger;
 use fantoch::time::RunTime;
 use fantoch_ps::executor::GraphExecutor;
-use tokio::fs::File;
-
-const BUFFER_SIZE: usize = 8 * 1024; // 8KB
 
 #[tokio::main]
 async fn main() {
@@ -19,21 +16,27 @@ async fn main() {
     let mut executor = GraphExecutor::new(process_id, shard_id, config);
     let time = RunTime;
 
-    // open execution log file
-    let file = File::open(execution_log)
-        .await
-        .expect("execution log should exist");
-
-    // create log parse
-    let mut rw = Rw::from(BUFFER_SIZE, BUFFER_SIZE, file);
+    // read all segments of the execution log, in order
+    for segment in 0.. {
+        let rw = execution_logger::open_segment(&execution_log, segment, 0)
+            .await
+            .expect("execution log should be readable");
+        let mut rw = match rw {
+            Some(rw) => rw,
+            None => {
+                assert!(segment > 0, "execution log should exist");
+                break;
+            }
+        };
 
-    while let Some(execution_info) = rw.recv().await {
-        println!("adding {:?}", execution_info);
-        // result should be empty as we're not wait for any rifl
-        executor.handle(execution_info, &time);
-        let res: Vec<_> = executor.to_clients_iter().collect();
-        assert!(res.is_empty());
-        println!("{:?}", executor);
+        while let Some(execution_info) = rw.recv().await {
+            println!("adding {:?}", execution_info);
+            // result should be empty as we're not wait for any rifl
+            executor.handle(execution_info, &time);
+            let res: Vec<_> = executor.to_clients_iter().collect();
+            assert!(res.is_empty());
+            println!("{:?}", executor);
+        }
     }
 }
 
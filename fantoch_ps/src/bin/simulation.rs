@@ -3,21 +3,20 @@ use fantoch::config::Config;
 use fantoch::executor::{ExecutorMetrics, ExecutorMetricsKind};
 use fantoch::id::ProcessId;
 use fantoch::metrics::Histogram;
-use fantoch::planet::{Planet, Region};
+use fantoch::planet::{LatencyDataset, Planet, Region};
 use fantoch::protocol::{Protocol, ProtocolMetrics, ProtocolMetricsKind};
-use fantoch::sim::Runner;
+use fantoch::sim::{Runner, SimulationMetrics, Sweep, SweepResults};
 use fantoch::HashMap;
 use fantoch_ps::protocol::{
     AtlasSequential, CaesarLocked, EPaxosSequential, FPaxos, TempoSequential,
 };
-use rayon::prelude::*;
 use std::time::Duration;
 
-// latency dir
-const LATENCY_AWS: &str = "../latency_aws/2020_06_05";
-
 const STACK_SIZE: usize = 64 * 1024 * 1024; // 64mb
 
+// seed of the simulations (runs with the same seed are the same)
+const SEED: u64 = 0;
+
 macro_rules! config {
     ($n:expr, $f:expr, $tiny_quorums:expr, $clock_bump_interval:expr, $skip_fast_ack:expr, $wait_condition:expr) => {{
         let mut config = Config::new($n, $f);
@@ -62,7 +61,8 @@ fn main() {
 }
 
 fn aws_planet() -> (Planet, Vec<Region>) {
-    let planet = Planet::from(LATENCY_AWS);
+    let planet = Planet::from_dataset(&LatencyDataset::aws())
+        .expect("AWS latency dataset should be loaded");
     let regions = vec![
         Region::new("eu-west-1"),
         Region::new("us-west-1"),
@@ -162,11 +162,37 @@ fn tempo(aws: bool) {
     let (planet, regions) = if aws { aws_planet() } else { gcp_planet() };
     println!("{}", planet.distance_matrix(regions.clone()).unwrap());
 
-    let ns = vec![5];
-    // let clients_per_region = vec![64, 128, 256, 512];
-    // let pool_sizes = vec![100, 50, 10, 1];
-    // let conflicts = vec![0, 2, 10, 30, 50, 100];
-    let clients_per_region = vec![
+    // (n, f, tiny quorums, clock bump interval, skip fast ack, wait
+    // condition)
+    let config = config!(5, 2, false, None, false, true);
+
+    // clients workload
+    let shard_count = 1;
+    let key_gen = KeyGen::ConflictPool {
+        conflict_rate: 2,
+        pool_size: 1,
+    };
+    let keys_per_command = 1;
+    let commands_per_client = 200;
+    let payload_size = 0;
+    let workload = Workload::new(
+        shard_count,
+        key_gen,
+        keys_per_command,
+        commands_per_client,
+        payload_size,
+    );
+
+    let mut sweep = Sweep::new(planet, regions, config, workload, SEED);
+    // sweep.set_protocols(vec!["Atlas", "EPaxos", "FPaxos", "Tempo"]);
+    sweep.set_protocols(vec!["Caesar"]);
+    sweep.set_ns(vec![5]);
+    sweep.set_fs(vec![2]);
+    // to run leader-based protocols with each possible leader:
+    // sweep.set_leaders((1..=5).collect());
+    // sweep.set_conflict_rates(vec![0, 2, 10, 30, 50, 100]);
+    sweep.set_conflict_rates(vec![2]);
+    sweep.set_clients_per_region(vec![
         32,
         512,
         1024,
@@ -175,132 +201,23 @@ fn tempo(aws: bool) {
         1024 * 8,
         1024 * 16,
         1024 * 20,
-    ];
-    let pool_sizes = vec![1];
-    let conflicts = vec![2];
-
-    ns.into_par_iter().for_each(|n| {
-        let regions: Vec<_> = regions.clone().into_iter().take(n).collect();
-
-        let configs = if n == 3 {
-            vec![
-                // (protocol, (n, f, tiny quorums, clock bump interval, skip
-                // fast ack))
-                ("Atlas", config!(n, 1, false, None, false, false)),
-                // ("EPaxos", config!(n, 1, false, None, false, false)),
-                // ("FPaxos", config!(n, 1, false, None, false, false)),
-                ("Tempo", config!(n, 1, false, None, false, false)),
-            ]
-        } else if n == 5 {
-            vec![
-                // (protocol, (n, f, tiny quorums, clock bump interval, skip
-                // fast ack))
-                // ("Atlas", config!(n, 1, false, None, false, false)),
-                // ("Atlas", config!(n, 2, false, None, false, false)),
-                // ("EPaxos", config!(n, 0, false, None, false, false)),
-                // ("FPaxos", config!(n, 1, false, None, false, false)),
-                // ("FPaxos", config!(n, 2, false, None, false, false)),
-                // ("Tempo", config!(n, 1, false, None, false, false)),
-                // ("Tempo", config!(n, 2, false, None, false, false)),
-                // ("Caesar", config!(n, 2, false, None, false, false)),
-                ("Caesar", config!(n, 2, false, None, false, true)),
-            ]
-        } else {
-            panic!("unsupported number of processes {}", n);
-        };
-
-        pool_sizes.iter().for_each(|&pool_size| {
-            println!("POOL_SIZE: {:?}", pool_size);
-            conflicts.iter().for_each(|&conflict_rate| {
-                println!("CONFLICTS: {:?}", conflict_rate);
-                clients_per_region.par_iter().for_each(|&clients| {
-                    configs.par_iter().for_each(|&(protocol, mut config)| {
-                        // TODO check if the protocol is leader-based, and if
-                        // yes, run for all possible
-                        // leader configurations
-
-                        // set leader if FPaxos
-                        if protocol == "FPaxos" {
-                            config.set_leader(1);
-                        }
-
-                        // clients workload
-                        let shard_count = 1;
-                        let key_gen = KeyGen::ConflictPool {
-                            conflict_rate,
-                            pool_size,
-                        };
-                        let keys_per_command = 1;
-                        let commands_per_client = 200;
-                        let payload_size = 0;
-                        let workload = Workload::new(
-                            shard_count,
-                            key_gen,
-                            keys_per_command,
-                            commands_per_client,
-                            payload_size,
-                        );
-
-                        // process regions, client regions and planet
-                        let process_regions = regions.clone();
-                        let client_regions = regions.clone();
-                        let planet = planet.clone();
-
-                        let (metrics, client_latencies) = match protocol {
-                            "Atlas" => run::<AtlasSequential>(
-                                config,
-                                workload,
-                                clients,
-                                process_regions,
-                                client_regions,
-                                planet,
-                            ),
-                            "EPaxos" => run::<EPaxosSequential>(
-                                config,
-                                workload,
-                                clients,
-                                process_regions,
-                                client_regions,
-                                planet,
-                            ),
-                            "FPaxos" => run::<FPaxos>(
-                                config,
-                                workload,
-                                clients,
-                                process_regions,
-                                client_regions,
-                                planet,
-                            ),
-                            "Tempo" => run::<TempoSequential>(
-                                config,
-                                workload,
-                                clients,
-                                process_regions,
-                                client_regions,
-                                planet,
-                            ),
-                            "Caesar" => run::<CaesarLocked>(
-                                config,
-                                workload,
-                                clients,
-                                process_regions,
-                                client_regions,
-                                planet,
-                            ),
-                            _ => panic!("unsupported protocol {:?}", protocol),
-                        };
-                        handle_run_result(
-                            protocol,
-                            config,
-                            clients,
-                            metrics,
-                            client_latencies,
-                        );
-                    })
-                })
-            })
-        })
+    ]);
+
+    let results = sweep.run(|mut run| match run.point().protocol() {
+        "Atlas" => run.run::<AtlasSequential>(),
+        "EPaxos" => run.run::<EPaxosSequential>(),
+        "FPaxos" => {
+            // unless the point sets a leader, process 1 is the leader
+            if run.point().leader().is_none() {
+                run.config_mut().set_leader(1);
+            }
+            run.run::<FPaxos>()
+        }
+        "Tempo" => run.run::<TempoSequential>(),
+        "Caesar" => run.run::<CaesarLocked>(),
+        protocol => panic!("unsupported protocol {:?}", protocol),
     });
+    handle_sweep_results(results);
 }
 
 #[allow(dead_code)]
@@ -339,9 +256,9 @@ fn fairest_leader() {
             let client_regions = regions.clone();
             let planet = planet.clone();
 
-            let (_, client_latencies) = run::<FPaxos>(
+            let (_, client_latencies, _) = run::<FPaxos>(
                 config,
-                workload,
+                workload.clone(),
                 clients_per_region,
                 process_regions,
                 client_regions,
@@ -417,9 +334,9 @@ fn equidistant<P: Protocol>(protocol_name: &str) {
         // config
         let config = Config::new(n, f);
 
-        let (process_metrics, client_latencies) = run::<P>(
+        let (process_metrics, client_latencies, simulation_metrics) = run::<P>(
             config,
-            workload,
+            workload.clone(),
             clients_per_region,
             process_regions,
             client_regions,
@@ -431,6 +348,7 @@ fn equidistant<P: Protocol>(protocol_name: &str) {
             clients_per_region,
             process_metrics,
             client_latencies,
+            simulation_metrics,
         );
     }
 }
@@ -492,9 +410,9 @@ fn increasing_regions<P: Protocol>(protocol_name: &str) {
         // client regions
         let client_regions = regions13.clone();
 
-        let (process_metrics, client_latencies) = run::<P>(
+        let (process_metrics, client_latencies, simulation_metrics) = run::<P>(
             config,
-            workload,
+            workload.clone(),
             clients_per_region,
             process_regions,
             client_regions,
@@ -506,6 +424,7 @@ fn increasing_regions<P: Protocol>(protocol_name: &str) {
             clients_per_region,
             process_metrics,
             client_latencies,
+            simulation_metrics,
         );
     }
 }
@@ -520,6 +439,7 @@ fn run<P: Protocol>(
 ) -> (
     HashMap<ProcessId, (ProtocolMetrics, ExecutorMetrics)>,
     HashMap<Region, (usize, Histogram)>,
+    SimulationMetrics,
 ) {
     // compute number of regions and total number of expected commands per
     // region
@@ -535,6 +455,7 @@ fn run<P: Protocol>(
         clients_per_region,
         process_regions,
         client_regions,
+        SEED,
     );
     let (metrics, _executors_monitors, client_latencies) = runner.run(None);
 
@@ -551,7 +472,7 @@ fn run<P: Protocol>(
         );
     }
 
-    (metrics, client_latencies)
+    (metrics, client_latencies, runner.simulation_metrics())
 }
 
 fn handle_run_result(
@@ -560,9 +481,8 @@ fn handle_run_result(
     clients_per_region: usize,
     metrics: HashMap<ProcessId, (ProtocolMetrics, ExecutorMetrics)>,
     client_latencies: HashMap<Region, (usize, Histogram)>,
+    simulation_metrics: SimulationMetrics,
 ) {
-    let mut fast_paths = 0;
-    let mut slow_paths = 0;
     let mut wait_condition_delay = Histogram::new();
     let mut commit_latency = Histogram::new();
     let mut execution_delay = Histogram::new();
@@ -576,14 +496,6 @@ fn handle_run_result(
             println!("  executor metrics:");
             println!("{:?}", executor_metrics);
 
-            let process_fast_paths = process_metrics
-                .get_aggregated(ProtocolMetricsKind::FastPath)
-                .cloned()
-                .unwrap_or_default();
-            let process_slow_paths = process_metrics
-                .get_aggregated(ProtocolMetricsKind::SlowPath)
-                .cloned()
-                .unwrap_or_default();
             let process_wait_condition_delay = process_metrics
                 .get_collected(ProtocolMetricsKind::WaitConditionDelay);
             let process_commit_latency = process_metrics
@@ -591,8 +503,6 @@ fn handle_run_result(
             let executor_execution_delay = executor_metrics
                 .get_collected(ExecutorMetricsKind::ExecutionDelay);
 
-            fast_paths += process_fast_paths;
-            slow_paths += process_slow_paths;
             if let Some(h) = process_wait_condition_delay {
                 wait_condition_delay.merge(h);
             }
@@ -605,8 +515,21 @@ fn handle_run_result(
         },
     );
     // compute the percentage of fast paths
-    let total = fast_paths + slow_paths;
-    let fp_percentage = (fast_paths as f64 * 100f64) / total as f64;
+    let fp_percentage = simulation_metrics
+        .total()
+        .fast_path_ratio()
+        .map(|ratio| ratio * 100f64)
+        .unwrap_or(f64::NAN);
+
+    // show per-region stats
+    for (region, region_metrics) in simulation_metrics.regions() {
+        println!(
+            "region = {:<14} | commit latency: {:?} | execution delay: {:?}",
+            region.name(),
+            region_metrics.commit_latency(),
+            region_metrics.execution_delay()
+        );
+    }
 
     // compute clients stats
     let execution_latency = client_latencies.into_iter().fold(
@@ -642,4 +565,49 @@ fn handle_run_result(
     println!("{} | execution latency   : {:?}", prefix, execution_latency);
     println!("{} | execution delay     : {:?}", prefix, execution_delay);
     println!("{} | fast path rate      : {:<7.1}", prefix, fp_percentage);
+    show_latency_percentiles(&prefix, &simulation_metrics);
+}
+
+fn handle_sweep_results(results: SweepResults) {
+    for (point, simulation_metrics) in results.iter() {
+        let total = simulation_metrics.total();
+        let fp_percentage = total
+            .fast_path_ratio()
+            .map(|ratio| ratio * 100f64)
+            .unwrap_or(f64::NAN);
+        let mut prefix = format!(
+            "{:<8} n = {} f = {} conflicts = {:<3} c = {:<3}",
+            point.protocol(),
+            point.n(),
+            point.f(),
+            point.conflict_rate(),
+            point.clients_per_region()
+        );
+        if let Some(leader) = point.leader() {
+            prefix = format!("{} leader = {}", prefix, leader);
+        }
+        println!(
+            "{} | commit latency      : {:?}",
+            prefix,
+            total.commit_latency()
+        );
+        println!("{} | execution latency   : {:?}", prefix, total.latency());
+        println!(
+            "{} | execution delay     : {:?}",
+            prefix,
+            total.execution_delay()
+        );
+        println!("{} | fast path rate      : {:<7.1}", prefix, fp_percentage);
+        show_latency_percentiles(&prefix, simulation_metrics);
+    }
+}
+
+fn show_latency_percentiles(
+    prefix: &str,
+    simulation_metrics: &SimulationMetrics,
+) {
+    for (region, percentiles) in simulation_metrics.latency_percentiles() {
+        let name = region.as_ref().map(Region::name).map_or("all", |name| name);
+        println!("{} | latency {:<12}: {}", prefix, name, percentiles);
+    }
 }
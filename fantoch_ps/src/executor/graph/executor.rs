@@ -1,20 +1,22 @@
 use crate::executor::graph::DependencyGraph;
 use crate::protocol::common::graph::Dependency;
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::executor::{
-    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult,
+    DelayedReads, ExecutionOrderMonitor, Executor, ExecutorMetrics,
+    ExecutorMetricsKind, ExecutorResult, LocalRead,
 };
-use fantoch::id::{Dot, ProcessId, ShardId};
-use fantoch::kvs::KVStore;
+use fantoch::id::{Dot, ProcessId, Rifl, ShardId};
+use fantoch::kvs::{KVOp, KVOpResult, KVStore, Key, Store, WatchNotification};
 use fantoch::protocol::MessageIndex;
 use fantoch::time::SysTime;
-use fantoch::HashSet;
 use fantoch::{debug, trace};
+use fantoch::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
 use std::iter::FromIterator;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct GraphExecutor {
@@ -25,7 +27,12 @@ pub struct GraphExecutor {
     graph: DependencyGraph,
     store: KVStore,
     to_clients: VecDeque<ExecutorResult>,
+    // local reads waiting for the writes they should observe
+    delayed_reads: DelayedReads,
     to_executors: Vec<(ShardId, GraphExecutionInfo)>,
+    // results of speculative executions not yet confirmed (along with the
+    // time at which they happened)
+    speculated: HashMap<(Rifl, Key), (u64, Vec<KVOpResult>)>,
 }
 
 impl Executor for GraphExecutor {
@@ -35,9 +42,16 @@ impl Executor for GraphExecutor {
         // this value will be overwritten
         let executor_index = 0;
         let graph = DependencyGraph::new(process_id, shard_id, &config);
-        let store = KVStore::new(config.executor_monitor_execution_order());
+        let mut store = KVStore::new(config.executor_monitor_execution_order());
+        store.set_capacity(config.executor_store_capacity());
+        store.set_dedup_window(config.executor_dedup_window());
+        store.set_hot_keys(config.executor_hot_keys());
+        // with more than one shard, each store only has part of the keys
+        store.set_scans(config.shard_count() == 1);
         let to_clients = Default::default();
+        let delayed_reads = DelayedReads::new();
         let to_executors = Default::default();
+        let speculated = Default::default();
         Self {
             executor_index,
             process_id,
@@ -46,7 +60,9 @@ impl Executor for GraphExecutor {
             graph,
             store,
             to_clients,
+            delayed_reads,
             to_executors,
+            speculated,
         }
     }
 
@@ -70,8 +86,15 @@ impl Executor for GraphExecutor {
         match info {
             GraphExecutionInfo::Add { dot, cmd, deps } => {
                 if self.config.execute_at_commit() {
-                    self.execute(cmd);
+                    let now = time.millis();
+                    let timestamps = ExecutionTimestamps::new(now, now);
+                    self.execute(cmd, timestamps, time);
                 } else {
+                    if self.config.executor_speculative() {
+                        // execute the command right away, predicting that
+                        // the final order will be the order of commit
+                        self.speculate(&cmd, time);
+                    }
                     // handle new command
                     let deps = Vec::from_iter(deps);
                     self.graph.handle_add(dot, cmd, deps, time);
@@ -90,16 +113,60 @@ impl Executor for GraphExecutor {
                 self.graph.handle_executed(dots, time);
             }
         }
+        self.store.update_metrics(self.graph.metrics_mut());
+    }
+
+    fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store.set_store(store);
+    }
+
+    fn local_read(&mut self, read: LocalRead) {
+        match self.delayed_reads.delay(read, &self.store) {
+            Some(read) => self.execute_local_read(read),
+            None => self
+                .graph
+                .metrics_mut()
+                .aggregate(ExecutorMetricsKind::DelayedReads, 1),
+        }
+    }
+
+    fn store_index(_key: &Key) -> Option<(usize, usize)> {
+        // the store is only kept by the main executor (the one handling
+        // `GraphExecutionInfo::Add`)
+        main_executor()
+    }
+
+    fn watch(&mut self, key: Key) {
+        self.store.watch(key);
+    }
+
+    fn unwatch(&mut self, key: &Key) {
+        self.store.unwatch(key);
     }
 
     fn to_clients(&mut self) -> Option<ExecutorResult> {
+        // execute the delayed reads whose writes have meanwhile been applied
+        while let Some(read) = self.delayed_reads.next_ready(&self.store) {
+            self.execute_local_read(read);
+        }
         self.to_clients.pop_front()
     }
 
+    fn to_watchers(&mut self) -> Vec<WatchNotification> {
+        self.store.take_notifications()
+    }
+
     fn to_executors(&mut self) -> Option<(ShardId, GraphExecutionInfo)> {
         self.to_executors.pop()
     }
 
+    fn execution_info_dot(info: &Self::ExecutionInfo) -> Option<Dot> {
+        match info {
+            GraphExecutionInfo::Add { dot, .. } => Some(*dot),
+            _ => None,
+        }
+    }
+
     fn parallel() -> bool {
         true
     }
@@ -123,17 +190,17 @@ impl GraphExecutor {
         }
     }
 
-    fn fetch_commands_to_execute(&mut self, _time: &dyn SysTime) {
+    fn fetch_commands_to_execute(&mut self, time: &dyn SysTime) {
         // get more commands that are ready to be executed
-        while let Some(cmd) = self.graph.command_to_execute() {
+        while let Some((cmd, timestamps)) = self.graph.command_to_execute() {
             trace!(
                 "p{}: @{} GraphExecutor::comands_to_execute {:?} | time = {}",
                 self.process_id,
                 self.executor_index,
                 cmd.rifl(),
-                _time.millis()
+                time.millis()
             );
-            self.execute(cmd);
+            self.execute(cmd, timestamps, time);
         }
     }
 
@@ -181,10 +248,147 @@ impl GraphExecutor {
         }
     }
 
-    fn execute(&mut self, cmd: Command) {
-        // execute the command
-        let results = cmd.execute(self.shard_id, &mut self.store);
-        self.to_clients.extend(results);
+    fn execute(
+        &mut self,
+        cmd: Command,
+        timestamps: ExecutionTimestamps,
+        time: &dyn SysTime,
+    ) {
+        let timestamps = timestamps
+            .with_submit(cmd.submit_time())
+            .with_fast_path(cmd.fast_path());
+        // execute the command at the logical time assigned by its coordinator
+        self.store
+            .set_time_on(Self::logical_time(&cmd), cmd.keys(self.shard_id));
+        let conditional = cmd.has_conditions_on(self.shard_id);
+        if conditional && self.config.executor_speculative() {
+            // commands with ops that may fail are never speculated, and they
+            // should not observe speculative executions: undo the ones on
+            // their keys
+            let keys: Vec<_> = cmd.keys(self.shard_id).cloned().collect();
+            for key in keys {
+                self.rollback(&key);
+            }
+        }
+        if conditional || !self.config.executor_speculative() {
+            // execute the command
+            let results = cmd
+                .execute(self.shard_id, &mut self.store)
+                .map(|result| result.with_timestamps(timestamps));
+            self.to_clients.extend(results);
+            return;
+        }
+
+        let rifl = cmd.rifl();
+        let read_only = cmd.read_only();
+        for (key, ops) in cmd.into_iter(self.shard_id) {
+            // scans are never speculated, and they should not observe
+            // speculative executions: undo the ones on the keys scanned
+            for op in ops.iter() {
+                if let KVOp::Scan { end, .. } = op {
+                    for scanned in self.store.speculated_keys(&key, end) {
+                        self.rollback(&scanned);
+                    }
+                }
+            }
+
+            let partial_results = if self.store.confirm(&key, rifl) {
+                // the prediction was correct: reuse the speculative results
+                let (speculated_at, partial_results) = self
+                    .speculated
+                    .remove(&(rifl, key.clone()))
+                    .expect("confirmed speculative execution should exist");
+                let gain = time.millis() - speculated_at;
+                self.graph
+                    .metrics_mut()
+                    .collect(ExecutorMetricsKind::SpeculationGain, gain);
+                partial_results
+            } else {
+                // the prediction was wrong: undo all speculative executions
+                // on this key, and execute the command
+                self.rollback(&key);
+                let ops = Arc::try_unwrap(ops)
+                    .unwrap_or_else(|ops| ops.as_ref().clone());
+                if read_only {
+                    self.store.execute_as(&key, ops, rifl, true)
+                } else {
+                    self.store.execute(&key, ops, rifl)
+                }
+            };
+            self.to_clients.push_back(
+                ExecutorResult::new(rifl, key, partial_results)
+                    .with_timestamps(timestamps),
+            );
+        }
+    }
+
+    fn execute_local_read(&mut self, read: LocalRead) {
+        // local reads should not observe speculative executions: undo the ones
+        // on the keys read
+        self.rollback(&read.key);
+        for op in read.ops.iter() {
+            if let KVOp::Scan { end, .. } = op {
+                for scanned in self.store.speculated_keys(&read.key, end) {
+                    self.rollback(&scanned);
+                }
+            }
+        }
+        self.to_clients.push_back(read.execute(&mut self.store));
+    }
+
+    #[allow(clippy::ptr_arg)]
+    fn rollback(&mut self, key: &Key) {
+        let rolled_back = self.store.rollback(key);
+        trace!(
+            "p{}: @{} GraphExecutor::rollback {:?} on key {:?}",
+            self.process_id,
+            self.executor_index,
+            rolled_back,
+            key
+        );
+        self.graph.metrics_mut().aggregate(
+            ExecutorMetricsKind::Rollbacks,
+            rolled_back.len() as u64,
+        );
+        for rolled_back_rifl in rolled_back {
+            self.speculated.remove(&(rolled_back_rifl, key.clone()));
+        }
+    }
+
+    fn speculate(&mut self, cmd: &Command, time: &dyn SysTime) {
+        // scans read keys other than the ones in the command, and thus can't
+        // be speculatively executed
+        if cmd.has_scans_on(self.shard_id) {
+            return;
+        }
+        // ops that may fail are checked on all keys of the command at once
+        // (so that either all or none of them is executed), and thus also
+        // can't be speculatively executed key by key
+        if cmd.has_conditions_on(self.shard_id) {
+            return;
+        }
+        // speculative executions are only confirmed if they were executed in
+        // the same order, and thus at the same time
+        self.store
+            .set_time_on(Self::logical_time(cmd), cmd.keys(self.shard_id));
+        let rifl = cmd.rifl();
+        for (key, ops) in cmd.iter(self.shard_id) {
+            let partial_results =
+                self.store.speculate(key, ops.as_ref().clone(), rifl);
+            self.speculated
+                .insert((rifl, key.clone()), (time.millis(), partial_results));
+        }
+    }
+
+    // Returns the logical time at which `cmd` is executed. Since replicas
+    // must expire values identically, commands without one can't be executed.
+    fn logical_time(cmd: &Command) -> u64 {
+        cmd.logical_time().unwrap_or_else(|| {
+            panic!(
+                "command {:?} should have a logical time assigned by its coordinator",
+                cmd.rifl()
+            )
+        })
     }
 }
 
@@ -231,19 +435,19 @@ impl GraphExecutionInfo {
     }
 }
 
-impl MessageIndex for GraphExecutionInfo {
-    fn index(&self) -> Option<(usize, usize)> {
-        const MAIN_INDEX: usize = 0;
-        const SECONDARY_INDEX: usize = 1;
+const MAIN_INDEX: usize = 0;
+const SECONDARY_INDEX: usize = 1;
 
-        const fn main_executor() -> Option<(usize, usize)> {
-            Some((0, MAIN_INDEX))
-        }
+const fn main_executor() -> Option<(usize, usize)> {
+    Some((0, MAIN_INDEX))
+}
 
-        const fn secondary_executor() -> Option<(usize, usize)> {
-            Some((0, SECONDARY_INDEX))
-        }
+const fn secondary_executor() -> Option<(usize, usize)> {
+    Some((0, SECONDARY_INDEX))
+}
 
+impl MessageIndex for GraphExecutionInfo {
+    fn index(&self) -> Option<(usize, usize)> {
         match self {
             Self::Add { .. } => main_executor(),
             Self::Request { .. } => secondary_executor(),
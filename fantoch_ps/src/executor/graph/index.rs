@@ -41,6 +41,11 @@ impl VertexIndex {
         self.index.iter().map(|entry| *entry.key())
     }
 
+    /// Returns the number of vertices indexed.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
     pub fn find(&self, dot: &Dot) -> Option<VertexRef<'_>> {
         self.index.get(dot)
     }
@@ -201,6 +206,12 @@ impl PendingIndex {
         None
     }
 
+    /// Returns the pending dots for a given dependency dot (without removing
+    /// them).
+    pub fn get(&self, dep_dot: &Dot) -> Option<&HashSet<Dot>> {
+        self.index.get(dep_dot)
+    }
+
     /// Finds all pending dots for a given dependency dot.
     pub fn remove(&mut self, dep_dot: &Dot) -> Option<HashSet<Dot>> {
         self.index.remove(dep_dot)
@@ -4,6 +4,10 @@ mod tarjan;
 /// This module contains the definition of `VertexIndex` and `PendingIndex`.
 mod index;
 
+// This module contains the implementation of a parallel SCC finder used when
+// the pending graph is large.
+mod parallel;
+
 /// This modules contains the definition of `GraphExecutor` and
 /// `GraphExecutionInfo`.
 mod executor;
@@ -14,7 +18,7 @@ pub use executor::{GraphExecutionInfo, GraphExecutor};
 use self::index::{PendingIndex, VertexIndex};
 use self::tarjan::{FinderResult, TarjanSCCFinder, Vertex, SCC};
 use crate::protocol::common::graph::Dependency;
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::executor::{ExecutorMetrics, ExecutorMetricsKind};
 use fantoch::id::{Dot, ProcessId, ShardId};
@@ -46,17 +50,21 @@ pub enum RequestReply {
 pub struct DependencyGraph {
     executor_index: usize,
     process_id: ProcessId,
+    shard_count: usize,
     executed_clock: AEClock<ProcessId>,
     vertex_index: VertexIndex,
     pending_index: PendingIndex,
     finder: TarjanSCCFinder,
+    // if set, pending graphs with at least this number of vertices are
+    // searched with the parallel SCC finder
+    parallel_scc_threshold: Option<usize>,
     metrics: ExecutorMetrics,
     // worker 0 (handles commands):
     // - adds new commands `to_execute`
     // - `out_requests` dependencies to be able to order commands
     // - notifies remaining workers about what's been executed through
     //   `added_to_executed_clock`
-    to_execute: VecDeque<Command>,
+    to_execute: VecDeque<(Command, ExecutionTimestamps)>,
     out_requests: HashMap<ShardId, HashSet<Dot>>,
     added_to_executed_clock: HashSet<Dot>,
     // auxiliary workers (handles requests):
@@ -97,6 +105,7 @@ impl DependencyGraph {
         let pending_index = PendingIndex::new(shard_id, *config);
         // create finder
         let finder = TarjanSCCFinder::new(process_id, *config);
+        let parallel_scc_threshold = config.executor_parallel_scc_threshold();
         let metrics = ExecutorMetrics::new();
         // create to execute
         let to_execute = Default::default();
@@ -109,10 +118,12 @@ impl DependencyGraph {
         DependencyGraph {
             executor_index,
             process_id,
+            shard_count: config.shard_count(),
             executed_clock,
             vertex_index,
             pending_index,
             finder,
+            parallel_scc_threshold,
             metrics,
             to_execute,
             out_requests,
@@ -128,7 +139,9 @@ impl DependencyGraph {
 
     /// Returns a new command ready to be executed.
     #[must_use]
-    pub fn command_to_execute(&mut self) -> Option<Command> {
+    pub fn command_to_execute(
+        &mut self,
+    ) -> Option<(Command, ExecutionTimestamps)> {
         self.to_execute.pop_front()
     }
 
@@ -157,12 +170,19 @@ impl DependencyGraph {
     #[cfg(test)]
     fn commands_to_execute(&mut self) -> VecDeque<Command> {
         std::mem::take(&mut self.to_execute)
+            .into_iter()
+            .map(|(cmd, _)| cmd)
+            .collect()
     }
 
     fn metrics(&self) -> &ExecutorMetrics {
         &self.metrics
     }
 
+    fn metrics_mut(&mut self) -> &mut ExecutorMetrics {
+        &mut self.metrics
+    }
+
     fn cleanup(&mut self, time: &dyn SysTime) {
         trace!(
             "p{}: @{} Graph::cleanup | time = {}",
@@ -511,14 +531,16 @@ impl DependencyGraph {
             dots.push(dot);
 
             // get command
-            let (duration_ms, cmd) = vertex.into_command(time);
+            let (timestamps, cmd) = vertex.into_command(time);
 
             // save execution delay metric
-            self.metrics
-                .collect(ExecutorMetricsKind::ExecutionDelay, duration_ms);
+            self.metrics.collect(
+                ExecutorMetricsKind::ExecutionDelay,
+                timestamps.execution_delay(),
+            );
 
             // add command to commands to be executed
-            self.to_execute.push_back(cmd);
+            self.to_execute.push_back((cmd, timestamps));
         })
     }
 
@@ -560,6 +582,14 @@ impl DependencyGraph {
         time: &dyn SysTime,
     ) {
         assert_eq!(self.executor_index, 0);
+        if !dots.is_empty() && self.use_parallel_finder() {
+            // when new SCCs were found and the pending graph is large, instead
+            // of trying pending commands one by one, compute at once all SCCs
+            // that are ready in the subgraph reachable from the commands
+            // pending on the new SCCs; the dots executed are then handled
+            // below in order to update `pending_index`
+            self.find_sccs_parallel(&mut dots, total_scc_count, time);
+        }
         while let Some(dot) = dots.pop() {
             // get pending commands that depend on this dot
             if let Some(pending) = self.pending_index.remove(&dot) {
@@ -586,6 +616,55 @@ impl DependencyGraph {
         // possible to be executed, so we give up!
     }
 
+    fn use_parallel_finder(&self) -> bool {
+        self.parallel_scc_threshold
+            .map(|threshold| self.vertex_index.len() >= threshold)
+            .unwrap_or(false)
+    }
+
+    fn find_sccs_parallel(
+        &mut self,
+        dots: &mut Vec<Dot>,
+        total_scc_count: &mut usize,
+        time: &dyn SysTime,
+    ) {
+        assert_eq!(self.executor_index, 0);
+        // only the commands pending on the new dots may have become ready
+        let roots: Vec<_> = dots
+            .iter()
+            .filter_map(|dot| self.pending_index.get(dot))
+            .flatten()
+            .cloned()
+            .collect();
+        let threshold = self
+            .parallel_scc_threshold
+            .expect("the parallel SCC finder should only be used if enabled");
+        let sccs = parallel::ready_sccs(
+            self.process_id,
+            &self.vertex_index,
+            &self.executed_clock,
+            roots,
+            threshold,
+        );
+        debug!(
+            "p{}: @{} Graph::find_sccs_parallel found {} SCCs | time = {}",
+            self.process_id,
+            self.executor_index,
+            sccs.len(),
+            time.millis()
+        );
+        for scc in sccs {
+            *total_scc_count += scc.len();
+            for dot in scc.iter() {
+                self.executed_clock.add(&dot.source(), dot.sequence());
+                if self.shard_count > 1 {
+                    self.added_to_executed_clock.insert(*dot);
+                }
+            }
+            self.save_scc(scc, dots, time);
+        }
+    }
+
     fn try_pending(
         &mut self,
         pending: HashSet<Dot>,
@@ -691,8 +770,11 @@ impl fmt::Debug for DependencyGraph {
 mod tests {
     use super::*;
     use crate::util;
+    use fantoch::command::ShardMap;
+    use fantoch::executor::conformance::ConformanceCase;
+    use fantoch::executor::Executor;
     use fantoch::id::{ClientId, Rifl, ShardId};
-    use fantoch::kvs::{KVOp, Key};
+    use fantoch::kvs::{KVOp, Key, Value};
     use fantoch::time::RunTime;
     use fantoch::HashMap;
     use permutator::{Combination, Permutation};
@@ -729,14 +811,14 @@ mod tests {
         // cmd 0
         let cmd_0 = Command::from(
             Rifl::new(1, 1),
-            vec![(String::from("A"), KVOp::Put(String::new()))],
+            vec![(String::from("A"), KVOp::Put(Value::new()))],
         );
         let deps_0 = vec![dep(dot_1, shard_id)];
 
         // cmd 1
         let cmd_1 = Command::from(
             Rifl::new(2, 1),
-            vec![(String::from("A"), KVOp::Put(String::new()))],
+            vec![(String::from("A"), KVOp::Put(Value::new()))],
         );
         let deps_1 = vec![dep(dot_0, shard_id)];
 
@@ -1037,20 +1119,65 @@ mod tests {
         let total_order = check_termination(n, args.clone());
         args.permutation().for_each(|permutation| {
             println!("permutation = {:?}", permutation);
-            let sorted = check_termination(n, permutation);
+            let sorted = check_termination(n, permutation.clone());
+            assert_eq!(total_order, sorted);
+            // with a threshold of 1, the parallel finder is always used
+            let sorted = check_termination_with(n, Some(1), permutation);
             assert_eq!(total_order, sorted);
         });
     }
 
+    #[test]
+    fn parallel_scc() {
+        let shard_id = 0;
+        let n = 3;
+        let iterations = 10;
+        let events_per_process = 20;
+
+        (0..iterations).for_each(|_| {
+            let args = random_adds(shard_id, n, events_per_process);
+            let sequential = check_termination(n, args.clone());
+            // with a threshold of 1, the parallel finder is always used
+            let parallel = check_termination_with(n, Some(1), args);
+            assert_eq!(sequential, parallel);
+        });
+    }
+
+    #[test]
+    fn parallel_scc_reverse_order() {
+        let shard_id = 0;
+        let n = 5;
+        let events_per_process = 100;
+
+        let mut args = random_adds(shard_id, n, events_per_process);
+        // deliver commands in reverse order so that most are pending until the
+        // last ones arrive (and thus the parallel finder is used on a large
+        // pending graph)
+        args.sort_by_key(|(dot, _, _)| std::cmp::Reverse(*dot));
+
+        let sequential = check_termination(n, args.clone());
+        let parallel = check_termination_with(n, Some(100), args);
+        assert_eq!(sequential, parallel);
+    }
+
     fn check_termination(
         n: usize,
         args: Vec<(Dot, Option<BTreeSet<Key>>, HashSet<Dot>)>,
+    ) -> BTreeMap<Key, Vec<Rifl>> {
+        check_termination_with(n, None, args)
+    }
+
+    fn check_termination_with(
+        n: usize,
+        parallel_scc_threshold: Option<usize>,
+        args: Vec<(Dot, Option<BTreeSet<Key>>, HashSet<Dot>)>,
     ) -> BTreeMap<Key, Vec<Rifl>> {
         // create queue
         let process_id = 1;
         let shard_id = 0;
         let f = 1;
-        let config = Config::new(n, f);
+        let mut config = Config::new(n, f);
+        config.set_executor_parallel_scc_threshold(parallel_scc_threshold);
         let mut queue = DependencyGraph::new(process_id, shard_id, &config);
         let time = RunTime;
         let mut all_rifls = HashSet::new();
@@ -1071,7 +1198,7 @@ mod tests {
                 BTreeSet::from_iter(vec![String::from("CONF")])
             });
             let ops = keys.into_iter().map(|key| {
-                let value = String::from("");
+                let value = Value::new();
                 (key, KVOp::Put(value))
             });
             let cmd = Command::from(rifl, ops);
@@ -1144,7 +1271,7 @@ mod tests {
             let rifl = Rifl::new(1, 1);
             Command::from(
                 rifl,
-                vec![(String::from("CONF"), KVOp::Put(String::new()))],
+                vec![(String::from("CONF"), KVOp::Put(Value::new()))],
             )
         };
 
@@ -1346,4 +1473,240 @@ mod tests {
             panic!("FinderInfo::MissingDependency not found");
         }
     }
+
+    #[test]
+    #[should_panic(expected = "should have a logical time")]
+    fn execute_without_logical_time() {
+        let process_id = 1;
+        let shard_id = 0;
+        let config = Config::new(3, 1);
+        let mut executor = GraphExecutor::new(process_id, shard_id, config);
+
+        // commands that were not assigned a logical time by their coordinator
+        // can't be executed
+        let cmd = Command::from(
+            Rifl::new(1, 1),
+            vec![(String::from("a"), KVOp::Put(Value::from("1")))],
+        );
+        let info = GraphExecutionInfo::add(Dot::new(1, 1), cmd, HashSet::new());
+        executor.handle(info, &RunTime);
+    }
+
+    fantoch::executor_conformance!(
+        graph_executor_conformance,
+        GraphExecutor,
+        conformance_cases(false)
+    );
+
+    // with speculative execution, commands are executed in the order they are
+    // handled, and then rolled back if that order was wrong
+    fantoch::executor_conformance!(
+        graph_executor_speculative_conformance,
+        GraphExecutor,
+        conformance_cases(true)
+    );
+
+    fn conformance_cases(
+        speculative: bool,
+    ) -> Vec<ConformanceCase<GraphExecutor>> {
+        let process_id = 1;
+        let shard_id = 0;
+        let n = 3;
+        let f = 1;
+        let mut config = Config::new(n, f);
+        config.set_executor_speculative(speculative);
+        let keys = vec![String::from("a"), String::from("b")];
+
+        // commands are assigned a logical time by their coordinator (here, the
+        // sequence of their rifl)
+        let submitted = |mut cmd: Command| {
+            cmd.set_logical_time(cmd.rifl().sequence());
+            cmd
+        };
+
+        // commands are submitted by processes in a round-robin fashion, and
+        // depend on the latest command on each of the keys they access
+        let chain = |command_count: u64| {
+            let mut latest = HashMap::new();
+            let mut commands = Vec::new();
+            let mut infos = Vec::new();
+            for seq in 1..=command_count {
+                let source = (seq % n as u64) as ProcessId + 1;
+                let dot = Dot::new(source, seq);
+                let rifl = Rifl::new(source as ClientId, seq);
+                let cmd_keys: Vec<_> = keys
+                    .iter()
+                    .take(1 + seq as usize % keys.len())
+                    .cloned()
+                    .collect();
+                let deps = cmd_keys
+                    .iter()
+                    .filter_map(|key| latest.insert(key.clone(), dot))
+                    .map(|dep_dot| dep(dep_dot, shard_id))
+                    .collect();
+                let ops = cmd_keys.into_iter().map(|key| {
+                    let op = if seq % 2 == 0 {
+                        KVOp::Get
+                    } else {
+                        KVOp::Put(Value::from(seq.to_string()))
+                    };
+                    (key, op)
+                });
+                let cmd = submitted(Command::from(rifl, ops));
+                infos.push(GraphExecutionInfo::add(dot, cmd.clone(), deps));
+                commands.push(cmd);
+            }
+            ConformanceCase::new(process_id, shard_id, config, commands, infos)
+        };
+
+        // two commands that depend on each other are executed in dot order
+        let cycle = {
+            let key = keys[0].clone();
+            let dot_1 = Dot::new(1, 1);
+            let dot_2 = Dot::new(2, 1);
+            let cmd_1 = submitted(Command::from(
+                Rifl::new(1, 1),
+                vec![(key.clone(), KVOp::Put(Value::from("1")))],
+            ));
+            let cmd_2 = submitted(Command::from(
+                Rifl::new(2, 1),
+                vec![(key, KVOp::Get)],
+            ));
+            let infos = vec![
+                GraphExecutionInfo::add(
+                    dot_2,
+                    cmd_2.clone(),
+                    fantoch::singleton![dep(dot_1, shard_id)],
+                ),
+                GraphExecutionInfo::add(
+                    dot_1,
+                    cmd_1.clone(),
+                    fantoch::singleton![dep(dot_2, shard_id)],
+                ),
+            ];
+            ConformanceCase::new(
+                process_id,
+                shard_id,
+                config,
+                vec![cmd_1, cmd_2],
+                infos,
+            )
+        };
+
+        // a scan depends on the previous writes on all keys, and the next
+        // writes depend on it
+        let scan = {
+            let put = |seq: u64, key: &Key| {
+                submitted(Command::from(
+                    Rifl::new(1, seq),
+                    vec![(
+                        key.clone(),
+                        KVOp::Put(Value::from(seq.to_string())),
+                    )],
+                ))
+            };
+            let cmd_1 = put(1, &keys[0]);
+            let cmd_2 = put(2, &keys[1]);
+            let cmd_3 = submitted(Command::scan(
+                Rifl::new(1, 3),
+                keys[0].clone(),
+                String::from("c"),
+                10,
+            ));
+            let cmd_4 = put(4, &keys[1]);
+            let dots: Vec<_> = (1..=4).map(|seq| Dot::new(1, seq)).collect();
+            let infos = vec![
+                GraphExecutionInfo::add(dots[0], cmd_1.clone(), HashSet::new()),
+                GraphExecutionInfo::add(dots[1], cmd_2.clone(), HashSet::new()),
+                GraphExecutionInfo::add(
+                    dots[2],
+                    cmd_3.clone(),
+                    vec![dep(dots[0], shard_id), dep(dots[1], shard_id)]
+                        .into_iter()
+                        .collect(),
+                ),
+                GraphExecutionInfo::add(
+                    dots[3],
+                    cmd_4.clone(),
+                    vec![dep(dots[1], shard_id), dep(dots[2], shard_id)]
+                        .into_iter()
+                        .collect(),
+                ),
+            ];
+            ConformanceCase::new(
+                process_id,
+                shard_id,
+                config,
+                vec![cmd_1, cmd_2, cmd_3, cmd_4],
+                infos,
+            )
+        };
+
+        // transactions read and write both keys, and depend on the previous
+        // transaction
+        let transaction = {
+            let value = |seq: u64| Value::from(seq.to_string());
+            let shard_map = ShardMap::new(1);
+            let cmd_1 = submitted(Command::transaction(
+                &shard_map,
+                Rifl::new(1, 1),
+                vec![],
+                vec![(keys[0].clone(), value(1)), (keys[1].clone(), value(1))],
+            ));
+            let cmd_2 = submitted(Command::transaction(
+                &shard_map,
+                Rifl::new(2, 1),
+                keys.clone(),
+                vec![(keys[0].clone(), value(2))],
+            ));
+            let cmd_3 = submitted(Command::transaction(
+                &shard_map,
+                Rifl::new(3, 1),
+                keys.clone(),
+                vec![(keys[1].clone(), value(3))],
+            ));
+            let cmd_4 = submitted(Command::transaction(
+                &shard_map,
+                Rifl::new(1, 2),
+                keys.clone(),
+                vec![],
+            ));
+            let dots = [
+                Dot::new(1, 1),
+                Dot::new(2, 1),
+                Dot::new(3, 1),
+                Dot::new(1, 2),
+            ];
+            let infos = vec![
+                GraphExecutionInfo::add(dots[0], cmd_1.clone(), HashSet::new()),
+                GraphExecutionInfo::add(
+                    dots[1],
+                    cmd_2.clone(),
+                    fantoch::singleton![dep(dots[0], shard_id)],
+                ),
+                GraphExecutionInfo::add(
+                    dots[2],
+                    cmd_3.clone(),
+                    fantoch::singleton![dep(dots[1], shard_id)],
+                ),
+                GraphExecutionInfo::add(
+                    dots[3],
+                    cmd_4.clone(),
+                    fantoch::singleton![dep(dots[2], shard_id)],
+                ),
+            ];
+            ConformanceCase::new(
+                process_id,
+                shard_id,
+                config,
+                vec![cmd_1, cmd_2, cmd_3, cmd_4],
+                infos,
+            )
+        };
+
+        (1..=6)
+            .map(chain)
+            .chain(vec![cycle, scan, transaction])
+            .collect()
+    }
 }
@@ -0,0 +1,442 @@
+use super::index::VertexIndex;
+use super::tarjan::SCC;
+use fantoch::id::{Dot, ProcessId};
+use fantoch::{HashMap, HashSet};
+use std::collections::BTreeSet;
+use threshold::AEClock;
+
+/// Compact representation of the pending graph, where each vertex is
+/// identified by its position in `dots` (which is sorted).
+struct Graph {
+    dots: Vec<Dot>,
+    // for each vertex, the pending vertices it depends on
+    out_edges: Vec<Vec<usize>>,
+    // for each vertex, whether it has a dependency that is neither pending
+    // nor executed
+    missing: Vec<bool>,
+}
+
+/// Finds all SCCs in the subgraph of the pending graph reachable from `roots`
+/// (i.e. the pending commands that may have become ready, and their pending
+/// dependencies) using Pearce's variant of Tarjan's algorithm, and returns
+/// those that can be executed in a deterministic topological order, i.e.:
+/// - an SCC is only returned if all its transitive dependencies have been
+///   executed (or are returned before it)
+/// - ties between SCCs that are ready at the same time are broken by their
+///   lowest dot
+///
+/// Since an SCC never spans more than one weakly connected component of the
+/// subgraph, each component is searched independently; if the subgraph has at
+/// least `threshold` vertices, components are searched in parallel. As the
+/// SCC partition of a graph is unique and the order between ready SCCs is
+/// fully determined, the result is the same no matter how the work is split
+/// across threads.
+pub fn ready_sccs(
+    process_id: ProcessId,
+    vertex_index: &VertexIndex,
+    executed_clock: &AEClock<ProcessId>,
+    roots: impl IntoIterator<Item = Dot>,
+    threshold: usize,
+) -> Vec<SCC> {
+    let graph =
+        build_graph(process_id, vertex_index, executed_clock, roots, threshold);
+    let parallel = graph.dots.len() >= threshold;
+    let components = weakly_connected_components(&graph);
+    let sccs =
+        map(parallel, &components, |component| pearce(&graph, component))
+            .into_iter()
+            .flatten()
+            .collect();
+    topological_order(&graph, sccs)
+}
+
+fn build_graph(
+    process_id: ProcessId,
+    vertex_index: &VertexIndex,
+    executed_clock: &AEClock<ProcessId>,
+    roots: impl IntoIterator<Item = Dot>,
+    threshold: usize,
+) -> Graph {
+    // collect the pending vertices reachable from the roots; since the
+    // dependencies of these vertices are also in the subgraph (unless
+    // executed), a dependency outside of it is missing
+    let executed =
+        |dot: &Dot| executed_clock.contains(&dot.source(), dot.sequence());
+    let mut to_visit: Vec<_> = roots
+        .into_iter()
+        .filter(|dot| !executed(dot) && vertex_index.find(dot).is_some())
+        .collect();
+    let mut visited: HashSet<_> = to_visit.iter().cloned().collect();
+    while let Some(dot) = to_visit.pop() {
+        let vertex_ref = vertex_index.find(&dot).unwrap_or_else(|| {
+            panic!(
+                "p{}: ready_sccs pending dot {:?} should exist",
+                process_id, dot
+            )
+        });
+        let vertex = vertex_ref.read();
+        for dep in vertex.deps.iter() {
+            let dep_dot = dep.dot;
+            if !executed(&dep_dot)
+                && vertex_index.find(&dep_dot).is_some()
+                && visited.insert(dep_dot)
+            {
+                to_visit.push(dep_dot);
+            }
+        }
+    }
+
+    // sort dots so that vertex positions (and thus pivots) are deterministic
+    let mut dots: Vec<_> = visited.into_iter().collect();
+    dots.sort_unstable();
+    let positions: HashMap<_, _> = dots
+        .iter()
+        .enumerate()
+        .map(|(position, dot)| (*dot, position))
+        .collect();
+
+    // compute the pending dependencies of each vertex
+    let deps = map(dots.len() >= threshold, &dots, |dot| {
+        let vertex_ref = vertex_index.find(dot).unwrap_or_else(|| {
+            panic!(
+                "p{}: ready_sccs pending dot {:?} should exist",
+                process_id, dot
+            )
+        });
+        let vertex = vertex_ref.read();
+        let mut missing = false;
+        let mut out = Vec::with_capacity(vertex.deps.len());
+        for dep in vertex.deps.iter() {
+            let dep_dot = dep.dot;
+            // ignore self or if already executed
+            if dep_dot == *dot
+                || executed_clock
+                    .contains(&dep_dot.source(), dep_dot.sequence())
+            {
+                continue;
+            }
+            match positions.get(&dep_dot) {
+                Some(position) => out.push(*position),
+                None => missing = true,
+            }
+        }
+        (out, missing)
+    });
+
+    let (out_edges, missing) = deps.into_iter().unzip();
+    Graph {
+        dots,
+        out_edges,
+        missing,
+    }
+}
+
+fn weakly_connected_components(graph: &Graph) -> Vec<Vec<usize>> {
+    // union-find over the vertices, merging both ends of every edge
+    let mut parents: Vec<_> = (0..graph.dots.len()).collect();
+    for (from, out) in graph.out_edges.iter().enumerate() {
+        for to in out.iter() {
+            let from_root = find_root(&mut parents, from);
+            let to_root = find_root(&mut parents, *to);
+            if from_root != to_root {
+                // always keep the lowest vertex as the root
+                let (root, other) = if from_root < to_root {
+                    (from_root, to_root)
+                } else {
+                    (to_root, from_root)
+                };
+                parents[other] = root;
+            }
+        }
+    }
+
+    // group vertices by their root
+    let mut components = Vec::new();
+    let mut root_to_component = HashMap::new();
+    for vertex in 0..graph.dots.len() {
+        let root = find_root(&mut parents, vertex);
+        let component = *root_to_component.entry(root).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[component].push(vertex);
+    }
+    components
+}
+
+fn find_root(parents: &mut [usize], mut vertex: usize) -> usize {
+    while parents[vertex] != vertex {
+        // path halving: make each vertex visited point to its grandparent
+        parents[vertex] = parents[parents[vertex]];
+        vertex = parents[vertex];
+    }
+    vertex
+}
+
+/// Finds the SCCs in `component` with the iterative version of Pearce's
+/// algorithm ("A space-efficient algorithm for finding strongly connected
+/// components", 2016). Unlike Tarjan's algorithm, each vertex only keeps its
+/// `rindex`, which is:
+/// - while the vertex is being visited, the lowest visitation index reachable
+///   from it
+/// - once its SCC is found, the index of that SCC, which is always higher than
+///   any visitation index (so that edges to it are ignored)
+fn pearce(graph: &Graph, component: &[usize]) -> Vec<Vec<usize>> {
+    let mut rindex = HashMap::with_capacity(component.len());
+    // vertices that may still be the root of their SCC
+    let mut roots = HashSet::new();
+    let mut next_index = 0;
+    let mut next_scc_index = usize::MAX;
+    // vertices visited whose SCC hasn't been found yet
+    let mut stack = Vec::new();
+    // vertices being visited and the position of their next edge to follow;
+    // this replaces the recursion in the original algorithm
+    let mut visiting: Vec<(usize, usize)> = Vec::new();
+    let mut sccs = Vec::new();
+
+    // lowers the `rindex` of `vertex` if it reaches a lower one through `to`
+    let follow = |rindex: &mut HashMap<usize, usize>,
+                  roots: &mut HashSet<usize>,
+                  vertex: usize,
+                  to: usize| {
+        if rindex[&to] < rindex[&vertex] {
+            rindex.insert(vertex, rindex[&to]);
+            roots.remove(&vertex);
+        }
+    };
+
+    for start in component {
+        if rindex.contains_key(start) {
+            continue;
+        }
+        rindex.insert(*start, next_index);
+        next_index += 1;
+        roots.insert(*start);
+        visiting.push((*start, 0));
+
+        while let Some(&(vertex, edge)) = visiting.last() {
+            if let Some(to) = graph.out_edges[vertex].get(edge) {
+                // move to the next edge
+                visiting.last_mut().expect("vertex is being visited").1 += 1;
+                if rindex.contains_key(to) {
+                    follow(&mut rindex, &mut roots, vertex, *to);
+                } else {
+                    // start visiting the dependency
+                    rindex.insert(*to, next_index);
+                    next_index += 1;
+                    roots.insert(*to);
+                    visiting.push((*to, 0));
+                }
+                continue;
+            }
+
+            // all edges followed: finish visiting the vertex
+            visiting.pop();
+            if roots.remove(&vertex) {
+                // the vertex is the root of an SCC, which contains all the
+                // vertices in the stack visited after it
+                let vertex_rindex = rindex[&vertex];
+                let mut scc = vec![vertex];
+                next_index -= 1;
+                while let Some(other) = stack.last() {
+                    if vertex_rindex > rindex[other] {
+                        break;
+                    }
+                    rindex.insert(*other, next_scc_index);
+                    scc.push(*other);
+                    stack.pop();
+                    next_index -= 1;
+                }
+                rindex.insert(vertex, next_scc_index);
+                next_scc_index -= 1;
+                sccs.push(scc);
+            } else {
+                stack.push(vertex);
+            }
+
+            // let the vertex that led us here know about the `rindex` found
+            if let Some(&(from, _)) = visiting.last() {
+                follow(&mut rindex, &mut roots, from, vertex);
+            }
+        }
+    }
+    sccs
+}
+
+fn topological_order(graph: &Graph, sccs: Vec<Vec<usize>>) -> Vec<SCC> {
+    // compute the SCC of each vertex
+    let mut scc_of = vec![0; graph.dots.len()];
+    for (index, scc) in sccs.iter().enumerate() {
+        for vertex in scc {
+            scc_of[*vertex] = index;
+        }
+    }
+
+    // compute the condensation of the graph: for each SCC, the number of
+    // other SCCs it depends on, and the SCCs that depend on it
+    let mut pending_deps = vec![0; sccs.len()];
+    let mut dependents = vec![Vec::new(); sccs.len()];
+    // an SCC is blocked if some member (or a dependency) has a missing
+    // dependency
+    let mut blocked = vec![false; sccs.len()];
+    for (index, scc) in sccs.iter().enumerate() {
+        let mut deps = BTreeSet::new();
+        for vertex in scc {
+            blocked[index] |= graph.missing[*vertex];
+            for to in graph.out_edges[*vertex].iter() {
+                let dep_scc = scc_of[*to];
+                if dep_scc != index {
+                    deps.insert(dep_scc);
+                }
+            }
+        }
+        pending_deps[index] = deps.len();
+        for dep_scc in deps {
+            dependents[dep_scc].push(index);
+        }
+    }
+
+    // an SCC is identified by its lowest dot (vertices are sorted by dot)
+    let lowest =
+        |index: usize| *sccs[index].iter().min().expect("SCCs are non-empty");
+
+    // Kahn's algorithm, always picking the SCC with the lowest dot
+    let mut ready: BTreeSet<_> = (0..sccs.len())
+        .filter(|index| pending_deps[*index] == 0)
+        .map(|index| (lowest(index), index))
+        .collect();
+    let mut result = Vec::new();
+    while let Some(&(lowest_vertex, index)) = ready.iter().next() {
+        ready.remove(&(lowest_vertex, index));
+
+        if !blocked[index] {
+            result.push(
+                sccs[index]
+                    .iter()
+                    .map(|vertex| graph.dots[*vertex])
+                    .collect(),
+            );
+        }
+        for dependent in dependents[index].iter() {
+            // SCCs that depend on a blocked SCC are also blocked
+            blocked[*dependent] |= blocked[index];
+            pending_deps[*dependent] -= 1;
+            if pending_deps[*dependent] == 0 {
+                ready.insert((lowest(*dependent), *dependent));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(feature = "parallel-executor")]
+fn map<T, R, F>(parallel: bool, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+    if parallel {
+        items.par_iter().map(f).collect()
+    } else {
+        items.iter().map(f).collect()
+    }
+}
+
+#[cfg(not(feature = "parallel-executor"))]
+fn map<T, R, F>(_parallel: bool, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    // computes the SCCs of `graph`: the SCC of each vertex has the vertices
+    // it reaches that also reach it
+    fn naive_sccs(graph: &Graph) -> BTreeSet<BTreeSet<usize>> {
+        let reaches: Vec<_> = (0..graph.dots.len())
+            .map(|start| {
+                let mut visited = BTreeSet::new();
+                visited.insert(start);
+                let mut to_visit = vec![start];
+                while let Some(vertex) = to_visit.pop() {
+                    for next in graph.out_edges[vertex].iter() {
+                        if visited.insert(*next) {
+                            to_visit.push(*next);
+                        }
+                    }
+                }
+                visited
+            })
+            .collect();
+        reaches
+            .iter()
+            .enumerate()
+            .map(|(vertex, reach)| {
+                reach
+                    .iter()
+                    .filter(|other| reaches[**other].contains(&vertex))
+                    .cloned()
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn random_graph(vertex_count: usize, edge_count: usize) -> Graph {
+        let mut rng = rand::thread_rng();
+        let mut out_edges = vec![Vec::new(); vertex_count];
+        for _ in 0..edge_count {
+            let from = rng.gen_range(0..vertex_count);
+            let to = rng.gen_range(0..vertex_count);
+            if from != to && !out_edges[from].contains(&to) {
+                out_edges[from].push(to);
+            }
+        }
+        Graph {
+            dots: (1..=vertex_count as u64)
+                .map(|seq| Dot::new(1, seq))
+                .collect(),
+            out_edges,
+            missing: vec![false; vertex_count],
+        }
+    }
+
+    #[test]
+    fn pearce_finds_all_sccs() {
+        let iterations = 100;
+        let vertex_count = 50;
+        (0..iterations).for_each(|iteration| {
+            // vary the density of the graph, from many small components to
+            // a few large SCCs
+            let graph = random_graph(vertex_count, iteration);
+            let components = weakly_connected_components(&graph);
+
+            // each vertex is in exactly one component
+            let mut vertices: Vec<_> =
+                components.iter().flatten().cloned().collect();
+            vertices.sort_unstable();
+            assert_eq!(vertices, (0..vertex_count).collect::<Vec<_>>());
+
+            let sccs: Vec<_> = components
+                .iter()
+                .flat_map(|component| pearce(&graph, component))
+                .collect();
+            // each vertex is in exactly one SCC
+            let vertex_total: usize = sccs.iter().map(Vec::len).sum();
+            assert_eq!(vertex_total, vertex_count);
+            let sccs: BTreeSet<BTreeSet<_>> = sccs
+                .into_iter()
+                .map(|scc| scc.into_iter().collect())
+                .collect();
+            assert_eq!(sccs, naive_sccs(&graph));
+        });
+    }
+}
@@ -1,6 +1,6 @@
 use super::index::{VertexIndex, VertexRef};
 use crate::protocol::common::graph::Dependency;
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::id::{Dot, ProcessId};
 use fantoch::singleton;
@@ -347,10 +347,15 @@ impl Vertex {
         }
     }
 
-    /// Consumes the vertex, returning its command.
-    pub fn into_command(self, time: &dyn SysTime) -> (u64, Command) {
+    /// Consumes the vertex, returning its command and the times at which it
+    /// was committed and executed.
+    pub fn into_command(
+        self,
+        time: &dyn SysTime,
+    ) -> (ExecutionTimestamps, Command) {
         let end_time_ms = time.millis();
-        let duration_ms = end_time_ms - self.start_time_ms;
-        (duration_ms, self.cmd)
+        let timestamps =
+            ExecutionTimestamps::new(self.start_time_ms, end_time_ms);
+        (timestamps, self.cmd)
     }
 }
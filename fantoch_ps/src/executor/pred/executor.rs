@@ -1,12 +1,13 @@
 use crate::executor::pred::PredecessorsGraph;
 use crate::protocol::common::pred::{CaesarDeps, Clock};
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::executor::{
-    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult,
+    DelayedReads, ExecutionOrderMonitor, Executor, ExecutorMetrics,
+    ExecutorMetricsKind, ExecutorResult, LocalRead,
 };
 use fantoch::id::{Dot, ProcessId, ShardId};
-use fantoch::kvs::KVStore;
+use fantoch::kvs::{KVStore, Key, Store, WatchNotification};
 use fantoch::protocol::{CommittedAndExecuted, MessageIndex};
 use fantoch::time::SysTime;
 use fantoch::trace;
@@ -21,6 +22,8 @@ pub struct PredecessorsExecutor {
     graph: PredecessorsGraph,
     store: KVStore,
     to_clients: VecDeque<ExecutorResult>,
+    // local reads waiting for the writes they should observe
+    delayed_reads: DelayedReads,
 }
 
 impl Executor for PredecessorsExecutor {
@@ -28,14 +31,22 @@ impl Executor for PredecessorsExecutor {
 
     fn new(process_id: ProcessId, shard_id: ShardId, config: Config) -> Self {
         let graph = PredecessorsGraph::new(process_id, &config);
-        let store = KVStore::new(config.executor_monitor_execution_order());
+        let mut store = KVStore::new(config.executor_monitor_execution_order());
+        store.set_capacity(config.executor_store_capacity());
+        store.set_dedup_window(config.executor_dedup_window());
+        store.set_hot_keys(config.executor_hot_keys());
+        // key clocks don't make scans conflict with writes on the keys in
+        // their range, and thus scans are not ordered with them
+        store.set_scans(false);
         let to_clients = Default::default();
+        let delayed_reads = DelayedReads::new();
         Self {
             process_id,
             shard_id,
             graph,
             store,
             to_clients,
+            delayed_reads,
         }
     }
 
@@ -45,21 +56,54 @@ impl Executor for PredecessorsExecutor {
             .add(info.dot, info.cmd, info.clock, info.deps, time);
 
         // get more commands that are ready to be executed
-        while let Some(cmd) = self.graph.command_to_execute() {
+        while let Some((cmd, timestamps)) = self.graph.command_to_execute() {
             trace!(
                 "p{}: PredecessorsExecutor::comands_to_execute {:?} | time = {}",
                 self.process_id,
                 cmd.rifl(),
                 time.millis()
             );
-            self.execute(cmd);
+            self.execute(cmd, timestamps);
         }
+        self.store.update_metrics(self.graph.metrics_mut());
+    }
+
+    fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store.set_store(store);
+    }
+
+    fn local_read(&mut self, read: LocalRead) {
+        match self.delayed_reads.delay(read, &self.store) {
+            Some(read) => {
+                self.to_clients.push_back(read.execute(&mut self.store))
+            }
+            None => self
+                .graph
+                .metrics_mut()
+                .aggregate(ExecutorMetricsKind::DelayedReads, 1),
+        }
+    }
+
+    fn watch(&mut self, key: Key) {
+        self.store.watch(key);
+    }
+
+    fn unwatch(&mut self, key: &Key) {
+        self.store.unwatch(key);
     }
 
     fn to_clients(&mut self) -> Option<ExecutorResult> {
+        // execute the delayed reads whose writes have meanwhile been applied
+        while let Some(read) = self.delayed_reads.next_ready(&self.store) {
+            self.to_clients.push_back(read.execute(&mut self.store));
+        }
         self.to_clients.pop_front()
     }
 
+    fn to_watchers(&mut self) -> Vec<WatchNotification> {
+        self.store.take_notifications()
+    }
+
     fn executed(
         &mut self,
         _time: &dyn SysTime,
@@ -74,6 +118,10 @@ impl Executor for PredecessorsExecutor {
         Some(committed_and_executed)
     }
 
+    fn execution_info_dot(info: &Self::ExecutionInfo) -> Option<Dot> {
+        Some(info.dot)
+    }
+
     fn parallel() -> bool {
         false
     }
@@ -88,9 +136,24 @@ impl Executor for PredecessorsExecutor {
 }
 
 impl PredecessorsExecutor {
-    fn execute(&mut self, cmd: Command) {
-        // execute the command
-        let results = cmd.execute(self.shard_id, &mut self.store);
+    fn execute(&mut self, cmd: Command, timestamps: ExecutionTimestamps) {
+        let timestamps = timestamps
+            .with_submit(cmd.submit_time())
+            .with_fast_path(cmd.fast_path());
+        // execute the command at the logical time assigned by its coordinator
+        // (commands without one can't be executed, as replicas must expire
+        // values identically)
+        let logical_time = cmd.logical_time().unwrap_or_else(|| {
+            panic!(
+                "command {:?} should have a logical time assigned by its coordinator",
+                cmd.rifl()
+            )
+        });
+        self.store
+            .set_time_on(logical_time, cmd.keys(self.shard_id));
+        let results = cmd
+            .execute(self.shard_id, &mut self.store)
+            .map(|result| result.with_timestamps(timestamps));
         self.to_clients.extend(results);
     }
 }
@@ -1,5 +1,5 @@
 use crate::protocol::common::pred::{CaesarDeps, Clock};
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::hash_map::HashMap;
 use fantoch::id::Dot;
 use fantoch::time::SysTime;
@@ -53,11 +53,16 @@ impl Vertex {
         self.missing_deps -= 1;
     }
 
-    /// Consumes the vertex, returning its command.
-    pub fn into_command(self, time: &dyn SysTime) -> (u64, Command) {
+    /// Consumes the vertex, returning its command and the times at which it
+    /// was committed and executed.
+    pub fn into_command(
+        self,
+        time: &dyn SysTime,
+    ) -> (ExecutionTimestamps, Command) {
         let end_time_ms = time.millis();
-        let duration_ms = end_time_ms - self.start_time_ms;
-        (duration_ms, self.cmd)
+        let timestamps =
+            ExecutionTimestamps::new(self.start_time_ms, end_time_ms);
+        (timestamps, self.cmd)
     }
 }
 
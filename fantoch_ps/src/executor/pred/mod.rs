@@ -11,7 +11,7 @@ pub use executor::{PredecessorsExecutionInfo, PredecessorsExecutor};
 
 use self::index::{PendingIndex, Vertex, VertexIndex};
 use crate::protocol::common::pred::{CaesarDeps, Clock};
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::executor::{ExecutorMetrics, ExecutorMetricsKind};
 use fantoch::id::{Dot, ProcessId};
@@ -39,7 +39,7 @@ pub struct PredecessorsGraph {
     new_committed_dots: u64,
     // dots of new commands executed
     new_executed_dots: Vec<Dot>,
-    to_execute: VecDeque<Command>,
+    to_execute: VecDeque<(Command, ExecutionTimestamps)>,
     execute_at_commit: bool,
 }
 
@@ -80,13 +80,18 @@ impl PredecessorsGraph {
 
     /// Returns a new command ready to be executed.
     #[must_use]
-    pub fn command_to_execute(&mut self) -> Option<Command> {
+    pub fn command_to_execute(
+        &mut self,
+    ) -> Option<(Command, ExecutionTimestamps)> {
         self.to_execute.pop_front()
     }
 
     #[cfg(test)]
     fn commands_to_execute(&mut self) -> VecDeque<Command> {
         std::mem::take(&mut self.to_execute)
+            .into_iter()
+            .map(|(cmd, _)| cmd)
+            .collect()
     }
 
     fn committed_and_executed(&mut self) -> CommittedAndExecuted {
@@ -100,6 +105,10 @@ impl PredecessorsGraph {
         &self.metrics
     }
 
+    fn metrics_mut(&mut self) -> &mut ExecutorMetrics {
+        &mut self.metrics
+    }
+
     /// Add a new command.
     pub fn add(
         &mut self,
@@ -126,7 +135,9 @@ impl PredecessorsGraph {
         assert!(!deps.contains(&dot));
 
         if self.execute_at_commit {
-            self.execute(dot, cmd, time);
+            let now = time.millis();
+            let timestamps = ExecutionTimestamps::new(now, now);
+            self.execute(dot, cmd, timestamps, time);
         } else {
             // index the command
             self.index_committed_command(dot, cmd, clock, deps, time);
@@ -353,20 +364,28 @@ impl PredecessorsGraph {
             .expect("ready-to-execute command should exist");
 
         // get command
-        let (duration_ms, cmd) = vertex.into_command(time);
+        let (timestamps, cmd) = vertex.into_command(time);
 
         // save execution delay metric
-        self.metrics
-            .collect(ExecutorMetricsKind::ExecutionDelay, duration_ms);
+        self.metrics.collect(
+            ExecutorMetricsKind::ExecutionDelay,
+            timestamps.execution_delay(),
+        );
 
         // mark dot as executed and add command to commands to be executed
-        self.execute(dot, cmd, time);
+        self.execute(dot, cmd, timestamps, time);
 
         // try commands pending at phase two due to this command
         self.try_phase_two_pending(dot, time);
     }
 
-    fn execute(&mut self, dot: Dot, cmd: Command, _time: &dyn SysTime) {
+    fn execute(
+        &mut self,
+        dot: Dot,
+        cmd: Command,
+        timestamps: ExecutionTimestamps,
+        _time: &dyn SysTime,
+    ) {
         trace!(
             "p{}: Predecessors::update_executed {:?} | time = {}",
             self.process_id,
@@ -379,7 +398,7 @@ impl PredecessorsGraph {
         assert!(self.executed_clock.add(&dot.source(), dot.sequence()));
 
         // add command to commands to be executed
-        self.to_execute.push_back(cmd);
+        self.to_execute.push_back((cmd, timestamps));
     }
 }
 
@@ -400,7 +419,7 @@ impl fmt::Debug for PredecessorsGraph {
 mod tests {
     use super::*;
     use fantoch::id::{ClientId, Rifl};
-    use fantoch::kvs::{KVOp, Key};
+    use fantoch::kvs::{KVOp, Key, Value};
     use fantoch::time::RunTime;
     use fantoch::{HashMap, HashSet};
     use permutator::{Combination, Permutation};
@@ -433,7 +452,7 @@ mod tests {
         // cmd 0
         let cmd_0 = Command::from(
             Rifl::new(1, 1),
-            vec![(String::from("A"), KVOp::Put(String::new()))],
+            vec![(String::from("A"), KVOp::Put(Value::new()))],
         );
         let clock_0 = Clock::from(2, p1);
         let deps_0 = caesar_deps(vec![dot_1]);
@@ -441,7 +460,7 @@ mod tests {
         // cmd 1
         let cmd_1 = Command::from(
             Rifl::new(2, 1),
-            vec![(String::from("A"), KVOp::Put(String::new()))],
+            vec![(String::from("A"), KVOp::Put(Value::new()))],
         );
         let clock_1 = Clock::from(1, p2);
         let deps_1 = caesar_deps(vec![dot_0]);
@@ -613,7 +632,7 @@ mod tests {
                 BTreeSet::from_iter(vec![String::from("CONF")])
             });
             let ops = keys.into_iter().map(|key| {
-                let value = String::from("");
+                let value = Value::new();
                 (key, KVOp::Put(value))
             });
             let cmd = Command::from(rifl, ops);
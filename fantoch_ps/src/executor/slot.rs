@@ -1,10 +1,11 @@
-use fantoch::command::Command;
+use fantoch::command::{Command, ExecutionTimestamps};
 use fantoch::config::Config;
 use fantoch::executor::{
-    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult,
+    DelayedReads, ExecutionOrderMonitor, Executor, ExecutorMetrics,
+    ExecutorMetricsKind, ExecutorResult, LocalRead,
 };
 use fantoch::id::{ProcessId, ShardId};
-use fantoch::kvs::KVStore;
+use fantoch::kvs::{KVStore, Key, Store, WatchNotification};
 use fantoch::protocol::MessageIndex;
 use fantoch::time::SysTime;
 use fantoch::HashMap;
@@ -20,22 +21,31 @@ pub struct SlotExecutor {
     store: KVStore,
     next_slot: Slot,
     // TODO maybe BinaryHeap
-    to_execute: HashMap<Slot, Command>,
+    // mapping from slot to its command and the time it was committed
+    to_execute: HashMap<Slot, (Command, u64)>,
     metrics: ExecutorMetrics,
     to_clients: VecDeque<ExecutorResult>,
+    // local reads waiting for the writes they should observe
+    delayed_reads: DelayedReads,
 }
 
 impl Executor for SlotExecutor {
     type ExecutionInfo = SlotExecutionInfo;
 
     fn new(_process_id: ProcessId, shard_id: ShardId, config: Config) -> Self {
-        let store = KVStore::new(config.executor_monitor_execution_order());
+        let mut store = KVStore::new(config.executor_monitor_execution_order());
+        store.set_capacity(config.executor_store_capacity());
+        store.set_dedup_window(config.executor_dedup_window());
+        store.set_hot_keys(config.executor_hot_keys());
+        // with more than one shard, each store only has part of the keys
+        store.set_scans(config.shard_count() == 1);
         // the next slot to be executed is 1
         let next_slot = 1;
         // there's nothing to execute in the beginning
         let to_execute = HashMap::new();
         let metrics = ExecutorMetrics::new();
         let to_clients = Default::default();
+        let delayed_reads = DelayedReads::new();
         Self {
             shard_id,
             config,
@@ -44,34 +54,75 @@ impl Executor for SlotExecutor {
             to_execute,
             metrics,
             to_clients,
+            delayed_reads,
         }
     }
 
-    fn handle(&mut self, info: Self::ExecutionInfo, _time: &dyn SysTime) {
+    fn cleanup(&mut self, _time: &dyn SysTime) {
+        // commands are executed at their slot, and thus the ones not yet
+        // executed are executed after the last slot executed
+        let last_executed = self.next_slot - 1;
+        self.store.purge_expired(|_| last_executed);
+    }
+
+    fn handle(&mut self, info: Self::ExecutionInfo, time: &dyn SysTime) {
         let SlotExecutionInfo { slot, cmd } = info;
+        let commit_time = time.millis();
         // we shouldn't receive execution info about slots already executed
         // TODO actually, if recovery is involved, then this may not be
         // necessarily true
         assert!(slot >= self.next_slot);
 
         if self.config.execute_at_commit() {
-            self.execute(cmd);
+            self.execute(slot, cmd, commit_time, time);
         } else {
             // add received command to the commands to be executed and try to
             // execute commands
             // TODO here we could optimize and only insert the command if it
             // isn't the command that will be executed in the next
             // slot
-            let res = self.to_execute.insert(slot, cmd);
+            let res = self.to_execute.insert(slot, (cmd, commit_time));
             assert!(res.is_none());
-            self.try_next_slot();
+            self.try_next_slot(time);
         }
+        self.store.update_metrics(&mut self.metrics);
+    }
+
+    fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store.set_store(store);
+    }
+
+    fn local_read(&mut self, read: LocalRead) {
+        match self.delayed_reads.delay(read, &self.store) {
+            Some(read) => {
+                self.to_clients.push_back(read.execute(&mut self.store))
+            }
+            None => {
+                self.metrics.aggregate(ExecutorMetricsKind::DelayedReads, 1)
+            }
+        }
+    }
+
+    fn watch(&mut self, key: Key) {
+        self.store.watch(key);
+    }
+
+    fn unwatch(&mut self, key: &Key) {
+        self.store.unwatch(key);
     }
 
     fn to_clients(&mut self) -> Option<ExecutorResult> {
+        // execute the delayed reads whose writes have meanwhile been applied
+        while let Some(read) = self.delayed_reads.next_ready(&self.store) {
+            self.to_clients.push_back(read.execute(&mut self.store));
+        }
         self.to_clients.pop_front()
     }
 
+    fn to_watchers(&mut self) -> Vec<WatchNotification> {
+        self.store.take_notifications()
+    }
+
     fn parallel() -> bool {
         false
     }
@@ -86,18 +137,32 @@ impl Executor for SlotExecutor {
 }
 
 impl SlotExecutor {
-    fn try_next_slot(&mut self) {
+    fn try_next_slot(&mut self, time: &dyn SysTime) {
         // gather commands while the next command to be executed exists
-        while let Some(cmd) = self.to_execute.remove(&self.next_slot) {
-            self.execute(cmd);
+        while let Some((cmd, commit_time)) =
+            self.to_execute.remove(&self.next_slot)
+        {
+            self.execute(self.next_slot, cmd, commit_time, time);
             // update the next slot to be executed
             self.next_slot += 1;
         }
     }
 
-    fn execute(&mut self, cmd: Command) {
-        // execute the command
-        let results = cmd.execute(self.shard_id, &mut self.store);
+    fn execute(
+        &mut self,
+        slot: Slot,
+        cmd: Command,
+        commit_time: u64,
+        time: &dyn SysTime,
+    ) {
+        // execute the command, using its slot as the logical time at which
+        // it's executed
+        self.store.set_time(slot);
+        let timestamps = ExecutionTimestamps::new(commit_time, time.millis())
+            .with_submit(cmd.submit_time());
+        let results = cmd
+            .execute(self.shard_id, &mut self.store)
+            .map(|result| result.with_timestamps(timestamps));
         // update results if this rifl is pending
         self.to_clients.extend(results);
     }
@@ -124,8 +189,9 @@ impl MessageIndex for SlotExecutionInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fantoch::executor::conformance::ConformanceCase;
     use fantoch::id::Rifl;
-    use fantoch::kvs::KVOp;
+    use fantoch::kvs::{KVOp, KVOpResult, Value};
     use permutator::Permutation;
     use std::collections::BTreeMap;
 
@@ -143,30 +209,32 @@ mod tests {
         let key = String::from("a");
         let cmd_1 = Command::from(
             rifl_1,
-            vec![(key.clone(), KVOp::Put(String::from("1")))],
+            vec![(key.clone(), KVOp::Put(Value::from("1")))],
         );
         let cmd_2 = Command::from(rifl_2, vec![(key.clone(), KVOp::Get)]);
         let cmd_3 = Command::from(
             rifl_3,
-            vec![(key.clone(), KVOp::Put(String::from("2")))],
+            vec![(key.clone(), KVOp::Put(Value::from("2")))],
         );
         let cmd_4 = Command::from(rifl_4, vec![(key.clone(), KVOp::Get)]);
         let cmd_5 = Command::from(
             rifl_5,
-            vec![(key.clone(), KVOp::Put(String::from("3")))],
+            vec![(key.clone(), KVOp::Put(Value::from("3")))],
         );
         let cmd_6 = Command::from(rifl_6, vec![(key.clone(), KVOp::Get)]);
 
         // create expected results:
         // - we don't expect rifl 1 because we will not wait for it in the
         //   executor
+        let none = KVOpResult::Value(None);
+        let some = |value| KVOpResult::Value(Some(Value::from(value)));
         let mut expected_results = BTreeMap::new();
-        expected_results.insert(rifl_1, vec![None]);
-        expected_results.insert(rifl_2, vec![Some(String::from("1"))]);
-        expected_results.insert(rifl_3, vec![None]);
-        expected_results.insert(rifl_4, vec![Some(String::from("2"))]);
-        expected_results.insert(rifl_5, vec![None]);
-        expected_results.insert(rifl_6, vec![Some(String::from("3"))]);
+        expected_results.insert(rifl_1, vec![none.clone()]);
+        expected_results.insert(rifl_2, vec![some("1")]);
+        expected_results.insert(rifl_3, vec![none.clone()]);
+        expected_results.insert(rifl_4, vec![some("2")]);
+        expected_results.insert(rifl_5, vec![none]);
+        expected_results.insert(rifl_6, vec![some("3")]);
 
         // create execution info
         let ei_1 = SlotExecutionInfo::new(1, cmd_1);
@@ -210,4 +278,50 @@ mod tests {
             assert_eq!(results, expected_results);
         });
     }
+
+    fantoch::executor_conformance!(
+        slot_executor_conformance,
+        SlotExecutor,
+        conformance_cases()
+    );
+
+    fn conformance_cases() -> Vec<ConformanceCase<SlotExecutor>> {
+        let process_id = 1;
+        let shard_id = 0;
+        let config = Config::new(0, 0);
+        let keys = [String::from("a"), String::from("b")];
+
+        // commands alternate between writing and reading one or both keys
+        (1..=4)
+            .map(|command_count| {
+                let commands: Vec<_> = (1..=command_count)
+                    .map(|seq| {
+                        let rifl = Rifl::new(1, seq);
+                        let ops = keys
+                            .iter()
+                            .take(1 + seq as usize % keys.len())
+                            .map(|key| {
+                                let op = if seq % 2 == 0 {
+                                    KVOp::Get
+                                } else {
+                                    KVOp::Put(Value::from(seq.to_string()))
+                                };
+                                (key.clone(), op)
+                            });
+                        Command::from(rifl, ops)
+                    })
+                    .collect();
+                let infos = commands
+                    .iter()
+                    .zip(1..)
+                    .map(|(cmd, slot)| {
+                        SlotExecutionInfo::new(slot, cmd.clone())
+                    })
+                    .collect();
+                ConformanceCase::new(
+                    process_id, shard_id, config, commands, infos,
+                )
+            })
+            .collect()
+    }
 }
@@ -1,12 +1,15 @@
 use crate::executor::table::MultiVotesTable;
 use crate::protocol::common::table::VoteRange;
+use fantoch::command::ExecutionTimestamps;
 use fantoch::config::Config;
 use fantoch::executor::{
-    ExecutionOrderMonitor, Executor, ExecutorMetrics, ExecutorResult,
-    MessageKey,
+    DelayedReads, ExecutionOrderMonitor, Executor, ExecutorMetrics,
+    ExecutorMetricsKind, ExecutorResult, LocalRead, MessageKey,
 };
 use fantoch::id::{Dot, ProcessId, Rifl, ShardId};
-use fantoch::kvs::{KVOp, KVStore, Key};
+use fantoch::kvs::{
+    self, KVOp, KVOpError, KVStore, Key, Store, WatchNotification,
+};
 use fantoch::shared::SharedMap;
 use fantoch::time::SysTime;
 use fantoch::trace;
@@ -15,6 +18,7 @@ use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct TableExecutor {
@@ -25,9 +29,13 @@ pub struct TableExecutor {
     store: KVStore,
     metrics: ExecutorMetrics,
     to_clients: VecDeque<ExecutorResult>,
+    // local reads waiting for the writes they should observe
+    delayed_reads: DelayedReads,
     to_executors: Vec<(ShardId, TableExecutionInfo)>,
     pending: HashMap<Key, PendingPerKey>,
-    rifl_to_stable_count: Arc<SharedMap<Rifl, Mutex<u64>>>,
+    rifl_to_stable_count: Arc<SharedMap<Rifl, Mutex<StableCount>>>,
+    compaction_interval: Option<Duration>,
+    last_compaction_ms: u64,
 }
 
 #[derive(Clone, Default)]
@@ -36,6 +44,20 @@ struct PendingPerKey {
     stable_shards_buffered: HashMap<Rifl, usize>,
 }
 
+impl PendingPerKey {
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.stable_shards_buffered.is_empty()
+    }
+}
+
+// number of keys on this shard at which a command is stable, and whether its
+// ops on some of them would fail (see `Pending::check`)
+#[derive(Default)]
+struct StableCount {
+    count: u64,
+    aborted: bool,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Pending {
     rifl: Rifl,
@@ -45,6 +67,22 @@ pub struct Pending {
     // number of shards the key is not stable at yet
     missing_stable_shards: usize,
     ops: Arc<Vec<KVOp>>,
+    // whether the ops on the keys of this shard are executed all or nothing,
+    // i.e. the command has conditions or validations on this shard
+    atomic: bool,
+    // op that would fail on this key, if any, and whether the command is
+    // aborted (i.e. some op would fail on some key of this shard)
+    failure: Option<(usize, KVOpError)>,
+    aborted: bool,
+    // clock assigned to the command, used as the logical time at which it's
+    // executed
+    clock: u64,
+    // time at which the command was submitted (in milliseconds), if known
+    submit_time: Option<u64>,
+    // time at which the command was committed (in milliseconds)
+    commit_time: u64,
+    // whether the command was committed in the fast path, if known
+    fast_path: Option<bool>,
 }
 
 impl Pending {
@@ -53,6 +91,11 @@ impl Pending {
         rifl: Rifl,
         shard_to_keys: Arc<HashMap<ShardId, Vec<Key>>>,
         ops: Arc<Vec<KVOp>>,
+        atomic: bool,
+        clock: u64,
+        submit_time: Option<u64>,
+        commit_time: u64,
+        fast_path: Option<bool>,
     ) -> Self {
         let shard_key_count = shard_to_keys
             .get(&shard_id)
@@ -65,6 +108,13 @@ impl Pending {
             shard_key_count,
             missing_stable_shards,
             ops,
+            atomic,
+            failure: None,
+            aborted: false,
+            clock,
+            submit_time,
+            commit_time,
+            fast_path,
         }
     }
 
@@ -73,6 +123,19 @@ impl Pending {
         // number of keys accessed in that shard is one
         self.missing_stable_shards == 1 && self.shard_key_count == 1
     }
+
+    /// If the command is executed all or nothing, checks whether its ops
+    /// would fail on `key`. This should only be called once the command is
+    /// stable at `key`: from then on, and until the command is executed, no
+    /// other command is executed on `key`, and thus the outcome of this check
+    /// doesn't change.
+    fn check(&mut self, key: &Key, store: &mut KVStore) {
+        if self.atomic {
+            store.set_time(self.clock);
+            self.failure = store.check(key, &self.ops, self.rifl);
+            self.aborted = self.failure.is_some();
+        }
+    }
 }
 
 impl Executor for TableExecutor {
@@ -86,9 +149,17 @@ impl Executor for TableExecutor {
             config.n(),
             stability_threshold,
         );
-        let store = KVStore::new(config.executor_monitor_execution_order());
+        let mut store = KVStore::new(config.executor_monitor_execution_order());
+        store.set_capacity(config.executor_store_capacity());
+        store.set_dedup_window(config.executor_dedup_window());
+        store.set_hot_keys(config.executor_hot_keys());
+        // key clocks don't make scans conflict with writes on the keys in
+        // their range, and thus scans are not ordered with them (besides,
+        // each executor only has part of the keys)
+        store.set_scans(false);
         let metrics = ExecutorMetrics::new();
         let to_clients = Default::default();
+        let delayed_reads = DelayedReads::new();
         let to_executors = Default::default();
         let pending = Default::default();
         let rifl_to_stable_count = Arc::new(SharedMap::new());
@@ -101,13 +172,30 @@ impl Executor for TableExecutor {
             store,
             metrics,
             to_clients,
+            delayed_reads,
             to_executors,
             pending,
             rifl_to_stable_count,
+            compaction_interval: config.executor_compaction_interval(),
+            last_compaction_ms: 0,
         }
     }
 
-    fn handle(&mut self, info: Self::ExecutionInfo, _time: &dyn SysTime) {
+    fn cleanup(&mut self, time: &dyn SysTime) {
+        if let Some(interval) = self.compaction_interval {
+            let now_ms = time.millis();
+            if now_ms - self.last_compaction_ms >= interval.as_millis() as u64 {
+                self.last_compaction_ms = now_ms;
+                self.compact();
+            }
+        }
+        // commands are executed at their clock, and thus the ones on some key
+        // not yet executed are executed after the stable clock of the key
+        let table = &self.table;
+        self.store.purge_expired(|key| table.stable_clock(key));
+    }
+
+    fn handle(&mut self, info: Self::ExecutionInfo, time: &dyn SysTime) {
         // handle each new info by updating the votes table and execute ready
         // commands
         match info {
@@ -118,39 +206,90 @@ impl Executor for TableExecutor {
                 rifl,
                 shard_to_keys,
                 ops,
+                atomic,
                 votes,
+                submit_time,
+                fast_path,
             } => {
-                let pending =
-                    Pending::new(self.shard_id, rifl, shard_to_keys, ops);
+                let pending = Pending::new(
+                    self.shard_id,
+                    rifl,
+                    shard_to_keys,
+                    ops,
+                    atomic,
+                    clock,
+                    submit_time,
+                    time.millis(),
+                    fast_path,
+                );
                 if self.execute_at_commit {
-                    self.execute(key, pending);
+                    self.execute(key, pending, time);
                 } else {
                     let to_execute = self
                         .table
                         .add_attached_votes(dot, clock, &key, pending, votes);
-                    self.send_stable_or_execute(key, to_execute);
+                    self.send_stable_or_execute(key, to_execute, time);
                 }
             }
             TableExecutionInfo::DetachedVotes { key, votes } => {
                 if !self.execute_at_commit {
                     let to_execute = self.table.add_detached_votes(&key, votes);
-                    self.send_stable_or_execute(key, to_execute);
+                    self.send_stable_or_execute(key, to_execute, time);
                 }
             }
-            TableExecutionInfo::StableAtShard { key, rifl } => {
-                self.handle_stable_msg(key, rifl)
+            TableExecutionInfo::StableAtShard { key, rifl, aborted } => {
+                self.handle_stable_msg(key, rifl, aborted, time)
             }
         }
+        self.store.update_metrics(&mut self.metrics);
+    }
+
+    fn set_store(&mut self, store: Box<dyn Store>) {
+        self.store.set_store(store);
+    }
+
+    fn local_read(&mut self, read: LocalRead) {
+        match self.delayed_reads.delay(read, &self.store) {
+            Some(read) => {
+                self.to_clients.push_back(read.execute(&mut self.store))
+            }
+            None => {
+                self.metrics.aggregate(ExecutorMetricsKind::DelayedReads, 1)
+            }
+        }
+    }
+
+    fn watch(&mut self, key: Key) {
+        self.store.watch(key);
+    }
+
+    fn unwatch(&mut self, key: &Key) {
+        self.store.unwatch(key);
     }
 
     fn to_clients(&mut self) -> Option<ExecutorResult> {
+        // execute the delayed reads whose writes have meanwhile been applied
+        while let Some(read) = self.delayed_reads.next_ready(&self.store) {
+            self.to_clients.push_back(read.execute(&mut self.store));
+        }
         self.to_clients.pop_front()
     }
 
+    fn to_watchers(&mut self) -> Vec<WatchNotification> {
+        self.store.take_notifications()
+    }
+
     fn to_executors(&mut self) -> Option<(ShardId, TableExecutionInfo)> {
         self.to_executors.pop()
     }
 
+    fn execution_info_dot(info: &Self::ExecutionInfo) -> Option<Dot> {
+        match info {
+            TableExecutionInfo::AttachedVotes { dot, .. } => Some(*dot),
+            _ => None,
+        }
+    }
+
     fn parallel() -> bool {
         true
     }
@@ -165,7 +304,28 @@ impl Executor for TableExecutor {
 }
 
 impl TableExecutor {
-    fn handle_stable_msg(&mut self, key: Key, rifl: Rifl) {
+    /// Compacts the votes table and drops the metadata kept for keys without
+    /// pending commands.
+    pub fn compact(&mut self) {
+        let compacted = self.table.compact();
+        self.pending
+            .retain(|_, pending_per_key| !pending_per_key.is_empty());
+        trace!(
+            "p{}: TableExecutor::compact {} tables",
+            self.process_id,
+            compacted
+        );
+        self.metrics
+            .aggregate(ExecutorMetricsKind::CompactedTables, compacted as u64);
+    }
+
+    fn handle_stable_msg(
+        &mut self,
+        key: Key,
+        rifl: Rifl,
+        aborted: bool,
+        time: &dyn SysTime,
+    ) {
         // get pending commands on this key
         let pending_per_key = self.pending.entry(key.clone()).or_default();
 
@@ -175,6 +335,8 @@ impl TableExecutor {
             if pending.rifl == rifl {
                 // decrease number of missing stable shards
                 pending.missing_stable_shards -= 1;
+                // and abort the command if it was aborted at some key
+                pending.aborted |= aborted;
                 trace!(
                     "p{}: key={} StableAtShard {:?} | missing shards {:?}",
                     self.process_id,
@@ -192,6 +354,7 @@ impl TableExecutor {
                         pending,
                         &mut self.store,
                         &mut self.to_clients,
+                        time,
                     );
 
                     // try to execute the remaining pending commands
@@ -207,6 +370,7 @@ impl TableExecutor {
                                 &mut self.to_executors,
                                 &mut pending_per_key.stable_shards_buffered,
                                 &self.rifl_to_stable_count,
+                                time,
                             );
                         if let Some(pending) = try_result {
                             // if this command cannot be executed, buffer it and
@@ -234,8 +398,12 @@ impl TableExecutor {
         }
     }
 
-    fn send_stable_or_execute<I>(&mut self, key: Key, mut to_execute: I)
-    where
+    fn send_stable_or_execute<I>(
+        &mut self,
+        key: Key,
+        mut to_execute: I,
+        time: &dyn SysTime,
+    ) where
         I: Iterator<Item = Pending>,
     {
         let pending_per_key = self.pending.entry(key.clone()).or_default();
@@ -263,6 +431,7 @@ impl TableExecutor {
                 &mut self.to_executors,
                 &mut pending_per_key.stable_shards_buffered,
                 &self.rifl_to_stable_count,
+                time,
             );
             if let Some(pending) = try_result {
                 // if this command cannot be executed, then add it (and all the
@@ -284,24 +453,36 @@ impl TableExecutor {
         to_clients: &mut VecDeque<ExecutorResult>,
         to_executors: &mut Vec<(ShardId, TableExecutionInfo)>,
         stable_shards_buffered: &mut HashMap<Rifl, usize>,
-        rifl_to_stable_count: &Arc<SharedMap<Rifl, Mutex<u64>>>,
+        rifl_to_stable_count: &Arc<SharedMap<Rifl, Mutex<StableCount>>>,
+        time: &dyn SysTime,
     ) -> Option<Pending> {
         let rifl = pending.rifl;
+        // the command is now stable at this key, so check if it fails here
+        pending.check(key, store);
         if pending.single_key_command() {
             // if the command is single-key, execute immediately
-            Self::do_execute(key.clone(), pending, store, to_clients);
+            Self::do_execute(key.clone(), pending, store, to_clients, time);
             None
         } else {
-            // closure that sends the stable message
-            let mut send_stable_msg = || {
-                for (shard_id, shard_keys) in pending.shard_to_keys.iter() {
+            // closure that sends the stable message; whether the command was
+            // aborted only matters to the keys on this shard, as ops on other
+            // shards are not executed all or nothing with ours
+            let shard_id = pending
+                .shard_to_keys
+                .iter()
+                .find(|(_, shard_keys)| shard_keys.contains(key))
+                .map(|(shard_id, _)| *shard_id)
+                .expect("key should be accessed by the command");
+            let mut send_stable_msg = |aborted: bool| {
+                for (to, shard_keys) in pending.shard_to_keys.iter() {
                     for shard_key in shard_keys {
                         if shard_key != key {
                             let msg = TableExecutionInfo::stable_at_shard(
                                 shard_key.clone(),
                                 rifl,
+                                aborted && *to == shard_id,
                             );
-                            to_executors.push((*shard_id, msg));
+                            to_executors.push((*to, msg));
                         }
                     }
                 }
@@ -311,24 +492,27 @@ impl TableExecutor {
             if pending.shard_key_count == 1 {
                 // if this command access a single key on this shard, then send
                 // the stable message right away
-                assert!(send_stable_msg());
+                assert!(send_stable_msg(pending.aborted));
                 // and update the number of shards the key is stable at
                 pending.missing_stable_shards -= 1;
             } else {
                 // otherwise, increase rifl count
-                let count_ref =
-                    rifl_to_stable_count.get_or(&rifl, || Mutex::new(0));
+                let count_ref = rifl_to_stable_count
+                    .get_or(&rifl, || Mutex::new(StableCount::default()));
                 let mut count = count_ref.lock();
-                *count += 1;
+                count.count += 1;
+                count.aborted |= pending.aborted;
 
                 // if we're the last key at this shard increasing the rifl count
                 // to the number of keys in this shard, then
                 // notify all keys that the command is stable at
                 // this shard
-                if *count == pending.shard_key_count {
+                if count.count == pending.shard_key_count {
+                    // at this point, all keys in this shard have been checked
+                    pending.aborted = count.aborted;
                     // the command is stable at this shard; so send stable
                     // messsage
-                    assert!(send_stable_msg());
+                    assert!(send_stable_msg(pending.aborted));
                     // and update the number of shards the key is stable at
                     pending.missing_stable_shards -= 1;
 
@@ -348,7 +532,7 @@ impl TableExecutor {
 
             if pending.missing_stable_shards == 0 {
                 // if the command is already stable at shards, then execute it
-                Self::do_execute(key.clone(), pending, store, to_clients);
+                Self::do_execute(key.clone(), pending, store, to_clients, time);
                 None
             } else {
                 // in this case, the command cannot be executed; so send it back
@@ -358,8 +542,14 @@ impl TableExecutor {
         }
     }
 
-    fn execute(&mut self, key: Key, stable: Pending) {
-        Self::do_execute(key, stable, &mut self.store, &mut self.to_clients)
+    fn execute(&mut self, key: Key, stable: Pending, time: &dyn SysTime) {
+        Self::do_execute(
+            key,
+            stable,
+            &mut self.store,
+            &mut self.to_clients,
+            time,
+        )
     }
 
     fn do_execute(
@@ -367,16 +557,31 @@ impl TableExecutor {
         stable: Pending,
         store: &mut KVStore,
         to_clients: &mut VecDeque<ExecutorResult>,
+        time: &dyn SysTime,
     ) {
-        // take the ops inside the arc if we're the last with a reference to it
-        // (otherwise, clone them)
         let rifl = stable.rifl;
-        let ops = stable.ops;
-        let ops =
-            Arc::try_unwrap(ops).unwrap_or_else(|ops| ops.as_ref().clone());
-        // execute ops in the `KVStore`
-        let partial_results = store.execute(&key, ops, rifl);
-        to_clients.push_back(ExecutorResult::new(rifl, key, partial_results));
+        let partial_results = if stable.aborted {
+            // if the command was aborted, none of its ops are executed (as in
+            // `Command::execute`)
+            kvs::aborted(stable.ops.len(), stable.failure)
+        } else {
+            // take the ops inside the arc if we're the last with a reference
+            // to it (otherwise, clone them)
+            let ops = Arc::try_unwrap(stable.ops)
+                .unwrap_or_else(|ops| ops.as_ref().clone());
+            // execute ops in the `KVStore`; since all replicas execute the
+            // command with the same clock, they also expire values identically
+            store.set_time(stable.clock);
+            store.execute(&key, ops, rifl)
+        };
+        let timestamps =
+            ExecutionTimestamps::new(stable.commit_time, time.millis())
+                .with_submit(stable.submit_time)
+                .with_fast_path(stable.fast_path);
+        to_clients.push_back(
+            ExecutorResult::new(rifl, key, partial_results)
+                .with_timestamps(timestamps),
+        );
     }
 }
 
@@ -389,7 +594,15 @@ pub enum TableExecutionInfo {
         rifl: Rifl,
         shard_to_keys: Arc<HashMap<ShardId, Vec<Key>>>,
         ops: Arc<Vec<KVOp>>,
+        // whether the ops on the keys of this shard are executed all or
+        // nothing
+        atomic: bool,
         votes: Vec<VoteRange>,
+        // time at which the command was submitted to its coordinator
+        submit_time: Option<u64>,
+        // whether the command was committed in the fast path (only known by
+        // its coordinator)
+        fast_path: Option<bool>,
     },
     DetachedVotes {
         key: Key,
@@ -398,10 +611,13 @@ pub enum TableExecutionInfo {
     StableAtShard {
         key: Key,
         rifl: Rifl,
+        // whether the command was aborted at some key of the shard of `key`
+        aborted: bool,
     },
 }
 
 impl TableExecutionInfo {
+    #[allow(clippy::too_many_arguments)]
     pub fn attached_votes(
         dot: Dot,
         clock: u64,
@@ -409,7 +625,10 @@ impl TableExecutionInfo {
         rifl: Rifl,
         shard_to_keys: Arc<HashMap<ShardId, Vec<Key>>>,
         ops: Arc<Vec<KVOp>>,
+        atomic: bool,
         votes: Vec<VoteRange>,
+        submit_time: Option<u64>,
+        fast_path: Option<bool>,
     ) -> Self {
         Self::AttachedVotes {
             dot,
@@ -418,7 +637,10 @@ impl TableExecutionInfo {
             rifl,
             shard_to_keys,
             ops,
+            atomic,
             votes,
+            submit_time,
+            fast_path,
         }
     }
 
@@ -426,8 +648,8 @@ impl TableExecutionInfo {
         Self::DetachedVotes { key, votes }
     }
 
-    pub fn stable_at_shard(key: Key, rifl: Rifl) -> Self {
-        Self::StableAtShard { key, rifl }
+    pub fn stable_at_shard(key: Key, rifl: Rifl, aborted: bool) -> Self {
+        Self::StableAtShard { key, rifl, aborted }
     }
 }
 
@@ -25,6 +25,13 @@ pub struct MultiVotesTable {
     n: usize,
     stability_threshold: usize,
     tables: HashMap<Key, VotesTable>,
+    // tables without pending ops and whose votes form a contiguous prefix are
+    // compacted into a single frontier per process (ordered by process id)
+    compacted: HashMap<Key, Vec<u64>>,
+    // fully stable tables (i.e. compacted tables in which all processes have
+    // the same frontier) are dropped, keeping only that frontier; it's still
+    // needed since the next votes of each process start right after it
+    stable: HashMap<Key, u64>,
 }
 
 impl MultiVotesTable {
@@ -41,9 +48,65 @@ impl MultiVotesTable {
             n,
             stability_threshold,
             tables: HashMap::new(),
+            compacted: HashMap::new(),
+            stable: HashMap::new(),
         }
     }
 
+    /// Compacts all tables that have no pending ops and whose votes form a
+    /// contiguous prefix, returning the number of tables compacted. Tables
+    /// that are fully stable are dropped, keeping only their stable clock.
+    pub fn compact(&mut self) -> usize {
+        let to_compact: Vec<_> = self
+            .tables
+            .iter()
+            .filter(|(_, table)| table.compactable())
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in to_compact.iter() {
+            let table = self
+                .tables
+                .remove(key)
+                .expect("table to be compacted should exist");
+            trace!(
+                "p{}: key={} MultiVotesTable::compact {:?}",
+                self.process_id,
+                key,
+                table.votes_clock
+            );
+            let frontiers = table.frontiers();
+            if frontiers.iter().all(|frontier| *frontier == frontiers[0]) {
+                self.stable.insert(key.clone(), frontiers[0]);
+            } else {
+                self.compacted.insert(key.clone(), frontiers);
+            }
+        }
+        to_compact.len()
+    }
+
+    /// Returns the stable clock of `key`: all commands on it with a higher
+    /// clock are yet to be executed. Keys without votes have stable clock 0.
+    pub fn stable_clock(&self, key: &Key) -> u64 {
+        if let Some(stable_clock) = self.stable.get(key) {
+            return *stable_clock;
+        }
+        let mut frontiers =
+            match (self.tables.get(key), self.compacted.get(key)) {
+                (Some(table), _) => table.frontiers(),
+                (None, Some(frontiers)) => frontiers.clone(),
+                (None, None) => return 0,
+            };
+        frontiers.sort_unstable();
+        frontiers[frontiers.len() - self.stability_threshold]
+    }
+
+    /// Returns the number of tables (not compacted, compacted and fully
+    /// stable).
+    #[cfg(test)]
+    fn table_count(&self) -> (usize, usize, usize) {
+        (self.tables.len(), self.compacted.len(), self.stable.len())
+    }
+
     /// Add a new command, its clock and votes to the votes table.
     pub fn add_attached_votes(
         &mut self,
@@ -85,14 +148,20 @@ impl MultiVotesTable {
         let table = match self.tables.get_mut(key) {
             Some(table) => table,
             None => {
-                // table does not exist, let's create a new one and insert it
-                let table = VotesTable::new(
+                // table does not exist, let's create a new one (restoring its
+                // votes if it has been compacted) and insert it
+                let mut table = VotesTable::new(
                     key.clone(),
                     self.process_id,
                     self.shard_id,
                     self.n,
                     self.stability_threshold,
                 );
+                if let Some(frontiers) = self.compacted.remove(key) {
+                    table.restore(self.shard_id, frontiers);
+                } else if let Some(stable_clock) = self.stable.remove(key) {
+                    table.restore(self.shard_id, vec![stable_clock; self.n]);
+                }
                 self.tables.entry(key.clone()).or_insert(table)
             }
         };
@@ -138,6 +207,42 @@ impl VotesTable {
         }
     }
 
+    // A table can be compacted if there are no pending ops and there are no
+    // votes above the frontier of any process.
+    fn compactable(&self) -> bool {
+        self.ops.is_empty()
+            && self
+                .votes_clock
+                .iter()
+                .all(|(_, eset)| eset.events().1.is_empty())
+    }
+
+    // Returns the frontier of each process, ordered by process id.
+    fn frontiers(&self) -> Vec<u64> {
+        let mut frontiers: Vec<_> = self
+            .votes_clock
+            .iter()
+            .map(|(process_id, eset)| (*process_id, eset.frontier()))
+            .collect();
+        frontiers.sort_unstable();
+        frontiers
+            .into_iter()
+            .map(|(_, frontier)| frontier)
+            .collect()
+    }
+
+    // Restores the votes of a compacted table.
+    fn restore(&mut self, shard_id: ShardId, frontiers: Vec<u64>) {
+        let mut ids: Vec<_> = util::process_ids(shard_id, self.n).collect();
+        ids.sort_unstable();
+        assert_eq!(ids.len(), frontiers.len());
+        for (process_id, frontier) in ids.into_iter().zip(frontiers) {
+            if frontier > 0 {
+                self.votes_clock.add_range(&process_id, 1, frontier);
+            }
+        }
+    }
+
     fn add_attached_votes(
         &mut self,
         dot: Dot,
@@ -269,9 +374,11 @@ impl VotesTable {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fantoch::command::DEFAULT_SHARD_ID;
+    use fantoch::command::{Command, DEFAULT_SHARD_ID};
+    use fantoch::config::Config;
+    use fantoch::executor::conformance::ConformanceCase;
     use fantoch::id::{ClientId, Rifl};
-    use fantoch::kvs::KVOp;
+    use fantoch::kvs::{KVOp, Value, Version};
     use permutator::Permutation;
     use std::sync::Arc;
 
@@ -300,15 +407,30 @@ mod tests {
 
         // in this example we'll use the dot as rifl;
         // also, all commands access a single key
-        let pending = |value: &'static str, rifl: Rifl| -> Pending {
-            let shard_to_keys = Arc::new(
-                vec![(DEFAULT_SHARD_ID, vec!["KEY".to_string()])]
-                    .into_iter()
-                    .collect(),
-            );
-            let ops = Arc::new(vec![KVOp::Put(String::from(value))]);
-            Pending::new(DEFAULT_SHARD_ID, rifl, shard_to_keys, ops)
-        };
+        let pending =
+            |value: &'static str, rifl: Rifl, clock: u64| -> Pending {
+                let shard_to_keys = Arc::new(
+                    vec![(DEFAULT_SHARD_ID, vec!["KEY".to_string()])]
+                        .into_iter()
+                        .collect(),
+                );
+                let ops = Arc::new(vec![KVOp::Put(Value::from(value))]);
+                let atomic = false;
+                let submit_time = None;
+                let commit_time = 0;
+                let fast_path = None;
+                Pending::new(
+                    DEFAULT_SHARD_ID,
+                    rifl,
+                    shard_to_keys,
+                    ops,
+                    atomic,
+                    clock,
+                    submit_time,
+                    commit_time,
+                    fast_path,
+                )
+            };
 
         // a1
         let a1 = "A1";
@@ -381,18 +503,18 @@ mod tests {
         table.add_attached_votes(
             a1_dot,
             a1_clock,
-            pending(a1, a1_rifl),
+            pending(a1, a1_rifl, a1_clock),
             a1_votes.clone(),
         );
         // get stable: a1
         let stable = table.stable_ops().collect::<Vec<_>>();
-        assert_eq!(stable, vec![pending(a1, a1_rifl)]);
+        assert_eq!(stable, vec![pending(a1, a1_rifl, a1_clock)]);
 
         // add d1 to table
         table.add_attached_votes(
             d1_dot,
             d1_clock,
-            pending(d1, d1_rifl),
+            pending(d1, d1_rifl, d1_clock),
             d1_votes.clone(),
         );
         // get stable: none
@@ -403,18 +525,24 @@ mod tests {
         table.add_attached_votes(
             c1_dot,
             c1_clock,
-            pending(c1, c1_rifl),
+            pending(c1, c1_rifl, c1_clock),
             c1_votes.clone(),
         );
         // get stable: c1 then d1
         let stable = table.stable_ops().collect::<Vec<_>>();
-        assert_eq!(stable, vec![pending(c1, c1_rifl), pending(d1, d1_rifl)]);
+        assert_eq!(
+            stable,
+            vec![
+                pending(c1, c1_rifl, c1_clock),
+                pending(d1, d1_rifl, d1_clock)
+            ]
+        );
 
         // add e2 to table
         table.add_attached_votes(
             e2_dot,
             e2_clock,
-            pending(e2, e2_rifl),
+            pending(e2, e2_rifl, e2_clock),
             e2_votes.clone(),
         );
         // get stable: none
@@ -425,28 +553,34 @@ mod tests {
         table.add_attached_votes(
             e1_dot,
             e1_clock,
-            pending(e1, e1_rifl),
+            pending(e1, e1_rifl, e1_clock),
             e1_votes.clone(),
         );
         // get stable: none
         let stable = table.stable_ops().collect::<Vec<_>>();
-        assert_eq!(stable, vec![pending(e1, e1_rifl), pending(e2, e2_rifl)]);
+        assert_eq!(
+            stable,
+            vec![
+                pending(e1, e1_rifl, e1_clock),
+                pending(e2, e2_rifl, e2_clock)
+            ]
+        );
 
         // run all the permutations of the above and check that the final total
         // order is the same
         let total_order = vec![
-            pending(a1, a1_rifl),
-            pending(c1, c1_rifl),
-            pending(d1, d1_rifl),
-            pending(e1, e1_rifl),
-            pending(e2, e2_rifl),
+            pending(a1, a1_rifl, a1_clock),
+            pending(c1, c1_rifl, c1_clock),
+            pending(d1, d1_rifl, d1_clock),
+            pending(e1, e1_rifl, e1_clock),
+            pending(e2, e2_rifl, e2_clock),
         ];
         let mut all_ops = vec![
-            (a1_dot, a1_clock, pending(a1, a1_rifl), a1_votes),
-            (c1_dot, c1_clock, pending(c1, c1_rifl), c1_votes),
-            (d1_dot, d1_clock, pending(d1, d1_rifl), d1_votes),
-            (e1_dot, e1_clock, pending(e1, e1_rifl), e1_votes),
-            (e2_dot, e2_clock, pending(e2, e2_rifl), e2_votes),
+            (a1_dot, a1_clock, pending(a1, a1_rifl, a1_clock), a1_votes),
+            (c1_dot, c1_clock, pending(c1, c1_rifl, c1_clock), c1_votes),
+            (d1_dot, d1_clock, pending(d1, d1_rifl, d1_clock), d1_votes),
+            (e1_dot, e1_clock, pending(e1, e1_rifl, e1_clock), e1_votes),
+            (e2_dot, e2_clock, pending(e2, e2_rifl, e2_clock), e2_votes),
         ];
 
         all_ops.permutation().for_each(|p| {
@@ -495,15 +629,30 @@ mod tests {
 
         // in this example we'll use the dot as rifl;
         // also, all commands access a single key
-        let pending = |value: &'static str, rifl: Rifl| -> Pending {
-            let shard_to_keys = Arc::new(
-                vec![(DEFAULT_SHARD_ID, vec!["KEY".to_string()])]
-                    .into_iter()
-                    .collect(),
-            );
-            let ops = Arc::new(vec![KVOp::Put(String::from(value))]);
-            Pending::new(DEFAULT_SHARD_ID, rifl, shard_to_keys, ops)
-        };
+        let pending =
+            |value: &'static str, rifl: Rifl, clock: u64| -> Pending {
+                let shard_to_keys = Arc::new(
+                    vec![(DEFAULT_SHARD_ID, vec!["KEY".to_string()])]
+                        .into_iter()
+                        .collect(),
+                );
+                let ops = Arc::new(vec![KVOp::Put(Value::from(value))]);
+                let atomic = false;
+                let submit_time = None;
+                let commit_time = 0;
+                let fast_path = None;
+                Pending::new(
+                    DEFAULT_SHARD_ID,
+                    rifl,
+                    shard_to_keys,
+                    ops,
+                    atomic,
+                    clock,
+                    submit_time,
+                    commit_time,
+                    fast_path,
+                )
+            };
 
         // a1
         let a1 = "A1";
@@ -521,7 +670,7 @@ mod tests {
         table.add_attached_votes(
             a1_dot,
             a1_clock,
-            pending(a1, a1_rifl),
+            pending(a1, a1_rifl, a1_clock),
             a1_votes.clone(),
         );
         // get stable: none
@@ -545,7 +694,7 @@ mod tests {
         table.add_attached_votes(
             c1_dot,
             c1_clock,
-            pending(c1, c1_rifl),
+            pending(c1, c1_rifl, c1_clock),
             c1_votes.clone(),
         );
         // get stable: none
@@ -568,12 +717,18 @@ mod tests {
         table.add_attached_votes(
             e1_dot,
             e1_clock,
-            pending(e1, e1_rifl),
+            pending(e1, e1_rifl, e1_clock),
             e1_votes.clone(),
         );
         // get stable: a1 and e1
         let stable = table.stable_ops().collect::<Vec<_>>();
-        assert_eq!(stable, vec![pending(a1, a1_rifl), pending(e1, e1_rifl)]);
+        assert_eq!(
+            stable,
+            vec![
+                pending(a1, a1_rifl, a1_clock),
+                pending(e1, e1_rifl, e1_clock)
+            ]
+        );
 
         // a2
         let a2 = "A2";
@@ -592,7 +747,7 @@ mod tests {
         table.add_attached_votes(
             a2_dot,
             a2_clock,
-            pending(a2, a2_rifl),
+            pending(a2, a2_rifl, a2_clock),
             a2_votes.clone(),
         );
         // get stable: none
@@ -616,7 +771,7 @@ mod tests {
         table.add_attached_votes(
             d1_dot,
             d1_clock,
-            pending(d1, d1_rifl),
+            pending(d1, d1_rifl, d1_clock),
             d1_votes.clone(),
         );
         // get stable
@@ -624,9 +779,9 @@ mod tests {
         assert_eq!(
             stable,
             vec![
-                pending(c1, c1_rifl),
-                pending(a2, a2_rifl),
-                pending(d1, d1_rifl),
+                pending(c1, c1_rifl, c1_clock),
+                pending(a2, a2_rifl, a2_clock),
+                pending(d1, d1_rifl, d1_clock),
             ]
         );
     }
@@ -648,11 +803,14 @@ mod tests {
 
         // closure to compute the stable clock for some key
         let stable_clock = |table: &mut MultiVotesTable, key: &Key| {
-            table
+            let stable_clock = table
                 .tables
                 .get_mut(key)
                 .expect("table for this key should exist")
-                .stable_clock()
+                .stable_clock();
+            // which is also the one returned to purge expired values
+            assert_eq!(table.stable_clock(key), stable_clock);
+            stable_clock
         };
 
         // p1 votes on key A
@@ -712,4 +870,218 @@ mod tests {
         assert_eq!(stable_clock(&mut table, &key_a), 1);
         assert_eq!(stable_clock(&mut table, &key_b), 1);
     }
+
+    #[test]
+    fn compaction() {
+        let shard_id = 0;
+
+        // create table
+        let process_id = 1;
+        let n = 3;
+        let stability_threshold = 2;
+        let mut table =
+            MultiVotesTable::new(process_id, shard_id, n, stability_threshold);
+
+        // create keys
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+
+        // closure to compute the stable clock for some key
+        let stable_clock = |table: &mut MultiVotesTable, key: &Key| {
+            table
+                .tables
+                .get_mut(key)
+                .expect("table for this key should exist")
+                .stable_clock()
+        };
+
+        // p1 and p2 vote on key A with contiguous votes
+        let stable = table
+            .add_detached_votes(
+                &key_a,
+                vec![VoteRange::new(1, 1, 2), VoteRange::new(2, 1, 3)],
+            )
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+        assert_eq!(stable_clock(&mut table, &key_a), 2);
+
+        // p1 votes on key B leaving a gap
+        let stable = table
+            .add_detached_votes(&key_b, vec![VoteRange::new(1, 2, 2)])
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+
+        // only key A can be compacted
+        assert_eq!(table.compact(), 1);
+        assert_eq!(table.table_count(), (1, 1, 0));
+
+        // compacting again does nothing
+        assert_eq!(table.compact(), 0);
+        assert_eq!(table.table_count(), (1, 1, 0));
+
+        // once more votes are added to key A, its table is restored with the
+        // votes it had before being compacted
+        let stable = table
+            .add_detached_votes(&key_a, vec![VoteRange::new(3, 1, 1)])
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+        assert_eq!(table.table_count(), (2, 0, 0));
+        assert_eq!(stable_clock(&mut table, &key_a), 2);
+
+        // p1 fills the gap on key B, which can now be compacted as well
+        let stable = table
+            .add_detached_votes(&key_b, vec![VoteRange::new(1, 1, 1)])
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+        assert_eq!(table.compact(), 2);
+        assert_eq!(table.table_count(), (0, 2, 0));
+
+        // once p2 and p3 vote on key B up to the frontier of p1, key B is
+        // fully stable, and only its stable clock is kept
+        let stable = table
+            .add_detached_votes(
+                &key_b,
+                vec![VoteRange::new(2, 1, 2), VoteRange::new(3, 1, 2)],
+            )
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+        assert_eq!(table.compact(), 1);
+        assert_eq!(table.table_count(), (0, 1, 1));
+        assert_eq!(table.stable_clock(&key_b), 2);
+
+        // more votes on key B restore all the votes it had
+        let stable = table
+            .add_detached_votes(&key_b, vec![VoteRange::new(1, 3, 3)])
+            .collect::<Vec<_>>();
+        assert!(stable.is_empty());
+        assert_eq!(table.table_count(), (1, 1, 0));
+        assert_eq!(stable_clock(&mut table, &key_b), 2);
+    }
+
+    fantoch::executor_conformance!(
+        table_executor_conformance,
+        TableExecutor,
+        conformance_cases()
+    );
+
+    fn conformance_cases() -> Vec<ConformanceCase<TableExecutor>> {
+        let process_id = 1;
+        let shard_id = 0;
+        let n = 3;
+        let f = 1;
+        let config = Config::new(n, f);
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let value = |value: &str| Value::from(value.to_string());
+
+        // each command is assigned the next clock, which all processes vote
+        // for on every key the command accesses
+        let case = |commands: Vec<Command>| {
+            let infos = commands
+                .iter()
+                .zip(1..)
+                .flat_map(|(cmd, clock)| {
+                    let dot = Dot::new(1, clock);
+                    let atomic = cmd.has_conditions_on(shard_id)
+                        || cmd.has_validations_on(shard_id);
+                    cmd.iter(shard_id)
+                        .map(|(key, ops)| {
+                            let votes = (1..=n as ProcessId)
+                                .map(|voter| {
+                                    VoteRange::new(voter, clock, clock)
+                                })
+                                .collect();
+                            TableExecutionInfo::attached_votes(
+                                dot,
+                                clock,
+                                key.clone(),
+                                cmd.rifl(),
+                                cmd.shard_to_keys().clone(),
+                                ops.clone(),
+                                atomic,
+                                votes,
+                                None,
+                                None,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            ConformanceCase::new(process_id, shard_id, config, commands, infos)
+        };
+
+        // a failed condition on one key leaves the other keys untouched
+        let conditional = case(vec![
+            Command::from(
+                Rifl::new(1, 1),
+                vec![
+                    (key_a.clone(), KVOp::Put(value("1"))),
+                    (key_b.clone(), KVOp::Put(value("1"))),
+                ],
+            ),
+            Command::from(
+                Rifl::new(2, 1),
+                vec![
+                    (
+                        key_a.clone(),
+                        KVOp::Cas {
+                            expected: value("0"),
+                            new: value("2"),
+                        },
+                    ),
+                    (key_b.clone(), KVOp::Put(value("2"))),
+                ],
+            ),
+            Command::from(
+                Rifl::new(3, 1),
+                vec![(key_a.clone(), KVOp::Get), (key_b.clone(), KVOp::Get)],
+            ),
+            // while a condition that holds lets all ops execute
+            Command::from(
+                Rifl::new(1, 2),
+                vec![
+                    (
+                        key_a.clone(),
+                        KVOp::Cas {
+                            expected: value("1"),
+                            new: value("3"),
+                        },
+                    ),
+                    (key_b.clone(), KVOp::Put(value("3"))),
+                ],
+            ),
+            Command::from(
+                Rifl::new(2, 2),
+                vec![(key_a.clone(), KVOp::Get), (key_b.clone(), KVOp::Get)],
+            ),
+        ]);
+
+        // the same holds for validations (here, of a version key A never had)
+        let version = Version {
+            rifl: Rifl::new(1, 1),
+            time: 1,
+        };
+        let validation = case(vec![
+            Command::from(
+                Rifl::new(1, 1),
+                vec![
+                    (key_a.clone(), KVOp::Get),
+                    (key_b.clone(), KVOp::Put(value("1"))),
+                ],
+            ),
+            Command::from(
+                Rifl::new(2, 1),
+                vec![
+                    (key_a.clone(), KVOp::Validate(Some(version))),
+                    (key_b.clone(), KVOp::Put(value("2"))),
+                ],
+            ),
+            Command::from(
+                Rifl::new(3, 1),
+                vec![(key_a.clone(), KVOp::Get), (key_b.clone(), KVOp::Get)],
+            ),
+        ]);
+
+        vec![conditional, validation]
+    }
 }
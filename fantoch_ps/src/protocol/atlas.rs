@@ -118,7 +118,14 @@ impl<KD: KeyDeps> Protocol for Atlas<KD> {
     }
 
     /// Submits a command issued by some client.
-    fn submit(&mut self, dot: Option<Dot>, cmd: Command, _time: &dyn SysTime) {
+    fn submit(
+        &mut self,
+        dot: Option<Dot>,
+        mut cmd: Command,
+        time: &dyn SysTime,
+    ) {
+        cmd.set_submit_time(time.millis());
+        cmd.set_logical_time(self.bp.next_logical_time());
         self.handle_submit(dot, cmd, true)
     }
 
@@ -267,6 +274,9 @@ impl<KD: KeyDeps> Atlas<KD> {
             time.micros()
         );
 
+        // commands submitted next by this process are assigned a later time
+        self.bp.observe_logical_time(&cmd);
+
         // get cmd info
         let info = self.cmds.get(dot);
 
@@ -370,8 +380,10 @@ impl<KD: KeyDeps> Atlas<KD> {
             // create consensus value
             let value = ConsensusValue::with(all_deps);
 
-            // fast path metrics
-            let cmd = info.cmd.as_ref().unwrap();
+            // fast path metrics; the command is also tagged with the path
+            // taken, so that its result reports it to the client
+            let cmd = info.cmd.as_mut().unwrap();
+            cmd.set_fast_path(fast_path);
             self.bp.path(fast_path, cmd.read_only());
 
             // fast path condition:
@@ -890,6 +902,29 @@ impl MessageIndex for Message {
             Self::MStable { .. } => None,
         }
     }
+
+    fn background(&self) -> bool {
+        matches!(
+            self,
+            Self::MCommitDot { .. }
+                | Self::MGarbageCollection { .. }
+                | Self::MStable { .. }
+        )
+    }
+
+    fn dot(&self) -> Option<&Dot> {
+        match self {
+            Self::MCollect { dot, .. }
+            | Self::MCollectAck { dot, .. }
+            | Self::MCommit { dot, .. }
+            | Self::MConsensus { dot, .. }
+            | Self::MConsensusAck { dot, .. }
+            | Self::MForwardSubmit { dot, .. }
+            | Self::MShardCommit { dot, .. }
+            | Self::MShardAggregatedCommit { dot, .. } => Some(dot),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -137,7 +137,14 @@ impl<KC: KeyClocks> Protocol for Caesar<KC> {
     }
 
     /// Submits a command issued by some client.
-    fn submit(&mut self, dot: Option<Dot>, cmd: Command, _time: &dyn SysTime) {
+    fn submit(
+        &mut self,
+        dot: Option<Dot>,
+        mut cmd: Command,
+        time: &dyn SysTime,
+    ) {
+        cmd.set_submit_time(time.millis());
+        cmd.set_logical_time(self.bp.next_logical_time());
         self.handle_submit(dot, cmd);
     }
 
@@ -287,6 +294,9 @@ impl<KC: KeyClocks> Caesar<KC> {
         // merge clocks
         self.key_clocks.clock_join(&remote_clock);
 
+        // commands submitted next by this process are assigned a later time
+        self.bp.observe_logical_time(&cmd);
+
         // get cmd info
         let info_ref = self.cmds.get_or_default(dot);
         let mut info = info_ref.lock();
@@ -562,8 +572,10 @@ impl<KC: KeyClocks> Caesar<KC> {
             let (aggregated_clock, aggregated_deps, fast_path) =
                 info.quorum_clocks.aggregated();
 
-            // fast path metrics
-            let cmd = info.cmd.as_ref().unwrap();
+            // fast path metrics; the command is also tagged with the path
+            // taken, so that its result reports it to the client
+            let cmd = info.cmd.as_mut().unwrap();
+            cmd.set_fast_path(fast_path);
             self.bp.path(fast_path, cmd.read_only());
 
             // fast path condition: all processes reported ok
@@ -1358,6 +1370,21 @@ impl MessageIndex for Message {
             Self::MGCDot { dot } => worker_dot_index_shift(&dot),
         }
     }
+
+    fn background(&self) -> bool {
+        matches!(self, Self::MGarbageCollection { .. } | Self::MGCDot { .. })
+    }
+
+    fn dot(&self) -> Option<&Dot> {
+        match self {
+            Self::MPropose { dot, .. }
+            | Self::MProposeAck { dot, .. }
+            | Self::MCommit { dot, .. }
+            | Self::MRetry { dot, .. }
+            | Self::MRetryAck { dot, .. } => Some(dot),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
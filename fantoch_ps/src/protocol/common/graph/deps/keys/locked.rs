@@ -13,6 +13,7 @@ pub struct LockedKeyDeps {
     nfr: bool,
     latest: Arc<SharedMap<Key, RwLock<LatestRWDep>>>,
     latest_noop: Arc<RwLock<LatestDep>>,
+    latest_scan: Arc<RwLock<LatestDep>>,
 }
 
 impl KeyDeps for LockedKeyDeps {
@@ -23,6 +24,7 @@ impl KeyDeps for LockedKeyDeps {
             nfr,
             latest: Arc::new(SharedMap::new()),
             latest_noop: Arc::new(RwLock::new(None)),
+            latest_scan: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -93,6 +95,17 @@ impl LockedKeyDeps {
             true
         });
 
+        // scans conflict with all writes: if the command has a scan, then
+        // it depends on the latest write on every key (and it becomes the
+        // latest scan); as in `do_add_noop`, we first set ourselves as the
+        // latest scan, so that writes on keys we may miss in the iteration
+        // will see us
+        let scan = cmd.has_scans_on(self.shard_id);
+        if scan {
+            self.latest_scan.write().replace(cmd_dep.clone());
+            self.do_scan_deps(&mut deps);
+        }
+
         // iterate through all command keys, grab a write lock, get their
         // current latest and set ourselves to be the new latest
         cmd.keys(self.shard_id).for_each(|key| {
@@ -119,6 +132,11 @@ impl LockedKeyDeps {
         // `do_add_noop` is correct
         self.maybe_add_noop_latest(&mut deps);
 
+        // include the latest scan, if any (and if the command is not a scan)
+        if !scan {
+            self.maybe_add_scan_latest(read_only, &mut deps);
+        }
+
         // and finally return the computed deps
         deps
     }
@@ -149,6 +167,36 @@ impl LockedKeyDeps {
         deps
     }
 
+    fn maybe_add_scan_latest(
+        &self,
+        read_only: bool,
+        deps: &mut HashSet<Dependency>,
+    ) {
+        // as with reads, writes only depend on scans if NFR is not enabled
+        if !read_only && !self.nfr {
+            // for this operation we only need a read lock
+            if let Some(dep) = self.latest_scan.read().as_ref() {
+                deps.insert(dep.clone());
+            }
+        }
+    }
+
+    /// Includes the latest write on every key in `deps`. Like
+    /// `do_noop_deps`, this is linear in the number of keys ever accessed, and
+    /// grabs a read lock on each of them, so scans are much more expensive
+    /// than single-key commands, and slow down concurrent writes while they
+    /// iterate.
+    fn do_scan_deps(&self, deps: &mut HashSet<Dependency>) {
+        // iterate through all keys, grab a read lock, and include their latest
+        // write in the final `deps`
+        self.latest.iter().for_each(|entry| {
+            let latest_rw = entry.value().read();
+            if let Some(wdep) = latest_rw.write.as_ref() {
+                deps.insert(wdep.clone());
+            }
+        });
+    }
+
     fn do_noop_deps(&self, deps: &mut HashSet<Dependency>) {
         // iterate through all keys, grab a read lock, and include their latest
         // in the final `deps`
@@ -169,6 +217,12 @@ impl LockedKeyDeps {
         // flag indicating whether the command is read-only
         let read_only = cmd.read_only();
 
+        if cmd.has_scans_on(self.shard_id) {
+            self.do_scan_deps(deps);
+        } else {
+            self.maybe_add_scan_latest(read_only, deps);
+        }
+
         cmd.keys(self.shard_id).for_each(|key| {
             // get latest read and write on this key
             let entry = self.latest.get_or(key, || RwLock::default());
@@ -112,7 +112,7 @@ mod tests {
     use super::*;
     use crate::util;
     use fantoch::id::{DotGen, ProcessId, Rifl};
-    use fantoch::kvs::KVOp;
+    use fantoch::kvs::{KVOp, Value};
     use fantoch::{HashMap, HashSet};
     use std::iter::FromIterator;
     use std::thread;
@@ -122,6 +122,7 @@ mod tests {
         key_deps_flow::<SequentialKeyDeps>();
         read_deps::<SequentialKeyDeps>(false);
         read_deps::<SequentialKeyDeps>(true);
+        scan_deps::<SequentialKeyDeps>();
     }
 
     #[test]
@@ -129,6 +130,7 @@ mod tests {
         key_deps_flow::<LockedKeyDeps>();
         read_deps::<LockedKeyDeps>(false);
         read_deps::<LockedKeyDeps>(true);
+        scan_deps::<LockedKeyDeps>();
     }
 
     fn get(rifl: Rifl, key: String) -> Command {
@@ -138,7 +140,7 @@ mod tests {
         )
     }
 
-    fn multi_put(rifl: Rifl, keys: Vec<String>, value: String) -> Command {
+    fn multi_put(rifl: Rifl, keys: Vec<String>, value: Value) -> Command {
         Command::from(
             rifl,
             keys.into_iter()
@@ -160,7 +162,7 @@ mod tests {
         let key_a = String::from("A");
         let key_b = String::from("B");
         let key_c = String::from("C");
-        let value = String::from("");
+        let value = Value::new();
 
         // command a
         let cmd_a_rifl = Rifl::new(100, 1); // client 100, 1st op
@@ -391,7 +393,7 @@ mod tests {
 
         // keys
         let key = String::from("A");
-        let value = String::from("");
+        let value = Value::new();
 
         // read
         let read_rifl = Rifl::new(100, 1); // client 100, 1st op
@@ -484,6 +486,54 @@ mod tests {
         }
     }
 
+    fn scan_deps<KD: KeyDeps>() {
+        // create key deps
+        let shard_id = 0;
+        let nfr = false;
+        let mut key_deps = KD::new(shard_id, nfr);
+
+        // create dot gen
+        let process_id = 1;
+        let mut dot_gen = DotGen::new(process_id);
+
+        // keys
+        let key_a = String::from("A");
+        let key_b = String::from("B");
+        let key_c = String::from("C");
+        let value = Value::new();
+
+        // commands
+        let scan_rifl = Rifl::new(100, 1); // client 100, 1st op
+        let scan = Command::scan(scan_rifl, key_a.clone(), key_c.clone(), 10);
+        let write_a_rifl = Rifl::new(101, 1); // client 101, 1st op
+        let write_a = multi_put(write_a_rifl, vec![key_a], value.clone());
+        let write_b_rifl = Rifl::new(102, 1); // client 102, 1st op
+        let write_b = multi_put(write_b_rifl, vec![key_b], value.clone());
+        let read_c_rifl = Rifl::new(103, 1); // client 103, 1st op
+        let read_c = get(read_c_rifl, key_c.clone());
+
+        // add write a with {1,1}, write b with {1,2} and read c with {1,3}
+        key_deps.add_cmd(dot_gen.next_id(), &write_a, None);
+        key_deps.add_cmd(dot_gen.next_id(), &write_b, None);
+        key_deps.add_cmd(dot_gen.next_id(), &read_c, None);
+
+        // the scan depends on all writes, but not on reads
+        let deps = key_deps.add_cmd(dot_gen.next_id(), &scan, None);
+        assert_eq!(
+            extract_dots(deps),
+            HashSet::from_iter([Dot::new(1, 1), Dot::new(1, 2)])
+        );
+
+        // writes depend on the scan {1,4} (even if on keys the scan didn't
+        // access), but reads don't
+        let write_c = multi_put(Rifl::new(104, 1), vec![key_c.clone()], value);
+        assert_eq!(
+            key_deps.cmd_deps(&write_c),
+            HashSet::from_iter([Dot::new(1, 3), Dot::new(1, 4)])
+        );
+        assert_eq!(key_deps.cmd_deps(&read_c), HashSet::new());
+    }
+
     #[test]
     fn concurrent_locked_test() {
         let nthreads = 2;
@@ -10,6 +10,7 @@ pub struct SequentialKeyDeps {
     nfr: bool,
     latest: HashMap<Key, LatestRWDep>,
     latest_noop: LatestDep,
+    latest_scan: LatestDep,
 }
 
 impl KeyDeps for SequentialKeyDeps {
@@ -20,6 +21,7 @@ impl KeyDeps for SequentialKeyDeps {
             nfr,
             latest: HashMap::new(),
             latest_noop: None,
+            latest_scan: None,
         }
     }
 
@@ -89,6 +91,16 @@ impl SequentialKeyDeps {
             true
         });
 
+        // scans conflict with all writes: if the command has a scan, then
+        // it depends on the latest write on every key (and it becomes the
+        // latest scan); otherwise, it may depend on the latest scan
+        if cmd.has_scans_on(self.shard_id) {
+            self.do_scan_deps(&mut deps);
+            self.latest_scan = Some(cmd_dep.clone());
+        } else {
+            self.maybe_add_scan_latest(read_only, &mut deps);
+        }
+
         // iterate through all command keys, get their current latest and set
         // ourselves to be the new latest
         cmd.keys(self.shard_id).for_each(|key| {
@@ -135,6 +147,29 @@ impl SequentialKeyDeps {
         deps
     }
 
+    fn maybe_add_scan_latest(
+        &self,
+        read_only: bool,
+        deps: &mut HashSet<Dependency>,
+    ) {
+        // as with reads, writes only depend on scans if NFR is not enabled
+        if !read_only && !self.nfr {
+            if let Some(dep) = self.latest_scan.as_ref() {
+                deps.insert(dep.clone());
+            }
+        }
+    }
+
+    fn do_scan_deps(&self, deps: &mut HashSet<Dependency>) {
+        // iterate through all keys and include their latest write in the final
+        // `deps`
+        self.latest.values().for_each(|latest_rw| {
+            if let Some(wdep) = latest_rw.write.as_ref() {
+                deps.insert(wdep.clone());
+            }
+        });
+    }
+
     fn do_noop_deps(&self, deps: &mut HashSet<Dependency>) {
         // iterate through all keys, grab a read lock, and include their latest
         // in the final `deps`
@@ -153,6 +188,12 @@ impl SequentialKeyDeps {
         // flag indicating whether the command is read-only
         let read_only = cmd.read_only();
 
+        if cmd.has_scans_on(self.shard_id) {
+            self.do_scan_deps(deps);
+        } else {
+            self.maybe_add_scan_latest(read_only, deps);
+        }
+
         cmd.keys(self.shard_id).for_each(|key| {
             // get latest command on this key
             if let Some(latest_rw) = self.latest.get(key) {
@@ -165,7 +165,7 @@ impl LockedKeyClocks {
 mod tests {
     use super::*;
     use fantoch::id::Rifl;
-    use fantoch::kvs::KVOp;
+    use fantoch::kvs::{KVOp, Value};
     use std::iter::FromIterator;
 
     fn deps(deps: Vec<Dot>) -> HashSet<Dot> {
@@ -207,27 +207,27 @@ mod tests {
         // create command on key A
         let cmd_a = Command::from(
             Rifl::new(1, 1),
-            vec![(String::from("A"), KVOp::Put(String::new()))],
+            vec![(String::from("A"), KVOp::Put(Value::new()))],
         );
 
         // create command on key B
         let cmd_b = Command::from(
             Rifl::new(1, 1),
-            vec![(String::from("B"), KVOp::Put(String::new()))],
+            vec![(String::from("B"), KVOp::Put(Value::new()))],
         );
 
         // create command on key C
         let cmd_c = Command::from(
             Rifl::new(1, 1),
-            vec![(String::from("C"), KVOp::Put(String::new()))],
+            vec![(String::from("C"), KVOp::Put(Value::new()))],
         );
 
         // create command on keys A and C
         let cmd_ac = Command::from(
             Rifl::new(1, 1),
             vec![
-                (String::from("A"), KVOp::Put(String::new())),
-                (String::from("C"), KVOp::Put(String::new())),
+                (String::from("A"), KVOp::Put(Value::new())),
+                (String::from("C"), KVOp::Put(Value::new())),
             ],
         );
 
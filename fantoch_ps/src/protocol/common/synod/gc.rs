@@ -31,6 +31,11 @@ impl GCTrack {
         self.committed.add_event(slot);
     }
 
+    /// Checks whether a command has been committed.
+    pub fn is_committed(&self, slot: u64) -> bool {
+        self.committed.is_event(slot)
+    }
+
     /// Returns a clock representing the set of commands committed locally.
     /// Note that there might be more commands committed than the ones being
     /// represented by the returned clock.
@@ -12,5 +12,5 @@ mod gc;
 
 // Re-exports.
 pub use gc::GCTrack;
-pub use multi::{MultiSynod, MultiSynodMessage};
+pub use multi::{AcceptedSlots, MultiSynod, MultiSynodMessage};
 pub use single::{Synod, SynodMessage};
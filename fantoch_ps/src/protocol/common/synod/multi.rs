@@ -8,7 +8,7 @@ type Slot = u64;
 // The first component is the ballot in which the value (the second component)
 // was accepted.
 type Accepted<V> = (Ballot, V);
-type AcceptedSlots<V> = HashMap<Slot, Accepted<V>>;
+pub type AcceptedSlots<V> = HashMap<Slot, Accepted<V>>;
 type Accepts = HashSet<ProcessId>;
 
 /// Implementation of Flexible multi-decree Paxos in which:
@@ -19,13 +19,20 @@ pub enum MultiSynodMessage<V> {
     // to be handled outside of this module
     MChosen(Slot, V),
     MForwardSubmit(V),
+    // the slots (up to the last one) that should be proposed again once a new
+    // leader is prepared, and the value to be proposed in each of them (`None`
+    // if no value was accepted in that slot, which should be filled with a
+    // no-op)
+    MPrepared(Ballot, Vec<(Slot, Option<V>)>),
     // messages to root mod
     MSpawnCommander(Ballot, Slot, V),
     // messages to acceptor
     MPrepare(Ballot),
     MAccept(Ballot, Slot, V),
-    // messages to leader
-    MPromise(Ballot, AcceptedSlots<V>),
+    // messages to leader: the last component is the highest slot garbage
+    // collected by the acceptor (i.e. all slots up to it were committed by all
+    // processes)
+    MPromise(Ballot, AcceptedSlots<V>, Slot),
     // messages to the commander
     MAccepted(Ballot, Slot),
 }
@@ -39,6 +46,7 @@ pub struct MultiSynod<V> {
     // paxos agents
     leader: Leader,
     acceptor: Acceptor<V>,
+    scout: Option<Scout<V>>,
     commanders: HashMap<Slot, Commander<V>>,
 }
 
@@ -58,10 +66,40 @@ where
             f,
             leader: Leader::new(process_id, initial_leader),
             acceptor: Acceptor::new(initial_leader),
+            scout: None,
             commanders: HashMap::new(),
         }
     }
 
+    /// Changes the leader to `leader`. If we're the new leader, an `MPrepare`
+    /// is returned, which should be sent to all processes; until enough
+    /// promises are gathered, submits are not handled by this process (see
+    /// `MultiSynodMessage::MPrepared`).
+    pub fn set_leader(
+        &mut self,
+        leader: ProcessId,
+    ) -> Option<MultiSynodMessage<V>> {
+        // commanders of the previous leader are no longer needed, as all the
+        // slots not yet chosen are proposed again by the new leader
+        self.commanders.clear();
+        self.scout = None;
+        self.leader.is_leader = false;
+
+        if leader == self.leader.process_id {
+            // pick the lowest ballot owned by us that is higher than any
+            // ballot seen so far
+            let n = self.n as Ballot;
+            let highest =
+                std::cmp::max(self.leader.ballot, self.acceptor.ballot);
+            let ballot =
+                (highest / n + 1) * n + self.leader.process_id as Ballot;
+            self.scout = Some(Scout::spawn(self.n, self.f, ballot));
+            Some(MultiSynodMessage::MPrepare(ballot))
+        } else {
+            None
+        }
+    }
+
     pub fn submit(&mut self, value: V) -> MultiSynodMessage<V> {
         if let Some((ballot, slot)) = self.leader.try_submit() {
             // if we're the leader, create a spawn commander message:
@@ -94,15 +132,16 @@ where
                 self.acceptor.handle_accept(b, slot, value)
             }
             // handle messages to leader
-            MultiSynodMessage::MPromise(_b, _previous) => {
-                todo!("handling of MultiSynodMessage::MPromise not implemented yet");
+            MultiSynodMessage::MPromise(b, accepted, gc_slot) => {
+                self.handle_mpromise(from, b, accepted, gc_slot)
             }
             // handle messages to comamnders
             MultiSynodMessage::MAccepted(b, slot) => {
                 self.handle_maccepted(from, b, slot)
             }
             MultiSynodMessage::MChosen(_, _) => panic!("MultiSynod::MChosen messages are to be handled outside of MultiSynod"),
-            MultiSynodMessage::MForwardSubmit(_) => panic!("MultiSynod::MForwardSubmit messages are to be handled outside of MultiSynod")
+            MultiSynodMessage::MForwardSubmit(_) => panic!("MultiSynod::MForwardSubmit messages are to be handled outside of MultiSynod"),
+            MultiSynodMessage::MPrepared(_, _) => panic!("MultiSynod::MPrepared messages are to be handled outside of MultiSynod")
         }
     }
 
@@ -132,6 +171,29 @@ where
         MultiSynodMessage::MAccept(ballot, slot, value)
     }
 
+    fn handle_mpromise(
+        &mut self,
+        from: ProcessId,
+        ballot: Ballot,
+        accepted: AcceptedSlots<V>,
+        gc_slot: Slot,
+    ) -> Option<MultiSynodMessage<V>> {
+        // ignore the promise if we're no longer preparing this ballot
+        let scout =
+            self.scout.as_mut().filter(|scout| scout.ballot == ballot)?;
+        if scout.handle_promise(from, accepted, gc_slot) {
+            // if the scout has gathered enough promises, we're the leader
+            let (slots, last_slot) =
+                self.scout.take().expect("the scout should exist").destroy();
+            self.leader.is_leader = true;
+            self.leader.ballot = ballot;
+            self.leader.last_slot = last_slot;
+            Some(MultiSynodMessage::MPrepared(ballot, slots))
+        } else {
+            None
+        }
+    }
+
     fn handle_maccepted(
         &mut self,
         from: ProcessId,
@@ -209,6 +271,93 @@ impl Leader {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Scout<V> {
+    // number of processes
+    n: usize,
+    // maximum number of allowed failures
+    f: usize,
+    // ballot being prepared
+    ballot: Ballot,
+    // set of processes that have promised
+    promises: HashSet<ProcessId>,
+    // values accepted (in the highest ballot) by the processes that have
+    // promised
+    accepted: AcceptedSlots<V>,
+    // highest slot garbage collected by the processes that have promised
+    gc_slot: Slot,
+}
+
+impl<V> Scout<V>
+where
+    V: Clone,
+{
+    // Spawns a new scout to gather promises on some ballot.
+    fn spawn(n: usize, f: usize, ballot: Ballot) -> Self {
+        Self {
+            n,
+            f,
+            ballot,
+            promises: HashSet::new(),
+            accepted: HashMap::new(),
+            gc_slot: 0,
+        }
+    }
+
+    // Processes a promise, returning a bool indicating whether we have enough
+    // promises.
+    fn handle_promise(
+        &mut self,
+        from: ProcessId,
+        accepted: AcceptedSlots<V>,
+        gc_slot: Slot,
+    ) -> bool {
+        if !self.promises.insert(from) {
+            return false;
+        }
+        // keep the value accepted in the highest ballot of each slot
+        for (slot, (b, value)) in accepted {
+            match self.accepted.entry(slot) {
+                Entry::Occupied(mut entry) => {
+                    if entry.get().0 < b {
+                        entry.insert((b, value));
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((b, value));
+                }
+            }
+        }
+        self.gc_slot = std::cmp::max(self.gc_slot, gc_slot);
+
+        // check if we have enough (i.e. n - f) promises
+        self.promises.len() == self.n - self.f
+    }
+
+    // Destroys the scout, returning the slots to be proposed again (i.e. the
+    // ones not garbage collected up to the highest slot accepted) and the
+    // last of them. This should be called once `handle_promise` returns true.
+    // It will panic otherwise.
+    fn destroy(mut self) -> (Vec<(Slot, Option<V>)>, Slot) {
+        assert_eq!(self.promises.len(), self.n - self.f);
+        let last_slot = self
+            .accepted
+            .keys()
+            .copied()
+            .max()
+            .map_or(self.gc_slot, |slot| std::cmp::max(slot, self.gc_slot));
+        // slots with no value accepted by the processes that have promised
+        // were never chosen, and so they can be filled with no-ops
+        let slots = (self.gc_slot + 1..=last_slot)
+            .map(|slot| {
+                let value = self.accepted.remove(&slot).map(|(_, value)| value);
+                (slot, value)
+            })
+            .collect();
+        (slots, last_slot)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Commander<V> {
     // maximum number of allowed failures
@@ -263,6 +412,8 @@ where
 struct Acceptor<Value> {
     ballot: Ballot,
     accepted: HashMap<Slot, Accepted<Value>>,
+    // highest slot garbage collected
+    gc_slot: Slot,
 }
 
 impl<V> Acceptor<V>
@@ -276,12 +427,14 @@ where
         Self {
             ballot: initial_leader as Ballot,
             accepted: HashMap::new(),
+            gc_slot: 0,
         }
     }
 
     // The reply to this prepare request contains:
     // - a promise to never accept a proposal numbered less than `b`
     // - the non-GCed proposals accepted at ballots less than `b`, if any
+    // - the highest slot GCed
     fn handle_prepare(&mut self, b: Ballot) -> Option<MultiSynodMessage<V>> {
         // since we need to promise that we won't accept any proposal numbered
         // less then `b`, there's no point in letting such proposal be
@@ -290,7 +443,11 @@ where
             // update current ballot
             self.ballot = b;
             // create promise message
-            let promise = MultiSynodMessage::MPromise(b, self.accepted.clone());
+            let promise = MultiSynodMessage::MPromise(
+                b,
+                self.accepted.clone(),
+                self.gc_slot,
+            );
             Some(promise)
         } else {
             None
@@ -319,6 +476,7 @@ where
     /// Performs garbage collection of stable slots.
     /// Returns how many stable does were removed.
     fn gc(&mut self, (start, end): (u64, u64)) -> usize {
+        self.gc_slot = std::cmp::max(self.gc_slot, end);
         (start..=end)
             .filter(|slot| {
                 // remove slot:
@@ -408,4 +566,81 @@ mod tests {
             ),
         };
     }
+
+    #[test]
+    fn multi_synod_leader_change() {
+        // n and f
+        let n = 3;
+        let f = 1;
+
+        // initial leader is 1
+        let initial_leader = 1;
+
+        // create all synods
+        let mut synod_1 = MultiSynod::<usize>::new(1, initial_leader, n, f);
+        let mut synod_2 = MultiSynod::<usize>::new(2, initial_leader, n, f);
+        let mut synod_3 = MultiSynod::<usize>::new(3, initial_leader, n, f);
+
+        // synod 1 (the leader) submits 4 values, each accepted by a different
+        // set of acceptors:
+        // - slot 1 by synod 1 and 2
+        // - slot 2 by synod 3
+        // - slot 3 by synod 1
+        // - slot 4 by synod 3
+        let mut accept = |value| {
+            let spawn = synod_1.submit(value);
+            synod_1.handle(1, spawn).expect("there should be an accept")
+        };
+        let accept_1 = accept(10);
+        let accept_2 = accept(20);
+        let accept_3 = accept(30);
+        let accept_4 = accept(40);
+        assert!(synod_1.handle(1, accept_1.clone()).is_some());
+        assert!(synod_2.handle(1, accept_1).is_some());
+        assert!(synod_3.handle(1, accept_2).is_some());
+        assert!(synod_1.handle(1, accept_3).is_some());
+        assert!(synod_3.handle(1, accept_4).is_some());
+
+        // synod 1 fails and synod 2 becomes the leader
+        assert!(synod_3.set_leader(2).is_none());
+        let prepare = synod_2
+            .set_leader(2)
+            .expect("the new leader should send a prepare");
+        let ballot = match prepare {
+            MultiSynodMessage::MPrepare(ballot) => ballot,
+            msg => panic!("expected an MPrepare, found {:?}", msg),
+        };
+        assert!(ballot > initial_leader as Ballot);
+
+        // while preparing, submits are not handled by the new leader
+        assert_eq!(synod_2.submit(50), MultiSynodMessage::MForwardSubmit(50));
+
+        // gather promises from synod 2 and 3
+        let promise_2 = synod_2
+            .handle(2, prepare.clone())
+            .expect("there should be a promise from 2");
+        let promise_3 = synod_3
+            .handle(2, prepare.clone())
+            .expect("there should be a promise from 3");
+        assert!(synod_2.handle(2, promise_2).is_none());
+        let prepared = synod_2
+            .handle(3, promise_3)
+            .expect("there should be a prepared message");
+
+        // slot 3 was not accepted by synod 2 and 3, and so it's a no-op
+        let slots =
+            vec![(1, Some(10)), (2, Some(20)), (3, None), (4, Some(40))];
+        assert_eq!(prepared, MultiSynodMessage::MPrepared(ballot, slots));
+
+        // accepts from the previous leader are now rejected
+        assert!(synod_3
+            .handle(1, MultiSynodMessage::MAccept(1, 5, 60))
+            .is_none());
+
+        // new submits at the new leader use the next slot
+        assert_eq!(
+            synod_2.submit(50),
+            MultiSynodMessage::MSpawnCommander(ballot, 5, 50)
+        );
+    }
 }
@@ -194,8 +194,8 @@ mod common {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fantoch::kvs::KVOp;
     use fantoch::id::Rifl;
+    use fantoch::kvs::{KVOp, Value};
 
     #[test]
     fn bump_test() {
@@ -217,7 +217,7 @@ mod tests {
         // update command bump the clock
         let cmd = Command::from(
             rifl,
-            vec![(String::from("K"), KVOp::Put(String::new()))],
+            vec![(String::from("K"), KVOp::Put(Value::new()))],
         );
         let (clock, votes) = clocks.proposal(&cmd, 0);
         assert_eq!(clock, 1);
@@ -43,7 +43,7 @@ mod tests {
     use super::*;
     use crate::util;
     use fantoch::id::Rifl;
-    use fantoch::kvs::{KVOp, Key};
+    use fantoch::kvs::{KVOp, Key, Value};
     use std::collections::BTreeSet;
     use std::iter::FromIterator;
     use std::thread;
@@ -103,7 +103,8 @@ mod tests {
     fn multi_put(rifl: Rifl, keys: Vec<String>) -> Command {
         Command::from(
             rifl,
-            keys.into_iter().map(|key| (key.clone(), KVOp::Put(key))),
+            keys.into_iter()
+                .map(|key| (key.clone(), KVOp::Put(Value::from(key)))),
         )
     }
 
@@ -1,10 +1,13 @@
 use crate::executor::{SlotExecutionInfo, SlotExecutor};
-use crate::protocol::common::synod::{GCTrack, MultiSynod, MultiSynodMessage};
+use crate::protocol::common::synod::{
+    AcceptedSlots, GCTrack, MultiSynod, MultiSynodMessage,
+};
 use fantoch::command::Command;
 use fantoch::config::Config;
-use fantoch::id::{Dot, ProcessId, ShardId};
+use fantoch::id::{ClientId, Dot, ProcessId, Rifl, ShardId};
 use fantoch::protocol::{
-    Action, BaseProcess, MessageIndex, Protocol, ProtocolMetrics,
+    Action, BaseProcess, FailureDetection, MessageIndex, Protocol,
+    ProtocolMetrics,
 };
 use fantoch::time::SysTime;
 use fantoch::{singleton, trace};
@@ -18,6 +21,23 @@ pub struct FPaxos {
     leader: ProcessId,
     multi_synod: MultiSynod<Command>,
     gc_track: GCTrack,
+    // processes suspected by the failure detector
+    suspected: HashSet<ProcessId>,
+    // whether accepts are only sent to the write quorum; since the write
+    // quorum may contain crashed processes, accepts are sent to all processes
+    // once the leader changes
+    thrifty: bool,
+    // mapping from client to the last command of that client forwarded to the
+    // leader, until it's chosen (as it's forwarded again if the leader
+    // changes); since clients wait for the result of each command before
+    // issuing the next one, keeping only the last one bounds this mapping
+    // even if chosen commands are handled by a different worker
+    forwarded: HashMap<ClientId, Command>,
+    // commands submitted while we're becoming the leader
+    buffered: Vec<Command>,
+    // commands proposed again when we became the leader (which shouldn't be
+    // proposed twice if forwarded again by other processes)
+    recovered: HashSet<Rifl>,
     to_processes: Vec<Action<Self>>,
     to_executors: Vec<SlotExecutionInfo>,
 }
@@ -62,6 +82,11 @@ impl Protocol for FPaxos {
             leader: initial_leader,
             multi_synod,
             gc_track: GCTrack::new(process_id, config.n()),
+            suspected: HashSet::new(),
+            thrifty: true,
+            forwarded: HashMap::new(),
+            buffered: Vec::new(),
+            recovered: HashSet::new(),
             to_processes,
             to_executors,
         };
@@ -98,7 +123,13 @@ impl Protocol for FPaxos {
     }
 
     /// Submits a command issued by some client.
-    fn submit(&mut self, dot: Option<Dot>, cmd: Command, _time: &dyn SysTime) {
+    fn submit(
+        &mut self,
+        dot: Option<Dot>,
+        mut cmd: Command,
+        time: &dyn SysTime,
+    ) {
+        cmd.set_submit_time(time.millis());
         self.handle_submit(dot, cmd);
     }
 
@@ -112,6 +143,14 @@ impl Protocol for FPaxos {
     ) {
         match msg {
             Message::MForwardSubmit { cmd } => self.handle_submit(None, cmd),
+            Message::MPrepare { ballot } => {
+                self.handle_mprepare(from, ballot, time)
+            }
+            Message::MPromise {
+                ballot,
+                accepted,
+                gc_slot,
+            } => self.handle_mpromise(from, ballot, accepted, gc_slot, time),
             Message::MSpawnCommander { ballot, slot, cmd } => {
                 self.handle_mspawn_commander(from, ballot, slot, cmd, time)
             }
@@ -139,6 +178,43 @@ impl Protocol for FPaxos {
         }
     }
 
+    /// Handles the suspicions of the failure detector: once the leader is
+    /// suspected, the next process (by identifier) not suspected becomes the
+    /// leader.
+    fn handle_failure(
+        &mut self,
+        process_id: ProcessId,
+        detection: FailureDetection,
+        time: &dyn SysTime,
+    ) {
+        match detection {
+            FailureDetection::Suspected => {
+                self.suspected.insert(process_id);
+                if process_id == self.leader {
+                    let mut processes: Vec<_> = self
+                        .bp
+                        .all()
+                        .into_iter()
+                        .filter(|process_id| {
+                            !self.suspected.contains(process_id)
+                        })
+                        .collect();
+                    processes.sort_unstable();
+                    let leader = processes
+                        .iter()
+                        .find(|&&process_id| process_id > self.leader)
+                        .or_else(|| processes.first())
+                        .copied()
+                        .expect("we should not suspect ourselves");
+                    self.change_leader(leader, time);
+                }
+            }
+            FailureDetection::Trusted => {
+                self.suspected.remove(&process_id);
+            }
+        }
+    }
+
     /// Returns a new action to be sent to other processes.
     fn to_processes(&mut self) -> Option<Action<Self>> {
         self.to_processes.pop()
@@ -165,6 +241,11 @@ impl Protocol for FPaxos {
 impl FPaxos {
     /// Handles a submit operation by a client.
     fn handle_submit(&mut self, _dot: Option<Dot>, cmd: Command) {
+        if self.recovered.contains(&cmd.rifl()) {
+            // in this case, the command was already proposed again when we
+            // became the leader
+            return;
+        }
         match self.multi_synod.submit(cmd) {
             MultiSynodMessage::MSpawnCommander(ballot, slot, cmd) => {
                 // in this case, we're the leader: record command size
@@ -179,9 +260,17 @@ impl FPaxos {
                 // save new action
                 self.to_processes.push(Action::ToForward { msg: mspawn });
             }
+            MultiSynodMessage::MForwardSubmit(cmd)
+                if self.leader == self.id() =>
+            {
+                // in this case, we're becoming the leader: buffer the command
+                // until we are
+                self.buffered.push(cmd);
+            }
             MultiSynodMessage::MForwardSubmit(cmd) => {
                 // in this case, we're not the leader and should forward the
-                // command to the leader
+                // command to the leader (keeping it until it's chosen)
+                self.forwarded.insert(cmd.rifl().source(), cmd.clone());
                 let mforward = Message::MForwardSubmit { cmd };
                 let target = singleton![self.leader];
 
@@ -195,6 +284,136 @@ impl FPaxos {
         }
     }
 
+    /// Changes the leader to `leader`.
+    fn change_leader(&mut self, leader: ProcessId, _time: &dyn SysTime) {
+        trace!(
+            "p{}: leader changed from {} to {} | time={}",
+            self.id(),
+            self.leader,
+            leader,
+            _time.micros()
+        );
+        self.leader = leader;
+        self.thrifty = false;
+
+        // if we're the new leader, start preparing a new ballot
+        if let Some(msg) = self.multi_synod.set_leader(leader) {
+            match msg {
+                MultiSynodMessage::MPrepare(ballot) => {
+                    self.to_processes.push(Action::ToSend {
+                        target: self.bp.all(),
+                        msg: Message::MPrepare { ballot },
+                    });
+                }
+                msg => panic!("can't handle {:?} in change_leader", msg),
+            }
+        }
+
+        // submit again the commands not yet chosen (by the order of their
+        // identifiers, so that runs with the same seed are the same)
+        let mut pending: Vec<_> = self
+            .forwarded
+            .drain()
+            .map(|(_, cmd)| cmd)
+            .chain(self.buffered.drain(..))
+            .collect();
+        pending.sort_unstable_by_key(|cmd| cmd.rifl());
+        for cmd in pending {
+            self.handle_submit(None, cmd);
+        }
+    }
+
+    fn handle_mprepare(
+        &mut self,
+        from: ProcessId,
+        ballot: u64,
+        _time: &dyn SysTime,
+    ) {
+        trace!(
+            "p{}: MPrepare({:?}) from {} | time={}",
+            self.id(),
+            ballot,
+            from,
+            _time.micros()
+        );
+
+        if let Some(msg) = self
+            .multi_synod
+            .handle(from, MultiSynodMessage::MPrepare(ballot))
+        {
+            match msg {
+                MultiSynodMessage::MPromise(ballot, accepted, gc_slot) => {
+                    // create `MPromise` and target
+                    let mpromise = Message::MPromise {
+                        ballot,
+                        accepted,
+                        gc_slot,
+                    };
+                    let target = singleton![from];
+
+                    // save new action
+                    self.to_processes.push(Action::ToSend {
+                        target,
+                        msg: mpromise,
+                    });
+                }
+                msg => panic!("can't handle {:?} in handle_mprepare", msg),
+            }
+        }
+    }
+
+    fn handle_mpromise(
+        &mut self,
+        from: ProcessId,
+        ballot: u64,
+        accepted: AcceptedSlots<Command>,
+        gc_slot: u64,
+        _time: &dyn SysTime,
+    ) {
+        trace!(
+            "p{}: MPromise({:?}, {:?}, {:?}) from {} | time={}",
+            self.id(),
+            ballot,
+            accepted,
+            gc_slot,
+            from,
+            _time.micros()
+        );
+
+        let msg = MultiSynodMessage::MPromise(ballot, accepted, gc_slot);
+        if let Some(msg) = self.multi_synod.handle(from, msg) {
+            match msg {
+                MultiSynodMessage::MPrepared(ballot, slots) => {
+                    // in this case, we're now the leader: propose again the
+                    // values that may have been chosen, and no-ops in the
+                    // remaining slots
+                    for (slot, cmd) in slots {
+                        let cmd = match cmd {
+                            Some(cmd) => {
+                                self.recovered.insert(cmd.rifl());
+                                cmd
+                            }
+                            None => Command::from(
+                                Rifl::new(0, slot),
+                                std::iter::empty(),
+                            ),
+                        };
+                        let mspawn =
+                            Message::MSpawnCommander { ballot, slot, cmd };
+                        self.to_processes
+                            .push(Action::ToForward { msg: mspawn });
+                    }
+
+                    // submit the commands buffered meanwhile
+                    for cmd in std::mem::take(&mut self.buffered) {
+                        self.handle_submit(None, cmd);
+                    }
+                }
+                msg => panic!("can't handle {:?} in handle_mpromise", msg),
+            }
+        }
+    }
+
     fn handle_mspawn_commander(
         &mut self,
         from: ProcessId,
@@ -224,7 +443,11 @@ impl FPaxos {
             MultiSynodMessage::MAccept(ballot, slot, cmd) => {
                 // create `MAccept`
                 let maccept = Message::MAccept { ballot, slot, cmd };
-                let target = self.bp.write_quorum();
+                let target = if self.thrifty {
+                    self.bp.write_quorum()
+                } else {
+                    self.bp.all()
+                };
 
                 // save new action
                 self.to_processes.push(Action::ToSend {
@@ -323,14 +546,27 @@ impl FPaxos {
             _time.micros()
         );
 
+        // slots are chosen again when the leader changes, and so they may
+        // already have been committed
+        if self.gc_track.is_committed(slot) {
+            return;
+        }
+        let rifl = cmd.rifl();
+        if self
+            .forwarded
+            .get(&rifl.source())
+            .is_some_and(|forwarded| forwarded.rifl() == rifl)
+        {
+            self.forwarded.remove(&rifl.source());
+        }
+
         // create execution info
         let execution_info = SlotExecutionInfo::new(slot, cmd);
         self.to_executors.push(execution_info);
 
-        if self.gc_running() {
-            // register that it has been committed
-            self.gc_track.commit(slot);
-        } else {
+        // register that it has been committed
+        self.gc_track.commit(slot);
+        if !self.gc_running() {
             // if we're not running gc, remove the slot info now
             self.multi_synod.gc_single(slot);
         }
@@ -384,6 +620,14 @@ pub enum Message {
     MForwardSubmit {
         cmd: Command,
     },
+    MPrepare {
+        ballot: u64,
+    },
+    MPromise {
+        ballot: u64,
+        accepted: AcceptedSlots<Command>,
+        gc_slot: u64,
+    },
     MSpawnCommander {
         ballot: u64,
         slot: u64,
@@ -420,8 +664,12 @@ impl MessageIndex for Message {
                 // forward commands to the leader worker
                 worker_index_no_shift(LEADER_WORKER_INDEX)
             }
-            Self::MAccept { .. } => {
-                // forward accepts to the acceptor worker
+            Self::MPromise { .. } => {
+                // forward promises to the leader worker
+                worker_index_no_shift(LEADER_WORKER_INDEX)
+            }
+            Self::MPrepare { .. } | Self::MAccept { .. } => {
+                // forward prepares and accepts to the acceptor worker
                 worker_index_no_shift(ACCEPTOR_WORKER_INDEX)
             }
             Self::MChosen { .. } => {
@@ -448,6 +696,10 @@ impl MessageIndex for Message {
             }
         }
     }
+
+    fn background(&self) -> bool {
+        matches!(self, Self::MGarbageCollection { .. })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
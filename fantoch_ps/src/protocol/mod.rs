@@ -38,6 +38,7 @@ mod tests {
     use fantoch::protocol::{Protocol, ProtocolMetrics};
     use fantoch::run::tests::{run_test_with_inspect_fun, tokio_test_runtime};
     use fantoch::sim::Runner;
+    use fantoch::time::ClockSkew;
     use fantoch::HashMap;
     use std::time::Duration;
 
@@ -51,6 +52,7 @@ mod tests {
     const READ_ONLY_PERCENTAGE: usize = 0;
     const COMMANDS_PER_CLIENT: usize = 100;
     const CLIENTS_PER_PROCESS: usize = 10;
+    const FUZZ_SEEDS: u64 = 50;
 
     macro_rules! config {
         ($n:expr, $f:expr) => {{
@@ -196,6 +198,44 @@ mod tests {
         assert_eq!(metrics.slow_paths(), 0);
     }
 
+    #[test]
+    fn sim_real_time_tempo_5_1_clock_skew_test() {
+        // skew the clocks of processes by up to 20ms and 100us per second
+        let clock_skews = vec![
+            (1, ClockSkew::new(20_000, 0.0)),
+            (2, ClockSkew::new(-20_000, 100.0)),
+            (3, ClockSkew::new(5_000, -100.0)),
+        ];
+        let clock_bump_interval = Duration::from_millis(50);
+        // the executors monitors are checked, and so safety is preserved
+        let metrics = sim_clock_skew_test::<TempoSequential>(
+            tempo_config!(5, 1, clock_bump_interval),
+            READ_ONLY_PERCENTAGE,
+            KEYS_PER_COMMAND,
+            COMMANDS_PER_CLIENT,
+            CLIENTS_PER_PROCESS,
+            clock_skews,
+        );
+        assert_eq!(metrics.slow_paths(), 0);
+    }
+
+    #[test]
+    fn sim_tempo_3_1_linearizability_test() {
+        sim_linearizability_test::<TempoSequential>(tempo_config!(3, 1));
+    }
+
+    #[test]
+    fn fuzz_tempo_3_1_test() {
+        // messages are not duplicated, since tempo's executor assumes that
+        // votes are never received twice
+        fuzz_test::<TempoSequential>(tempo_config!(3, 1), 0.0);
+    }
+
+    #[test]
+    fn golden_tempo_3_1_test() {
+        golden_test::<TempoSequential>(tempo_config!(3, 1), "tempo_3_1");
+    }
+
     #[test]
     fn run_tempo_3_1_atomic_test() {
         // tempo atomic can handle as many workers as we want but we may want to
@@ -381,6 +421,26 @@ mod tests {
         assert_eq!(metrics.slow_paths_reads(), 0);
     }
 
+    #[test]
+    fn sim_atlas_3_1_linearizability_test() {
+        sim_linearizability_test::<AtlasSequential>(config!(3, 1));
+    }
+
+    #[test]
+    fn fuzz_atlas_3_1_test() {
+        fuzz_test::<AtlasSequential>(config!(3, 1), 0.0);
+    }
+
+    #[test]
+    fn golden_atlas_3_1_test() {
+        golden_test::<AtlasSequential>(config!(3, 1), "atlas_3_1");
+    }
+
+    #[test]
+    fn fuzz_atlas_3_1_duplicates_test() {
+        fuzz_test::<AtlasSequential>(config!(3, 1), 0.1);
+    }
+
     #[test]
     fn run_atlas_3_1_locked_test() {
         // atlas locked can handle as many workers as we want but only one
@@ -492,6 +552,26 @@ mod tests {
         assert_eq!(metrics.slow_paths_reads(), 0);
     }
 
+    #[test]
+    fn sim_epaxos_3_1_linearizability_test() {
+        sim_linearizability_test::<EPaxosSequential>(config!(3, 1));
+    }
+
+    #[test]
+    fn fuzz_epaxos_3_1_test() {
+        fuzz_test::<EPaxosSequential>(config!(3, 1), 0.0);
+    }
+
+    #[test]
+    fn golden_epaxos_3_1_test() {
+        golden_test::<EPaxosSequential>(config!(3, 1), "epaxos_3_1");
+    }
+
+    #[test]
+    fn fuzz_epaxos_3_1_duplicates_test() {
+        fuzz_test::<EPaxosSequential>(config!(3, 1), 0.1);
+    }
+
     #[test]
     fn run_epaxos_3_1_locked_test() {
         // epaxos locked can handle as many workers as we want but only one
@@ -554,6 +634,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sim_caesar_3_1_linearizability_test() {
+        sim_linearizability_test::<CaesarLocked>(caesar_config!(3, 1, true));
+    }
+
+    #[test]
+    fn fuzz_caesar_3_1_test() {
+        fuzz_test::<CaesarLocked>(caesar_config!(3, 1, true), 0.0);
+    }
+
+    #[test]
+    fn golden_caesar_3_1_test() {
+        golden_test::<CaesarLocked>(caesar_config!(3, 1, true), "caesar_3_1");
+    }
+
+    #[test]
+    fn fuzz_caesar_3_1_duplicates_test() {
+        fuzz_test::<CaesarLocked>(caesar_config!(3, 1, true), 0.1);
+    }
+
     #[test]
     fn run_caesar_3_1_wait_locked_test() {
         let workers = 4;
@@ -608,6 +708,154 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sim_fpaxos_3_1_linearizability_test() {
+        let leader = 1;
+        sim_linearizability_test::<FPaxos>(config!(3, 1, leader));
+    }
+
+    #[test]
+    fn sim_tempo_3_1_dots_test() {
+        let mut config = tempo_config!(3, 1);
+        update_config(&mut config, SHARD_COUNT);
+
+        // planet and process regions, with clients in all of them
+        let planet = Planet::new();
+        let mut regions = planet.regions();
+        regions.truncate(config.n());
+        let clients_per_process = 2;
+        let workload = Workload::new(
+            SHARD_COUNT,
+            KEY_GEN,
+            KEYS_PER_COMMAND,
+            COMMANDS_PER_CLIENT,
+            1,
+        );
+        let seed = 0;
+        let mut runner: Runner<TempoSequential> = Runner::new(
+            planet,
+            config,
+            workload,
+            clients_per_process,
+            regions.clone(),
+            regions,
+            seed,
+        );
+        runner.record_dots();
+        runner.run(Some(Duration::from_secs(1)));
+
+        // check the lifecycle of each command
+        let dots = runner.dots().expect("dots should be recorded");
+        let commands = COMMANDS_PER_CLIENT * clients_per_process * config.n();
+        assert_eq!(dots.len(), commands);
+        for lifecycle in dots.sorted() {
+            let coordinator = lifecycle.dot().source();
+            let submit = lifecycle.submit().expect("dot should be submitted");
+            // the coordinator commits once it has the replies of its fast
+            // quorum (of size 2, and so 1 reply besides its own)
+            let collected = lifecycle
+                .replies_at("MCollectAck", 1)
+                .expect("the coordinator should get a reply");
+            let commit = lifecycle
+                .commit(coordinator)
+                .expect("the coordinator should commit");
+            assert!(submit < collected && collected <= commit);
+            // and then all processes commit and execute it
+            assert_eq!(lifecycle.commits().len(), config.n());
+            assert_eq!(lifecycle.executions().len(), config.n());
+            let execution = lifecycle
+                .execution(coordinator)
+                .expect("the coordinator should execute");
+            let result = lifecycle.result().expect("the client should be done");
+            assert!(commit <= execution && execution <= result);
+        }
+    }
+
+    #[test]
+    fn sim_fpaxos_3_1_failover_test() {
+        let leader = 1;
+        let mut config = config!(3, 1, leader);
+        update_config(&mut config, SHARD_COUNT);
+
+        // planet and process regions
+        let planet = Planet::new();
+        let mut regions = planet.regions();
+        regions.truncate(config.n());
+
+        // clients workload with reads and read-modify-writes (as in
+        // `sim_linearizability_test`); since clients don't fail over to other
+        // processes, there are only clients in the regions of the processes
+        // that don't crash
+        let payload_size = 1;
+        let clients_per_process = 3;
+        let mut workload = Workload::new(
+            SHARD_COUNT,
+            KEY_GEN,
+            KEYS_PER_COMMAND,
+            COMMANDS_PER_CLIENT,
+            payload_size,
+        );
+        workload.set_read_only_percentage(20);
+        workload.set_rmw_percentage(50);
+        let clients = regions[1..]
+            .iter()
+            .map(|region| {
+                (region.clone(), clients_per_process, workload.clone())
+            })
+            .collect();
+
+        // create runner with a random seed, which is shown so that failing
+        // runs can be reproduced
+        let seed = rand::random();
+        println!("simulation seed: {}", seed);
+        let mut runner: Runner<FPaxos> =
+            Runner::with_clients(planet, config, regions, clients, seed);
+        runner.reorder_messages();
+        runner.record_history();
+
+        // the leader crashes after 1 second, and it's suspected 500
+        // milliseconds later
+        let at = 1000;
+        let detection_delay = 500;
+        runner.failover(leader, at, detection_delay);
+
+        // run simulation until the clients end
+        let extra_sim_time = None;
+        runner.run(extra_sim_time);
+
+        // check that all commands completed (after the new leader took over)
+        // and that the history is linearizable
+        let history = runner.history().expect("history should be recorded");
+        let commands = COMMANDS_PER_CLIENT * clients_per_process * 2;
+        assert_eq!(history.len(), commands);
+        if let Err(key) = history.check_linearizability() {
+            panic!("history of key {:?} is not linearizable", key);
+        }
+
+        // check that the failover caused a latency spike
+        let failover = runner
+            .failover_metrics()
+            .expect("there should be failover metrics");
+        assert_eq!(failover.at(), at);
+        assert!(failover.latency_spike() > detection_delay);
+        assert!(failover.latency_spike() > failover.latency_before());
+        assert!(failover.recovery_time() > detection_delay);
+    }
+
+    #[test]
+    fn fuzz_fpaxos_3_1_test() {
+        let leader = 1;
+        // messages are not duplicated, since fpaxos' executor assumes that
+        // slots are never committed twice
+        fuzz_test::<FPaxos>(config!(3, 1, leader), 0.0);
+    }
+
+    #[test]
+    fn golden_fpaxos_3_1_test() {
+        let leader = 1;
+        golden_test::<FPaxos>(config!(3, 1, leader), "fpaxos_3_1");
+    }
+
     #[test]
     fn run_fpaxos_3_1_sequential_test() {
         let leader = 1;
@@ -700,11 +948,30 @@ mod tests {
     }
 
     fn sim_test<P: Protocol>(
+        config: Config,
+        read_only_percentage: usize,
+        keys_per_command: usize,
+        commands_per_client: usize,
+        clients_per_process: usize,
+    ) -> ProtocolMetrics {
+        let clock_skews = Vec::new();
+        sim_clock_skew_test::<P>(
+            config,
+            read_only_percentage,
+            keys_per_command,
+            commands_per_client,
+            clients_per_process,
+            clock_skews,
+        )
+    }
+
+    fn sim_clock_skew_test<P: Protocol>(
         mut config: Config,
         read_only_percentage: usize,
         keys_per_command: usize,
         commands_per_client: usize,
         clients_per_process: usize,
+        clock_skews: Vec<(ProcessId, ClockSkew)>,
     ) -> ProtocolMetrics {
         let shard_count = 1;
         update_config(&mut config, shard_count);
@@ -729,7 +996,10 @@ mod tests {
         let process_regions = regions.clone();
         let client_regions = regions.clone();
 
-        // create runner
+        // create runner with a random seed, which is shown so that failing
+        // runs can be reproduced
+        let seed = rand::random();
+        println!("simulation seed: {}", seed);
         let mut runner: Runner<P> = Runner::new(
             planet,
             config,
@@ -737,11 +1007,17 @@ mod tests {
             clients_per_process,
             process_regions,
             client_regions,
+            seed,
         );
 
         // reorder network messages
         runner.reorder_messages();
 
+        // skew the clocks of processes
+        for (process_id, skew) in clock_skews {
+            runner.set_clock_skew(process_id, skew);
+        }
+
         // run simulation until the clients end + another 10 seconds (for GC)
         let extra_sim_time = Some(Duration::from_secs(10));
         let (metrics, executors_monitors, _) = runner.run(extra_sim_time);
@@ -767,6 +1043,158 @@ mod tests {
         check_metrics(config, commands_per_client, clients_per_process, metrics)
     }
 
+    fn sim_linearizability_test<P: Protocol>(mut config: Config) {
+        let shard_count = 1;
+        update_config(&mut config, shard_count);
+
+        // planet
+        let planet = Planet::new();
+
+        // clients workload with reads and read-modify-writes, as otherwise
+        // commands return no values to be checked; the number of clients is
+        // kept low since checking linearizability is exponential in the number
+        // of commands concurrent on the same key
+        let payload_size = 1;
+        let clients_per_process = 3;
+        let mut workload = Workload::new(
+            shard_count,
+            KEY_GEN,
+            KEYS_PER_COMMAND,
+            COMMANDS_PER_CLIENT,
+            payload_size,
+        );
+        workload.set_read_only_percentage(20);
+        workload.set_rmw_percentage(50);
+
+        // process and client regions
+        let mut regions = planet.regions();
+        regions.truncate(config.n());
+        let process_regions = regions.clone();
+        let client_regions = regions.clone();
+
+        // create runner with a random seed, which is shown so that failing
+        // runs can be reproduced
+        let seed = rand::random();
+        println!("simulation seed: {}", seed);
+        let mut runner: Runner<P> = Runner::new(
+            planet,
+            config,
+            workload,
+            clients_per_process,
+            process_regions,
+            client_regions,
+            seed,
+        );
+        runner.reorder_messages();
+        runner.record_history();
+
+        // run simulation until the clients end
+        let extra_sim_time = None;
+        runner.run(extra_sim_time);
+
+        // check that all commands were recorded and that the history is
+        // linearizable
+        let history = runner.history().expect("history should be recorded");
+        let commands = COMMANDS_PER_CLIENT * clients_per_process * config.n();
+        assert_eq!(history.len(), commands);
+        if let Err(key) = history.check_linearizability() {
+            panic!("history of key {:?} is not linearizable", key);
+        }
+    }
+
+    fn fuzz_test<P: Protocol>(mut config: Config, duplicate_probability: f64) {
+        use fantoch::command::Command;
+        use fantoch::id::RiflGen;
+        use fantoch::kvs::KVOp;
+        use fantoch::sim::Fuzzer;
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+
+        let shard_count = 1;
+        update_config(&mut config, shard_count);
+
+        // commands on one or two of three keys, submitted by all processes
+        let keys = ["A", "B", "C"];
+        let commands_per_process = 3;
+        let max_steps = 100_000;
+
+        for seed in 0..FUZZ_SEEDS {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut fuzzer = Fuzzer::<P>::new(config, seed);
+            fuzzer.duplicate_messages(duplicate_probability);
+            for process_id in 1..=config.n() as ProcessId {
+                let mut rifl_gen = RiflGen::new(process_id as u64);
+                for _ in 0..commands_per_process {
+                    let key_count = rng.gen_range(1..=2);
+                    let mut cmd_keys = keys.to_vec();
+                    cmd_keys.shuffle(&mut rng);
+                    let ops: Vec<_> = cmd_keys
+                        .into_iter()
+                        .take(key_count)
+                        .map(|key| (key.to_string(), KVOp::Add(1)))
+                        .collect();
+                    let cmd = Command::from(rifl_gen.next_id(), ops);
+                    fuzzer.submit(process_id, cmd);
+                }
+            }
+            let result = fuzzer.run(max_steps);
+            assert!(result.is_ok(), "seed {}: {:?}", seed, result);
+        }
+    }
+
+    // Checks the history of a seeded simulation against the golden one in
+    // `golden/{name}.jsonl`. Run the test with `FANTOCH_UPDATE_GOLDEN` set to
+    // (re)record it after an intended change in behavior.
+    fn golden_test<P: Protocol>(mut config: Config, name: &str) {
+        use fantoch::sim::Golden;
+
+        let shard_count = 1;
+        update_config(&mut config, shard_count);
+
+        // planet
+        let planet = Planet::new();
+
+        // few clients and commands, so that golden histories stay small
+        let commands_per_client = 5;
+        let clients_per_process = 1;
+        let payload_size = 1;
+        let workload = Workload::new(
+            shard_count,
+            KEY_GEN,
+            KEYS_PER_COMMAND,
+            commands_per_client,
+            payload_size,
+        );
+
+        // process and client regions (sorted, as the regions of the planet
+        // are returned in an arbitrary order)
+        let mut regions = planet.regions();
+        regions.sort();
+        regions.truncate(config.n());
+
+        let seed = 0;
+        let mut runner: Runner<P> = Runner::new(
+            planet,
+            config,
+            workload,
+            clients_per_process,
+            regions.clone(),
+            regions,
+            seed,
+        );
+        runner.record_trace();
+        let (_, executors_monitors, _) = runner.run(None);
+
+        let trace = runner.trace().expect("trace should be recorded");
+        let golden = Golden::new(trace, &executors_monitors);
+        let path =
+            format!("{}/golden/{}.jsonl", env!("CARGO_MANIFEST_DIR"), name);
+        if let Err(e) = golden.verify(path) {
+            panic!("{:?}", e);
+        }
+    }
+
     fn update_config(config: &mut Config, shard_count: usize) {
         // make sure execution order is monitored
         config.set_executor_monitor_execution_order(true);
@@ -154,7 +154,13 @@ impl<KC: KeyClocks> Protocol for Tempo<KC> {
     }
 
     /// Submits a command issued by some client.
-    fn submit(&mut self, dot: Option<Dot>, cmd: Command, _time: &dyn SysTime) {
+    fn submit(
+        &mut self,
+        dot: Option<Dot>,
+        mut cmd: Command,
+        time: &dyn SysTime,
+    ) {
+        cmd.set_submit_time(time.millis());
         self.handle_submit(dot, cmd, true);
     }
 
@@ -535,7 +541,10 @@ impl<KC: KeyClocks> Tempo<KC> {
             // - if `max_clock` was reported by at least `threshold` processes
             let fast_path = max_count >= threshold;
 
-            // fast path metrics
+            // fast path metrics; the command is also tagged with the path
+            // taken, so that its result reports it to the client
+            let cmd = info.cmd.as_mut().unwrap();
+            cmd.set_fast_path(fast_path);
             self.bp.path(fast_path, cmd.read_only());
 
             if fast_path {
@@ -611,6 +620,10 @@ impl<KC: KeyClocks> Tempo<KC> {
             .as_ref()
             .expect("there should be a command payload");
         let rifl = cmd.rifl();
+        // ops with conditions or validations make the command execute all or
+        // nothing on this shard
+        let atomic = cmd.has_conditions_on(self.bp.shard_id)
+            || cmd.has_validations_on(self.bp.shard_id);
         let execution_info = cmd.iter(self.bp.shard_id).map(|(key, ops)| {
             // find votes on this key
             let key_votes = votes.remove(&key).unwrap_or_default();
@@ -630,7 +643,10 @@ impl<KC: KeyClocks> Tempo<KC> {
                 rifl,
                 shard_to_keys,
                 ops.clone(),
+                atomic,
                 key_votes,
+                cmd.submit_time(),
+                cmd.fast_path(),
             )
         });
         self.to_executors.extend(execution_info);
@@ -1279,6 +1295,30 @@ impl MessageIndex for Message {
             Self::MStable { .. } => None,
         }
     }
+
+    fn background(&self) -> bool {
+        matches!(
+            self,
+            Self::MCommitDot { .. }
+                | Self::MGarbageCollection { .. }
+                | Self::MStable { .. }
+        )
+    }
+
+    fn dot(&self) -> Option<&Dot> {
+        match self {
+            Self::MCollect { dot, .. }
+            | Self::MCollectAck { dot, .. }
+            | Self::MCommit { dot, .. }
+            | Self::MConsensus { dot, .. }
+            | Self::MConsensusAck { dot, .. }
+            | Self::MForwardSubmit { dot, .. }
+            | Self::MBump { dot, .. }
+            | Self::MShardCommit { dot, .. }
+            | Self::MShardAggregatedCommit { dot, .. } => Some(dot),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
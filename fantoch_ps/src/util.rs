@@ -6,7 +6,7 @@ mod tests {
     use fantoch::command::Command;
     use fantoch::id::ProcessId;
     use fantoch::id::Rifl;
-    use fantoch::kvs::KVOp;
+    use fantoch::kvs::{KVOp, Value};
     use rand::Rng;
     use threshold::{Clock, EventSet, MaxSet, VClock};
 
@@ -40,13 +40,22 @@ mod tests {
             .map(|_| {
                 // select random key
                 let key = format!("{}", rng.gen_range(0..keys_number));
-                let value = String::from("");
-                (key, KVOp::Put(value))
+                let value = Value::new();
+                // select random write: plain or conditional
+                let op = match rng.gen_range(0..3) {
+                    0 => KVOp::Put(value),
+                    1 => KVOp::Cas {
+                        expected: Value::new(),
+                        new: value,
+                    },
+                    _ => KVOp::PutIfAbsent(value),
+                };
+                (key, op)
             })
             .collect();
         // create fake rifl
         let rifl = Rifl::new(0, 0);
-        // create multi put command
+        // create multi write command
         Some(Command::from(rifl, cmd_data))
     }
 }